@@ -0,0 +1,573 @@
+//! ReAct-style reasoning loop
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use atlas_core::Metadata;
+use atlas_mcp::ToolInfo;
+
+use crate::error::Error;
+use crate::few_shot::ExampleStore;
+use crate::guardrails::{Checked, GuardrailSet, GuardrailTarget};
+use crate::llm::router::TAGS_KEY;
+use crate::llm::{ChatMessage, CompletionRequest, LanguageModel, ToolCall};
+use crate::reflection::{Critic, ReflectionEntry, Verdict};
+use crate::tool::ToolManager;
+use crate::types::TaskConfig;
+
+/// Default step limit when the task does not set `max_steps`
+const DEFAULT_MAX_STEPS: u32 = 10;
+
+/// A single reasoning step
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskStep {
+    /// Step index, starting at zero
+    pub index: u32,
+
+    /// Model reasoning for this step
+    pub thought: String,
+
+    /// Tool call made in this step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<ToolCall>,
+
+    /// Tool result observed in this step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observation: Option<Value>,
+
+    /// Tool error observed in this step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Metadata of the model response, such as the routing decision
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+/// Why the loop stopped
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model produced a final answer
+    FinalAnswer,
+
+    /// The step limit was reached
+    MaxSteps,
+}
+
+/// Callback told of each step as soon as it is taken
+pub type StepObserver = Arc<dyn Fn(TaskStep) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Outcome of a reasoning loop
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoopOutcome {
+    /// Final answer, if the model produced one
+    pub answer: Option<String>,
+
+    /// Why the loop stopped
+    pub stop_reason: StopReason,
+
+    /// Steps taken
+    pub steps: Vec<TaskStep>,
+
+    /// Critiques of intermediate answers
+    #[serde(default)]
+    pub reflections: Vec<ReflectionEntry>,
+}
+
+/// Reasoning loop alternating model calls and tool execution
+///
+/// Each iteration asks the model for its next move. Tool calls are executed
+/// through the [`ToolManager`] and their results fed back as observations;
+/// a response without tool calls is treated as the final answer.
+pub struct AgentLoop {
+    /// Language model
+    model: Arc<dyn LanguageModel>,
+
+    /// System prompt
+    system_prompt: Option<String>,
+
+    /// Step limit used when the task has none
+    max_steps: u32,
+
+    /// Critic for intermediate answers
+    critic: Option<Arc<dyn Critic>>,
+
+    /// Few-shot examples and how many to include
+    examples: Option<(Arc<ExampleStore>, usize)>,
+
+    /// Guardrails for answers and tool parameters
+    guardrails: GuardrailSet,
+
+    /// Callback told of each step
+    observer: Option<StepObserver>,
+}
+
+impl AgentLoop {
+    /// Create a new reasoning loop
+    pub fn new(model: Arc<dyn LanguageModel>) -> Self {
+        Self {
+            model,
+            system_prompt: None,
+            max_steps: DEFAULT_MAX_STEPS,
+            critic: None,
+            examples: None,
+            guardrails: GuardrailSet::new(),
+            observer: None,
+        }
+    }
+
+    /// Set the system prompt
+    pub fn with_system_prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Set the default step limit
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Critique each answer before accepting it
+    ///
+    /// The critic may accept the answer, ask for a revision with feedback, or
+    /// reject it so the task starts over; after `max_revisions` rounds the
+    /// latest answer is accepted.
+    pub fn with_critic(mut self, critic: Arc<dyn Critic>) -> Self {
+        self.critic = Some(critic);
+        self
+    }
+
+    /// Include the `k` most relevant examples for the task's name in the prompt
+    pub fn with_examples(mut self, store: Arc<ExampleStore>, k: usize) -> Self {
+        self.examples = Some((store, k));
+        self
+    }
+
+    /// Check answers and tool parameters with guardrails
+    ///
+    /// Answers failing a retrying guardrail are sent back to the model with
+    /// the reason, using up a step; tool calls failing a guardrail are
+    /// reported to the model as tool errors.
+    pub fn with_guardrails(mut self, guardrails: GuardrailSet) -> Self {
+        self.guardrails = guardrails;
+        self
+    }
+
+    /// Tell an observer of each step as soon as it is taken
+    ///
+    /// Steps reach the observer even if a later model call fails, so a
+    /// failed run still leaves a trace.
+    pub fn with_step_observer(mut self, observer: StepObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Record a step and tell the observer of it
+    async fn record(&self, steps: &mut Vec<TaskStep>, step: TaskStep) {
+        if let Some(observer) = &self.observer {
+            observer(step.clone()).await;
+        }
+        steps.push(step);
+    }
+
+    /// Run the loop for a task until a final answer or the step limit
+    pub async fn run(&self, tools: &ToolManager, task: &TaskConfig, input: &str) -> Result<LoopOutcome> {
+        for required in &task.constraints.required_tools {
+            if tools.get(required).is_none() {
+                return Err(Error::ToolNotFound(required.clone()).into());
+            }
+        }
+
+        let max_steps = task.constraints.max_steps.unwrap_or(self.max_steps);
+        let tool_infos = tools.list_tools().into_iter().map(ToolInfo::from).collect::<Vec<_>>();
+
+        let mut messages = Vec::new();
+        if let Some(prompt) = &self.system_prompt {
+            messages.push(ChatMessage::system(prompt.clone()));
+        }
+        if let Some((store, k)) = &self.examples {
+            messages.extend(store.messages(&task.name, input, *k).await?);
+        }
+        messages.push(ChatMessage::user(task_prompt(task, input)));
+        let initial_len = messages.len();
+
+        let mut steps = Vec::new();
+        let mut reflections: Vec<ReflectionEntry> = Vec::new();
+        let mut index = 0;
+
+        while index < max_steps {
+            let mut request = CompletionRequest::new(messages.clone()).with_tools(tool_infos.clone());
+            if !task.tags.is_empty() {
+                request.metadata.try_insert(TAGS_KEY, &task.tags)?;
+            }
+            let response = self.model.complete(request).await?;
+            let metadata = response.metadata;
+            let mut message = response.message;
+
+            if message.tool_calls.is_empty() {
+                match self.guardrails.apply(&GuardrailTarget::ModelOutput, &message.content).await? {
+                    Checked::Pass(content) => message.content = content,
+                    Checked::Retry(reason) => {
+                        let step = TaskStep {
+                            index,
+                            thought: message.content.clone(),
+                            action: None,
+                            observation: None,
+                            error: Some(reason.clone()),
+                            metadata: metadata.clone(),
+                        };
+                        self.record(&mut steps, step).await;
+                        messages.push(message);
+                        messages.push(ChatMessage::user(format!(
+                            "Your answer was rejected: {}. Respond again.",
+                            reason
+                        )));
+                        index += 1;
+                        continue;
+                    }
+                }
+
+                let step = TaskStep {
+                    index,
+                    thought: message.content.clone(),
+                    action: None,
+                    observation: None,
+                    error: None,
+                    metadata: metadata.clone(),
+                };
+                self.record(&mut steps, step).await;
+
+                if let Some(critic) = &self.critic {
+                    if (reflections.len() as u32) < critic.max_revisions() {
+                        let critique = critic.critique(task, &message.content).await?;
+                        let verdict = critique.verdict;
+                        let feedback = critique.feedback.clone();
+                        reflections.push(ReflectionEntry {
+                            attempt: reflections.len() as u32,
+                            result: message.content.clone(),
+                            critique,
+                        });
+
+                        match verdict {
+                            Verdict::Accept => {}
+                            Verdict::Revise => {
+                                messages.push(message);
+                                messages.push(ChatMessage::user(format!(
+                                    "Revise your answer using this feedback: {}",
+                                    feedback
+                                )));
+                                index += 1;
+                                continue;
+                            }
+                            Verdict::Retry => {
+                                messages.truncate(initial_len);
+                                messages.push(ChatMessage::user(format!(
+                                    "A previous attempt was rejected ({}). Start over.",
+                                    feedback
+                                )));
+                                index += 1;
+                                continue;
+                            }
+                        }
+                    }
+                }
+
+                return Ok(LoopOutcome {
+                    answer: Some(message.content),
+                    stop_reason: StopReason::FinalAnswer,
+                    steps,
+                    reflections,
+                });
+            }
+
+            messages.push(message.clone());
+
+            for call in &message.tool_calls {
+                if index >= max_steps {
+                    break;
+                }
+
+                let mut call = call.clone();
+                let target = GuardrailTarget::ToolParams {
+                    tool: call.name.clone(),
+                };
+                let result = match self.guardrails.apply_value(&target, &mut call.arguments).await {
+                    Ok(()) => tools.invoke(&call).await,
+                    Err(e) => Err(e),
+                };
+                let (observation, error) = match result {
+                    Ok(result) => (Some(serde_json::to_value(result)?), None),
+                    Err(e) => (None, Some(e.to_string())),
+                };
+
+                let content = match (&observation, &error) {
+                    (Some(value), _) => value.to_string(),
+                    (None, Some(e)) => format!("Error: {}", e),
+                    (None, None) => String::new(),
+                };
+                messages.push(ChatMessage::tool(call.id.clone(), content));
+
+                let step = TaskStep {
+                    index,
+                    thought: message.content.clone(),
+                    action: Some(call),
+                    observation,
+                    error,
+                    metadata: metadata.clone(),
+                };
+                self.record(&mut steps, step).await;
+                index += 1;
+            }
+        }
+
+        Ok(LoopOutcome {
+            answer: None,
+            stop_reason: StopReason::MaxSteps,
+            steps,
+            reflections,
+        })
+    }
+}
+
+/// Build the initial user prompt for a task
+fn task_prompt(task: &TaskConfig, input: &str) -> String {
+    let mut prompt = format!("Task: {}", task.name);
+    if let Some(description) = &task.description {
+        prompt.push_str(&format!("\n{}", description));
+    }
+    if !input.is_empty() {
+        prompt.push_str(&format!("\n\n{}", input));
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionResponse;
+    use async_trait::async_trait;
+    use atlas_mcp::MCPTool;
+    use serde_json::json;
+
+    struct AddTool;
+
+    #[async_trait]
+    impl MCPTool for AddTool {
+        fn name(&self) -> &str {
+            "add"
+        }
+
+        fn description(&self) -> &str {
+            "Add two numbers"
+        }
+
+        async fn execute(&self, params: Metadata) -> Result<Metadata> {
+            let a = params.get::<f64>("a").unwrap_or_default();
+            let b = params.get::<f64>("b").unwrap_or_default();
+            let mut result = Metadata::new();
+            result.insert("sum", a + b);
+            Ok(result)
+        }
+    }
+
+    /// Calls `add` once, then answers with the observed sum
+    struct ScriptedModel;
+
+    #[async_trait]
+    impl LanguageModel for ScriptedModel {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            let last = request.messages.last().unwrap();
+            let message = if last.role == crate::llm::Role::Tool {
+                ChatMessage::assistant(format!("The answer is {}", last.content))
+            } else {
+                ChatMessage::assistant("I should add the numbers").with_tool_calls(vec![ToolCall {
+                    id: "call_1".to_string(),
+                    name: "add".to_string(),
+                    arguments: json!({"a": 2, "b": 3}),
+                }])
+            };
+            Ok(CompletionResponse {
+                message,
+                model: "scripted".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    /// Never stops calling tools
+    struct LoopingModel;
+
+    #[async_trait]
+    impl LanguageModel for LoopingModel {
+        fn name(&self) -> &str {
+            "looping"
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant("again").with_tool_calls(vec![ToolCall {
+                    id: "call".to_string(),
+                    name: "add".to_string(),
+                    arguments: json!({}),
+                }]),
+                model: "looping".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    fn tools() -> ToolManager {
+        let mut tools = ToolManager::new();
+        tools.register("add".to_string(), AddTool);
+        tools
+    }
+
+    #[tokio::test]
+    async fn test_thought_action_observation() {
+        let agent_loop = AgentLoop::new(Arc::new(ScriptedModel));
+        let outcome = agent_loop
+            .run(&tools(), &TaskConfig::default(), "What is 2 + 3?")
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.stop_reason, StopReason::FinalAnswer);
+        assert_eq!(outcome.steps.len(), 2);
+        assert_eq!(outcome.steps[0].action.as_ref().unwrap().name, "add");
+        assert_eq!(outcome.steps[0].observation.as_ref().unwrap()["sum"], 5.0);
+        assert!(outcome.answer.unwrap().contains("5"));
+    }
+
+    #[tokio::test]
+    async fn test_max_steps_constraint() {
+        let mut task = TaskConfig::default();
+        task.constraints.max_steps = Some(3);
+
+        let outcome = AgentLoop::new(Arc::new(LoopingModel))
+            .run(&tools(), &task, "")
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.stop_reason, StopReason::MaxSteps);
+        assert_eq!(outcome.steps.len(), 3);
+        assert!(outcome.answer.is_none());
+    }
+
+    /// Asks for one revision, then accepts
+    struct OneRevisionCritic;
+
+    #[async_trait]
+    impl Critic for OneRevisionCritic {
+        async fn critique(&self, _task: &TaskConfig, result: &str) -> Result<crate::reflection::Critique> {
+            let verdict = if result.contains("revised") {
+                Verdict::Accept
+            } else {
+                Verdict::Revise
+            };
+            Ok(crate::reflection::Critique {
+                verdict,
+                feedback: "show your work".to_string(),
+            })
+        }
+
+        fn max_revisions(&self) -> u32 {
+            2
+        }
+    }
+
+    /// Answers directly, marking answers given after feedback as revised
+    struct DirectModel;
+
+    #[async_trait]
+    impl LanguageModel for DirectModel {
+        fn name(&self) -> &str {
+            "direct"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            let last = request.messages.last().unwrap();
+            let content = if last.content.contains("feedback") {
+                "revised answer"
+            } else {
+                "answer"
+            };
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(content),
+                model: "direct".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reflection_revises_answer() {
+        let outcome = AgentLoop::new(Arc::new(DirectModel))
+            .with_critic(Arc::new(OneRevisionCritic))
+            .run(&tools(), &TaskConfig::default(), "")
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.answer.as_deref(), Some("revised answer"));
+        assert_eq!(outcome.reflections.len(), 2);
+        assert_eq!(outcome.reflections[0].critique.verdict, Verdict::Revise);
+        assert_eq!(outcome.reflections[1].critique.verdict, Verdict::Accept);
+    }
+
+    /// Calls `add` once, then fails
+    struct FailingModel;
+
+    #[async_trait]
+    impl LanguageModel for FailingModel {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            if request.messages.last().unwrap().role == crate::llm::Role::Tool {
+                anyhow::bail!("provider unavailable");
+            }
+            ScriptedModel.complete(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_steps_observed_before_failure() {
+        let observed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = observed.clone();
+        let result = AgentLoop::new(Arc::new(FailingModel))
+            .with_step_observer(Arc::new(move |step: TaskStep| {
+                sink.lock().unwrap().push(step);
+                Box::pin(async {})
+            }))
+            .run(&tools(), &TaskConfig::default(), "What is 2 + 3?")
+            .await;
+
+        assert!(result.is_err());
+        let observed = observed.lock().unwrap();
+        assert_eq!(observed.len(), 1);
+        assert_eq!(observed[0].action.as_ref().unwrap().name, "add");
+    }
+
+    #[tokio::test]
+    async fn test_required_tools() {
+        let mut task = TaskConfig::default();
+        task.constraints.required_tools = vec!["search".to_string()];
+
+        let result = AgentLoop::new(Arc::new(ScriptedModel))
+            .run(&tools(), &task, "")
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,621 @@
+//! Fanning a task out to several agents and combining their answers
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use atlas_core::{DynAgent, Metadata, TaskId};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::llm::{ChatMessage, CompletionRequest, LanguageModel};
+use crate::output_parser::OutputParser;
+
+/// Answer one agent gave to a fanned-out task
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Contribution {
+    /// Name of the agent
+    pub agent: String,
+
+    /// Result of the task, if it succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Metadata>,
+
+    /// Error of the task, if it failed or timed out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// How long the agent took, in milliseconds
+    pub elapsed_ms: u64,
+
+    /// Score the strategy gave the answer, if it scores answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+
+    /// Whether the answer was merged into the result
+    pub chosen: bool,
+}
+
+/// Combined result of a fanned-out task, with where it came from
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Aggregate {
+    /// Merged result
+    pub result: Metadata,
+
+    /// Name of the strategy that combined the answers
+    pub strategy: String,
+
+    /// Why the strategy chose the result, if it says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rationale: Option<String>,
+
+    /// Answer of each agent, in the order the agents were added
+    pub contributions: Vec<Contribution>,
+}
+
+impl Aggregate {
+    /// Create an aggregate of contributions with its result
+    pub fn new(
+        strategy: impl Into<String>,
+        result: Metadata,
+        contributions: Vec<Contribution>,
+    ) -> Self {
+        Self {
+            result,
+            strategy: strategy.into(),
+            rationale: None,
+            contributions,
+        }
+    }
+
+    /// Explain why the result was chosen
+    pub fn with_rationale(mut self, rationale: impl Into<String>) -> Self {
+        self.rationale = Some(rationale.into());
+        self
+    }
+
+    /// Get the names of the agents whose answers were chosen
+    pub fn chosen(&self) -> Vec<&str> {
+        self.contributions
+            .iter()
+            .filter(|c| c.chosen)
+            .map(|c| c.agent.as_str())
+            .collect()
+    }
+}
+
+/// Way of combining the answers of several agents into one
+///
+/// Strategies are given every contribution, including failed ones, and
+/// only combine those with an output.
+#[async_trait]
+pub trait AggregationStrategy: Send + Sync {
+    /// Name of the strategy
+    fn name(&self) -> &str;
+
+    /// Combine the answers to a task
+    async fn aggregate(
+        &self,
+        params: &Metadata,
+        contributions: Vec<Contribution>,
+    ) -> Result<Aggregate>;
+}
+
+/// Picks the answer most agents agree on
+///
+/// Answers are compared whole, or by the value at a dotted path. Ties go to
+/// the answer given by the agent added first.
+#[derive(Clone, Debug, Default)]
+pub struct MajorityVote {
+    /// Path of the value compared, or the whole answer if unset
+    field: Option<String>,
+
+    /// Share of the answers that must agree, from 0 to 1
+    quorum: f64,
+}
+
+impl MajorityVote {
+    /// Vote on whole answers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Vote on the value at a dotted path of each answer
+    pub fn on_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    /// Fail unless at least this share of the answers agree
+    pub fn with_quorum(mut self, quorum: f64) -> Self {
+        self.quorum = quorum.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Get what an answer votes for
+    fn ballot(&self, output: &Metadata) -> Option<Value> {
+        match &self.field {
+            Some(field) => output.get_path(field),
+            None => serde_json::to_value(output).ok(),
+        }
+    }
+}
+
+#[async_trait]
+impl AggregationStrategy for MajorityVote {
+    fn name(&self) -> &str {
+        "majority_vote"
+    }
+
+    async fn aggregate(
+        &self,
+        _params: &Metadata,
+        mut contributions: Vec<Contribution>,
+    ) -> Result<Aggregate> {
+        let ballots: Vec<Option<Value>> = contributions
+            .iter()
+            .map(|c| c.output.as_ref().and_then(|output| self.ballot(output)))
+            .collect();
+        let cast = ballots.iter().flatten().count();
+        let votes = |ballot: &Value| ballots.iter().flatten().filter(|b| *b == ballot).count();
+
+        // The first ballot with the most votes wins
+        let winner = ballots
+            .iter()
+            .flatten()
+            .fold(None::<(&Value, usize)>, |best, ballot| {
+                let count = votes(ballot);
+                match best {
+                    Some((_, most)) if most >= count => best,
+                    _ => Some((ballot, count)),
+                }
+            });
+        let Some((winner, count)) = winner else {
+            return Err(Error::TaskError("No answers to vote on".to_string()).into());
+        };
+        let share = count as f64 / cast as f64;
+        if share < self.quorum {
+            return Err(Error::TaskError(format!(
+                "No quorum: the most common answer has {} of {} votes",
+                count, cast
+            ))
+            .into());
+        }
+
+        let winner = winner.clone();
+        let mut result = None;
+        for (contribution, ballot) in contributions.iter_mut().zip(&ballots) {
+            let Some(ballot) = ballot else {
+                continue;
+            };
+            contribution.score = Some(votes(ballot) as f64 / cast as f64);
+            contribution.chosen = *ballot == winner;
+            if contribution.chosen && result.is_none() {
+                result = contribution.output.clone();
+            }
+        }
+        let rationale = format!("{} of {} answers agree", count, cast);
+        Ok(
+            Aggregate::new(self.name(), result.unwrap_or_default(), contributions)
+                .with_rationale(rationale),
+        )
+    }
+}
+
+/// Scores an answer, higher being better
+pub type Scorer = Arc<dyn Fn(&Metadata) -> Option<f64> + Send + Sync>;
+
+/// Picks the answer with the best score
+///
+/// Answers without a score are never picked.
+#[derive(Clone)]
+pub struct BestOfScore {
+    /// Scores the answers
+    scorer: Scorer,
+}
+
+impl BestOfScore {
+    /// Score answers with a function
+    pub fn new<F>(scorer: F) -> Self
+    where
+        F: Fn(&Metadata) -> Option<f64> + Send + Sync + 'static,
+    {
+        Self {
+            scorer: Arc::new(scorer),
+        }
+    }
+
+    /// Score answers by the number at a dotted path, such as a confidence
+    /// the agents report
+    pub fn on_field(field: impl Into<String>) -> Self {
+        let field = field.into();
+        Self::new(move |output| output.get_path(&field))
+    }
+}
+
+impl std::fmt::Debug for BestOfScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BestOfScore").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AggregationStrategy for BestOfScore {
+    fn name(&self) -> &str {
+        "best_of_score"
+    }
+
+    async fn aggregate(
+        &self,
+        _params: &Metadata,
+        mut contributions: Vec<Contribution>,
+    ) -> Result<Aggregate> {
+        let mut best: Option<(usize, f64)> = None;
+        for (i, contribution) in contributions.iter_mut().enumerate() {
+            contribution.score = contribution.output.as_ref().and_then(|o| (self.scorer)(o));
+            if let Some(score) = contribution.score {
+                if best.is_none_or(|(_, top)| score > top) {
+                    best = Some((i, score));
+                }
+            }
+        }
+        let Some((best, score)) = best else {
+            return Err(Error::TaskError("No answers could be scored".to_string()).into());
+        };
+        contributions[best].chosen = true;
+        let result = contributions[best].output.clone().unwrap_or_default();
+        let agent = contributions[best].agent.clone();
+        Ok(Aggregate::new(self.name(), result, contributions)
+            .with_rationale(format!("{} scored highest with {}", agent, score)))
+    }
+}
+
+/// Judgement of the answers by a language model
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Judgement {
+    /// Number of the best answer, starting at 1
+    pub best: usize,
+
+    /// Why it is the best
+    pub reason: String,
+}
+
+/// Has a language model judge which answer is best
+pub struct LlmJudge {
+    /// Language model
+    model: Arc<dyn LanguageModel>,
+
+    /// Criteria the answers are judged against
+    criteria: Vec<String>,
+}
+
+impl LlmJudge {
+    /// Create a judge
+    pub fn new(model: Arc<dyn LanguageModel>) -> Self {
+        Self {
+            model,
+            criteria: vec!["The answer is correct and fully addresses the task".to_string()],
+        }
+    }
+
+    /// Judge the answers against these criteria
+    pub fn with_criteria(mut self, criteria: Vec<String>) -> Self {
+        self.criteria = criteria;
+        self
+    }
+}
+
+#[async_trait]
+impl AggregationStrategy for LlmJudge {
+    fn name(&self) -> &str {
+        "llm_judge"
+    }
+
+    async fn aggregate(
+        &self,
+        params: &Metadata,
+        mut contributions: Vec<Contribution>,
+    ) -> Result<Aggregate> {
+        let candidates: Vec<usize> = contributions
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.output.is_some())
+            .map(|(i, _)| i)
+            .collect();
+        if candidates.is_empty() {
+            return Err(Error::TaskError("No answers to judge".to_string()).into());
+        }
+
+        let criteria = self
+            .criteria
+            .iter()
+            .map(|c| format!("- {}", c))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let answers = candidates
+            .iter()
+            .enumerate()
+            .map(|(n, &i)| {
+                let output = serde_json::to_string(&contributions[i].output)?;
+                Ok(format!("Answer {}:\n{}", n + 1, output))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join("\n\n");
+        let request = CompletionRequest::new(vec![
+            ChatMessage::system(format!(
+                "Several agents answered the same task. Judge their answers against these \
+                 criteria and pick the best one:\n{}",
+                criteria
+            )),
+            ChatMessage::user(format!(
+                "Task:\n{}\n\n{}",
+                serde_json::to_string(params)?,
+                answers
+            )),
+        ])
+        .with_temperature(0.0);
+
+        let judgement = OutputParser::<Judgement>::new()?
+            .complete(self.model.as_ref(), request)
+            .await?;
+        let Some(&best) = judgement
+            .best
+            .checked_sub(1)
+            .and_then(|n| candidates.get(n))
+        else {
+            return Err(Error::OutputParseError(format!(
+                "Judge picked answer {} of {}",
+                judgement.best,
+                candidates.len()
+            ))
+            .into());
+        };
+        contributions[best].chosen = true;
+        let result = contributions[best].output.clone().unwrap_or_default();
+        Ok(Aggregate::new(self.name(), result, contributions).with_rationale(judgement.reason))
+    }
+}
+
+/// Fans a task out to several agents and combines their answers
+pub struct Aggregator {
+    /// Agents answering, by name
+    agents: Vec<(String, Arc<dyn DynAgent>)>,
+
+    /// How the answers are combined
+    strategy: Arc<dyn AggregationStrategy>,
+
+    /// How long each agent may take
+    timeout: Option<Duration>,
+
+    /// Answers needed before combining them
+    min_answers: usize,
+}
+
+impl Aggregator {
+    /// Create an aggregator combining answers with a strategy
+    pub fn new<S: AggregationStrategy + 'static>(strategy: S) -> Self {
+        Self {
+            agents: Vec::new(),
+            strategy: Arc::new(strategy),
+            timeout: None,
+            min_answers: 1,
+        }
+    }
+
+    /// Ask an agent too
+    pub fn with_agent(mut self, name: impl Into<String>, agent: Arc<dyn DynAgent>) -> Self {
+        self.agents.push((name.into(), agent));
+        self
+    }
+
+    /// Stop waiting for an agent after this long
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail unless at least this many agents answer
+    pub fn with_min_answers(mut self, min_answers: usize) -> Self {
+        self.min_answers = min_answers;
+        self
+    }
+
+    /// Run a task on every agent at once and combine the answers
+    pub async fn run(&self, params: Metadata) -> Result<Aggregate> {
+        if self.agents.is_empty() {
+            return Err(Error::InvalidConfig("No agents to aggregate".to_string()).into());
+        }
+        let contributions = futures::future::join_all(
+            self.agents
+                .iter()
+                .map(|(name, agent)| self.ask(name, agent.as_ref(), params.clone())),
+        )
+        .await;
+
+        let answers = contributions.iter().filter(|c| c.output.is_some()).count();
+        if answers < self.min_answers {
+            return Err(Error::TaskError(format!(
+                "Only {} of {} agents answered, {} needed",
+                answers,
+                self.agents.len(),
+                self.min_answers
+            ))
+            .into());
+        }
+        let aggregate = self.strategy.aggregate(&params, contributions).await?;
+        tracing::debug!(
+            strategy = %aggregate.strategy,
+            chosen = ?aggregate.chosen(),
+            "Aggregated answers"
+        );
+        Ok(aggregate)
+    }
+
+    /// Run the task on one agent
+    async fn ask(&self, name: &str, agent: &dyn DynAgent, params: Metadata) -> Contribution {
+        let started = Instant::now();
+        let task = agent.execute_task(TaskId::new(), params);
+        let result = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, task).await {
+                Ok(result) => result,
+                Err(_) => Err(atlas_core::Error::Timeout(format!(
+                    "{} did not answer within {:?}",
+                    name, timeout
+                ))
+                .into()),
+            },
+            None => task.await,
+        };
+        if let Err(e) = &result {
+            tracing::warn!(agent = %name, error = %e, "Agent failed to answer");
+        }
+        Contribution {
+            agent: name.to_string(),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(|e| e.to_string()),
+            output: result.ok(),
+            score: None,
+            chosen: false,
+        }
+    }
+}
+
+impl std::fmt::Debug for Aggregator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Aggregator")
+            .field(
+                "agents",
+                &self.agents.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("strategy", &self.strategy.name())
+            .field("timeout", &self.timeout)
+            .field("min_answers", &self.min_answers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionResponse;
+    use crate::{AgentBuilder, Config};
+    use atlas_mcp::MCPTool;
+
+    /// Tool answering with a fixed answer and confidence
+    struct Answer(&'static str, f64);
+
+    #[async_trait]
+    impl MCPTool for Answer {
+        fn name(&self) -> &str {
+            "answer"
+        }
+
+        fn description(&self) -> &str {
+            "Answer the question"
+        }
+
+        async fn execute(&self, _params: Metadata) -> Result<Metadata> {
+            let mut result = Metadata::new();
+            result.insert("answer", self.0);
+            result.insert("confidence", self.1);
+            Ok(result)
+        }
+    }
+
+    fn agent(name: &str, answer: &'static str, confidence: f64) -> Arc<dyn DynAgent> {
+        Arc::new(
+            AgentBuilder::new()
+                .config(Config {
+                    name: name.to_string(),
+                    description: None,
+                    capabilities: vec![],
+                    config: Metadata::new(),
+                })
+                .tool("answer", Answer(answer, confidence))
+                .build()
+                .unwrap(),
+        )
+    }
+
+    fn panel<S: AggregationStrategy + 'static>(strategy: S) -> Aggregator {
+        Aggregator::new(strategy)
+            .with_agent("a", agent("a", "paris", 0.6))
+            .with_agent("b", agent("b", "lyon", 0.9))
+            .with_agent("c", agent("c", "paris", 0.7))
+    }
+
+    fn question() -> Metadata {
+        let mut params = Metadata::new();
+        params.insert("tool", "answer");
+        params.insert("question", "Capital of France?");
+        params
+    }
+
+    #[tokio::test]
+    async fn test_vote_and_score() {
+        let vote = panel(MajorityVote::new().on_field("answer"))
+            .run(question())
+            .await
+            .unwrap();
+        assert_eq!(
+            vote.result.get::<String>("answer").as_deref(),
+            Some("paris")
+        );
+        assert_eq!(vote.chosen(), ["a", "c"]);
+        assert_eq!(vote.contributions[1].score, Some(1.0 / 3.0));
+
+        let strict = panel(MajorityVote::new().on_field("answer").with_quorum(0.9));
+        assert!(strict.run(question()).await.is_err());
+
+        let best = panel(BestOfScore::on_field("confidence"))
+            .run(question())
+            .await
+            .unwrap();
+        assert_eq!(best.result.get::<String>("answer").as_deref(), Some("lyon"));
+        assert_eq!(best.chosen(), ["b"]);
+
+        // Agents that fail are recorded but not counted
+        let mut params = question();
+        params.insert("tool", "missing");
+        let err = panel(MajorityVote::new()).run(params).await.unwrap_err();
+        assert!(err.to_string().contains("Only 0 of 3"));
+    }
+
+    struct Judge;
+
+    #[async_trait]
+    impl LanguageModel for Judge {
+        fn name(&self) -> &str {
+            "judge"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            assert!(request
+                .messages
+                .last()
+                .unwrap()
+                .content
+                .contains("Answer 3"));
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(r#"{"best": 3, "reason": "Most precise"}"#),
+                model: "judge".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_llm_judge() {
+        let judged = panel(LlmJudge::new(Arc::new(Judge)))
+            .run(question())
+            .await
+            .unwrap();
+        assert_eq!(judged.chosen(), ["c"]);
+        assert_eq!(judged.rationale.as_deref(), Some("Most precise"));
+        assert_eq!(judged.strategy, "llm_judge");
+    }
+}
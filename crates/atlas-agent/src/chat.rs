@@ -0,0 +1,291 @@
+//! Multi-turn chat sessions
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::error::Error;
+use crate::llm::{ChatMessage, CompletionRequest, LanguageModel, Role};
+use crate::State;
+
+/// Prefix for chat session keys in agent memory
+const SESSION_KEY_PREFIX: &str = "chat_session:";
+
+/// History retention policy
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryPolicy {
+    /// Keep every message
+    Unbounded,
+
+    /// Keep only the most recent messages
+    KeepLast {
+        /// Number of messages to keep
+        messages: usize,
+    },
+
+    /// Drop the oldest messages once the total content exceeds a character budget
+    MaxChars {
+        /// Maximum number of content characters
+        chars: usize,
+    },
+
+    /// Summarize older messages once the history grows past a threshold
+    Summarize {
+        /// Number of messages that triggers summarization
+        threshold: usize,
+
+        /// Number of recent messages kept verbatim
+        keep_last: usize,
+    },
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self::KeepLast { messages: 50 }
+    }
+}
+
+/// Conversation with message history
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatSession {
+    /// Session ID
+    pub id: String,
+
+    /// System prompt sent ahead of the history
+    pub system_prompt: Option<String>,
+
+    /// Message history
+    messages: Vec<ChatMessage>,
+
+    /// History retention policy
+    policy: HistoryPolicy,
+}
+
+impl ChatSession {
+    /// Create a new chat session
+    pub fn new<S: Into<String>>(id: S) -> Self {
+        Self {
+            id: id.into(),
+            system_prompt: None,
+            messages: Vec::new(),
+            policy: HistoryPolicy::default(),
+        }
+    }
+
+    /// Set the system prompt
+    pub fn with_system_prompt<S: Into<String>>(mut self, prompt: S) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Set the history policy
+    pub fn with_policy(mut self, policy: HistoryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Get the message history
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// Append a message to the history
+    pub fn push(&mut self, message: ChatMessage) {
+        self.messages.push(message);
+    }
+
+    /// Build a completion request from the system prompt and history
+    pub fn request(&self) -> CompletionRequest {
+        let mut messages = Vec::with_capacity(self.messages.len() + 1);
+        if let Some(prompt) = &self.system_prompt {
+            messages.push(ChatMessage::system(prompt.clone()));
+        }
+        messages.extend(self.messages.iter().cloned());
+        CompletionRequest::new(messages)
+    }
+
+    /// Send a user message and record the model's reply
+    pub async fn send<S: Into<String>>(
+        &mut self,
+        model: &dyn LanguageModel,
+        content: S,
+    ) -> Result<ChatMessage> {
+        self.push(ChatMessage::user(content));
+
+        let response = model.complete(self.request()).await?;
+        self.push(response.message.clone());
+
+        self.apply_policy(model).await?;
+        Ok(response.message)
+    }
+
+    /// Apply the history policy, summarizing through the model if required
+    pub async fn apply_policy(&mut self, model: &dyn LanguageModel) -> Result<()> {
+        match self.policy {
+            HistoryPolicy::Unbounded => {}
+            HistoryPolicy::KeepLast { messages } => {
+                if self.messages.len() > messages {
+                    self.messages.drain(..self.messages.len() - messages);
+                }
+            }
+            HistoryPolicy::MaxChars { chars } => {
+                let mut total: usize = self.messages.iter().map(|m| m.content.len()).sum();
+                let mut drop = 0;
+                // Always keep the latest message, even if it exceeds the budget
+                while total > chars && drop + 1 < self.messages.len() {
+                    total -= self.messages[drop].content.len();
+                    drop += 1;
+                }
+                self.messages.drain(..drop);
+            }
+            HistoryPolicy::Summarize {
+                threshold,
+                keep_last,
+            } => {
+                if self.messages.len() > threshold && self.messages.len() > keep_last {
+                    let older: Vec<ChatMessage> = self
+                        .messages
+                        .drain(..self.messages.len() - keep_last)
+                        .collect();
+                    let summary = summarize(model, &older).await?;
+                    self.messages.insert(
+                        0,
+                        ChatMessage::system(format!(
+                            "Summary of the earlier conversation: {}",
+                            summary
+                        )),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a session from agent memory
+    pub async fn load(state: &RwLock<State>, id: &str) -> Result<Option<Self>> {
+        let state = state.read().await;
+        match state.memory.get(&session_key(id)) {
+            Some(value) => {
+                let session = serde_json::from_value(value.clone())
+                    .map_err(|e| Error::MemoryError(format!("Invalid chat session {}: {}", id, e)))?;
+                Ok(Some(session))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Save the session to agent memory
+    pub async fn save(&self, state: &RwLock<State>) -> Result<()> {
+        let value = serde_json::to_value(self)?;
+        let mut state = state.write().await;
+        state.memory.insert(session_key(&self.id), value);
+        Ok(())
+    }
+}
+
+/// Get the memory key for a session
+fn session_key(id: &str) -> String {
+    format!("{}{}", SESSION_KEY_PREFIX, id)
+}
+
+/// Summarize messages into a single paragraph
+async fn summarize(model: &dyn LanguageModel, messages: &[ChatMessage]) -> Result<String> {
+    let transcript = messages
+        .iter()
+        .map(|m| {
+            let role = match m.role {
+                Role::System => "system",
+                Role::User => "user",
+                Role::Assistant => "assistant",
+                Role::Tool => "tool",
+            };
+            format!("{}: {}", role, m.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let request = CompletionRequest::new(vec![
+        ChatMessage::system(
+            "Summarize the following conversation in a short paragraph, \
+             keeping any facts, decisions, and open questions.",
+        ),
+        ChatMessage::user(transcript),
+    ]);
+
+    Ok(model.complete(request).await?.message.content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionResponse;
+    use async_trait::async_trait;
+
+    struct EchoModel;
+
+    #[async_trait]
+    impl LanguageModel for EchoModel {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            let last = request.messages.last().unwrap();
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(format!(
+                    "{} messages, last: {}",
+                    request.messages.len(),
+                    last.content
+                )),
+                model: "echo".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_is_threaded() {
+        let mut session = ChatSession::new("s1").with_system_prompt("Be brief");
+
+        session.send(&EchoModel, "hello").await.unwrap();
+        let reply = session.send(&EchoModel, "again").await.unwrap();
+
+        // system + user + assistant + user
+        assert_eq!(reply.content, "4 messages, last: again");
+        assert_eq!(session.messages().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_truncation_policies() {
+        let mut session =
+            ChatSession::new("s1").with_policy(HistoryPolicy::KeepLast { messages: 2 });
+        session.send(&EchoModel, "one").await.unwrap();
+        session.send(&EchoModel, "two").await.unwrap();
+        assert_eq!(session.messages().len(), 2);
+        assert_eq!(session.messages()[0].content, "two");
+
+        let mut session = ChatSession::new("s2").with_policy(HistoryPolicy::Summarize {
+            threshold: 3,
+            keep_last: 1,
+        });
+        session.send(&EchoModel, "one").await.unwrap();
+        session.send(&EchoModel, "two").await.unwrap();
+        assert_eq!(session.messages().len(), 2);
+        assert_eq!(session.messages()[0].role, Role::System);
+        assert!(session.messages()[0].content.starts_with("Summary"));
+    }
+
+    #[tokio::test]
+    async fn test_session_persistence() {
+        let state = RwLock::new(State::default());
+        let mut session = ChatSession::new("s1");
+        session.push(ChatMessage::user("remember me"));
+        session.save(&state).await.unwrap();
+
+        let loaded = ChatSession::load(&state, "s1").await.unwrap().unwrap();
+        assert_eq!(loaded.messages(), session.messages());
+        assert!(ChatSession::load(&state, "missing").await.unwrap().is_none());
+    }
+}
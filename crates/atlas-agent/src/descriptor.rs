@@ -0,0 +1,215 @@
+//! Self-description of what an agent can do
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use atlas_core::{AgentId, HealthStatus, Metadata};
+use atlas_mcp::ToolInfo;
+
+use crate::guardrails::REDACTED;
+use crate::handler::UnknownEventPolicy;
+use crate::lifecycle::LifecycleState;
+use crate::Agent;
+
+/// Document describing an agent to orchestrators and user interfaces
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AgentDescriptor {
+    /// Agent ID
+    pub id: AgentId,
+
+    /// Agent name
+    pub name: String,
+
+    /// Agent description
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Declared capabilities
+    pub capabilities: Vec<String>,
+
+    /// Agent configuration, with secrets redacted (see [`redact_secrets`])
+    pub config: Metadata,
+
+    /// Registered tools with their input schemas, sorted by name
+    pub tools: Vec<ToolInfo>,
+
+    /// Event types and patterns the agent has handlers for, sorted
+    pub event_types: Vec<String>,
+
+    /// Topics the agent subscribes to when it listens
+    pub subscriptions: Vec<String>,
+
+    /// What the agent does with events no handler matches
+    pub unknown_events: UnknownEventPolicy,
+
+    /// Whether the agent has a language model to reason with
+    pub has_model: bool,
+
+    /// Stage of the agent's lifecycle
+    pub lifecycle: LifecycleState,
+
+    /// Health of the agent when it was described; registries replace it with
+    /// the health the agent last beat with
+    #[serde(default)]
+    pub health: HealthStatus,
+}
+
+impl Agent {
+    /// Describe the agent's configuration, tools and handled events
+    pub async fn describe(&self) -> Result<AgentDescriptor> {
+        let config = self.config();
+        let mut tools = self.list_tools().await?;
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        let (event_types, unknown_events) = {
+            let handlers = self.handlers()?;
+            (handlers.event_types(), handlers.unknown_policy())
+        };
+        Ok(AgentDescriptor {
+            id: self.id,
+            name: config.name.clone(),
+            description: config.description.clone(),
+            capabilities: config.capabilities.clone(),
+            config: redact_secrets(&config.config),
+            tools,
+            event_types,
+            subscriptions: self.topics.clone(),
+            unknown_events,
+            has_model: self.model.is_some(),
+            lifecycle: self.lifecycle_state(),
+            health: self.check_health().await,
+        })
+    }
+}
+
+/// Words in a setting name marking its value as secret
+const SECRET_WORDS: &[&str] = &[
+    "secret",
+    "token",
+    "password",
+    "passwd",
+    "credential",
+    "credentials",
+    "key",
+    "auth",
+    "authorization",
+];
+
+/// Copy a configuration with the values of secret-looking settings, at any
+/// depth, replaced by [`REDACTED`]
+///
+/// A setting is secret when a word of its name, split at non-alphanumeric
+/// characters, is or ends with one of the [`SECRET_WORDS`], so `api_key`,
+/// `apiKey` and `access_token` are redacted but `max_tokens` is not.
+pub fn redact_secrets(config: &Metadata) -> Metadata {
+    config
+        .iter()
+        .map(|(name, value)| (name.clone(), redact_setting(name, value)))
+        .collect()
+}
+
+/// Redact a setting if its name marks it secret, or the secrets nested in it
+fn redact_setting(name: &str, value: &Value) -> Value {
+    if is_secret(name) {
+        return Value::String(REDACTED.to_string());
+    }
+    match value {
+        Value::Object(settings) => Value::Object(
+            settings
+                .iter()
+                .map(|(name, value)| (name.clone(), redact_setting(name, value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| redact_setting("", value))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// Check whether a setting name marks its value as secret
+fn is_secret(name: &str) -> bool {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| SECRET_WORDS.iter().any(|secret| word.ends_with(secret)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentBuilder, Config};
+    use atlas_mcp::MCPTool;
+
+    struct Search;
+
+    #[async_trait::async_trait]
+    impl MCPTool for Search {
+        fn name(&self) -> &str {
+            "search"
+        }
+
+        fn description(&self) -> &str {
+            "Search the web"
+        }
+
+        fn input_schema(&self) -> Option<serde_json::Value> {
+            Some(serde_json::json!({"type": "object"}))
+        }
+
+        async fn execute(&self, _params: Metadata) -> Result<Metadata> {
+            Ok(Metadata::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_describe() {
+        let agent = AgentBuilder::new()
+            .config(Config {
+                name: "researcher".to_string(),
+                description: Some("Finds sources".to_string()),
+                capabilities: vec!["search".to_string()],
+                config: Metadata::new(),
+            })
+            .tool("search", Search)
+            .subscribe("task.*")
+            .build()
+            .unwrap();
+        agent.on("task.*", |_| async { Ok(()) }).unwrap();
+        agent.on("note", |_| async { Ok(()) }).unwrap();
+
+        let descriptor = agent.describe().await.unwrap();
+        assert_eq!(descriptor.id, agent.id());
+        assert_eq!(descriptor.capabilities, ["search"]);
+        assert_eq!(descriptor.tools[0].name, "search");
+        assert!(descriptor.tools[0].input_schema.is_some());
+        assert_eq!(descriptor.event_types, ["note", "task.*"]);
+        assert_eq!(descriptor.subscriptions, ["task.*"]);
+
+        let json = serde_json::to_value(&descriptor).unwrap();
+        assert_eq!(json["id"], agent.id().to_string());
+        assert_eq!(json["lifecycle"], "running");
+        assert_eq!(json["health"]["level"], "healthy");
+        assert!(!json["has_model"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn test_redact_secrets() {
+        let config = Metadata::try_from(serde_json::json!({
+            "api_key": "sk-1",
+            "max_tokens": 100,
+            "model": {"name": "small", "accessToken": "t-1"},
+            "servers": [{"url": "http://a", "password": "p"}],
+        }))
+        .unwrap();
+        let redacted = serde_json::Value::from(redact_secrets(&config));
+        assert_eq!(redacted["api_key"], REDACTED);
+        assert_eq!(redacted["max_tokens"], 100);
+        assert_eq!(redacted["model"]["name"], "small");
+        assert_eq!(redacted["model"]["accessToken"], REDACTED);
+        assert_eq!(redacted["servers"][0]["url"], "http://a");
+        assert_eq!(redacted["servers"][0]["password"], REDACTED);
+    }
+}
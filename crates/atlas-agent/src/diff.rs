@@ -0,0 +1,104 @@
+//! Differences between state snapshots
+
+use std::collections::BTreeMap;
+
+use atlas_core::Metadata;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Value of a key before and after a change
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueChange {
+    /// Previous value
+    pub old: Value,
+
+    /// New value
+    pub new: Value,
+}
+
+/// Keys added, removed and changed between two snapshots
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StateDiff {
+    /// Keys only in the second snapshot, with their values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub added: BTreeMap<String, Value>,
+
+    /// Keys only in the first snapshot, with their values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub removed: BTreeMap<String, Value>,
+
+    /// Keys in both snapshots with different values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub changed: BTreeMap<String, ValueChange>,
+}
+
+impl StateDiff {
+    /// Whether the snapshots were identical
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compute what changed from snapshot `a` to snapshot `b`
+pub fn diff(a: &Metadata, b: &Metadata) -> StateDiff {
+    let mut a = entries(a);
+    let mut diff = StateDiff::default();
+    for (key, new) in entries(b) {
+        match a.remove(&key) {
+            None => {
+                diff.added.insert(key, new);
+            }
+            Some(old) if old != new => {
+                diff.changed.insert(key, ValueChange { old, new });
+            }
+            Some(_) => {}
+        }
+    }
+    diff.removed.extend(a);
+    diff
+}
+
+/// Keys and values of a snapshot
+fn entries(metadata: &Metadata) -> Map<String, Value> {
+    match serde_json::to_value(metadata) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let mut a = Metadata::new();
+        a.insert("status", "running");
+        a.insert("attempts", 1);
+        a.insert("draft", "v1");
+        let mut b = Metadata::new();
+        b.insert("status", "done");
+        b.insert("attempts", 1);
+        b.insert("answer", 42);
+
+        let changes = diff(&a, &b);
+        assert_eq!(
+            changes.added,
+            BTreeMap::from([("answer".to_string(), json!(42))])
+        );
+        assert_eq!(
+            changes.removed,
+            BTreeMap::from([("draft".to_string(), json!("v1"))])
+        );
+        assert_eq!(
+            changes.changed["status"],
+            ValueChange {
+                old: json!("running"),
+                new: json!("done"),
+            }
+        );
+        assert_eq!(changes.changed.len(), 1);
+        assert!(diff(&a, &a).is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+//! Text embedding providers
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::few_shot::ExampleScorer;
+
+/// Text embedding provider
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Get the provider's name
+    fn name(&self) -> &str;
+
+    /// Get the number of dimensions of each embedding
+    fn dimensions(&self) -> usize;
+
+    /// Embed a batch of texts, returning one vector per text in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Cosine similarity of two vectors, or zero when either is empty or zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Dependency-free embedder using hashed word counts
+///
+/// Vectors only capture word overlap, not meaning; useful for tests and
+/// offline use when no model is available.
+#[derive(Clone, Copy, Debug)]
+pub struct HashEmbedder {
+    /// Number of dimensions
+    dimensions: usize,
+}
+
+impl HashEmbedder {
+    /// Create an embedder with the given number of dimensions
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+        }
+    }
+
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            let bucket = fnv1a(word.to_lowercase().as_bytes()) % self.dimensions as u64;
+            vector[bucket as usize] += 1.0;
+        }
+        vector
+    }
+}
+
+/// 64-bit FNV-1a hash, fixed so stored embeddings stay comparable across
+/// Rust releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashEmbedder {
+    fn name(&self) -> &str {
+        "hash"
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|t| self.embed_one(t)).collect())
+    }
+}
+
+/// Example scorer ranking by embedding similarity
+pub struct EmbeddingScorer {
+    /// Embedding provider
+    provider: Arc<dyn EmbeddingProvider>,
+}
+
+impl EmbeddingScorer {
+    /// Create a new scorer
+    pub fn new(provider: Arc<dyn EmbeddingProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl ExampleScorer for EmbeddingScorer {
+    async fn score(&self, query: &str, candidates: &[&str]) -> Result<Vec<f32>> {
+        let mut texts = Vec::with_capacity(candidates.len() + 1);
+        texts.push(query.to_string());
+        texts.extend(candidates.iter().map(|c| c.to_string()));
+
+        let vectors = self.provider.embed(&texts).await?;
+        let (query, candidates) = vectors
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("Embedding provider returned no vectors"))?;
+        Ok(candidates
+            .iter()
+            .map(|c| cosine_similarity(query, c))
+            .collect())
+    }
+}
+
+#[cfg(feature = "openai")]
+pub use self::openai::OpenAiEmbeddings;
+
+#[cfg(feature = "openai")]
+mod openai {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::error::Error;
+
+    const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+    #[derive(Deserialize)]
+    struct EmbeddingResponse {
+        data: Vec<EmbeddingData>,
+    }
+
+    #[derive(Deserialize)]
+    struct EmbeddingData {
+        index: usize,
+        embedding: Vec<f32>,
+    }
+
+    /// OpenAI embeddings API provider
+    pub struct OpenAiEmbeddings {
+        /// HTTP client
+        client: reqwest::Client,
+
+        /// API key
+        api_key: String,
+
+        /// Embedding model
+        model: String,
+
+        /// API base URL
+        base_url: String,
+
+        /// Number of dimensions
+        dimensions: usize,
+    }
+
+    impl OpenAiEmbeddings {
+        /// Create a provider for `text-embedding-3-small`
+        pub fn new<S: Into<String>>(api_key: S) -> Self {
+            Self {
+                client: reqwest::Client::new(),
+                api_key: api_key.into(),
+                model: "text-embedding-3-small".to_string(),
+                base_url: DEFAULT_BASE_URL.to_string(),
+                dimensions: 1536,
+            }
+        }
+
+        /// Set the model and its number of dimensions
+        pub fn with_model<S: Into<String>>(mut self, model: S, dimensions: usize) -> Self {
+            self.model = model.into();
+            self.dimensions = dimensions;
+            self
+        }
+
+        /// Set the API base URL, for compatible servers
+        pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+            self.base_url = base_url.into();
+            self
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for OpenAiEmbeddings {
+        fn name(&self) -> &str {
+            "openai"
+        }
+
+        fn dimensions(&self) -> usize {
+            self.dimensions
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            if texts.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let response = self
+                .client
+                .post(format!("{}/embeddings", self.base_url))
+                .bearer_auth(&self.api_key)
+                .json(&json!({ "model": self.model, "input": texts }))
+                .send()
+                .await?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(Error::ProviderError(status.as_u16(), body).into());
+            }
+
+            let mut data = response.json::<EmbeddingResponse>().await?.data;
+            data.sort_by_key(|d| d.index);
+            Ok(data.into_iter().map(|d| d.embedding).collect())
+        }
+    }
+}
+
+#[cfg(feature = "fastembed")]
+pub use self::local::LocalEmbeddings;
+
+#[cfg(feature = "fastembed")]
+mod local {
+    use super::*;
+    use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+
+    /// Local ONNX embedding provider backed by fastembed
+    pub struct LocalEmbeddings {
+        /// Loaded model
+        model: Arc<TextEmbedding>,
+
+        /// Number of dimensions
+        dimensions: usize,
+    }
+
+    impl LocalEmbeddings {
+        /// Load `all-MiniLM-L6-v2`, downloading it on first use
+        pub fn new() -> Result<Self> {
+            Self::with_model(EmbeddingModel::AllMiniLML6V2)
+        }
+
+        /// Load a specific model
+        pub fn with_model(model: EmbeddingModel) -> Result<Self> {
+            let dimensions = TextEmbedding::get_model_info(&model)?.dim;
+            let model = TextEmbedding::try_new(InitOptions::new(model))?;
+            Ok(Self {
+                model: Arc::new(model),
+                dimensions,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for LocalEmbeddings {
+        fn name(&self) -> &str {
+            "fastembed"
+        }
+
+        fn dimensions(&self) -> usize {
+            self.dimensions
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            let model = self.model.clone();
+            let texts = texts.to_vec();
+            tokio::task::spawn_blocking(move || model.embed(texts, None)).await?
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::few_shot::{Example, ExampleStore};
+
+    #[tokio::test]
+    async fn test_hash_embedder() {
+        let embedder = HashEmbedder::new(64);
+        let vectors = embedder
+            .embed(&[
+                "Rust async runtime".to_string(),
+                "rust ASYNC".to_string(),
+                "".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(vectors.len(), 3);
+        assert!(vectors.iter().all(|v| v.len() == 64));
+        assert!(cosine_similarity(&vectors[0], &vectors[1]) > 0.5);
+        assert_eq!(cosine_similarity(&vectors[0], &vectors[2]), 0.0);
+    }
+
+    #[test]
+    fn test_hash_embedder_is_stable() {
+        // Stored embeddings depend on these buckets never changing
+        assert_eq!(fnv1a(b"rust"), 0xbffe_df1f_6f66_c727);
+        let vector = HashEmbedder::new(64).embed_one("Rust async rust");
+        let mut expected = vec![0.0; 64];
+        expected[39] = 2.0;
+        expected[47] = 1.0;
+        assert_eq!(vector, expected);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_scorer_selects_examples() {
+        let store = ExampleStore::new().with_scorer(Arc::new(EmbeddingScorer::new(Arc::new(
+            HashEmbedder::default(),
+        ))));
+        store
+            .add("qa", Example::new("capital of france", "Paris"))
+            .await;
+        store
+            .add("qa", Example::new("boiling point of water", "100C"))
+            .await;
+
+        let selected = store
+            .select("qa", "what is the capital of spain", 1)
+            .await
+            .unwrap();
+        assert_eq!(selected[0].output, "Paris");
+    }
+}
@@ -1,5 +1,6 @@
 //! Error types for the agent system
 
+use atlas_core::Metadata;
 use thiserror::Error;
 
 /// Agent error types
@@ -26,6 +27,24 @@ pub enum Error {
     #[error("Memory error: {0}")]
     MemoryError(String),
 
+    #[error("Template error: {0}")]
+    TemplateError(String),
+
+    #[error("Output parse error: {0}")]
+    OutputParseError(String),
+
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(String),
+
+    #[error("Model provider error ({0}): {1}")]
+    ProviderError(u16, String),
+
+    #[error("Guardrail violation: {0}")]
+    GuardrailViolation(String),
+
+    #[error("Tool policy violation: {0}")]
+    ToolPolicyViolation(String),
+
     #[error(transparent)]
     Core(#[from] atlas_core::Error),
 
@@ -43,12 +62,20 @@ impl From<Error> for atlas_core::Error {
     fn from(err: Error) -> Self {
         match err {
             Error::InvalidConfig(msg) => atlas_core::Error::Config(msg),
-            Error::InvalidRequest(msg) => atlas_core::Error::Agent(msg),
+            Error::InvalidRequest(msg) => atlas_core::Error::InvalidRequest(msg),
             Error::ToolNotFound(msg) => atlas_core::Error::Tool(msg),
             Error::ToolExecutionFailed(msg) => atlas_core::Error::Tool(msg),
             Error::StateError(msg) => atlas_core::Error::State(msg),
             Error::TaskError(msg) => atlas_core::Error::Agent(msg),
             Error::MemoryError(msg) => atlas_core::Error::State(msg),
+            Error::TemplateError(msg) => atlas_core::Error::Agent(msg),
+            Error::OutputParseError(msg) => atlas_core::Error::Agent(msg),
+            Error::BudgetExceeded(msg) => atlas_core::Error::Agent(msg),
+            Error::ProviderError(status, msg) => {
+                atlas_core::Error::Agent(format!("Provider returned {}: {}", status, msg))
+            }
+            Error::GuardrailViolation(msg) => atlas_core::Error::InvalidRequest(msg),
+            Error::ToolPolicyViolation(msg) => atlas_core::Error::InvalidRequest(msg),
             Error::Core(e) => e,
             Error::MCP(e) => atlas_core::Error::Other(e.into()),
             Error::Other(e) => atlas_core::Error::Other(e),
@@ -66,6 +93,14 @@ impl From<Error> for atlas_mcp::Error {
             Error::StateError(msg) => atlas_mcp::Error::ServerError(msg),
             Error::TaskError(msg) => atlas_mcp::Error::ServerError(msg),
             Error::MemoryError(msg) => atlas_mcp::Error::ServerError(msg),
+            Error::TemplateError(msg) => atlas_mcp::Error::InvalidRequest(msg),
+            Error::OutputParseError(msg) => atlas_mcp::Error::ServerError(msg),
+            Error::BudgetExceeded(msg) => atlas_mcp::Error::ServerError(msg),
+            Error::ProviderError(status, msg) => {
+                atlas_mcp::Error::ServerError(format!("Provider returned {}: {}", status, msg))
+            }
+            Error::GuardrailViolation(msg) => atlas_mcp::Error::InvalidRequest(msg),
+            Error::ToolPolicyViolation(msg) => atlas_mcp::Error::InvalidRequest(msg),
             Error::Core(e) => atlas_mcp::Error::Other(e.into()),
             Error::MCP(e) => e,
             Error::Other(e) => atlas_mcp::Error::Other(e),
@@ -73,10 +108,73 @@ impl From<Error> for atlas_mcp::Error {
     }
 }
 
+impl Error {
+    /// Get the stable, machine-readable code of the error
+    pub fn code(&self) -> atlas_core::ErrorCode {
+        use atlas_core::ErrorCode;
+
+        match self {
+            Error::InvalidConfig(_) => ErrorCode::Config,
+            Error::InvalidRequest(_)
+            | Error::GuardrailViolation(_)
+            | Error::ToolPolicyViolation(_) => ErrorCode::InvalidRequest,
+            Error::ToolNotFound(_) => ErrorCode::NotFound,
+            Error::ToolExecutionFailed(_) => ErrorCode::Tool,
+            Error::StateError(_) | Error::MemoryError(_) => ErrorCode::State,
+            Error::ProviderError(status, _) if *status == 429 || *status >= 500 => {
+                ErrorCode::Unavailable
+            }
+            Error::TaskError(_)
+            | Error::TemplateError(_)
+            | Error::OutputParseError(_)
+            | Error::BudgetExceeded(_)
+            | Error::ProviderError(..) => ErrorCode::Agent,
+            Error::Core(e) => e.code(),
+            Error::MCP(_) | Error::Other(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl From<Error> for atlas_core::WireError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Core(e) => e.into(),
+            Error::MCP(e) => e.into(),
+            Error::Other(e) => atlas_core::Error::Other(e).into(),
+            err @ Error::ProviderError(status, _) => {
+                let mut details = Metadata::new();
+                details.insert("status", status);
+                Self {
+                    code: err.code(),
+                    message: err.to_string(),
+                    details: Some(details),
+                    retryable: err.code().is_retryable(),
+                }
+            }
+            err => Self::new(err.code(), err.to_string()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_wire_error_conversion() {
+        let wire = atlas_core::WireError::from(Error::ProviderError(503, "overloaded".to_string()));
+        assert_eq!(wire.code, atlas_core::ErrorCode::Unavailable);
+        assert!(wire.retryable);
+        assert_eq!(wire.details.and_then(|d| d.get::<u16>("status")), Some(503));
+
+        let core = atlas_core::Error::Timeout("search".to_string());
+        let wire = atlas_core::WireError::from(Error::Core(core));
+        assert_eq!(wire.code, atlas_core::ErrorCode::Timeout);
+        assert!(wire.retryable);
+        let wire = atlas_core::WireError::from(Error::ToolNotFound("search".to_string()));
+        assert_eq!((wire.code, wire.retryable), (atlas_core::ErrorCode::NotFound, false));
+    }
+
     #[test]
     fn test_error_conversion() {
         let err = Error::InvalidConfig("test error".to_string());
@@ -94,6 +192,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_core_error_codes_match() {
+        let errors = [
+            Error::InvalidRequest("bad".to_string()),
+            Error::GuardrailViolation("blocked".to_string()),
+            Error::ToolPolicyViolation("denied".to_string()),
+        ];
+        for err in errors {
+            let code = err.code();
+            let core: atlas_core::Error = err.into();
+            assert_eq!(core.code(), code);
+            assert!(!core.is_retryable());
+        }
+    }
+
     #[test]
     fn test_error_display() {
         let err = Error::ToolNotFound("test_tool".to_string());
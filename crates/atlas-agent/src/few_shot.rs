@@ -0,0 +1,194 @@
+//! Few-shot example selection
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::llm::ChatMessage;
+
+/// Input/output exemplar
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Example {
+    /// Example input
+    pub input: String,
+
+    /// Expected output
+    pub output: String,
+}
+
+impl Example {
+    /// Create a new example
+    pub fn new<I: Into<String>, O: Into<String>>(input: I, output: O) -> Self {
+        Self {
+            input: input.into(),
+            output: output.into(),
+        }
+    }
+}
+
+/// Scores example inputs by relevance to a query
+#[async_trait]
+pub trait ExampleScorer: Send + Sync {
+    /// Score each candidate against the query; higher is more relevant
+    async fn score(&self, query: &str, candidates: &[&str]) -> Result<Vec<f32>>;
+}
+
+/// Scorer using word overlap (Jaccard similarity)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LexicalScorer;
+
+#[async_trait]
+impl ExampleScorer for LexicalScorer {
+    async fn score(&self, query: &str, candidates: &[&str]) -> Result<Vec<f32>> {
+        let query = words(query);
+        Ok(candidates
+            .iter()
+            .map(|candidate| {
+                let candidate = words(candidate);
+                let union = query.union(&candidate).count();
+                if union == 0 {
+                    0.0
+                } else {
+                    query.intersection(&candidate).count() as f32 / union as f32
+                }
+            })
+            .collect())
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Store of few-shot examples grouped by task type
+///
+/// Examples are registered under a task type (the task name when used by
+/// the reasoning loop), and the most relevant ones for an input are
+/// selected with the configured [`ExampleScorer`].
+pub struct ExampleStore {
+    /// Examples by task type
+    examples: RwLock<HashMap<String, Vec<Example>>>,
+
+    /// Relevance scorer
+    scorer: Arc<dyn ExampleScorer>,
+}
+
+impl Default for ExampleStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExampleStore {
+    /// Create an empty store using lexical scoring
+    pub fn new() -> Self {
+        Self {
+            examples: RwLock::new(HashMap::new()),
+            scorer: Arc::new(LexicalScorer),
+        }
+    }
+
+    /// Set the relevance scorer
+    pub fn with_scorer(mut self, scorer: Arc<dyn ExampleScorer>) -> Self {
+        self.scorer = scorer;
+        self
+    }
+
+    /// Register an example for a task type
+    pub async fn add(&self, task_type: impl Into<String>, example: Example) {
+        self.examples
+            .write()
+            .await
+            .entry(task_type.into())
+            .or_default()
+            .push(example);
+    }
+
+    /// Get all examples for a task type
+    pub async fn list(&self, task_type: &str) -> Vec<Example> {
+        self.examples
+            .read()
+            .await
+            .get(task_type)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Select the `k` examples most relevant to the input, best first
+    pub async fn select(&self, task_type: &str, input: &str, k: usize) -> Result<Vec<Example>> {
+        let examples = self.list(task_type).await;
+        if examples.is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let inputs: Vec<&str> = examples.iter().map(|e| e.input.as_str()).collect();
+        let scores = self.scorer.score(input, &inputs).await?;
+
+        let mut ranked: Vec<(f32, Example)> = scores.into_iter().zip(examples).collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(ranked.into_iter().take(k).map(|(_, e)| e).collect())
+    }
+
+    /// Select examples and format them as user/assistant message pairs
+    pub async fn messages(
+        &self,
+        task_type: &str,
+        input: &str,
+        k: usize,
+    ) -> Result<Vec<ChatMessage>> {
+        Ok(self
+            .select(task_type, input, k)
+            .await?
+            .into_iter()
+            .flat_map(|e| [ChatMessage::user(e.input), ChatMessage::assistant(e.output)])
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_select_most_relevant() {
+        let store = ExampleStore::new();
+        store
+            .add(
+                "translate",
+                Example::new("translate hello to french", "bonjour"),
+            )
+            .await;
+        store
+            .add(
+                "translate",
+                Example::new("translate cat to german", "Katze"),
+            )
+            .await;
+        store
+            .add(
+                "summarize",
+                Example::new("summarize this french text", "..."),
+            )
+            .await;
+
+        let selected = store
+            .select("translate", "translate goodbye to french", 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            selected,
+            vec![Example::new("translate hello to french", "bonjour")]
+        );
+
+        let messages = store.messages("translate", "anything", 5).await.unwrap();
+        assert_eq!(messages.len(), 4);
+        assert!(store.select("missing", "x", 3).await.unwrap().is_empty());
+    }
+}
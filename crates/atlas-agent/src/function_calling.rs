@@ -0,0 +1,273 @@
+//! Provider function-calling formats for tools
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use atlas_core::Metadata;
+use atlas_mcp::ToolInfo;
+
+use crate::error::Error;
+use crate::llm::ToolCall;
+use crate::tool::ToolManager;
+
+/// Function-calling wire format
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolFormat {
+    /// OpenAI chat completions `tools`
+    OpenAI,
+
+    /// Anthropic messages `tools`
+    Anthropic,
+}
+
+impl ToolFormat {
+    /// Export tool definitions in this format
+    pub fn export(&self, tools: &[ToolInfo]) -> Value {
+        match self {
+            ToolFormat::OpenAI => to_openai_tools(tools),
+            ToolFormat::Anthropic => to_anthropic_tools(tools),
+        }
+    }
+
+    /// Parse tool calls from a provider response payload
+    pub fn parse_tool_calls(&self, payload: &Value) -> Result<Vec<ToolCall>> {
+        match self {
+            ToolFormat::OpenAI => parse_openai_tool_calls(payload),
+            ToolFormat::Anthropic => parse_anthropic_tool_calls(payload),
+        }
+    }
+}
+
+/// Schema used for tools that declare no parameters
+fn empty_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {}
+    })
+}
+
+/// Convert tools to the OpenAI `tools` array
+pub fn to_openai_tools(tools: &[ToolInfo]) -> Value {
+    Value::Array(
+        tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.input_schema.clone().unwrap_or_else(empty_schema),
+                    }
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Convert tools to the Anthropic `tools` array
+pub fn to_anthropic_tools(tools: &[ToolInfo]) -> Value {
+    Value::Array(
+        tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.input_schema.clone().unwrap_or_else(empty_schema),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Parse OpenAI tool calls
+///
+/// Accepts a full chat completion, a single message, or a bare `tool_calls`
+/// array. Arguments arrive as JSON-encoded strings and are decoded here.
+pub fn parse_openai_tool_calls(payload: &Value) -> Result<Vec<ToolCall>> {
+    let calls = if let Some(calls) = payload.as_array() {
+        calls
+    } else if let Some(calls) = payload.pointer("/choices/0/message/tool_calls") {
+        calls.as_array().ok_or_else(|| invalid("tool_calls is not an array"))?
+    } else if let Some(calls) = payload.get("tool_calls") {
+        calls.as_array().ok_or_else(|| invalid("tool_calls is not an array"))?
+    } else {
+        return Ok(Vec::new());
+    };
+
+    calls
+        .iter()
+        .map(|call| {
+            let id = call["id"]
+                .as_str()
+                .ok_or_else(|| invalid("tool call is missing an id"))?;
+            let function = call
+                .get("function")
+                .ok_or_else(|| invalid("tool call is missing a function"))?;
+            let name = function["name"]
+                .as_str()
+                .ok_or_else(|| invalid("tool call is missing a function name"))?;
+
+            let arguments = match &function["arguments"] {
+                Value::String(raw) if raw.trim().is_empty() => json!({}),
+                Value::String(raw) => serde_json::from_str(raw).map_err(|e| {
+                    invalid(&format!("invalid arguments for {}: {}", name, e))
+                })?,
+                Value::Null => json!({}),
+                other => other.clone(),
+            };
+
+            Ok(ToolCall {
+                id: id.to_string(),
+                name: name.to_string(),
+                arguments,
+            })
+        })
+        .collect()
+}
+
+/// Parse Anthropic `tool_use` content blocks
+///
+/// Accepts a full message or a bare `content` array.
+pub fn parse_anthropic_tool_calls(payload: &Value) -> Result<Vec<ToolCall>> {
+    let blocks = match payload.get("content").unwrap_or(payload) {
+        Value::Array(blocks) => blocks,
+        _ => return Ok(Vec::new()),
+    };
+
+    blocks
+        .iter()
+        .filter(|block| block["type"] == "tool_use")
+        .map(|block| {
+            let id = block["id"]
+                .as_str()
+                .ok_or_else(|| invalid("tool_use block is missing an id"))?;
+            let name = block["name"]
+                .as_str()
+                .ok_or_else(|| invalid("tool_use block is missing a name"))?;
+
+            Ok(ToolCall {
+                id: id.to_string(),
+                name: name.to_string(),
+                arguments: block.get("input").cloned().unwrap_or_else(|| json!({})),
+            })
+        })
+        .collect()
+}
+
+fn invalid(message: &str) -> anyhow::Error {
+    Error::InvalidRequest(format!("Malformed tool call payload: {}", message)).into()
+}
+
+impl ToolManager {
+    /// Export the registered tools in a provider format
+    pub fn export_tools(&self, format: ToolFormat) -> Value {
+        let mut infos: Vec<ToolInfo> = self.list_tools().into_iter().map(ToolInfo::from).collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        format.export(&infos)
+    }
+
+    /// Execute a tool call returned by a model
+    pub async fn invoke(&self, call: &ToolCall) -> Result<Metadata> {
+        let params = Metadata::try_from(call.arguments.clone())
+            .map_err(|e| invalid(&format!("arguments of {}: {}", call.name, e)))?;
+        self.execute(&call.name, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use atlas_mcp::MCPTool;
+
+    struct WeatherTool;
+
+    #[async_trait]
+    impl MCPTool for WeatherTool {
+        fn name(&self) -> &str {
+            "weather"
+        }
+
+        fn description(&self) -> &str {
+            "Get the weather"
+        }
+
+        fn input_schema(&self) -> Option<Value> {
+            Some(json!({
+                "type": "object",
+                "properties": {"location": {"type": "string"}},
+                "required": ["location"]
+            }))
+        }
+
+        async fn execute(&self, params: Metadata) -> Result<Metadata> {
+            let mut result = Metadata::new();
+            result.insert("location", params.get::<String>("location"));
+            Ok(result)
+        }
+    }
+
+    fn manager() -> ToolManager {
+        let mut manager = ToolManager::new();
+        manager.register("weather".to_string(), WeatherTool);
+        manager
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let manager = manager();
+
+        let openai = manager.export_tools(ToolFormat::OpenAI);
+        assert_eq!(openai[0]["type"], "function");
+        assert_eq!(openai[0]["function"]["name"], "weather");
+        assert_eq!(openai[0]["function"]["parameters"]["required"][0], "location");
+
+        let anthropic = manager.export_tools(ToolFormat::Anthropic);
+        assert_eq!(anthropic[0]["name"], "weather");
+        assert_eq!(anthropic[0]["input_schema"]["type"], "object");
+    }
+
+    #[tokio::test]
+    async fn test_openai_round_trip() {
+        let payload = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "weather", "arguments": "{\"location\":\"Oslo\"}"}
+                    }]
+                }
+            }]
+        });
+
+        let calls = ToolFormat::OpenAI.parse_tool_calls(&payload).unwrap();
+        assert_eq!(calls.len(), 1);
+
+        let result = manager().invoke(&calls[0]).await.unwrap();
+        assert_eq!(result.get::<String>("location"), Some("Oslo".to_string()));
+    }
+
+    #[test]
+    fn test_anthropic_parsing() {
+        let payload = json!({
+            "content": [
+                {"type": "text", "text": "Let me check."},
+                {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {"location": "Lima"}}
+            ]
+        });
+
+        let calls = ToolFormat::Anthropic.parse_tool_calls(&payload).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "toolu_1");
+        assert_eq!(calls[0].arguments["location"], "Lima");
+
+        let bad = json!({"tool_calls": [{"function": {"name": "weather"}}]});
+        assert!(ToolFormat::OpenAI.parse_tool_calls(&bad).is_err());
+    }
+}
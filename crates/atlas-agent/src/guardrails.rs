@@ -0,0 +1,376 @@
+//! Guardrails for model outputs and tool parameters
+
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// Replacement text for redacted content
+pub const REDACTED: &str = "[REDACTED]";
+
+/// What a guardrail is checking
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GuardrailTarget {
+    /// Final output of the model
+    ModelOutput,
+
+    /// Parameters passed to a tool
+    ToolParams {
+        /// Tool name
+        tool: String,
+    },
+}
+
+/// Action taken when a guardrail is violated
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailAction {
+    /// Reject the content with an error
+    Block,
+
+    /// Replace the offending content and continue
+    Redact,
+
+    /// Ask the model for another response; tool parameters are rejected
+    Retry,
+}
+
+/// Result of a single guardrail check
+#[derive(Clone, Debug, PartialEq)]
+pub enum GuardrailOutcome {
+    /// The content is acceptable
+    Pass,
+
+    /// The content violates the guardrail
+    Violation {
+        /// Why the content was rejected
+        reason: String,
+
+        /// Action to take
+        action: GuardrailAction,
+
+        /// Content with the violation removed, for redacting guardrails
+        redacted: Option<String>,
+    },
+}
+
+/// Check applied to model outputs and tool parameters
+#[async_trait]
+pub trait Guardrail: Send + Sync {
+    /// Get the guardrail's name
+    fn name(&self) -> &str;
+
+    /// Check a piece of content
+    async fn check(&self, target: &GuardrailTarget, content: &str) -> Result<GuardrailOutcome>;
+}
+
+/// Regex or keyword content filter
+pub struct ContentFilter {
+    /// Filter name
+    name: String,
+
+    /// Pattern matching disallowed content
+    pattern: Regex,
+
+    /// Action on match
+    action: GuardrailAction,
+}
+
+impl ContentFilter {
+    /// Create a filter matching a regular expression
+    pub fn regex<S: Into<String>>(name: S, pattern: &str, action: GuardrailAction) -> Result<Self> {
+        let pattern = Regex::new(pattern)
+            .map_err(|e| Error::InvalidConfig(format!("Invalid guardrail pattern: {}", e)))?;
+        Ok(Self {
+            name: name.into(),
+            pattern,
+            action,
+        })
+    }
+
+    /// Create a filter matching any of the keywords, ignoring case
+    pub fn keywords<S: Into<String>>(
+        name: S,
+        keywords: &[&str],
+        action: GuardrailAction,
+    ) -> Result<Self> {
+        let alternatives = keywords
+            .iter()
+            .map(|k| regex::escape(k))
+            .collect::<Vec<_>>()
+            .join("|");
+        Self::regex(name, &format!(r"(?i)\b(?:{})\b", alternatives), action)
+    }
+}
+
+#[async_trait]
+impl Guardrail for ContentFilter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self, _target: &GuardrailTarget, content: &str) -> Result<GuardrailOutcome> {
+        if !self.pattern.is_match(content) {
+            return Ok(GuardrailOutcome::Pass);
+        }
+
+        let redacted = match self.action {
+            GuardrailAction::Redact => {
+                Some(self.pattern.replace_all(content, REDACTED).into_owned())
+            }
+            _ => None,
+        };
+        Ok(GuardrailOutcome::Violation {
+            reason: format!("Content matched filter {}", self.name),
+            action: self.action,
+            redacted,
+        })
+    }
+}
+
+/// Requires model outputs to be valid JSON
+pub struct JsonValidity {
+    /// Action when the output is not valid JSON
+    action: GuardrailAction,
+}
+
+impl JsonValidity {
+    /// Create a new JSON validity check
+    pub fn new(action: GuardrailAction) -> Self {
+        Self { action }
+    }
+}
+
+#[async_trait]
+impl Guardrail for JsonValidity {
+    fn name(&self) -> &str {
+        "json_validity"
+    }
+
+    async fn check(&self, target: &GuardrailTarget, content: &str) -> Result<GuardrailOutcome> {
+        if *target != GuardrailTarget::ModelOutput {
+            return Ok(GuardrailOutcome::Pass);
+        }
+
+        match serde_json::from_str::<Value>(content.trim()) {
+            Ok(_) => Ok(GuardrailOutcome::Pass),
+            Err(e) => Ok(GuardrailOutcome::Violation {
+                reason: format!("Output is not valid JSON: {}", e),
+                action: self.action,
+                redacted: None,
+            }),
+        }
+    }
+}
+
+/// Guardrail backed by an async function
+///
+/// The function returns `Some(reason)` when the content should be rejected.
+pub struct FnGuardrail<F> {
+    /// Guardrail name
+    name: String,
+
+    /// Action on violation
+    action: GuardrailAction,
+
+    /// Check function
+    check: F,
+}
+
+impl<F, Fut> FnGuardrail<F>
+where
+    F: Fn(GuardrailTarget, String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Option<String>>> + Send,
+{
+    /// Create a new guardrail from a function
+    pub fn new<S: Into<String>>(name: S, action: GuardrailAction, check: F) -> Self {
+        Self {
+            name: name.into(),
+            action,
+            check,
+        }
+    }
+}
+
+#[async_trait]
+impl<F, Fut> Guardrail for FnGuardrail<F>
+where
+    F: Fn(GuardrailTarget, String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Option<String>>> + Send,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self, target: &GuardrailTarget, content: &str) -> Result<GuardrailOutcome> {
+        Ok(
+            match (self.check)(target.clone(), content.to_string()).await? {
+                Some(reason) => GuardrailOutcome::Violation {
+                    reason,
+                    action: self.action,
+                    redacted: None,
+                },
+                None => GuardrailOutcome::Pass,
+            },
+        )
+    }
+}
+
+/// Result of applying guardrails to a model output
+#[derive(Clone, Debug, PartialEq)]
+pub enum Checked {
+    /// The output may be used, possibly redacted
+    Pass(String),
+
+    /// The model should be asked again
+    Retry(String),
+}
+
+/// Ordered set of guardrails
+///
+/// Guardrails run in order; a redaction is passed on to the following
+/// guardrails, while a block or retry stops the chain.
+#[derive(Clone, Default)]
+pub struct GuardrailSet {
+    /// Guardrails
+    guardrails: Vec<Arc<dyn Guardrail>>,
+}
+
+impl GuardrailSet {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a guardrail
+    pub fn with<G>(mut self, guardrail: G) -> Self
+    where
+        G: Guardrail + 'static,
+    {
+        self.guardrails.push(Arc::new(guardrail));
+        self
+    }
+
+    /// Add a shared guardrail
+    pub fn push(&mut self, guardrail: Arc<dyn Guardrail>) {
+        self.guardrails.push(guardrail);
+    }
+
+    /// Whether the set has no guardrails
+    pub fn is_empty(&self) -> bool {
+        self.guardrails.is_empty()
+    }
+
+    /// Apply the guardrails to a piece of text
+    pub async fn apply(&self, target: &GuardrailTarget, content: &str) -> Result<Checked> {
+        let mut content = content.to_string();
+
+        for guardrail in &self.guardrails {
+            let GuardrailOutcome::Violation {
+                reason,
+                action,
+                redacted,
+            } = guardrail.check(target, &content).await?
+            else {
+                continue;
+            };
+
+            tracing::debug!(guardrail = guardrail.name(), %reason, "Guardrail violated");
+            match (action, redacted) {
+                (GuardrailAction::Redact, Some(redacted)) => content = redacted,
+                (GuardrailAction::Redact, None) => content = REDACTED.to_string(),
+                (GuardrailAction::Retry, _) if *target == GuardrailTarget::ModelOutput => {
+                    return Ok(Checked::Retry(reason));
+                }
+                _ => {
+                    return Err(Error::GuardrailViolation(format!(
+                        "{}: {}",
+                        guardrail.name(),
+                        reason
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        Ok(Checked::Pass(content))
+    }
+
+    /// Apply the guardrails to every string in a JSON value, such as tool parameters
+    pub async fn apply_value(&self, target: &GuardrailTarget, value: &mut Value) -> Result<()> {
+        let mut stack = vec![value];
+        while let Some(value) = stack.pop() {
+            match value {
+                Value::String(s) => match self.apply(target, s).await? {
+                    Checked::Pass(checked) => *s = checked,
+                    Checked::Retry(reason) => return Err(Error::GuardrailViolation(reason).into()),
+                },
+                Value::Array(items) => stack.extend(items.iter_mut()),
+                Value::Object(map) => stack.extend(map.values_mut()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_filters_and_actions() {
+        let guardrails = GuardrailSet::new()
+            .with(ContentFilter::regex("email", r"[\w.]+@[\w.]+", GuardrailAction::Redact).unwrap())
+            .with(
+                ContentFilter::keywords("secrets", &["password"], GuardrailAction::Block).unwrap(),
+            )
+            .with(JsonValidity::new(GuardrailAction::Retry));
+
+        let output = GuardrailTarget::ModelOutput;
+        assert_eq!(
+            guardrails
+                .apply(&output, r#"{"to": "a@b.com"}"#)
+                .await
+                .unwrap(),
+            Checked::Pass(format!(r#"{{"to": "{}"}}"#, REDACTED))
+        );
+        assert!(matches!(
+            guardrails.apply(&output, "not json").await.unwrap(),
+            Checked::Retry(_)
+        ));
+        assert!(guardrails.apply(&output, "my PASSWORD is").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tool_params_and_custom_checks() {
+        let guardrails = GuardrailSet::new()
+            .with(ContentFilter::regex("email", r"[\w.]+@[\w.]+", GuardrailAction::Redact).unwrap())
+            .with(FnGuardrail::new(
+                "no_rm",
+                GuardrailAction::Retry,
+                |_, content: String| async move {
+                    Ok(content
+                        .contains("rm -rf")
+                        .then(|| "Destructive command".to_string()))
+                },
+            ));
+
+        let target = GuardrailTarget::ToolParams {
+            tool: "shell".to_string(),
+        };
+        let mut params = json!({"notify": ["a@b.com"], "cmd": "ls"});
+        guardrails.apply_value(&target, &mut params).await.unwrap();
+        assert_eq!(params["notify"][0], REDACTED);
+
+        let mut params = json!({"cmd": "rm -rf /"});
+        assert!(guardrails.apply_value(&target, &mut params).await.is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Event handler routing for agents
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::Result;
+use atlas_core::{Event, TopicPattern};
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// What an agent does with an event no handler is registered for
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownEventPolicy {
+    /// Merge the payload into agent memory
+    #[default]
+    Remember,
+
+    /// Skip the event
+    Ignore,
+
+    /// Fail handling with an error
+    Reject,
+}
+
+/// Type-erased event handler
+type Handler = Arc<dyn Fn(Event) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// Event handlers of an agent by event type
+///
+/// An event goes to the handler registered for its exact type, or else to
+/// the first handler registered for a pattern matching it, such as `task.*`.
+/// Events no handler matches follow the [`UnknownEventPolicy`].
+#[derive(Clone, Default)]
+pub struct EventRouter {
+    /// Handlers of exact event types
+    routes: HashMap<String, Handler>,
+
+    /// Handlers of wildcard patterns, in registration order
+    patterns: Vec<(TopicPattern, Handler)>,
+
+    /// What to do with unmatched events
+    unknown: UnknownEventPolicy,
+}
+
+impl EventRouter {
+    /// Create a router with no handlers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what to do with events no handler matches
+    pub fn with_unknown(mut self, policy: UnknownEventPolicy) -> Self {
+        self.unknown = policy;
+        self
+    }
+
+    /// Get what happens to events no handler matches
+    pub fn unknown_policy(&self) -> UnknownEventPolicy {
+        self.unknown
+    }
+
+    /// Get the event types and patterns with a handler, sorted
+    pub fn event_types(&self) -> Vec<String> {
+        let mut event_types: Vec<String> = self.routes.keys().cloned().collect();
+        event_types.extend(self.patterns.iter().map(|(pattern, _)| pattern.to_string()));
+        event_types.sort();
+        event_types
+    }
+
+    /// Whether no handlers are registered
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Handle events of a type or pattern, replacing its previous handler
+    pub fn on<F, Fut>(&mut self, event_type: &str, handler: F) -> Result<()>
+    where
+        F: Fn(Event) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |event| Box::pin(handler(event)));
+        let pattern = TopicPattern::parse(event_type)?;
+        if pattern.is_literal() {
+            self.routes.insert(event_type.to_string(), handler);
+        } else if let Some(route) = self.patterns.iter_mut().find(|(p, _)| *p == pattern) {
+            route.1 = handler;
+        } else {
+            self.patterns.push((pattern, handler));
+        }
+        Ok(())
+    }
+
+    /// Handle events of a type or pattern whose payload is a `T`
+    ///
+    /// Events whose payload does not convert fail handling before the
+    /// handler runs.
+    pub fn on_typed<T, F, Fut>(&mut self, event_type: &str, handler: F) -> Result<()>
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(T, Event) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.on(event_type, move |event: Event| {
+            let handler = handler.clone();
+            async move {
+                let payload = event.try_payload::<T>()?;
+                handler(payload, event).await
+            }
+        })
+    }
+
+    /// Start the handler matching an event, if there is one
+    pub fn dispatch(&self, event: &Event) -> Option<BoxFuture<'static, Result<()>>> {
+        let handler = self.routes.get(event.topic()).or_else(|| {
+            self.patterns
+                .iter()
+                .find(|(pattern, _)| pattern.matches(event.topic()))
+                .map(|(_, handler)| handler)
+        })?;
+        Some(handler(event.clone()))
+    }
+}
+
+impl std::fmt::Debug for EventRouter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut event_types: Vec<&str> = self.routes.keys().map(String::as_str).collect();
+        event_types.extend(self.patterns.iter().map(|(pattern, _)| pattern.as_str()));
+        f.debug_struct("EventRouter")
+            .field("event_types", &event_types)
+            .field("unknown", &self.unknown)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::Metadata;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[derive(Deserialize)]
+    struct TaskCreated {
+        priority: u32,
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let seen = Arc::new(AtomicU32::new(0));
+        let mut router = EventRouter::new();
+        router
+            .on_typed("task.created", {
+                let seen = seen.clone();
+                move |task: TaskCreated, _| {
+                    let seen = seen.clone();
+                    async move {
+                        seen.fetch_add(task.priority, Ordering::SeqCst);
+                        Ok(())
+                    }
+                }
+            })
+            .unwrap();
+        router
+            .on("task.*", {
+                let seen = seen.clone();
+                move |_| {
+                    let seen = seen.clone();
+                    async move {
+                        seen.fetch_add(100, Ordering::SeqCst);
+                        Ok(())
+                    }
+                }
+            })
+            .unwrap();
+
+        let mut payload = Metadata::new();
+        payload.insert("priority", 2);
+        router
+            .dispatch(&Event::new("task.created", payload))
+            .unwrap()
+            .await
+            .unwrap();
+        router
+            .dispatch(&Event::new("task.done", Metadata::new()))
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(seen.load(Ordering::SeqCst), 102);
+
+        // The exact handler wins over the pattern, and checks the payload
+        let wrong = router
+            .dispatch(&Event::new("task.created", Metadata::new()))
+            .unwrap()
+            .await;
+        assert!(wrong.unwrap_err().to_string().contains("priority"));
+        assert!(router
+            .dispatch(&Event::new("note", Metadata::new()))
+            .is_none());
+    }
+}
@@ -0,0 +1,146 @@
+//! Health reporting for agents
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use atlas_core::{HealthLevel, HealthStatus};
+
+use crate::lifecycle::LifecycleState;
+use crate::Agent;
+
+/// Thresholds at which an agent reports itself degraded or unhealthy
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Tasks and events in progress beyond which the agent is backlogged
+    pub max_active: usize,
+
+    /// Consecutive failures after which a tool is reported failing
+    pub max_tool_failures: u32,
+
+    /// How long memory changes may wait to be persisted before persistence
+    /// is reported stale, in milliseconds
+    pub persistence_timeout_ms: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            max_active: 32,
+            max_tool_failures: 3,
+            persistence_timeout_ms: 1_000,
+        }
+    }
+}
+
+impl Agent {
+    /// Check the lifecycle, work in progress, tools and memory persistence
+    pub(crate) async fn check_health(&self) -> HealthStatus {
+        let config = &self.health;
+        let mut status = HealthStatus::healthy();
+        match self.lifecycle.state() {
+            LifecycleState::Running => {}
+            LifecycleState::Paused => {
+                status = status.with_issue(HealthLevel::Degraded, "Agent is paused");
+            }
+            LifecycleState::Stopping | LifecycleState::Stopped => {
+                status = status.with_issue(HealthLevel::Unhealthy, "Agent is stopped");
+            }
+        }
+
+        let active = self.lifecycle.active();
+        if active > config.max_active {
+            status = status.with_issue(
+                HealthLevel::Degraded,
+                format!("{} tasks and events in progress", active),
+            );
+        }
+
+        let mut failing = self.tools.read().await.tool_failures();
+        failing.sort();
+        for (tool, failures) in failing {
+            if failures >= config.max_tool_failures {
+                status = status.with_issue(
+                    HealthLevel::Degraded,
+                    format!("Tool {} failed {} times in a row", tool, failures),
+                );
+            }
+        }
+
+        if let Some(memory) = &self.memory {
+            let persistence = memory.persistence_status();
+            let timeout = Duration::from_millis(config.persistence_timeout_ms);
+            if let Some(e) = persistence.failure {
+                status = status.with_issue(
+                    HealthLevel::Unhealthy,
+                    format!("Memory persistence failing: {}", e),
+                );
+            } else if matches!(persistence.pending_since, Some(since) if since.elapsed() > timeout)
+            {
+                status = status.with_issue(HealthLevel::Degraded, "Memory persistence is stale");
+            }
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentBuilder, Config};
+    use anyhow::Result;
+    use atlas_core::{Agent as CoreAgent, Metadata, TaskId};
+    use atlas_mcp::MCPTool;
+
+    struct Broken;
+
+    #[async_trait::async_trait]
+    impl MCPTool for Broken {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn description(&self) -> &str {
+            "Always fails"
+        }
+
+        async fn execute(&self, _params: Metadata) -> Result<Metadata> {
+            Err(anyhow::anyhow!("connection refused"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let agent = AgentBuilder::new()
+            .config(Config {
+                name: "probed".to_string(),
+                description: None,
+                capabilities: vec![],
+                config: Metadata::new(),
+            })
+            .tool("broken", Broken)
+            .health(HealthConfig {
+                max_tool_failures: 2,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        assert!(agent.health().await.is_healthy());
+
+        let mut params = Metadata::new();
+        params.insert("tool", "broken");
+        for _ in 0..2 {
+            assert!(agent
+                .execute_task(TaskId::new(), params.clone())
+                .await
+                .is_err());
+        }
+        let health = agent.health().await;
+        assert_eq!(health.level, HealthLevel::Degraded);
+        assert_eq!(health.issues, ["Tool broken failed 2 times in a row"]);
+
+        agent.on_stop().await.unwrap();
+        assert_eq!(agent.health().await.level, HealthLevel::Unhealthy);
+    }
+}
@@ -0,0 +1,161 @@
+//! Heartbeats of agents and how their absence is judged
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use atlas_core::{Event, HealthLevel, Metadata, ValidationErrors};
+
+use crate::Agent;
+
+/// Topic agents beat on
+pub const AGENT_HEARTBEAT: &str = "agent.heartbeat";
+
+/// Payload key naming the agent a heartbeat is from
+pub const AGENT_KEY: &str = "agent";
+
+/// Payload key holding the ID of the running instance a heartbeat is from,
+/// telling apart agents sharing a name
+pub const INSTANCE_KEY: &str = "instance";
+
+/// Payload key holding the [`AgentId`](atlas_core::AgentId) of a beating
+/// agent, for registries
+pub const ID_KEY: &str = "id";
+
+/// Payload key holding the health of a beating agent
+pub const HEALTH_KEY: &str = "health";
+
+/// How often agents beat and how many missed beats are tolerated
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HeartbeatConfig {
+    /// Time between beats, in milliseconds
+    pub interval_ms: u64,
+
+    /// Missed beats after which an agent is stale and no longer routed to
+    pub stale_after: u32,
+
+    /// Missed beats after which an agent is dead and reported failed
+    pub dead_after: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 1_000,
+            stale_after: 3,
+            dead_after: 10,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// Get the time between beats
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+
+    /// Judge an agent by the time since its last beat
+    pub fn liveness(&self, silence: Duration) -> Liveness {
+        let missed = silence.as_millis() / u128::from(self.interval_ms.max(1));
+        if missed >= u128::from(self.dead_after) {
+            Liveness::Dead
+        } else if missed >= u128::from(self.stale_after) {
+            Liveness::Stale
+        } else {
+            Liveness::Alive
+        }
+    }
+
+    /// Check that the interval is positive and agents go stale before they
+    /// are dead
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+        errors.ensure(self.interval_ms > 0, "Heartbeat interval must be positive");
+        errors.ensure(
+            self.stale_after > 0,
+            "Agents must miss at least one heartbeat to go stale",
+        );
+        errors.ensure(
+            self.dead_after > self.stale_after,
+            "Agents must go stale before they are dead",
+        );
+        errors.into_result()
+    }
+}
+
+/// Whether an agent beats as often as it should
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    /// Beating, or not watched
+    #[default]
+    Alive,
+
+    /// Missed a few beats; tasks are not routed to it
+    Stale,
+
+    /// Missed so many beats it is reported failed
+    Dead,
+}
+
+impl Agent {
+    /// Beat on the agent's event bus for as long as it answers health checks
+    /// in time without being unhealthy, so registries watching heartbeats
+    /// drop it once it hangs, breaks or is dropped
+    pub fn spawn_heartbeat(self: &Arc<Self>, config: HeartbeatConfig) -> Result<JoinHandle<()>> {
+        config.validate()?;
+        let bus = self.event_bus()?.clone();
+        let weak = Arc::downgrade(self);
+        Ok(tokio::spawn(async move {
+            if let Err(e) = bus.register_topic(AGENT_HEARTBEAT).await {
+                tracing::warn!(error = %e, "Failed to register heartbeat topic");
+            }
+            let mut ticks = tokio::time::interval(config.interval());
+            loop {
+                ticks.tick().await;
+                let Some(agent) = weak.upgrade() else {
+                    return;
+                };
+                let health =
+                    match tokio::time::timeout(config.interval(), agent.check_health()).await {
+                        Ok(health) if health.level != HealthLevel::Unhealthy => health,
+                        _ => continue,
+                    };
+                let mut payload = Metadata::new();
+                payload.insert(AGENT_KEY, &agent.config().name);
+                payload.insert(INSTANCE_KEY, agent.id);
+                payload.insert(ID_KEY, agent.id);
+                payload.insert(HEALTH_KEY, &health);
+                if let Err(e) = bus.publish(Event::new(AGENT_HEARTBEAT, payload)).await {
+                    tracing::debug!(agent = %agent.id, error = %e, "Failed to publish heartbeat");
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_liveness() {
+        let config = HeartbeatConfig {
+            interval_ms: 100,
+            stale_after: 2,
+            dead_after: 5,
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.liveness(Duration::from_millis(150)), Liveness::Alive);
+        assert_eq!(config.liveness(Duration::from_millis(200)), Liveness::Stale);
+        assert_eq!(config.liveness(Duration::from_millis(500)), Liveness::Dead);
+        let config = HeartbeatConfig {
+            dead_after: 2,
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -2,31 +2,112 @@
 //! 
 //! This crate provides the agent implementation for the Atlas framework.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::StreamExt;
+use tracing::Instrument;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use atlas_core::{Agent as CoreAgent, AgentConfig, AgentState, Metadata, Tool};
+use atlas_core::{
+    Agent as CoreAgent, AgentConfig, AgentId, AgentState, CancellationContext, Cause,
+    ConfigLoader, EventBus, HealthStatus, Metadata, Tool, TraceContext, ValidationErrors,
+};
 use atlas_mcp::{MCPTool, ToolInfo};
 
+pub mod agent_loop;
+pub mod aggregation;
+pub mod chat;
+pub mod descriptor;
+pub mod diff;
+pub mod embedding;
 pub mod error;
+pub mod few_shot;
+pub mod function_calling;
+pub mod guardrails;
+pub mod handler;
+pub mod health;
+pub mod heartbeat;
+pub mod lifecycle;
+pub mod llm;
+pub mod memory;
+pub mod messaging;
+pub mod output_parser;
+pub mod planner;
+pub mod policy;
+pub mod progress;
+pub mod prompt;
+pub mod reflection;
+pub mod registry;
+pub mod reload;
+pub mod router;
+pub mod snapshot;
 pub mod state;
+pub mod system_prompt;
 pub mod tool;
 pub mod types;
+pub mod usage;
 
 // Re-exports
+pub use agent_loop::{AgentLoop, LoopOutcome, StepObserver, StopReason, TaskStep};
+pub use aggregation::{
+    Aggregate, AggregationStrategy, Aggregator, BestOfScore, Contribution, LlmJudge, MajorityVote,
+};
+pub use chat::{ChatSession, HistoryPolicy};
+pub use descriptor::AgentDescriptor;
+pub use diff::{diff, StateDiff, ValueChange};
+pub use embedding::{cosine_similarity, EmbeddingProvider, EmbeddingScorer, HashEmbedder};
 pub use error::Error;
-pub use state::AgentStateManager;
+pub use few_shot::{Example, ExampleScorer, ExampleStore, LexicalScorer};
+pub use function_calling::ToolFormat;
+pub use guardrails::{
+    ContentFilter, FnGuardrail, Guardrail, GuardrailAction, GuardrailSet, GuardrailTarget,
+    JsonValidity,
+};
+pub use handler::{EventRouter, UnknownEventPolicy};
+pub use health::HealthConfig;
+pub use heartbeat::{HeartbeatConfig, Liveness, AGENT_HEARTBEAT};
+pub use lifecycle::{LifecycleState, DEFAULT_PAUSE_QUEUE};
+pub use llm::{
+    CacheConfig, CachedModel, ChatMessage, CompletionRequest, CompletionResponse, LanguageModel,
+    ModelRouter, Role, RouteTarget, RoutingDecision, RoutingRule,
+};
+pub use messaging::{AgentRef, Envelope, ExecuteTask, Message, DEFAULT_ASK_TIMEOUT};
+pub use output_parser::OutputParser;
+pub use planner::{LlmPlanner, PlanStep, Planner, TaskPlan};
+pub use progress::{DEFAULT_PROGRESS_INTERVAL, TASK_PROGRESS};
+pub use policy::{ParamConstraint, ParamRule, ToolPolicy, TOOL_POLICY_VIOLATION};
+pub use prompt::PromptTemplate;
+pub use reflection::{Critic, Critique, LlmCritic, ReflectionConfig, ReflectionEntry, Verdict};
+pub use registry::{AgentRegistry, REGISTRY_TOPIC};
+pub use reload::{ConfigWatcher, CONFIG_RELOADED, TOOL_OPTIONS_KEY};
+pub use router::{Balancer, DelegateStats, Dispatch, RouterAgent, RouterConfig};
+pub use snapshot::AgentSnapshot;
+pub use memory::{
+    Blackboard, BlackboardChange, BlackboardSection, CompactionConfig, DedupConfig,
+    DuplicateAction, EvictionPolicy, HnswIndex, InMemoryStore, MemoryCompactor, MemoryCursor,
+    MemoryFilter, MemoryKind, MemoryScope, MemoryStore, PromotionConfig, ScoringConfig,
+    SectionAccess, SharedMemory, SpillConfig, StateStore, TagExpr, TaskStore,
+};
+pub use state::{
+    AgentStateManager, MemoryConfig, MemoryEntry, MemoryFormat, MemoryPage, PersistenceBackend,
+    StateChange, StateTransaction,
+};
+pub use system_prompt::SystemPromptBuilder;
 pub use tool::ToolManager;
 pub use types::{AgentContext, AgentResponse, TaskConfig};
+pub use usage::{
+    CostSummary, MeteredModel, ModelPrice, PriceTable, UsageReport, UsageTracker, METRICS_KEY,
+};
+
+use lifecycle::Lifecycle;
 
 /// Agent configuration
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Config {
     /// Agent name
     pub name: String,
@@ -35,18 +116,51 @@ pub struct Config {
     pub description: Option<String>,
     
     /// Agent capabilities
+    #[serde(default)]
     pub capabilities: Vec<String>,
     
     /// Agent configuration
+    #[serde(default)]
     pub config: Metadata,
 }
 
+impl Config {
+    /// Prefix of the environment variables overriding file settings
+    pub const ENV_PREFIX: &'static str = "ATLAS_AGENT";
+
+    /// Load and validate a configuration file in JSON, TOML or YAML, going by
+    /// its extension
+    ///
+    /// Settings may reference environment variables as `${VAR}`, and
+    /// variables such as `ATLAS_AGENT_NAME` override them.
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let config: Self = ConfigLoader::new()
+            .with_file(path)
+            .with_env_prefix(Self::ENV_PREFIX)
+            .load()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Record the problems with the configuration on its own
+    fn check(&self, errors: &mut ValidationErrors) {
+        errors.ensure(!self.name.trim().is_empty(), "Agent name is required");
+        let mut seen = HashSet::new();
+        for capability in &self.capabilities {
+            if capability.trim().is_empty() {
+                errors.push("Agent capabilities must not be blank");
+            } else if !seen.insert(capability) {
+                errors.push(format!("Capability {} is declared more than once", capability));
+            }
+        }
+    }
+}
+
 impl AgentConfig for Config {
     fn validate(&self) -> Result<()> {
-        if self.name.is_empty() {
-            return Err(Error::InvalidConfig("Agent name is required".to_string()).into());
-        }
-        Ok(())
+        let mut errors = ValidationErrors::new();
+        self.check(&mut errors);
+        errors.into_result()
     }
 }
 
@@ -88,6 +202,14 @@ pub struct TaskState {
     
     /// Task error
     pub error: Option<String>,
+
+    /// Reasoning steps taken while executing the task
+    #[serde(default)]
+    pub steps: Vec<TaskStep>,
+
+    /// Model usage and cost attributed to the task
+    #[serde(default)]
+    pub usage: CostSummary,
 }
 
 /// Task status
@@ -110,9 +232,25 @@ pub enum TaskStatus {
 /// Atlas agent builder
 #[derive(Default)]
 pub struct AgentBuilder {
+    id: Option<AgentId>,
     config: Option<Config>,
     tools: Vec<(String, Box<dyn MCPTool>)>,
     state: Option<State>,
+    model: Option<Arc<dyn LanguageModel>>,
+    reflection: Option<ReflectionConfig>,
+    prices: PriceTable,
+    examples: Option<(Arc<ExampleStore>, usize)>,
+    guardrails: GuardrailSet,
+    tool_policy: Option<ToolPolicy>,
+    system_prompt: Option<SystemPromptBuilder>,
+    memory: Option<AgentStateManager>,
+    shared_memory: Option<SharedMemory>,
+    event_bus: Option<Arc<dyn EventBus>>,
+    topics: Vec<String>,
+    handlers: EventRouter,
+    health: HealthConfig,
+    pause_queue: Option<usize>,
+    progress_interval: Option<std::time::Duration>,
 }
 
 impl AgentBuilder {
@@ -136,38 +274,233 @@ impl AgentBuilder {
         self
     }
 
+    /// Set the agent's ID, such as one it was registered under before a
+    /// restart; a new ID is generated otherwise
+    pub fn id(mut self, id: AgentId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
     /// Set the initial agent state
     pub fn state(mut self, state: State) -> Self {
         self.state = Some(state);
         self
     }
 
+    /// Set the language model used for chat and reasoning
+    pub fn model<M>(mut self, model: M) -> Self
+    where
+        M: LanguageModel + 'static,
+    {
+        self.model = Some(Arc::new(model));
+        self
+    }
+
+    /// Enable self-critique of task answers
+    pub fn reflection(mut self, config: ReflectionConfig) -> Self {
+        self.reflection = Some(config);
+        self
+    }
+
+    /// Set the model price table used for cost accounting
+    pub fn price_table(mut self, prices: PriceTable) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    /// Include the `k` most relevant few-shot examples in task prompts
+    pub fn examples(mut self, store: Arc<ExampleStore>, k: usize) -> Self {
+        self.examples = Some((store, k));
+        self
+    }
+
+    /// Add a guardrail applied to model outputs and tool parameters
+    pub fn guardrail<G>(mut self, guardrail: G) -> Self
+    where
+        G: Guardrail + 'static,
+    {
+        self.guardrails = self.guardrails.with(guardrail);
+        self
+    }
+
+    /// Restrict which tools the agent may run and with which parameters;
+    /// refused executions are reported on the event bus
+    pub fn tool_policy(mut self, policy: ToolPolicy) -> Self {
+        self.tool_policy = Some(policy);
+        self
+    }
+
+    /// Set the system prompt builder; registered tools are added when the prompt is built
+    pub fn system_prompt(mut self, builder: SystemPromptBuilder) -> Self {
+        self.system_prompt = Some(builder);
+        self
+    }
+
+    /// Set the long-term memory included in snapshots
+    pub fn memory(mut self, memory: AgentStateManager) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Attach memory shared with other agents holding a clone of the same handle
+    pub fn shared_memory(mut self, memory: SharedMemory) -> Self {
+        self.shared_memory = Some(memory);
+        self
+    }
+
+    /// Set the event bus the agent publishes to and listens on
+    pub fn event_bus(mut self, bus: Arc<dyn EventBus>) -> Self {
+        self.event_bus = Some(bus);
+        self
+    }
+
+    /// Handle the events published on a topic, or on topics matching a pattern
+    /// such as `task.*`, once the agent listens
+    pub fn subscribe(mut self, topic: impl Into<String>) -> Self {
+        self.topics.push(topic.into());
+        self
+    }
+
+    /// Set the handlers events are dispatched to by type
+    pub fn event_router(mut self, router: EventRouter) -> Self {
+        self.handlers = router;
+        self
+    }
+
+    /// Set what happens to events no handler is registered for
+    pub fn unknown_events(mut self, policy: UnknownEventPolicy) -> Self {
+        self.handlers = self.handlers.with_unknown(policy);
+        self
+    }
+
+    /// Set the thresholds at which the agent reports itself degraded or
+    /// unhealthy
+    pub fn health(mut self, config: HealthConfig) -> Self {
+        self.health = config;
+        self
+    }
+
+    /// Set how many tasks and events may wait while the agent is paused;
+    /// more are refused until it resumes
+    pub fn pause_queue(mut self, capacity: usize) -> Self {
+        self.pause_queue = Some(capacity);
+        self
+    }
+
+    /// Set how often the agent reports the progress of a running task on
+    /// its event bus
+    pub fn progress_interval(mut self, interval: std::time::Duration) -> Self {
+        self.progress_interval = Some(interval);
+        self
+    }
+
     /// Build the agent
-    pub fn build(self) -> Result<Agent> {
-        let config = self.config.ok_or_else(|| {
+    ///
+    /// Fails listing every problem found if the configuration is invalid or
+    /// disagrees with the registered tools.
+    pub fn build(mut self) -> Result<Agent> {
+        let config = self.config.take().ok_or_else(|| {
             Error::InvalidConfig("Agent configuration is required".to_string())
         })?;
+        self.validate(&config)?;
 
         let mut tool_manager = ToolManager::new();
         for (name, tool) in self.tools {
             tool_manager.register(name, tool);
         }
+        if let Some(policy) = self.tool_policy {
+            tool_manager.set_policy(policy);
+        }
+        if let Some(bus) = &self.event_bus {
+            tool_manager.set_audit(bus.clone(), config.name.clone());
+        }
 
         let state = self.state.unwrap_or_default();
 
         Ok(Agent {
-            config,
+            id: self.id.unwrap_or_default(),
+            config: std::sync::RwLock::new(Arc::new(config)),
             state: Arc::new(RwLock::new(state)),
             tools: Arc::new(RwLock::new(tool_manager)),
+            model: self.model,
+            reflection: self.reflection,
+            usage: Arc::new(UsageTracker::new(self.prices)),
+            examples: self.examples,
+            guardrails: self.guardrails,
+            system_prompt: self.system_prompt,
+            memory: self.memory.map(Arc::new),
+            shared_memory: self.shared_memory,
+            event_bus: self.event_bus,
+            topics: self.topics,
+            handlers: std::sync::RwLock::new(self.handlers),
+            lifecycle: Lifecycle::new(self.pause_queue.unwrap_or(DEFAULT_PAUSE_QUEUE)),
+            health: self.health,
+            progress_interval: self
+                .progress_interval
+                .unwrap_or(DEFAULT_PROGRESS_INTERVAL),
         })
     }
+
+    /// Check the configuration and that it agrees with the registered tools
+    fn validate(&self, config: &Config) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+        config.check(&mut errors);
+        let mut names = HashSet::new();
+        for (name, _) in &self.tools {
+            if name.trim().is_empty() {
+                errors.push("Tool names must not be blank");
+            } else if !names.insert(name.as_str()) {
+                errors.push(format!("Tool {} is registered more than once", name));
+            }
+        }
+        match reload::tool_options(config) {
+            Ok(options) => {
+                let mut unknown: Vec<_> = options
+                    .keys()
+                    .filter(|name| !names.contains(name.as_str()))
+                    .collect();
+                unknown.sort();
+                for name in unknown {
+                    errors.push(format!("Options are set for unregistered tool {}", name));
+                }
+            }
+            Err(e) => errors.push(format!("Invalid tool options: {}", e)),
+        }
+        if let Some(policy) = &self.tool_policy {
+            let mut unknown: Vec<_> = policy
+                .named()
+                .filter(|name| !names.contains(name))
+                .collect();
+            unknown.sort();
+            unknown.dedup();
+            for name in unknown {
+                errors.push(format!("Tool policy names unregistered tool {}", name));
+            }
+        }
+        errors.into_result()
+    }
 }
 
 /// Atlas agent
 pub struct Agent {
-    config: Config,
+    id: AgentId,
+    config: std::sync::RwLock<Arc<Config>>,
     state: Arc<RwLock<State>>,
     tools: Arc<RwLock<ToolManager>>,
+    model: Option<Arc<dyn LanguageModel>>,
+    reflection: Option<ReflectionConfig>,
+    usage: Arc<UsageTracker>,
+    examples: Option<(Arc<ExampleStore>, usize)>,
+    guardrails: GuardrailSet,
+    system_prompt: Option<SystemPromptBuilder>,
+    memory: Option<Arc<AgentStateManager>>,
+    shared_memory: Option<SharedMemory>,
+    event_bus: Option<Arc<dyn EventBus>>,
+    topics: Vec<String>,
+    handlers: std::sync::RwLock<EventRouter>,
+    lifecycle: Lifecycle,
+    health: HealthConfig,
+    progress_interval: std::time::Duration,
 }
 
 #[async_trait]
@@ -186,37 +519,143 @@ impl CoreAgent for Agent {
     }
 
     async fn handle_event(&self, event: atlas_core::Event) -> Result<()> {
-        let mut state = self.state.write().await;
-        state.update(event.payload)?;
-        Ok(())
+        let work = self.lifecycle.admit().await?;
+        let (handler, unknown) = {
+            let handlers = self.handlers()?;
+            (handlers.dispatch(&event), handlers.unknown_policy())
+        };
+        // Continue the publisher's trace, if it sent one
+        let trace = TraceContext::of(&event)
+            .map_or_else(TraceContext::root, |parent| parent.child());
+        let span = tracing::info_span!(
+            "handle_event",
+            agent = %self.config().name,
+            event_type = %event.event_type,
+            event_id = %event.id,
+            trace_id = %trace.trace_id(),
+            span_id = %trace.span_id(),
+            parent_span_id = trace.parent_span_id().as_deref(),
+        );
+        let cause = Cause::of(&event);
+        let handling = async {
+            if let Some(handler) = handler {
+                return handler.await;
+            }
+            match unknown {
+                UnknownEventPolicy::Remember => {
+                    let mut state = self.state.write().await;
+                    state.update(event.payload)
+                }
+                UnknownEventPolicy::Ignore => {
+                    tracing::debug!(event_type = %event.event_type, "Ignoring unhandled event");
+                    Ok(())
+                }
+                UnknownEventPolicy::Reject => Err(Error::InvalidRequest(format!(
+                    "No handler for event {}",
+                    event.event_type
+                ))
+                .into()),
+            }
+        };
+        work.run(cause.scope(trace.scope(handling.instrument(span))))
+            .await
     }
 
     async fn execute_task(&self, task_id: atlas_core::TaskId, params: Metadata) -> Result<Metadata> {
-        let mut state = self.state.write().await;
+        let cancel = CancellationContext::current().unwrap_or_default();
+        self.execute_task_with(task_id, params, &cancel).await
+    }
+
+    async fn execute_task_with(
+        &self,
+        task_id: atlas_core::TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        let work = self.lifecycle.admit().await?;
+        let id = *task_id.as_uuid();
         
-        // Create task state
+        // Create task state, releasing the lock before executing
         let task_state = TaskState {
-            id: *task_id,
+            id,
             status: TaskStatus::Running,
             result: None,
             error: None,
+            steps: Vec::new(),
+            usage: CostSummary::default(),
         };
-        state.tasks.insert(*task_id, task_state);
+        self.state.write().await.tasks.insert(id, task_state);
 
         // Execute task
-        match self.execute_with_tools(params).await {
-            Ok(result) => {
-                state.tasks.get_mut(task_id).unwrap().status = TaskStatus::Completed;
-                state.tasks.get_mut(task_id).unwrap().result = Some(result.clone());
-                Ok(result)
+        let trace = TraceContext::child_of_current();
+        let span = task_span(id, &trace);
+        let execution = trace.scope(cancel.run(self.execute_with_tools(params)).instrument(span));
+        let result = Cause::task(id)
+            .scope(self.reporting_progress(work.run(execution)))
+            .await;
+
+        let mut state = self.state.write().await;
+        if let Some(task_state) = state.tasks.get_mut(&id) {
+            match &result {
+                Ok(result) => {
+                    task_state.status = TaskStatus::Completed;
+                    task_state.result = Some(result.clone());
+                }
+                Err(e) => {
+                    task_state.status = TaskStatus::Failed;
+                    task_state.error = Some(e.to_string());
+                }
             }
-            Err(e) => {
-                state.tasks.get_mut(task_id).unwrap().status = TaskStatus::Failed;
-                state.tasks.get_mut(task_id).unwrap().error = Some(e.to_string());
-                Err(e)
+        }
+        result
+    }
+
+    async fn on_start(&self) -> Result<()> {
+        match self.lifecycle.state() {
+            LifecycleState::Stopping => Err(Error::InvalidRequest(
+                "Cannot start an agent while it stops".to_string(),
+            )
+            .into()),
+            LifecycleState::Paused => Ok(()),
+            _ => {
+                self.lifecycle.set(LifecycleState::Running);
+                Ok(())
+            }
+        }
+    }
+
+    async fn on_stop(&self) -> Result<()> {
+        if self.lifecycle.state() != LifecycleState::Stopped {
+            self.lifecycle.set(LifecycleState::Stopping);
+            self.lifecycle.drained().await;
+            self.lifecycle.set(LifecycleState::Stopped);
+        }
+        Ok(())
+    }
+
+    async fn pause(&self) -> Result<()> {
+        match self.lifecycle.state() {
+            LifecycleState::Running | LifecycleState::Paused => {
+                self.lifecycle.set(LifecycleState::Paused);
+                Ok(())
             }
+            _ => Err(Error::InvalidRequest("Cannot pause a stopped agent".to_string()).into()),
         }
     }
+
+    async fn resume(&self) -> Result<()> {
+        match self.lifecycle.state() {
+            LifecycleState::Running | LifecycleState::Paused => {
+                self.lifecycle.set(LifecycleState::Running);
+                Ok(())
+            }
+            _ => Err(Error::InvalidRequest("Cannot resume a stopped agent".to_string()).into()),
+        }
+    }
+
+    async fn health(&self) -> HealthStatus {
+        self.check_health().await
+    }
 }
 
 impl Agent {
@@ -225,25 +664,334 @@ impl Agent {
         AgentBuilder::new()
     }
 
-    /// Get the agent's configuration
-    pub fn config(&self) -> &Config {
-        &self.config
+    /// Get the agent's ID
+    pub fn id(&self) -> AgentId {
+        self.id
+    }
+
+    /// Get the stage of the agent's lifecycle
+    pub fn lifecycle_state(&self) -> LifecycleState {
+        self.lifecycle.state()
+    }
+
+    /// Get the agent's current configuration
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
     }
 
     /// Get a list of available tools
     pub async fn list_tools(&self) -> Result<Vec<ToolInfo>> {
         let tools = self.tools.read().await;
-        let mut tool_list = Vec::new();
-
-        for (name, tool) in tools.tools.iter() {
-            tool_list.push(ToolInfo {
-                name: name.clone(),
-                description: tool.description().to_string(),
-                input_schema: None,
-            });
+        Ok(tools.list_tools().into_iter().map(ToolInfo::from).collect())
+    }
+
+    /// Build the agent's system prompt from its configuration and registered tools
+    pub async fn system_prompt(&self) -> Result<String> {
+        let builder = self
+            .system_prompt
+            .clone()
+            .unwrap_or_else(|| SystemPromptBuilder::from_config(&self.config()));
+        Ok(builder.tools(self.list_tools().await?).build())
+    }
+
+    /// Get the agent's language model
+    pub fn model(&self) -> Result<Arc<dyn LanguageModel>> {
+        self.model
+            .clone()
+            .ok_or_else(|| Error::InvalidConfig("No language model configured".to_string()).into())
+    }
+
+    /// Get token usage and cost recorded by this agent
+    pub fn usage_report(&self) -> UsageReport {
+        self.usage.report()
+    }
+
+    /// Wrap the agent's language model so its usage is recorded
+    fn metered_model(&self) -> Result<MeteredModel> {
+        Ok(MeteredModel::new(self.model()?, self.usage.clone()))
+    }
+
+    /// Send a message in a chat session, creating the session if needed
+    ///
+    /// The session history is loaded from and saved back to agent memory, so
+    /// consecutive calls with the same session ID form one conversation.
+    pub async fn chat(&self, session_id: &str, content: impl Into<String>) -> Result<ChatMessage> {
+        let model = self.metered_model()?;
+
+        let mut session = match ChatSession::load(&self.state, session_id).await? {
+            Some(session) => session,
+            None => ChatSession::new(session_id).with_system_prompt(self.system_prompt().await?),
+        };
+
+        let reply = session.send(&model, content).await?;
+        session.save(&self.state).await?;
+        Ok(reply)
+    }
+
+    /// Ask the language model for a value of type `T`, validated against its schema
+    pub async fn complete_structured<T>(&self, request: CompletionRequest) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned + schemars::JsonSchema,
+    {
+        let model = self.metered_model()?;
+        OutputParser::<T>::new()?.complete(&model, request).await
+    }
+
+    /// Run a task through the reasoning loop, recording each step in the task state
+    ///
+    /// Steps are recorded as they are taken, so a failed run keeps its trace.
+    /// Model usage is metered against the task, and `constraints.max_cost`
+    /// stops the loop once the task's spend reaches it. The loop also stops
+    /// when the current [`CancellationContext`] is cancelled or times out.
+    pub async fn run_task(
+        &self,
+        task_id: atlas_core::TaskId,
+        task: &TaskConfig,
+        input: &str,
+    ) -> Result<LoopOutcome> {
+        let work = self.lifecycle.admit().await?;
+        let id = *task_id.as_uuid();
+        self.state.write().await.tasks.insert(
+            id,
+            TaskState {
+                id,
+                status: TaskStatus::Running,
+                result: None,
+                error: None,
+                steps: Vec::new(),
+                usage: CostSummary::default(),
+            },
+        );
+
+        let model: Arc<dyn LanguageModel> = Arc::new(
+            self.metered_model()?
+                .for_task(id)
+                .with_budget(task.constraints.max_cost),
+        );
+        let mut agent_loop =
+            AgentLoop::new(model.clone()).with_system_prompt(self.system_prompt().await?);
+        if let Some((store, k)) = &self.examples {
+            agent_loop = agent_loop.with_examples(store.clone(), *k);
+        }
+        if !self.guardrails.is_empty() {
+            agent_loop = agent_loop.with_guardrails(self.guardrails.clone());
+        }
+        if let Some(reflection) = &self.reflection {
+            agent_loop = agent_loop.with_critic(Arc::new(LlmCritic::new(model, reflection.clone())));
+        }
+        let state = self.state.clone();
+        agent_loop = agent_loop.with_step_observer(Arc::new(move |step| {
+            let state = state.clone();
+            Box::pin(async move {
+                if let Some(task_state) = state.write().await.tasks.get_mut(&id) {
+                    task_state.steps.push(step);
+                }
+            })
+        }));
+
+        let outcome = {
+            let tools = self.tools.read().await;
+            let trace = TraceContext::child_of_current();
+            let span = task_span(id, &trace);
+            let cancel = CancellationContext::current().unwrap_or_default();
+            let run = cancel.run(agent_loop.run(&tools, task, input));
+            Cause::task(id)
+                .scope(self.reporting_progress(work.run(trace.scope(run.instrument(span)))))
+                .await
+        };
+
+        let mut state = self.state.write().await;
+        if let Ok(outcome) = &outcome {
+            if !outcome.reflections.is_empty() {
+                let reflections = serde_json::to_value(&outcome.reflections)?;
+                state.memory.insert(format!("reflections:{}", id), reflections);
+            }
         }
 
-        Ok(tool_list)
+        let task_state = state
+            .tasks
+            .get_mut(&id)
+            .ok_or_else(|| Error::TaskError(format!("Task state missing: {}", id)))?;
+        task_state.usage = self.usage.task_usage(id);
+
+        match &outcome {
+            Ok(outcome) => {
+                match (&outcome.stop_reason, &outcome.answer) {
+                    (StopReason::FinalAnswer, Some(answer)) => {
+                        let mut result = Metadata::new();
+                        result.insert("answer", answer);
+                        result.insert(METRICS_KEY, task_state.usage);
+                        task_state.status = TaskStatus::Completed;
+                        task_state.result = Some(result);
+                    }
+                    _ => {
+                        task_state.status = TaskStatus::Failed;
+                        task_state.error = Some("Maximum number of steps reached".to_string());
+                    }
+                }
+            }
+            Err(e) => {
+                task_state.status = TaskStatus::Failed;
+                task_state.error = Some(e.to_string());
+            }
+        }
+
+        outcome
+    }
+
+    /// Render a prompt template against the given parameters and the agent's state
+    pub async fn render_prompt(&self, template: &PromptTemplate, params: &Metadata) -> Result<String> {
+        let snapshot = self.state.read().await.snapshot()?;
+        template.render_with_state(params, &snapshot)
+    }
+
+    /// Get the agent's long-term memory
+    pub fn memory(&self) -> Option<&Arc<AgentStateManager>> {
+        self.memory.as_ref()
+    }
+
+    /// Get the memory the agent shares with its team
+    pub fn shared_memory(&self) -> Option<&SharedMemory> {
+        self.shared_memory.as_ref()
+    }
+
+    /// Get the event bus the agent publishes to and listens on
+    pub fn event_bus(&self) -> Result<&Arc<dyn EventBus>> {
+        self.event_bus
+            .as_ref()
+            .ok_or_else(|| Error::InvalidConfig("No event bus configured".to_string()).into())
+    }
+
+    /// Publish an event on the agent's event bus
+    ///
+    /// An event published while the agent handles another event or runs a
+    /// task is linked to it, unless it was linked explicitly, and carries the
+    /// current trace context so its handlers continue the trace.
+    pub async fn publish(&self, event: atlas_core::Event) -> Result<()> {
+        let event = event.with_current_cause().with_current_trace();
+        self.event_bus()?.publish(event).await
+    }
+
+    /// Handle events of a type or pattern, such as `task.*`, replacing its
+    /// previous handler
+    pub fn on<F, Fut>(&self, event_type: &str, handler: F) -> Result<()>
+    where
+        F: Fn(atlas_core::Event) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.handlers_mut()?.on(event_type, handler)
+    }
+
+    /// Handle events of a type or pattern whose payload is a `T`, failing
+    /// events whose payload does not convert
+    pub fn on_typed<T, F, Fut>(&self, event_type: &str, handler: F) -> Result<()>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+        F: Fn(T, atlas_core::Event) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        self.handlers_mut()?.on_typed(event_type, handler)
+    }
+
+    /// Read the event handlers
+    fn handlers(&self) -> Result<std::sync::RwLockReadGuard<'_, EventRouter>> {
+        self.handlers
+            .read()
+            .map_err(|_| Error::StateError("Event handlers poisoned".to_string()).into())
+    }
+
+    /// Modify the event handlers
+    fn handlers_mut(&self) -> Result<std::sync::RwLockWriteGuard<'_, EventRouter>> {
+        self.handlers
+            .write()
+            .map_err(|_| Error::StateError("Event handlers poisoned".to_string()).into())
+    }
+
+    /// Subscribe to the agent's topics and handle their events in the background
+    ///
+    /// Events published after this returns are passed to `handle_event` in
+    /// order, which dispatches them to the handlers registered with
+    /// [`Agent::on`]. Messages sent to the agent through an [`AgentRef`] are
+    /// received too. Events wait while the agent is paused. Handling stops
+    /// when the agent is stopped or dropped, or the bus closes.
+    pub async fn listen(self: &Arc<Self>) -> Result<tokio::task::JoinHandle<()>> {
+        let bus = self.event_bus()?;
+        let mut streams = Vec::with_capacity(self.topics.len() + 1);
+        for topic in &self.topics {
+            streams.push(bus.subscribe(topic).await?);
+        }
+        streams.push(bus.subscribe(&messaging::inbox_pattern(self.id)).await?);
+
+        let agent = Arc::downgrade(self);
+        let mut events = futures::stream::select_all(streams);
+        Ok(tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                let Some(agent) = agent.upgrade() else {
+                    break;
+                };
+                if matches!(
+                    agent.lifecycle_state(),
+                    LifecycleState::Stopping | LifecycleState::Stopped
+                ) {
+                    break;
+                }
+                let event_type = event.event_type.clone();
+                if let Err(e) = agent.handle_event(event).await {
+                    tracing::warn!(%event_type, error = %e, "Event handling failed");
+                }
+            }
+        }))
+    }
+
+    /// Capture the agent's state, tasks and memory
+    pub async fn snapshot(&self) -> Result<AgentSnapshot> {
+        let (state, tasks) = {
+            let state = self.state.read().await;
+            (state.memory.clone(), state.tasks.values().cloned().collect())
+        };
+        let memory = match &self.memory {
+            Some(memory) => memory.list_memory().await?,
+            None => Vec::new(),
+        };
+        Ok(AgentSnapshot {
+            config_hash: snapshot::config_hash(&self.config())?,
+            taken_at: chrono::Utc::now(),
+            state,
+            tasks,
+            memory,
+        })
+    }
+
+    /// Replace the agent's state, tasks and memory with a snapshot
+    ///
+    /// Fails without changing anything if the snapshot was taken by an agent
+    /// with a different configuration, or holds memory entries and the agent
+    /// has no memory.
+    pub async fn restore(&self, snapshot: AgentSnapshot) -> Result<()> {
+        if snapshot.config_hash != snapshot::config_hash(&self.config())? {
+            return Err(Error::InvalidConfig(
+                "Snapshot was taken by an agent with a different configuration".to_string(),
+            )
+            .into());
+        }
+        match &self.memory {
+            Some(memory) => memory.replace_memory(snapshot.memory).await?,
+            None if !snapshot.memory.is_empty() => {
+                return Err(Error::StateError(
+                    "Snapshot has memory entries but the agent has no memory".to_string(),
+                )
+                .into());
+            }
+            None => {}
+        }
+
+        let mut state = self.state.write().await;
+        state.memory = snapshot.state;
+        state.tasks = snapshot.tasks.into_iter().map(|t| (t.id, t)).collect();
+        Ok(())
     }
 
     /// Execute a task using available tools
@@ -256,15 +1004,22 @@ impl Agent {
             .ok_or_else(|| Error::InvalidRequest("Tool name is required".to_string()))?;
 
         // Get tool
-        let tool = tools
-            .get(&tool_name)
-            .ok_or_else(|| Error::ToolNotFound(tool_name))?;
-
         // Execute tool
-        tool.execute(params).await
+        tools.execute(&tool_name, params).await
     }
 }
 
+/// Span of the work executing a task
+fn task_span(task_id: Uuid, trace: &TraceContext) -> tracing::Span {
+    tracing::info_span!(
+        "task",
+        %task_id,
+        trace_id = %trace.trace_id(),
+        span_id = %trace.span_id(),
+        parent_span_id = trace.parent_span_id().as_deref(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -309,6 +1064,56 @@ mod tests {
         assert_eq!(tools[0].name, "test_tool");
     }
 
+    #[test]
+    fn test_builder_validation() {
+        let mut options = Metadata::new();
+        options.insert(TOOL_OPTIONS_KEY, serde_json::json!({"browser": {}}));
+        let config = Config {
+            name: " ".to_string(),
+            description: None,
+            capabilities: vec!["search".to_string(), "search".to_string()],
+            config: options,
+        };
+
+        let err = AgentBuilder::new()
+            .config(config)
+            .tool("test_tool", TestTool)
+            .tool("test_tool", TestTool)
+            .tool_policy(ToolPolicy::new().allow("test_tool").allow("shell"))
+            .build()
+            .err()
+            .unwrap();
+        let problems: Vec<String> = err
+            .downcast_ref::<atlas_core::Error>()
+            .and_then(atlas_core::Error::details)
+            .and_then(|details| details.get("problems"))
+            .unwrap();
+        assert_eq!(
+            problems,
+            [
+                "Agent name is required",
+                "Capability search is declared more than once",
+                "Tool test_tool is registered more than once",
+                "Options are set for unregistered tool browser",
+                "Tool policy names unregistered tool shell",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_from_file() {
+        let path = std::env::temp_dir().join(format!("atlas-agent-{}.json", Uuid::new_v4()));
+        std::fs::write(&path, r#"{"name": "researcher", "config": {"max_steps": 5}}"#).unwrap();
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.name, "researcher");
+        assert!(config.capabilities.is_empty());
+        assert_eq!(config.config.get::<u32>("max_steps"), Some(5));
+
+        std::fs::write(&path, r#"{"name": ""}"#).unwrap();
+        assert!(Config::from_file(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[tokio::test]
     async fn test_agent_execution() {
         let config = Config {
@@ -330,4 +1135,314 @@ mod tests {
         let result = agent.execute_task(atlas_core::TaskId::new(), params).await.unwrap();
         assert_eq!(result.get::<bool>("success"), Some(true));
     }
+
+    #[tokio::test]
+    async fn test_snapshot_restore() {
+        let config = Config {
+            name: "test_agent".to_string(),
+            description: None,
+            capabilities: vec![],
+            config: Metadata::new(),
+        };
+        let build = |config: Config| {
+            AgentBuilder::new()
+                .config(config)
+                .tool("test_tool", TestTool)
+                .memory(AgentStateManager::new(State::default(), MemoryConfig::default()))
+                .build()
+                .unwrap()
+        };
+
+        let agent = build(config.clone());
+        let mut params = Metadata::new();
+        params.insert("tool", "test_tool");
+        agent.execute_task(atlas_core::TaskId::new(), params).await.unwrap();
+        let mut payload = Metadata::new();
+        payload.insert("user", "ada");
+        agent
+            .handle_event(atlas_core::Event::new("note", payload))
+            .await
+            .unwrap();
+        agent
+            .memory()
+            .unwrap()
+            .add_memory(serde_json::json!("likes tea"), Metadata::new())
+            .await
+            .unwrap();
+
+        // Ship the snapshot to a fresh agent as JSON
+        let json = serde_json::to_string(&agent.snapshot().await.unwrap()).unwrap();
+        let snapshot: AgentSnapshot = serde_json::from_str(&json).unwrap();
+        let resumed = build(config.clone());
+        resumed.restore(snapshot.clone()).await.unwrap();
+
+        let state = resumed.state.read().await;
+        assert_eq!(state.memory.get("user"), Some(&serde_json::json!("ada")));
+        assert_eq!(state.tasks.len(), 1);
+        let entries = resumed.memory().unwrap().list_memory().await.unwrap();
+        assert_eq!(entries[0].data, serde_json::json!("likes tea"));
+
+        let other = build(Config {
+            name: "other_agent".to_string(),
+            ..config
+        });
+        assert!(other.restore(snapshot).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shared_memory() {
+        let team = SharedMemory::default().namespace("research");
+        let build = |name: &str| {
+            AgentBuilder::new()
+                .config(Config {
+                    name: name.to_string(),
+                    description: None,
+                    capabilities: vec![],
+                    config: Metadata::new(),
+                })
+                .shared_memory(team.clone())
+                .build()
+                .unwrap()
+        };
+        let planner = build("planner");
+        let writer = build("writer");
+
+        let entry = planner
+            .shared_memory()
+            .unwrap()
+            .insert(serde_json::json!("deadline is friday"), Metadata::new())
+            .await
+            .unwrap();
+        let found = writer.shared_memory().unwrap().search("deadline", 1).await.unwrap();
+        assert_eq!(found[0].id, entry.id);
+    }
+
+    #[derive(Deserialize)]
+    struct TaskCreated {
+        title: String,
+    }
+
+    #[tokio::test]
+    async fn test_event_handlers() {
+        let agent = AgentBuilder::new()
+            .config(Config {
+                name: "router".to_string(),
+                description: None,
+                capabilities: vec![],
+                config: Metadata::new(),
+            })
+            .unknown_events(UnknownEventPolicy::Reject)
+            .build()
+            .unwrap();
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+        agent
+            .on_typed("task.created", move |task: TaskCreated, event| {
+                let sender = sender.clone();
+                async move {
+                    sender.send((task.title, Cause::current(), TraceContext::current()))?;
+                    assert!(event.caused_by.is_none());
+                    Ok(())
+                }
+            })
+            .unwrap();
+
+        let mut payload = Metadata::new();
+        payload.insert("title", "fetch");
+        let publisher = TraceContext::root();
+        let event = publisher
+            .scope(async {
+                atlas_core::Event::new("task.created", payload).with_current_trace()
+            })
+            .await;
+        agent.handle_event(event.clone()).await.unwrap();
+        let (title, cause, trace) = received.recv().await.unwrap();
+        assert_eq!(title, "fetch");
+        assert_eq!(cause, Some(Cause::of(&event)));
+        let trace = trace.unwrap();
+        assert_eq!(trace.trace_id(), publisher.trace_id());
+        assert_eq!(trace.parent_span_id(), Some(publisher.span_id()));
+        assert!(agent
+            .handle_event(atlas_core::Event::new("note", Metadata::new()))
+            .await
+            .is_err());
+        assert!(agent.state.read().await.memory.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_listen_for_events() {
+        let bus = Arc::new(atlas_core::InMemoryEventBus::new());
+        bus.register_topic("note").await.unwrap();
+        let agent = Arc::new(
+            AgentBuilder::new()
+                .config(Config {
+                    name: "listener".to_string(),
+                    description: None,
+                    capabilities: vec![],
+                    config: Metadata::new(),
+                })
+                .event_bus(bus.clone())
+                .subscribe("note")
+                .build()
+                .unwrap(),
+        );
+        let mut observed = bus.subscribe("note").await.unwrap();
+        let listener = agent.listen().await.unwrap();
+
+        let mut payload = Metadata::new();
+        payload.insert("user", "ada");
+        let request = atlas_core::Event::new("request", Metadata::new());
+        Cause::of(&request)
+            .scope(agent.publish(atlas_core::Event::new("note", payload)))
+            .await
+            .unwrap();
+        let note = observed.next().await.unwrap();
+        assert_eq!(note.caused_by, Some(request.id.into()));
+        assert_eq!(note.correlation_id, request.correlation_id);
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while !agent.state.read().await.memory.contains_key("user") {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        drop(agent);
+        drop(bus);
+        listener.await.unwrap();
+    }
+    struct SlowTool;
+
+    #[async_trait]
+    impl MCPTool for SlowTool {
+        fn name(&self) -> &str {
+            "slow_tool"
+        }
+
+        fn description(&self) -> &str {
+            "A tool that never finishes"
+        }
+
+        async fn execute(&self, _params: Metadata) -> Result<Metadata> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_task_cancellation() {
+        let agent = AgentBuilder::new()
+            .config(Config {
+                name: "worker".to_string(),
+                description: None,
+                capabilities: vec![],
+                config: Metadata::new(),
+            })
+            .tool("slow_tool", SlowTool)
+            .build()
+            .unwrap();
+        let mut params = Metadata::new();
+        params.insert("tool", "slow_tool");
+        let task_id = atlas_core::TaskId::new();
+        let cancel = CancellationContext::new().with_timeout(std::time::Duration::from_millis(10));
+
+        let err = agent
+            .execute_task_with(task_id, params, &cancel)
+            .await
+            .unwrap_err();
+        let err = err.downcast::<atlas_core::Error>().unwrap();
+        assert_eq!(err.code(), atlas_core::ErrorCode::Timeout);
+        let state = agent.state.read().await;
+        assert_eq!(state.tasks[task_id.as_uuid()].status, TaskStatus::Failed);
+    }
+
+    struct AnsweringModel;
+
+    #[async_trait]
+    impl LanguageModel for AnsweringModel {
+        fn name(&self) -> &str {
+            "answering"
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant("done"),
+                model: "answering".to_string(),
+                usage: llm::TokenUsage {
+                    prompt_tokens: 1000,
+                    completion_tokens: 500,
+                },
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_task_metrics() {
+        let agent = AgentBuilder::new()
+            .config(Config {
+                name: "worker".to_string(),
+                description: None,
+                capabilities: vec![],
+                config: Metadata::new(),
+            })
+            .model(AnsweringModel)
+            .price_table(PriceTable::new().with_price("answering", ModelPrice::new(1.0, 2.0)))
+            .build()
+            .unwrap();
+        let task_id = atlas_core::TaskId::new();
+
+        agent
+            .run_task(task_id, &TaskConfig::default(), "work")
+            .await
+            .unwrap();
+        let state = agent.state.read().await;
+        let task = &state.tasks[task_id.as_uuid()];
+        let metrics = task.result.as_ref().unwrap().get::<CostSummary>(METRICS_KEY);
+        assert_eq!(metrics, Some(task.usage));
+        assert_eq!(task.usage.cost, 2.0);
+        assert_eq!(agent.usage_report().total.requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle() {
+        let agent = Arc::new(
+            AgentBuilder::new()
+                .config(Config {
+                    name: "worker".to_string(),
+                    description: None,
+                    capabilities: vec![],
+                    config: Metadata::new(),
+                })
+                .tool("test_tool", TestTool)
+                .build()
+                .unwrap(),
+        );
+        let mut params = Metadata::new();
+        params.insert("tool", "test_tool");
+        agent.on_start().await.unwrap();
+
+        // Paused agents hold new tasks until resumed
+        agent.pause().await.unwrap();
+        let task = tokio::spawn({
+            let agent = agent.clone();
+            let params = params.clone();
+            async move { agent.execute_task(atlas_core::TaskId::new(), params).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!task.is_finished());
+        agent.resume().await.unwrap();
+        assert!(task.await.unwrap().is_ok());
+
+        agent.on_stop().await.unwrap();
+        assert_eq!(agent.lifecycle_state(), LifecycleState::Stopped);
+        assert!(agent
+            .execute_task(atlas_core::TaskId::new(), params.clone())
+            .await
+            .is_err());
+        assert!(agent.pause().await.is_err());
+        agent.on_start().await.unwrap();
+        assert!(agent
+            .execute_task(atlas_core::TaskId::new(), params)
+            .await
+            .is_ok());
+    }
 }
@@ -0,0 +1,379 @@
+//! Starting, pausing and stopping agents
+
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+use atlas_core::{CancellationContext, Event, EventType, Metadata};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::Agent;
+
+/// Tasks and events that may wait while an agent is paused, unless set with
+/// [`AgentBuilder::pause_queue`](crate::AgentBuilder::pause_queue)
+pub const DEFAULT_PAUSE_QUEUE: usize = 256;
+
+/// Stage of an agent's lifecycle
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleState {
+    /// Taking new tasks and events
+    Running,
+
+    /// Holding new tasks and events until resumed
+    Paused,
+
+    /// Refusing new work while the work in progress finishes
+    Stopping,
+
+    /// Refusing new work
+    Stopped,
+}
+
+/// Lifecycle of an agent and the work it is doing
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    /// Current stage
+    state: watch::Sender<LifecycleState>,
+
+    /// Tasks and events being handled
+    active: watch::Sender<usize>,
+
+    /// Tasks and events waiting for the agent to resume
+    queued: watch::Sender<usize>,
+
+    /// Tasks and events that may wait at once
+    queue_capacity: usize,
+
+    /// Context cancelling the work being handled when a shutdown runs out
+    /// of time
+    work: Mutex<CancellationContext>,
+}
+
+/// Work admitted by [`Lifecycle::admit`], counted as active until dropped
+#[derive(Debug)]
+pub(crate) struct WorkGuard<'a> {
+    /// Lifecycle the work was admitted by
+    lifecycle: &'a Lifecycle,
+
+    /// Context the work is cancelled through
+    cancel: CancellationContext,
+}
+
+/// Place of work waiting in the pause queue, given up when dropped
+#[derive(Debug)]
+struct QueueSlot<'a> {
+    /// Lifecycle whose queue the work waits in
+    lifecycle: &'a Lifecycle,
+}
+
+impl Lifecycle {
+    /// Create the lifecycle of a running agent, letting up to
+    /// `queue_capacity` tasks and events wait while it is paused
+    pub(crate) fn new(queue_capacity: usize) -> Self {
+        Self {
+            state: watch::channel(LifecycleState::Running).0,
+            active: watch::channel(0).0,
+            queued: watch::channel(0).0,
+            queue_capacity,
+            work: Mutex::default(),
+        }
+    }
+
+    /// Get the current stage
+    pub(crate) fn state(&self) -> LifecycleState {
+        *self.state.borrow()
+    }
+
+    /// Get how many tasks and events are being handled
+    pub(crate) fn active(&self) -> usize {
+        *self.active.borrow()
+    }
+
+    /// Get how many tasks and events wait for the agent to resume
+    pub(crate) fn queued(&self) -> usize {
+        *self.queued.borrow()
+    }
+
+    /// Move to a stage
+    pub(crate) fn set(&self, state: LifecycleState) {
+        self.state.send_replace(state);
+    }
+
+    /// Admit new work, waiting in the pause queue while the agent is paused;
+    /// fails if the queue is full or once the agent is stopping
+    pub(crate) async fn admit(&self) -> Result<WorkGuard<'_>> {
+        let mut changes = self.state.subscribe();
+        let mut slot = None;
+        loop {
+            if slot.is_none() && self.state() == LifecycleState::Paused {
+                slot = Some(self.enqueue()?);
+            }
+            let _ = changes
+                .wait_for(|state| *state != LifecycleState::Paused)
+                .await;
+            // Count the work before a stop can see it, so stopping waits for it
+            let state = self.state.borrow();
+            match *state {
+                LifecycleState::Running => {
+                    self.active.send_modify(|active| *active += 1);
+                    return Ok(WorkGuard {
+                        lifecycle: self,
+                        cancel: self.lock_work().clone(),
+                    });
+                }
+                LifecycleState::Paused => continue,
+                LifecycleState::Stopping | LifecycleState::Stopped => {
+                    return Err(
+                        atlas_core::Error::Unavailable("Agent is stopped".to_string()).into(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Take a place in the pause queue
+    fn enqueue(&self) -> Result<QueueSlot<'_>> {
+        let mut queued = false;
+        self.queued.send_if_modified(|waiting| {
+            queued = *waiting < self.queue_capacity;
+            if queued {
+                *waiting += 1;
+            }
+            queued
+        });
+        if !queued {
+            return Err(atlas_core::Error::Unavailable(format!(
+                "Agent is paused and {} tasks are already waiting",
+                self.queue_capacity
+            ))
+            .into());
+        }
+        Ok(QueueSlot { lifecycle: self })
+    }
+
+    /// Wait until no work is being handled
+    pub(crate) async fn drained(&self) {
+        let mut active = self.active.subscribe();
+        let _ = active.wait_for(|active| *active == 0).await;
+    }
+
+    /// Cancel the work being handled, leaving work admitted later alone
+    pub(crate) fn cancel_work(&self) {
+        std::mem::take(&mut *self.lock_work()).cancel();
+    }
+
+    /// Lock the context work is cancelled through
+    fn lock_work(&self) -> MutexGuard<'_, CancellationContext> {
+        self.work.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.lifecycle
+            .queued
+            .send_modify(|queued| *queued = queued.saturating_sub(1));
+    }
+}
+
+impl WorkGuard<'_> {
+    /// Run the work, failing with [`atlas_core::Error::Cancelled`] if the
+    /// agent shuts down first
+    pub(crate) async fn run<F, T>(&self, work: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        tokio::select! {
+            result = work => result,
+            _ = self.cancel.cancelled() => Err(atlas_core::Error::Cancelled(
+                "Agent shut down before the work finished".to_string(),
+            )
+            .into()),
+        }
+    }
+}
+
+impl Agent {
+    /// Get how many tasks and events wait for the paused agent to resume
+    pub fn queued(&self) -> usize {
+        self.lifecycle.queued()
+    }
+
+    /// Stop the agent, giving the work in progress time to finish
+    ///
+    /// New tasks and events are refused at once, and tasks and events still
+    /// running after `grace` are cancelled. Memory persistence is then
+    /// flushed and, if the agent has an event bus, an
+    /// [`EventType::AgentStopped`] event carrying a final
+    /// [`AgentSnapshot`](crate::AgentSnapshot) is published.
+    pub async fn shutdown(&self, grace: Duration) -> Result<()> {
+        if self.lifecycle.state() == LifecycleState::Stopped {
+            return Ok(());
+        }
+        self.lifecycle.set(LifecycleState::Stopping);
+        if tokio::time::timeout(grace, self.lifecycle.drained())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                agent = %self.config().name,
+                active = self.lifecycle.active(),
+                "Cancelling work still running after the shutdown grace period"
+            );
+            self.lifecycle.cancel_work();
+            self.lifecycle.drained().await;
+        }
+        self.lifecycle.set(LifecycleState::Stopped);
+
+        if let Some(memory) = &self.memory {
+            memory.flush().await?;
+        }
+        if let Some(bus) = &self.event_bus {
+            let mut payload = Metadata::new();
+            payload.insert("agent", self.config().name.clone());
+            payload.insert("id", self.id());
+            payload.try_insert("snapshot", &self.snapshot().await?)?;
+            bus.register_topic(EventType::AgentStopped.as_str()).await?;
+            self.publish(Event::new(EventType::AgentStopped, payload))
+                .await?;
+        }
+        tracing::info!(agent = %self.config().name, "Agent shut down");
+        Ok(())
+    }
+}
+
+impl Drop for WorkGuard<'_> {
+    fn drop(&mut self) {
+        self.lifecycle
+            .active
+            .send_modify(|active| *active = active.saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentBuilder, Config};
+    use async_trait::async_trait;
+    use atlas_core::{Agent as CoreAgent, EventBus, InMemoryEventBus, TaskId};
+    use atlas_mcp::MCPTool;
+    use futures::StreamExt;
+    use std::sync::Arc;
+
+    /// Tool sleeping for the number of milliseconds it is given
+    struct Sleep;
+
+    #[async_trait]
+    impl MCPTool for Sleep {
+        fn name(&self) -> &str {
+            "sleep"
+        }
+
+        fn description(&self) -> &str {
+            "Sleep for a while"
+        }
+
+        async fn execute(&self, params: Metadata) -> Result<Metadata> {
+            let ms = params.get::<u64>("ms").unwrap_or_default();
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok(Metadata::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let bus = Arc::new(InMemoryEventBus::new());
+        let agent = Arc::new(
+            AgentBuilder::new()
+                .config(Config {
+                    name: "worker".to_string(),
+                    description: None,
+                    capabilities: vec![],
+                    config: Metadata::new(),
+                })
+                .tool("sleep", Sleep)
+                .event_bus(bus.clone())
+                .build()
+                .unwrap(),
+        );
+        bus.register_topic(EventType::AgentStopped.as_str())
+            .await
+            .unwrap();
+        let mut events = bus
+            .subscribe(EventType::AgentStopped.as_str())
+            .await
+            .unwrap();
+
+        let sleep = |ms: u64| {
+            let agent = agent.clone();
+            let mut params = Metadata::new();
+            params.insert("tool", "sleep");
+            params.insert("ms", ms);
+            tokio::spawn(async move { agent.execute_task(TaskId::new(), params).await })
+        };
+        let quick = sleep(10);
+        let stuck = sleep(60_000);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        agent.shutdown(Duration::from_millis(50)).await.unwrap();
+        assert_eq!(agent.lifecycle_state(), LifecycleState::Stopped);
+        assert!(quick.await.unwrap().is_ok());
+        let err = stuck.await.unwrap().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<atlas_core::Error>(),
+            Some(atlas_core::Error::Cancelled(_))
+        ));
+        assert!(sleep(0).await.unwrap().is_err());
+
+        let event = events.next().await.unwrap();
+        assert_eq!(event.event_type, EventType::AgentStopped);
+        let snapshot: crate::AgentSnapshot = event.payload.get("snapshot").unwrap();
+        assert_eq!(snapshot.tasks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pause_queue() {
+        let agent = Arc::new(
+            AgentBuilder::new()
+                .config(Config {
+                    name: "worker".to_string(),
+                    description: None,
+                    capabilities: vec![],
+                    config: Metadata::new(),
+                })
+                .tool("sleep", Sleep)
+                .pause_queue(1)
+                .build()
+                .unwrap(),
+        );
+        let mut params = Metadata::new();
+        params.insert("tool", "sleep");
+
+        agent.pause().await.unwrap();
+        let held = tokio::spawn({
+            let agent = agent.clone();
+            let params = params.clone();
+            async move { agent.execute_task(TaskId::new(), params).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(agent.queued(), 1);
+
+        // Work beyond the queue is refused instead of piling up
+        let err = agent
+            .execute_task(TaskId::new(), params.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<atlas_core::Error>(),
+            Some(atlas_core::Error::Unavailable(_))
+        ));
+
+        agent.resume().await.unwrap();
+        assert!(held.await.unwrap().is_ok());
+        assert_eq!(agent.queued(), 0);
+        assert!(agent.execute_task(TaskId::new(), params).await.is_ok());
+    }
+}
@@ -0,0 +1,314 @@
+//! Response caching for deterministic completions
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::llm::{CompletionRequest, CompletionResponse, LanguageModel};
+
+/// Metadata key set on responses served from the cache
+pub const CACHED_KEY: &str = "cached";
+
+/// Response cache configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// How long entries stay valid, in seconds
+    pub ttl_secs: u64,
+
+    /// Maximum number of cached responses
+    pub max_entries: usize,
+
+    /// Only cache requests with temperature 0
+    pub deterministic_only: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 3600,
+            max_entries: 1024,
+            deterministic_only: true,
+        }
+    }
+}
+
+/// Cache hit and miss counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Requests served from the cache
+    pub hits: u64,
+
+    /// Requests sent to the provider
+    pub misses: u64,
+}
+
+struct CacheEntry {
+    response: CompletionResponse,
+    inserted_at: Instant,
+    last_used: Instant,
+}
+
+/// Language model wrapper that caches responses
+///
+/// Wrap a model client with it before handing the client to an agent.
+/// Requests are keyed by the provider, model, sampling parameters, tool
+/// definitions, metadata, and the messages with whitespace normalized.
+/// Cached responses report zero token usage, so metering does not bill them
+/// again, and carry [`CACHED_KEY`] in their metadata. When full, the least
+/// recently used entry is evicted.
+pub struct CachedModel {
+    /// Wrapped model
+    inner: Arc<dyn LanguageModel>,
+
+    /// Cache configuration
+    config: CacheConfig,
+
+    /// Cached responses by key
+    entries: Mutex<HashMap<String, CacheEntry>>,
+
+    /// Cache hits
+    hits: AtomicU64,
+
+    /// Cache misses
+    misses: AtomicU64,
+}
+
+impl CachedModel {
+    /// Wrap a model
+    pub fn new(inner: Arc<dyn LanguageModel>, config: CacheConfig) -> Self {
+        Self {
+            inner,
+            config,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the cache hit and miss counters
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Remove all cached responses
+    pub fn clear(&self) {
+        self.entries.lock().expect("cache lock poisoned").clear();
+    }
+
+    fn cacheable(&self, request: &CompletionRequest) -> bool {
+        !self.config.deterministic_only || request.temperature == Some(0.0)
+    }
+
+    fn key(&self, request: &CompletionRequest) -> String {
+        let messages: Vec<_> = request
+            .messages
+            .iter()
+            .map(|m| {
+                json!({
+                    "role": m.role,
+                    "content": normalize(&m.content),
+                    "tool_calls": m.tool_calls,
+                    "tool_call_id": m.tool_call_id,
+                })
+            })
+            .collect();
+        json!({
+            "provider": self.inner.name(),
+            "model": request.model,
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens,
+            "tools": request.tools,
+            "metadata": request.metadata,
+            "messages": messages,
+        })
+        .to_string()
+    }
+
+    fn lookup(&self, key: &str) -> Option<CompletionResponse> {
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+
+        match entries.get_mut(key) {
+            Some(entry) if entry.inserted_at.elapsed() < ttl => {
+                entry.last_used = Instant::now();
+                Some(entry.response.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, key: String, response: CompletionResponse) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        if entries.len() >= self.config.max_entries && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        let now = Instant::now();
+        entries.insert(
+            key,
+            CacheEntry {
+                response,
+                inserted_at: now,
+                last_used: now,
+            },
+        );
+    }
+}
+
+#[async_trait]
+impl LanguageModel for CachedModel {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        if !self.cacheable(&request) {
+            return self.inner.complete(request).await;
+        }
+
+        let key = self.key(&request);
+        if let Some(mut response) = self.lookup(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            response.usage = Default::default();
+            response.metadata.insert(CACHED_KEY, true);
+            return Ok(response);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let response = self.inner.complete(request).await?;
+        self.store(key, response.clone());
+        Ok(response)
+    }
+}
+
+/// Collapse runs of whitespace so formatting differences share an entry
+fn normalize(content: &str) -> String {
+    content.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, TokenUsage};
+    use atlas_mcp::ToolInfo;
+
+    struct CountingModel {
+        calls: AtomicU64,
+    }
+
+    #[async_trait]
+    impl LanguageModel for CountingModel {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(format!("reply {}", calls)),
+                model: "counting".to_string(),
+                usage: TokenUsage {
+                    prompt_tokens: 10,
+                    completion_tokens: 5,
+                },
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    fn request(content: &str, temperature: f32) -> CompletionRequest {
+        CompletionRequest::new(vec![ChatMessage::user(content)]).with_temperature(temperature)
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_requests_are_cached() {
+        let model = CachedModel::new(
+            Arc::new(CountingModel {
+                calls: AtomicU64::new(0),
+            }),
+            CacheConfig::default(),
+        );
+
+        let first = model.complete(request("hello  world", 0.0)).await.unwrap();
+        let second = model.complete(request("hello world\n", 0.0)).await.unwrap();
+        assert_eq!(first.message.content, second.message.content);
+        assert_eq!(second.usage.total(), 0);
+        assert_eq!(second.metadata.get::<bool>(CACHED_KEY), Some(true));
+
+        let sampled = model.complete(request("hello world", 0.7)).await.unwrap();
+        assert_eq!(sampled.message.content, "reply 1");
+        assert_eq!(model.stats(), CacheStats { hits: 1, misses: 1 });
+
+        // Tool definitions and metadata are part of the key
+        let mut with_tool = request("hello world", 0.0);
+        with_tool.tools.push(ToolInfo {
+            name: "search".to_string(),
+            description: "Search the web".to_string(),
+            input_schema: None,
+        });
+        let mut described = with_tool.clone();
+        described.tools[0].description = "Search the docs".to_string();
+        let mut tagged = request("hello world", 0.0);
+        tagged.metadata.insert("tenant", "acme");
+        for request in [with_tool, described, tagged] {
+            let response = model.complete(request).await.unwrap();
+            assert!(response.metadata.get::<bool>(CACHED_KEY).is_none());
+        }
+        assert_eq!(model.stats(), CacheStats { hits: 1, misses: 4 });
+    }
+
+    #[tokio::test]
+    async fn test_ttl_and_eviction() {
+        let model = CachedModel::new(
+            Arc::new(CountingModel {
+                calls: AtomicU64::new(0),
+            }),
+            CacheConfig {
+                ttl_secs: 3600,
+                max_entries: 1,
+                deterministic_only: true,
+            },
+        );
+
+        model.complete(request("a", 0.0)).await.unwrap();
+        model.complete(request("b", 0.0)).await.unwrap();
+        let again = model.complete(request("a", 0.0)).await.unwrap();
+        assert_eq!(again.message.content, "reply 2");
+
+        let expired = CachedModel::new(
+            Arc::new(CountingModel {
+                calls: AtomicU64::new(0),
+            }),
+            CacheConfig {
+                ttl_secs: 0,
+                ..Default::default()
+            },
+        );
+        expired.complete(request("a", 0.0)).await.unwrap();
+        let again = expired.complete(request("a", 0.0)).await.unwrap();
+        assert_eq!(again.message.content, "reply 1");
+    }
+}
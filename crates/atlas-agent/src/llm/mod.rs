@@ -0,0 +1,203 @@
+//! Language model abstraction for agents
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use atlas_core::Metadata;
+use atlas_mcp::ToolInfo;
+
+pub mod cache;
+pub mod router;
+
+pub use cache::{CacheConfig, CacheStats, CachedModel};
+pub use router::{ModelRouter, RouteTarget, RoutingDecision, RoutingRule};
+
+/// Chat message role
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// System instructions
+    System,
+
+    /// User input
+    User,
+
+    /// Model output
+    Assistant,
+
+    /// Tool execution result
+    Tool,
+}
+
+/// Chat message
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// Message role
+    pub role: Role,
+
+    /// Message content
+    pub content: String,
+
+    /// Tool calls requested by the model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+
+    /// ID of the tool call this message answers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    /// Create a new message
+    pub fn new<S: Into<String>>(role: Role, content: S) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// Create a system message
+    pub fn system<S: Into<String>>(content: S) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    /// Create a user message
+    pub fn user<S: Into<String>>(content: S) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    /// Create an assistant message
+    pub fn assistant<S: Into<String>>(content: S) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    /// Create a tool result message
+    pub fn tool<I: Into<String>, S: Into<String>>(call_id: I, content: S) -> Self {
+        Self {
+            tool_call_id: Some(call_id.into()),
+            ..Self::new(Role::Tool, content)
+        }
+    }
+
+    /// Add tool calls to the message
+    pub fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = tool_calls;
+        self
+    }
+}
+
+/// Tool call requested by a model
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Call ID, used to match the tool result
+    pub id: String,
+
+    /// Tool name
+    pub name: String,
+
+    /// Tool arguments
+    pub arguments: Value,
+}
+
+/// Completion request
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CompletionRequest {
+    /// Conversation messages
+    pub messages: Vec<ChatMessage>,
+
+    /// Model override
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// Sampling temperature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Maximum number of tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+
+    /// Tools the model may call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolInfo>,
+
+    /// Request metadata
+    pub metadata: Metadata,
+}
+
+impl CompletionRequest {
+    /// Create a new completion request
+    pub fn new(messages: Vec<ChatMessage>) -> Self {
+        Self {
+            messages,
+            ..Default::default()
+        }
+    }
+
+    /// Set the model
+    pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Set the sampling temperature
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the tools the model may call
+    pub fn with_tools(mut self, tools: Vec<ToolInfo>) -> Self {
+        self.tools = tools;
+        self
+    }
+}
+
+/// Completion response
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompletionResponse {
+    /// Generated message
+    pub message: ChatMessage,
+
+    /// Model that produced the response
+    pub model: String,
+
+    /// Token usage reported by the provider
+    #[serde(default)]
+    pub usage: TokenUsage,
+
+    /// Response metadata, such as routing decisions
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+/// Token usage for a completion
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Tokens in the prompt
+    pub prompt_tokens: u64,
+
+    /// Tokens in the completion
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Total number of tokens
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Language model provider
+#[async_trait]
+pub trait LanguageModel: Send + Sync {
+    /// Get the provider's name
+    fn name(&self) -> &str;
+
+    /// Generate a completion for the given request
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse>;
+}
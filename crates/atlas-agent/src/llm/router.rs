@@ -0,0 +1,409 @@
+//! Rule-based model routing with fallback
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::llm::{CompletionRequest, CompletionResponse, LanguageModel};
+use crate::usage::ModelPrice;
+
+/// Metadata key holding the request tags matched by routing rules
+pub const TAGS_KEY: &str = "tags";
+
+/// Metadata key holding the routing decision on a response
+pub const ROUTING_KEY: &str = "routing";
+
+/// Model a router can send requests to
+#[derive(Clone)]
+pub struct RouteTarget {
+    /// Target name, referenced by routing rules
+    pub name: String,
+
+    /// Language model
+    pub model: Arc<dyn LanguageModel>,
+
+    /// Model to request from the provider, overriding the request's model
+    pub model_name: Option<String>,
+
+    /// Model price, checked against cost ceilings
+    pub price: ModelPrice,
+
+    /// Expected latency in milliseconds, checked against latency targets
+    pub latency_ms: Option<u64>,
+}
+
+impl RouteTarget {
+    /// Create a new target
+    pub fn new<S: Into<String>>(name: S, model: Arc<dyn LanguageModel>) -> Self {
+        Self {
+            name: name.into(),
+            model,
+            model_name: None,
+            price: ModelPrice::default(),
+            latency_ms: None,
+        }
+    }
+
+    /// Set the model to request from the provider
+    pub fn with_model_name<S: Into<String>>(mut self, model_name: S) -> Self {
+        self.model_name = Some(model_name.into());
+        self
+    }
+
+    /// Set the model price
+    pub fn with_price(mut self, price: ModelPrice) -> Self {
+        self.price = price;
+        self
+    }
+
+    /// Set the expected latency
+    pub fn with_latency_ms(mut self, latency_ms: u64) -> Self {
+        self.latency_ms = Some(latency_ms);
+        self
+    }
+}
+
+/// Rule selecting targets for matching requests
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RoutingRule {
+    /// Rule name
+    pub name: String,
+
+    /// Tags a request must carry for the rule to match
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Highest acceptable price per thousand tokens, prompt or completion
+    #[serde(default)]
+    pub max_cost_per_1k: Option<f64>,
+
+    /// Highest acceptable expected latency in milliseconds
+    #[serde(default)]
+    pub max_latency_ms: Option<u64>,
+
+    /// Targets in order of preference; later targets are fallbacks
+    pub targets: Vec<String>,
+}
+
+impl RoutingRule {
+    /// Create a rule routing to the given targets
+    pub fn new<S: Into<String>>(name: S, targets: Vec<String>) -> Self {
+        Self {
+            name: name.into(),
+            targets,
+            ..Default::default()
+        }
+    }
+
+    /// Only match requests carrying all of these tags
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Skip targets priced above this ceiling
+    pub fn with_max_cost_per_1k(mut self, max_cost_per_1k: f64) -> Self {
+        self.max_cost_per_1k = Some(max_cost_per_1k);
+        self
+    }
+
+    /// Skip targets expected to be slower than this
+    pub fn with_max_latency_ms(mut self, max_latency_ms: u64) -> Self {
+        self.max_latency_ms = Some(max_latency_ms);
+        self
+    }
+
+    fn matches(&self, tags: &[String]) -> bool {
+        self.tags.iter().all(|tag| tags.contains(tag))
+    }
+
+    fn allows(&self, target: &RouteTarget) -> bool {
+        let within_cost = match self.max_cost_per_1k {
+            Some(max) => target.price.prompt_per_1k <= max && target.price.completion_per_1k <= max,
+            None => true,
+        };
+        let within_latency = match (self.max_latency_ms, target.latency_ms) {
+            (Some(max), Some(latency)) => latency <= max,
+            _ => true,
+        };
+        within_cost && within_latency
+    }
+}
+
+/// Single attempt made while routing a request
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RouteAttempt {
+    /// Target name
+    pub target: String,
+
+    /// Elapsed time in milliseconds
+    pub latency_ms: u64,
+
+    /// Error returned by the target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How a request was routed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoutingDecision {
+    /// Matching rule, if any
+    pub rule: Option<String>,
+
+    /// Candidate targets in the order they were tried
+    pub candidates: Vec<String>,
+
+    /// Target that produced the response
+    pub target: String,
+
+    /// Attempts made, including failed ones
+    pub attempts: Vec<RouteAttempt>,
+}
+
+/// Language model that routes requests across several targets
+///
+/// The first rule whose tags match the request picks the candidate targets,
+/// filtered by the rule's cost ceiling and latency target; requests matching
+/// no rule try every target in registration order. Rate limits (429) and
+/// server errors (5xx) fall back to the next candidate, while other errors
+/// are returned immediately. The decision is recorded in the response
+/// metadata under [`ROUTING_KEY`].
+#[derive(Default)]
+pub struct ModelRouter {
+    /// Registered targets
+    targets: Vec<RouteTarget>,
+
+    /// Routing rules, checked in order
+    rules: Vec<RoutingRule>,
+}
+
+impl ModelRouter {
+    /// Create an empty router
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a target
+    pub fn target(mut self, target: RouteTarget) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    /// Add a routing rule
+    pub fn rule(mut self, rule: RoutingRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Select the candidate targets for a request
+    pub fn route(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<(Option<&RoutingRule>, Vec<&RouteTarget>)> {
+        let tags = request
+            .metadata
+            .get::<Vec<String>>(TAGS_KEY)
+            .unwrap_or_default();
+
+        let Some(rule) = self.rules.iter().find(|rule| rule.matches(&tags)) else {
+            return Ok((None, self.targets.iter().collect()));
+        };
+
+        let mut candidates = Vec::new();
+        for name in &rule.targets {
+            let target = self
+                .targets
+                .iter()
+                .find(|t| &t.name == name)
+                .ok_or_else(|| Error::InvalidConfig(format!("Unknown route target: {}", name)))?;
+            if rule.allows(target) {
+                candidates.push(target);
+            }
+        }
+
+        if candidates.is_empty() {
+            return Err(Error::InvalidRequest(format!(
+                "No target satisfies routing rule {}",
+                rule.name
+            ))
+            .into());
+        }
+        Ok((Some(rule), candidates))
+    }
+}
+
+#[async_trait]
+impl LanguageModel for ModelRouter {
+    fn name(&self) -> &str {
+        "router"
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let (rule, candidates) = self.route(&request)?;
+        let mut attempts = Vec::new();
+        let mut last_error = None;
+
+        for target in &candidates {
+            let mut request = request.clone();
+            if let Some(model_name) = &target.model_name {
+                request.model = Some(model_name.clone());
+            }
+
+            let started = Instant::now();
+            let result = target.model.complete(request).await;
+            let latency_ms = started.elapsed().as_millis() as u64;
+
+            match result {
+                Ok(mut response) => {
+                    attempts.push(RouteAttempt {
+                        target: target.name.clone(),
+                        latency_ms,
+                        error: None,
+                    });
+                    let decision = RoutingDecision {
+                        rule: rule.map(|r| r.name.clone()),
+                        candidates: candidates.iter().map(|t| t.name.clone()).collect(),
+                        target: target.name.clone(),
+                        attempts,
+                    };
+                    response.metadata.try_insert(ROUTING_KEY, decision)?;
+                    return Ok(response);
+                }
+                Err(e) if is_retryable(&e) => {
+                    tracing::warn!(target = %target.name, error = %e, "Falling back to next model");
+                    attempts.push(RouteAttempt {
+                        target: target.name.clone(),
+                        latency_ms,
+                        error: Some(e.to_string()),
+                    });
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            Error::InvalidConfig("No route targets registered".to_string()).into()
+        }))
+    }
+}
+
+/// Whether an error should fall back to the next target
+fn is_retryable(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<Error>() {
+        Some(Error::ProviderError(status, _)) => *status == 429 || *status >= 500,
+        Some(Error::Core(e)) => e.is_retryable(),
+        _ => err
+            .downcast_ref::<atlas_core::Error>()
+            .is_some_and(atlas_core::Error::is_retryable),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Returns a fixed status error, or succeeds when `status` is zero
+    struct StatusModel {
+        status: u16,
+        calls: AtomicUsize,
+    }
+
+    impl StatusModel {
+        fn new(status: u16) -> Arc<Self> {
+            Arc::new(Self {
+                status,
+                calls: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl LanguageModel for StatusModel {
+        fn name(&self) -> &str {
+            "status"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.status != 0 {
+                return Err(Error::ProviderError(self.status, "unavailable".to_string()).into());
+            }
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant("ok"),
+                model: request.model.unwrap_or_default(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    fn tagged(tags: &[&str]) -> CompletionRequest {
+        let mut request = CompletionRequest::default();
+        request.metadata.insert(TAGS_KEY, tags);
+        request
+    }
+
+    #[tokio::test]
+    async fn test_rules_and_ceilings() {
+        let router = ModelRouter::new()
+            .target(
+                RouteTarget::new("large", StatusModel::new(0))
+                    .with_model_name("large-v1")
+                    .with_price(ModelPrice::new(10.0, 30.0)),
+            )
+            .target(
+                RouteTarget::new("small", StatusModel::new(0))
+                    .with_model_name("small-v1")
+                    .with_price(ModelPrice::new(0.5, 1.5)),
+            )
+            .rule(
+                RoutingRule::new("cheap", vec!["large".to_string(), "small".to_string()])
+                    .with_tags(vec!["bulk".to_string()])
+                    .with_max_cost_per_1k(2.0),
+            );
+
+        let response = router.complete(tagged(&["bulk"])).await.unwrap();
+        assert_eq!(response.model, "small-v1");
+        let decision = response
+            .metadata
+            .get::<RoutingDecision>(ROUTING_KEY)
+            .unwrap();
+        assert_eq!(decision.rule.as_deref(), Some("cheap"));
+        assert_eq!(decision.candidates, vec!["small"]);
+
+        let response = router.complete(tagged(&[])).await.unwrap();
+        assert_eq!(response.model, "large-v1");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_on_retryable_errors() {
+        let limited = StatusModel::new(429);
+        let broken = StatusModel::new(400);
+        let healthy = StatusModel::new(0);
+
+        let router = ModelRouter::new()
+            .target(RouteTarget::new("limited", limited.clone()))
+            .target(RouteTarget::new("healthy", healthy.clone()));
+        let response = router.complete(CompletionRequest::default()).await.unwrap();
+        let decision = response
+            .metadata
+            .get::<RoutingDecision>(ROUTING_KEY)
+            .unwrap();
+        assert_eq!(decision.target, "healthy");
+        assert_eq!(decision.attempts.len(), 2);
+        assert!(decision.attempts[0].error.is_some());
+
+        let router = ModelRouter::new()
+            .target(RouteTarget::new("broken", broken.clone()))
+            .target(RouteTarget::new("healthy", healthy.clone()));
+        assert!(router.complete(CompletionRequest::default()).await.is_err());
+        assert_eq!(healthy.calls.load(Ordering::SeqCst), 1);
+    }
+}
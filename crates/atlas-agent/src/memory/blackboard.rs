@@ -0,0 +1,473 @@
+//! Workspace shared by the members of a team
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use anyhow::Result;
+use atlas_core::{Event, EventBus, Metadata};
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::{InMemoryStore, MemoryFilter, MemoryScope, MemoryStore};
+use crate::error::Error;
+use crate::state::MemoryEntry;
+
+/// Metadata key holding the name of the section an entry stores
+pub const SECTION_KEY: &str = "section";
+
+/// Metadata key holding the member who last wrote a section
+pub const AUTHOR_KEY: &str = "author";
+
+/// Topic blackboards with an event bus publish their changes on
+pub const BLACKBOARD_TOPIC: &str = "blackboard.change";
+
+/// Changes kept for subscribers that fall behind
+const CHANGE_BUFFER: usize = 64;
+
+/// Who may read and write a section of a blackboard
+///
+/// Sections are open to every member unless restricted. Members who may
+/// write a section may also read it.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SectionAccess {
+    /// Members who may read the section, or everyone if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    readers: Option<HashSet<String>>,
+
+    /// Members who may write the section, or everyone if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    writers: Option<HashSet<String>>,
+}
+
+impl SectionAccess {
+    /// Let every member read and write the section
+    pub fn open() -> Self {
+        Self::default()
+    }
+
+    /// Let a member read the section, restricting it to the members added
+    pub fn with_reader(mut self, member: impl Into<String>) -> Self {
+        self.readers
+            .get_or_insert_with(HashSet::new)
+            .insert(member.into());
+        self
+    }
+
+    /// Let a member write the section, restricting it to the members added
+    pub fn with_writer(mut self, member: impl Into<String>) -> Self {
+        self.writers
+            .get_or_insert_with(HashSet::new)
+            .insert(member.into());
+        self
+    }
+
+    /// Whether a member may read the section
+    pub fn can_read(&self, member: &str) -> bool {
+        self.readers
+            .as_ref()
+            .is_none_or(|readers| readers.contains(member))
+            || self.can_write(member)
+    }
+
+    /// Whether a member may write the section
+    pub fn can_write(&self, member: &str) -> bool {
+        self.writers
+            .as_ref()
+            .is_none_or(|writers| writers.contains(member))
+    }
+}
+
+/// Section of a blackboard at a version
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlackboardSection {
+    /// Name of the section
+    pub name: String,
+
+    /// Contents of the section
+    pub data: Metadata,
+
+    /// Version of the section, starting at 1
+    pub version: u64,
+
+    /// Member who wrote this version, if written by a member
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    /// When this version was written
+    pub updated_at: DateTime<Utc>,
+}
+
+impl BlackboardSection {
+    /// Read a section from the entry storing it
+    fn from_entry(entry: MemoryEntry) -> Result<Self> {
+        Ok(Self {
+            name: entry.metadata.try_get(SECTION_KEY)?,
+            data: serde_json::from_value(entry.data)?,
+            version: entry.version,
+            author: entry.metadata.get(AUTHOR_KEY),
+            updated_at: entry.timestamp,
+        })
+    }
+}
+
+/// Notification that a section of a blackboard was written
+///
+/// Changes carry no data, so subscribers read the section to see it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlackboardChange {
+    /// Name of the blackboard
+    pub blackboard: String,
+
+    /// Name of the section
+    pub section: String,
+
+    /// Version written
+    pub version: u64,
+
+    /// Member who wrote it, if written by a member
+    pub author: Option<String>,
+}
+
+/// Workspace the members of a team read and write during a task
+///
+/// A blackboard is made of named sections of [`Metadata`], kept as entries
+/// of a [`MemoryStore`] under [`MemoryScope::Shared`]. Clones of a
+/// blackboard share its store, permissions and subscribers;
+/// [`Blackboard::as_member`] gives a handle acting for a member, which may
+/// only read and write the sections its [`SectionAccess`] allows. A handle
+/// acting for no member may read and write every section. Writes use
+/// optimistic concurrency: each section carries a version, checked by the
+/// store as it writes, and writing one fails if it changed since the caller
+/// read it. Blackboards over the same store thus see each other's writes;
+/// with an event bus, they also see each other's changes, published on
+/// [`BLACKBOARD_TOPIC`].
+#[derive(Clone)]
+pub struct Blackboard {
+    /// Name of the blackboard
+    name: String,
+
+    /// Backing store
+    store: Arc<dyn MemoryStore>,
+
+    /// Member this handle acts for, if any
+    member: Option<String>,
+
+    /// Permissions of the restricted sections
+    access: Arc<RwLock<HashMap<String, SectionAccess>>>,
+
+    /// Bus changes are published on, if any
+    event_bus: Option<Arc<dyn EventBus>>,
+
+    /// Notifies subscribers of writes when there is no bus
+    changes: broadcast::Sender<BlackboardChange>,
+}
+
+impl std::fmt::Debug for Blackboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Blackboard")
+            .field("name", &self.name)
+            .field("member", &self.member)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Blackboard {
+    /// Create a blackboard kept in memory
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::from_store(name, Arc::new(InMemoryStore::new()))
+    }
+
+    /// Create a blackboard backed by a shared store
+    pub fn from_store(name: impl Into<String>, store: Arc<dyn MemoryStore>) -> Self {
+        let (changes, _) = broadcast::channel(CHANGE_BUFFER);
+        Self {
+            name: name.into(),
+            store,
+            member: None,
+            access: Arc::new(RwLock::new(HashMap::new())),
+            event_bus: None,
+            changes,
+        }
+    }
+
+    /// Publish changes on an event bus, for the blackboards over the same
+    /// store in other handles or processes
+    pub fn with_event_bus(mut self, bus: Arc<dyn EventBus>) -> Self {
+        self.event_bus = Some(bus);
+        self
+    }
+
+    /// Restrict who may read and write a section
+    pub fn with_access(self, section: impl Into<String>, access: SectionAccess) -> Self {
+        self.access
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(section.into(), access);
+        self
+    }
+
+    /// Get a handle acting for a member
+    pub fn as_member(&self, member: impl Into<String>) -> Self {
+        Self {
+            member: Some(member.into()),
+            ..self.clone()
+        }
+    }
+
+    /// Get the name of the blackboard
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the member this handle acts for
+    pub fn member(&self) -> Option<&str> {
+        self.member.as_deref()
+    }
+
+    /// Whether this handle may read a section
+    pub fn can_read(&self, section: &str) -> bool {
+        self.allows(section, SectionAccess::can_read)
+    }
+
+    /// Whether this handle may write a section
+    pub fn can_write(&self, section: &str) -> bool {
+        self.allows(section, SectionAccess::can_write)
+    }
+
+    /// Get a section, if it was written
+    pub async fn read(&self, section: &str) -> Result<Option<BlackboardSection>> {
+        self.check(section, "read", self.can_read(section))?;
+        self.entry(section)
+            .await?
+            .map(BlackboardSection::from_entry)
+            .transpose()
+    }
+
+    /// Replace a section's data if it is still at `expected_version`, or
+    /// create it if `expected_version` is 0
+    ///
+    /// Returns the section at its new version, or an error if another
+    /// member changed it first.
+    pub async fn write(
+        &self,
+        section: &str,
+        data: Metadata,
+        expected_version: u64,
+    ) -> Result<BlackboardSection> {
+        self.check(section, "write", self.can_write(section))?;
+        let mut metadata = Metadata::new();
+        metadata.insert(SECTION_KEY, section);
+        if let Some(member) = &self.member {
+            metadata.insert(AUTHOR_KEY, member);
+        }
+        let mut entry = MemoryEntry::new(serde_json::to_value(&data)?, metadata);
+        entry.id = self.section_id(section);
+        entry.scope = self.scope();
+        let entry = self.store.put_versioned(entry, expected_version).await?;
+
+        self.notify(BlackboardChange {
+            blackboard: self.name.clone(),
+            section: section.to_string(),
+            version: entry.version,
+            author: self.member.clone(),
+        })
+        .await;
+        BlackboardSection::from_entry(entry)
+    }
+
+    /// List the sections this handle may read, by name
+    pub async fn sections(&self) -> Result<Vec<BlackboardSection>> {
+        let filter = MemoryFilter::new().scope(self.scope());
+        let mut sections = Vec::new();
+        for entry in self.store.query(&filter).await? {
+            let section = BlackboardSection::from_entry(entry)?;
+            if self.can_read(&section.name) {
+                sections.push(section);
+            }
+        }
+        sections.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(sections)
+    }
+
+    /// Get the data of the sections this handle may read, keyed by section
+    pub async fn snapshot(&self) -> Result<Metadata> {
+        let mut snapshot = Metadata::new();
+        for section in self.sections().await? {
+            snapshot.insert(&section.name, section.data);
+        }
+        Ok(snapshot)
+    }
+
+    /// Stream the writes of every section made after subscribing
+    ///
+    /// With an event bus, the stream carries the writes of every blackboard
+    /// of this name on the bus; otherwise those made through this blackboard
+    /// and its clones.
+    pub async fn subscribe(&self) -> Result<BoxStream<'static, BlackboardChange>> {
+        if let Some(bus) = &self.event_bus {
+            bus.register_topic(BLACKBOARD_TOPIC).await?;
+            let name = self.name.clone();
+            let changes = bus
+                .subscribe(BLACKBOARD_TOPIC)
+                .await?
+                .filter_map(move |event| {
+                    let change = event.payload.to_typed::<BlackboardChange>().ok();
+                    let change = change.filter(|change| change.blackboard == name);
+                    async move { change }
+                });
+            return Ok(changes.boxed());
+        }
+        Ok(
+            futures::stream::unfold(self.changes.subscribe(), |mut changes| async move {
+                loop {
+                    match changes.recv().await {
+                        Ok(change) => return Some((change, changes)),
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            tracing::warn!(skipped, "Blackboard change subscriber lagged");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            })
+            .boxed(),
+        )
+    }
+
+    /// Tell subscribers about a write, which has happened whether or not
+    /// they hear of it
+    async fn notify(&self, change: BlackboardChange) {
+        let Some(bus) = &self.event_bus else {
+            // Nobody listening is not an error
+            let _ = self.changes.send(change);
+            return;
+        };
+        let published = async {
+            bus.register_topic(BLACKBOARD_TOPIC).await?;
+            let payload = Metadata::from_serialize(&change)?;
+            bus.publish(Event::new(BLACKBOARD_TOPIC, payload)).await
+        };
+        if let Err(e) = published.await {
+            tracing::warn!(blackboard = %self.name, error = %e, "Failed to publish blackboard change");
+        }
+    }
+
+    /// Scope of the entries storing the sections
+    fn scope(&self) -> MemoryScope {
+        MemoryScope::Shared(format!("blackboard.{}", self.name))
+    }
+
+    /// ID of the entry storing a section, the same for every blackboard of
+    /// this name, so their writes are versioned together
+    fn section_id(&self, section: &str) -> Uuid {
+        let key = format!("blackboard.{}.{}", self.name, section);
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes())
+    }
+
+    /// Get the entry storing a section
+    async fn entry(&self, section: &str) -> Result<Option<MemoryEntry>> {
+        let entry = self.store.get(self.section_id(section)).await?;
+        Ok(entry.filter(|entry| entry.scope == self.scope()))
+    }
+
+    /// Whether the section's permissions allow this handle's member
+    fn allows(&self, section: &str, allowed: fn(&SectionAccess, &str) -> bool) -> bool {
+        let Some(member) = &self.member else {
+            return true;
+        };
+        self.access
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(section)
+            .is_none_or(|access| allowed(access, member))
+    }
+
+    /// Fail unless an operation on a section is allowed
+    fn check(&self, section: &str, operation: &str, allowed: bool) -> Result<()> {
+        if allowed {
+            return Ok(());
+        }
+        Err(Error::InvalidRequest(format!(
+            "{} may not {} section {} of blackboard {}",
+            self.member.as_deref().unwrap_or_default(),
+            operation,
+            section,
+            self.name
+        ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(steps: u32) -> Metadata {
+        let mut data = Metadata::new();
+        data.insert("steps", steps);
+        data
+    }
+
+    #[tokio::test]
+    async fn test_versions_and_permissions() {
+        let board = Blackboard::new("launch").with_access(
+            "plan",
+            SectionAccess::open()
+                .with_writer("planner")
+                .with_reader("reviewer"),
+        );
+        let planner = board.as_member("planner");
+        let executor = board.as_member("executor");
+        let reviewer = board.as_member("reviewer");
+        let mut changes = board.subscribe().await.unwrap();
+
+        let written = planner.write("plan", plan(3), 0).await.unwrap();
+        assert_eq!(written.version, 1);
+        assert_eq!(written.author.as_deref(), Some("planner"));
+        let change = changes.next().await.unwrap();
+        assert_eq!((change.section.as_str(), change.version), ("plan", 1));
+
+        // Both read version 1; the second write loses
+        planner.write("plan", plan(4), 1).await.unwrap();
+        let conflict = board.write("plan", plan(5), 1).await.unwrap_err();
+        assert!(conflict.to_string().contains("Version conflict"));
+
+        let read = reviewer.read("plan").await.unwrap().unwrap();
+        assert_eq!((read.version, read.data.get::<u32>("steps")), (2, Some(4)));
+        assert!(reviewer.write("plan", plan(1), 2).await.is_err());
+        assert!(executor.read("plan").await.is_err());
+
+        executor.write("notes", plan(0), 0).await.unwrap();
+        let names: Vec<String> = executor
+            .sections()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|section| section.name)
+            .collect();
+        assert_eq!(names, ["notes"]);
+        let snapshot = board.snapshot().await.unwrap();
+        assert_eq!(snapshot.get_path::<u32>("plan.steps"), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_shared_store() {
+        let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::new());
+        let bus: Arc<dyn EventBus> = Arc::new(atlas_core::InMemoryEventBus::new());
+        let first = Blackboard::from_store("launch", store.clone()).with_event_bus(bus.clone());
+        let second = Blackboard::from_store("launch", store).with_event_bus(bus);
+        let mut changes = second.subscribe().await.unwrap();
+
+        // Boards built separately over one store conflict and notify alike
+        first.write("plan", plan(3), 0).await.unwrap();
+        assert!(second.write("plan", plan(4), 0).await.is_err());
+        let change = changes.next().await.unwrap();
+        assert_eq!((change.section.as_str(), change.version), ("plan", 1));
+        second.write("plan", plan(4), 1).await.unwrap();
+        assert!(first.write("plan", plan(5), 1).await.is_err());
+        assert_eq!(first.read("plan").await.unwrap().unwrap().version, 2);
+    }
+}
@@ -0,0 +1,215 @@
+//! Summarization of old memory entries
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use atlas_core::Metadata;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::IMPORTANCE_KEY;
+use crate::error::Error;
+use crate::llm::{ChatMessage, CompletionRequest, LanguageModel};
+use crate::state::MemoryEntry;
+
+/// Metadata key listing the IDs of the entries a summary replaced
+pub const SUMMARY_OF_KEY: &str = "summary_of";
+
+/// Compaction configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CompactionConfig {
+    /// Fraction of capacity at which the oldest entries are summarized
+    pub threshold: f32,
+
+    /// Number of oldest entries summarized into one entry
+    pub batch_size: usize,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.9,
+            batch_size: 20,
+        }
+    }
+}
+
+/// Summarizes batches of memory entries with a language model
+pub struct MemoryCompactor {
+    /// Language model
+    model: Arc<dyn LanguageModel>,
+
+    /// Compaction configuration
+    config: CompactionConfig,
+}
+
+impl MemoryCompactor {
+    /// Create a new compactor
+    pub fn new(model: Arc<dyn LanguageModel>, config: CompactionConfig) -> Self {
+        Self { model, config }
+    }
+
+    /// Get the compaction configuration
+    pub fn config(&self) -> &CompactionConfig {
+        &self.config
+    }
+
+    /// Whether a store holding `len` of `capacity` entries should be compacted
+    pub fn should_compact(&self, len: usize, capacity: usize) -> bool {
+        let threshold = (capacity as f32 * self.config.threshold).ceil() as usize;
+        len >= 2 && self.config.batch_size >= 2 && len >= threshold
+    }
+
+    /// Summarize entries, oldest first, into a single entry
+    ///
+    /// The summary takes the scope of the first entry, the newest timestamp
+    /// and highest importance of the batch, and lists the replaced IDs under
+    /// `summary_of`.
+    pub async fn summarize(&self, entries: &[MemoryEntry]) -> Result<MemoryEntry> {
+        let newest = entries
+            .iter()
+            .map(|e| e.timestamp)
+            .max()
+            .ok_or_else(|| Error::MemoryError("No entries to summarize".to_string()))?;
+
+        let transcript = entries
+            .iter()
+            .map(|e| format!("[{}] {}", e.timestamp.to_rfc3339(), e.text()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let request = CompletionRequest::new(vec![
+            ChatMessage::system(
+                "You compact an agent's memory. Summarize the entries below into a short, \
+                 self-contained note that keeps every fact, decision and outcome the agent may \
+                 need later. Respond with the note only.",
+            ),
+            ChatMessage::user(transcript),
+        ])
+        .with_temperature(0.0);
+        let response = self.model.complete(request).await?;
+
+        let mut metadata = Metadata::new();
+        metadata.insert(
+            SUMMARY_OF_KEY,
+            entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+        );
+        let importance = entries
+            .iter()
+            .map(|e| e.importance())
+            .fold(f64::NEG_INFINITY, f64::max);
+        if importance > 0.0 {
+            metadata.insert(IMPORTANCE_KEY, importance);
+        }
+
+        let mut summary = MemoryEntry::new(
+            Value::String(response.message.content.trim().to_string()),
+            metadata,
+        );
+        summary.timestamp = newest;
+        summary.scope = entries[0].scope.clone();
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionResponse;
+    use crate::state::{AgentStateManager, MemoryConfig};
+    use crate::State;
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    struct CountingSummarizer;
+
+    #[async_trait]
+    impl LanguageModel for CountingSummarizer {
+        fn name(&self) -> &str {
+            "summarizer"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            let lines = request.messages.last().unwrap().content.lines().count();
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(format!("{} events happened", lines)),
+                model: "summarizer".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compaction_replaces_oldest_entries() {
+        let compactor = MemoryCompactor::new(
+            Arc::new(CountingSummarizer),
+            CompactionConfig {
+                threshold: 0.8,
+                batch_size: 3,
+            },
+        );
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                capacity: 5,
+                ..Default::default()
+            },
+        )
+        .with_compactor(compactor);
+
+        let mut ids = Vec::new();
+        for i in 0..4 {
+            ids.push(
+                manager
+                    .add_memory(json!(format!("event {}", i)), Metadata::new())
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        // The fourth entry reached the threshold, so the first three were summarized
+        let entries = manager.list_memory().await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, ids[3]);
+        assert_eq!(entries[1].data, json!("3 events happened"));
+        assert_eq!(
+            entries[1].metadata.get::<Vec<uuid::Uuid>>(SUMMARY_OF_KEY),
+            Some(ids[..3].to_vec())
+        );
+    }
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl LanguageModel for Unreachable {
+        fn name(&self) -> &str {
+            "unreachable"
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            Err(anyhow::anyhow!("connection refused"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_compaction_keeps_entry() {
+        let compactor = MemoryCompactor::new(Arc::new(Unreachable), CompactionConfig::default());
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                capacity: 2,
+                ..Default::default()
+            },
+        )
+        .with_compactor(compactor);
+
+        // Memory is full after the second entry, which is stored all the same
+        for i in 0..2 {
+            manager
+                .add_memory(json!(format!("event {}", i)), Metadata::new())
+                .await
+                .unwrap();
+        }
+        assert_eq!(manager.list_memory().await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,117 @@
+//! Deduplication of memory entries on insert
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::promotion::OCCURRENCES_KEY;
+use crate::state::{MemoryEntry, TAGS_KEY};
+
+/// What to do with an entry that duplicates an existing one
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateAction {
+    /// Drop the new entry
+    Skip,
+
+    /// Merge the new entry's metadata and tags into the existing entry
+    Merge,
+
+    /// Count the repetition on the existing entry
+    #[default]
+    Count,
+}
+
+/// Deduplication configuration
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DedupConfig {
+    /// What to do with duplicates
+    #[serde(default)]
+    pub action: DuplicateAction,
+
+    /// Embedding similarity at or above which entries are near-duplicates;
+    /// only exact duplicates are detected when unset or without an
+    /// embedding provider
+    #[serde(default)]
+    pub similarity_threshold: Option<f32>,
+}
+
+/// Hash of an entry's data, equal for exact duplicates
+pub fn content_hash(data: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    // Serialization sorts object keys, so equal values hash equally
+    data.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Whether two entries can be duplicates of each other
+///
+/// Entries in different scopes or of different kinds are kept apart.
+pub fn comparable(a: &MemoryEntry, b: &MemoryEntry) -> bool {
+    a.scope == b.scope && a.kind == b.kind
+}
+
+/// Apply a duplicate to the existing entry, returning the updated entry or
+/// `None` if it is unchanged
+pub fn absorb(
+    existing: &MemoryEntry,
+    duplicate: &MemoryEntry,
+    action: DuplicateAction,
+) -> Option<MemoryEntry> {
+    let mut entry = existing.clone();
+    match action {
+        DuplicateAction::Skip => return None,
+        DuplicateAction::Merge => {
+            if let Ok(Value::Object(fields)) = serde_json::to_value(&duplicate.metadata) {
+                for (key, value) in fields {
+                    entry.metadata.insert(key, value);
+                }
+            }
+            entry.tags.extend(duplicate.tags.iter().cloned());
+            if !entry.tags.is_empty() {
+                entry.metadata.insert(TAGS_KEY, &entry.tags);
+            }
+        }
+        DuplicateAction::Count => {
+            let occurrences = entry.metadata.get::<usize>(OCCURRENCES_KEY).unwrap_or(1);
+            entry.metadata.insert(OCCURRENCES_KEY, occurrences + 1);
+        }
+    }
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::Metadata;
+    use serde_json::json;
+
+    #[test]
+    fn test_absorb_duplicate() {
+        let mut metadata = Metadata::new();
+        metadata.insert(TAGS_KEY, ["tool"]);
+        let existing = MemoryEntry::new(json!({"status": 200, "body": "ok"}), metadata);
+        let mut metadata = Metadata::new();
+        metadata.insert(TAGS_KEY, ["http"]);
+        metadata.insert("source", "fetch");
+        let duplicate = MemoryEntry::new(json!({"body": "ok", "status": 200}), metadata);
+        assert_eq!(content_hash(&existing.data), content_hash(&duplicate.data));
+
+        assert!(absorb(&existing, &duplicate, DuplicateAction::Skip).is_none());
+        let counted = absorb(&existing, &duplicate, DuplicateAction::Count).unwrap();
+        assert_eq!(counted.metadata.get::<usize>(OCCURRENCES_KEY), Some(2));
+
+        let merged = absorb(&existing, &duplicate, DuplicateAction::Merge).unwrap();
+        assert_eq!(
+            merged.metadata.get::<String>("source").as_deref(),
+            Some("fetch")
+        );
+        assert_eq!(
+            merged.metadata.get::<Vec<String>>(TAGS_KEY),
+            Some(vec!["http".to_string(), "tool".to_string()])
+        );
+        assert_eq!(merged.id, existing.id);
+    }
+}
@@ -0,0 +1,221 @@
+//! Memory entry filters
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{MemoryKind, MemoryScope};
+use crate::state::MemoryEntry;
+
+/// Boolean expression over entry tags
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagExpr {
+    /// The entry has the tag
+    Tag(String),
+
+    /// Every expression matches
+    All(Vec<TagExpr>),
+
+    /// At least one expression matches
+    Any(Vec<TagExpr>),
+
+    /// The expression does not match
+    Not(Box<TagExpr>),
+}
+
+impl TagExpr {
+    /// Match entries with a tag
+    pub fn tag<S: Into<String>>(tag: S) -> Self {
+        Self::Tag(tag.into())
+    }
+
+    /// Match entries matching both expressions
+    pub fn and(self, other: TagExpr) -> Self {
+        match self {
+            Self::All(mut exprs) => {
+                exprs.push(other);
+                Self::All(exprs)
+            }
+            expr => Self::All(vec![expr, other]),
+        }
+    }
+
+    /// Match entries matching either expression
+    pub fn or(self, other: TagExpr) -> Self {
+        match self {
+            Self::Any(mut exprs) => {
+                exprs.push(other);
+                Self::Any(exprs)
+            }
+            expr => Self::Any(vec![expr, other]),
+        }
+    }
+
+    /// Whether a set of tags matches the expression
+    pub fn matches(&self, tags: &BTreeSet<String>) -> bool {
+        match self {
+            Self::Tag(tag) => tags.contains(tag),
+            Self::All(exprs) => exprs.iter().all(|e| e.matches(tags)),
+            Self::Any(exprs) => exprs.iter().any(|e| e.matches(tags)),
+            Self::Not(expr) => !expr.matches(tags),
+        }
+    }
+
+    /// Narrow the entries that can match using a tag index
+    ///
+    /// Returns `None` when the expression can match entries outside the
+    /// index, such as under a negation.
+    pub fn candidates(&self, index: &HashMap<String, HashSet<Uuid>>) -> Option<HashSet<Uuid>> {
+        match self {
+            Self::Tag(tag) => Some(index.get(tag).cloned().unwrap_or_default()),
+            Self::All(exprs) => exprs
+                .iter()
+                .filter_map(|e| e.candidates(index))
+                .reduce(|a, b| a.intersection(&b).copied().collect()),
+            Self::Any(exprs) => {
+                let mut union = HashSet::new();
+                for expr in exprs {
+                    union.extend(expr.candidates(index)?);
+                }
+                Some(union)
+            }
+            Self::Not(_) => None,
+        }
+    }
+}
+
+impl std::ops::Not for TagExpr {
+    type Output = TagExpr;
+
+    /// Match entries not matching the expression
+    fn not(self) -> Self::Output {
+        Self::Not(Box::new(self))
+    }
+}
+
+/// Position in the chronological order of memory entries
+///
+/// Entries are ordered by timestamp, then ID. A cursor names the last entry
+/// returned, so iteration resumes correctly when entries are added.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct MemoryCursor {
+    /// Timestamp of the entry
+    pub timestamp: DateTime<Utc>,
+
+    /// ID of the entry
+    pub id: Uuid,
+}
+
+impl MemoryCursor {
+    /// Cursor positioned at an entry
+    pub fn of(entry: &MemoryEntry) -> Self {
+        Self {
+            timestamp: entry.timestamp,
+            id: entry.id,
+        }
+    }
+}
+
+/// Criteria memory entries must match to be returned
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryFilter {
+    /// Only entries in this scope
+    pub scope: Option<MemoryScope>,
+
+    /// Only entries whose tags match this expression
+    pub tags: Option<TagExpr>,
+
+    /// Only entries of this kind
+    pub kind: Option<MemoryKind>,
+
+    /// Only entries at or after this time
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only entries before this time
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl MemoryFilter {
+    /// Create a filter matching every entry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match entries in a scope
+    pub fn scope(mut self, scope: MemoryScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Only match entries whose tags match an expression
+    pub fn tags(mut self, tags: TagExpr) -> Self {
+        self.tags = Some(tags);
+        self
+    }
+
+    /// Only match entries of a kind
+    pub fn kind(mut self, kind: MemoryKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Only match entries from `from`, inclusive, to `to`, exclusive
+    pub fn between(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.since = Some(from);
+        self.until = Some(to);
+        self
+    }
+
+    /// Whether an entry matches the filter
+    pub fn matches(&self, entry: &MemoryEntry) -> bool {
+        let in_scope = match &self.scope {
+            Some(scope) => entry.scope == *scope,
+            None => true,
+        };
+        let tagged = match &self.tags {
+            Some(tags) => tags.matches(&entry.tags),
+            None => true,
+        };
+        let in_range = match (self.since, self.until) {
+            (Some(since), _) if entry.timestamp < since => false,
+            (_, Some(until)) if entry.timestamp >= until => false,
+            _ => true,
+        };
+        let of_kind = match self.kind {
+            Some(kind) => entry.kind == kind,
+            None => true,
+        };
+        in_scope && tagged && of_kind && in_range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> BTreeSet<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tag_expressions() {
+        let expr = TagExpr::tag("observation")
+            .and(TagExpr::tag("tool:search").or(TagExpr::tag("tool:fetch")))
+            .and(!TagExpr::tag("error"));
+
+        assert!(expr.matches(&tags(&["observation", "tool:fetch"])));
+        assert!(!expr.matches(&tags(&["observation", "tool:fetch", "error"])));
+        assert!(!expr.matches(&tags(&["tool:search"])));
+
+        let a = Uuid::new_v4();
+        let b = Uuid::new_v4();
+        let mut index = HashMap::new();
+        index.insert("observation".to_string(), HashSet::from([a, b]));
+        index.insert("tool:fetch".to_string(), HashSet::from([b]));
+        assert_eq!(expr.candidates(&index), Some(HashSet::from([b])));
+        assert_eq!((!TagExpr::tag("error")).candidates(&index), None);
+    }
+}
@@ -0,0 +1,403 @@
+//! In-process HNSW vector index
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use uuid::Uuid;
+
+/// Candidate ordered by distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Graph node
+#[derive(Clone, Debug)]
+struct Node {
+    /// Entry ID
+    id: Uuid,
+
+    /// Normalized vector
+    vector: Vec<f32>,
+
+    /// Neighbors per layer
+    links: Vec<Vec<usize>>,
+
+    /// Removed nodes stay in the graph for navigation
+    deleted: bool,
+}
+
+/// Hierarchical navigable small world index over cosine similarity
+///
+/// Removal marks nodes as deleted so the graph stays navigable; the graph is
+/// rebuilt from the live nodes once deleted nodes outnumber them.
+#[derive(Clone, Debug)]
+pub struct HnswIndex {
+    /// Maximum neighbors per node on upper layers
+    m: usize,
+
+    /// Candidate list size while inserting
+    ef_construction: usize,
+
+    /// Candidate list size while searching
+    ef_search: usize,
+
+    /// Graph nodes
+    nodes: Vec<Node>,
+
+    /// Node index by entry ID
+    ids: HashMap<Uuid, usize>,
+
+    /// Entry point node
+    entry: Option<usize>,
+
+    /// Random state for level assignment
+    seed: u64,
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(16, 100)
+    }
+}
+
+impl HnswIndex {
+    /// Create an index with `m` neighbors per node and the given construction breadth
+    pub fn new(m: usize, ef_construction: usize) -> Self {
+        Self {
+            m: m.max(2),
+            ef_construction: ef_construction.max(1),
+            ef_search: 64,
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            entry: None,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    /// Set the candidate list size used while searching
+    pub fn with_ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = ef_search.max(1);
+        self
+    }
+
+    /// Number of live entries
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether the index has no live entries
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Whether the index contains an entry
+    pub fn contains(&self, id: Uuid) -> bool {
+        self.ids.contains_key(&id)
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.ids.clear();
+        self.entry = None;
+    }
+
+    /// Insert or replace the vector for an entry
+    pub fn insert(&mut self, id: Uuid, vector: &[f32]) {
+        self.remove(id);
+
+        let vector = normalize(vector);
+        let level = self.random_level();
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            id,
+            vector,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.ids.insert(id, node);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+
+        let query = self.nodes[node].vector.clone();
+        let top = self.nodes[entry].links.len() - 1;
+        let mut nearest = entry;
+        for layer in (level + 1..=top).rev() {
+            nearest = self.greedy(&query, nearest, layer);
+        }
+
+        let mut entry_points = vec![nearest];
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(&query, &entry_points, self.ef_construction, layer);
+            let neighbors: Vec<usize> = candidates
+                .iter()
+                .take(self.max_links(layer))
+                .map(|c| c.node)
+                .collect();
+
+            for &neighbor in &neighbors {
+                self.nodes[neighbor].links[layer].push(node);
+                self.prune(neighbor, layer);
+            }
+            self.nodes[node].links[layer] = neighbors;
+            entry_points = candidates.into_iter().map(|c| c.node).collect();
+        }
+
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    /// Remove an entry, returning whether it was present
+    pub fn remove(&mut self, id: Uuid) -> bool {
+        let Some(node) = self.ids.remove(&id) else {
+            return false;
+        };
+        self.nodes[node].deleted = true;
+
+        if self.ids.is_empty() {
+            self.clear();
+        } else if self.nodes.len() > 2 * self.ids.len() {
+            self.rebuild();
+        }
+        true
+    }
+
+    /// Find the `k` entries most similar to the query, best first
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(Uuid, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let query = normalize(query);
+        let mut nearest = entry;
+        for layer in (1..self.nodes[entry].links.len()).rev() {
+            nearest = self.greedy(&query, nearest, layer);
+        }
+
+        // Deleted nodes take up candidate slots, so widen the search to compensate
+        let deleted = self.nodes.len() - self.ids.len();
+        let ef = self.ef_search.max(k) + deleted.min(self.ef_search);
+        self.search_layer(&query, &[nearest], ef, 0)
+            .into_iter()
+            .filter(|c| !self.nodes[c.node].deleted)
+            .take(k)
+            .map(|c| (self.nodes[c.node].id, 1.0 - c.distance))
+            .collect()
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    fn random_level(&mut self) -> usize {
+        // xorshift64*
+        self.seed ^= self.seed >> 12;
+        self.seed ^= self.seed << 25;
+        self.seed ^= self.seed >> 27;
+        let bits = self.seed.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+        let uniform = (bits as f64 + 1.0) / (1u64 << 53) as f64;
+        (-uniform.ln() / (self.m as f64).ln()) as usize
+    }
+
+    fn distance(&self, query: &[f32], node: usize) -> f32 {
+        let dot: f32 = query
+            .iter()
+            .zip(&self.nodes[node].vector)
+            .map(|(a, b)| a * b)
+            .sum();
+        1.0 - dot
+    }
+
+    fn greedy(&self, query: &[f32], start: usize, layer: usize) -> usize {
+        let mut current = start;
+        let mut best = self.distance(query, current);
+        loop {
+            let mut improved = false;
+            for &neighbor in &self.nodes[current].links[layer] {
+                let distance = self.distance(query, neighbor);
+                if distance < best {
+                    best = distance;
+                    current = neighbor;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return current;
+            }
+        }
+    }
+
+    /// Beam search on one layer, returning candidates closest first
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_points: &[usize],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entry_points.iter().copied().collect();
+        let mut frontier: BinaryHeap<Reverse<Candidate>> = BinaryHeap::new();
+        let mut found: BinaryHeap<Candidate> = BinaryHeap::new();
+
+        for &node in entry_points {
+            let candidate = Candidate {
+                distance: self.distance(query, node),
+                node,
+            };
+            frontier.push(Reverse(candidate));
+            found.push(candidate);
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Reverse(current)) = frontier.pop() {
+            if let Some(worst) = found.peek() {
+                if found.len() >= ef && current.distance > worst.distance {
+                    break;
+                }
+            }
+
+            for &neighbor in &self.nodes[current.node].links[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    distance: self.distance(query, neighbor),
+                    node: neighbor,
+                };
+                let closer = match found.peek() {
+                    Some(worst) => candidate.distance < worst.distance,
+                    None => true,
+                };
+                if found.len() < ef || closer {
+                    frontier.push(Reverse(candidate));
+                    found.push(candidate);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        found.into_sorted_vec()
+    }
+
+    fn prune(&mut self, node: usize, layer: usize) {
+        let max = self.max_links(layer);
+        if self.nodes[node].links[layer].len() <= max {
+            return;
+        }
+
+        let vector = self.nodes[node].vector.clone();
+        let mut links: Vec<Candidate> = self.nodes[node].links[layer]
+            .iter()
+            .map(|&n| Candidate {
+                distance: self.distance(&vector, n),
+                node: n,
+            })
+            .collect();
+        links.sort();
+        links.truncate(max);
+        self.nodes[node].links[layer] = links.into_iter().map(|c| c.node).collect();
+    }
+
+    fn rebuild(&mut self) {
+        let live: Vec<(Uuid, Vec<f32>)> = self
+            .nodes
+            .iter()
+            .filter(|n| !n.deleted)
+            .map(|n| (n.id, n.vector.clone()))
+            .collect();
+
+        self.clear();
+        for (id, vector) in live {
+            self.insert(id, &vector);
+        }
+    }
+}
+
+/// Scale a vector to unit length so dot products are cosine similarities
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        vector.to_vec()
+    } else {
+        vector.iter().map(|x| x / norm).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(i: usize) -> Vec<f32> {
+        let angle = i as f32 * 0.01;
+        vec![angle.cos(), angle.sin(), (i % 7) as f32 * 0.001]
+    }
+
+    #[test]
+    fn test_search_matches_brute_force() {
+        let mut index = HnswIndex::new(8, 64);
+        let ids: Vec<Uuid> = (0..300).map(|_| Uuid::new_v4()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            index.insert(*id, &vector(i));
+        }
+        assert_eq!(index.len(), 300);
+
+        let results = index.search(&vector(150), 3);
+        let found: Vec<Uuid> = results.iter().map(|(id, _)| *id).collect();
+        assert_eq!(found[0], ids[150]);
+        assert!(found.contains(&ids[149]) && found.contains(&ids[151]));
+        assert!(results[0].1 > 0.999);
+    }
+
+    #[test]
+    fn test_remove_and_rebuild() {
+        let mut index = HnswIndex::default();
+        let ids: Vec<Uuid> = (0..50).map(|_| Uuid::new_v4()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            index.insert(*id, &vector(i));
+        }
+
+        for id in &ids[..40] {
+            assert!(index.remove(*id));
+        }
+        assert_eq!(index.len(), 10);
+        assert!(!index.contains(ids[0]));
+
+        let results = index.search(&vector(0), 5);
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|(id, _)| ids[40..].contains(id)));
+        assert_eq!(results[0].0, ids[40]);
+    }
+}
@@ -0,0 +1,415 @@
+//! Default in-process memory store
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::{EvictionPolicy, MemoryCursor, MemoryFilter, MemoryStore};
+use crate::error::Error;
+use crate::state::MemoryEntry;
+
+/// Eviction order of an entry; the smallest key is evicted first
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+struct EvictionKey {
+    /// Order-preserving encoding of the entry's importance
+    rank: u64,
+
+    /// Insertion or access tick, breaking ties oldest first
+    tick: u64,
+}
+
+/// Stored entry with its bookkeeping
+#[derive(Debug)]
+struct Slot {
+    /// The entry
+    entry: MemoryEntry,
+
+    /// Insertion sequence number
+    seq: u64,
+
+    /// Current eviction key
+    key: EvictionKey,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Entries by ID
+    slots: HashMap<Uuid, Slot>,
+
+    /// Entry IDs in insertion order
+    order: BTreeMap<u64, Uuid>,
+
+    /// Entry IDs in eviction order
+    queue: BTreeSet<(EvictionKey, Uuid)>,
+
+    /// Entry IDs by tag
+    tags: HashMap<String, HashSet<Uuid>>,
+
+    /// Entries in chronological order
+    timeline: BTreeSet<MemoryCursor>,
+
+    /// Monotonic counter for sequence numbers and access ticks
+    tick: u64,
+}
+
+impl Inner {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, id: Uuid) -> Option<MemoryEntry> {
+        let slot = self.slots.remove(&id)?;
+        self.order.remove(&slot.seq);
+        self.queue.remove(&(slot.key, id));
+        self.timeline.remove(&MemoryCursor::of(&slot.entry));
+        for tag in &slot.entry.tags {
+            if let Some(ids) = self.tags.get_mut(tag) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+        Some(slot.entry)
+    }
+
+    /// Entries matching a filter, oldest first
+    ///
+    /// Tag filters are resolved through the tag index where possible.
+    fn matching<'a>(&'a self, filter: &'a MemoryFilter) -> Vec<&'a Slot> {
+        let candidates = filter.tags.as_ref().and_then(|t| t.candidates(&self.tags));
+        let mut slots: Vec<&Slot> = match candidates {
+            Some(ids) => {
+                let mut slots: Vec<&Slot> =
+                    ids.iter().filter_map(|id| self.slots.get(id)).collect();
+                slots.sort_by_key(|s| s.seq);
+                slots
+            }
+            None => self
+                .order
+                .values()
+                .filter_map(|id| self.slots.get(id))
+                .collect(),
+        };
+        slots.retain(|s| filter.matches(&s.entry));
+        slots
+    }
+
+    /// Move an entry to the back of the LRU queue
+    fn touch(&mut self, id: Uuid) {
+        let tick = self.next_tick();
+        if let Some(slot) = self.slots.get_mut(&id) {
+            // Pinned entries are not queued
+            if self.queue.remove(&(slot.key, id)) {
+                slot.key.tick = tick;
+                self.queue.insert((slot.key, id));
+            }
+        }
+    }
+}
+
+/// Memory store keeping entries in process
+///
+/// Lookups are constant time and insertion, removal and eviction are
+/// logarithmic. Entries are evicted according to the store's
+/// [`EvictionPolicy`], except pinned entries, which are never evicted.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    /// Eviction policy
+    policy: EvictionPolicy,
+
+    /// Entries and indexes
+    inner: Mutex<Inner>,
+}
+
+impl InMemoryStore {
+    /// Create an empty store with FIFO eviction
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the eviction policy
+    pub fn with_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Inner>> {
+        self.inner
+            .lock()
+            .map_err(|_| Error::MemoryError("Memory store lock poisoned".to_string()).into())
+    }
+
+    /// Insert an entry into the indexes, replacing any with the same ID
+    fn insert(&self, inner: &mut Inner, entry: MemoryEntry) {
+        let id = entry.id;
+        // A replaced entry keeps its position in insertion order
+        let seq = match inner.slots.get(&id) {
+            Some(slot) => slot.seq,
+            None => inner.next_tick(),
+        };
+        inner.remove(id);
+        let rank = match self.policy {
+            EvictionPolicy::Importance => importance_rank(entry.importance()),
+            EvictionPolicy::Fifo | EvictionPolicy::Lru => 0,
+        };
+        let tick = match self.policy {
+            EvictionPolicy::Lru => inner.next_tick(),
+            EvictionPolicy::Fifo | EvictionPolicy::Importance => seq,
+        };
+        let key = EvictionKey { rank, tick };
+
+        inner.order.insert(seq, id);
+        if !entry.is_pinned() {
+            inner.queue.insert((key, id));
+        }
+        inner.timeline.insert(MemoryCursor::of(&entry));
+        for tag in &entry.tags {
+            inner.tags.entry(tag.clone()).or_default().insert(id);
+        }
+        inner.slots.insert(id, Slot { entry, seq, key });
+    }
+}
+
+/// Fail unless the entry stored under an ID is at the expected version
+fn check_version(inner: &Inner, id: Uuid, expected_version: u64) -> Result<()> {
+    let version = inner.slots.get(&id).map_or(0, |slot| slot.entry.version);
+    if version != expected_version {
+        return Err(Error::MemoryError(format!(
+            "Version conflict on memory entry {}: expected {}, found {}",
+            id, expected_version, version
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl MemoryStore for InMemoryStore {
+    async fn put(&self, entry: MemoryEntry) -> Result<()> {
+        let mut inner = self.lock()?;
+        self.insert(&mut inner, entry);
+        Ok(())
+    }
+
+    async fn put_versioned(
+        &self,
+        mut entry: MemoryEntry,
+        expected_version: u64,
+    ) -> Result<MemoryEntry> {
+        let mut inner = self.lock()?;
+        check_version(&inner, entry.id, expected_version)?;
+        entry.version = expected_version + 1;
+        self.insert(&mut inner, entry.clone());
+        Ok(entry)
+    }
+
+    async fn remove_versioned(&self, id: Uuid, expected_version: u64) -> Result<()> {
+        let mut inner = self.lock()?;
+        check_version(&inner, id, expected_version)?;
+        inner.remove(id);
+        Ok(())
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Option<MemoryEntry>> {
+        let mut inner = self.lock()?;
+        if self.policy == EvictionPolicy::Lru {
+            inner.touch(id);
+        }
+        Ok(inner.slots.get(&id).map(|s| s.entry.clone()))
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        filter: &MemoryFilter,
+        k: usize,
+    ) -> Result<Vec<MemoryEntry>> {
+        let mut inner = self.lock()?;
+        let found: Vec<MemoryEntry> = inner
+            .matching(filter)
+            .into_iter()
+            .filter(|s| s.entry.text().contains(query))
+            .take(k)
+            .map(|s| s.entry.clone())
+            .collect();
+        if self.policy == EvictionPolicy::Lru {
+            for entry in &found {
+                inner.touch(entry.id);
+            }
+        }
+        Ok(found)
+    }
+
+    async fn list(&self) -> Result<Vec<MemoryEntry>> {
+        let inner = self.lock()?;
+        Ok(inner
+            .order
+            .values()
+            .filter_map(|id| inner.slots.get(id))
+            .map(|s| s.entry.clone())
+            .collect())
+    }
+
+    async fn query(&self, filter: &MemoryFilter) -> Result<Vec<MemoryEntry>> {
+        let inner = self.lock()?;
+        Ok(inner
+            .matching(filter)
+            .into_iter()
+            .map(|s| s.entry.clone())
+            .collect())
+    }
+
+    async fn chronological(
+        &self,
+        filter: &MemoryFilter,
+        after: Option<&MemoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<MemoryEntry>> {
+        use std::ops::Bound;
+
+        let inner = self.lock()?;
+        // Start at the later of the cursor and the start of the time range
+        let start = match (after, filter.since) {
+            (Some(after), Some(since)) if since > after.timestamp => {
+                Bound::Included(MemoryCursor {
+                    timestamp: since,
+                    id: Uuid::nil(),
+                })
+            }
+            (Some(after), _) => Bound::Excluded(*after),
+            (None, Some(since)) => Bound::Included(MemoryCursor {
+                timestamp: since,
+                id: Uuid::nil(),
+            }),
+            (None, None) => Bound::Unbounded,
+        };
+        Ok(inner
+            .timeline
+            .range((start, Bound::Unbounded))
+            .take_while(|c| match filter.until {
+                Some(until) => c.timestamp < until,
+                None => true,
+            })
+            .filter_map(|c| inner.slots.get(&c.id))
+            .filter(|s| filter.matches(&s.entry))
+            .take(limit)
+            .map(|s| s.entry.clone())
+            .collect())
+    }
+
+    async fn remove(&self, id: Uuid) -> Result<bool> {
+        Ok(self.lock()?.remove(id).is_some())
+    }
+
+    async fn evict(&self, capacity: usize) -> Result<Vec<MemoryEntry>> {
+        let mut inner = self.lock()?;
+        let mut evicted = Vec::new();
+        while inner.slots.len() > capacity {
+            let Some(&(_, id)) = inner.queue.first() else {
+                break;
+            };
+            evicted.extend(inner.remove(id));
+        }
+        Ok(evicted)
+    }
+
+    async fn len(&self) -> Result<usize> {
+        Ok(self.lock()?.slots.len())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let mut inner = self.lock()?;
+        inner.slots.clear();
+        inner.order.clear();
+        inner.queue.clear();
+        inner.tags.clear();
+        inner.timeline.clear();
+        Ok(())
+    }
+}
+
+/// Map an importance score to an integer with the same ordering
+fn importance_rank(importance: f64) -> u64 {
+    let bits = importance.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{IMPORTANCE_KEY, PINNED_KEY};
+    use atlas_core::Metadata;
+    use serde_json::json;
+
+    async fn fill(store: &InMemoryStore, importance: &[f64]) -> Vec<Uuid> {
+        let mut ids = Vec::new();
+        for (i, score) in importance.iter().enumerate() {
+            let mut metadata = Metadata::new();
+            metadata.insert(IMPORTANCE_KEY, score);
+            let entry = MemoryEntry::new(json!(format!("entry {}", i)), metadata);
+            ids.push(entry.id);
+            store.put(entry).await.unwrap();
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_fifo_and_search() {
+        let store = InMemoryStore::new();
+        let ids = fill(&store, &[0.0, 0.0, 0.0]).await;
+
+        let all = MemoryFilter::new();
+        assert_eq!(store.search("entry", &all, 10).await.unwrap().len(), 3);
+        let evicted = store.evict(1).await.unwrap();
+        assert_eq!(evicted.iter().map(|e| e.id).collect::<Vec<_>>(), ids[..2]);
+        assert_eq!(store.len().await.unwrap(), 1);
+        assert!(store.get(ids[2]).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_lru_and_importance() {
+        let store = InMemoryStore::new().with_policy(EvictionPolicy::Lru);
+        let ids = fill(&store, &[0.0, 0.0, 0.0]).await;
+        store.get(ids[0]).await.unwrap();
+        let evicted = store.evict(2).await.unwrap();
+        assert_eq!(evicted[0].id, ids[1]);
+
+        let store = InMemoryStore::new().with_policy(EvictionPolicy::Importance);
+        let ids = fill(&store, &[0.9, -1.0, 0.1, 0.5]).await;
+        let evicted = store.evict(2).await.unwrap();
+        assert_eq!(
+            evicted.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![ids[1], ids[2]]
+        );
+        let kept = store.list().await.unwrap();
+        assert_eq!(
+            kept.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![ids[0], ids[3]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pinned_entries_survive_eviction() {
+        let store = InMemoryStore::new().with_policy(EvictionPolicy::Lru);
+        let mut metadata = Metadata::new();
+        metadata.insert(PINNED_KEY, true);
+        let pinned = MemoryEntry::new(json!("always answer in french"), metadata);
+        store.put(pinned.clone()).await.unwrap();
+        let ids = fill(&store, &[0.0, 0.0]).await;
+        store.get(pinned.id).await.unwrap();
+
+        let evicted = store.evict(0).await.unwrap();
+        assert_eq!(evicted.iter().map(|e| e.id).collect::<Vec<_>>(), ids);
+        assert_eq!(store.len().await.unwrap(), 1);
+        assert!(store.get(pinned.id).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,215 @@
+//! Memory storage and retrieval for agents
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::state::MemoryEntry;
+use crate::TaskState;
+
+pub mod blackboard;
+pub mod compaction;
+pub mod dedup;
+pub mod filter;
+pub mod hnsw;
+pub mod in_memory;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod promotion;
+pub mod scope;
+pub mod scoring;
+pub mod shared;
+pub mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "full-text")]
+pub mod text;
+pub mod wal;
+
+pub use blackboard::{Blackboard, BlackboardChange, BlackboardSection, SectionAccess};
+pub use compaction::{CompactionConfig, MemoryCompactor, SUMMARY_OF_KEY};
+pub use dedup::{DedupConfig, DuplicateAction};
+pub use filter::{MemoryCursor, MemoryFilter, TagExpr};
+pub use hnsw::HnswIndex;
+pub use in_memory::InMemoryStore;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+pub use promotion::PromotionConfig;
+pub use scope::MemoryScope;
+pub use scoring::ScoringConfig;
+pub use shared::SharedMemory;
+pub use spill::{SpillConfig, SpillDir};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+#[cfg(feature = "full-text")]
+pub use text::TextIndex;
+pub use wal::{MemoryWal, WalRecord, WalWriter, WriterStatus};
+
+/// Metadata key holding an entry's importance score
+pub const IMPORTANCE_KEY: &str = "importance";
+
+/// Metadata key marking an entry as pinned, exempting it from eviction
+pub const PINNED_KEY: &str = "pinned";
+
+/// Kind of knowledge a memory entry holds
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryKind {
+    /// Timestamped observation of something that happened
+    #[default]
+    Episodic,
+
+    /// Distilled fact that holds independently of when it was learned
+    Semantic,
+}
+
+impl MemoryKind {
+    /// Name of the kind as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Episodic => "episodic",
+            Self::Semantic => "semantic",
+        }
+    }
+}
+
+/// Which entries are removed first when memory is over capacity
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// Oldest inserted entries first
+    #[default]
+    Fifo,
+
+    /// Least recently read entries first
+    Lru,
+
+    /// Lowest importance first, oldest first among equals
+    Importance,
+}
+
+/// Primary storage for memory entries
+///
+/// The agent reads and writes memory through this trait; the default is
+/// [`InMemoryStore`].
+#[async_trait]
+pub trait MemoryStore: Send + Sync {
+    /// Insert an entry, replacing any entry with the same ID
+    async fn put(&self, entry: MemoryEntry) -> Result<()>;
+
+    /// Insert an entry at the next version if the entry stored under its ID
+    /// is still at `expected_version`, where 0 means none is stored
+    ///
+    /// The version check and the write are atomic, so of several writers
+    /// expecting the same version only one succeeds. Returns the entry as
+    /// stored, or an error naming the version found.
+    async fn put_versioned(&self, entry: MemoryEntry, expected_version: u64)
+        -> Result<MemoryEntry>;
+
+    /// Remove an entry if it is still at `expected_version`, atomically
+    async fn remove_versioned(&self, id: Uuid, expected_version: u64) -> Result<()>;
+
+    /// Get an entry by ID
+    async fn get(&self, id: Uuid) -> Result<Option<MemoryEntry>>;
+
+    /// Find up to `k` entries matching the filter whose text matches the query
+    async fn search(
+        &self,
+        query: &str,
+        filter: &MemoryFilter,
+        k: usize,
+    ) -> Result<Vec<MemoryEntry>>;
+
+    /// List all entries, oldest first
+    async fn list(&self) -> Result<Vec<MemoryEntry>>;
+
+    /// List the entries matching a filter, oldest first
+    async fn query(&self, filter: &MemoryFilter) -> Result<Vec<MemoryEntry>> {
+        let mut entries = self.list().await?;
+        entries.retain(|e| filter.matches(e));
+        Ok(entries)
+    }
+
+    /// List up to `limit` entries matching a filter in chronological order,
+    /// starting after a cursor
+    async fn chronological(
+        &self,
+        filter: &MemoryFilter,
+        after: Option<&MemoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<MemoryEntry>> {
+        let mut entries = self.query(filter).await?;
+        entries.sort_by_key(MemoryCursor::of);
+        Ok(entries
+            .into_iter()
+            .filter(|e| match after {
+                Some(after) => MemoryCursor::of(e) > *after,
+                None => true,
+            })
+            .take(limit)
+            .collect())
+    }
+
+    /// Remove an entry, returning whether it existed
+    async fn remove(&self, id: Uuid) -> Result<bool>;
+
+    /// Evict entries until at most `capacity` remain, returning the evicted entries
+    ///
+    /// Which entries go first is up to the store's eviction policy. Pinned
+    /// entries are never evicted, so more than `capacity` may remain.
+    async fn evict(&self, capacity: usize) -> Result<Vec<MemoryEntry>>;
+
+    /// Number of entries
+    async fn len(&self) -> Result<usize>;
+
+    /// Whether the store has no entries
+    async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Remove all entries
+    async fn clear(&self) -> Result<()>;
+}
+
+/// Durable storage for task states
+#[async_trait]
+pub trait TaskStore: Send + Sync {
+    /// Insert or replace a task state
+    async fn put_task(&self, task: &TaskState) -> Result<()>;
+
+    /// Get a task state by ID
+    async fn get_task(&self, id: Uuid) -> Result<Option<TaskState>>;
+
+    /// Remove a task state, returning whether it existed
+    async fn remove_task(&self, id: Uuid) -> Result<bool>;
+
+    /// Load all task states
+    async fn load_tasks(&self) -> Result<Vec<TaskState>>;
+}
+
+/// Durable storage for memory entries and task states
+#[async_trait]
+pub trait StateStore: TaskStore {
+    /// Insert or replace a memory entry
+    async fn put_memory(&self, entry: &MemoryEntry) -> Result<()>;
+
+    /// Get a memory entry by ID
+    async fn get_memory(&self, id: Uuid) -> Result<Option<MemoryEntry>>;
+
+    /// Remove a memory entry, returning whether it existed
+    async fn remove_memory(&self, id: Uuid) -> Result<bool>;
+
+    /// Load all memory entries, oldest first
+    async fn load_memory(&self) -> Result<Vec<MemoryEntry>>;
+
+    /// Remove all memory entries
+    async fn clear_memory(&self) -> Result<()>;
+
+    /// Insert or replace a key-value state entry spilled out of process memory
+    async fn put_state(&self, key: &str, value: &Value) -> Result<()>;
+
+    /// Get a spilled key-value state entry
+    async fn get_state(&self, key: &str) -> Result<Option<Value>>;
+}
@@ -0,0 +1,392 @@
+//! Postgres persistence for memory entries and task states
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::types::Json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use super::{StateStore, TaskStore};
+use crate::state::MemoryEntry;
+use crate::{TaskState, TaskStatus};
+
+/// Advisory lock key serializing migrations across processes
+const MIGRATION_LOCK: i64 = 0x4154_4c41_535f_4d31;
+
+/// Columns selected for memory entries
+const MEMORY_COLUMNS: &str =
+    "id, timestamp, data, metadata, embedding, expires_at, scope, tags, kind, version";
+
+/// Schema migrations, each a list of statements applied in one transaction
+const MIGRATIONS: &[&[&str]] = &[
+    &[
+        r#"CREATE TABLE atlas_memory (
+        id UUID PRIMARY KEY,
+        timestamp TIMESTAMPTZ NOT NULL,
+        data JSONB NOT NULL,
+        metadata JSONB NOT NULL,
+        embedding REAL[]
+    )"#,
+        "CREATE INDEX atlas_memory_timestamp ON atlas_memory (timestamp)",
+        r#"CREATE TABLE atlas_tasks (
+        id UUID PRIMARY KEY,
+        status TEXT NOT NULL,
+        result JSONB,
+        error TEXT,
+        steps JSONB NOT NULL,
+        usage JSONB NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )"#,
+        "CREATE INDEX atlas_tasks_status ON atlas_tasks (status)",
+    ],
+    &["ALTER TABLE atlas_memory ADD COLUMN expires_at TIMESTAMPTZ"],
+    &[
+        "ALTER TABLE atlas_memory ADD COLUMN scope TEXT NOT NULL DEFAULT 'global'",
+        "CREATE INDEX atlas_memory_scope ON atlas_memory (scope)",
+    ],
+    &[
+        "ALTER TABLE atlas_memory ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}'",
+        "CREATE INDEX atlas_memory_tags ON atlas_memory USING GIN (tags)",
+    ],
+    &["ALTER TABLE atlas_memory ADD COLUMN kind TEXT NOT NULL DEFAULT 'episodic'"],
+    &[r#"CREATE TABLE atlas_state (
+        key TEXT PRIMARY KEY,
+        value JSONB NOT NULL
+    )"#],
+    &["ALTER TABLE atlas_memory ADD COLUMN version BIGINT NOT NULL DEFAULT 0"],
+];
+
+/// Postgres store for agent memory and tasks
+///
+/// Tasks are stored as rows with queryable status columns and memory
+/// entries as JSONB documents, for deployments that share durable state
+/// between processes.
+#[derive(Clone, Debug)]
+pub struct PostgresStore {
+    /// Connection pool
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    /// Connect to a database and apply pending migrations
+    pub async fn connect(url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new().max_connections(5).connect(url).await?;
+        Self::from_pool(pool).await
+    }
+
+    /// Use an existing pool and apply pending migrations
+    pub async fn from_pool(pool: PgPool) -> Result<Self> {
+        let store = Self { pool };
+        store.migrate().await?;
+        Ok(store)
+    }
+
+    /// Get the connection pool
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Get the tasks with a given status
+    pub async fn tasks_with_status(&self, status: TaskStatus) -> Result<Vec<TaskState>> {
+        let rows = sqlx::query(
+            "SELECT id, status, result, error, steps, usage FROM atlas_tasks \
+             WHERE status = $1 ORDER BY updated_at",
+        )
+        .bind(status_name(status)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(task_from_row).collect()
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS atlas_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut *tx)
+            .await?;
+
+        let version: i32 =
+            sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM atlas_migrations")
+                .fetch_one(&mut *tx)
+                .await?;
+        for (i, statements) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            for statement in *statements {
+                sqlx::query(statement).execute(&mut *tx).await?;
+            }
+            sqlx::query("INSERT INTO atlas_migrations (version) VALUES ($1)")
+                .bind(i as i32 + 1)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TaskStore for PostgresStore {
+    async fn put_task(&self, task: &TaskState) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO atlas_tasks (id, status, result, error, steps, usage, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, now())
+             ON CONFLICT (id) DO UPDATE SET
+                status = EXCLUDED.status,
+                result = EXCLUDED.result,
+                error = EXCLUDED.error,
+                steps = EXCLUDED.steps,
+                usage = EXCLUDED.usage,
+                updated_at = now()",
+        )
+        .bind(task.id)
+        .bind(status_name(task.status)?)
+        .bind(task.result.as_ref().map(Json))
+        .bind(&task.error)
+        .bind(Json(&task.steps))
+        .bind(Json(&task.usage))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_task(&self, id: Uuid) -> Result<Option<TaskState>> {
+        let row = sqlx::query(
+            "SELECT id, status, result, error, steps, usage FROM atlas_tasks WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(task_from_row).transpose()
+    }
+
+    async fn remove_task(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM atlas_tasks WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn load_tasks(&self) -> Result<Vec<TaskState>> {
+        let rows = sqlx::query(
+            "SELECT id, status, result, error, steps, usage FROM atlas_tasks ORDER BY updated_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(task_from_row).collect()
+    }
+}
+
+#[async_trait]
+impl StateStore for PostgresStore {
+    async fn put_memory(&self, entry: &MemoryEntry) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO atlas_memory
+                (id, timestamp, data, metadata, embedding, expires_at, scope, tags, kind, version)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             ON CONFLICT (id) DO UPDATE SET
+                timestamp = EXCLUDED.timestamp,
+                data = EXCLUDED.data,
+                metadata = EXCLUDED.metadata,
+                embedding = EXCLUDED.embedding,
+                expires_at = EXCLUDED.expires_at,
+                scope = EXCLUDED.scope,
+                tags = EXCLUDED.tags,
+                kind = EXCLUDED.kind,
+                version = EXCLUDED.version",
+        )
+        .bind(entry.id)
+        .bind(entry.timestamp)
+        .bind(Json(&entry.data))
+        .bind(Json(&entry.metadata))
+        .bind(&entry.embedding)
+        .bind(entry.expires_at)
+        .bind(entry.scope.to_string())
+        .bind(entry.tags.iter().collect::<Vec<_>>())
+        .bind(entry.kind.as_str())
+        .bind(entry.version as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_memory(&self, id: Uuid) -> Result<Option<MemoryEntry>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM atlas_memory WHERE id = $1",
+            MEMORY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(memory_from_row).transpose()
+    }
+
+    async fn remove_memory(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM atlas_memory WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn load_memory(&self) -> Result<Vec<MemoryEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM atlas_memory ORDER BY timestamp, id",
+            MEMORY_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(memory_from_row).collect()
+    }
+
+    async fn clear_memory(&self) -> Result<()> {
+        sqlx::query("DELETE FROM atlas_memory")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn put_state(&self, key: &str, value: &Value) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO atlas_state (key, value) VALUES ($1, $2)
+             ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+        )
+        .bind(key)
+        .bind(Json(value))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_state(&self, key: &str) -> Result<Option<Value>> {
+        let value: Option<Json<Value>> =
+            sqlx::query_scalar("SELECT value FROM atlas_state WHERE key = $1")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(value.map(|Json(value)| value))
+    }
+}
+
+/// Name of a status as stored in the `status` column
+fn status_name(status: TaskStatus) -> Result<String> {
+    Ok(serde_json::from_value(serde_json::to_value(status)?)?)
+}
+
+fn task_from_row(row: &PgRow) -> Result<TaskState> {
+    let status: String = row.try_get("status")?;
+    Ok(TaskState {
+        id: row.try_get("id")?,
+        status: serde_json::from_value(serde_json::Value::String(status))?,
+        result: row
+            .try_get::<Option<Json<_>>, _>("result")?
+            .map(|Json(result)| result),
+        error: row.try_get("error")?,
+        steps: row.try_get::<Json<_>, _>("steps")?.0,
+        usage: row.try_get::<Json<_>, _>("usage")?.0,
+    })
+}
+
+fn memory_from_row(row: &PgRow) -> Result<MemoryEntry> {
+    Ok(MemoryEntry {
+        id: row.try_get("id")?,
+        timestamp: row.try_get("timestamp")?,
+        data: row.try_get::<Json<_>, _>("data")?.0,
+        metadata: row.try_get::<Json<_>, _>("metadata")?.0,
+        embedding: row.try_get("embedding")?,
+        expires_at: row.try_get("expires_at")?,
+        scope: row.try_get::<String, _>("scope")?.parse()?,
+        tags: row.try_get::<Vec<String>, _>("tags")?.into_iter().collect(),
+        kind: serde_json::from_value(serde_json::Value::String(row.try_get("kind")?))?,
+        version: row.try_get::<i64, _>("version")? as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MemoryKind, MemoryScope};
+    use atlas_core::Metadata;
+    use serde_json::json;
+
+    /// Connect to the database named by `ATLAS_TEST_POSTGRES_URL`, if set
+    async fn store() -> Option<PostgresStore> {
+        let url = std::env::var("ATLAS_TEST_POSTGRES_URL").ok()?;
+        Some(PostgresStore::connect(&url).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_memory_round_trip() {
+        let Some(store) = store().await else {
+            return;
+        };
+        // Whole seconds, which the column stores exactly
+        let timestamp = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut entry = MemoryEntry::new_at(json!({"text": "fact"}), Metadata::new(), timestamp);
+        entry.embedding = Some(vec![0.25, -1.0]);
+        entry.expires_at = Some(timestamp + chrono::Duration::hours(1));
+        entry.scope = MemoryScope::Shared("team".to_string());
+        entry.tags = ["billing".to_string()].into();
+        entry.kind = MemoryKind::Semantic;
+        entry.version = 3;
+        store.put_memory(&entry).await.unwrap();
+
+        // Connecting again runs migrations as a no-op and sees the entry
+        let store = self::store().await.unwrap();
+        let loaded = store.get_memory(entry.id).await.unwrap().unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&entry).unwrap()
+        );
+        assert!(store.remove_memory(entry.id).await.unwrap());
+        assert!(!store.remove_memory(entry.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_tasks_and_state() {
+        let Some(store) = store().await else {
+            return;
+        };
+        let mut task = TaskState {
+            id: Uuid::new_v4(),
+            status: TaskStatus::Running,
+            result: None,
+            error: None,
+            steps: Vec::new(),
+            usage: Default::default(),
+        };
+        store.put_task(&task).await.unwrap();
+        let mut result = Metadata::new();
+        result.insert("answer", 42);
+        task.status = TaskStatus::Completed;
+        task.result = Some(result);
+        store.put_task(&task).await.unwrap();
+
+        let loaded = store.get_task(task.id).await.unwrap().unwrap();
+        assert_eq!(loaded.status, TaskStatus::Completed);
+        assert_eq!(loaded.result.unwrap().get::<i64>("answer"), Some(42));
+        let completed = store
+            .tasks_with_status(TaskStatus::Completed)
+            .await
+            .unwrap();
+        assert!(completed.iter().any(|t| t.id == task.id));
+        assert!(store.remove_task(task.id).await.unwrap());
+        assert!(store.get_task(task.id).await.unwrap().is_none());
+
+        let key = format!("draft-{}", Uuid::new_v4());
+        store.put_state(&key, &json!({"v": 1})).await.unwrap();
+        store.put_state(&key, &json!({"v": 2})).await.unwrap();
+        assert_eq!(store.get_state(&key).await.unwrap(), Some(json!({"v": 2})));
+        assert_eq!(store.get_state("missing").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Promotion of repeated episodic memories to semantic facts
+
+use atlas_core::Metadata;
+use serde::{Deserialize, Serialize};
+
+use super::{MemoryKind, IMPORTANCE_KEY};
+use crate::state::{MemoryEntry, TAGS_KEY};
+
+/// Metadata key holding the normalized text a fact was promoted from
+pub const PATTERN_KEY: &str = "pattern";
+
+/// Metadata key counting the episodes a fact was observed in
+pub const OCCURRENCES_KEY: &str = "occurrences";
+
+/// Metadata key listing the IDs of the episodes that were promoted
+pub const PROMOTED_FROM_KEY: &str = "promoted_from";
+
+/// Promotion configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PromotionConfig {
+    /// Number of matching episodes in a scope that makes a fact
+    pub min_occurrences: usize,
+}
+
+impl Default for PromotionConfig {
+    fn default() -> Self {
+        Self { min_occurrences: 3 }
+    }
+}
+
+/// Normalized text identifying repetitions of the same observation
+///
+/// Case and whitespace differences are ignored.
+pub fn pattern_of(entry: &MemoryEntry) -> String {
+    entry
+        .text()
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Distill repeated episodes, oldest first, into a semantic fact
+///
+/// The fact takes the data and scope of the latest episode, the union of
+/// their tags and the highest importance.
+pub fn promote(episodes: &[MemoryEntry]) -> Option<MemoryEntry> {
+    let latest = episodes.last()?;
+
+    let mut metadata = Metadata::new();
+    metadata.insert(PATTERN_KEY, pattern_of(latest));
+    metadata.insert(OCCURRENCES_KEY, episodes.len());
+    metadata.insert(
+        PROMOTED_FROM_KEY,
+        episodes.iter().map(|e| e.id).collect::<Vec<_>>(),
+    );
+    let tags: Vec<&String> = episodes.iter().flat_map(|e| &e.tags).collect();
+    if !tags.is_empty() {
+        metadata.insert(TAGS_KEY, tags);
+    }
+    let importance = episodes
+        .iter()
+        .map(|e| e.importance())
+        .fold(f64::NEG_INFINITY, f64::max);
+    if importance > 0.0 {
+        metadata.insert(IMPORTANCE_KEY, importance);
+    }
+
+    let mut fact = MemoryEntry::new(latest.data.clone(), metadata);
+    fact.timestamp = latest.timestamp;
+    fact.scope = latest.scope.clone();
+    fact.kind = MemoryKind::Semantic;
+    Some(fact)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_promote_episodes() {
+        let episodes: Vec<MemoryEntry> =
+            ["User prefers  metric units", "user prefers metric UNITS"]
+                .into_iter()
+                .map(|text| {
+                    let mut metadata = Metadata::new();
+                    metadata.insert(TAGS_KEY, ["preference"]);
+                    MemoryEntry::new(json!(text), metadata)
+                })
+                .collect();
+        assert_eq!(pattern_of(&episodes[0]), pattern_of(&episodes[1]));
+
+        let fact = promote(&episodes).unwrap();
+        assert_eq!(fact.kind, MemoryKind::Semantic);
+        assert_eq!(fact.data, episodes[1].data);
+        assert_eq!(fact.metadata.get::<usize>(OCCURRENCES_KEY), Some(2));
+        assert!(fact.tags.contains("preference"));
+        assert!(promote(&[]).is_none());
+    }
+}
@@ -0,0 +1,96 @@
+//! Memory namespaces
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::Error;
+
+/// Namespace a memory entry belongs to
+///
+/// Serialized as `global`, `task:<id>`, `conversation:<id>` or
+/// `shared:<namespace>`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum MemoryScope {
+    /// Shared by everything the agent does
+    #[default]
+    Global,
+
+    /// Private to a task and removed when the task finishes
+    Task(Uuid),
+
+    /// Private to a conversation
+    Conversation(String),
+
+    /// A namespace of memory shared between agents
+    Shared(String),
+}
+
+impl fmt::Display for MemoryScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Global => f.write_str("global"),
+            Self::Task(id) => write!(f, "task:{}", id),
+            Self::Conversation(id) => write!(f, "conversation:{}", id),
+            Self::Shared(namespace) => write!(f, "shared:{}", namespace),
+        }
+    }
+}
+
+impl FromStr for MemoryScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "global" => Ok(Self::Global),
+            Some(("task", id)) => id
+                .parse()
+                .map(Self::Task)
+                .map_err(|e| Error::MemoryError(format!("Invalid task scope {}: {}", s, e))),
+            Some(("conversation", id)) if !id.is_empty() => Ok(Self::Conversation(id.to_string())),
+            Some(("shared", namespace)) if !namespace.is_empty() => {
+                Ok(Self::Shared(namespace.to_string()))
+            }
+            _ => Err(Error::MemoryError(format!("Invalid memory scope: {}", s))),
+        }
+    }
+}
+
+impl From<MemoryScope> for String {
+    fn from(scope: MemoryScope) -> Self {
+        scope.to_string()
+    }
+}
+
+impl TryFrom<String> for MemoryScope {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_round_trip() {
+        let id = Uuid::new_v4();
+        for scope in [
+            MemoryScope::Global,
+            MemoryScope::Task(id),
+            MemoryScope::Conversation("chat:42".to_string()),
+            MemoryScope::Shared("research".to_string()),
+        ] {
+            let json = serde_json::to_string(&scope).unwrap();
+            assert_eq!(serde_json::from_str::<MemoryScope>(&json).unwrap(), scope);
+        }
+        assert_eq!(MemoryScope::Task(id).to_string(), format!("task:{}", id));
+        assert!("task:nope".parse::<MemoryScope>().is_err());
+        assert!("team".parse::<MemoryScope>().is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Composite relevance scoring for memory retrieval
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::state::MemoryEntry;
+
+/// Weights combining similarity, recency and importance into one score
+///
+/// Each component is in `[0, 1]`: similarity as reported by the search
+/// index, recency as `0.5^(age / half_life)`, and importance as the entry's
+/// `importance` metadata clamped to that range.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    /// Weight of similarity to the query
+    pub similarity: f32,
+
+    /// Weight of recency
+    pub recency: f32,
+
+    /// Weight of importance
+    pub importance: f32,
+
+    /// Age at which the recency score halves, in seconds
+    pub half_life_secs: f64,
+
+    /// Candidates fetched by similarity per requested result before re-ranking
+    pub candidates: usize,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            similarity: 1.0,
+            recency: 1.0,
+            importance: 1.0,
+            half_life_secs: 24.0 * 60.0 * 60.0,
+            candidates: 4,
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// Score an entry with the given similarity to the query
+    pub fn score(&self, entry: &MemoryEntry, similarity: f32, now: DateTime<Utc>) -> f32 {
+        let age = (now - entry.timestamp).num_milliseconds().max(0) as f64 / 1000.0;
+        let recency = 0.5_f64.powf(age / self.half_life_secs.max(f64::EPSILON));
+        let importance = entry.importance().clamp(0.0, 1.0);
+        self.similarity * similarity.clamp(0.0, 1.0)
+            + self.recency * recency as f32
+            + self.importance * importance as f32
+    }
+
+    /// Order entries by score, best first, keeping the top `k`
+    pub fn rank(
+        &self,
+        hits: Vec<(MemoryEntry, f32)>,
+        now: DateTime<Utc>,
+        k: usize,
+    ) -> Vec<MemoryEntry> {
+        let mut scored: Vec<(f32, MemoryEntry)> = hits
+            .into_iter()
+            .map(|(entry, similarity)| (self.score(&entry, similarity, now), entry))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(k).map(|(_, entry)| entry).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::IMPORTANCE_KEY;
+    use atlas_core::{Clock, Metadata, MockClock};
+    use serde_json::json;
+
+    #[test]
+    fn test_rank_combines_components() {
+        let now: DateTime<Utc> = MockClock::default().now().into();
+        let entry = |age_hours: i64, importance: f64| {
+            let mut metadata = Metadata::new();
+            metadata.insert(IMPORTANCE_KEY, importance);
+            let mut entry = MemoryEntry::new(json!("entry"), metadata);
+            entry.timestamp = now - chrono::Duration::hours(age_hours);
+            entry
+        };
+        let similar_but_stale = entry(72, 0.0);
+        let recent = entry(0, 0.0);
+        let important = entry(72, 1.0);
+
+        let config = ScoringConfig::default();
+        assert!((config.score(&recent, 0.5, now) - 1.5).abs() < 1e-3);
+        let ranked = config.rank(
+            vec![
+                (similar_but_stale.clone(), 0.9),
+                (recent.clone(), 0.5),
+                (important.clone(), 0.5),
+            ],
+            now,
+            2,
+        );
+        assert_eq!(
+            ranked.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![important.id, recent.id]
+        );
+
+        let similarity_only = ScoringConfig {
+            recency: 0.0,
+            importance: 0.0,
+            ..Default::default()
+        };
+        let ranked = similarity_only.rank(
+            vec![(recent, 0.5), (similar_but_stale.clone(), 0.9)],
+            now,
+            1,
+        );
+        assert_eq!(ranked[0].id, similar_but_stale.id);
+    }
+}
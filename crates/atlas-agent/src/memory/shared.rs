@@ -0,0 +1,207 @@
+//! Memory shared between agents
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use atlas_core::{Clock, Metadata, SystemClock};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{InMemoryStore, MemoryFilter, MemoryScope, MemoryStore};
+use crate::error::Error;
+use crate::state::MemoryEntry;
+
+/// Namespace used by a new handle
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Get the version of a shared memory entry, starting at 1
+pub fn version_of(entry: &MemoryEntry) -> u64 {
+    entry.version
+}
+
+/// Handle to a knowledge base shared by a team of agents
+///
+/// Clones of a handle share the same store, so every agent holding one sees
+/// the others' writes. Entries live in named namespaces, stored under
+/// [`MemoryScope::Shared`]; [`SharedMemory::namespace`] gives a handle onto
+/// another namespace of the same store. Writes use optimistic concurrency:
+/// each entry carries a version in [`MemoryEntry::version`], checked by the
+/// store as it writes, and updating or removing an entry fails if it changed
+/// since the caller read it, whichever handle changed it.
+#[derive(Clone)]
+pub struct SharedMemory {
+    /// Backing store
+    store: Arc<dyn MemoryStore>,
+
+    /// Namespace this handle reads and writes
+    namespace: String,
+
+    /// Source of entry timestamps
+    clock: Arc<dyn Clock>,
+}
+
+impl std::fmt::Debug for SharedMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedMemory")
+            .field("namespace", &self.namespace)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for SharedMemory {
+    fn default() -> Self {
+        Self::new(InMemoryStore::new())
+    }
+}
+
+impl SharedMemory {
+    /// Create shared memory backed by a store
+    pub fn new<S: MemoryStore + 'static>(store: S) -> Self {
+        Self::from_store(Arc::new(store))
+    }
+
+    /// Create shared memory backed by a shared store
+    pub fn from_store(store: Arc<dyn MemoryStore>) -> Self {
+        Self {
+            store,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Take entry timestamps from a clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Get a handle onto another namespace of the same store
+    pub fn namespace<S: Into<String>>(&self, namespace: S) -> Self {
+        Self {
+            namespace: namespace.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Get the namespace this handle reads and writes
+    pub fn namespace_name(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Scope of the entries in this handle's namespace
+    fn scope(&self) -> MemoryScope {
+        MemoryScope::Shared(self.namespace.clone())
+    }
+
+    /// Add an entry at version 1
+    pub async fn insert(&self, data: Value, metadata: Metadata) -> Result<MemoryEntry> {
+        let mut entry = MemoryEntry::new_at(data, metadata, self.clock.now().into());
+        entry.scope = self.scope();
+        self.store.put_versioned(entry, 0).await
+    }
+
+    /// Get an entry in this namespace by ID
+    pub async fn get(&self, id: Uuid) -> Result<Option<MemoryEntry>> {
+        let entry = self.store.get(id).await?;
+        Ok(entry.filter(|e| e.scope == self.scope()))
+    }
+
+    /// Replace an entry's data if it is still at `expected_version`
+    ///
+    /// Returns the entry at its new version, or an error if another agent
+    /// changed or removed it first.
+    pub async fn update(
+        &self,
+        id: Uuid,
+        data: Value,
+        expected_version: u64,
+    ) -> Result<MemoryEntry> {
+        let mut entry = self.checked(id).await?;
+        entry.data = data;
+        entry.timestamp = self.clock.now().into();
+        self.store.put_versioned(entry, expected_version).await
+    }
+
+    /// Remove an entry if it is still at `expected_version`
+    pub async fn remove(&self, id: Uuid, expected_version: u64) -> Result<()> {
+        self.checked(id).await?;
+        self.store.remove_versioned(id, expected_version).await
+    }
+
+    /// Get an entry, failing unless it is in this namespace
+    async fn checked(&self, id: Uuid) -> Result<MemoryEntry> {
+        self.get(id).await?.ok_or_else(|| {
+            Error::MemoryError(format!(
+                "Shared memory entry {} not found in namespace {}",
+                id, self.namespace
+            ))
+            .into()
+        })
+    }
+
+    /// Find up to `k` entries in this namespace whose text matches the query
+    pub async fn search(&self, query: &str, k: usize) -> Result<Vec<MemoryEntry>> {
+        let filter = MemoryFilter::new().scope(self.scope());
+        self.store.search(query, &filter, k).await
+    }
+
+    /// List the entries in this namespace, oldest first
+    pub async fn list(&self) -> Result<Vec<MemoryEntry>> {
+        let filter = MemoryFilter::new().scope(self.scope());
+        self.store.query(&filter).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_namespaces_and_versions() {
+        let team = SharedMemory::default();
+        let planner = team.namespace("research");
+        let writer = team.namespace("research");
+
+        let entry = planner
+            .insert(json!("api is rate limited"), Metadata::new())
+            .await
+            .unwrap();
+        assert_eq!(version_of(&entry), 1);
+        assert_eq!(writer.list().await.unwrap().len(), 1);
+        assert!(team.get(entry.id).await.unwrap().is_none());
+        assert!(team.search("rate", 5).await.unwrap().is_empty());
+
+        // Both agents read version 1; the second write loses
+        let updated = writer
+            .update(entry.id, json!("api allows 10 rps"), 1)
+            .await
+            .unwrap();
+        assert_eq!(version_of(&updated), 2);
+        let conflict = planner.update(entry.id, json!("stale"), 1).await;
+        assert!(conflict
+            .unwrap_err()
+            .to_string()
+            .contains("Version conflict"));
+        assert!(planner.remove(entry.id, 1).await.is_err());
+
+        planner.remove(entry.id, 2).await.unwrap();
+        assert!(writer.get(entry.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_separate_handles() {
+        let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::new());
+        let first = SharedMemory::from_store(store.clone());
+        let second = SharedMemory::from_store(store);
+
+        // User metadata under "version" is kept apart from the entry's version
+        let mut metadata = Metadata::new();
+        metadata.insert("version", "v2 api");
+        let entry = first.insert(json!("notes"), metadata).await.unwrap();
+        let updated = second.update(entry.id, json!("more"), 1).await.unwrap();
+        assert!(first.update(entry.id, json!("stale"), 1).await.is_err());
+        assert_eq!(version_of(&updated), 2);
+        assert_eq!(updated.metadata.get::<String>("version").unwrap(), "v2 api");
+    }
+}
@@ -0,0 +1,220 @@
+//! Spilling of cold key-value state out of process memory
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+/// Limits on the key-value state kept in process
+///
+/// Once a limit is exceeded, the least recently accessed keys are written
+/// to the persistence backend and dropped from memory until they are read
+/// again.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SpillConfig {
+    /// Maximum number of keys kept in process
+    #[serde(default)]
+    pub max_keys: Option<usize>,
+
+    /// Maximum serialized size of the values kept in process, in bytes
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+/// Spilled state value as stored on disk
+#[derive(Serialize, Deserialize)]
+struct SpilledValue {
+    /// State key
+    key: String,
+
+    /// State value
+    value: Value,
+}
+
+/// Directory holding spilled state values for file persistence, one JSON
+/// file per key
+#[derive(Clone, Debug)]
+pub struct SpillDir {
+    /// Directory path
+    path: PathBuf,
+}
+
+impl SpillDir {
+    /// Use a directory, creating it on first write
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Write a value, replacing any earlier value for the key
+    pub async fn put(&self, key: &str, value: &Value) -> Result<()> {
+        tokio::fs::create_dir_all(&self.path).await?;
+        let path = self.file(key);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        let json = serde_json::to_vec(&SpilledValue {
+            key: key.to_string(),
+            value: value.clone(),
+        })?;
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        file.write_all(&json).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    /// Read the value for a key
+    pub async fn get(&self, key: &str) -> Result<Option<Value>> {
+        let json = match tokio::fs::read(self.file(key)).await {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let spilled: SpilledValue = serde_json::from_slice(&json)?;
+        // File names are hashes, so check for a colliding key
+        Ok((spilled.key == key).then_some(spilled.value))
+    }
+
+    /// File holding a key's value
+    fn file(&self, key: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.path.join(format!("{:016x}.json", hasher.finish()))
+    }
+}
+
+/// Access recency and size of the state keys held in process, and which
+/// keys are spilled
+#[derive(Clone, Debug, Default)]
+pub struct SpillTracker {
+    /// Monotonic access counter
+    tick: u64,
+
+    /// Last access of each key held in process
+    accessed: HashMap<String, u64>,
+
+    /// Serialized size of each value held in process
+    sizes: HashMap<String, usize>,
+
+    /// Keys whose current value is only in the backend
+    spilled: HashSet<String>,
+}
+
+impl SpillTracker {
+    /// Record an access to a key held in process
+    pub fn touch(&mut self, key: &str, value: &Value) {
+        self.tick += 1;
+        self.accessed.insert(key.to_string(), self.tick);
+        self.sizes.insert(key.to_string(), value_size(value));
+        self.spilled.remove(key);
+    }
+
+    /// Whether a key's value is spilled
+    pub fn is_spilled(&self, key: &str) -> bool {
+        self.spilled.contains(key)
+    }
+
+    /// Keys whose values are spilled
+    pub fn spilled(&self) -> impl Iterator<Item = &String> {
+        self.spilled.iter()
+    }
+
+    /// Choose the coldest keys to spill so the rest fit the limits, and
+    /// mark them spilled
+    ///
+    /// Keys added without an access are the coldest.
+    pub fn select(&mut self, memory: &HashMap<String, Value>, config: &SpillConfig) -> Vec<String> {
+        self.accessed.retain(|key, _| memory.contains_key(key));
+        self.sizes.retain(|key, _| memory.contains_key(key));
+        let mut bytes = 0;
+        for (key, value) in memory {
+            bytes += *self
+                .sizes
+                .entry(key.clone())
+                .or_insert_with(|| value_size(value));
+        }
+
+        let mut keys: Vec<&String> = memory.keys().collect();
+        keys.sort_by_key(|key| self.accessed.get(*key).copied().unwrap_or(0));
+        let mut count = keys.len();
+        let mut selected = Vec::new();
+        for key in keys {
+            let over_keys = config.max_keys.is_some_and(|max| count > max);
+            let over_bytes = config.max_bytes.is_some_and(|max| bytes > max);
+            if !over_keys && !over_bytes {
+                break;
+            }
+            count -= 1;
+            bytes -= self.sizes.get(key).copied().unwrap_or(0);
+            selected.push(key.clone());
+        }
+
+        for key in &selected {
+            self.accessed.remove(key);
+            self.sizes.remove(key);
+            self.spilled.insert(key.clone());
+        }
+        selected
+    }
+}
+
+/// Serialized size of a value in bytes
+fn value_size(value: &Value) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_select_coldest_keys() {
+        let memory: HashMap<String, Value> = [
+            ("a", json!(1)),
+            ("b", json!("x".repeat(100))),
+            ("c", json!(3)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        let mut tracker = SpillTracker::default();
+        for key in ["c", "a", "b"] {
+            tracker.touch(key, &memory[key]);
+        }
+
+        let config = SpillConfig {
+            max_keys: Some(2),
+            max_bytes: None,
+        };
+        assert_eq!(tracker.select(&memory, &config), vec!["c".to_string()]);
+        assert!(tracker.is_spilled("c"));
+
+        let mut memory = memory;
+        memory.remove("c");
+        let config = SpillConfig {
+            max_keys: None,
+            max_bytes: Some(50),
+        };
+        assert_eq!(
+            tracker.select(&memory, &config),
+            vec!["a".to_string(), "b".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spill_dir_round_trip() {
+        let dir = std::env::temp_dir().join(format!("atlas-spill-{}", uuid::Uuid::new_v4()));
+        let spill = SpillDir::new(&dir);
+        assert_eq!(spill.get("draft").await.unwrap(), None);
+        spill.put("draft", &json!({"v": 1})).await.unwrap();
+        spill.put("draft", &json!({"v": 2})).await.unwrap();
+        assert_eq!(spill.get("draft").await.unwrap(), Some(json!({"v": 2})));
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+//! SQLite persistence for memory entries and task states
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use atlas_core::{Clock, SystemClock};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{StateStore, TaskStore};
+use crate::error::Error;
+use crate::state::MemoryEntry;
+use crate::TaskState;
+
+/// Schema migrations, applied in order and tracked with `PRAGMA user_version`
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE memory (
+        id TEXT PRIMARY KEY NOT NULL,
+        timestamp TEXT NOT NULL,
+        entry TEXT NOT NULL
+    );
+    CREATE INDEX memory_timestamp ON memory (timestamp);
+
+    CREATE TABLE tasks (
+        id TEXT PRIMARY KEY NOT NULL,
+        status TEXT NOT NULL,
+        task TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE INDEX tasks_status ON tasks (status);
+"#, r#"
+    CREATE TABLE state (
+        key TEXT PRIMARY KEY NOT NULL,
+        value TEXT NOT NULL
+    );
+"#];
+
+/// SQLite store for agent memory and tasks
+///
+/// Each entry and task is its own row, so writes touch only what changed.
+/// The database runs in WAL mode; blocking calls are moved off the async
+/// runtime.
+#[derive(Clone)]
+pub struct SqliteStore {
+    /// Database connection
+    conn: Arc<Mutex<Connection>>,
+
+    /// Source of task update times
+    clock: Arc<dyn Clock>,
+}
+
+impl SqliteStore {
+    /// Open or create a database file and apply pending migrations
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Open a private in-memory database
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self> {
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        migrate(&mut conn)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Take task update times from a clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Schema version of the database
+    pub async fn schema_version(&self) -> Result<usize> {
+        self.call(|conn| Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?))
+            .await
+    }
+
+    /// Run a closure against the connection on the blocking thread pool
+    async fn call<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| Error::StateError("SQLite connection poisoned".to_string()))?;
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl TaskStore for SqliteStore {
+    async fn put_task(&self, task: &TaskState) -> Result<()> {
+        let id = task.id.to_string();
+        let status = serde_json::to_value(task.status)?
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let json = serde_json::to_string(task)?;
+        let updated_at = chrono::DateTime::<chrono::Utc>::from(self.clock.now()).to_rfc3339();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO tasks (id, status, task, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, status, json, updated_at],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_task(&self, id: Uuid) -> Result<Option<TaskState>> {
+        let json: Option<String> = self
+            .call(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT task FROM tasks WHERE id = ?1",
+                        params![id.to_string()],
+                        |row| row.get(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+        Ok(json.map(|j| serde_json::from_str(&j)).transpose()?)
+    }
+
+    async fn remove_task(&self, id: Uuid) -> Result<bool> {
+        self.call(move |conn| {
+            Ok(conn.execute("DELETE FROM tasks WHERE id = ?1", params![id.to_string()])? > 0)
+        })
+        .await
+    }
+
+    async fn load_tasks(&self) -> Result<Vec<TaskState>> {
+        let rows: Vec<String> = self
+            .call(|conn| {
+                let mut stmt = conn.prepare("SELECT task FROM tasks ORDER BY updated_at")?;
+                let rows = stmt
+                    .query_map([], |row| row.get(0))?
+                    .collect::<rusqlite::Result<_>>()?;
+                Ok(rows)
+            })
+            .await?;
+        rows.iter()
+            .map(|json| Ok(serde_json::from_str(json)?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl StateStore for SqliteStore {
+    async fn put_memory(&self, entry: &MemoryEntry) -> Result<()> {
+        let id = entry.id.to_string();
+        let timestamp = entry.timestamp.to_rfc3339();
+        let json = serde_json::to_string(entry)?;
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO memory (id, timestamp, entry) VALUES (?1, ?2, ?3)",
+                params![id, timestamp, json],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_memory(&self, id: Uuid) -> Result<Option<MemoryEntry>> {
+        let json: Option<String> = self
+            .call(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT entry FROM memory WHERE id = ?1",
+                        params![id.to_string()],
+                        |row| row.get(0),
+                    )
+                    .optional()?)
+            })
+            .await?;
+        Ok(json.map(|j| serde_json::from_str(&j)).transpose()?)
+    }
+
+    async fn remove_memory(&self, id: Uuid) -> Result<bool> {
+        self.call(move |conn| {
+            Ok(conn.execute("DELETE FROM memory WHERE id = ?1", params![id.to_string()])? > 0)
+        })
+        .await
+    }
+
+    async fn load_memory(&self) -> Result<Vec<MemoryEntry>> {
+        let rows: Vec<String> = self
+            .call(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT entry FROM memory ORDER BY timestamp, rowid")?;
+                let rows = stmt
+                    .query_map([], |row| row.get(0))?
+                    .collect::<rusqlite::Result<_>>()?;
+                Ok(rows)
+            })
+            .await?;
+        rows.iter()
+            .map(|json| Ok(serde_json::from_str(json)?))
+            .collect()
+    }
+
+    async fn clear_memory(&self) -> Result<()> {
+        self.call(|conn| {
+            conn.execute("DELETE FROM memory", [])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn put_state(&self, key: &str, value: &Value) -> Result<()> {
+        let key = key.to_string();
+        let json = serde_json::to_string(value)?;
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO state (key, value) VALUES (?1, ?2)",
+                params![key, json],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_state(&self, key: &str) -> Result<Option<Value>> {
+        let key = key.to_string();
+        let json: Option<String> = self
+            .call(move |conn| {
+                Ok(conn
+                    .query_row("SELECT value FROM state WHERE key = ?1", params![key], |row| {
+                        row.get(0)
+                    })
+                    .optional()?)
+            })
+            .await?;
+        Ok(json.map(|j| serde_json::from_str(&j)).transpose()?)
+    }
+}
+
+/// Apply migrations newer than the database's schema version
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(Error::StateError(format!(
+            "Database schema version {} is newer than supported version {}",
+            version,
+            MIGRATIONS.len()
+        ))
+        .into());
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TaskStatus;
+    use atlas_core::Metadata;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_memory_and_tasks_round_trip() {
+        let path = std::env::temp_dir().join(format!("atlas-{}.db", Uuid::new_v4()));
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.schema_version().await.unwrap(), MIGRATIONS.len());
+
+        let first = MemoryEntry::new(json!("first"), Metadata::new());
+        let second = MemoryEntry::new(json!({"text": "second"}), Metadata::new());
+        store.put_memory(&first).await.unwrap();
+        store.put_memory(&second).await.unwrap();
+        assert!(store.remove_memory(first.id).await.unwrap());
+
+        let task = TaskState {
+            id: Uuid::new_v4(),
+            status: TaskStatus::Completed,
+            result: None,
+            error: None,
+            steps: Vec::new(),
+            usage: Default::default(),
+        };
+        store.put_task(&task).await.unwrap();
+        drop(store);
+
+        // Reopening runs migrations again as a no-op and sees the data
+        let store = SqliteStore::open(&path).unwrap();
+        let memory = store.load_memory().await.unwrap();
+        assert_eq!(memory.len(), 1);
+        assert_eq!(memory[0].data, json!({"text": "second"}));
+        assert!(store.get_memory(first.id).await.unwrap().is_none());
+
+        let tasks = store.load_tasks().await.unwrap();
+        assert_eq!(tasks[0].id, task.id);
+        assert_eq!(tasks[0].status, TaskStatus::Completed);
+
+        store.put_state("draft", &json!({"v": 1})).await.unwrap();
+        assert_eq!(store.get_state("draft").await.unwrap(), Some(json!({"v": 1})));
+        assert_eq!(store.get_state("missing").await.unwrap(), None);
+
+        drop(store);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
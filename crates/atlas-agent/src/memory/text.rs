@@ -0,0 +1,208 @@
+//! Full-text index over memory entries
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, STORED, STRING, TEXT};
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::state::MemoryEntry;
+
+/// Memory budget of the index writer in bytes
+const WRITER_MEMORY: usize = 15_000_000;
+
+/// Indexed fields
+#[derive(Clone, Copy, Debug)]
+struct Fields {
+    /// Entry ID
+    id: Field,
+
+    /// Tokenized entry text
+    text: Field,
+
+    /// Untokenized entry tags
+    tags: Field,
+}
+
+/// Inverted index ranking memory entries by BM25 relevance
+///
+/// Queries use tantivy's query syntax, so `"exact phrase"`, `tags:error`,
+/// `+required -excluded` and `term^2` boosts are supported. Bare terms
+/// search both the entry text and its tags, with tags boosted by default.
+pub struct TextIndex {
+    /// Index fields
+    fields: Fields,
+
+    /// Query parser over the text and tags fields
+    parser: QueryParser,
+
+    /// Index writer
+    writer: Mutex<IndexWriter>,
+
+    /// Index reader
+    reader: IndexReader,
+
+    /// Whether there are changes not yet visible to searches
+    dirty: AtomicBool,
+}
+
+impl TextIndex {
+    /// Create an empty in-memory index
+    pub fn new() -> Result<Self> {
+        let mut schema = Schema::builder();
+        let fields = Fields {
+            id: schema.add_text_field("id", STRING | STORED),
+            text: schema.add_text_field("text", TEXT),
+            tags: schema.add_text_field("tags", STRING),
+        };
+        let index = Index::create_in_ram(schema.build());
+        let writer = index.writer_with_num_threads(1, WRITER_MEMORY)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+
+        let mut parser = QueryParser::for_index(&index, vec![fields.text, fields.tags]);
+        parser.set_field_boost(fields.tags, 2.0);
+
+        Ok(Self {
+            fields,
+            parser,
+            writer: Mutex::new(writer),
+            reader,
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Set the boost applied to matches on the entry text
+    pub fn with_text_boost(mut self, boost: f32) -> Self {
+        self.parser.set_field_boost(self.fields.text, boost);
+        self
+    }
+
+    /// Set the boost applied to matches on entry tags
+    pub fn with_tag_boost(mut self, boost: f32) -> Self {
+        self.parser.set_field_boost(self.fields.tags, boost);
+        self
+    }
+
+    /// Index an entry, replacing any previous version
+    pub fn insert(&self, entry: &MemoryEntry) -> Result<()> {
+        let mut doc = TantivyDocument::new();
+        doc.add_text(self.fields.id, entry.id.to_string());
+        doc.add_text(self.fields.text, entry.text());
+        for tag in &entry.tags {
+            doc.add_text(self.fields.tags, tag);
+        }
+
+        let writer = self.lock()?;
+        writer.delete_term(self.id_term(entry.id));
+        writer.add_document(doc)?;
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Remove an entry from the index
+    pub fn remove(&self, id: Uuid) -> Result<()> {
+        self.lock()?.delete_term(self.id_term(id));
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Remove every entry from the index
+    pub fn clear(&self) -> Result<()> {
+        self.lock()?.delete_all_documents()?;
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Find the `k` entries most relevant to a query, best first
+    ///
+    /// Malformed parts of the query are ignored rather than rejected.
+    pub fn search(&self, query: &str, k: usize) -> Result<Vec<(Uuid, f32)>> {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+        self.commit()?;
+
+        let (query, _) = self.parser.parse_query_lenient(query);
+        let searcher = self.reader.searcher();
+        let mut hits = Vec::new();
+        for (score, address) in searcher.search(&query, &TopDocs::with_limit(k))? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let id = doc
+                .get_first(self.fields.id)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| Error::MemoryError("Indexed entry has no ID".to_string()))?;
+            hits.push((id.parse()?, score));
+        }
+        Ok(hits)
+    }
+
+    /// Make pending changes visible to searches
+    fn commit(&self) -> Result<()> {
+        if self.dirty.swap(false, Ordering::AcqRel) {
+            self.lock()?.commit()?;
+            self.reader.reload()?;
+        }
+        Ok(())
+    }
+
+    fn id_term(&self, id: Uuid) -> Term {
+        Term::from_field_text(self.fields.id, &id.to_string())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, IndexWriter>> {
+        self.writer
+            .lock()
+            .map_err(|_| Error::MemoryError("Text index lock poisoned".to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::Metadata;
+    use serde_json::json;
+
+    fn entry(text: &str, tags: &[&str]) -> MemoryEntry {
+        let mut metadata = Metadata::new();
+        metadata.insert(crate::state::TAGS_KEY, tags);
+        MemoryEntry::new(json!(text), metadata)
+    }
+
+    #[test]
+    fn test_ranked_and_phrase_search() {
+        let index = TextIndex::new().unwrap();
+        let weather = entry("The weather in Paris is sunny", &["tool:weather"]);
+        let flights = entry("Booked flights from Berlin to Paris", &["tool:travel"]);
+        let rain = entry("Rain expected in Berlin tomorrow, weather alert", &[]);
+        for e in [&weather, &flights, &rain] {
+            index.insert(e).unwrap();
+        }
+
+        let hits = index.search("paris", 10).unwrap();
+        assert_eq!(hits.len(), 2);
+
+        let hits = index.search("\"weather in paris\"", 10).unwrap();
+        assert_eq!(
+            hits.iter().map(|h| h.0).collect::<Vec<_>>(),
+            vec![weather.id]
+        );
+
+        // Tag matches outrank text matches
+        let hits = index.search("tags:\"tool:weather\" OR berlin", 10).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].0, weather.id);
+
+        index.remove(flights.id).unwrap();
+        assert_eq!(index.search("paris", 10).unwrap().len(), 1);
+        index.clear().unwrap();
+        assert!(index.search("weather", 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,467 @@
+//! Write-ahead log for file-persisted memory
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use uuid::Uuid;
+
+use super::MemoryStore;
+use crate::error::Error;
+use crate::state::MemoryEntry;
+
+/// Change to memory recorded in the log
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WalRecord {
+    /// An entry was added or replaced
+    Put {
+        /// The entry
+        entry: MemoryEntry,
+    },
+
+    /// An entry was removed
+    Remove {
+        /// Entry ID
+        id: Uuid,
+    },
+
+    /// All entries were removed
+    Clear,
+}
+
+/// Open log file and the number of records it holds
+#[derive(Debug, Default)]
+struct LogState {
+    /// Log opened for appending
+    file: Option<File>,
+
+    /// Records appended since the last compaction
+    records: usize,
+}
+
+/// Append-only log of memory changes on top of a JSON snapshot
+///
+/// Each change is appended as one JSON line to `<snapshot>.wal` and synced,
+/// so persisting an insert costs one small write. Once the log holds
+/// `compact_after` records, the caller rewrites the snapshot from the
+/// stores with [`MemoryWal::compact`] and the log is truncated. Loading replays the log
+/// over the snapshot and drops a torn final line left by a crash.
+#[derive(Debug)]
+pub struct MemoryWal {
+    /// Snapshot file, a JSON array of entries
+    snapshot: PathBuf,
+
+    /// Log file
+    log: PathBuf,
+
+    /// Records appended before compaction is due
+    compact_after: usize,
+
+    /// Open log
+    state: Mutex<LogState>,
+}
+
+impl MemoryWal {
+    /// Create a log for a snapshot file, compacting after `compact_after` records
+    pub fn new<P: Into<PathBuf>>(snapshot: P, compact_after: usize) -> Self {
+        let snapshot = snapshot.into();
+        let mut log = snapshot.clone().into_os_string();
+        log.push(".wal");
+        Self {
+            snapshot,
+            log: log.into(),
+            compact_after: compact_after.max(1),
+            state: Mutex::new(LogState::default()),
+        }
+    }
+
+    /// Get the snapshot path
+    pub fn snapshot_path(&self) -> &Path {
+        &self.snapshot
+    }
+
+    /// Get the log path
+    pub fn log_path(&self) -> &Path {
+        &self.log
+    }
+
+    /// Durably append records, returning whether compaction is due
+    pub async fn append(&self, records: &[WalRecord]) -> Result<bool> {
+        if records.is_empty() {
+            return Ok(false);
+        }
+        let mut buf = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut buf, record)?;
+            buf.push(b'\n');
+        }
+
+        let mut state = self.state.lock().await;
+        if state.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.log)
+                .await?;
+            state.file = Some(file);
+        }
+        if let Some(file) = state.file.as_mut() {
+            file.write_all(&buf).await?;
+            file.sync_data().await?;
+        }
+        state.records += records.len();
+        Ok(state.records >= self.compact_after)
+    }
+
+    /// Replace the snapshot with the entries held in `stores` and truncate
+    /// the log
+    ///
+    /// The entries are listed, written and the log truncated while holding
+    /// the log, so no record appended meanwhile is lost. The snapshot is
+    /// written to a temporary file and renamed into place, so a crash leaves
+    /// either the old or the new snapshot.
+    pub async fn compact(&self, stores: &[Arc<dyn MemoryStore>]) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let mut entries = Vec::new();
+        for store in stores {
+            entries.extend(store.list().await?);
+        }
+
+        let mut tmp = self.snapshot.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp).await?;
+        file.write_all(&serde_json::to_vec_pretty(&entries)?).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp, &self.snapshot).await?;
+
+        let log = File::create(&self.log).await?;
+        log.sync_all().await?;
+        state.file = None;
+        state.records = 0;
+        Ok(())
+    }
+
+    /// Load the snapshot and replay the log over it
+    pub async fn load(&self) -> Result<Vec<MemoryEntry>> {
+        let mut entries: Vec<Option<MemoryEntry>> = match tokio::fs::read(&self.snapshot).await {
+            Ok(json) => serde_json::from_slice::<Vec<MemoryEntry>>(&json)?
+                .into_iter()
+                .map(Some)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut positions: HashMap<Uuid, usize> = entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| e.as_ref().map(|e| (e.id, i)))
+            .collect();
+
+        let log = match tokio::fs::read(&self.log).await {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut records = 0;
+        let mut offset = 0;
+        for line in log.split_inclusive(|b| *b == b'\n') {
+            let record = match serde_json::from_slice::<WalRecord>(line) {
+                Ok(record) => record,
+                // A crash mid-append leaves a torn final line; drop it so
+                // later appends start on a fresh line
+                Err(e) if offset + line.len() == log.len() && !line.ends_with(b"\n") => {
+                    tracing::warn!(error = %e, "Truncating torn memory log record");
+                    let file = OpenOptions::new().write(true).open(&self.log).await?;
+                    file.set_len(offset as u64).await?;
+                    file.sync_all().await?;
+                    break;
+                }
+                Err(e) => {
+                    return Err(Error::MemoryError(format!(
+                        "Corrupt memory log on line {}: {}",
+                        records + 1,
+                        e
+                    ))
+                    .into());
+                }
+            };
+            records += 1;
+            offset += line.len();
+            match record {
+                WalRecord::Put { entry } => match positions.get(&entry.id) {
+                    Some(&i) => entries[i] = Some(entry),
+                    None => {
+                        positions.insert(entry.id, entries.len());
+                        entries.push(Some(entry));
+                    }
+                },
+                WalRecord::Remove { id } => {
+                    if let Some(i) = positions.remove(&id) {
+                        entries[i] = None;
+                    }
+                }
+                WalRecord::Clear => {
+                    entries.clear();
+                    positions.clear();
+                }
+            }
+        }
+
+        self.state.lock().await.records = records;
+        Ok(entries.into_iter().flatten().collect())
+    }
+}
+
+/// Messages a log writer queues before senders wait for the disk
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Message to the background log writer
+enum WriterMessage {
+    /// Records to append
+    Records(Vec<WalRecord>),
+
+    /// Reply once everything queued before is durable
+    Flush(oneshot::Sender<std::result::Result<(), String>>),
+}
+
+/// How far a log writer is behind memory, for health checks
+#[derive(Clone, Debug, Default)]
+pub struct WriterStatus {
+    /// Error of the last write, if it left the files behind memory
+    pub failure: Option<String>,
+
+    /// When the oldest records not yet written were queued, if any are
+    pub pending_since: Option<Instant>,
+}
+
+/// What a writer has yet to write, shared with its handles
+#[derive(Debug, Default)]
+struct Progress {
+    /// Status reported to handles
+    status: WriterStatus,
+
+    /// Batches queued and not yet written
+    pending: usize,
+}
+
+/// Shared progress of a writer
+type SharedProgress = Arc<std::sync::Mutex<Progress>>;
+
+/// Handle to a background task appending records to a log in batches
+///
+/// Records are queued without waiting for the disk, unless the queue is full
+/// because the disk is falling behind. The task gathers
+/// whatever is queued, optionally waiting `delay` for more, and appends it
+/// with a single write and sync. If a write fails, the next one rewrites the
+/// snapshot from the stores instead, so the files catch up with memory once
+/// the disk recovers.
+#[derive(Debug, Clone)]
+pub struct WalWriter {
+    /// Queue to the task
+    sender: mpsc::Sender<WriterMessage>,
+
+    /// What the task has yet to write
+    progress: SharedProgress,
+}
+
+impl WalWriter {
+    /// Spawn a writer for a log over the entries held in `stores`
+    ///
+    /// The task exits once every handle is dropped and the queue is drained.
+    pub fn spawn(wal: Arc<MemoryWal>, stores: Vec<Arc<dyn MemoryStore>>, delay: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let progress = SharedProgress::default();
+        tokio::spawn(run_writer(wal, stores, delay, receiver, progress.clone()));
+        Self { sender, progress }
+    }
+
+    /// Queue records to append, waiting for room if the queue is full
+    pub async fn send(&self, records: Vec<WalRecord>) -> Result<()> {
+        {
+            let mut progress = lock(&self.progress);
+            progress.pending += 1;
+            progress
+                .status
+                .pending_since
+                .get_or_insert_with(Instant::now);
+        }
+        self.sender
+            .send(WriterMessage::Records(records))
+            .await
+            .map_err(|_| Error::MemoryError("Memory log writer stopped".to_string()).into())
+    }
+
+    /// Get how far the writer is behind, without waiting for it
+    pub fn status(&self) -> WriterStatus {
+        lock(&self.progress).status.clone()
+    }
+
+    /// Wait until every record queued so far is durable
+    pub async fn flush(&self) -> Result<()> {
+        let (reply, done) = oneshot::channel();
+        self.sender
+            .send(WriterMessage::Flush(reply))
+            .await
+            .map_err(|_| Error::MemoryError("Memory log writer stopped".to_string()))?;
+        done.await
+            .map_err(|_| Error::MemoryError("Memory log writer stopped".to_string()))?
+            .map_err(|e| Error::MemoryError(format!("Failed to persist memory: {}", e)).into())
+    }
+}
+
+/// Append queued records in batches until every handle is dropped
+async fn run_writer(
+    wal: Arc<MemoryWal>,
+    stores: Vec<Arc<dyn MemoryStore>>,
+    delay: Duration,
+    mut receiver: mpsc::Receiver<WriterMessage>,
+    progress: SharedProgress,
+) {
+    // Error of the last write, which left the files behind memory
+    let mut failure: Option<String> = None;
+    // Batches gathered but not yet written
+    let mut unwritten = 0;
+    while let Some(message) = receiver.recv().await {
+        let mut records = Vec::new();
+        let mut waiters = Vec::new();
+        let mut gather = |message| match message {
+            WriterMessage::Records(batch) => {
+                records.extend(batch);
+                unwritten += 1;
+            }
+            WriterMessage::Flush(reply) => waiters.push(reply),
+        };
+        let flushing = matches!(message, WriterMessage::Flush(_));
+        gather(message);
+        if !flushing && !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        while let Ok(message) = receiver.try_recv() {
+            gather(message);
+        }
+
+        if !records.is_empty() || (failure.is_some() && !waiters.is_empty()) {
+            let result = write_batch(&wal, &stores, &records, failure.is_some()).await;
+            failure = result.err().map(|e| {
+                tracing::error!(error = %e, "Failed to persist memory");
+                e.to_string()
+            });
+            let mut progress = lock(&progress);
+            if failure.is_none() {
+                progress.pending -= unwritten;
+                unwritten = 0;
+                let behind = progress.pending > 0;
+                progress.status.pending_since = behind.then(Instant::now);
+            }
+            progress.status.failure = failure.clone();
+        }
+        for waiter in waiters {
+            let _ = waiter.send(failure.clone().map_or(Ok(()), Err));
+        }
+    }
+}
+
+/// Lock the progress of a writer
+fn lock(progress: &SharedProgress) -> std::sync::MutexGuard<'_, Progress> {
+    progress.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Append a batch, rewriting the snapshot when due or after a failed write
+async fn write_batch(
+    wal: &MemoryWal,
+    stores: &[Arc<dyn MemoryStore>],
+    records: &[WalRecord],
+    behind: bool,
+) -> Result<()> {
+    if behind || wal.append(records).await? {
+        wal.compact(stores).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryStore;
+    use atlas_core::Metadata;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_replay_and_compaction() {
+        let dir = std::env::temp_dir().join(format!("atlas-wal-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let wal = MemoryWal::new(dir.join("memory.json"), 3);
+
+        let a = MemoryEntry::new(json!("a"), Metadata::new());
+        let b = MemoryEntry::new(json!("b"), Metadata::new());
+        let put = |e: &MemoryEntry| WalRecord::Put { entry: e.clone() };
+        assert!(!wal.append(&[put(&a), put(&b)]).await.unwrap());
+        assert!(wal.append(&[WalRecord::Remove { id: a.id }]).await.unwrap());
+
+        // A crash mid-append leaves a torn last record
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(wal.log_path())
+            .await
+            .unwrap();
+        log.write_all(b"{\"op\":\"put\",\"ent").await.unwrap();
+        let entries = wal.load().await.unwrap();
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![b.id]);
+        wal.append(&[put(&a)]).await.unwrap();
+        assert_eq!(wal.load().await.unwrap().len(), 2);
+
+        let store = InMemoryStore::new();
+        for entry in &entries {
+            store.put(entry.clone()).await.unwrap();
+        }
+        wal.compact(&[Arc::new(store)]).await.unwrap();
+        assert!(tokio::fs::read(wal.log_path()).await.unwrap().is_empty());
+        let entries = wal.load().await.unwrap();
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![b.id]);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_writer_recovers_from_failed_write() {
+        let dir = std::env::temp_dir().join(format!("atlas-wal-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let wal = Arc::new(MemoryWal::new(dir.join("memory.json"), 3));
+        let store = Arc::new(InMemoryStore::new());
+        let writer = WalWriter::spawn(wal.clone(), vec![store.clone()], Duration::ZERO);
+
+        let put = |text: &str| MemoryEntry::new(json!(text), Metadata::new());
+        for entry in [put("a"), put("b")] {
+            store.put(entry.clone()).await.unwrap();
+            writer.send(vec![WalRecord::Put { entry }]).await.unwrap();
+        }
+        writer.flush().await.unwrap();
+        assert_eq!(wal.load().await.unwrap().len(), 2);
+        assert!(writer.status().pending_since.is_none());
+
+        // Compaction fails while the directory is gone, then catches up
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        let c = put("c");
+        store.put(c.clone()).await.unwrap();
+        writer
+            .send(vec![WalRecord::Put { entry: c }])
+            .await
+            .unwrap();
+        assert!(writer.flush().await.is_err());
+        let status = writer.status();
+        assert!(status.failure.is_some() && status.pending_since.is_some());
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(wal.load().await.unwrap().len(), 3);
+        assert!(writer.status().failure.is_none());
+        assert!(writer.status().pending_since.is_none());
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+//! Messages sent directly from one agent to another
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use atlas_core::{Agent as CoreAgent, AgentId, Event, EventBus, Metadata, TaskId, WireError};
+
+use crate::error::Error;
+use crate::{Agent, AgentDescriptor};
+
+/// How long [`AgentRef::ask`] waits for a response unless told otherwise
+pub const DEFAULT_ASK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Message one agent sends another
+///
+/// The kind names the message on the bus, so the sender and receiver agree
+/// on it without sharing a type, and the response is what the receiver
+/// answers [`AgentRef::ask`] with; messages that are only sent can use `()`.
+pub trait Message: Serialize + DeserializeOwned + Send + 'static {
+    /// Name of the message, unique among the messages an agent handles
+    const KIND: &'static str;
+
+    /// Answer to the message
+    type Response: Serialize + DeserializeOwned + Send + 'static;
+}
+
+/// Message with its sender and recipient, as published on the bus
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Envelope<M> {
+    /// Sending agent, unless sent from outside an agent
+    pub from: Option<AgentId>,
+
+    /// Receiving agent
+    pub to: AgentId,
+
+    /// Message
+    pub message: M,
+}
+
+/// Request to run a task, answered with its result
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExecuteTask {
+    /// ID of the task
+    pub task_id: TaskId,
+
+    /// Task parameters
+    pub params: Metadata,
+}
+
+impl Message for ExecuteTask {
+    const KIND: &'static str = "execute_task";
+    type Response = Metadata;
+}
+
+/// Get the topic an agent receives messages of a kind on
+pub fn inbox_topic(id: AgentId, kind: &str) -> String {
+    format!("agent.{}.inbox.{}", id, kind)
+}
+
+/// Get the pattern matching every topic an agent receives messages on
+pub(crate) fn inbox_pattern(id: AgentId) -> String {
+    format!("agent.{}.inbox.#", id)
+}
+
+/// Handle to an agent messages can be sent to over an event bus
+///
+/// References are usually resolved from an
+/// [`AgentRegistry`](crate::AgentRegistry), so the recipient may live in
+/// another process sharing the bus. The recipient handles messages with
+/// [`Agent::on_message`] while it listens.
+#[derive(Clone)]
+pub struct AgentRef {
+    /// Receiving agent
+    id: AgentId,
+
+    /// Name of the receiving agent
+    name: String,
+
+    /// Sending agent, if any
+    from: Option<AgentId>,
+
+    /// Bus messages are delivered over
+    event_bus: Arc<dyn EventBus>,
+
+    /// How long to wait for a response
+    timeout: Duration,
+}
+
+impl AgentRef {
+    /// Refer to an agent by ID
+    pub fn new(id: AgentId, name: impl Into<String>, event_bus: Arc<dyn EventBus>) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            from: None,
+            event_bus,
+            timeout: DEFAULT_ASK_TIMEOUT,
+        }
+    }
+
+    /// Refer to a described agent
+    pub fn from_descriptor(descriptor: &AgentDescriptor, event_bus: Arc<dyn EventBus>) -> Self {
+        Self::new(descriptor.id, descriptor.name.clone(), event_bus)
+    }
+
+    /// Send messages on behalf of an agent
+    pub fn with_sender(mut self, from: AgentId) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Wait this long for responses
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Get the ID of the receiving agent
+    pub fn id(&self) -> AgentId {
+        self.id
+    }
+
+    /// Get the name of the receiving agent
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Send a message without waiting for it to be handled
+    pub async fn send<M: Message>(&self, message: M) -> Result<()> {
+        let event = self.envelope(message)?;
+        self.event_bus.register_topic(event.topic()).await?;
+        self.event_bus.publish(event).await
+    }
+
+    /// Send a message and wait for the response, failing with the
+    /// recipient's error if handling it failed
+    pub async fn ask<M: Message>(&self, message: M) -> Result<M::Response> {
+        let event = self.envelope(message)?;
+        self.event_bus.register_topic(event.topic()).await?;
+        let reply = self.event_bus.request(event, self.timeout).await?;
+        if let Some(error) = reply.payload.get::<WireError>("error") {
+            return Err(atlas_core::Error::from(error).into());
+        }
+        reply.payload.try_get("response").map_err(|e| {
+            Error::InvalidRequest(format!(
+                "Invalid response to {} from {}: {}",
+                M::KIND,
+                self.name,
+                e
+            ))
+            .into()
+        })
+    }
+
+    /// Wrap a message in an event addressed to the agent
+    fn envelope<M: Message>(&self, message: M) -> Result<Event> {
+        let envelope = Envelope {
+            from: self.from,
+            to: self.id,
+            message,
+        };
+        let payload = Metadata::from_serialize(&envelope)?;
+        Ok(Event::new(inbox_topic(self.id, M::KIND), payload)
+            .with_current_cause()
+            .with_current_trace())
+    }
+}
+
+impl std::fmt::Debug for AgentRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentRef")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("from", &self.from)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl Agent {
+    /// Get a reference other code can message the agent through
+    pub fn agent_ref(&self) -> Result<AgentRef> {
+        Ok(AgentRef::new(
+            self.id,
+            self.config().name.clone(),
+            self.event_bus()?.clone(),
+        ))
+    }
+
+    /// Handle messages of a kind sent to the agent, answering the ones
+    /// sent with [`AgentRef::ask`] with the handler's response or error
+    ///
+    /// Messages are received while the agent listens.
+    pub fn on_message<M, F, Fut>(&self, handler: F) -> Result<()>
+    where
+        M: Message,
+        F: Fn(Envelope<M>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<M::Response>> + Send + 'static,
+    {
+        let bus = self.event_bus()?.clone();
+        let handler = Arc::new(handler);
+        self.on(&inbox_topic(self.id, M::KIND), move |event| {
+            let bus = bus.clone();
+            let handler = handler.clone();
+            async move {
+                let result = match event.try_payload::<Envelope<M>>() {
+                    Ok(envelope) => handler(envelope).await,
+                    Err(e) => Err(e),
+                };
+                if event.reply_to().is_none() {
+                    return result.map(|_| ());
+                }
+                let mut payload = Metadata::new();
+                match result {
+                    Ok(response) => payload.try_insert("response", &response)?,
+                    Err(e) => payload.try_insert("error", wire_error(e))?,
+                };
+                bus.respond(&event, payload).await
+            }
+        })
+    }
+
+    /// Run the tasks sent to the agent as [`ExecuteTask`] messages, so
+    /// routers and agents in other processes can delegate to it
+    pub fn accept_tasks(self: &Arc<Self>) -> Result<()> {
+        self.accept_tasks_with(|_| Ok(()))
+    }
+
+    /// Run the tasks sent to the agent as [`ExecuteTask`] messages that
+    /// `authorize` lets through, answering the others with its error
+    pub fn accept_tasks_with<F>(self: &Arc<Self>, authorize: F) -> Result<()>
+    where
+        F: Fn(&Envelope<ExecuteTask>) -> Result<()> + Send + Sync + 'static,
+    {
+        let agent = Arc::downgrade(self);
+        self.on_message(move |envelope: Envelope<ExecuteTask>| {
+            let agent = agent.upgrade();
+            let authorized = authorize(&envelope);
+            async move {
+                let agent =
+                    agent.ok_or_else(|| Error::InvalidRequest("Agent was dropped".to_string()))?;
+                authorized?;
+                let ExecuteTask { task_id, params } = envelope.message;
+                CoreAgent::execute_task(&*agent, task_id, params).await
+            }
+        })
+    }
+}
+
+/// Convert a failure to its form on the bus
+fn wire_error(e: anyhow::Error) -> WireError {
+    match e.downcast::<Error>() {
+        Ok(e) => atlas_core::Error::from(e).into(),
+        Err(e) => atlas_core::Error::Other(e).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentBuilder, Config};
+    use atlas_core::InMemoryEventBus;
+
+    #[derive(Deserialize, Serialize)]
+    struct Greet {
+        name: String,
+    }
+
+    impl Message for Greet {
+        const KIND: &'static str = "greet";
+        type Response = String;
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct Note(String);
+
+    impl Message for Note {
+        const KIND: &'static str = "note";
+        type Response = ();
+    }
+
+    #[tokio::test]
+    async fn test_send_and_ask() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        let agent = Arc::new(
+            AgentBuilder::new()
+                .config(Config {
+                    name: "greeter".to_string(),
+                    description: None,
+                    capabilities: vec![],
+                    config: Metadata::new(),
+                })
+                .event_bus(bus.clone())
+                .build()
+                .unwrap(),
+        );
+        agent
+            .on_message(|envelope: Envelope<Greet>| async move {
+                if envelope.message.name.is_empty() {
+                    return Err(Error::InvalidRequest("Nobody to greet".to_string()).into());
+                }
+                Ok(format!("Hello, {}", envelope.message.name))
+            })
+            .unwrap();
+        let (notes, mut received) = tokio::sync::mpsc::unbounded_channel();
+        agent
+            .on_message(move |envelope: Envelope<Note>| {
+                let notes = notes.clone();
+                async move {
+                    notes.send((envelope.from, envelope.message.0)).unwrap();
+                    Ok(())
+                }
+            })
+            .unwrap();
+        agent.listen().await.unwrap();
+
+        let sender = AgentId::new();
+        let greeter = agent.agent_ref().unwrap().with_sender(sender);
+        let greeting = greeter
+            .ask(Greet {
+                name: "Ada".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(greeting, "Hello, Ada");
+
+        let err = greeter
+            .ask(Greet {
+                name: String::new(),
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Nobody to greet"));
+
+        greeter.send(Note("done".to_string())).await.unwrap();
+        assert_eq!(
+            received.recv().await.unwrap(),
+            (Some(sender), "done".to_string())
+        );
+
+        let nobody =
+            AgentRef::new(AgentId::new(), "nobody", bus).with_timeout(Duration::from_millis(10));
+        assert!(nobody.ask(Note("hello".to_string())).await.is_err());
+    }
+}
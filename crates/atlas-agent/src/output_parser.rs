@@ -0,0 +1,246 @@
+//! Structured output parsing for model responses
+
+use std::marker::PhantomData;
+
+use anyhow::Result;
+use jsonschema::JSONSchema;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::llm::{ChatMessage, CompletionRequest, LanguageModel};
+
+/// Default number of re-prompts after an invalid response
+const DEFAULT_MAX_RETRIES: usize = 2;
+
+/// Parser that turns model output into a typed value
+///
+/// The JSON schema for `T` is derived with `schemars`, sent to the model as
+/// format instructions, and used to validate every response. Invalid
+/// responses are answered with the validation errors so the model can
+/// correct itself, up to `max_retries` times.
+pub struct OutputParser<T> {
+    /// JSON schema for the target type
+    schema: Value,
+
+    /// Compiled schema validator
+    validator: JSONSchema,
+
+    /// Maximum number of re-prompts
+    max_retries: usize,
+
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> OutputParser<T>
+where
+    T: DeserializeOwned + JsonSchema,
+{
+    /// Create a parser for `T`
+    pub fn new() -> Result<Self> {
+        let schema = serde_json::to_value(schemars::schema_for!(T))?;
+        let validator = JSONSchema::compile(&schema).map_err(|e| {
+            Error::OutputParseError(format!("Invalid schema for output type: {}", e))
+        })?;
+
+        Ok(Self {
+            schema,
+            validator,
+            max_retries: DEFAULT_MAX_RETRIES,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Set the maximum number of re-prompts
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Get the JSON schema for the target type
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Get the format instructions sent to the model
+    pub fn format_instructions(&self) -> String {
+        format!(
+            "Respond only with a JSON value that conforms to this JSON schema:\n{}",
+            self.schema
+        )
+    }
+
+    /// Parse and validate a single model output
+    pub fn parse(&self, output: &str) -> Result<T> {
+        self.try_parse(output)
+            .map_err(|errors| Error::OutputParseError(errors.join("; ")).into())
+    }
+
+    /// Ask the model for a typed value, re-prompting on invalid output
+    pub async fn complete(
+        &self,
+        model: &dyn LanguageModel,
+        mut request: CompletionRequest,
+    ) -> Result<T> {
+        request
+            .messages
+            .insert(0, ChatMessage::system(self.format_instructions()));
+
+        let mut attempt = 0;
+        loop {
+            let response = model.complete(request.clone()).await?;
+            let output = response.message.content;
+
+            let errors = match self.try_parse(&output) {
+                Ok(value) => return Ok(value),
+                Err(errors) => errors,
+            };
+
+            if attempt >= self.max_retries {
+                return Err(Error::OutputParseError(format!(
+                    "Output still invalid after {} attempts: {}",
+                    attempt + 1,
+                    errors.join("; ")
+                ))
+                .into());
+            }
+            attempt += 1;
+
+            tracing::debug!(attempt, "Re-prompting after invalid structured output");
+            request.messages.push(ChatMessage::assistant(output));
+            request.messages.push(ChatMessage::user(format!(
+                "Your response did not match the required schema:\n- {}\nRespond again with corrected JSON only.",
+                errors.join("\n- ")
+            )));
+        }
+    }
+
+    fn try_parse(&self, output: &str) -> std::result::Result<T, Vec<String>> {
+        let json = extract_json(output);
+        let value: Value = serde_json::from_str(json)
+            .map_err(|e| vec![format!("Response is not valid JSON: {}", e)])?;
+
+        if let Err(errors) = self.validator.validate(&value) {
+            return Err(errors
+                .map(|e| {
+                    let path = e.instance_path.to_string();
+                    if path.is_empty() {
+                        e.to_string()
+                    } else {
+                        format!("{}: {}", path, e)
+                    }
+                })
+                .collect());
+        }
+
+        serde_json::from_value(value).map_err(|e| vec![e.to_string()])
+    }
+}
+
+/// Extract the JSON part of a model response
+///
+/// Handles fenced code blocks and prose around a single JSON object or array.
+fn extract_json(output: &str) -> &str {
+    let trimmed = output.trim();
+
+    if let Some(start) = trimmed.find("```") {
+        let after = &trimmed[start + 3..];
+        let body = after.strip_prefix("json").unwrap_or(after);
+        if let Some(end) = body.find("```") {
+            return body[..end].trim();
+        }
+    }
+
+    let start = trimmed.find(['{', '[']);
+    let end = trimmed.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+        _ => trimmed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionResponse;
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct Verdict {
+        label: String,
+        score: u8,
+    }
+
+    /// Returns an invalid response first, then a valid one
+    struct FlakyModel {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LanguageModel for FlakyModel {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            let content = match self.calls.fetch_add(1, Ordering::SeqCst) {
+                0 => r#"{"label": "spam"}"#.to_string(),
+                _ => {
+                    assert!(request.messages.last().unwrap().content.contains("score"));
+                    "```json\n{\"label\": \"spam\", \"score\": 9}\n```".to_string()
+                }
+            };
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(content),
+                model: "flaky".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_parse_with_surrounding_text() {
+        let parser = OutputParser::<Verdict>::new().unwrap();
+        let verdict = parser
+            .parse("Here you go: {\"label\": \"ham\", \"score\": 1} Thanks!")
+            .unwrap();
+        assert_eq!(
+            verdict,
+            Verdict {
+                label: "ham".to_string(),
+                score: 1
+            }
+        );
+
+        let err = parser.parse("{\"label\": 3, \"score\": 1}").unwrap_err();
+        assert!(err.to_string().contains("label"));
+    }
+
+    #[tokio::test]
+    async fn test_retries_with_validation_errors() {
+        let model = FlakyModel {
+            calls: AtomicUsize::new(0),
+        };
+        let parser = OutputParser::<Verdict>::new().unwrap();
+
+        let verdict = parser
+            .complete(&model, CompletionRequest::new(vec![ChatMessage::user("classify")]))
+            .await
+            .unwrap();
+        assert_eq!(verdict.score, 9);
+        assert_eq!(model.calls.load(Ordering::SeqCst), 2);
+
+        let model = FlakyModel {
+            calls: AtomicUsize::new(0),
+        };
+        let parser = OutputParser::<Verdict>::new().unwrap().with_max_retries(0);
+        assert!(parser
+            .complete(&model, CompletionRequest::new(vec![]))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,355 @@
+//! Goal decomposition into task graphs
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::join_all;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use atlas_core::Metadata;
+use atlas_mcp::ToolInfo;
+
+use crate::error::Error;
+use crate::llm::{ChatMessage, CompletionRequest, LanguageModel};
+use crate::output_parser::OutputParser;
+use crate::types::TaskConfig;
+use crate::Agent;
+
+/// A single step in a task plan
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PlanStep {
+    /// Step ID, unique within the plan
+    pub id: String,
+
+    /// Task to perform
+    pub task: TaskConfig,
+
+    /// Tool assigned to the step; steps without a tool run through the reasoning loop
+    #[serde(default)]
+    pub tool: Option<String>,
+
+    /// IDs of steps that must complete first
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+/// Task plan forming a dependency graph
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TaskPlan {
+    /// Goal the plan achieves
+    pub goal: String,
+
+    /// Plan steps
+    pub steps: Vec<PlanStep>,
+}
+
+impl TaskPlan {
+    /// Validate the plan: unique IDs, known dependencies, and no cycles
+    pub fn validate(&self) -> Result<()> {
+        self.layers().map(|_| ())
+    }
+
+    /// Group steps into layers that can run concurrently, in dependency order
+    pub fn layers(&self) -> Result<Vec<Vec<&PlanStep>>> {
+        let mut by_id: HashMap<&str, &PlanStep> = HashMap::new();
+        for step in &self.steps {
+            if by_id.insert(step.id.as_str(), step).is_some() {
+                return Err(Error::InvalidRequest(format!("Duplicate plan step: {}", step.id)).into());
+            }
+        }
+
+        let mut pending: HashMap<&str, usize> = HashMap::new();
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for step in &self.steps {
+            for dep in &step.depends_on {
+                if !by_id.contains_key(dep.as_str()) {
+                    return Err(Error::InvalidRequest(format!(
+                        "Plan step {} depends on unknown step {}",
+                        step.id, dep
+                    ))
+                    .into());
+                }
+                dependents.entry(dep.as_str()).or_default().push(step.id.as_str());
+            }
+            pending.insert(step.id.as_str(), step.depends_on.len());
+        }
+
+        // Kahn's algorithm, keeping declaration order within each layer
+        let mut ready: VecDeque<&str> = self
+            .steps
+            .iter()
+            .filter(|s| s.depends_on.is_empty())
+            .map(|s| s.id.as_str())
+            .collect();
+        let mut layers = Vec::new();
+        let mut visited = 0;
+
+        while !ready.is_empty() {
+            let layer: Vec<&str> = ready.drain(..).collect();
+            visited += layer.len();
+
+            for id in &layer {
+                for dependent in dependents.get(id).into_iter().flatten() {
+                    let count = pending.get_mut(dependent).expect("dependent is a known step");
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(dependent);
+                    }
+                }
+            }
+            layers.push(layer.into_iter().map(|id| by_id[id]).collect());
+        }
+
+        if visited != self.steps.len() {
+            return Err(Error::InvalidRequest("Plan contains a dependency cycle".to_string()).into());
+        }
+        Ok(layers)
+    }
+}
+
+/// Goal planner
+#[async_trait]
+pub trait Planner: Send + Sync {
+    /// Decompose a goal into a task plan using the given tools
+    async fn plan(&self, goal: &str, tools: &[ToolInfo]) -> Result<TaskPlan>;
+}
+
+/// Planner backed by a language model
+pub struct LlmPlanner {
+    /// Language model
+    model: Arc<dyn LanguageModel>,
+
+    /// Maximum number of re-prompts for invalid plans
+    max_retries: usize,
+}
+
+impl LlmPlanner {
+    /// Create a new planner
+    pub fn new(model: Arc<dyn LanguageModel>) -> Self {
+        Self {
+            model,
+            max_retries: 2,
+        }
+    }
+
+    /// Set the maximum number of re-prompts for invalid plans
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+}
+
+#[async_trait]
+impl Planner for LlmPlanner {
+    async fn plan(&self, goal: &str, tools: &[ToolInfo]) -> Result<TaskPlan> {
+        let tool_list = tools
+            .iter()
+            .map(|t| format!("- {}: {}", t.name, t.description))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let request = CompletionRequest::new(vec![
+            ChatMessage::system(
+                "You are a planner. Break the goal into small steps forming a \
+                 dependency graph. Assign a tool to a step when one fits, and \
+                 list the IDs of the steps each step depends on.",
+            ),
+            ChatMessage::user(format!("Goal: {}\n\nAvailable tools:\n{}", goal, tool_list)),
+        ]);
+
+        let plan = OutputParser::<TaskPlan>::new()?
+            .with_max_retries(self.max_retries)
+            .complete(self.model.as_ref(), request)
+            .await?;
+
+        plan.validate()?;
+        for step in &plan.steps {
+            if let Some(tool) = &step.tool {
+                if !tools.iter().any(|t| &t.name == tool) {
+                    return Err(Error::ToolNotFound(format!("{} (plan step {})", tool, step.id)).into());
+                }
+            }
+        }
+        Ok(plan)
+    }
+}
+
+impl Agent {
+    /// Execute a plan, running independent steps concurrently
+    ///
+    /// Tool steps receive the task parameters plus a `dependencies` map of
+    /// upstream results; other steps run through the reasoning loop with the
+    /// upstream results as input. Returns each step's result by ID.
+    pub async fn execute_plan(&self, plan: &TaskPlan) -> Result<HashMap<String, Metadata>> {
+        let mut results: HashMap<String, Metadata> = HashMap::new();
+
+        for layer in plan.layers()? {
+            let runs = layer.iter().map(|step| {
+                let dependencies: HashMap<&String, &Metadata> = step
+                    .depends_on
+                    .iter()
+                    .filter_map(|dep| results.get(dep).map(|r| (dep, r)))
+                    .collect();
+                let dependencies = serde_json::to_value(dependencies);
+                async move {
+                    let dependencies = dependencies?;
+                    let result = match &step.tool {
+                        Some(tool) => {
+                            let mut params = Metadata::from(step.task.parameters.clone());
+                            params.insert("dependencies", dependencies);
+                            self.tools.read().await.execute(tool, params).await
+                        }
+                        None => {
+                            let input = format!("Results of previous steps: {}", dependencies);
+                            self.run_task(atlas_core::TaskId::new(), &step.task, &input)
+                                .await
+                                .and_then(|outcome| {
+                                    let answer = outcome.answer.ok_or_else(|| {
+                                        Error::TaskError("Maximum number of steps reached".to_string())
+                                    })?;
+                                    let mut result = Metadata::new();
+                                    result.insert("answer", answer);
+                                    Ok(result)
+                                })
+                        }
+                    };
+                    result.map_err(|e| {
+                        anyhow::Error::from(Error::TaskError(format!("Plan step {} failed: {}", step.id, e)))
+                    })
+                }
+            });
+
+            let outputs = join_all(runs).await;
+            for (step, output) in layer.iter().zip(outputs) {
+                results.insert(step.id.clone(), output?);
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionResponse;
+    use crate::Config;
+    use atlas_mcp::MCPTool;
+    use serde_json::json;
+
+    fn step(id: &str, deps: &[&str]) -> PlanStep {
+        PlanStep {
+            id: id.to_string(),
+            task: TaskConfig {
+                name: id.to_string(),
+                ..Default::default()
+            },
+            tool: Some("count".to_string()),
+            depends_on: deps.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    struct CountTool;
+
+    #[async_trait]
+    impl MCPTool for CountTool {
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        fn description(&self) -> &str {
+            "Count upstream results"
+        }
+
+        async fn execute(&self, params: Metadata) -> Result<Metadata> {
+            let deps = params.get::<serde_json::Map<String, serde_json::Value>>("dependencies");
+            let mut result = Metadata::new();
+            result.insert("upstream", deps.map(|d| d.len()).unwrap_or_default());
+            Ok(result)
+        }
+    }
+
+    struct PlanningModel;
+
+    #[async_trait]
+    impl LanguageModel for PlanningModel {
+        fn name(&self) -> &str {
+            "planning"
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            let plan = json!({
+                "goal": "report",
+                "steps": [
+                    {"id": "fetch", "task": {"name": "fetch"}, "tool": "count"},
+                    {"id": "write", "task": {"name": "write"}, "tool": "count", "depends_on": ["fetch"]}
+                ]
+            });
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(plan.to_string()),
+                model: "planning".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_plan_layers() {
+        let plan = TaskPlan {
+            goal: "test".to_string(),
+            steps: vec![step("a", &[]), step("b", &["a"]), step("c", &["a"]), step("d", &["b", "c"])],
+        };
+
+        let layers = plan.layers().unwrap();
+        let ids: Vec<Vec<&str>> = layers
+            .iter()
+            .map(|l| l.iter().map(|s| s.id.as_str()).collect())
+            .collect();
+        assert_eq!(ids, vec![vec!["a"], vec!["b", "c"], vec!["d"]]);
+
+        let cyclic = TaskPlan {
+            goal: "test".to_string(),
+            steps: vec![step("a", &["b"]), step("b", &["a"])],
+        };
+        assert!(cyclic.validate().is_err());
+
+        let dangling = TaskPlan {
+            goal: "test".to_string(),
+            steps: vec![step("a", &["missing"])],
+        };
+        assert!(dangling.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_llm_planner_and_execution() {
+        let tools = vec![ToolInfo {
+            name: "count".to_string(),
+            description: "Count upstream results".to_string(),
+            input_schema: None,
+        }];
+        let plan = LlmPlanner::new(Arc::new(PlanningModel))
+            .plan("report", &tools)
+            .await
+            .unwrap();
+        assert_eq!(plan.steps.len(), 2);
+
+        let agent = Agent::builder()
+            .config(Config {
+                name: "planner".to_string(),
+                description: None,
+                capabilities: vec![],
+                config: Metadata::new(),
+            })
+            .tool("count", CountTool)
+            .build()
+            .unwrap();
+
+        let results = agent.execute_plan(&plan).await.unwrap();
+        assert_eq!(results["fetch"].get::<usize>("upstream"), Some(0));
+        assert_eq!(results["write"].get::<usize>("upstream"), Some(1));
+    }
+}
@@ -0,0 +1,283 @@
+//! Which tools an agent may run, and with which parameters
+
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+use atlas_core::Metadata;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// Topic a refused tool execution is reported on
+pub const TOOL_POLICY_VIOLATION: &str = "tool.policy_violation";
+
+/// Constraint on the value of a tool parameter
+#[derive(Clone, Debug)]
+pub enum ParamRule {
+    /// Absolute path inside a directory, judged without touching the file
+    /// system; relative paths and paths leaving it through `..` are refused
+    PathUnder(PathBuf),
+
+    /// One of a set of values
+    OneOf(Vec<Value>),
+
+    /// String matching a regular expression
+    Matches(Regex),
+}
+
+impl ParamRule {
+    /// Require an absolute path inside a directory
+    pub fn path_under(root: impl Into<PathBuf>) -> Self {
+        Self::PathUnder(root.into())
+    }
+
+    /// Require one of a set of values
+    pub fn one_of<V: Into<Value>>(values: impl IntoIterator<Item = V>) -> Self {
+        Self::OneOf(values.into_iter().map(Into::into).collect())
+    }
+
+    /// Require a string matching a regular expression
+    pub fn matches(pattern: &str) -> Result<Self> {
+        let pattern = Regex::new(pattern)
+            .map_err(|e| Error::InvalidConfig(format!("Invalid parameter pattern: {}", e)))?;
+        Ok(Self::Matches(pattern))
+    }
+
+    /// Check a value, describing what it must be if it breaks the rule
+    fn check(&self, value: &Value) -> std::result::Result<(), String> {
+        match self {
+            Self::PathUnder(root) => {
+                let inside = value.as_str().map(Path::new).is_some_and(|path| {
+                    path.is_absolute()
+                        && normalize(path)
+                            .zip(normalize(root))
+                            .is_some_and(|(path, root)| path.starts_with(root))
+                });
+                if inside {
+                    Ok(())
+                } else {
+                    Err(format!("a path under {}", root.display()))
+                }
+            }
+            Self::OneOf(values) => {
+                if values.contains(value) {
+                    Ok(())
+                } else {
+                    Err(format!("one of {}", Value::Array(values.clone())))
+                }
+            }
+            Self::Matches(pattern) => {
+                if value.as_str().is_some_and(|s| pattern.is_match(s)) {
+                    Ok(())
+                } else {
+                    Err(format!("a string matching {}", pattern))
+                }
+            }
+        }
+    }
+}
+
+/// Constraint on one parameter of a tool
+#[derive(Clone, Debug)]
+pub struct ParamConstraint {
+    /// Tool the constraint applies to
+    pub tool: String,
+
+    /// Dot-separated path of the parameter
+    pub param: String,
+
+    /// Rule the parameter must follow
+    pub rule: ParamRule,
+}
+
+/// Tools an agent may run, and rules their parameters must follow
+///
+/// Without an allowlist every tool not denied may run; once a tool is
+/// allowed, only the allowed ones may. Constraints only check parameters
+/// that are present, so tools should not default what they constrain to
+/// something unsafe.
+#[derive(Clone, Debug, Default)]
+pub struct ToolPolicy {
+    /// Tools that may run, if restricted
+    allowed: Option<HashSet<String>>,
+
+    /// Tools that may never run
+    denied: HashSet<String>,
+
+    /// Rules of the parameters, in the order they were added
+    constraints: Vec<ParamConstraint>,
+}
+
+impl ToolPolicy {
+    /// Create a policy letting every tool run
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let a tool run, refusing every tool not allowed
+    pub fn allow(mut self, tool: impl Into<String>) -> Self {
+        self.allowed
+            .get_or_insert_with(HashSet::new)
+            .insert(tool.into());
+        self
+    }
+
+    /// Never let a tool run, even if allowed
+    pub fn deny(mut self, tool: impl Into<String>) -> Self {
+        self.denied.insert(tool.into());
+        self
+    }
+
+    /// Require a parameter of a tool to follow a rule
+    pub fn constrain(
+        mut self,
+        tool: impl Into<String>,
+        param: impl Into<String>,
+        rule: ParamRule,
+    ) -> Self {
+        self.constraints.push(ParamConstraint {
+            tool: tool.into(),
+            param: param.into(),
+            rule,
+        });
+        self
+    }
+
+    /// Get the rules of the parameters, in the order they were added
+    pub fn constraints(&self) -> &[ParamConstraint] {
+        &self.constraints
+    }
+
+    /// Check that a tool may run with the given parameters
+    pub fn check(&self, tool: &str, params: &Metadata) -> Result<()> {
+        if self.denied.contains(tool) {
+            return Err(violation(format!("Tool {} is denied", tool)));
+        }
+        if self
+            .allowed
+            .as_ref()
+            .is_some_and(|allowed| !allowed.contains(tool))
+        {
+            return Err(violation(format!("Tool {} is not allowed", tool)));
+        }
+        for constraint in self.constraints.iter().filter(|c| c.tool == tool) {
+            let Some(value) = params.get_path::<Value>(&constraint.param) else {
+                continue;
+            };
+            if let Err(expected) = constraint.rule.check(&value) {
+                return Err(violation(format!(
+                    "Parameter {} of tool {} must be {}, got {}",
+                    constraint.param, tool, expected, value
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the tools the policy allows or constrains, which must be
+    /// registered
+    pub(crate) fn named(&self) -> impl Iterator<Item = &str> {
+        self.allowed
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(self.constraints.iter().map(|c| c.tool.as_str()))
+    }
+}
+
+/// Resolve the `.` and `..` components of a path without touching the file
+/// system, or `None` if it climbs above its root
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    Some(normalized)
+}
+
+/// Refuse a tool execution
+fn violation(message: String) -> anyhow::Error {
+    Error::ToolPolicyViolation(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(value: Value) -> Metadata {
+        Metadata::try_from(value).unwrap()
+    }
+
+    #[test]
+    fn test_tool_policy() {
+        let policy = ToolPolicy::new()
+            .allow("read_file")
+            .allow("shell")
+            .deny("shell")
+            .constrain("read_file", "path", ParamRule::path_under("/workspace"))
+            .constrain(
+                "read_file",
+                "options.mode",
+                ParamRule::one_of(["text", "bytes"]),
+            );
+
+        let allowed = [
+            json!({"path": "/workspace/notes.md"}),
+            json!({"path": "/workspace/a/../b.md", "options": {"mode": "text"}}),
+            json!({}),
+        ];
+        for value in allowed {
+            assert!(policy.check("read_file", &params(value)).is_ok());
+        }
+
+        let refused = [
+            json!({"path": "/etc/passwd"}),
+            json!({"path": "/workspace/../etc/passwd"}),
+            json!({"path": "/workspace-other/x"}),
+            json!({"path": "notes.md"}),
+            json!({"path": 7}),
+            json!({"path": "/workspace/x", "options": {"mode": "exec"}}),
+        ];
+        for value in refused {
+            let err = policy.check("read_file", &params(value)).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::ToolPolicyViolation(_))
+            ));
+        }
+
+        let err = policy.check("shell", &Metadata::new()).unwrap_err();
+        assert!(err.to_string().contains("Tool shell is denied"));
+        let err = policy.check("search", &Metadata::new()).unwrap_err();
+        assert!(err.to_string().contains("Tool search is not allowed"));
+    }
+
+    #[test]
+    fn test_pattern_rule() {
+        let policy = ToolPolicy::new().constrain(
+            "fetch",
+            "url",
+            ParamRule::matches(r"^https://example\.com/").unwrap(),
+        );
+        assert!(policy
+            .check("fetch", &params(json!({"url": "https://example.com/a"})))
+            .is_ok());
+        assert!(policy
+            .check("fetch", &params(json!({"url": "http://evil.test/"})))
+            .is_err());
+        // Tools without an allowlist run unless denied
+        assert!(policy.check("search", &Metadata::new()).is_ok());
+        assert!(ParamRule::matches("(").is_err());
+    }
+}
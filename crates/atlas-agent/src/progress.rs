@@ -0,0 +1,113 @@
+//! Progress reports of the tasks an agent runs
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use atlas_core::{Event, Metadata};
+
+use crate::heartbeat::AGENT_KEY;
+use crate::Agent;
+
+/// Topic agents report the progress of their tasks on, linking each report
+/// to its task with [`Cause`](atlas_core::Cause)
+pub const TASK_PROGRESS: &str = "task.progress";
+
+/// Payload key holding how long a task has been running, in milliseconds
+pub const ELAPSED_KEY: &str = "elapsed_ms";
+
+/// Time between the progress reports of a task used by a new agent
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Agent {
+    /// Do a task's work, reporting on [`TASK_PROGRESS`] as it starts and then
+    /// every progress interval until it finishes, if the agent has a bus
+    ///
+    /// Reports are linked to the current cause, so the work must run in the
+    /// task's [`Cause::scope`](atlas_core::Cause::scope).
+    pub(crate) async fn reporting_progress<T>(&self, work: impl Future<Output = T>) -> T {
+        let Some(bus) = &self.event_bus else {
+            return work.await;
+        };
+        if let Err(e) = bus.register_topic(TASK_PROGRESS).await {
+            tracing::debug!(error = %e, "Failed to register progress topic");
+        }
+        let started = Instant::now();
+        let mut reports = tokio::time::interval(self.progress_interval);
+        tokio::pin!(work);
+        loop {
+            tokio::select! {
+                output = &mut work => return output,
+                _ = reports.tick() => {
+                    let mut payload = Metadata::new();
+                    payload.insert(AGENT_KEY, &self.config().name);
+                    payload.insert(ELAPSED_KEY, started.elapsed().as_millis() as u64);
+                    let report = Event::new(TASK_PROGRESS, payload).with_current_cause();
+                    if let Err(e) = bus.publish(report).await {
+                        tracing::debug!(agent = %self.id, error = %e, "Failed to report progress");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentBuilder, Config};
+    use atlas_core::{Agent as _, EventBus, InMemoryEventBus, TaskId};
+    use atlas_mcp::MCPTool;
+    use futures::StreamExt;
+    use std::sync::Arc;
+
+    struct Slow;
+
+    #[async_trait::async_trait]
+    impl MCPTool for Slow {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn description(&self) -> &str {
+            "Take a while"
+        }
+
+        async fn execute(&self, _params: Metadata) -> anyhow::Result<Metadata> {
+            tokio::time::sleep(Duration::from_millis(35)).await;
+            Ok(Metadata::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_reports() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        bus.register_topic(TASK_PROGRESS).await.unwrap();
+        let mut reports = bus.subscribe(TASK_PROGRESS).await.unwrap();
+        let agent = AgentBuilder::new()
+            .config(Config {
+                name: "worker".to_string(),
+                description: None,
+                capabilities: vec![],
+                config: Metadata::new(),
+            })
+            .tool("slow", Slow)
+            .event_bus(bus)
+            .progress_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+
+        let task_id = TaskId::new();
+        let mut params = Metadata::new();
+        params.insert("tool", "slow");
+        agent.execute_task(task_id, params).await.unwrap();
+        let mut seen = 0;
+        while let Ok(Some(report)) =
+            tokio::time::timeout(Duration::from_millis(10), reports.next()).await
+        {
+            assert_eq!(report.caused_by, Some(*task_id.as_uuid()));
+            assert_eq!(report.payload.get::<String>(AGENT_KEY).unwrap(), "worker");
+            seen += 1;
+        }
+        assert!(seen >= 3, "expected a report every interval, got {}", seen);
+    }
+}
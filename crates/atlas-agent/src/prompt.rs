@@ -0,0 +1,359 @@
+//! Prompt templates for agents
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use atlas_core::Metadata;
+
+use crate::error::Error;
+
+/// Maximum nesting depth for partial expansion
+const MAX_PARTIAL_DEPTH: usize = 16;
+
+/// Template node
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// Literal text
+    Text(String),
+
+    /// Variable placeholder (`{{name}}` or `{{a.b}}`)
+    Variable(String),
+
+    /// Partial inclusion (`{{> name}}`)
+    Partial(String),
+
+    /// Conditional block (`{{#if name}}...{{else}}...{{/if}}`)
+    If {
+        condition: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// Handlebars-style prompt template
+///
+/// Supports `{{variable}}` placeholders with dotted paths, `{{> partial}}`
+/// inclusion, `{{#if variable}}...{{else}}...{{/if}}` blocks and
+/// `{{! comments}}`. Templates are parsed once at compile time so syntax
+/// errors and missing variables are reported before a prompt is sent.
+#[derive(Clone, Debug, Default)]
+pub struct PromptTemplate {
+    /// Parsed template body
+    nodes: Vec<Node>,
+
+    /// Registered partials
+    partials: HashMap<String, Vec<Node>>,
+}
+
+impl PromptTemplate {
+    /// Compile a template from source
+    pub fn compile(source: &str) -> Result<Self> {
+        Ok(Self {
+            nodes: parse(source)?,
+            partials: HashMap::new(),
+        })
+    }
+
+    /// Register a partial that can be included with `{{> name}}`
+    pub fn partial(mut self, name: impl Into<String>, source: &str) -> Result<Self> {
+        self.partials.insert(name.into(), parse(source)?);
+        Ok(self)
+    }
+
+    /// Get the variables that must be present to render this template
+    ///
+    /// Variables referenced only inside conditional blocks are optional and
+    /// are not included.
+    pub fn required_variables(&self) -> Result<BTreeSet<String>> {
+        let mut required = BTreeSet::new();
+        self.collect_required(&self.nodes, &mut required, 0)?;
+        Ok(required)
+    }
+
+    /// Validate that the given context provides every required variable
+    pub fn validate(&self, params: &Metadata, state: &Metadata) -> Result<()> {
+        let missing: Vec<String> = self
+            .required_variables()?
+            .into_iter()
+            .filter(|name| lookup(name, params, state).is_none())
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(Error::TemplateError(format!(
+                "Missing required variables: {}",
+                missing.join(", ")
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Render the template from parameters alone
+    pub fn render(&self, params: &Metadata) -> Result<String> {
+        self.render_with_state(params, &Metadata::new())
+    }
+
+    /// Render the template from parameters and an agent state snapshot
+    ///
+    /// Parameters take precedence over state when both define a variable.
+    pub fn render_with_state(&self, params: &Metadata, state: &Metadata) -> Result<String> {
+        self.validate(params, state)?;
+
+        let mut output = String::new();
+        self.render_nodes(&self.nodes, params, state, &mut output, 0)?;
+        Ok(output)
+    }
+
+    fn collect_required(
+        &self,
+        nodes: &[Node],
+        required: &mut BTreeSet<String>,
+        depth: usize,
+    ) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Variable(name) => {
+                    required.insert(name.clone());
+                }
+                Node::Partial(name) => {
+                    let partial = self.resolve_partial(name, depth)?;
+                    self.collect_required(partial, required, depth + 1)?;
+                }
+                Node::Text(_) | Node::If { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn render_nodes(
+        &self,
+        nodes: &[Node],
+        params: &Metadata,
+        state: &Metadata,
+        output: &mut String,
+        depth: usize,
+    ) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => output.push_str(text),
+                Node::Variable(name) => {
+                    let value = lookup(name, params, state).ok_or_else(|| {
+                        Error::TemplateError(format!("Missing variable: {}", name))
+                    })?;
+                    match value {
+                        Value::String(s) => output.push_str(&s),
+                        other => output.push_str(&other.to_string()),
+                    }
+                }
+                Node::Partial(name) => {
+                    let partial = self.resolve_partial(name, depth)?;
+                    self.render_nodes(partial, params, state, output, depth + 1)?;
+                }
+                Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    let branch = if is_truthy(lookup(condition, params, state).as_ref()) {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    self.render_nodes(branch, params, state, output, depth)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_partial(&self, name: &str, depth: usize) -> Result<&Vec<Node>> {
+        if depth >= MAX_PARTIAL_DEPTH {
+            return Err(Error::TemplateError(format!(
+                "Partial nesting too deep while including: {}",
+                name
+            ))
+            .into());
+        }
+        self.partials
+            .get(name)
+            .ok_or_else(|| Error::TemplateError(format!("Unknown partial: {}", name)).into())
+    }
+}
+
+/// Look up a (possibly dotted) variable in the parameters, then the state
+fn lookup(path: &str, params: &Metadata, state: &Metadata) -> Option<Value> {
+    let root = path.split('.').next()?;
+    let source = if params.get::<Value>(root).is_some() {
+        params
+    } else {
+        state
+    };
+    source.get_path(path)
+}
+
+/// Handlebars truthiness: null, false, 0, and empty strings/collections are false
+fn is_truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(b)) => *b,
+        Some(Value::Number(n)) => n.as_f64().map(|n| n != 0.0).unwrap_or(true),
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(Value::Array(a)) => !a.is_empty(),
+        Some(Value::Object(o)) => !o.is_empty(),
+    }
+}
+
+/// Open block on the parser stack
+struct Block {
+    condition: String,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+/// Parse template source into nodes
+fn parse(source: &str) -> Result<Vec<Node>> {
+    let mut root = Vec::new();
+    let mut stack: Vec<Block> = Vec::new();
+    let mut rest = source;
+
+    fn current<'a>(root: &'a mut Vec<Node>, stack: &'a mut [Block]) -> &'a mut Vec<Node> {
+        match stack.last_mut() {
+            Some(block) => block.otherwise.as_mut().unwrap_or(&mut block.then),
+            None => root,
+        }
+    }
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            current(&mut root, &mut stack).push(Node::Text(rest[..start].to_string()));
+        }
+
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| Error::TemplateError("Unclosed tag: expected '}}'".to_string()))?;
+        let tag = after[..end].trim();
+        rest = &after[end + 2..];
+
+        if tag.starts_with('!') {
+            continue;
+        } else if let Some(name) = tag.strip_prefix('>') {
+            let name = parse_name(name.trim())?;
+            current(&mut root, &mut stack).push(Node::Partial(name));
+        } else if let Some(condition) = tag.strip_prefix("#if") {
+            stack.push(Block {
+                condition: parse_name(condition.trim())?,
+                then: Vec::new(),
+                otherwise: None,
+            });
+        } else if tag == "else" {
+            let block = stack
+                .last_mut()
+                .ok_or_else(|| Error::TemplateError("'else' outside of an if block".to_string()))?;
+            if block.otherwise.is_some() {
+                return Err(Error::TemplateError("Duplicate 'else' in if block".to_string()).into());
+            }
+            block.otherwise = Some(Vec::new());
+        } else if tag == "/if" {
+            let block = stack
+                .pop()
+                .ok_or_else(|| Error::TemplateError("Unmatched '/if'".to_string()))?;
+            current(&mut root, &mut stack).push(Node::If {
+                condition: block.condition,
+                then: block.then,
+                otherwise: block.otherwise.unwrap_or_default(),
+            });
+        } else {
+            current(&mut root, &mut stack).push(Node::Variable(parse_name(tag)?));
+        }
+    }
+
+    if !rest.is_empty() {
+        current(&mut root, &mut stack).push(Node::Text(rest.to_string()));
+    }
+
+    if let Some(block) = stack.pop() {
+        return Err(Error::TemplateError(format!(
+            "Unclosed if block for: {}",
+            block.condition
+        ))
+        .into());
+    }
+
+    Ok(root)
+}
+
+/// Validate a variable or partial name
+fn parse_name(name: &str) -> Result<String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if !valid {
+        return Err(Error::TemplateError(format!("Invalid name in tag: '{}'", name)).into());
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_variables_and_conditionals() {
+        let template = PromptTemplate::compile(
+            "Hello {{user.name}}!{{#if urgent}} This is urgent.{{else}} No rush.{{/if}}",
+        )
+        .unwrap();
+
+        let mut params = Metadata::new();
+        params.insert("user", serde_json::json!({"name": "Ada"}));
+        params.insert("urgent", true);
+
+        assert_eq!(
+            template.render(&params).unwrap(),
+            "Hello Ada! This is urgent."
+        );
+
+        params.insert("urgent", false);
+        assert_eq!(template.render(&params).unwrap(), "Hello Ada! No rush.");
+    }
+
+    #[test]
+    fn test_partials_and_state() {
+        let template = PromptTemplate::compile("{{> header}}Task: {{task}}")
+            .unwrap()
+            .partial("header", "You are {{agent_name}}. ")
+            .unwrap();
+
+        let mut params = Metadata::new();
+        params.insert("task", "summarize");
+        let mut state = Metadata::new();
+        state.insert("agent_name", "atlas");
+
+        let required = template.required_variables().unwrap();
+        assert!(required.contains("agent_name"));
+        assert!(required.contains("task"));
+
+        assert_eq!(
+            template.render_with_state(&params, &state).unwrap(),
+            "You are atlas. Task: summarize"
+        );
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        assert!(PromptTemplate::compile("{{#if open}}never closed").is_err());
+        assert!(PromptTemplate::compile("{{/if}}").is_err());
+        assert!(PromptTemplate::compile("{{bad name}}").is_err());
+
+        let template = PromptTemplate::compile("{{a}} and {{b}}").unwrap();
+        let mut params = Metadata::new();
+        params.insert("a", 1);
+
+        let err = template.render(&params).unwrap_err();
+        assert!(err.to_string().contains("b"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Self-critique of intermediate results
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::llm::{ChatMessage, CompletionRequest, LanguageModel};
+use crate::output_parser::OutputParser;
+use crate::types::TaskConfig;
+
+/// Reflection configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReflectionConfig {
+    /// Criteria the result is judged against
+    pub criteria: Vec<String>,
+
+    /// Maximum number of revisions or retries before the result is accepted as-is
+    pub max_revisions: u32,
+}
+
+impl Default for ReflectionConfig {
+    fn default() -> Self {
+        Self {
+            criteria: vec![
+                "The result fully addresses the task".to_string(),
+                "The result is factually consistent with the observations".to_string(),
+            ],
+            max_revisions: 2,
+        }
+    }
+}
+
+/// Critique decision
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// The result meets the criteria
+    Accept,
+
+    /// The result should be revised using the feedback
+    Revise,
+
+    /// The result should be discarded and the task attempted again
+    Retry,
+}
+
+/// Critique of a result
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Critique {
+    /// Decision
+    pub verdict: Verdict,
+
+    /// Feedback explaining the decision
+    pub feedback: String,
+}
+
+/// Structured reflection record kept in agent memory
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReflectionEntry {
+    /// Attempt number, starting at zero
+    pub attempt: u32,
+
+    /// Result that was critiqued
+    pub result: String,
+
+    /// Critique of the result
+    pub critique: Critique,
+}
+
+/// Evaluates intermediate results
+#[async_trait]
+pub trait Critic: Send + Sync {
+    /// Critique a result produced for a task
+    async fn critique(&self, task: &TaskConfig, result: &str) -> Result<Critique>;
+
+    /// Maximum number of revisions or retries
+    fn max_revisions(&self) -> u32;
+}
+
+/// Critic backed by a language model
+pub struct LlmCritic {
+    /// Language model
+    model: Arc<dyn LanguageModel>,
+
+    /// Reflection configuration
+    config: ReflectionConfig,
+}
+
+impl LlmCritic {
+    /// Create a new critic
+    pub fn new(model: Arc<dyn LanguageModel>, config: ReflectionConfig) -> Self {
+        Self { model, config }
+    }
+}
+
+#[async_trait]
+impl Critic for LlmCritic {
+    async fn critique(&self, task: &TaskConfig, result: &str) -> Result<Critique> {
+        let criteria = self
+            .config
+            .criteria
+            .iter()
+            .map(|c| format!("- {}", c))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let request = CompletionRequest::new(vec![
+            ChatMessage::system(format!(
+                "You review results produced for a task. Judge the result against these criteria:\n{}\n\
+                 Answer \"accept\" if it meets them, \"revise\" if it can be fixed with feedback, \
+                 or \"retry\" if it should be discarded.",
+                criteria
+            )),
+            ChatMessage::user(format!(
+                "Task: {}\n{}\n\nResult:\n{}",
+                task.name,
+                task.description.as_deref().unwrap_or_default(),
+                result
+            )),
+        ])
+        .with_temperature(0.0);
+
+        OutputParser::<Critique>::new()?
+            .complete(self.model.as_ref(), request)
+            .await
+    }
+
+    fn max_revisions(&self) -> u32 {
+        self.config.max_revisions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionResponse;
+
+    struct StrictModel;
+
+    #[async_trait]
+    impl LanguageModel for StrictModel {
+        fn name(&self) -> &str {
+            "strict"
+        }
+
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+            let content = if request.messages.last().unwrap().content.contains("draft") {
+                r#"{"verdict": "revise", "feedback": "Add a conclusion"}"#
+            } else {
+                r#"{"verdict": "accept", "feedback": "Looks good"}"#
+            };
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant(content),
+                model: "strict".to_string(),
+                usage: Default::default(),
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_llm_critic() {
+        let critic = LlmCritic::new(Arc::new(StrictModel), ReflectionConfig::default());
+        let task = TaskConfig::default();
+
+        let critique = critic.critique(&task, "a draft").await.unwrap();
+        assert_eq!(critique.verdict, Verdict::Revise);
+        assert_eq!(critique.feedback, "Add a conclusion");
+
+        let critique = critic.critique(&task, "final text").await.unwrap();
+        assert_eq!(critique.verdict, Verdict::Accept);
+        assert_eq!(critic.max_revisions(), 2);
+    }
+}
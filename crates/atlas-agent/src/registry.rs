@@ -0,0 +1,617 @@
+//! Discovering agents and the capabilities they provide
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use atlas_core::{AgentId, Event, EventBus, HealthLevel, HealthStatus, Metadata};
+
+use crate::error::Error;
+use crate::heartbeat::{HeartbeatConfig, Liveness, AGENT_HEARTBEAT, HEALTH_KEY, ID_KEY};
+use crate::lifecycle::LifecycleState;
+use crate::{Agent, AgentDescriptor, AgentRef};
+
+/// Topic on which registries share registrations between processes
+pub const REGISTRY_TOPIC: &str = "agent.registry";
+
+/// Change to a registry, shared with the registries on the same bus
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Announcement {
+    /// An agent was registered or its descriptor changed
+    Register { descriptor: Box<AgentDescriptor> },
+
+    /// An agent was removed
+    Deregister { id: AgentId },
+
+    /// A registry joined and asks the others for their agents
+    Query,
+}
+
+/// Registered agents, and which of them were registered in this process
+#[derive(Debug, Default)]
+struct Entries {
+    /// Descriptors by agent ID
+    descriptors: HashMap<AgentId, AgentDescriptor>,
+
+    /// Agents registered through this registry rather than announced
+    local: HashSet<AgentId>,
+
+    /// When each agent was last registered or beat
+    seen: HashMap<AgentId, Instant>,
+}
+
+impl Entries {
+    /// Record a descriptor, as just seen
+    fn insert(&mut self, descriptor: AgentDescriptor) {
+        self.seen.insert(descriptor.id, Instant::now());
+        self.descriptors.insert(descriptor.id, descriptor);
+    }
+
+    /// Forget an agent
+    fn remove(&mut self, id: &AgentId) -> Option<AgentDescriptor> {
+        self.local.remove(id);
+        self.seen.remove(id);
+        self.descriptors.remove(id)
+    }
+}
+
+/// Directory of agents and what they can do
+///
+/// Agents register their [`AgentDescriptor`] so other agents and routers can
+/// find who provides a capability at runtime. With an event bus, a registry
+/// announces its registrations on [`REGISTRY_TOPIC`] and, once
+/// [`AgentRegistry::sync`] runs, learns those of every other registry on the
+/// bus, including the ones made before it joined.
+///
+/// A syncing registry also watches [`AGENT_HEARTBEAT`] for the beats of its
+/// agents (see [`Agent::spawn_heartbeat`]), keeping the health in their
+/// descriptors as last reported. With heartbeats configured, agents that
+/// miss beats go stale and are left out of lookups, so routers stop picking
+/// them, and dead ones are forgotten until they register again.
+///
+/// With a time to live, agents announced by other registries are forgotten
+/// once they are neither announced again nor beat for that long, so the
+/// agents of a process that died do not linger. Syncing registries announce
+/// their own agents again often enough to keep them.
+pub struct AgentRegistry {
+    /// Registered agents
+    entries: Mutex<Entries>,
+
+    /// Bus registrations are shared on, if any
+    event_bus: Option<Arc<dyn EventBus>>,
+
+    /// ID of this registry, to skip its own announcements
+    origin: Uuid,
+
+    /// How agents are judged by their heartbeats, if they are
+    heartbeats: Option<HeartbeatConfig>,
+
+    /// How long announced agents are kept without news of them, if limited
+    ttl: Option<Duration>,
+}
+
+impl Default for AgentRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AgentRegistry {
+    /// Create a registry local to this process
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::default(),
+            event_bus: None,
+            origin: Uuid::new_v4(),
+            heartbeats: None,
+            ttl: None,
+        }
+    }
+
+    /// Share registrations with the registries on an event bus
+    pub fn with_event_bus(mut self, bus: Arc<dyn EventBus>) -> Self {
+        self.event_bus = Some(bus);
+        self
+    }
+
+    /// Judge agents by their heartbeats, which [`AgentRegistry::sync`]
+    /// watches for
+    pub fn with_heartbeats(mut self, config: HeartbeatConfig) -> Self {
+        self.heartbeats = Some(config);
+        self
+    }
+
+    /// Forget announced agents not announced again, or beating, within a
+    /// time to live, and announce local agents again often enough to keep
+    /// them on the other registries
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Register an agent's descriptor, replacing the one registered before
+    pub async fn register(&self, descriptor: AgentDescriptor) -> Result<()> {
+        {
+            let mut entries = self.lock();
+            entries.local.insert(descriptor.id);
+            entries.insert(descriptor.clone());
+        }
+        tracing::debug!(agent = %descriptor.name, id = %descriptor.id, "Agent registered");
+        self.announce(Announcement::Register {
+            descriptor: Box::new(descriptor),
+        })
+        .await
+    }
+
+    /// Register an agent as it currently describes itself
+    pub async fn register_agent(&self, agent: &Agent) -> Result<()> {
+        self.register(agent.describe().await?).await
+    }
+
+    /// Remove an agent, returning its descriptor if it was registered
+    pub async fn deregister(&self, id: AgentId) -> Result<Option<AgentDescriptor>> {
+        let removed = self.lock().remove(&id);
+        if removed.is_some() {
+            self.announce(Announcement::Deregister { id }).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Get the descriptor of an agent, stale or not
+    pub fn get(&self, id: AgentId) -> Option<AgentDescriptor> {
+        self.lock().descriptors.get(&id).cloned()
+    }
+
+    /// Judge an agent by its heartbeats, if it is registered; agents are
+    /// alive when the registry does not watch heartbeats
+    pub fn liveness(&self, id: AgentId) -> Option<Liveness> {
+        let entries = self.lock();
+        entries
+            .descriptors
+            .contains_key(&id)
+            .then(|| self.judge(&entries, &id))
+    }
+
+    /// Find the agents registered under a name
+    pub fn find(&self, name: &str) -> Vec<AgentDescriptor> {
+        self.matching(|descriptor| descriptor.name == name)
+    }
+
+    /// Find the agents declaring a capability
+    pub fn providers(&self, capability: &str) -> Vec<AgentDescriptor> {
+        self.matching(|descriptor| descriptor.capabilities.iter().any(|c| c == capability))
+    }
+
+    /// Find the agents having a tool
+    pub fn with_tool(&self, tool: &str) -> Vec<AgentDescriptor> {
+        self.matching(|descriptor| descriptor.tools.iter().any(|t| t.name == tool))
+    }
+
+    /// Get every registered agent that is not stale, sorted by name
+    pub fn list(&self) -> Vec<AgentDescriptor> {
+        self.matching(|_| true)
+    }
+
+    /// Find the agents tasks can be routed to that declare every capability
+    /// and have every tool, leaving out stopping, stopped and unhealthy
+    /// ones
+    pub fn routable(&self, capabilities: &[String], tools: &[String]) -> Vec<AgentDescriptor> {
+        self.matching(|descriptor| {
+            !matches!(
+                descriptor.lifecycle,
+                LifecycleState::Stopping | LifecycleState::Stopped
+            ) && descriptor.health.level != HealthLevel::Unhealthy
+                && capabilities
+                    .iter()
+                    .all(|capability| descriptor.capabilities.contains(capability))
+                && tools
+                    .iter()
+                    .all(|tool| descriptor.tools.iter().any(|t| t.name == *tool))
+        })
+    }
+
+    /// Get a reference to message a registered agent over the bus
+    pub fn agent_ref(&self, id: AgentId) -> Result<AgentRef> {
+        let descriptor = self.get(id).ok_or_else(|| {
+            atlas_core::Error::Unavailable(format!("No agent is registered with ID {}", id))
+        })?;
+        self.reference(&descriptor)
+    }
+
+    /// Get a reference to message the agent registered under a name
+    pub fn resolve(&self, name: &str) -> Result<AgentRef> {
+        let descriptor = self.find(name).into_iter().next().ok_or_else(|| {
+            atlas_core::Error::Unavailable(format!("No agent is registered as {}", name))
+        })?;
+        self.reference(&descriptor)
+    }
+
+    /// Get a reference to message a routable agent declaring a capability
+    pub fn resolve_provider(&self, capability: &str) -> Result<AgentRef> {
+        let descriptor = self
+            .routable(&[capability.to_string()], &[])
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                atlas_core::Error::Unavailable(format!("No agent provides {}", capability))
+            })?;
+        self.reference(&descriptor)
+    }
+
+    /// Learn the registrations announced by the other registries on the
+    /// bus, in the background, and ask them for the ones made earlier
+    ///
+    /// The beats of registered agents are watched too, and with a time to
+    /// live, local agents are announced again periodically.
+    /// Syncing stops when the registry is dropped or the bus closes.
+    pub async fn sync(self: &Arc<Self>) -> Result<JoinHandle<()>> {
+        let bus = self.event_bus()?;
+        bus.register_topic(REGISTRY_TOPIC).await?;
+        bus.register_topic(AGENT_HEARTBEAT).await?;
+        let events = bus.subscribe(REGISTRY_TOPIC).await?;
+        let mut events =
+            futures::stream::select(events, bus.subscribe(AGENT_HEARTBEAT).await?).boxed();
+        self.announce(Announcement::Query).await?;
+
+        let registry = Arc::downgrade(self);
+        let period = self.ttl.map(|ttl| (ttl / 3).max(Duration::from_millis(1)));
+        Ok(tokio::spawn(async move {
+            let mut renewals = period.map(tokio::time::interval);
+            loop {
+                let event = match renewals.as_mut() {
+                    Some(renewals) => tokio::select! {
+                        event = events.next() => event,
+                        _ = renewals.tick() => {
+                            let Some(registry) = registry.upgrade() else {
+                                break;
+                            };
+                            if let Err(e) = registry.renew().await {
+                                tracing::warn!(error = %e, "Failed to announce registrations again");
+                            }
+                            continue;
+                        }
+                    },
+                    None => events.next().await,
+                };
+                let Some(event) = event else {
+                    break;
+                };
+                let Some(registry) = registry.upgrade() else {
+                    break;
+                };
+                if let Err(e) = registry.apply(event).await {
+                    tracing::warn!(error = %e, "Ignoring invalid registry announcement");
+                }
+            }
+        }))
+    }
+
+    /// Apply an announcement from another registry, or a heartbeat
+    async fn apply(&self, event: Event) -> Result<()> {
+        if event.event_type.as_str() == AGENT_HEARTBEAT {
+            self.beat(&event.payload)?;
+            return Ok(());
+        }
+        if event.payload.try_get::<Uuid>("origin")? == self.origin {
+            return Ok(());
+        }
+        match event.payload.try_get::<Announcement>("announcement")? {
+            Announcement::Register { descriptor } => {
+                let mut entries = self.lock();
+                if !entries.local.contains(&descriptor.id) {
+                    entries.insert(*descriptor);
+                }
+            }
+            Announcement::Deregister { id } => {
+                let mut entries = self.lock();
+                if !entries.local.contains(&id) {
+                    entries.remove(&id);
+                }
+            }
+            Announcement::Query => self.renew().await?,
+        }
+        Ok(())
+    }
+
+    /// Announce the agents registered through this registry again
+    async fn renew(&self) -> Result<()> {
+        let local: Vec<AgentDescriptor> = {
+            let entries = self.lock();
+            entries
+                .local
+                .iter()
+                .filter_map(|id| entries.descriptors.get(id).cloned())
+                .collect()
+        };
+        for descriptor in local {
+            self.announce(Announcement::Register {
+                descriptor: Box::new(descriptor),
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Note that a registered agent beat, taking the health it reported;
+    /// beats of agents not beating under their ID, such as hosted ones, are
+    /// ignored
+    fn beat(&self, payload: &Metadata) -> Result<()> {
+        let Some(id) = payload.get::<AgentId>(ID_KEY) else {
+            return Ok(());
+        };
+        let health: Option<HealthStatus> = payload.get(HEALTH_KEY);
+        let mut entries = self.lock();
+        let Some(descriptor) = entries.descriptors.get_mut(&id) else {
+            return Ok(());
+        };
+        if let Some(health) = health {
+            descriptor.health = health;
+        }
+        entries.seen.insert(id, Instant::now());
+        Ok(())
+    }
+
+    /// Judge a registered agent by the time since it was last seen; announced
+    /// agents outliving the time to live are dead
+    fn judge(&self, entries: &Entries, id: &AgentId) -> Liveness {
+        let Some(seen) = entries.seen.get(id) else {
+            return Liveness::Alive;
+        };
+        let expired = matches!(self.ttl, Some(ttl) if seen.elapsed() > ttl);
+        if expired && !entries.local.contains(id) {
+            return Liveness::Dead;
+        }
+        match &self.heartbeats {
+            Some(config) => config.liveness(seen.elapsed()),
+            None => Liveness::Alive,
+        }
+    }
+
+    /// Share a change with the other registries, if there is a bus
+    async fn announce(&self, announcement: Announcement) -> Result<()> {
+        let Some(bus) = &self.event_bus else {
+            return Ok(());
+        };
+        let mut payload = Metadata::new();
+        payload.insert("origin", self.origin);
+        payload.try_insert("announcement", &announcement)?;
+        bus.register_topic(REGISTRY_TOPIC).await?;
+        bus.publish(Event::new(REGISTRY_TOPIC, payload)).await
+    }
+
+    /// Refer to a registered agent over the bus
+    fn reference(&self, descriptor: &AgentDescriptor) -> Result<AgentRef> {
+        Ok(AgentRef::from_descriptor(
+            descriptor,
+            self.event_bus()?.clone(),
+        ))
+    }
+
+    /// Get the bus registrations are shared on
+    fn event_bus(&self) -> Result<&Arc<dyn EventBus>> {
+        self.event_bus
+            .as_ref()
+            .ok_or_else(|| Error::InvalidConfig("No event bus configured".to_string()).into())
+    }
+
+    /// Get the live agents matching a condition, sorted by name, forgetting
+    /// the dead ones
+    fn matching(&self, condition: impl Fn(&AgentDescriptor) -> bool) -> Vec<AgentDescriptor> {
+        let mut entries = self.lock();
+        let dead: Vec<AgentId> = entries
+            .descriptors
+            .keys()
+            .filter(|id| self.judge(&entries, id) == Liveness::Dead)
+            .copied()
+            .collect();
+        for id in dead {
+            if let Some(descriptor) = entries.remove(&id) {
+                tracing::warn!(agent = %descriptor.name, %id, "Agent went silent, forgetting it");
+            }
+        }
+        let mut found: Vec<AgentDescriptor> = entries
+            .descriptors
+            .values()
+            .filter(|descriptor| self.judge(&entries, &descriptor.id) == Liveness::Alive)
+            .filter(|descriptor| condition(descriptor))
+            .cloned()
+            .collect();
+        found.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| a.id.as_uuid().cmp(b.id.as_uuid()))
+        });
+        found
+    }
+
+    /// Lock the registered agents
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentBuilder, Config};
+    use atlas_core::InMemoryEventBus;
+    use std::time::Duration;
+
+    async fn descriptor(name: &str, capabilities: &[&str]) -> AgentDescriptor {
+        AgentBuilder::new()
+            .config(Config {
+                name: name.to_string(),
+                description: None,
+                capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+                config: Metadata::new(),
+            })
+            .build()
+            .unwrap()
+            .describe()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_local_discovery() {
+        let registry = AgentRegistry::new();
+        let researcher = descriptor("researcher", &["search", "summarize"]).await;
+        let writer = descriptor("writer", &["summarize"]).await;
+        registry.register(writer.clone()).await.unwrap();
+        registry.register(researcher.clone()).await.unwrap();
+
+        let names = |found: Vec<AgentDescriptor>| -> Vec<String> {
+            found
+                .into_iter()
+                .map(|descriptor| descriptor.name)
+                .collect()
+        };
+        assert_eq!(
+            names(registry.providers("summarize")),
+            ["researcher", "writer"]
+        );
+        assert_eq!(names(registry.providers("search")), ["researcher"]);
+        assert_eq!(registry.find("writer")[0].id, writer.id);
+
+        assert!(registry.deregister(researcher.id).await.unwrap().is_some());
+        assert!(registry.get(researcher.id).is_none());
+        assert!(registry.providers("search").is_empty());
+        assert!(Arc::new(registry).sync().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_distributed_discovery() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        let first = Arc::new(AgentRegistry::new().with_event_bus(bus.clone()));
+        first.sync().await.unwrap();
+        let researcher = descriptor("researcher", &["search"]).await;
+        first.register(researcher.clone()).await.unwrap();
+
+        // A registry joining later learns the earlier registrations
+        let second = Arc::new(AgentRegistry::new().with_event_bus(bus.clone()));
+        second.sync().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(second.providers("search")[0].id, researcher.id);
+
+        let writer = descriptor("writer", &["write"]).await;
+        second.register(writer.clone()).await.unwrap();
+        assert_eq!(second.resolve_provider("write").unwrap().id(), writer.id);
+        assert!(second.resolve("editor").is_err());
+        first.deregister(researcher.id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(first.providers("write")[0].id, writer.id);
+        assert!(second.providers("search").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        let config = HeartbeatConfig {
+            interval_ms: 10,
+            stale_after: 3,
+            dead_after: 8,
+        };
+        let registry = Arc::new(
+            AgentRegistry::new()
+                .with_event_bus(bus.clone())
+                .with_heartbeats(config.clone()),
+        );
+        registry.sync().await.unwrap();
+        let agent = Arc::new(
+            AgentBuilder::new()
+                .config(Config {
+                    name: "researcher".to_string(),
+                    description: None,
+                    capabilities: vec!["search".to_string()],
+                    config: Metadata::new(),
+                })
+                .event_bus(bus.clone())
+                .build()
+                .unwrap(),
+        );
+        registry.register_agent(&agent).await.unwrap();
+        let heartbeat = agent.spawn_heartbeat(config).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(registry.liveness(agent.id), Some(Liveness::Alive));
+        assert_eq!(registry.providers("search").len(), 1);
+
+        // A silent agent is no longer found, then forgotten
+        heartbeat.abort();
+        tokio::time::sleep(Duration::from_millis(45)).await;
+        assert_eq!(registry.liveness(agent.id), Some(Liveness::Stale));
+        assert!(registry.providers("search").is_empty());
+        assert!(registry.get(agent.id).is_some());
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(registry.list().is_empty());
+        assert!(registry.get(agent.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ttl() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        let ttl = Duration::from_millis(60);
+        let first = Arc::new(
+            AgentRegistry::new()
+                .with_event_bus(bus.clone())
+                .with_ttl(ttl),
+        );
+        let sync = first.sync().await.unwrap();
+        let second = Arc::new(
+            AgentRegistry::new()
+                .with_event_bus(bus.clone())
+                .with_ttl(ttl),
+        );
+        second.sync().await.unwrap();
+        let researcher = descriptor("researcher", &["search"]).await;
+        first.register(researcher.clone()).await.unwrap();
+
+        // Renewed announcements keep the agent past its time to live
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(second.providers("search")[0].id, researcher.id);
+
+        // Once its registry stops announcing it, the agent expires
+        sync.abort();
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(second.providers("search").is_empty());
+        assert!(second.get(researcher.id).is_none());
+        assert_eq!(first.providers("search")[0].id, researcher.id);
+    }
+
+    #[tokio::test]
+    async fn test_routable() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        let registry = Arc::new(AgentRegistry::new().with_event_bus(bus.clone()));
+        registry.sync().await.unwrap();
+        let researcher = descriptor("researcher", &["search"]).await;
+        registry.register(researcher.clone()).await.unwrap();
+        let search = ["search".to_string()];
+        assert_eq!(registry.routable(&search, &[]).len(), 1);
+        assert!(registry
+            .routable(&search, &["fetch".to_string()])
+            .is_empty());
+
+        // The health an agent beats with replaces the one it registered with
+        let mut payload = Metadata::new();
+        payload.insert(ID_KEY, researcher.id);
+        payload.insert(
+            HEALTH_KEY,
+            HealthStatus::healthy().with_issue(HealthLevel::Unhealthy, "model is down"),
+        );
+        bus.publish(Event::new(AGENT_HEARTBEAT, payload))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let health = registry.get(researcher.id).unwrap().health;
+        assert_eq!(health.level, HealthLevel::Unhealthy);
+        assert!(registry.routable(&search, &[]).is_empty());
+        assert!(registry.resolve_provider("search").is_err());
+        assert_eq!(registry.providers("search").len(), 1);
+    }
+}
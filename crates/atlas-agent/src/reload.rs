@@ -0,0 +1,238 @@
+//! Applying configuration changes to a running agent
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use tokio::task::JoinHandle;
+
+use atlas_core::{AgentConfig, Event, EventType, Metadata, MetadataError};
+
+use crate::error::Error;
+use crate::{Agent, Config};
+
+/// Topic on which an agent announces that its configuration changed
+pub const CONFIG_RELOADED: &str = "config.reloaded";
+
+/// Key of [`Config::config`] holding the options of each tool, by tool name
+pub const TOOL_OPTIONS_KEY: &str = "tools";
+
+impl Agent {
+    /// Replace the configuration of the running agent
+    ///
+    /// The new configuration is validated in full before anything changes,
+    /// so an invalid update leaves the agent as it was. The agent's name
+    /// cannot change, and the options under [`TOOL_OPTIONS_KEY`] must name
+    /// registered tools. Once applied, a [`CONFIG_RELOADED`] event listing
+    /// the changes is published if the agent has an event bus.
+    pub async fn reload_config(&self, config: Config) -> Result<()> {
+        config.validate()?;
+        let current = self.config();
+        if config.name != current.name {
+            return Err(Error::InvalidConfig(format!(
+                "Cannot rename agent {} to {} while it runs",
+                current.name, config.name
+            ))
+            .into());
+        }
+        let options = tool_options(&config)
+            .map_err(|e| Error::InvalidConfig(format!("Invalid tool options: {}", e)))?;
+
+        {
+            let mut tools = self.tools.write().await;
+            let mut updated = Vec::with_capacity(options.len());
+            for (name, options) in options {
+                let mut tool = tools
+                    .get_config(&name)
+                    .cloned()
+                    .ok_or_else(|| Error::ToolNotFound(name.clone()))?;
+                tool.config = options;
+                updated.push((name, tool));
+            }
+            for (name, tool) in updated {
+                tools.update_config(&name, tool)?;
+            }
+            *self
+                .config
+                .write()
+                .unwrap_or_else(std::sync::PoisonError::into_inner) = Arc::new(config);
+        }
+
+        let changes = crate::diff::diff(
+            &Metadata::from_serialize(current.as_ref())?,
+            &Metadata::from_serialize(self.config().as_ref())?,
+        );
+        tracing::info!(agent = %current.name, "Agent configuration reloaded");
+        if let Some(bus) = &self.event_bus {
+            let mut payload = Metadata::new();
+            payload.insert("agent", current.name.clone());
+            payload.try_insert("changes", &changes)?;
+            bus.register_topic(CONFIG_RELOADED).await?;
+            self.publish(Event::new(EventType::ConfigReloaded, payload)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Options of each tool named in a configuration
+pub(crate) fn tool_options(
+    config: &Config,
+) -> std::result::Result<HashMap<String, Metadata>, MetadataError> {
+    if !config.config.contains_key(TOOL_OPTIONS_KEY) {
+        return Ok(HashMap::new());
+    }
+    config.config.try_get(TOOL_OPTIONS_KEY)
+}
+
+/// Watcher reloading an agent's configuration whenever its file changes
+///
+/// The file is polled for changes to its modification time. Each change is
+/// loaded with [`Config::from_file`] and applied with
+/// [`Agent::reload_config`]; files that fail to load or apply are logged and
+/// the agent keeps its running configuration.
+#[derive(Clone, Debug)]
+pub struct ConfigWatcher {
+    /// Configuration file
+    path: PathBuf,
+
+    /// How often the file is checked
+    interval: Duration,
+}
+
+impl ConfigWatcher {
+    /// Create a watcher for a configuration file
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            interval: Duration::from_secs(2),
+        }
+    }
+
+    /// Set how often the file is checked
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Watch the file in the background until the agent is dropped or the
+    /// task is aborted
+    pub fn spawn(self, agent: &Arc<Agent>) -> JoinHandle<()> {
+        let agent = Arc::downgrade(agent);
+        tokio::spawn(async move { self.run(agent).await })
+    }
+
+    /// Poll the file, applying each change
+    async fn run(self, agent: Weak<Agent>) {
+        let mut modified = self.modified();
+        let mut ticks = tokio::time::interval(self.interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let Some(agent) = agent.upgrade() else {
+                return;
+            };
+            let current = self.modified();
+            if current.is_none() || current == modified {
+                continue;
+            }
+            modified = current;
+            let result = async {
+                let config = Config::from_file(&self.path)?;
+                agent.reload_config(config).await
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::warn!(
+                    path = %self.path.display(),
+                    error = %e,
+                    "Rejected configuration change"
+                );
+            }
+        }
+    }
+
+    /// Modification time of the file, if it can be read
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AgentBuilder;
+    use atlas_core::{EventBus, InMemoryEventBus};
+    use atlas_mcp::MCPTool;
+    use futures::StreamExt;
+    use serde_json::json;
+
+    struct Search;
+
+    #[async_trait::async_trait]
+    impl MCPTool for Search {
+        fn name(&self) -> &str {
+            "search"
+        }
+
+        fn description(&self) -> &str {
+            "Search the web"
+        }
+
+        async fn execute(&self, _params: Metadata) -> Result<Metadata> {
+            Ok(Metadata::new())
+        }
+    }
+
+    fn config(capabilities: &[&str], tools: serde_json::Value) -> Config {
+        let mut config = Metadata::new();
+        config.insert("max_steps", 5);
+        config.insert(TOOL_OPTIONS_KEY, tools);
+        Config {
+            name: "reloader".to_string(),
+            description: None,
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            config,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_config() {
+        let bus = Arc::new(InMemoryEventBus::new());
+        let agent = AgentBuilder::new()
+            .config(config(&["search"], json!({})))
+            .tool("search", Search)
+            .event_bus(bus.clone())
+            .build()
+            .unwrap();
+        bus.register_topic(CONFIG_RELOADED).await.unwrap();
+        let mut events = bus.subscribe(CONFIG_RELOADED).await.unwrap();
+
+        let update = config(&["search", "summarize"], json!({"search": {"limit": 3}}));
+        agent.reload_config(update).await.unwrap();
+        assert_eq!(agent.config().capabilities, ["search", "summarize"]);
+        let tools = agent.tools.read().await;
+        let options = &tools.get_config("search").unwrap().config;
+        assert_eq!(options.get::<u32>("limit"), Some(3));
+        drop(tools);
+        let event = events.next().await.unwrap();
+        let changes: crate::StateDiff = event.payload.get("changes").unwrap();
+        assert!(changes.changed.contains_key("capabilities"));
+
+        // Invalid updates change nothing
+        for invalid in [
+            config(&[], json!({"browser": {}})),
+            config(&[], json!({"search": "fast"})),
+            Config {
+                name: "renamed".to_string(),
+                ..config(&[], json!({}))
+            },
+        ] {
+            assert!(agent.reload_config(invalid).await.is_err());
+        }
+        assert_eq!(agent.config().capabilities.len(), 2);
+    }
+}
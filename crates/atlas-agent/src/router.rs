@@ -0,0 +1,466 @@
+//! Routing tasks to the registered agents best able to do them
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use atlas_core::{
+    AgentId, CancellationContext, DynAgent, Event, HealthLevel, HealthStatus, Metadata, TaskId,
+};
+
+use crate::error::Error;
+use crate::messaging::ExecuteTask;
+use crate::types::TaskConstraints;
+use crate::{AgentDescriptor, AgentRegistry};
+
+/// Task parameter holding the [`TaskConstraints`] a delegate must meet
+pub const CONSTRAINTS_KEY: &str = "constraints";
+
+/// Task parameter holding the capabilities a delegate must declare
+pub const CAPABILITIES_KEY: &str = "capabilities";
+
+/// How a router weighs the delegates able to take a task
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RouterConfig {
+    /// Penalty for each task a delegate is running for the router
+    pub load_weight: f64,
+
+    /// Penalty for a degraded delegate, and for each task it failed in a
+    /// row
+    pub health_weight: f64,
+
+    /// How long to wait for a delegate's result, in milliseconds
+    pub timeout_ms: u64,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            load_weight: 1.0,
+            health_weight: 2.0,
+            timeout_ms: 300_000,
+        }
+    }
+}
+
+/// What a router knows of a delegate from the tasks it sent it
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DelegateStats {
+    /// Tasks running on the delegate
+    pub in_flight: usize,
+
+    /// Tasks sent to the delegate
+    pub dispatched: u64,
+
+    /// Tasks the delegate failed since it last succeeded
+    pub failures: u32,
+}
+
+/// Chooser of the delegate for each task by load and health, keeping what
+/// it learns of the delegates from the tasks it sends them
+///
+/// Unhealthy candidates are left out. Of the others, the one with the
+/// least weighted load and ill health is chosen; ties go to the candidate
+/// sent the fewest tasks, then to the first, so equal delegates take turns.
+#[derive(Debug)]
+pub struct Balancer<K> {
+    /// How delegates are weighed
+    config: RouterConfig,
+
+    /// What is known of each delegate
+    stats: Mutex<HashMap<K, DelegateStats>>,
+}
+
+impl<K: Clone + Eq + Hash> Balancer<K> {
+    /// Create a balancer knowing nothing of its delegates yet
+    pub fn new(config: RouterConfig) -> Self {
+        Self {
+            config,
+            stats: Mutex::default(),
+        }
+    }
+
+    /// Get how delegates are weighed
+    pub fn config(&self) -> &RouterConfig {
+        &self.config
+    }
+
+    /// Get what is known of a delegate
+    pub fn stats(&self, key: &K) -> DelegateStats {
+        self.lock().get(key).cloned().unwrap_or_default()
+    }
+
+    /// Choose between candidates, given with their health
+    pub fn choose<T>(
+        &self,
+        candidates: impl IntoIterator<Item = (K, HealthLevel, T)>,
+    ) -> Option<(K, T)> {
+        let stats = self.lock();
+        candidates
+            .into_iter()
+            .filter(|(_, health, _)| *health != HealthLevel::Unhealthy)
+            .map(|(key, health, candidate)| {
+                let known = stats.get(&key).cloned().unwrap_or_default();
+                let degraded = f64::from(u8::from(health == HealthLevel::Degraded));
+                let penalty = self.config.load_weight * known.in_flight as f64
+                    + self.config.health_weight * (degraded + f64::from(known.failures));
+                (penalty, known.dispatched, key, candidate)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+            .map(|(_, _, key, candidate)| (key, candidate))
+    }
+
+    /// Count a task sent to a delegate until the returned dispatch is
+    /// finished or dropped
+    pub fn dispatch(&self, key: K) -> Dispatch<'_, K> {
+        let mut stats = self.lock();
+        let known = stats.entry(key.clone()).or_default();
+        known.in_flight += 1;
+        known.dispatched += 1;
+        Dispatch {
+            balancer: self,
+            key,
+        }
+    }
+
+    /// Lock the delegate statistics
+    fn lock(&self) -> MutexGuard<'_, HashMap<K, DelegateStats>> {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Task running on a delegate, counted until it finishes or is abandoned
+pub struct Dispatch<'a, K: Clone + Eq + Hash> {
+    /// Balancer that chose the delegate
+    balancer: &'a Balancer<K>,
+
+    /// Delegate running the task
+    key: K,
+}
+
+impl<K: Clone + Eq + Hash> Dispatch<'_, K> {
+    /// Note how the task ended, counting the delegate's failures in a row
+    pub fn finish<T>(self, result: &Result<T>) {
+        if let Some(known) = self.balancer.lock().get_mut(&self.key) {
+            match result {
+                Ok(_) => known.failures = 0,
+                Err(_) => known.failures += 1,
+            }
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash> Drop for Dispatch<'_, K> {
+    fn drop(&mut self) {
+        if let Some(known) = self.balancer.lock().get_mut(&self.key) {
+            known.in_flight -= 1;
+        }
+    }
+}
+
+/// Agent delegating each task it receives to the best registered agent
+///
+/// Candidates are the [`routable`](AgentRegistry::routable) agents in the
+/// [`AgentRegistry`] having every tool in the task's [`CONSTRAINTS_KEY`]
+/// `required_tools` and declaring every capability listed under
+/// [`CAPABILITIES_KEY`]. A [`Balancer`] weighs them by the health they last
+/// reported and the tasks the router sent them, and the one chosen is sent
+/// the task as an [`ExecuteTask`] message, so delegates must
+/// [`accept_tasks`](crate::Agent::accept_tasks).
+pub struct RouterAgent {
+    /// ID of the router, never routed to itself
+    id: AgentId,
+
+    /// Agents tasks are routed to
+    registry: Arc<AgentRegistry>,
+
+    /// Chooser of the delegates
+    balancer: Balancer<AgentId>,
+}
+
+impl RouterAgent {
+    /// Create a router delegating to the agents of a registry, which needs
+    /// an event bus to reach them
+    pub fn new(registry: Arc<AgentRegistry>) -> Self {
+        Self {
+            id: AgentId::new(),
+            registry,
+            balancer: Balancer::new(RouterConfig::default()),
+        }
+    }
+
+    /// Weigh delegates with a configuration
+    pub fn with_config(mut self, config: RouterConfig) -> Self {
+        self.balancer = Balancer::new(config);
+        self
+    }
+
+    /// Use an ID, such as the one the router is registered under
+    pub fn with_id(mut self, id: AgentId) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Get the ID of the router
+    pub fn id(&self) -> AgentId {
+        self.id
+    }
+
+    /// Get what the router knows of a delegate
+    pub fn stats(&self, id: AgentId) -> DelegateStats {
+        self.balancer.stats(&id)
+    }
+
+    /// Choose the delegate for a task
+    pub fn select(&self, params: &Metadata) -> Result<AgentDescriptor> {
+        let constraints: TaskConstraints = optional(params, CONSTRAINTS_KEY)?;
+        let capabilities: Vec<String> = optional(params, CAPABILITIES_KEY)?;
+
+        let candidates = self
+            .registry
+            .routable(&capabilities, &constraints.required_tools)
+            .into_iter()
+            .filter(|descriptor| descriptor.id != self.id)
+            .map(|descriptor| (descriptor.id, descriptor.health.level, descriptor));
+        self.balancer
+            .choose(candidates)
+            .map(|(_, descriptor)| descriptor)
+            .ok_or_else(|| {
+                atlas_core::Error::Unavailable(format!(
+                    "No agent has tools {:?} and capabilities {:?}",
+                    constraints.required_tools, capabilities
+                ))
+                .into()
+            })
+    }
+
+    /// Run a task on the best delegate, returning its result
+    pub async fn route(&self, task_id: TaskId, params: Metadata) -> Result<Metadata> {
+        let delegate = self.select(&params)?;
+        let agent = self
+            .registry
+            .agent_ref(delegate.id)?
+            .with_sender(self.id)
+            .with_timeout(Duration::from_millis(self.balancer.config().timeout_ms));
+        tracing::debug!(
+            task_id = %task_id,
+            delegate = %delegate.name,
+            id = %delegate.id,
+            "Routing task"
+        );
+
+        let dispatch = self.balancer.dispatch(delegate.id);
+        let result = agent.ask(ExecuteTask { task_id, params }).await;
+        dispatch.finish(&result);
+        result
+    }
+}
+
+impl std::fmt::Debug for RouterAgent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RouterAgent")
+            .field("id", &self.id)
+            .field("config", self.balancer.config())
+            .finish()
+    }
+}
+
+/// Read a task parameter, defaulting when it is missing
+fn optional<T: DeserializeOwned + Default>(params: &Metadata, key: &str) -> Result<T> {
+    if !params.contains_key(key) {
+        return Ok(T::default());
+    }
+    params
+        .try_get(key)
+        .map_err(|e| Error::InvalidRequest(format!("Invalid task {}: {}", key, e)).into())
+}
+
+#[async_trait]
+impl DynAgent for RouterAgent {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    async fn snapshot(&self) -> Result<Metadata> {
+        let stats: HashMap<String, DelegateStats> = self
+            .balancer
+            .lock()
+            .iter()
+            .map(|(id, stats)| (id.to_string(), stats.clone()))
+            .collect();
+        let mut snapshot = Metadata::new();
+        snapshot.try_insert("delegates", &stats)?;
+        Ok(snapshot)
+    }
+
+    async fn update_state(&self, _data: Metadata) -> Result<()> {
+        Err(Error::InvalidRequest("Routers have no state to update".to_string()).into())
+    }
+
+    async fn handle_event(&self, _event: Event) -> Result<()> {
+        Ok(())
+    }
+
+    async fn execute_task(&self, task_id: TaskId, params: Metadata) -> Result<Metadata> {
+        self.route(task_id, params).await
+    }
+
+    async fn execute_task_with(
+        &self,
+        task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        cancel.run(self.route(task_id, params)).await
+    }
+
+    async fn on_start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_stop(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn pause(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn resume(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn health(&self) -> HealthStatus {
+        let delegates = self
+            .registry
+            .list()
+            .iter()
+            .filter(|descriptor| descriptor.id != self.id)
+            .count();
+        if delegates == 0 {
+            HealthStatus::healthy().with_issue(HealthLevel::Degraded, "No agents to route to")
+        } else {
+            HealthStatus::healthy()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Agent, AgentBuilder, Config};
+    use atlas_core::{EventBus, InMemoryEventBus};
+    use atlas_mcp::MCPTool;
+
+    struct Search;
+
+    #[async_trait]
+    impl MCPTool for Search {
+        fn name(&self) -> &str {
+            "search"
+        }
+
+        fn description(&self) -> &str {
+            "Search the web"
+        }
+
+        async fn execute(&self, _params: Metadata) -> Result<Metadata> {
+            let mut result = Metadata::new();
+            result.insert("found", true);
+            Ok(result)
+        }
+    }
+
+    async fn delegate(
+        bus: &Arc<dyn EventBus>,
+        registry: &AgentRegistry,
+        name: &str,
+        search: bool,
+    ) -> Arc<Agent> {
+        let mut builder = AgentBuilder::new()
+            .config(Config {
+                name: name.to_string(),
+                description: None,
+                capabilities: vec!["research".to_string()],
+                config: Metadata::new(),
+            })
+            .event_bus(bus.clone());
+        if search {
+            builder = builder.tool("search", Search);
+        }
+        let agent = Arc::new(builder.build().unwrap());
+        agent.accept_tasks().unwrap();
+        agent.listen().await.unwrap();
+        registry.register_agent(&agent).await.unwrap();
+        agent
+    }
+
+    fn task(tools: &[&str], capabilities: &[&str]) -> Metadata {
+        let mut params = Metadata::new();
+        params.insert("tool", "search");
+        params.insert(
+            CONSTRAINTS_KEY,
+            TaskConstraints {
+                required_tools: tools.iter().map(|t| t.to_string()).collect(),
+                ..Default::default()
+            },
+        );
+        params.insert(CAPABILITIES_KEY, capabilities);
+        params
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        let registry = Arc::new(AgentRegistry::new().with_event_bus(bus.clone()));
+        let first = delegate(&bus, &registry, "first", true).await;
+        let second = delegate(&bus, &registry, "second", true).await;
+        let plain = delegate(&bus, &registry, "plain", false).await;
+        let router = RouterAgent::new(registry.clone());
+
+        // Only agents with the required tools are chosen, taking turns
+        for _ in 0..2 {
+            let result = router
+                .execute_task(TaskId::new(), task(&["search"], &["research"]))
+                .await
+                .unwrap();
+            assert_eq!(result.get::<bool>("found"), Some(true));
+        }
+        assert_eq!(router.stats(first.id()).dispatched, 1);
+        assert_eq!(router.stats(second.id()).dispatched, 1);
+        assert_eq!(router.stats(plain.id()).dispatched, 0);
+        assert!(router.select(&task(&[], &["writing"])).is_err());
+
+        // Failing delegates lose their turn to healthy ones
+        router
+            .balancer
+            .lock()
+            .get_mut(&first.id())
+            .unwrap()
+            .failures = 2;
+        router
+            .balancer
+            .lock()
+            .get_mut(&second.id())
+            .unwrap()
+            .in_flight = 1;
+        assert_eq!(
+            router.select(&task(&["search"], &[])).unwrap().id,
+            second.id()
+        );
+
+        second.shutdown(Duration::from_secs(1)).await.unwrap();
+        registry.register_agent(&second).await.unwrap();
+        assert_eq!(
+            router.select(&task(&["search"], &[])).unwrap().id,
+            first.id()
+        );
+        assert_eq!(router.health().await.level, HealthLevel::Healthy);
+    }
+}
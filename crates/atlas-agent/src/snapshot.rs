@@ -0,0 +1,48 @@
+//! Serializable agent snapshots
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::state::MemoryEntry;
+use crate::{Config, TaskState};
+
+/// Point-in-time copy of an agent's state, tasks and memory
+///
+/// Snapshots are plain data, so they can be serialized, shipped to another
+/// process and passed to [`crate::Agent::restore`] on an agent built with
+/// the same configuration.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AgentSnapshot {
+    /// Hash of the configuration of the agent that took the snapshot
+    pub config_hash: String,
+
+    /// When the snapshot was taken
+    pub taken_at: DateTime<Utc>,
+
+    /// Key-value state
+    pub state: HashMap<String, Value>,
+
+    /// Task states
+    pub tasks: Vec<TaskState>,
+
+    /// Memory entries, oldest first
+    #[serde(default)]
+    pub memory: Vec<MemoryEntry>,
+}
+
+/// Hash an agent configuration
+///
+/// The configuration is hashed as canonical JSON with FNV-1a, so the hash is
+/// stable across processes and builds.
+pub fn config_hash(config: &Config) -> Result<String> {
+    // Converting to a value first sorts object keys
+    let json = serde_json::to_vec(&serde_json::to_value(config)?)?;
+    let hash = json.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    Ok(format!("{:016x}", hash))
+}
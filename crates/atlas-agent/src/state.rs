@@ -1,18 +1,47 @@
 //! State management for agents
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::sync::RwLock;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::{broadcast, Mutex, RwLock, RwLockWriteGuard};
 use uuid::Uuid;
 
-use atlas_core::Metadata;
+use atlas_core::{AgentState, AsyncAgentState, Clock, MergeStrategy, Metadata, SystemClock};
 
+use crate::diff::{diff, StateDiff};
+use crate::embedding::EmbeddingProvider;
 use crate::error::Error;
-use crate::{State, TaskState};
+#[cfg(feature = "postgres")]
+use crate::memory::PostgresStore;
+#[cfg(feature = "sqlite")]
+use crate::memory::SqliteStore;
+#[cfg(feature = "full-text")]
+use crate::memory::TextIndex;
+use crate::memory::dedup::{self, DedupConfig};
+use crate::memory::promotion::{self, PromotionConfig, OCCURRENCES_KEY, PATTERN_KEY};
+use crate::memory::spill::{SpillConfig, SpillDir, SpillTracker};
+use crate::memory::{
+    EvictionPolicy, HnswIndex, InMemoryStore, MemoryCompactor, MemoryCursor, MemoryFilter,
+    MemoryKind, MemoryScope, MemoryStore, MemoryWal, ScoringConfig, StateStore, WalRecord,
+    WalWriter, WriterStatus, IMPORTANCE_KEY, PINNED_KEY,
+};
+use crate::{State, TaskState, TaskStatus};
+
+/// Metadata key holding an entry's tags, as a list of strings
+pub const TAGS_KEY: &str = "tags";
+
+/// Metadata key overriding the configured TTL of a memory entry, in seconds
+pub const TTL_KEY: &str = "ttl_secs";
 
 /// Memory entry
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -28,16 +57,84 @@ pub struct MemoryEntry {
     
     /// Entry metadata
     pub metadata: Metadata,
+
+    /// Embedding of the entry's text, when an embedding provider is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+
+    /// When the entry expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Namespace the entry belongs to
+    #[serde(default)]
+    pub scope: MemoryScope,
+
+    /// Tags taken from the `tags` metadata value
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+
+    /// Whether the entry is an observation or a fact
+    #[serde(default)]
+    pub kind: MemoryKind,
+
+    /// Version of the entry, counting its writes through
+    /// [`MemoryStore::put_versioned`]; 0 if never written that way
+    #[serde(default)]
+    pub version: u64,
 }
 
 impl MemoryEntry {
-    /// Create a new memory entry
+    /// Create a new memory entry stamped with the system time, taking its
+    /// tags from the metadata
     pub fn new(data: Value, metadata: Metadata) -> Self {
+        Self::new_at(data, metadata, chrono::Utc::now())
+    }
+
+    /// Create a new memory entry stamped with `timestamp`, taking its tags
+    /// from the metadata
+    pub fn new_at(
+        data: Value,
+        metadata: Metadata,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
         Self {
             id: Uuid::new_v4(),
-            timestamp: chrono::Utc::now(),
+            timestamp,
+            tags: metadata.get(TAGS_KEY).unwrap_or_default(),
             data,
             metadata,
+            embedding: None,
+            expires_at: None,
+            scope: MemoryScope::Global,
+            kind: MemoryKind::Episodic,
+            version: 0,
+        }
+    }
+
+    /// Whether the entry has expired at the given time
+    pub fn is_expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= now,
+            None => false,
+        }
+    }
+
+    /// Get the entry's importance score from its metadata, defaulting to zero
+    pub fn importance(&self) -> f64 {
+        self.metadata.get(IMPORTANCE_KEY).unwrap_or(0.0)
+    }
+
+    /// Whether the entry is pinned and exempt from eviction
+    pub fn is_pinned(&self) -> bool {
+        self.metadata.get(PINNED_KEY).unwrap_or(false)
+    }
+
+    /// Get the text used to index the entry
+    pub fn text(&self) -> String {
+        match &self.data {
+            Value::String(s) => s.clone(),
+            data => data.to_string(),
         }
     }
 }
@@ -45,7 +142,7 @@ impl MemoryEntry {
 /// Memory configuration
 #[derive(Clone, Debug, Deserialize)]
 pub struct MemoryConfig {
-    /// Maximum number of entries to keep
+    /// Maximum number of episodic entries to keep
     pub capacity: usize,
     
     /// Whether to persist memory to disk
@@ -53,6 +150,57 @@ pub struct MemoryConfig {
     
     /// Path to persist memory to
     pub persist_path: Option<String>,
+
+    /// Storage used when persisting
+    #[serde(default)]
+    pub backend: PersistenceBackend,
+
+    /// Database connection URL for server-backed storage
+    #[serde(default)]
+    pub database_url: Option<String>,
+
+    /// Default time-to-live of entries in seconds; entries never expire when unset
+    #[serde(default)]
+    pub default_ttl_secs: Option<u64>,
+
+    /// Which entries to remove when over capacity
+    #[serde(default)]
+    pub eviction: EvictionPolicy,
+
+    /// Log records written before file persistence rewrites its snapshot;
+    /// defaults to the capacity
+    #[serde(default)]
+    pub wal_compact_after: Option<usize>,
+
+    /// Maximum number of semantic entries to keep; defaults to the capacity
+    #[serde(default)]
+    pub semantic_capacity: Option<usize>,
+
+    /// Promote repeated episodes to semantic facts
+    #[serde(default)]
+    pub promotion: Option<PromotionConfig>,
+
+    /// Rank search results by similarity, recency and importance combined;
+    /// results are ranked by similarity alone when unset
+    #[serde(default)]
+    pub scoring: Option<ScoringConfig>,
+
+    /// Maximum number of pinned entries; defaults to a quarter of the capacity
+    #[serde(default)]
+    pub max_pinned: Option<usize>,
+
+    /// Spill cold key-value state to the persistence backend beyond these limits
+    #[serde(default)]
+    pub spill: Option<SpillConfig>,
+
+    /// Milliseconds file persistence waits to gather changes into one write;
+    /// changes already queued are still written together when unset
+    #[serde(default)]
+    pub flush_interval_ms: Option<u64>,
+
+    /// Detect duplicate entries on insert
+    #[serde(default)]
+    pub dedup: Option<DedupConfig>,
 }
 
 impl Default for MemoryConfig {
@@ -61,12 +209,159 @@ impl Default for MemoryConfig {
             capacity: 1000,
             persistent: false,
             persist_path: None,
+            backend: PersistenceBackend::default(),
+            database_url: None,
+            default_ttl_secs: None,
+            eviction: EvictionPolicy::default(),
+            wal_compact_after: None,
+            semantic_capacity: None,
+            promotion: None,
+            scoring: None,
+            max_pinned: None,
+            spill: None,
+            flush_interval_ms: None,
+            dedup: None,
         }
     }
 }
 
-/// Agent state manager
+/// Storage used for persistent memory
+///
+/// The database backends need the `sqlite` and `postgres` features; without
+/// its feature, a backend fails to open with [`Error::InvalidConfig`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PersistenceBackend {
+    /// JSON snapshot at `persist_path` plus an append-only log of changes
+    #[default]
+    File,
+
+    /// SQLite database at `persist_path`, with one row per entry and task
+    Sqlite,
+
+    /// Postgres database at `database_url`, with one row per entry and task
+    Postgres,
+}
+
+/// File format for exported memory
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryFormat {
+    /// One JSON entry per line
+    #[default]
+    Jsonl,
+
+    /// A JSON array of entries, as written by file persistence
+    Json,
+}
+
+/// Page of memory entries in chronological order
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemoryPage {
+    /// Entries in the page, oldest first
+    pub entries: Vec<MemoryEntry>,
+
+    /// Cursor for the next page, if there may be more entries
+    pub next: Option<MemoryCursor>,
+}
+
+/// Number of state changes buffered for slow subscribers
+const CHANGE_BUFFER: usize = 256;
+
+/// Most similar entries checked when looking for a near-duplicate
+const NEAR_DUPLICATE_CANDIDATES: usize = 8;
+
+/// Change to managed state, delivered to subscribers
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StateChange {
+    /// State keys were set
+    StateUpdated {
+        /// Keys added or changed by the update
+        diff: StateDiff,
+    },
+
+    /// A task was created or changed status
+    TaskTransitioned {
+        /// Task ID
+        id: Uuid,
+
+        /// Previous status, if the task existed
+        from: Option<TaskStatus>,
+
+        /// New status
+        to: TaskStatus,
+    },
+
+    /// A memory entry was added
+    MemoryAdded {
+        /// The entry
+        entry: MemoryEntry,
+    },
+}
+
+tokio::task_local! {
+    /// Changes held back until the enclosing transaction commits
+    static PENDING_CHANGES: RefCell<Vec<StateChange>>;
+}
+
+/// Mutation staged in a transaction
 #[derive(Debug)]
+enum StagedOp {
+    UpdateState(Metadata),
+    AddMemory(MemoryEntry),
+    RemoveMemory(Uuid),
+    UpdateTask(TaskState),
+    RemoveTask(Uuid),
+}
+
+/// Mutations staged by [`AgentStateManager::transaction`]
+///
+/// Nothing is applied until the transaction closure returns `Ok`.
+pub struct StateTransaction<'a> {
+    /// Manager the transaction applies to
+    manager: &'a AgentStateManager,
+
+    /// Staged mutations, in order
+    ops: Vec<StagedOp>,
+}
+
+impl StateTransaction<'_> {
+    /// Stage a state update
+    pub fn update_state(&mut self, data: Metadata) {
+        self.ops.push(StagedOp::UpdateState(data));
+    }
+
+    /// Stage a memory entry in the global scope, returning its ID
+    pub fn add_memory(&mut self, data: Value, metadata: Metadata) -> Uuid {
+        self.add_memory_in(MemoryScope::Global, data, metadata)
+    }
+
+    /// Stage a memory entry in a scope, returning its ID
+    pub fn add_memory_in(&mut self, scope: MemoryScope, data: Value, metadata: Metadata) -> Uuid {
+        let entry = self.manager.new_entry(scope, data, metadata);
+        let id = entry.id;
+        self.ops.push(StagedOp::AddMemory(entry));
+        id
+    }
+
+    /// Stage the removal of a memory entry
+    pub fn remove_memory(&mut self, id: Uuid) {
+        self.ops.push(StagedOp::RemoveMemory(id));
+    }
+
+    /// Stage a task update
+    pub fn update_task(&mut self, task: TaskState) {
+        self.ops.push(StagedOp::UpdateTask(task));
+    }
+
+    /// Stage the removal of a task
+    pub fn remove_task(&mut self, id: Uuid) {
+        self.ops.push(StagedOp::RemoveTask(id));
+    }
+}
+
+/// Agent state manager
 pub struct AgentStateManager {
     /// Agent state
     state: Arc<RwLock<State>>,
@@ -74,18 +369,167 @@ pub struct AgentStateManager {
     /// Memory configuration
     memory_config: MemoryConfig,
     
-    /// Memory entries
-    memory: Arc<RwLock<Vec<MemoryEntry>>>,
+    /// Episodic memory entries
+    memory: Arc<dyn MemoryStore>,
+
+    /// Semantic memory entries
+    semantic: Arc<dyn MemoryStore>,
+
+    /// Embedding provider for semantic search
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
+
+    /// Vector index over memory embeddings
+    index: Arc<RwLock<HnswIndex>>,
+
+    /// Full-text index over memory entries
+    #[cfg(feature = "full-text")]
+    text_index: Option<Arc<TextIndex>>,
+
+    /// Change log for file persistence
+    wal: Option<Arc<MemoryWal>>,
+
+    /// Background writer appending to the change log, started on first use
+    wal_writer: std::sync::OnceLock<WalWriter>,
+
+    /// Database store, opened on first use
+    store: tokio::sync::OnceCell<Arc<dyn StateStore>>,
+
+    /// Directory receiving spilled state for file persistence
+    spill_dir: Option<SpillDir>,
+
+    /// Recency and size of state keys, and which keys are spilled
+    spill: std::sync::Mutex<SpillTracker>,
+
+    /// Summarizes old entries as memory nears capacity
+    compactor: Option<MemoryCompactor>,
+
+    /// Publishes state changes to subscribers
+    changes: broadcast::Sender<StateChange>,
+
+    /// Serializes transaction commits
+    commit_lock: Mutex<()>,
+
+    /// Source of memory timestamps and expiry times
+    clock: Arc<dyn Clock>,
+}
+
+impl std::fmt::Debug for AgentStateManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentStateManager")
+            .field("memory_config", &self.memory_config)
+            .finish_non_exhaustive()
+    }
 }
 
 impl AgentStateManager {
     /// Create a new state manager
     pub fn new(state: State, config: MemoryConfig) -> Self {
+        let wal = match (&config.persist_path, config.backend) {
+            (Some(path), PersistenceBackend::File) => Some(Arc::new(MemoryWal::new(
+                path,
+                config.wal_compact_after.unwrap_or(config.capacity),
+            ))),
+            _ => None,
+        };
+        let spill_dir = match (&config.persist_path, config.backend) {
+            (Some(path), PersistenceBackend::File) => {
+                Some(SpillDir::new(format!("{}.state", path)))
+            }
+            _ => None,
+        };
         Self {
             state: Arc::new(RwLock::new(state)),
+            memory: Arc::new(InMemoryStore::new().with_policy(config.eviction)),
+            semantic: Arc::new(InMemoryStore::new().with_policy(config.eviction)),
             memory_config: config,
-            memory: Arc::new(RwLock::new(Vec::new())),
+            embedder: None,
+            index: Arc::new(RwLock::new(HnswIndex::default())),
+            #[cfg(feature = "full-text")]
+            text_index: None,
+            wal,
+            wal_writer: std::sync::OnceLock::new(),
+            store: tokio::sync::OnceCell::new(),
+            spill_dir,
+            spill: std::sync::Mutex::new(SpillTracker::default()),
+            compactor: None,
+            changes: broadcast::channel(CHANGE_BUFFER).0,
+            commit_lock: Mutex::new(()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Load the memory and tasks persisted by a previous run
+    ///
+    /// Call this last, once the stores, clock and indexes are set, so the
+    /// loaded entries land where the manager will look for them. Fails if
+    /// persistence is not enabled in the configuration or the persisted data
+    /// cannot be read. Nothing has been persisted on a first run, so memory
+    /// starts empty.
+    pub async fn with_persistence(self) -> Result<Self> {
+        let config = &self.memory_config;
+        if !config.persistent {
+            return Err(
+                Error::InvalidConfig("Memory persistence is not enabled".to_string()).into(),
+            );
+        }
+        if config.backend == PersistenceBackend::File && config.persist_path.is_none() {
+            return Err(
+                Error::InvalidConfig("File persistence requires persist_path".to_string()).into(),
+            );
         }
+        self.load_memory().await?;
+        Ok(self)
+    }
+
+    /// Persist memory and tasks to an existing store instead of the configured backend
+    pub fn with_store(self, store: Arc<dyn StateStore>) -> Self {
+        let _ = self.store.set(store);
+        self
+    }
+
+    /// Keep episodic memory entries in a custom store
+    pub fn with_memory_store(mut self, store: Arc<dyn MemoryStore>) -> Self {
+        self.memory = store;
+        self
+    }
+
+    /// Keep semantic memory entries in a custom store
+    pub fn with_semantic_store(mut self, store: Arc<dyn MemoryStore>) -> Self {
+        self.semantic = store;
+        self
+    }
+
+    /// Take memory timestamps, expiry times and sweep schedules from a clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Get the current time of the manager's clock
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        self.clock.now().into()
+    }
+
+    /// Summarize old entries instead of evicting them as memory nears capacity
+    pub fn with_compactor(mut self, compactor: MemoryCompactor) -> Self {
+        self.compactor = Some(compactor);
+        self
+    }
+
+    /// Embed memory entries so searches rank them by semantic similarity
+    pub fn with_embedder(mut self, embedder: Arc<dyn EmbeddingProvider>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Rank text searches with a full-text index
+    ///
+    /// Without an embedding provider, searches are parsed as full-text
+    /// queries supporting phrases and field boosts.
+    #[cfg(feature = "full-text")]
+    pub fn with_text_index(mut self, index: TextIndex) -> Self {
+        self.text_index = Some(Arc::new(index));
+        self
     }
 
     /// Get the current state
@@ -94,189 +538,1934 @@ impl AgentStateManager {
     }
 
     /// Update the state
+    ///
+    /// With spill limits, the least recently accessed keys beyond them are
+    /// moved to the persistence backend.
     pub async fn update_state(&self, data: Metadata) -> Result<()> {
-        let mut state = self.state.write().await;
-        state.update(data)
+        let state = self.state.write().await;
+        let before = self.current_values(&state, &data).await?;
+        self.apply_update(state, &before, data).await
     }
 
-    /// Get a snapshot of the current state
-    pub async fn snapshot(&self) -> Result<Metadata> {
-        let state = self.state.read().await;
-        state.snapshot()
+    /// Merge data into the state, combining it with the values already held
+    /// under its keys instead of replacing them
+    ///
+    /// The state stays locked from reading the current values to writing the
+    /// merged ones, so concurrent merges all take effect.
+    pub async fn merge_state(&self, data: Metadata, strategy: MergeStrategy) -> Result<()> {
+        let state = self.state.write().await;
+        let before = self.current_values(&state, &data).await?;
+        let mut merged = before.clone();
+        merged.merge_deep(data, strategy);
+        self.apply_update(state, &before, merged).await
     }
 
-    /// Add a memory entry
-    pub async fn add_memory(&self, data: Value, metadata: Metadata) -> Result<Uuid> {
-        let entry = MemoryEntry::new(data, metadata);
-        let id = entry.id;
-        
-        let mut memory = self.memory.write().await;
-        
-        // Enforce capacity limit
-        if memory.len() >= self.memory_config.capacity {
-            memory.remove(0);
+    /// Get the values held under the keys of some data, including spilled ones
+    async fn current_values(&self, state: &State, data: &Metadata) -> Result<Metadata> {
+        let mut current = Metadata::new();
+        for key in data.keys() {
+            if let Some(old) = state.memory.get(key) {
+                current.insert(key.as_str(), old);
+            } else if self.spill_tracker()?.is_spilled(key) {
+                if let Some(old) = self.load_spilled(key).await? {
+                    current.insert(key.as_str(), old);
+                }
+            }
         }
-        
-        memory.push(entry);
-        
-        // Persist if configured
-        if self.memory_config.persistent {
-            self.persist_memory().await?;
+        Ok(current)
+    }
+
+    /// Write data into the locked state, then release it and publish the
+    /// changes from `before`
+    async fn apply_update(
+        &self,
+        mut state: RwLockWriteGuard<'_, State>,
+        before: &Metadata,
+        data: Metadata,
+    ) -> Result<()> {
+        let changes = diff(before, &data);
+        state.update(data.clone())?;
+        {
+            let mut spill = self.spill_tracker()?;
+            for (key, value) in data {
+                spill.touch(&key, &value);
+            }
         }
-        
-        Ok(id)
+        self.spill_cold(&mut state).await?;
+        drop(state);
+
+        if !changes.is_empty() {
+            self.notify(StateChange::StateUpdated { diff: changes });
+        }
+        Ok(())
     }
 
-    /// Get a memory entry by ID
-    pub async fn get_memory(&self, id: Uuid) -> Result<Option<MemoryEntry>> {
-        let memory = self.memory.read().await;
-        Ok(memory.iter().find(|e| e.id == id).cloned())
+    /// Get a state value, paging it back in if it was spilled
+    pub async fn get_state(&self, key: &str) -> Result<Option<Value>> {
+        let mut state = self.state.write().await;
+        if let Some(value) = state.memory.get(key).cloned() {
+            self.spill_tracker()?.touch(key, &value);
+            return Ok(Some(value));
+        }
+        if !self.spill_tracker()?.is_spilled(key) {
+            return Ok(None);
+        }
+
+        let value = self.load_spilled(key).await?.ok_or_else(|| {
+            Error::StateError(format!("Spilled state value {} is missing", key))
+        })?;
+        state.memory.insert(key.to_string(), value.clone());
+        self.spill_tracker()?.touch(key, &value);
+        self.spill_cold(&mut state).await?;
+        Ok(Some(value))
     }
 
-    /// Search memory entries
-    pub async fn search_memory(&self, query: &str) -> Result<Vec<MemoryEntry>> {
-        let memory = self.memory.read().await;
-        
-        // Simple substring search for now
-        // TODO: Implement proper search functionality
-        Ok(memory
-            .iter()
-            .filter(|e| {
-                serde_json::to_string(&e.data)
-                    .unwrap_or_default()
-                    .contains(query)
-            })
-            .cloned()
-            .collect())
+    /// Move the coldest keys beyond the spill limits to the backend
+    async fn spill_cold(&self, state: &mut State) -> Result<()> {
+        let Some(config) = &self.memory_config.spill else {
+            return Ok(());
+        };
+        let keys = self.spill_tracker()?.select(&state.memory, config);
+        for key in keys {
+            let Some(value) = state.memory.remove(&key) else {
+                continue;
+            };
+            if let Err(e) = self.store_spilled(&key, &value).await {
+                self.spill_tracker()?.touch(&key, &value);
+                state.memory.insert(key, value);
+                return Err(e);
+            }
+        }
+        Ok(())
     }
 
-    /// Get all memory entries
-    pub async fn list_memory(&self) -> Result<Vec<MemoryEntry>> {
-        let memory = self.memory.read().await;
-        Ok(memory.clone())
+    /// Write a spilled state value to the persistence backend
+    async fn store_spilled(&self, key: &str, value: &Value) -> Result<()> {
+        match (self.store().await?, &self.spill_dir) {
+            (Some(store), _) => store.put_state(key, value).await,
+            (None, Some(dir)) => dir.put(key, value).await,
+            (None, None) => Err(Error::InvalidConfig(
+                "Spilling state requires a persistence backend".to_string(),
+            )
+            .into()),
+        }
     }
 
-    /// Clear all memory entries
-    pub async fn clear_memory(&self) -> Result<()> {
-        let mut memory = self.memory.write().await;
-        memory.clear();
-        
-        if self.memory_config.persistent {
-            self.persist_memory().await?;
+    /// Read a spilled state value from the persistence backend
+    async fn load_spilled(&self, key: &str) -> Result<Option<Value>> {
+        match (self.store().await?, &self.spill_dir) {
+            (Some(store), _) => store.get_state(key).await,
+            (None, Some(dir)) => dir.get(key).await,
+            (None, None) => Ok(None),
         }
-        
-        Ok(())
     }
 
-    /// Get a task state by ID
-    pub async fn get_task(&self, id: Uuid) -> Result<Option<TaskState>> {
-        let state = self.state.read().await;
-        Ok(state.tasks.get(&id).cloned())
+    fn spill_tracker(&self) -> Result<std::sync::MutexGuard<'_, SpillTracker>> {
+        self.spill
+            .lock()
+            .map_err(|_| Error::StateError("State spill tracker poisoned".to_string()).into())
     }
 
-    /// Update a task state
-    pub async fn update_task(&self, task: TaskState) -> Result<()> {
-        let mut state = self.state.write().await;
-        state.tasks.insert(task.id, task);
-        Ok(())
+    /// Stream changes to state keys, tasks and memory made after subscribing
+    ///
+    /// A subscriber that falls more than a few hundred changes behind skips
+    /// the oldest ones. The stream ends when the manager is dropped.
+    pub fn subscribe(&self) -> BoxStream<'static, StateChange> {
+        futures::stream::unfold(self.changes.subscribe(), |mut changes| async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => return Some((change, changes)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "State change subscriber lagged");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
     }
 
-    /// Remove a task state
-    pub async fn remove_task(&self, id: Uuid) -> Result<()> {
-        let mut state = self.state.write().await;
-        state.tasks.remove(&id);
-        Ok(())
+    /// Publish a change to subscribers, or hold it until the current transaction commits
+    fn notify(&self, change: StateChange) {
+        let mut change = Some(change);
+        let _ = PENDING_CHANGES.try_with(|pending| pending.borrow_mut().extend(change.take()));
+        if let Some(change) = change {
+            // Sending only fails when nobody is subscribed
+            let _ = self.changes.send(change);
+        }
+    }
+
+    /// Apply several mutations so that either all of them or none take effect
+    ///
+    /// The closure stages mutations on a [`StateTransaction`]; they are
+    /// applied in order once it returns `Ok`. If the closure or any mutation
+    /// fails, state, tasks and memory are rolled back, including their
+    /// persisted copies, and subscribers see none of the changes. Commits are
+    /// serialized with each other but not with direct mutations.
+    pub async fn transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: for<'t> FnOnce(&'t mut StateTransaction<'_>) -> BoxFuture<'t, Result<T>>,
+    {
+        let mut tx = StateTransaction {
+            manager: self,
+            ops: Vec::new(),
+        };
+        let value = f(&mut tx).await?;
+        let ops = tx.ops;
+        if ops.is_empty() {
+            return Ok(value);
+        }
+
+        let _commit = self.commit_lock.lock().await;
+        let (state, spill) = {
+            let state = self.state.read().await;
+            (state.clone(), self.spill_tracker()?.clone())
+        };
+        let touches_memory = ops.iter().any(|op| {
+            matches!(
+                op,
+                StagedOp::AddMemory(_) | StagedOp::RemoveMemory(_) | StagedOp::UpdateTask(_)
+            )
+        });
+        let memory = if touches_memory {
+            Some(self.entries().await?)
+        } else {
+            None
+        };
+
+        let (applied, changes) = PENDING_CHANGES
+            .scope(RefCell::new(Vec::new()), async {
+                let applied = match self.apply(&ops).await {
+                    Ok(()) => self.flush().await,
+                    Err(e) => Err(e),
+                };
+                (applied, PENDING_CHANGES.with(|pending| pending.take()))
+            })
+            .await;
+        if let Err(e) = applied {
+            if let Err(rollback) = self.roll_back(state, spill, memory, &ops).await {
+                tracing::error!(error = %rollback, "State transaction rollback failed");
+            }
+            return Err(e);
+        }
+
+        for change in changes {
+            self.notify(change);
+        }
+        Ok(value)
     }
 
-    /// Persist memory to disk
-    async fn persist_memory(&self) -> Result<()> {
-        if let Some(path) = &self.memory_config.persist_path {
-            let memory = self.memory.read().await;
-            let json = serde_json::to_string_pretty(&*memory)?;
-            tokio::fs::write(path, json).await?;
+    /// Apply staged mutations in order
+    async fn apply(&self, ops: &[StagedOp]) -> Result<()> {
+        for op in ops {
+            match op {
+                StagedOp::UpdateState(data) => self.update_state(data.clone()).await?,
+                StagedOp::AddMemory(entry) => {
+                    self.add_entry(entry.clone()).await?;
+                }
+                StagedOp::RemoveMemory(id) => self.remove_entries(&[*id]).await?,
+                StagedOp::UpdateTask(task) => self.update_task(task.clone()).await?,
+                StagedOp::RemoveTask(id) => self.remove_task(*id).await?,
+            }
         }
         Ok(())
     }
 
-    /// Load memory from disk
-    async fn load_memory(&self) -> Result<()> {
-        if let Some(path) = &self.memory_config.persist_path {
-            if tokio::fs::try_exists(path).await? {
-                let json = tokio::fs::read_to_string(path).await?;
-                let entries: Vec<MemoryEntry> = serde_json::from_str(&json)?;
-                
-                let mut memory = self.memory.write().await;
-                *memory = entries;
+    /// Restore state, tasks and memory from before a failed transaction
+    async fn roll_back(
+        &self,
+        state: State,
+        spill: SpillTracker,
+        memory: Option<Vec<MemoryEntry>>,
+        ops: &[StagedOp],
+    ) -> Result<()> {
+        if self.memory_config.persistent {
+            if let Some(store) = self.store().await? {
+                for op in ops {
+                    let id = match op {
+                        StagedOp::UpdateTask(task) => task.id,
+                        StagedOp::RemoveTask(id) => *id,
+                        _ => continue,
+                    };
+                    match state.tasks.get(&id) {
+                        Some(task) => store.put_task(task).await?,
+                        None => {
+                            store.remove_task(id).await?;
+                        }
+                    }
+                }
             }
         }
+        {
+            let mut current = self.state.write().await;
+            *current = state;
+            *self.spill_tracker()? = spill;
+        }
+
+        if let Some(memory) = memory {
+            self.replace_memory(memory).await?;
+        }
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
+    /// Get a snapshot of the current state, including spilled values
+    pub async fn snapshot(&self) -> Result<Metadata> {
+        let state = self.state.read().await;
+        let mut snapshot = state.snapshot()?;
+        let spilled: Vec<String> = self.spill_tracker()?.spilled().cloned().collect();
+        for key in spilled {
+            if let Some(value) = self.load_spilled(&key).await? {
+                snapshot.insert(key, value);
+            }
+        }
+        Ok(snapshot)
+    }
 
-    #[tokio::test]
-    async fn test_state_update() {
-        let manager = AgentStateManager::new(State::default(), MemoryConfig::default());
-        
-        let mut data = Metadata::new();
-        data.insert("test", "value");
-        
-        manager.update_state(data).await.unwrap();
-        
-        let state = manager.state.read().await;
-        assert_eq!(
-            state.memory.get("test").unwrap().as_str().unwrap(),
-            "value"
-        );
+    /// Add a memory entry to the global scope
+    pub async fn add_memory(&self, data: Value, metadata: Metadata) -> Result<Uuid> {
+        self.add_memory_in(MemoryScope::Global, data, metadata).await
     }
 
-    #[tokio::test]
-    async fn test_memory_management() {
-        let manager = AgentStateManager::new(
-            State::default(),
-            MemoryConfig {
-                capacity: 2,
-                ..Default::default()
-            },
-        );
+    /// Add a memory entry to a scope
+    ///
+    /// The entry expires after the `ttl_secs` metadata value if present,
+    /// otherwise after the configured default TTL. With a compactor, the
+    /// oldest entries are summarized once memory nears capacity; a failed
+    /// summary is logged and tried again on the next insert. With
+    /// deduplication, a duplicate is absorbed by the existing entry, whose ID
+    /// is returned.
+    pub async fn add_memory_in(
+        &self,
+        scope: MemoryScope,
+        data: Value,
+        metadata: Metadata,
+    ) -> Result<Uuid> {
+        let entry = self.new_entry(scope, data, metadata);
+        self.add_entry(entry).await
+    }
 
-        let id1 = manager
-            .add_memory(json!("entry1"), Metadata::new())
-            .await
-            .unwrap();
-        let id2 = manager
-            .add_memory(json!("entry2"), Metadata::new())
-            .await
-            .unwrap();
-        let id3 = manager
-            .add_memory(json!("entry3"), Metadata::new())
-            .await
-            .unwrap();
+    /// Create an entry in a scope, applying its TTL
+    fn new_entry(&self, scope: MemoryScope, data: Value, metadata: Metadata) -> MemoryEntry {
+        let ttl = metadata
+            .get::<u64>(TTL_KEY)
+            .or(self.memory_config.default_ttl_secs);
+        let mut entry = MemoryEntry::new_at(data, metadata, self.now());
+        entry.scope = scope;
+        // TTLs too long to represent never expire
+        entry.expires_at = ttl.and_then(|ttl| {
+            let ttl = chrono::Duration::try_seconds(i64::try_from(ttl).ok()?)?;
+            entry.timestamp.checked_add_signed(ttl)
+        });
+        entry
+    }
 
-        // First entry should be removed due to capacity limit
-        assert!(manager.get_memory(id1).await.unwrap().is_none());
-        assert!(manager.get_memory(id2).await.unwrap().is_some());
-        assert!(manager.get_memory(id3).await.unwrap().is_some());
+    /// Add a semantic fact to the global scope
+    pub async fn add_fact(&self, data: Value, metadata: Metadata) -> Result<Uuid> {
+        let mut entry = self.new_entry(MemoryScope::Global, data, metadata);
+        entry.kind = MemoryKind::Semantic;
+        self.add_entry(entry).await
     }
 
-    #[tokio::test]
-    async fn test_memory_search() {
-        let manager = AgentStateManager::new(State::default(), MemoryConfig::default());
+    /// Insert a new entry, promoting repeated episodes and compacting memory
+    /// if it nears capacity, and return the ID it is stored under
+    async fn add_entry(&self, mut entry: MemoryEntry) -> Result<Uuid> {
+        if let Some(config) = &self.memory_config.dedup {
+            if let Some(existing) = self.find_duplicate(&mut entry, config).await? {
+                let id = existing.id;
+                if let Some(updated) = dedup::absorb(&existing, &entry, config.action) {
+                    self.replace_entry(updated).await?;
+                }
+                return Ok(id);
+            }
+        }
+        if entry.is_pinned() {
+            self.check_pin_capacity().await?;
+        }
+        let episode = match (&self.memory_config.promotion, entry.kind) {
+            (Some(config), MemoryKind::Episodic) => Some((config, entry.clone())),
+            _ => None,
+        };
+        let id = entry.id;
+        self.insert_entry(entry).await?;
+        if let Some((config, episode)) = episode {
+            self.promote(&episode, config).await?;
+        }
 
-        manager
-            .add_memory(json!({"text": "test entry"}), Metadata::new())
-            .await
-            .unwrap();
-        manager
-            .add_memory(json!({"text": "another entry"}), Metadata::new())
-            .await
-            .unwrap();
+        // The entry is stored, so failing the call would have it added again
+        if let Err(e) = self.compact_if_full().await {
+            tracing::warn!(error = %e, "Failed to compact memory");
+        }
+        Ok(id)
+    }
 
-        let results = manager.search_memory("test").await.unwrap();
-        assert_eq!(results.len(), 1);
+    /// Summarize the oldest entries if memory nears capacity
+    async fn compact_if_full(&self) -> Result<()> {
+        if let Some(compactor) = &self.compactor {
+            let len = self.memory.len().await?;
+            if compactor.should_compact(len, self.memory_config.capacity) {
+                self.compact_memory().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find an existing entry that a new entry duplicates
+    ///
+    /// Entries with identical data are exact duplicates. Near-duplicates are
+    /// found through the vector index, embedding the new entry on the way.
+    async fn find_duplicate(
+        &self,
+        entry: &mut MemoryEntry,
+        config: &DedupConfig,
+    ) -> Result<Option<MemoryEntry>> {
+        let now = self.now();
+        let filter = MemoryFilter::new().scope(entry.scope.clone()).kind(entry.kind);
+        let hash = dedup::content_hash(&entry.data);
+        let exact = self
+            .store_for(entry.kind)
+            .query(&filter)
+            .await?
+            .into_iter()
+            .find(|e| !e.is_expired(now) && dedup::content_hash(&e.data) == hash);
+        if exact.is_some() {
+            return Ok(exact);
+        }
+
+        let (Some(threshold), Some(embedder)) = (config.similarity_threshold, &self.embedder)
+        else {
+            return Ok(None);
+        };
+        let embedding = embed_text(embedder.as_ref(), entry.text()).await?;
+        let hits = self
+            .index
+            .read()
+            .await
+            .search(&embedding, NEAR_DUPLICATE_CANDIDATES);
+        entry.embedding = Some(embedding);
+        for (id, similarity) in hits {
+            if similarity < threshold {
+                break;
+            }
+            match self.get_memory(id).await? {
+                Some(candidate) if dedup::comparable(&candidate, entry) => {
+                    return Ok(Some(candidate))
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Count a repeated episode on its fact, or promote the episodes to a
+    /// fact once they repeat often enough
+    async fn promote(&self, episode: &MemoryEntry, config: &PromotionConfig) -> Result<()> {
+        let pattern = promotion::pattern_of(episode);
+        let in_scope = MemoryFilter::new().scope(episode.scope.clone());
+
+        let fact = self
+            .semantic
+            .query(&in_scope)
+            .await?
+            .into_iter()
+            .find(|f| f.metadata.get::<String>(PATTERN_KEY).as_ref() == Some(&pattern));
+        if let Some(mut fact) = fact {
+            let occurrences = fact.metadata.get::<usize>(OCCURRENCES_KEY).unwrap_or(0);
+            fact.metadata.insert(OCCURRENCES_KEY, occurrences + 1);
+            fact.timestamp = episode.timestamp;
+            return self.insert_entry(fact).await;
+        }
+
+        let mut episodes = self.memory.query(&in_scope).await?;
+        episodes.retain(|e| promotion::pattern_of(e) == pattern);
+        if episodes.len() >= config.min_occurrences {
+            if let Some(fact) = promotion::promote(&episodes) {
+                self.insert_entry(fact).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Summarize the oldest batch of episodic entries into one entry,
+    /// returning its ID
+    ///
+    /// The batch is taken from the scope of the oldest entry. Returns `None`
+    /// without a compactor or when the batch has fewer than two entries.
+    pub async fn compact_memory(&self) -> Result<Option<Uuid>> {
+        let Some(compactor) = &self.compactor else {
+            return Ok(None);
+        };
+
+        let entries = self.memory.list().await?;
+        let Some(scope) = entries.first().map(|e| e.scope.clone()) else {
+            return Ok(None);
+        };
+        let batch: Vec<MemoryEntry> = entries
+            .into_iter()
+            .filter(|e| e.scope == scope && !e.is_pinned())
+            .take(compactor.config().batch_size)
+            .collect();
+        if batch.len() < 2 {
+            return Ok(None);
+        }
+
+        let summary = compactor.summarize(&batch).await?;
+        let id = summary.id;
+        let ids: Vec<Uuid> = batch.iter().map(|e| e.id).collect();
+        self.remove_entries(&ids).await?;
+        self.insert_entry(summary).await?;
+        Ok(Some(id))
+    }
+
+    /// Embed, store and persist an entry, evicting entries over capacity
+    ///
+    /// Entries that already carry an embedding are indexed with it.
+    async fn insert_entry(&self, mut entry: MemoryEntry) -> Result<()> {
+        if let Some(embedder) = &self.embedder {
+            let embedding = match entry.embedding.take() {
+                Some(embedding) => embedding,
+                None => embed_text(embedder.as_ref(), entry.text()).await?,
+            };
+            self.index.write().await.insert(entry.id, &embedding);
+            entry.embedding = Some(embedding);
+        }
+        #[cfg(feature = "full-text")]
+        if let Some(text_index) = &self.text_index {
+            text_index.insert(&entry)?;
+        }
+
+        let store = self.store_for(entry.kind);
+        store.put(entry.clone()).await?;
+        if self.changes.receiver_count() > 0 {
+            self.notify(StateChange::MemoryAdded {
+                entry: entry.clone(),
+            });
+        }
+
+        // Enforce capacity limit
+        let evicted = store.evict(self.capacity_for(entry.kind)).await?;
+        if !evicted.is_empty() {
+            let ids: Vec<Uuid> = evicted.iter().map(|e| e.id).collect();
+            self.unindex(&ids).await?;
+        }
+        
+        // Persist if configured
+        if self.memory_config.persistent {
+            match self.store().await? {
+                Some(store) => {
+                    for e in &evicted {
+                        store.remove_memory(e.id).await?;
+                    }
+                    store.put_memory(&entry).await?;
+                }
+                None => {
+                    let mut records: Vec<WalRecord> = evicted
+                        .iter()
+                        .map(|e| WalRecord::Remove { id: e.id })
+                        .collect();
+                    records.push(WalRecord::Put { entry });
+                    self.persist_memory(&records).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store a changed entry in place and persist it
+    async fn replace_entry(&self, entry: MemoryEntry) -> Result<()> {
+        #[cfg(feature = "full-text")]
+        if let Some(text_index) = &self.text_index {
+            text_index.insert(&entry)?;
+        }
+        self.store_for(entry.kind).put(entry.clone()).await?;
+        if self.memory_config.persistent {
+            match self.store().await? {
+                Some(store) => store.put_memory(&entry).await?,
+                None => self.persist_memory(&[WalRecord::Put { entry }]).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Pin an entry so eviction and compaction never remove it, returning
+    /// whether it exists
+    ///
+    /// Fails if the maximum number of entries is already pinned.
+    pub async fn pin_memory(&self, id: Uuid) -> Result<bool> {
+        let Some(mut entry) = self.get_memory(id).await? else {
+            return Ok(false);
+        };
+        if !entry.is_pinned() {
+            self.check_pin_capacity().await?;
+            entry.metadata.insert(PINNED_KEY, true);
+            self.replace_entry(entry).await?;
+        }
+        Ok(true)
+    }
+
+    /// Unpin an entry, returning whether it exists
+    ///
+    /// The entry becomes subject to eviction again from the next insert.
+    pub async fn unpin_memory(&self, id: Uuid) -> Result<bool> {
+        let Some(mut entry) = self.get_memory(id).await? else {
+            return Ok(false);
+        };
+        if entry.is_pinned() {
+            entry.metadata.insert(PINNED_KEY, false);
+            self.replace_entry(entry).await?;
+        }
+        Ok(true)
+    }
+
+    /// Get the pinned entries, episodic entries first, each oldest first
+    pub async fn pinned_memory(&self) -> Result<Vec<MemoryEntry>> {
+        let mut entries = self.list_memory().await?;
+        entries.retain(MemoryEntry::is_pinned);
+        Ok(entries)
+    }
+
+    /// Fail if no more entries may be pinned
+    async fn check_pin_capacity(&self) -> Result<()> {
+        let max = self
+            .memory_config
+            .max_pinned
+            .unwrap_or(self.memory_config.capacity.div_ceil(4));
+        let pinned = self.pinned_memory().await?.len();
+        if pinned >= max {
+            return Err(Error::MemoryError(format!(
+                "Cannot pin more than {} memory entries",
+                max
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Remove entries from the search indexes
+    async fn unindex(&self, ids: &[Uuid]) -> Result<()> {
+        let mut index = self.index.write().await;
+        for id in ids {
+            index.remove(*id);
+        }
+        #[cfg(feature = "full-text")]
+        if let Some(text_index) = &self.text_index {
+            for id in ids {
+                text_index.remove(*id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Store holding entries of a kind
+    fn store_for(&self, kind: MemoryKind) -> &Arc<dyn MemoryStore> {
+        match kind {
+            MemoryKind::Episodic => &self.memory,
+            MemoryKind::Semantic => &self.semantic,
+        }
+    }
+
+    /// Capacity for entries of a kind
+    fn capacity_for(&self, kind: MemoryKind) -> usize {
+        match kind {
+            MemoryKind::Episodic => self.memory_config.capacity,
+            MemoryKind::Semantic => self
+                .memory_config
+                .semantic_capacity
+                .unwrap_or(self.memory_config.capacity),
+        }
+    }
+
+    /// Stores that can hold entries matching a filter
+    fn stores_for(&self, filter: &MemoryFilter) -> Vec<&Arc<dyn MemoryStore>> {
+        match filter.kind {
+            Some(kind) => vec![self.store_for(kind)],
+            None => vec![&self.memory, &self.semantic],
+        }
+    }
+
+    /// All entries, including expired ones, episodic first
+    async fn entries(&self) -> Result<Vec<MemoryEntry>> {
+        let mut entries = self.memory.list().await?;
+        entries.extend(self.semantic.list().await?);
+        Ok(entries)
+    }
+
+    /// Entries matching a filter, including expired ones, episodic first
+    async fn query_entries(&self, filter: &MemoryFilter) -> Result<Vec<MemoryEntry>> {
+        let mut entries = Vec::new();
+        for store in self.stores_for(filter) {
+            entries.extend(store.query(filter).await?);
+        }
+        Ok(entries)
+    }
+
+    /// Up to `limit` entries matching a filter in chronological order, after a cursor
+    async fn chronological_entries(
+        &self,
+        filter: &MemoryFilter,
+        after: Option<&MemoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<MemoryEntry>> {
+        let mut entries = Vec::new();
+        for store in self.stores_for(filter) {
+            entries.extend(store.chronological(filter, after, limit).await?);
+        }
+        entries.sort_by_key(MemoryCursor::of);
+        entries.truncate(limit);
+        Ok(entries)
+    }
+
+    /// Remove entries from the store, the search indexes and persistence
+    async fn remove_entries(&self, ids: &[Uuid]) -> Result<()> {
+        for id in ids {
+            if !self.memory.remove(*id).await? {
+                self.semantic.remove(*id).await?;
+            }
+        }
+        self.unindex(ids).await?;
+
+        if self.memory_config.persistent {
+            match self.store().await? {
+                Some(store) => {
+                    for id in ids {
+                        store.remove_memory(*id).await?;
+                    }
+                }
+                None => {
+                    let records: Vec<WalRecord> =
+                        ids.iter().map(|id| WalRecord::Remove { id: *id }).collect();
+                    self.persist_memory(&records).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a memory entry by ID
+    pub async fn get_memory(&self, id: Uuid) -> Result<Option<MemoryEntry>> {
+        let now = self.now();
+        let entry = match self.memory.get(id).await? {
+            Some(entry) => Some(entry),
+            None => self.semantic.get(id).await?,
+        };
+        Ok(entry.filter(|e| !e.is_expired(now)))
+    }
+
+    /// Search all scopes for the `k` entries most relevant to the query
+    ///
+    /// With an embedding provider, entries are ranked by semantic similarity
+    /// through the vector index, and with a full-text index by text
+    /// relevance; otherwise the memory store's text search is used.
+    pub async fn search_memory(&self, query: &str, k: usize) -> Result<Vec<MemoryEntry>> {
+        self.search_memory_matching(query, &MemoryFilter::new(), k)
+            .await
+    }
+
+    /// Search a single scope for the `k` entries most relevant to the query
+    pub async fn search_memory_in(
+        &self,
+        scope: MemoryScope,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<MemoryEntry>> {
+        self.search_memory_matching(query, &MemoryFilter::new().scope(scope), k)
+            .await
+    }
+
+    /// Search entries matching a filter for the `k` most relevant to the query
+    ///
+    /// With a scoring configuration, a wider pool of candidates is ranked by
+    /// similarity, recency and importance combined.
+    pub async fn search_memory_matching(
+        &self,
+        query: &str,
+        filter: &MemoryFilter,
+        k: usize,
+    ) -> Result<Vec<MemoryEntry>> {
+        let scoring = self.memory_config.scoring.as_ref();
+        let pool = scoring.map_or(k, |s| k.saturating_mul(s.candidates.max(1)));
+        let hits = self.search_hits(query, filter, pool).await?;
+        Ok(match scoring {
+            Some(scoring) => scoring.rank(hits, self.now(), k),
+            None => hits.into_iter().take(k).map(|(entry, _)| entry).collect(),
+        })
+    }
+
+    /// Find up to `k` entries matching a filter with their similarity to the query
+    async fn search_hits(
+        &self,
+        query: &str,
+        filter: &MemoryFilter,
+        k: usize,
+    ) -> Result<Vec<(MemoryEntry, f32)>> {
+        if let Some(embedder) = &self.embedder {
+            let embedding = embed_text(embedder.as_ref(), query.to_string()).await?;
+
+            let index = self.index.read().await;
+            return self
+                .collect_hits(filter, k, |fetch| Ok(index.search(&embedding, fetch)))
+                .await;
+        }
+
+        #[cfg(feature = "full-text")]
+        if let Some(text_index) = &self.text_index {
+            let mut hits = self
+                .collect_hits(filter, k, |fetch| text_index.search(query, fetch))
+                .await?;
+            // Text relevance is unbounded, so scale it against the best hit
+            let best = hits.first().map_or(1.0, |(_, score)| *score);
+            for (_, score) in &mut hits {
+                *score /= best.max(f32::EPSILON);
+            }
+            return Ok(hits);
+        }
+
+        // Facts are distilled, so they come before episodes
+        let now = self.now();
+        let mut results = Vec::new();
+        for store in self.stores_for(filter).into_iter().rev() {
+            if results.len() < k {
+                let mut found = store.search(query, filter, k).await?;
+                found.retain(|e| !e.is_expired(now));
+                results.extend(found);
+            }
+        }
+        results.truncate(k);
+        Ok(results.into_iter().map(|entry| (entry, 1.0)).collect())
+    }
+
+    /// Resolve ranked index hits to the first `k` live entries passing a filter
+    ///
+    /// The search is widened until enough entries pass or the index runs out.
+    async fn collect_hits<F>(
+        &self,
+        filter: &MemoryFilter,
+        k: usize,
+        search: F,
+    ) -> Result<Vec<(MemoryEntry, f32)>>
+    where
+        F: Fn(usize) -> Result<Vec<(Uuid, f32)>>,
+    {
+        let mut fetch = k;
+        loop {
+            let hits = search(fetch)?;
+            let exhausted = hits.len() < fetch;
+
+            let mut results = Vec::with_capacity(k);
+            for (id, score) in hits {
+                match self.get_memory(id).await? {
+                    Some(entry) if filter.matches(&entry) => results.push((entry, score)),
+                    _ => {}
+                }
+                if results.len() == k {
+                    break;
+                }
+            }
+            if results.len() == k || exhausted {
+                return Ok(results);
+            }
+            fetch = fetch.saturating_mul(2);
+        }
+    }
+
+    /// Get the `k` most recent episodic entries, newest first
+    pub async fn recent_episodes(&self, k: usize) -> Result<Vec<MemoryEntry>> {
+        let now = self.now();
+        let mut entries = self.memory.list().await?;
+        entries.retain(|e| !e.is_expired(now));
+        Ok(entries.into_iter().rev().take(k).collect())
+    }
+
+    /// Search semantic facts for the `k` most relevant to the query
+    pub async fn search_facts(&self, query: &str, k: usize) -> Result<Vec<MemoryEntry>> {
+        let filter = MemoryFilter::new().kind(MemoryKind::Semantic);
+        self.search_memory_matching(query, &filter, k).await
+    }
+
+    /// Get all memory entries, episodic entries first, each oldest first
+    pub async fn list_memory(&self) -> Result<Vec<MemoryEntry>> {
+        let now = self.now();
+        let mut entries = self.entries().await?;
+        entries.retain(|e| !e.is_expired(now));
+        Ok(entries)
+    }
+
+    /// Get the memory entries in a scope
+    pub async fn list_memory_in(&self, scope: &MemoryScope) -> Result<Vec<MemoryEntry>> {
+        self.list_memory_matching(&MemoryFilter::new().scope(scope.clone()))
+            .await
+    }
+
+    /// Get the memory entries from `from`, inclusive, to `to`, exclusive, oldest first
+    pub async fn list_memory_between(
+        &self,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<MemoryEntry>> {
+        let filter = MemoryFilter::new().between(from, to);
+        let now = self.now();
+        let mut entries = self
+            .chronological_entries(&filter, None, usize::MAX)
+            .await?;
+        entries.retain(|e| !e.is_expired(now));
+        Ok(entries)
+    }
+
+    /// Get up to `limit` entries matching a filter, oldest first, starting after a cursor
+    ///
+    /// Pass the returned `next` cursor to get the following page. Expired
+    /// entries are skipped, so a page may be short even when more follow.
+    pub async fn memory_page(
+        &self,
+        filter: &MemoryFilter,
+        after: Option<&MemoryCursor>,
+        limit: usize,
+    ) -> Result<MemoryPage> {
+        let mut entries = self.chronological_entries(filter, after, limit).await?;
+        let next = match entries.last() {
+            Some(last) if entries.len() == limit => Some(MemoryCursor::of(last)),
+            _ => None,
+        };
+        let now = self.now();
+        entries.retain(|e| !e.is_expired(now));
+        Ok(MemoryPage { entries, next })
+    }
+
+    /// Get the memory entries matching a filter
+    pub async fn list_memory_matching(&self, filter: &MemoryFilter) -> Result<Vec<MemoryEntry>> {
+        let now = self.now();
+        let mut entries = self.query_entries(filter).await?;
+        entries.retain(|e| !e.is_expired(now));
+        Ok(entries)
+    }
+
+    /// Remove every memory entry in a scope, returning how many were removed
+    pub async fn clear_scope(&self, scope: &MemoryScope) -> Result<usize> {
+        let ids: Vec<Uuid> = self
+            .query_entries(&MemoryFilter::new().scope(scope.clone()))
+            .await?
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        if !ids.is_empty() {
+            self.remove_entries(&ids).await?;
+        }
+        Ok(ids.len())
+    }
+
+    /// Remove expired memory entries, returning how many were removed
+    pub async fn purge_expired(&self) -> Result<usize> {
+        let now = self.now();
+        let expired: Vec<Uuid> = self
+            .entries()
+            .await?
+            .into_iter()
+            .filter(|e| e.is_expired(now))
+            .map(|e| e.id)
+            .collect();
+        if !expired.is_empty() {
+            self.remove_entries(&expired).await?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Periodically purge expired memory entries until the manager is dropped
+    pub fn spawn_expiry_sweep(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let manager = Arc::downgrade(self);
+        let clock = self.clock.clone();
+        tokio::spawn(async move {
+            loop {
+                clock.sleep(interval).await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                if let Err(e) = manager.purge_expired().await {
+                    tracing::warn!(error = %e, "Memory expiry sweep failed");
+                }
+            }
+        })
+    }
+
+    /// Replace all memory entries, keeping their IDs and embeddings
+    pub async fn replace_memory(&self, entries: Vec<MemoryEntry>) -> Result<()> {
+        self.set_memory(entries.clone()).await?;
+
+        if self.memory_config.persistent {
+            match self.store().await? {
+                Some(store) => {
+                    store.clear_memory().await?;
+                    for entry in &entries {
+                        store.put_memory(entry).await?;
+                    }
+                }
+                None => {
+                    let mut records = vec![WalRecord::Clear];
+                    records.extend(entries.into_iter().map(|entry| WalRecord::Put { entry }));
+                    self.persist_memory(&records).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Clear all memory entries
+    pub async fn clear_memory(&self) -> Result<()> {
+        self.memory.clear().await?;
+        self.semantic.clear().await?;
+        self.index.write().await.clear();
+        #[cfg(feature = "full-text")]
+        if let Some(text_index) = &self.text_index {
+            text_index.clear()?;
+        }
+        
+        if self.memory_config.persistent {
+            match self.store().await? {
+                Some(store) => store.clear_memory().await?,
+                None => self.persist_memory(&[WalRecord::Clear]).await?,
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// Write all live memory entries to a file, oldest first
+    ///
+    /// JSONL output is streamed entry by entry. Returns the number of
+    /// entries written.
+    pub async fn export_memory<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: MemoryFormat,
+    ) -> Result<usize> {
+        let entries = self.list_memory().await?;
+        let mut writer = BufWriter::new(tokio::fs::File::create(path).await?);
+        match format {
+            MemoryFormat::Jsonl => {
+                for entry in &entries {
+                    let mut line = serde_json::to_vec(entry)?;
+                    line.push(b'\n');
+                    writer.write_all(&line).await?;
+                }
+            }
+            MemoryFormat::Json => {
+                writer
+                    .write_all(&serde_json::to_vec_pretty(&entries)?)
+                    .await?;
+            }
+        }
+        writer.flush().await?;
+        Ok(entries.len())
+    }
+
+    /// Add the entries in an exported file to memory
+    ///
+    /// Accepts JSONL, read line by line, or a JSON array. Entries keep their
+    /// IDs, replacing existing entries with the same ID, and are re-embedded
+    /// with the configured provider. Returns the number of entries imported.
+    pub async fn import_memory<P: AsRef<Path>>(&self, path: P) -> Result<usize> {
+        let mut reader = BufReader::new(tokio::fs::File::open(path).await?);
+        let start = reader.fill_buf().await?.iter().find(|b| !b.is_ascii_whitespace());
+        if start == Some(&b'[') {
+            let mut json = Vec::new();
+            reader.read_to_end(&mut json).await?;
+            let entries: Vec<MemoryEntry> = serde_json::from_slice(&json)?;
+            let count = entries.len();
+            for entry in entries {
+                self.insert_entry(entry).await?;
+            }
+            return Ok(count);
+        }
+
+        let mut lines = reader.lines();
+        let mut count = 0;
+        let mut number = 0;
+        while let Some(line) = lines.next_line().await? {
+            number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: MemoryEntry = serde_json::from_str(&line).map_err(|e| {
+                Error::MemoryError(format!("Invalid memory entry on line {}: {}", number, e))
+            })?;
+            self.insert_entry(entry).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Get a task state by ID
+    pub async fn get_task(&self, id: Uuid) -> Result<Option<TaskState>> {
+        let state = self.state.read().await;
+        Ok(state.tasks.get(&id).cloned())
+    }
+
+    /// Update a task state
+    ///
+    /// Memory scoped to the task is removed once it completes or fails.
+    pub async fn update_task(&self, task: TaskState) -> Result<()> {
+        if matches!(task.status, TaskStatus::Completed | TaskStatus::Failed) {
+            self.clear_scope(&MemoryScope::Task(task.id)).await?;
+        }
+
+        if self.memory_config.persistent {
+            if let Some(store) = self.store().await? {
+                store.put_task(&task).await?;
+            }
+        }
+
+        let to = task.status;
+        let id = task.id;
+        let from = self.state.write().await.tasks.insert(id, task).map(|t| t.status);
+        if from != Some(to) {
+            self.notify(StateChange::TaskTransitioned { id, from, to });
+        }
+        Ok(())
+    }
+
+    /// Remove a task state
+    pub async fn remove_task(&self, id: Uuid) -> Result<()> {
+        if self.memory_config.persistent {
+            if let Some(store) = self.store().await? {
+                store.remove_task(id).await?;
+            }
+        }
+
+        let mut state = self.state.write().await;
+        state.tasks.remove(&id);
+        Ok(())
+    }
+
+    /// Queue memory changes for the file persistence log
+    ///
+    /// The changes are written in the background; [`Self::flush`] waits
+    /// for them. Queuing waits while the writer is far behind the disk.
+    async fn persist_memory(&self, records: &[WalRecord]) -> Result<()> {
+        if let Some(wal) = &self.wal {
+            let writer = self.wal_writer.get_or_init(|| {
+                let delay = self.memory_config.flush_interval_ms.unwrap_or(0);
+                WalWriter::spawn(
+                    wal.clone(),
+                    vec![self.memory.clone(), self.semantic.clone()],
+                    Duration::from_millis(delay),
+                )
+            });
+            writer.send(records.to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    /// Wait until every memory change so far is persisted
+    ///
+    /// File persistence writes changes in the background, and a failed
+    /// write is only reported here. Database backends write synchronously,
+    /// so there is nothing to wait for.
+    pub async fn flush(&self) -> Result<()> {
+        match self.wal_writer.get() {
+            Some(writer) => writer.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Get how far file persistence is behind memory, without waiting for
+    /// it or writing anything
+    pub fn persistence_status(&self) -> WriterStatus {
+        self.wal_writer
+            .get()
+            .map(WalWriter::status)
+            .unwrap_or_default()
+    }
+
+    /// Get the database store, opening the configured backend on first use
+    ///
+    /// Returns `None` for file persistence unless a store was provided.
+    async fn store(&self) -> Result<Option<&Arc<dyn StateStore>>> {
+        if let Some(store) = self.store.get() {
+            return Ok(Some(store));
+        }
+        if self.memory_config.backend == PersistenceBackend::File {
+            return Ok(None);
+        }
+        self.store
+            .get_or_try_init(|| self.open_store())
+            .await
+            .map(Some)
+    }
+
+    /// Open the database store for the configured backend
+    async fn open_store(&self) -> Result<Arc<dyn StateStore>> {
+        let config = &self.memory_config;
+        match config.backend {
+            PersistenceBackend::File => Err(Error::InvalidConfig(
+                "File persistence does not use a database store".to_string(),
+            )
+            .into()),
+            #[cfg(feature = "sqlite")]
+            PersistenceBackend::Sqlite => {
+                let path = config.persist_path.as_ref().ok_or_else(|| {
+                    Error::InvalidConfig("SQLite persistence requires persist_path".to_string())
+                })?;
+                Ok(Arc::new(SqliteStore::open(path)?.with_clock(self.clock.clone())))
+            }
+            #[cfg(feature = "postgres")]
+            PersistenceBackend::Postgres => {
+                let url = config.database_url.as_ref().ok_or_else(|| {
+                    Error::InvalidConfig("Postgres persistence requires database_url".to_string())
+                })?;
+                Ok(Arc::new(PostgresStore::connect(url).await?))
+            }
+            #[cfg(not(feature = "sqlite"))]
+            PersistenceBackend::Sqlite => Err(Error::InvalidConfig(
+                "SQLite persistence requires the `sqlite` feature".to_string(),
+            )
+            .into()),
+            #[cfg(not(feature = "postgres"))]
+            PersistenceBackend::Postgres => Err(Error::InvalidConfig(
+                "Postgres persistence requires the `postgres` feature".to_string(),
+            )
+            .into()),
+        }
+    }
+
+    /// Load persisted memory and tasks, replacing the memory held in process
+    pub async fn load_memory(&self) -> Result<()> {
+        if let Some(store) = self.store().await? {
+            let entries = store.load_memory().await?;
+            let tasks = store.load_tasks().await?;
+            self.set_memory(entries).await?;
+
+            let mut state = self.state.write().await;
+            state.tasks.extend(tasks.into_iter().map(|t| (t.id, t)));
+            return Ok(());
+        }
+
+        if let Some(wal) = &self.wal {
+            let entries = wal.load().await?;
+            self.set_memory(entries).await?;
+        }
+        Ok(())
+    }
+
+    /// Replace memory and rebuild the search indexes
+    async fn set_memory(&self, entries: Vec<MemoryEntry>) -> Result<()> {
+        let mut index = self.index.write().await;
+        index.clear();
+        for entry in &entries {
+            if let Some(embedding) = &entry.embedding {
+                index.insert(entry.id, embedding);
+            }
+        }
+        drop(index);
+        #[cfg(feature = "full-text")]
+        if let Some(text_index) = &self.text_index {
+            text_index.clear()?;
+            for entry in &entries {
+                text_index.insert(entry)?;
+            }
+        }
+
+        self.memory.clear().await?;
+        self.semantic.clear().await?;
+        for entry in entries {
+            self.store_for(entry.kind).put(entry).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AsyncAgentState for AgentStateManager {
+    async fn update(&self, data: Metadata) -> Result<()> {
+        self.update_state(data).await
+    }
+
+    async fn snapshot(&self) -> Result<Metadata> {
+        AgentStateManager::snapshot(self).await
+    }
+}
+
+/// Embed a single text
+async fn embed_text(embedder: &dyn EmbeddingProvider, text: String) -> Result<Vec<f32>> {
+    embedder.embed(&[text]).await?.pop().ok_or_else(|| {
+        Error::MemoryError("Embedding provider returned no vector".to_string()).into()
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::HashEmbedder;
+    use crate::memory::{DuplicateAction, TagExpr};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_state_update() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default());
+        
+        let mut data = Metadata::new();
+        data.insert("test", "value");
+        
+        manager.update_state(data).await.unwrap();
+        
+        let state = manager.state.read().await;
+        assert_eq!(
+            state.memory.get("test").unwrap().as_str().unwrap(),
+            "value"
+        );
+        drop(state);
+
+        let mut data = Metadata::new();
+        data.insert("profile", json!({"name": "atlas", "skills": ["search"]}));
+        manager.update_state(data).await.unwrap();
+        let mut data = Metadata::new();
+        data.insert("profile", json!({"skills": ["fetch"]}));
+        manager
+            .merge_state(data, MergeStrategy::CombineArrays)
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.get_state("profile").await.unwrap(),
+            Some(json!({"name": "atlas", "skills": ["search", "fetch"]}))
+        );
+
+        let merges = (0..10).map(|i| {
+            let mut data = Metadata::new();
+            data.insert("seen", json!([i]));
+            manager.merge_state(data, MergeStrategy::CombineArrays)
+        });
+        for merged in futures::future::join_all(merges).await {
+            merged.unwrap();
+        }
+        let seen = manager.get_state("seen").await.unwrap().unwrap();
+        assert_eq!(seen.as_array().unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_memory_management() {
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                capacity: 2,
+                ..Default::default()
+            },
+        );
+
+        let id1 = manager
+            .add_memory(json!("entry1"), Metadata::new())
+            .await
+            .unwrap();
+        let id2 = manager
+            .add_memory(json!("entry2"), Metadata::new())
+            .await
+            .unwrap();
+        let id3 = manager
+            .add_memory(json!("entry3"), Metadata::new())
+            .await
+            .unwrap();
+
+        // First entry should be removed due to capacity limit
+        assert!(manager.get_memory(id1).await.unwrap().is_none());
+        assert!(manager.get_memory(id2).await.unwrap().is_some());
+        assert!(manager.get_memory(id3).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_memory_search() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default());
+
+        manager
+            .add_memory(json!({"text": "test entry"}), Metadata::new())
+            .await
+            .unwrap();
+        manager
+            .add_memory(json!({"text": "another entry"}), Metadata::new())
+            .await
+            .unwrap();
+
+        let results = manager.search_memory("test", 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_task_scoped_memory() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default())
+            .with_embedder(Arc::new(HashEmbedder::default()));
+        let task_id = Uuid::new_v4();
+        let scope = MemoryScope::Task(task_id);
+
+        manager
+            .add_memory(json!("deploy runbook"), Metadata::new())
+            .await
+            .unwrap();
+        manager
+            .add_memory_in(scope.clone(), json!("deploy step one done"), Metadata::new())
+            .await
+            .unwrap();
+
+        let results = manager.search_memory_in(scope.clone(), "deploy", 5).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].scope, scope);
+        assert_eq!(manager.search_memory("deploy", 5).await.unwrap().len(), 2);
+
+        let mut task = TaskState {
+            id: task_id,
+            status: TaskStatus::Running,
+            result: None,
+            error: None,
+            steps: Vec::new(),
+            usage: Default::default(),
+        };
+        manager.update_task(task.clone()).await.unwrap();
+        assert_eq!(manager.list_memory_in(&scope).await.unwrap().len(), 1);
+
+        task.status = TaskStatus::Completed;
+        manager.update_task(task).await.unwrap();
+        assert!(manager.list_memory_in(&scope).await.unwrap().is_empty());
+        assert_eq!(manager.list_memory().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tagged_memory() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default());
+        let task = MemoryScope::Task(Uuid::new_v4());
+        for (scope, tags) in [
+            (task.clone(), json!(["observation", "tool:search"])),
+            (task.clone(), json!(["observation", "tool:fetch"])),
+            (MemoryScope::Global, json!(["observation", "tool:search"])),
+        ] {
+            let mut metadata = Metadata::new();
+            metadata.insert(TAGS_KEY, tags);
+            manager
+                .add_memory_in(scope, json!("result"), metadata)
+                .await
+                .unwrap();
+        }
+
+        let filter = MemoryFilter::new()
+            .scope(task)
+            .tags(TagExpr::tag("observation").and(TagExpr::tag("tool:search")));
+        let entries = manager.list_memory_matching(&filter).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].tags.contains("tool:search"));
+
+        let filter = MemoryFilter::new().tags(!TagExpr::tag("tool:fetch"));
+        assert_eq!(
+            manager
+                .search_memory_matching("result", &filter, 10)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[cfg(feature = "full-text")]
+    #[tokio::test]
+    async fn test_full_text_memory_search() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default())
+            .with_text_index(TextIndex::new().unwrap());
+        for text in ["searched the web for flights", "flights to Paris are cheap in spring"] {
+            manager.add_memory(json!(text), Metadata::new()).await.unwrap();
+        }
+
+        let hits = manager.search_memory("\"paris are cheap\"", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(manager.search_memory("Flights", 10).await.unwrap().len(), 2);
+
+        manager.clear_memory().await.unwrap();
+        assert!(manager.search_memory("flights", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_export_import() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default());
+        let mut metadata = Metadata::new();
+        metadata.insert(TAGS_KEY, ["curated"]);
+        manager.add_memory(json!("first"), metadata).await.unwrap();
+        manager.add_memory(json!({"second": 2}), Metadata::new()).await.unwrap();
+
+        let dir = std::env::temp_dir().join(format!("atlas-export-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let formats = [
+            ("memory.jsonl", MemoryFormat::Jsonl),
+            ("memory.json", MemoryFormat::Json),
+        ];
+        for (file, format) in formats {
+            let path = dir.join(file);
+            assert_eq!(manager.export_memory(&path, format).await.unwrap(), 2);
+
+            let seeded = AgentStateManager::new(State::default(), MemoryConfig::default());
+            assert_eq!(seeded.import_memory(&path).await.unwrap(), 2);
+            assert_eq!(
+                serde_json::to_value(seeded.list_memory().await.unwrap()).unwrap(),
+                serde_json::to_value(manager.list_memory().await.unwrap()).unwrap()
+            );
+        }
+
+        let path = dir.join("bad.jsonl");
+        tokio::fs::write(&path, "\n{\"oops\": true}\n").await.unwrap();
+        let err = manager.import_memory(&path).await.unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_persistence_log() {
+        let dir = std::env::temp_dir().join(format!("atlas-persist-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let config = MemoryConfig {
+            capacity: 3,
+            persistent: true,
+            persist_path: Some(dir.join("memory.json").to_string_lossy().into_owned()),
+            wal_compact_after: Some(4),
+            ..Default::default()
+        };
+
+        let manager = AgentStateManager::new(State::default(), config.clone());
+        for i in 0..5 {
+            manager
+                .add_memory(json!(format!("entry {}", i)), Metadata::new())
+                .await
+                .unwrap();
+        }
+        // Two evictions and five puts were logged, compacting once
+        manager.flush().await.unwrap();
+        assert!(tokio::fs::try_exists(dir.join("memory.json")).await.unwrap());
+
+        let restored = AgentStateManager::new(State::default(), config)
+            .with_persistence()
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(restored.list_memory().await.unwrap()).unwrap(),
+            serde_json::to_value(manager.list_memory().await.unwrap()).unwrap()
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_with_persistence_round_trip() {
+        let dir = std::env::temp_dir().join(format!("atlas-persist-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let config = MemoryConfig {
+            persistent: true,
+            persist_path: Some(dir.join("memory.json").to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let manager = AgentStateManager::new(State::default(), config.clone());
+        let id = manager
+            .add_memory(json!("remember me"), Metadata::new())
+            .await
+            .unwrap();
+        manager.flush().await.unwrap();
+
+        // Entries are loaded into the store configured before loading
+        let store = Arc::new(InMemoryStore::new());
+        let restored = AgentStateManager::new(State::default(), config.clone())
+            .with_memory_store(store.clone())
+            .with_persistence()
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get(id).await.unwrap().unwrap().data,
+            json!("remember me")
+        );
+        assert_eq!(restored.list_memory().await.unwrap().len(), 1);
+
+        // Load errors surface instead of starting with empty memory
+        tokio::fs::write(dir.join("memory.json"), b"not json")
+            .await
+            .unwrap();
+        let broken = AgentStateManager::new(State::default(), config);
+        assert!(broken.with_persistence().await.is_err());
+        let transient = AgentStateManager::new(State::default(), MemoryConfig::default());
+        assert!(transient.with_persistence().await.is_err());
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_state_spill() {
+        let dir = std::env::temp_dir().join(format!("atlas-spill-{}", Uuid::new_v4()));
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                persist_path: Some(dir.join("memory.json").to_string_lossy().into_owned()),
+                spill: Some(SpillConfig {
+                    max_keys: Some(2),
+                    max_bytes: None,
+                }),
+                ..Default::default()
+            },
+        );
+        for (key, value) in [("plan", 1), ("draft", 2), ("notes", 3)] {
+            let mut data = Metadata::new();
+            data.insert(key, value);
+            manager.update_state(data).await.unwrap();
+        }
+        assert_eq!(manager.state().read().await.memory.len(), 2);
+        assert!(!manager.state().read().await.memory.contains_key("plan"));
+
+        // Reading a spilled key pages it in and spills the coldest other key
+        assert_eq!(manager.get_state("plan").await.unwrap(), Some(json!(1)));
+        let state = manager.state().read().await.memory.clone();
+        assert_eq!(state.keys().len(), 2);
+        assert!(state.contains_key("plan") && !state.contains_key("draft"));
+
+        let snapshot = manager.snapshot().await.unwrap();
+        assert_eq!(snapshot.get::<i64>("draft"), Some(2));
+        assert_eq!(snapshot.get::<i64>("notes"), Some(3));
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_state_change_stream() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default());
+        let mut changes = manager.subscribe();
+
+        let mut data = Metadata::new();
+        data.insert("mood", "curious");
+        manager.update_state(data.clone()).await.unwrap();
+        // Setting the same value again is not a change
+        manager.update_state(data).await.unwrap();
+        let id = manager
+            .add_memory(json!("saw a fox"), Metadata::new())
+            .await
+            .unwrap();
+        let task = TaskState {
+            id: Uuid::new_v4(),
+            status: TaskStatus::Running,
+            result: None,
+            error: None,
+            steps: Vec::new(),
+            usage: Default::default(),
+        };
+        manager.update_task(task).await.unwrap();
+
+        match changes.next().await.unwrap() {
+            StateChange::StateUpdated { diff } => {
+                assert_eq!(diff.added.get("mood"), Some(&json!("curious")));
+            }
+            change => panic!("unexpected change: {:?}", change),
+        }
+        match changes.next().await.unwrap() {
+            StateChange::MemoryAdded { entry } => assert_eq!(entry.id, id),
+            change => panic!("unexpected change: {:?}", change),
+        }
+        assert!(matches!(
+            changes.next().await.unwrap(),
+            StateChange::TaskTransitioned {
+                from: None,
+                to: TaskStatus::Running,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_rollback() {
+        let dir = std::env::temp_dir().join(format!("atlas-tx-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                persistent: true,
+                persist_path: Some(dir.join("memory.json").to_string_lossy().into_owned()),
+                wal_compact_after: Some(1),
+                ..Default::default()
+            },
+        );
+        let kept = manager
+            .add_memory(json!("kept"), Metadata::new())
+            .await
+            .unwrap();
+        let mut changes = manager.subscribe();
+        let step = |n: i64| {
+            let mut data = Metadata::new();
+            data.insert("step", n);
+            data
+        };
+
+        // The closure fails, so nothing is applied
+        let result: Result<()> = manager
+            .transaction(|tx| {
+                Box::pin(async move {
+                    tx.update_state(step(1));
+                    tx.remove_memory(kept);
+                    Err(Error::ToolExecutionFailed("search timed out".to_string()).into())
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(manager.snapshot().await.unwrap().get::<i64>("step"), None);
+
+        // Persisting the removal fails midway, so the state update is rolled back
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        let result = manager
+            .transaction(|tx| {
+                Box::pin(async move {
+                    tx.update_state(step(2));
+                    tx.remove_memory(kept);
+                    Ok(())
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(manager.snapshot().await.unwrap().get::<i64>("step"), None);
+        assert!(manager.get_memory(kept).await.unwrap().is_some());
+
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let added = manager
+            .transaction(|tx| {
+                Box::pin(async move {
+                    tx.update_state(step(3));
+                    Ok(tx.add_memory(json!("added"), Metadata::new()))
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(manager.snapshot().await.unwrap().get::<i64>("step"), Some(3));
+        assert!(manager.get_memory(added).await.unwrap().is_some());
+
+        // Subscribers only see committed changes
+        match changes.next().await.unwrap() {
+            StateChange::StateUpdated { diff } => {
+                assert_eq!(diff.added.get("step"), Some(&json!(3)));
+            }
+            change => panic!("unexpected change: {:?}", change),
+        }
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_memory_time_range() {
+        let manager = AgentStateManager::new(State::default(), MemoryConfig::default())
+            .with_clock(Arc::new(atlas_core::MockClock::default()));
+        let now = manager.now();
+        let mut ids = Vec::new();
+        // Entries are inserted out of chronological order
+        for minutes in [90, 30, 10, 45] {
+            let mut entry = MemoryEntry::new(json!(minutes), Metadata::new());
+            entry.timestamp = now - chrono::Duration::minutes(minutes);
+            ids.push(entry.id);
+            manager.insert_entry(entry).await.unwrap();
+        }
+
+        let last_hour = manager
+            .list_memory_between(now - chrono::Duration::hours(1), now)
+            .await
+            .unwrap();
+        assert_eq!(
+            last_hour.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![ids[3], ids[1], ids[2]]
+        );
+
+        let all = MemoryFilter::new();
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = manager.memory_page(&all, cursor.as_ref(), 3).await.unwrap();
+            seen.extend(page.entries.iter().map(|e| e.id));
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec![ids[0], ids[3], ids[1], ids[2]]);
+    }
+
+    #[tokio::test]
+    async fn test_episodic_and_semantic_memory() {
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                capacity: 4,
+                semantic_capacity: Some(10),
+                promotion: Some(PromotionConfig { min_occurrences: 2 }),
+                ..Default::default()
+            },
+        );
+        manager
+            .add_fact(json!("The API base URL is api.example.com"), Metadata::new())
+            .await
+            .unwrap();
+        for text in ["Build failed on CI", "build failed  on ci", "Build failed on CI"] {
+            manager.add_memory(json!(text), Metadata::new()).await.unwrap();
+        }
+        // Episodes filling episodic capacity do not evict facts
+        for i in 0..4 {
+            manager
+                .add_memory(json!(format!("step {}", i)), Metadata::new())
+                .await
+                .unwrap();
+        }
+
+        let facts = manager.search_facts("", 10).await.unwrap();
+        assert_eq!(facts.len(), 2);
+        let promoted = &facts[1];
+        assert_eq!(promoted.kind, MemoryKind::Semantic);
+        assert_eq!(promoted.metadata.get::<usize>(OCCURRENCES_KEY), Some(3));
+
+        let recent = manager.recent_episodes(2).await.unwrap();
+        assert_eq!(recent[0].data, json!("step 3"));
+        assert_eq!(recent[1].data, json!("step 2"));
+        assert_eq!(manager.list_memory().await.unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_memory_expiry() {
+        let manager = Arc::new(AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                default_ttl_secs: Some(3600),
+                ..Default::default()
+            },
+        ));
+
+        let mut short = Metadata::new();
+        short.insert(TTL_KEY, 0);
+        let expired = manager.add_memory(json!("stale"), short).await.unwrap();
+        let kept = manager.add_memory(json!("fresh"), Metadata::new()).await.unwrap();
+
+        assert!(manager.get_memory(expired).await.unwrap().is_none());
+        assert_eq!(manager.list_memory().await.unwrap().len(), 1);
+        let fresh = manager.get_memory(kept).await.unwrap().unwrap();
+        assert!(fresh.expires_at.unwrap() > fresh.timestamp);
+
+        let sweep = manager.spawn_expiry_sweep(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(manager.memory.len().await.unwrap(), 1);
+        sweep.abort();
+
+        // TTLs too long to represent never expire instead of panicking
+        let mut forever = Metadata::new();
+        forever.insert(TTL_KEY, u64::MAX);
+        let id = manager.add_memory(json!("forever"), forever).await.unwrap();
+        assert!(manager.get_memory(id).await.unwrap().unwrap().expires_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_expiry_with_clock() {
+        let clock = atlas_core::MockClock::default();
+        let manager = Arc::new(
+            AgentStateManager::new(
+                State::default(),
+                MemoryConfig {
+                    default_ttl_secs: Some(60),
+                    ..Default::default()
+                },
+            )
+            .with_clock(Arc::new(clock.clone())),
+        );
+        let id = manager.add_memory(json!("note"), Metadata::new()).await.unwrap();
+        let entry = manager.get_memory(id).await.unwrap().unwrap();
+        assert_eq!(entry.timestamp, chrono::DateTime::<chrono::Utc>::UNIX_EPOCH);
+
+        let sweep = manager.spawn_expiry_sweep(Duration::from_secs(30));
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(30));
+        tokio::task::yield_now().await;
+        assert_eq!(manager.memory.len().await.unwrap(), 1);
+
+        // The next sweep runs once the entry has expired
+        clock.advance(Duration::from_secs(30));
+        assert!(manager.get_memory(id).await.unwrap().is_none());
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while manager.memory.len().await.unwrap() > 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        sweep.abort();
+    }
+
+    #[tokio::test]
+    async fn test_memory_pinning() {
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                capacity: 2,
+                max_pinned: Some(1),
+                ..Default::default()
+            },
+        );
+
+        let mut metadata = Metadata::new();
+        metadata.insert(PINNED_KEY, true);
+        let rule = manager.add_memory(json!("reply in french"), metadata).await.unwrap();
+        let note = manager.add_memory(json!("note"), Metadata::new()).await.unwrap();
+        assert!(manager.pin_memory(note).await.is_err());
+        for i in 0..3 {
+            manager.add_memory(json!(i), Metadata::new()).await.unwrap();
+        }
+        assert!(manager.get_memory(rule).await.unwrap().is_some());
+        assert_eq!(manager.pinned_memory().await.unwrap().len(), 1);
+
+        assert!(manager.unpin_memory(rule).await.unwrap());
+        manager.add_memory(json!("later"), Metadata::new()).await.unwrap();
+        assert!(manager.get_memory(rule).await.unwrap().is_none());
+        assert!(!manager.pin_memory(rule).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_deduplication() {
+        let config = |action| MemoryConfig {
+            dedup: Some(DedupConfig {
+                action,
+                similarity_threshold: Some(0.99),
+            }),
+            ..Default::default()
+        };
+        let manager = AgentStateManager::new(State::default(), config(DuplicateAction::Count))
+            .with_embedder(Arc::new(HashEmbedder::default()));
+        let first = manager
+            .add_memory(json!("the deploy failed with a timeout"), Metadata::new())
+            .await
+            .unwrap();
+        for text in ["the deploy failed with a timeout", "The deploy failed, with a timeout!"] {
+            let id = manager.add_memory(json!(text), Metadata::new()).await.unwrap();
+            assert_eq!(id, first);
+        }
+        let task = MemoryScope::Task(Uuid::new_v4());
+        let scoped = manager
+            .add_memory_in(task, json!("the deploy failed with a timeout"), Metadata::new())
+            .await
+            .unwrap();
+        assert_ne!(scoped, first);
+        let entry = manager.get_memory(first).await.unwrap().unwrap();
+        assert_eq!(entry.metadata.get::<usize>(OCCURRENCES_KEY), Some(3));
+
+        let manager = AgentStateManager::new(State::default(), config(DuplicateAction::Skip));
+        manager.add_memory(json!({"status": 500}), Metadata::new()).await.unwrap();
+        manager.add_memory(json!({"status": 500}), Metadata::new()).await.unwrap();
+        assert_eq!(manager.list_memory().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_memory_search() {
+        let manager = AgentStateManager::new(
+            State::default(),
+            MemoryConfig {
+                capacity: 3,
+                ..Default::default()
+            },
+        )
+        .with_embedder(Arc::new(HashEmbedder::default()));
+
+        for text in [
+            "the deploy failed with a timeout",
+            "user prefers dark mode",
+            "weekly report sent to finance",
+            "deploy succeeded after retry",
+        ] {
+            manager.add_memory(json!(text), Metadata::new()).await.unwrap();
+        }
+
+        let results = manager.search_memory("deploy status", 2).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].data, json!("deploy succeeded after retry"));
+        assert!(results
+            .iter()
+            .all(|e| e.data != json!("the deploy failed with a timeout")));
     }
 }
@@ -0,0 +1,164 @@
+//! System prompt composition
+
+use atlas_mcp::ToolInfo;
+
+use crate::Config;
+
+/// Composable system prompt builder
+///
+/// Produces an identity line from the agent name and description, followed
+/// by capability and tool lists and any custom sections, in that order.
+/// Empty parts are omitted.
+#[derive(Clone, Debug, Default)]
+pub struct SystemPromptBuilder {
+    /// Agent name
+    name: Option<String>,
+
+    /// Agent description
+    description: Option<String>,
+
+    /// Agent capabilities
+    capabilities: Vec<String>,
+
+    /// Available tools
+    tools: Vec<ToolInfo>,
+
+    /// Custom sections as (title, body)
+    sections: Vec<(String, String)>,
+}
+
+impl SystemPromptBuilder {
+    /// Create an empty builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder with the identity and capabilities from an agent configuration
+    pub fn from_config(config: &Config) -> Self {
+        let builder = Self::new()
+            .name(config.name.clone())
+            .capabilities(config.capabilities.clone());
+        match &config.description {
+            Some(description) => builder.description(description.clone()),
+            None => builder,
+        }
+    }
+
+    /// Set the agent name
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the agent description
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the agent capabilities
+    pub fn capabilities(mut self, capabilities: Vec<String>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set the available tools
+    pub fn tools(mut self, tools: Vec<ToolInfo>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Append a custom section
+    pub fn section<T: Into<String>, B: Into<String>>(mut self, title: T, body: B) -> Self {
+        self.sections.push((title.into(), body.into()));
+        self
+    }
+
+    /// Build the prompt
+    pub fn build(&self) -> String {
+        let mut parts = Vec::new();
+
+        match (&self.name, &self.description) {
+            (Some(name), Some(description)) => {
+                parts.push(format!("You are {}. {}", name, description))
+            }
+            (Some(name), None) => parts.push(format!("You are {}.", name)),
+            (None, Some(description)) => parts.push(description.clone()),
+            (None, None) => {}
+        }
+
+        if !self.capabilities.is_empty() {
+            parts.push(format!(
+                "Capabilities:\n{}",
+                bullet_list(self.capabilities.iter())
+            ));
+        }
+
+        if !self.tools.is_empty() {
+            let mut tools: Vec<&ToolInfo> = self.tools.iter().collect();
+            tools.sort_by(|a, b| a.name.cmp(&b.name));
+            let lines = tools
+                .iter()
+                .map(|t| format!("{}: {}", t.name, t.description));
+            parts.push(format!("Available tools:\n{}", bullet_list(lines)));
+        }
+
+        for (title, body) in &self.sections {
+            parts.push(format!("{}:\n{}", title, body));
+        }
+
+        parts.join("\n\n")
+    }
+}
+
+fn bullet_list<I, S>(items: I) -> String
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    items
+        .map(|item| format!("- {}", item.as_ref()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::Metadata;
+
+    #[test]
+    fn test_build_from_config() {
+        let config = Config {
+            name: "Scout".to_string(),
+            description: Some("You research topics.".to_string()),
+            capabilities: vec!["search".to_string()],
+            config: Metadata::new(),
+        };
+        let tools = vec![
+            ToolInfo {
+                name: "web_search".to_string(),
+                description: "Search the web".to_string(),
+                input_schema: None,
+            },
+            ToolInfo {
+                name: "fetch".to_string(),
+                description: "Fetch a page".to_string(),
+                input_schema: None,
+            },
+        ];
+
+        let prompt = SystemPromptBuilder::from_config(&config)
+            .tools(tools)
+            .section("Rules", "Cite sources.")
+            .build();
+        assert_eq!(
+            prompt,
+            "You are Scout. You research topics.\n\n\
+             Capabilities:\n- search\n\n\
+             Available tools:\n- fetch: Fetch a page\n- web_search: Search the web\n\n\
+             Rules:\nCite sources."
+        );
+        assert_eq!(SystemPromptBuilder::new().build(), "");
+    }
+}
@@ -4,30 +4,15 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-
-use atlas_core::Metadata;
+use atlas_core::{CancellationContext, Event, EventBus, Metadata, ToolDescriptor};
 use atlas_mcp::MCPTool;
 
 use crate::error::Error;
+use crate::guardrails::{Guardrail, GuardrailSet, GuardrailTarget};
+use crate::policy::{ToolPolicy, TOOL_POLICY_VIOLATION};
 
-/// Tool configuration
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct ToolConfig {
-    /// Tool name
-    pub name: String,
-    
-    /// Tool description
-    pub description: String,
-    
-    /// Tool configuration
-    pub config: Metadata,
-    
-    /// Tool input schema
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub input_schema: Option<Value>,
-}
+/// Tool configuration, as described to every layer
+pub type ToolConfig = ToolDescriptor;
 
 /// Tool execution context
 #[derive(Clone, Debug)]
@@ -57,13 +42,22 @@ impl ToolContext {
 }
 
 /// Tool registry for managing agent tools
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct ToolManager {
     /// Registered tools
     pub(crate) tools: HashMap<String, Arc<dyn MCPTool>>,
     
     /// Tool configurations
     configs: HashMap<String, ToolConfig>,
+
+    /// Consecutive failed executions of each tool that failed last time
+    failures: std::sync::Mutex<HashMap<String, u32>>,
+
+    /// Tools that may run and the rules of their parameters, if restricted
+    policy: Option<ToolPolicy>,
+
+    /// Bus refused executions are reported on, with the agent reporting them
+    audit: Option<(Arc<dyn EventBus>, String)>,
 }
 
 impl ToolManager {
@@ -72,6 +66,9 @@ impl ToolManager {
         Self {
             tools: HashMap::new(),
             configs: HashMap::new(),
+            failures: std::sync::Mutex::new(HashMap::new()),
+            policy: None,
+            audit: None,
         }
     }
 
@@ -82,11 +79,10 @@ impl ToolManager {
     {
         let config = ToolConfig {
             name: name.clone(),
-            description: tool.description().to_string(),
-            config: Metadata::new(),
-            input_schema: None,
+            ..tool.descriptor()
         };
-        
+
+
         self.configs.insert(name.clone(), config);
         self.tools.insert(name, Arc::new(tool));
     }
@@ -115,6 +111,89 @@ impl ToolManager {
         self.configs.values().collect()
     }
 
+    /// Restrict which tools may run and with which parameters
+    pub fn set_policy(&mut self, policy: ToolPolicy) {
+        self.policy = Some(policy);
+    }
+
+    /// Get the policy restricting the tools, if any
+    pub fn policy(&self) -> Option<&ToolPolicy> {
+        self.policy.as_ref()
+    }
+
+    /// Report the executions the policy refuses on a bus, on behalf of an
+    /// agent
+    pub fn set_audit(&mut self, bus: Arc<dyn EventBus>, agent: impl Into<String>) {
+        self.audit = Some((bus, agent.into()));
+    }
+
+    /// Execute a tool by name, in the current [`CancellationContext`] if any
+    ///
+    /// Executions the policy refuses fail with
+    /// [`Error::ToolPolicyViolation`] and are reported on
+    /// [`TOOL_POLICY_VIOLATION`].
+    pub async fn execute(&self, name: &str, params: Metadata) -> Result<Metadata> {
+        self.enforce(name, &params).await?;
+        let tool = self
+            .get(name)
+            .ok_or_else(|| Error::ToolNotFound(name.to_string()))?;
+        let result = match CancellationContext::current() {
+            Some(cancel) => tool.execute_with(params, &cancel).await,
+            None => tool.execute(params).await,
+        };
+        let mut failures = self
+            .failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        match &result {
+            Ok(_) => {
+                failures.remove(name);
+            }
+            Err(_) => *failures.entry(name.to_string()).or_default() += 1,
+        }
+        result
+    }
+
+    /// Get the tools whose last execution failed, with how many times in a
+    /// row they failed
+    pub fn tool_failures(&self) -> Vec<(String, u32)> {
+        self.failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .iter()
+            .map(|(name, failures)| (name.clone(), *failures))
+            .collect()
+    }
+
+    /// Check an execution against the policy, reporting it if refused
+    async fn enforce(&self, name: &str, params: &Metadata) -> Result<()> {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
+        let Err(e) = policy.check(name, params) else {
+            return Ok(());
+        };
+        tracing::warn!(tool = %name, error = %e, "Tool execution refused");
+        if let Some((bus, agent)) = &self.audit {
+            let mut payload = Metadata::new();
+            payload.insert("agent", agent);
+            payload.insert("tool", name);
+            payload.insert("reason", e.to_string());
+            let event = Event::new(TOOL_POLICY_VIOLATION, payload)
+                .with_current_cause()
+                .with_current_trace();
+            let reported = async {
+                bus.register_topic(TOOL_POLICY_VIOLATION).await?;
+                bus.publish(event).await
+            }
+            .await;
+            if let Err(report) = reported {
+                tracing::warn!(tool = %name, error = %report, "Failed to report policy violation");
+            }
+        }
+        Err(e)
+    }
+
     /// Create a tool execution context
     pub fn create_context(&self, name: &str, params: Metadata) -> Result<ToolContext> {
         let config = self.get_config(name)
@@ -128,6 +207,15 @@ impl ToolManager {
     }
 }
 
+impl std::fmt::Debug for ToolManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolManager")
+            .field("configs", &self.configs)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
 /// Tool execution middleware
 pub trait ToolMiddleware: Send + Sync {
     /// Process the tool execution
@@ -145,6 +233,9 @@ pub struct ToolPipeline {
     
     /// Middleware chain
     middleware: Vec<Box<dyn ToolMiddleware>>,
+
+    /// Guardrails applied to tool parameters
+    guardrails: GuardrailSet,
 }
 
 impl ToolPipeline {
@@ -153,6 +244,7 @@ impl ToolPipeline {
         Self {
             manager,
             middleware: Vec::new(),
+            guardrails: GuardrailSet::new(),
         }
     }
 
@@ -165,8 +257,29 @@ impl ToolPipeline {
         self
     }
 
+    /// Add a guardrail applied to tool parameters before execution
+    pub fn with_guardrail<G>(mut self, guardrail: G) -> Self
+    where
+        G: Guardrail + 'static,
+    {
+        self.guardrails = self.guardrails.with(guardrail);
+        self
+    }
+
     /// Execute a tool with the middleware chain
     pub async fn execute(&self, name: &str, params: Metadata) -> Result<Metadata> {
+        let params = if self.guardrails.is_empty() {
+            params
+        } else {
+            let target = GuardrailTarget::ToolParams {
+                tool: name.to_string(),
+            };
+            let mut value = serde_json::to_value(params)?;
+            self.guardrails.apply_value(&target, &mut value).await?;
+            serde_json::from_value(value)?
+        };
+
+        self.manager.enforce(name, &params).await?;
         let context = self.manager.create_context(name, params)?;
         
         let tool = self.manager
@@ -272,4 +385,41 @@ mod tests {
 
         assert_eq!(result.get::<bool>("success"), Some(true));
     }
+
+    #[tokio::test]
+    async fn test_tool_policy() {
+        use crate::policy::ParamRule;
+        use atlas_core::InMemoryEventBus;
+        use futures::StreamExt;
+
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        bus.register_topic(TOOL_POLICY_VIOLATION).await.unwrap();
+        let mut violations = bus.subscribe(TOOL_POLICY_VIOLATION).await.unwrap();
+
+        let mut manager = ToolManager::new();
+        manager.register("test_tool".to_string(), TestTool);
+        manager.set_policy(ToolPolicy::new().constrain(
+            "test_tool",
+            "path",
+            ParamRule::path_under("/workspace"),
+        ));
+        manager.set_audit(bus, "tester");
+
+        let mut params = Metadata::new();
+        params.insert("path", "/workspace/notes.md");
+        assert!(manager.execute("test_tool", params.clone()).await.is_ok());
+
+        params.insert("path", "/etc/passwd");
+        let err = manager.execute("test_tool", params).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ToolPolicyViolation(_))
+        ));
+        // Refusals are not failures of the tool
+        assert!(manager.tool_failures().is_empty());
+
+        let violation = violations.next().await.unwrap();
+        assert_eq!(violation.payload.get::<String>("agent").as_deref(), Some("tester"));
+        assert_eq!(violation.payload.get::<String>("tool").as_deref(), Some("test_tool"));
+    }
 }
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
@@ -53,7 +54,7 @@ impl AgentContext {
 }
 
 /// Task configuration
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct TaskConfig {
     /// Task name
     pub name: String,
@@ -62,19 +63,26 @@ pub struct TaskConfig {
     pub description: Option<String>,
     
     /// Task parameters
+    #[serde(default)]
     pub parameters: HashMap<String, Value>,
     
     /// Task constraints
+    #[serde(default)]
     pub constraints: TaskConstraints,
     
     /// Task timeout in seconds
     pub timeout: Option<u64>,
+    
+    /// Task tags, used to route model requests
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 /// Task constraints
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct TaskConstraints {
     /// Required tools
+    #[serde(default)]
     pub required_tools: Vec<String>,
     
     /// Maximum number of steps
@@ -85,6 +93,10 @@ pub struct TaskConstraints {
     
     /// Maximum execution time in seconds
     pub max_time: Option<u64>,
+    
+    /// Maximum model spend in dollars
+    #[serde(default)]
+    pub max_cost: Option<f64>,
 }
 
 /// Agent response types
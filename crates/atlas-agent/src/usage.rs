@@ -0,0 +1,378 @@
+//! Token usage and cost accounting
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::llm::router::ROUTING_KEY;
+use crate::llm::{
+    CompletionRequest, CompletionResponse, LanguageModel, RoutingDecision, TokenUsage,
+};
+
+/// Task result key holding the usage and cost of the task
+pub const METRICS_KEY: &str = "metrics";
+
+/// Characters per token assumed when estimating a prompt before dispatch
+const CHARS_PER_TOKEN: u64 = 4;
+
+/// Price of a model in dollars per thousand tokens
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// Price per thousand prompt tokens
+    pub prompt_per_1k: f64,
+
+    /// Price per thousand completion tokens
+    pub completion_per_1k: f64,
+}
+
+impl ModelPrice {
+    /// Create a new price
+    pub fn new(prompt_per_1k: f64, completion_per_1k: f64) -> Self {
+        Self {
+            prompt_per_1k,
+            completion_per_1k,
+        }
+    }
+
+    /// Cost of the given usage
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_per_1k
+            + usage.completion_tokens as f64 * self.completion_per_1k)
+            / 1000.0
+    }
+}
+
+/// Model price table
+///
+/// Prices are looked up by `provider/model`, then `model`, then `provider`.
+/// Usage for unpriced models is still counted, at zero cost.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PriceTable {
+    /// Prices by key
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    /// Create an empty price table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the price for a key (`provider/model`, `model`, or `provider`)
+    pub fn with_price(mut self, key: impl Into<String>, price: ModelPrice) -> Self {
+        self.prices.insert(key.into(), price);
+        self
+    }
+
+    /// Get the price for a provider and model
+    pub fn price_for(&self, provider: &str, model: &str) -> Option<&ModelPrice> {
+        self.prices
+            .get(&format!("{}/{}", provider, model))
+            .or_else(|| self.prices.get(model))
+            .or_else(|| self.prices.get(provider))
+    }
+
+    /// Cost of the given usage
+    pub fn cost(&self, provider: &str, model: &str, usage: &TokenUsage) -> f64 {
+        self.price_for(provider, model)
+            .map(|price| price.cost(usage))
+            .unwrap_or_default()
+    }
+}
+
+/// Aggregated usage and cost
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostSummary {
+    /// Number of requests
+    pub requests: u64,
+
+    /// Prompt tokens
+    pub prompt_tokens: u64,
+
+    /// Completion tokens
+    pub completion_tokens: u64,
+
+    /// Cost in dollars
+    pub cost: f64,
+}
+
+impl CostSummary {
+    fn add(&mut self, usage: &TokenUsage, cost: f64) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.cost += cost;
+    }
+}
+
+/// Usage report for an agent
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct UsageReport {
+    /// Totals across all requests
+    pub total: CostSummary,
+
+    /// Totals by provider
+    pub by_provider: HashMap<String, CostSummary>,
+
+    /// Totals by model
+    pub by_model: HashMap<String, CostSummary>,
+
+    /// Totals by task
+    pub by_task: HashMap<Uuid, CostSummary>,
+}
+
+/// Records usage and cost for an agent
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    /// Price table
+    prices: PriceTable,
+
+    /// Accumulated usage
+    report: Mutex<UsageReport>,
+}
+
+impl UsageTracker {
+    /// Create a tracker with the given prices
+    pub fn new(prices: PriceTable) -> Self {
+        Self {
+            prices,
+            report: Mutex::new(UsageReport::default()),
+        }
+    }
+
+    /// Record usage for a completion, returning its cost
+    pub fn record(
+        &self,
+        provider: &str,
+        model: &str,
+        usage: &TokenUsage,
+        task: Option<Uuid>,
+    ) -> f64 {
+        let cost = self.prices.cost(provider, model, usage);
+
+        let mut report = self.report.lock().expect("usage report lock poisoned");
+        report.total.add(usage, cost);
+        report
+            .by_provider
+            .entry(provider.to_string())
+            .or_default()
+            .add(usage, cost);
+        report
+            .by_model
+            .entry(model.to_string())
+            .or_default()
+            .add(usage, cost);
+        if let Some(task) = task {
+            report.by_task.entry(task).or_default().add(usage, cost);
+        }
+
+        cost
+    }
+
+    /// Estimate the cost of a request before it is sent
+    ///
+    /// Prompt tokens are estimated from the message length and completion
+    /// tokens from `max_tokens`, so requests without a limit are estimated
+    /// at their prompt cost only.
+    pub fn estimate(&self, provider: &str, request: &CompletionRequest) -> f64 {
+        let chars: usize = request.messages.iter().map(|m| m.content.len()).sum();
+        let usage = TokenUsage {
+            prompt_tokens: (chars as u64).div_ceil(CHARS_PER_TOKEN),
+            completion_tokens: request.max_tokens.unwrap_or_default() as u64,
+        };
+        let model = request.model.as_deref().unwrap_or(provider);
+        self.prices.cost(provider, model, &usage)
+    }
+
+    /// Get the usage recorded for a task
+    pub fn task_usage(&self, task: Uuid) -> CostSummary {
+        let report = self.report.lock().expect("usage report lock poisoned");
+        report.by_task.get(&task).copied().unwrap_or_default()
+    }
+
+    /// Get a copy of the full report
+    pub fn report(&self) -> UsageReport {
+        self.report
+            .lock()
+            .expect("usage report lock poisoned")
+            .clone()
+    }
+}
+
+/// Language model wrapper that records usage and enforces a budget
+///
+/// Usage of routed responses is attributed to the route target that served
+/// them rather than to the router.
+pub struct MeteredModel {
+    /// Wrapped model
+    inner: Arc<dyn LanguageModel>,
+
+    /// Usage tracker
+    tracker: Arc<UsageTracker>,
+
+    /// Task the usage is attributed to
+    task: Option<Uuid>,
+
+    /// Maximum cost for the task in dollars
+    budget: Option<f64>,
+}
+
+impl MeteredModel {
+    /// Wrap a model
+    pub fn new(inner: Arc<dyn LanguageModel>, tracker: Arc<UsageTracker>) -> Self {
+        Self {
+            inner,
+            tracker,
+            task: None,
+            budget: None,
+        }
+    }
+
+    /// Attribute usage to a task
+    pub fn for_task(mut self, task: Uuid) -> Self {
+        self.task = Some(task);
+        self
+    }
+
+    /// Refuse requests that would take the task past this many dollars
+    pub fn with_budget(mut self, budget: Option<f64>) -> Self {
+        self.budget = budget;
+        self
+    }
+}
+
+#[async_trait]
+impl LanguageModel for MeteredModel {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        if let (Some(task), Some(budget)) = (self.task, self.budget) {
+            let spent = self.tracker.task_usage(task).cost;
+            let estimate = self.tracker.estimate(self.inner.name(), &request);
+            if spent >= budget || spent + estimate > budget {
+                return Err(Error::BudgetExceeded(format!(
+                    "Task {} spent ${:.4} of its ${:.4} budget and the next request is estimated at ${:.4}",
+                    task, spent, budget, estimate
+                ))
+                .into());
+            }
+        }
+
+        let response = self.inner.complete(request).await?;
+        let provider = response
+            .metadata
+            .get::<RoutingDecision>(ROUTING_KEY)
+            .map(|decision| decision.target)
+            .unwrap_or_else(|| self.inner.name().to_string());
+        let cost = self
+            .tracker
+            .record(&provider, &response.model, &response.usage, self.task);
+        tracing::info!(
+            %provider,
+            model = %response.model,
+            task = ?self.task,
+            prompt_tokens = response.usage.prompt_tokens,
+            completion_tokens = response.usage.completion_tokens,
+            cost,
+            "Model usage recorded"
+        );
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, ModelRouter, RouteTarget};
+
+    struct FixedModel;
+
+    #[async_trait]
+    impl LanguageModel for FixedModel {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            Ok(CompletionResponse {
+                message: ChatMessage::assistant("ok"),
+                model: "fixed-large".to_string(),
+                usage: TokenUsage {
+                    prompt_tokens: 1000,
+                    completion_tokens: 500,
+                },
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_price_lookup() {
+        let prices = PriceTable::new()
+            .with_price("fixed", ModelPrice::new(1.0, 1.0))
+            .with_price("fixed-large", ModelPrice::new(2.0, 4.0));
+
+        let usage = TokenUsage {
+            prompt_tokens: 1000,
+            completion_tokens: 500,
+        };
+        assert_eq!(prices.cost("fixed", "fixed-large", &usage), 4.0);
+        assert_eq!(prices.cost("fixed", "fixed-small", &usage), 1.5);
+        assert_eq!(prices.cost("other", "other", &usage), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_metering_and_budget() {
+        let tracker = Arc::new(UsageTracker::new(
+            PriceTable::new().with_price("fixed-large", ModelPrice::new(2.0, 4.0)),
+        ));
+        let task = Uuid::new_v4();
+        let model = MeteredModel::new(Arc::new(FixedModel), tracker.clone())
+            .for_task(task)
+            .with_budget(Some(6.0));
+
+        // Unpriced requests are estimated at zero and always dispatched
+        model.complete(CompletionRequest::default()).await.unwrap();
+
+        // 1000 prompt and 500 completion tokens are estimated at $4
+        let request = CompletionRequest {
+            max_tokens: Some(500),
+            ..CompletionRequest::new(vec![ChatMessage::user("x".repeat(4000))])
+                .with_model("fixed-large")
+        };
+        assert_eq!(tracker.estimate("fixed", &request), 4.0);
+
+        // The next request would take the task from $4 to $8, past its budget
+        let err = model.complete(request).await.unwrap_err();
+        assert!(err.to_string().contains("budget"));
+
+        let report = tracker.report();
+        assert_eq!(report.total.requests, 1);
+        assert_eq!(report.total.cost, 4.0);
+        assert_eq!(report.by_provider["fixed"].prompt_tokens, 1000);
+        assert_eq!(tracker.task_usage(task).completion_tokens, 500);
+    }
+
+    #[tokio::test]
+    async fn test_routed_usage_attribution() {
+        let tracker = Arc::new(UsageTracker::new(
+            PriceTable::new().with_price("primary", ModelPrice::new(1.0, 1.0)),
+        ));
+        let router = ModelRouter::new().target(RouteTarget::new("primary", Arc::new(FixedModel)));
+        let model = MeteredModel::new(Arc::new(router), tracker.clone());
+
+        model.complete(CompletionRequest::default()).await.unwrap();
+
+        let report = tracker.report();
+        assert!(!report.by_provider.contains_key("router"));
+        assert_eq!(report.by_provider["primary"].requests, 1);
+        assert_eq!(report.total.cost, 1.5);
+    }
+}
@@ -0,0 +1,193 @@
+//! Cancellation and deadlines shared by every layer of a piece of work
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::future::{select_all, FutureExt};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::Error;
+
+tokio::task_local! {
+    /// Cancellation context of the work running in the current task
+    static CURRENT: CancellationContext;
+}
+
+/// Cancellation signal and deadline of a piece of work
+///
+/// The context is passed down from the caller through agents to tools, so
+/// each layer stops when the caller gives up instead of inventing its own
+/// mechanism. Work run inside [`CancellationContext::scope`] can also look
+/// it up with [`CancellationContext::current`]. Cancelling a context
+/// cancels every context derived from it with
+/// [`CancellationContext::child`], but not the one it was derived from.
+#[derive(Clone, Debug)]
+pub struct CancellationContext {
+    /// Signals of this context and the contexts it was derived from
+    signals: Vec<Arc<watch::Sender<bool>>>,
+
+    /// When the work must finish by, if ever
+    deadline: Option<Instant>,
+}
+
+impl Default for CancellationContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationContext {
+    /// Create a context without a deadline
+    pub fn new() -> Self {
+        Self {
+            signals: vec![Arc::new(watch::channel(false).0)],
+            deadline: None,
+        }
+    }
+
+    /// Set a deadline, keeping an earlier one
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+        self
+    }
+
+    /// Set a deadline some time from now, keeping an earlier one
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Derive a context that is cancelled along with this one and can be
+    /// cancelled on its own
+    pub fn child(&self) -> Self {
+        let mut signals = self.signals.clone();
+        signals.push(Arc::new(watch::channel(false).0));
+        Self {
+            signals,
+            deadline: self.deadline,
+        }
+    }
+
+    /// Get the cancellation context of the work running in the current task
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|context| context.clone()).ok()
+    }
+
+    /// Run work in this cancellation context
+    pub async fn scope<F: Future>(self, work: F) -> F::Output {
+        CURRENT.scope(self, work).await
+    }
+
+    /// Cancel the work and the work derived from it
+    pub fn cancel(&self) {
+        if let Some(signal) = self.signals.last() {
+            signal.send_replace(true);
+        }
+    }
+
+    /// Get the deadline, if any
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Get the time left before the deadline, if there is one
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether the work was cancelled or ran out of time
+    pub fn is_cancelled(&self) -> bool {
+        self.error().is_some()
+    }
+
+    /// Wait until the work is cancelled or runs out of time
+    pub async fn cancelled(&self) {
+        let signals = self.signals.iter().map(|signal| {
+            let mut receiver = signal.subscribe();
+            async move {
+                let _ = receiver.wait_for(|cancelled| *cancelled).await;
+            }
+            .boxed()
+        });
+        let cancelled = select_all(signals);
+        match self.deadline {
+            Some(deadline) => {
+                tokio::select! {
+                    _ = cancelled => {}
+                    _ = tokio::time::sleep_until(deadline) => {}
+                }
+            }
+            None => {
+                cancelled.await;
+            }
+        }
+    }
+
+    /// Run work in this context, failing with [`Error::Cancelled`] or
+    /// [`Error::Timeout`] if it is cancelled or runs out of time first
+    pub async fn run<F, T>(&self, work: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        if let Some(error) = self.error() {
+            return Err(error.into());
+        }
+        tokio::select! {
+            result = self.clone().scope(work) => result,
+            _ = self.cancelled() => Err(self
+                .error()
+                .unwrap_or_else(|| Error::Cancelled("Work was cancelled".to_string()))
+                .into()),
+        }
+    }
+
+    /// Error describing why the work stopped, if it did
+    fn error(&self) -> Option<Error> {
+        if self.signals.iter().any(|signal| *signal.borrow()) {
+            Some(Error::Cancelled("Work was cancelled".to_string()))
+        } else if self
+            .deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            Some(Error::Timeout("Deadline passed".to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancellation() {
+        let parent = CancellationContext::new();
+        let child = parent.child();
+        let sibling = parent.child();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled() && !sibling.is_cancelled());
+
+        let work = sibling.run(async {
+            assert!(CancellationContext::current().is_some());
+            std::future::pending::<Result<()>>().await
+        });
+        let (result, _) = tokio::join!(work, async { parent.cancel() });
+        let error = result.unwrap_err().downcast::<Error>().unwrap();
+        assert!(matches!(error, Error::Cancelled(_)));
+
+        let timed = CancellationContext::new().with_timeout(Duration::from_millis(10));
+        let result = timed
+            .run(async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            })
+            .await;
+        let error = result.unwrap_err().downcast::<Error>().unwrap();
+        assert!(matches!(error, Error::Timeout(_)));
+    }
+}
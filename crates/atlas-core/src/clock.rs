@@ -0,0 +1,132 @@
+//! Sources of time that tests can control
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// Source of the current time and of waits
+///
+/// Components that timestamp, expire or schedule work take a clock, so tests
+/// can substitute a [`MockClock`] and move time forward instead of sleeping.
+#[async_trait]
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Get the current wall-clock time
+    fn now(&self) -> SystemTime;
+
+    /// Get the current monotonic time, for measuring intervals
+    fn instant(&self) -> Instant;
+
+    /// Wait until some time has passed
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Clock following the system time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Clock that only moves when told to
+///
+/// Clones share the same time, and waits finish as soon as
+/// [`MockClock::advance`] moves time past their end.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    /// Wall-clock time the clock started at
+    start: SystemTime,
+
+    /// Monotonic time the clock started at
+    base: Instant,
+
+    /// Time passed since the start
+    elapsed: Arc<watch::Sender<Duration>>,
+}
+
+impl MockClock {
+    /// Create a clock starting at a wall-clock time
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            start,
+            base: Instant::now(),
+            elapsed: Arc::new(watch::channel(Duration::ZERO).0),
+        }
+    }
+
+    /// Move time forward, finishing the waits that end by then
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += duration);
+    }
+
+    /// Get the time passed since the clock started
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.borrow()
+    }
+}
+
+impl Default for MockClock {
+    /// Clock starting at the Unix epoch
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+#[async_trait]
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let end = self.elapsed() + duration;
+        let mut elapsed = self.elapsed.subscribe();
+        let _ = elapsed.wait_for(|elapsed| *elapsed >= end).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let clock = MockClock::default();
+        let started = clock.instant();
+        let sleeper = tokio::spawn({
+            let clock = clock.clone();
+            async move { clock.sleep(Duration::from_secs(60)).await }
+        });
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(30));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+
+        clock.advance(Duration::from_secs(30));
+        sleeper.await.unwrap();
+        assert_eq!(
+            clock.now(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(60)
+        );
+        assert_eq!(clock.instant() - started, Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,137 @@
+//! Wire formats for events, metadata and state snapshots
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(all(feature = "msgpack", feature = "cbor")))]
+use crate::Error;
+
+/// Format values are encoded in when they leave the process
+///
+/// JSON is always available. The binary formats are smaller and cheaper to
+/// encode and are enabled with the `msgpack` and `cbor` features; without
+/// its feature, a format fails to encode and decode with
+/// [`Error::Config`](crate::Error::Config). Both ends of a transport must use
+/// the same codec.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    /// JSON text
+    #[default]
+    Json,
+
+    /// MessagePack, with structs encoded as maps
+    MessagePack,
+
+    /// CBOR
+    Cbor,
+}
+
+impl Codec {
+    /// Encode a value
+    pub fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(value)?),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => Ok(rmp_serde::to_vec_named(value)?),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|e| anyhow::anyhow!("CBOR encoding failed: {}", e))?;
+                Ok(bytes)
+            }
+            #[cfg(not(feature = "msgpack"))]
+            Codec::MessagePack => Err(self.disabled("msgpack")),
+            #[cfg(not(feature = "cbor"))]
+            Codec::Cbor => Err(self.disabled("cbor")),
+        }
+    }
+
+    /// Decode a value
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        let decoded = match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(anyhow::Error::from),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(anyhow::Error::from),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => ciborium::from_reader(bytes).map_err(|e| anyhow::anyhow!("{}", e)),
+            #[cfg(not(feature = "msgpack"))]
+            Codec::MessagePack => return Err(self.disabled("msgpack")),
+            #[cfg(not(feature = "cbor"))]
+            Codec::Cbor => return Err(self.disabled("cbor")),
+        };
+        decoded.map_err(|e| anyhow::anyhow!("Invalid {} payload: {}", self.name(), e))
+    }
+
+    /// Get the MIME type of encoded values
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Codec::Json => "application/json",
+            Codec::MessagePack => "application/msgpack",
+            Codec::Cbor => "application/cbor",
+        }
+    }
+
+    /// Get the name of the format
+    fn name(&self) -> &'static str {
+        match self {
+            Codec::Json => "JSON",
+            Codec::MessagePack => "MessagePack",
+            Codec::Cbor => "CBOR",
+        }
+    }
+
+    /// Error for a format whose feature is off
+    #[cfg(not(all(feature = "msgpack", feature = "cbor")))]
+    fn disabled(&self, feature: &str) -> anyhow::Error {
+        Error::Config(format!(
+            "{} encoding requires the `{}` feature",
+            self.name(),
+            feature
+        ))
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Event, Metadata};
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let mut payload = Metadata::new();
+        payload.insert("answer", 42);
+        payload.insert(
+            "nested",
+            serde_json::json!({"tags": ["a", "b"], "ok": true}),
+        );
+        let event = Event::new("task.completed", payload.clone());
+
+        let codecs = [
+            Codec::Json,
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack,
+            #[cfg(feature = "cbor")]
+            Codec::Cbor,
+        ];
+        for codec in codecs {
+            let decoded: Event = codec.decode(&codec.encode(&event).unwrap()).unwrap();
+            assert_eq!(decoded.id, event.id);
+            assert_eq!(decoded.event_type, event.event_type);
+            assert_eq!(decoded.payload.get::<i64>("answer"), Some(42));
+
+            let decoded: Metadata = codec.decode(&codec.encode(&payload).unwrap()).unwrap();
+            assert_eq!(
+                decoded.get::<serde_json::Value>("nested"),
+                payload.get::<serde_json::Value>("nested")
+            );
+            assert!(codec.decode::<Event>(b"\xff\x00 not an event").is_err());
+        }
+
+        #[cfg(not(feature = "msgpack"))]
+        assert!(Codec::MessagePack.encode(&event).is_err());
+    }
+}
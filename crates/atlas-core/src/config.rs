@@ -0,0 +1,365 @@
+//! Layered configuration loading from files, the environment and code
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{Error, MergeStrategy, Metadata};
+
+/// Format of a configuration file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConfigFormat {
+    /// JSON
+    Json,
+
+    /// TOML; requires the `toml` feature
+    Toml,
+
+    /// YAML; requires the `yaml` feature
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Detect the format of a file from its extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => Ok(Self::Json),
+            Some("toml") => Ok(Self::Toml),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            _ => Err(
+                Error::Config(format!("Unknown configuration format: {}", path.display())).into(),
+            ),
+        }
+    }
+
+    /// Parse configuration text in this format
+    pub fn parse(&self, text: &str) -> Result<Value> {
+        match self {
+            Self::Json => Ok(serde_json::from_str(text)?),
+            #[cfg(feature = "toml")]
+            Self::Toml => Ok(toml::from_str(text)?),
+            #[cfg(not(feature = "toml"))]
+            Self::Toml => Err(missing_feature("toml")),
+            #[cfg(feature = "yaml")]
+            Self::Yaml => Ok(serde_yaml::from_str(text)?),
+            #[cfg(not(feature = "yaml"))]
+            Self::Yaml => Err(missing_feature("yaml")),
+        }
+    }
+}
+
+/// Loader merging configuration layers into one typed configuration
+///
+/// Layers are applied in order of precedence: files in the order they were
+/// added, then environment variables, then programmatic overrides. Objects
+/// are merged key by key, so a layer only replaces the values it sets.
+///
+/// String values in files may reference environment variables as `${VAR}`,
+/// or `${VAR:-default}` to fall back when the variable is unset. With an
+/// environment prefix such as `ATLAS`, a variable `ATLAS_SERVER__PORT` sets
+/// `server.port`; its value is parsed as JSON when possible, so `8080` is a
+/// number and `["a", "b"]` a list, except where a file sets a string, which
+/// the variable replaces as a string, so `1.0` stays `"1.0"`.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigLoader {
+    /// Files to read, lowest precedence first, and whether each must exist
+    files: Vec<(PathBuf, bool)>,
+
+    /// Prefix of the environment variables to read, if any
+    env_prefix: Option<String>,
+
+    /// Values set in code, by dot-separated path
+    overrides: Vec<(String, Value)>,
+}
+
+impl ConfigLoader {
+    /// Create a loader with no layers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, which must exist, with its format detected from its
+    /// extension
+    pub fn with_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.files.push((path.as_ref().to_path_buf(), true));
+        self
+    }
+
+    /// Add a file that is skipped if it does not exist
+    pub fn with_optional_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.files.push((path.as_ref().to_path_buf(), false));
+        self
+    }
+
+    /// Read environment variables starting with a prefix and an underscore
+    pub fn with_env_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.env_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Set a value by a dot-separated path, overriding every other layer
+    pub fn with_override<V: Into<Value>>(mut self, path: &str, value: V) -> Self {
+        self.overrides.push((path.to_string(), value.into()));
+        self
+    }
+
+    /// Merge the layers into untyped configuration
+    pub fn load_metadata(&self) -> Result<Metadata> {
+        let mut config = Metadata::new();
+        for (path, required) in &self.files {
+            if !required && !path.exists() {
+                continue;
+            }
+            config.merge_deep(read_file(path)?, MergeStrategy::Overwrite);
+        }
+
+        if let Some(prefix) = &self.env_prefix {
+            let prefix = format!("{}_", prefix);
+            let mut vars: Vec<_> = std::env::vars()
+                .filter(|(name, _)| name.starts_with(&prefix))
+                .collect();
+            vars.sort();
+            let mut layer = Metadata::new();
+            for (name, value) in vars {
+                let path = name[prefix.len()..].to_ascii_lowercase().replace("__", ".");
+                let value = env_value(config.get_path(&path), value);
+                layer.insert_path(&path, value)?;
+            }
+            config.merge_deep(layer, MergeStrategy::Overwrite);
+        }
+
+        let mut layer = Metadata::new();
+        for (path, value) in &self.overrides {
+            layer.insert_path(path, value.clone())?;
+        }
+        config.merge_deep(layer, MergeStrategy::Overwrite);
+        Ok(config)
+    }
+
+    /// Merge the layers into typed configuration
+    pub fn load<T: DeserializeOwned>(&self) -> Result<T> {
+        self.load_metadata()?
+            .to_typed()
+            .map_err(|e| Error::Config(e.to_string()).into())
+    }
+}
+
+/// Interpret the value of an environment variable replacing a setting,
+/// keeping it a string where the setting is one and otherwise parsing it as
+/// JSON when possible
+fn env_value(setting: Option<Value>, value: String) -> Value {
+    if matches!(setting, Some(Value::String(_))) {
+        return Value::String(value);
+    }
+    serde_json::from_str(&value).unwrap_or(Value::String(value))
+}
+
+/// Problems found while validating a configuration, collected so they can be
+/// reported together instead of one per attempt
+#[derive(Clone, Debug, Default)]
+pub struct ValidationErrors {
+    /// Problems found, in the order they were found
+    problems: Vec<String>,
+}
+
+impl ValidationErrors {
+    /// Create an empty collection
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a problem
+    pub fn push<S: Into<String>>(&mut self, problem: S) {
+        self.problems.push(problem.into());
+    }
+
+    /// Record a problem unless a condition holds
+    pub fn ensure<S: Into<String>>(&mut self, condition: bool, problem: S) {
+        if !condition {
+            self.push(problem);
+        }
+    }
+
+    /// Get the problems found
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    /// Whether no problem was found
+    pub fn is_empty(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Fail with an [`Error::Config`] listing every problem, with the
+    /// problems also under the `problems` detail, if any were found
+    pub fn into_result(self) -> Result<()> {
+        let message = match self.problems.as_slice() {
+            [] => return Ok(()),
+            [problem] => problem.clone(),
+            problems => format!("{} problems: {}", problems.len(), problems.join("; ")),
+        };
+        let mut details = Metadata::new();
+        details.insert("problems", self.problems);
+        Err(Error::Config(message).with_details(details).into())
+    }
+}
+
+/// Read and interpolate a configuration file
+fn read_file(path: &Path) -> Result<Metadata> {
+    let format = ConfigFormat::from_path(path)?;
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        Error::Config(format!(
+            "Failed to read configuration {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let mut value = format.parse(&text).map_err(|e| {
+        Error::Config(format!(
+            "Failed to parse configuration {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    interpolate(&mut value)?;
+    match value {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        _ => Err(Error::Config(format!(
+            "Configuration {} must contain a table of settings",
+            path.display()
+        ))
+        .into()),
+    }
+}
+
+/// Expand environment variable references in every string of a value
+fn interpolate(value: &mut Value) -> Result<()> {
+    match value {
+        Value::String(text) => *text = expand(text)?,
+        Value::Array(items) => items.iter_mut().try_for_each(interpolate)?,
+        Value::Object(map) => map.values_mut().try_for_each(interpolate)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expand the `${VAR}` and `${VAR:-default}` references in a string
+fn expand(text: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let reference = &rest[start + 2..];
+        let end = reference.find('}').ok_or_else(|| {
+            Error::Config(format!("Unterminated variable reference in {:?}", text))
+        })?;
+        let (name, default) = match reference[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&reference[..end], None),
+        };
+        let value = std::env::var(name)
+            .ok()
+            .or_else(|| default.map(str::to_string))
+            .ok_or_else(|| Error::Config(format!("Environment variable {} is not set", name)))?;
+        expanded.push_str(&value);
+        rest = &reference[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Error for a format whose feature is disabled
+#[cfg(not(all(feature = "toml", feature = "yaml")))]
+fn missing_feature(feature: &str) -> anyhow::Error {
+    Error::Config(format!(
+        "Loading {} configuration requires the `{}` feature",
+        feature, feature
+    ))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct ServerSettings {
+        name: String,
+        version: String,
+        port: u16,
+        token: String,
+        tools: Vec<String>,
+    }
+
+    #[test]
+    fn test_layered_loading() {
+        let dir = std::env::temp_dir().join(format!("atlas-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("base.json");
+        std::fs::write(
+            &base,
+            r#"{"server": {"name": "atlas", "version": "0.9", "port": 80,
+                "token": "${ATLAS_TEST_TOKEN}",
+                "tools": ["${ATLAS_TEST_TOOL:-search}"]}}"#,
+        )
+        .unwrap();
+        let local = dir.join("local.json");
+        std::fs::write(&local, r#"{"server": {"port": 8080}}"#).unwrap();
+        std::env::set_var("ATLAS_TEST_TOKEN", "secret");
+        std::env::set_var("ATLASTEST_SERVER__NAME", "from-env");
+        std::env::set_var("ATLASTEST_SERVER__VERSION", "1.0");
+        std::env::set_var("ATLASTEST_SERVER__WORKERS", "4");
+
+        let loader = ConfigLoader::new()
+            .with_file(&base)
+            .with_optional_file(&local)
+            .with_optional_file(dir.join("missing.json"))
+            .with_env_prefix("ATLASTEST")
+            .with_override("server.port", 9090);
+        let config = loader.load_metadata().unwrap();
+        let server: ServerSettings = config.get("server").unwrap();
+        assert_eq!(server.name, "from-env");
+        assert_eq!(server.version, "1.0");
+        assert_eq!(config.get_path::<u32>("server.workers"), Some(4));
+        assert_eq!(server.port, 9090);
+        assert_eq!(server.token, "secret");
+        assert_eq!(server.tools, ["search"]);
+
+        std::env::remove_var("ATLAS_TEST_TOKEN");
+        assert!(ConfigLoader::new()
+            .with_file(&base)
+            .load_metadata()
+            .is_err());
+        assert!(ConfigLoader::new()
+            .with_file(dir.join("missing.json"))
+            .load_metadata()
+            .is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let mut errors = ValidationErrors::new();
+        errors.ensure(true, "unreachable");
+        assert!(errors.is_empty());
+        assert!(errors.clone().into_result().is_ok());
+
+        errors.push("name is required");
+        errors.ensure(false, "port must not be 0");
+        let err = errors.into_result().unwrap_err();
+        let err = err.downcast_ref::<Error>().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Configuration error: 2 problems: name is required; port must not be 0"
+        );
+        let problems: Vec<String> = err.details().unwrap().get("problems").unwrap();
+        assert_eq!(problems, ["name is required", "port must not be 0"]);
+    }
+}
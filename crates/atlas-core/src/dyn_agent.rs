@@ -0,0 +1,248 @@
+//! Agents of any type behind a single object-safe trait
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    Agent, AgentConfig, AgentState, CancellationContext, Error, Event, HealthStatus, Metadata,
+    TaskId,
+};
+
+/// Agent with its configuration and state types erased, so agents of
+/// different types can be kept together as `Arc<dyn DynAgent>`
+///
+/// Every [`Agent`] implements it, exchanging its state as [`Metadata`]
+/// snapshots. Agents are created from a metadata configuration with
+/// [`new_dyn_agent`].
+#[async_trait]
+pub trait DynAgent: Send + Sync {
+    /// Get the name of the concrete agent type
+    fn type_name(&self) -> &'static str;
+
+    /// Get a snapshot of the agent's current state
+    async fn snapshot(&self) -> Result<Metadata>;
+
+    /// Update the agent's state with new data
+    async fn update_state(&self, data: Metadata) -> Result<()>;
+
+    /// Process an incoming event
+    async fn handle_event(&self, event: Event) -> Result<()>;
+
+    /// Execute a task with the given parameters
+    async fn execute_task(&self, task_id: TaskId, params: Metadata) -> Result<Metadata>;
+
+    /// Execute a task, stopping once the context is cancelled or its
+    /// deadline passes
+    async fn execute_task_with(
+        &self,
+        task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata>;
+
+    /// Prepare the agent to take work
+    async fn on_start(&self) -> Result<()>;
+
+    /// Stop taking work, returning once the work in progress has finished
+    async fn on_stop(&self) -> Result<()>;
+
+    /// Hold new work until the agent is resumed
+    async fn pause(&self) -> Result<()>;
+
+    /// Take new work again after a pause
+    async fn resume(&self) -> Result<()>;
+
+    /// Report whether the agent can do its work
+    async fn health(&self) -> HealthStatus;
+}
+
+impl fmt::Debug for dyn DynAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynAgent").field(&self.type_name()).finish()
+    }
+}
+
+#[async_trait]
+impl<A: Agent> DynAgent for A {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<A>()
+    }
+
+    async fn snapshot(&self) -> Result<Metadata> {
+        let state = Agent::state(self).await?;
+        let snapshot = state.read().await.snapshot();
+        snapshot
+    }
+
+    async fn update_state(&self, data: Metadata) -> Result<()> {
+        let state = Agent::state(self).await?;
+        let updated = state.write().await.update(data);
+        updated
+    }
+
+    async fn handle_event(&self, event: Event) -> Result<()> {
+        Agent::handle_event(self, event).await
+    }
+
+    async fn execute_task(&self, task_id: TaskId, params: Metadata) -> Result<Metadata> {
+        Agent::execute_task(self, task_id, params).await
+    }
+
+    async fn execute_task_with(
+        &self,
+        task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        Agent::execute_task_with(self, task_id, params, cancel).await
+    }
+
+    async fn on_start(&self) -> Result<()> {
+        Agent::on_start(self).await
+    }
+
+    async fn on_stop(&self) -> Result<()> {
+        Agent::on_stop(self).await
+    }
+
+    async fn pause(&self) -> Result<()> {
+        Agent::pause(self).await
+    }
+
+    async fn resume(&self) -> Result<()> {
+        Agent::resume(self).await
+    }
+
+    async fn health(&self) -> HealthStatus {
+        Agent::health(self).await
+    }
+}
+
+/// Create an agent of a given type from a metadata configuration, which is
+/// validated before the agent is created
+pub async fn new_dyn_agent<A>(config: Metadata) -> Result<Arc<dyn DynAgent>>
+where
+    A: Agent + 'static,
+    A::Config: DeserializeOwned,
+{
+    let config: A::Config = config.to_typed().map_err(|e| {
+        Error::Config(format!(
+            "Invalid configuration for {}: {}",
+            std::any::type_name::<A>(),
+            e
+        ))
+    })?;
+    config.validate()?;
+    Ok(Arc::new(A::new(config).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tokio::sync::RwLock;
+
+    #[derive(Clone, Debug, Deserialize)]
+    struct EchoConfig {
+        prefix: String,
+    }
+
+    impl AgentConfig for EchoConfig {
+        fn validate(&self) -> Result<()> {
+            if self.prefix.is_empty() {
+                return Err(Error::Config("prefix must not be empty".to_string()).into());
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct EchoState(Metadata);
+
+    impl AgentState for EchoState {
+        fn update(&mut self, data: Metadata) -> Result<()> {
+            self.0.merge(data, crate::MergeStrategy::Overwrite);
+            Ok(())
+        }
+
+        fn snapshot(&self) -> Result<Metadata> {
+            Ok(self.0.clone())
+        }
+    }
+
+    /// Agent echoing its parameters back with a prefix
+    struct Echo {
+        prefix: String,
+        state: Arc<RwLock<EchoState>>,
+    }
+
+    #[async_trait]
+    impl Agent for Echo {
+        type Config = EchoConfig;
+        type State = EchoState;
+
+        async fn new(config: EchoConfig) -> Result<Self> {
+            Ok(Self {
+                prefix: config.prefix,
+                state: Arc::default(),
+            })
+        }
+
+        async fn state(&self) -> Result<Arc<RwLock<EchoState>>> {
+            Ok(self.state.clone())
+        }
+
+        async fn handle_event(&self, event: Event) -> Result<()> {
+            self.state.write().await.update(event.payload)
+        }
+
+        async fn execute_task(&self, _task_id: TaskId, params: Metadata) -> Result<Metadata> {
+            let text = params.get::<String>("text").unwrap_or_default();
+            let mut result = Metadata::new();
+            result.insert("text", format!("{}{}", self.prefix, text));
+            Ok(result)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dyn_agent() {
+        let mut config = Metadata::new();
+        config.insert("prefix", "> ");
+        let agents: Vec<Arc<dyn DynAgent>> = vec![
+            new_dyn_agent::<Echo>(config).await.unwrap(),
+            Arc::new(
+                Echo::new(EchoConfig {
+                    prefix: "# ".to_string(),
+                })
+                .await
+                .unwrap(),
+            ),
+        ];
+
+        let mut params = Metadata::new();
+        params.insert("text", "hi");
+        let result = agents[0].execute_task(TaskId::new(), params).await.unwrap();
+        assert_eq!(result.get::<String>("text").as_deref(), Some("> hi"));
+
+        let mut payload = Metadata::new();
+        payload.insert("seen", true);
+        agents[1]
+            .handle_event(Event::new("note", payload))
+            .await
+            .unwrap();
+        assert_eq!(
+            agents[1].snapshot().await.unwrap().get::<bool>("seen"),
+            Some(true)
+        );
+        assert!(agents[1].type_name().ends_with("Echo"));
+
+        let mut invalid = Metadata::new();
+        invalid.insert("prefix", "");
+        assert!(new_dyn_agent::<Echo>(invalid).await.is_err());
+        assert!(new_dyn_agent::<Echo>(Metadata::new()).await.is_err());
+    }
+}
@@ -0,0 +1,536 @@
+//! Acknowledged, at-least-once event delivery
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use super::{Cause, Event, EventBus, EventStream};
+use crate::{Error, Metadata};
+
+/// Topic events go to once they run out of deliveries
+pub const DEAD_LETTER_TOPIC: &str = "dead_letter";
+
+/// Redelivery settings of an acknowledged subscription
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AckConfig {
+    /// How long a delivered event may go unacknowledged before it is
+    /// delivered again, in milliseconds
+    pub visibility_timeout_ms: u64,
+
+    /// Deliveries after which an unacknowledged event is dead-lettered
+    pub max_deliveries: u32,
+
+    /// Topic dead-lettered events are published on
+    pub dead_letter_topic: String,
+}
+
+impl Default for AckConfig {
+    fn default() -> Self {
+        Self {
+            visibility_timeout_ms: 30_000,
+            max_deliveries: 5,
+            dead_letter_topic: DEAD_LETTER_TOPIC.to_string(),
+        }
+    }
+}
+
+/// Stream of deliveries awaiting acknowledgement
+pub type AckStream = BoxStream<'static, Delivery>;
+
+/// Acknowledgement of one delivery to the broker that made it
+#[async_trait]
+pub trait BrokerAck: Send + Sync {
+    /// Tell the broker the event was handled
+    async fn ack(self: Box<Self>) -> Result<()>;
+
+    /// Tell the broker to deliver the event again
+    async fn nack(self: Box<Self>) -> Result<()>;
+}
+
+/// Event delivered by a broker that tracks its acknowledgement
+pub struct BrokerDelivery {
+    /// Delivered event
+    pub event: Event,
+
+    /// Deliveries of the event so far, including this one, as counted by
+    /// the broker
+    pub attempt: u32,
+
+    /// Acknowledges the delivery to the broker
+    pub ack: Box<dyn BrokerAck>,
+}
+
+/// Stream of deliveries acknowledged to the broker
+pub type BrokerAckStream = BoxStream<'static, BrokerDelivery>;
+
+/// Event delivered by an acknowledged subscription
+///
+/// The event is delivered again if it is neither acknowledged nor rejected
+/// within the visibility timeout, for example because the handler died.
+#[derive(Debug)]
+pub struct Delivery {
+    /// Delivered event
+    event: Event,
+
+    /// Deliveries of the event so far, including this one
+    attempt: u32,
+
+    /// Who the delivery is acknowledged to
+    acker: Acker,
+}
+
+/// Who tracks whether a delivery was acknowledged
+enum Acker {
+    /// The subscription, in this process
+    Inflight {
+        /// Key of the event among the unacknowledged ones
+        token: u64,
+
+        /// Unacknowledged events of the subscription
+        inflight: Arc<Inflight>,
+    },
+
+    /// The broker that made the delivery
+    Broker {
+        /// Acknowledges the delivery to the broker
+        ack: Box<dyn BrokerAck>,
+
+        /// Bus the event is dead-lettered on
+        bus: Arc<dyn EventBus>,
+
+        /// Redelivery settings
+        config: Arc<AckConfig>,
+    },
+}
+
+impl std::fmt::Debug for Acker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Acker::Inflight { token, .. } => f
+                .debug_struct("Inflight")
+                .field("token", token)
+                .finish_non_exhaustive(),
+            Acker::Broker { .. } => f.debug_struct("Broker").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl Delivery {
+    /// Get the delivered event
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    /// Get how many times the event has been delivered, including this time
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Mark the event handled so it is not delivered again
+    pub async fn ack(self) -> Result<()> {
+        match self.acker {
+            Acker::Inflight { token, inflight } => {
+                if let Ok(mut state) = inflight.state.lock() {
+                    state.pending.remove(&token);
+                }
+                Ok(())
+            }
+            Acker::Broker { ack, .. } => ack.ack().await,
+        }
+    }
+
+    /// Reject the event, delivering it again straight away unless it has run
+    /// out of deliveries
+    pub async fn nack<S: Into<String>>(self, reason: S) -> Result<()> {
+        match self.acker {
+            Acker::Inflight { token, inflight } => {
+                if let Ok(mut state) = inflight.state.lock() {
+                    if let Some(pending) = state.pending.get_mut(&token) {
+                        pending.error = Some(reason.into());
+                        state.rejected.push_back(token);
+                    }
+                }
+                inflight.changed.notify_one();
+                Ok(())
+            }
+            Acker::Broker { ack, bus, config } => {
+                if self.attempt < config.max_deliveries {
+                    return ack.nack().await;
+                }
+                let (topic, error) = (&config.dead_letter_topic, Some(reason.into()));
+                dead_letter(bus.as_ref(), topic, self.event, self.attempt, error).await;
+                ack.ack().await
+            }
+        }
+    }
+}
+
+/// Event awaiting acknowledgement
+#[derive(Debug)]
+struct Pending {
+    /// Delivered event
+    event: Event,
+
+    /// Deliveries so far
+    deliveries: u32,
+
+    /// When the event is delivered again
+    deadline: Instant,
+
+    /// Reason given by the last rejection
+    error: Option<String>,
+}
+
+/// Unacknowledged events of a subscription
+#[derive(Debug, Default)]
+struct InflightState {
+    /// Key of the next delivered event
+    next_token: u64,
+
+    /// Unacknowledged events by key
+    pending: HashMap<u64, Pending>,
+
+    /// Keys of rejected events, oldest first
+    rejected: VecDeque<u64>,
+}
+
+/// Shared record of the unacknowledged events of a subscription
+#[derive(Debug, Default)]
+struct Inflight {
+    /// Unacknowledged events
+    state: Mutex<InflightState>,
+
+    /// Signalled when an event is rejected
+    changed: Notify,
+}
+
+/// What to do next with an unacknowledged event
+enum Due {
+    /// Deliver it again
+    Redeliver(Delivery),
+
+    /// Publish it to the dead-letter topic
+    DeadLetter(Pending),
+}
+
+impl Inflight {
+    /// Lock the unacknowledged events
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, InflightState>> {
+        self.state
+            .lock()
+            .map_err(|_| Error::Event("Acknowledged subscription poisoned".to_string()).into())
+    }
+
+    /// Record a first delivery
+    fn deliver(self: &Arc<Self>, event: Event, config: &AckConfig) -> Result<Delivery> {
+        let mut state = self.lock()?;
+        let token = state.next_token;
+        state.next_token += 1;
+        state.pending.insert(
+            token,
+            Pending {
+                event: event.clone(),
+                deliveries: 1,
+                deadline: Instant::now() + visibility_timeout(config),
+                error: None,
+            },
+        );
+        Ok(Delivery {
+            event,
+            attempt: 1,
+            acker: Acker::Inflight {
+                token,
+                inflight: self.clone(),
+            },
+        })
+    }
+
+    /// Take the next rejected or timed out event, if any
+    fn next_due(self: &Arc<Self>, config: &AckConfig) -> Result<Option<Due>> {
+        let mut state = self.lock()?;
+        let now = Instant::now();
+        let mut token = None;
+        while let Some(rejected) = state.rejected.pop_front() {
+            if state.pending.contains_key(&rejected) {
+                token = Some(rejected);
+                break;
+            }
+        }
+        let token = token.or_else(|| {
+            state
+                .pending
+                .iter()
+                .filter(|(_, pending)| pending.deadline <= now)
+                .min_by_key(|(_, pending)| pending.deadline)
+                .map(|(token, _)| *token)
+        });
+        let Some(token) = token else {
+            return Ok(None);
+        };
+
+        let Some(pending) = state.pending.get_mut(&token) else {
+            return Ok(None);
+        };
+        if pending.deliveries >= config.max_deliveries {
+            return Ok(state.pending.remove(&token).map(Due::DeadLetter));
+        }
+        pending.deliveries += 1;
+        pending.deadline = now + visibility_timeout(config);
+        Ok(Some(Due::Redeliver(Delivery {
+            event: pending.event.clone(),
+            attempt: pending.deliveries,
+            acker: Acker::Inflight {
+                token,
+                inflight: self.clone(),
+            },
+        })))
+    }
+
+    /// Get when the next unacknowledged event times out; `None` if every
+    /// event was acknowledged
+    fn next_deadline(&self) -> Result<Option<Instant>> {
+        let state = self.lock()?;
+        Ok(state.pending.values().map(|pending| pending.deadline).min())
+    }
+}
+
+/// State of an acknowledged subscription's stream
+struct Subscription {
+    /// Bus dead-lettered events are published on
+    bus: Arc<dyn EventBus>,
+
+    /// Redelivery settings
+    config: AckConfig,
+
+    /// Events delivered by the bus
+    events: EventStream,
+
+    /// Whether the bus closed the subscription
+    closed: bool,
+
+    /// Unacknowledged events
+    inflight: Arc<Inflight>,
+}
+
+impl Subscription {
+    /// Wait for the next delivery or redelivery; `None` once the bus closed
+    /// the subscription and every event was acknowledged or dead-lettered
+    async fn next(&mut self) -> Option<Delivery> {
+        loop {
+            match self.inflight.next_due(&self.config) {
+                Ok(Some(Due::Redeliver(delivery))) => return Some(delivery),
+                Ok(Some(Due::DeadLetter(pending))) => {
+                    let topic = &self.config.dead_letter_topic;
+                    let Pending {
+                        event,
+                        deliveries,
+                        error,
+                        ..
+                    } = pending;
+                    dead_letter(self.bus.as_ref(), topic, event, deliveries, error).await;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(error = %e, "Acknowledged subscription failed");
+                    return None;
+                }
+            }
+
+            let deadline = self.inflight.next_deadline().ok()?;
+            if self.closed && deadline.is_none() {
+                return None;
+            }
+            tokio::select! {
+                event = self.events.next(), if !self.closed => match event {
+                    Some(event) => match self.inflight.deliver(event, &self.config) {
+                        Ok(delivery) => return Some(delivery),
+                        Err(e) => {
+                            tracing::warn!(error = %e, "Acknowledged subscription failed");
+                            return None;
+                        }
+                    },
+                    None => self.closed = true,
+                },
+                _ = self.inflight.changed.notified() => {}
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                    if deadline.is_some() => {}
+            }
+        }
+    }
+}
+
+/// Subscribe to a topic or pattern with at-least-once delivery
+///
+/// Every delivery must be acknowledged with [`Delivery::ack`]. Events that
+/// are rejected or time out are delivered again, and after
+/// `max_deliveries` they are published on the dead-letter topic, which is
+/// registered here, as an event carrying the original event, the number of
+/// deliveries and the last rejection reason.
+///
+/// Transports whose broker tracks acknowledgements, see
+/// [`EventBus::subscribe_with_acks`], are acknowledged through it, so
+/// events survive the subscriber's process. Otherwise unacknowledged events
+/// are tracked in this process.
+pub async fn subscribe_acked(
+    bus: Arc<dyn EventBus>,
+    topic: &str,
+    config: AckConfig,
+) -> Result<AckStream> {
+    bus.register_topic(&config.dead_letter_topic).await?;
+    let config = AckConfig {
+        max_deliveries: config.max_deliveries.max(1),
+        ..config
+    };
+    if let Some(deliveries) = bus.subscribe_with_acks(topic, &config).await? {
+        return Ok(broker_deliveries(bus, Arc::new(config), deliveries));
+    }
+    let events = bus.subscribe(topic).await?;
+    let subscription = Subscription {
+        bus,
+        config,
+        events,
+        closed: false,
+        inflight: Arc::new(Inflight::default()),
+    };
+    Ok(
+        futures::stream::unfold(subscription, |mut subscription| async move {
+            let delivery = subscription.next().await?;
+            Some((delivery, subscription))
+        })
+        .boxed(),
+    )
+}
+
+/// Deliver the events of a broker tracking their acknowledgements,
+/// dead-lettering the ones that timed out on their last delivery
+fn broker_deliveries(
+    bus: Arc<dyn EventBus>,
+    config: Arc<AckConfig>,
+    deliveries: BrokerAckStream,
+) -> AckStream {
+    deliveries
+        .filter_map(move |delivery| {
+            let bus = bus.clone();
+            let config = config.clone();
+            async move {
+                if delivery.attempt <= config.max_deliveries {
+                    return Some(Delivery {
+                        event: delivery.event,
+                        attempt: delivery.attempt,
+                        acker: Acker::Broker {
+                            ack: delivery.ack,
+                            bus,
+                            config,
+                        },
+                    });
+                }
+                let topic = &config.dead_letter_topic;
+                let deliveries = delivery.attempt - 1;
+                dead_letter(bus.as_ref(), topic, delivery.event, deliveries, None).await;
+                if let Err(e) = delivery.ack.ack().await {
+                    tracing::warn!(error = %e, "Failed to acknowledge dead-lettered event");
+                }
+                None
+            }
+        })
+        .boxed()
+}
+
+/// Publish an event that ran out of deliveries to the dead-letter topic
+async fn dead_letter(
+    bus: &dyn EventBus,
+    topic: &str,
+    original: Event,
+    deliveries: u32,
+    error: Option<String>,
+) {
+    let result = async {
+        let mut payload = Metadata::new();
+        payload.try_insert("event", &original)?;
+        payload.insert("deliveries", deliveries);
+        payload.insert("error", error);
+        let event = Event::new(topic, payload)
+            .with_cause(Cause::of(&original))
+            .with_priority(original.priority);
+        bus.publish(event).await
+    }
+    .await;
+    match result {
+        Ok(()) => tracing::warn!(
+            event_type = %original.event_type,
+            event_id = %original.id,
+            deliveries,
+            "Event dead-lettered after running out of deliveries"
+        ),
+        Err(e) => tracing::error!(
+            event_type = %original.event_type,
+            event_id = %original.id,
+            error = %e,
+            "Failed to dead-letter event"
+        ),
+    }
+}
+
+/// Visibility timeout of a subscription
+fn visibility_timeout(config: &AckConfig) -> Duration {
+    Duration::from_millis(config.visibility_timeout_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryEventBus;
+
+    #[tokio::test]
+    async fn test_redelivery_and_dead_letter() {
+        let bus = Arc::new(InMemoryEventBus::new());
+        bus.register_topic("task.run").await.unwrap();
+        bus.register_topic(DEAD_LETTER_TOPIC).await.unwrap();
+        let mut dead = bus.subscribe(DEAD_LETTER_TOPIC).await.unwrap();
+        let config = AckConfig {
+            visibility_timeout_ms: 20,
+            max_deliveries: 2,
+            ..Default::default()
+        };
+        let mut deliveries = subscribe_acked(bus.clone(), "task.run", config)
+            .await
+            .unwrap();
+
+        let first = Event::new("task.run", Metadata::new());
+        let second = Event::new("task.run", Metadata::new());
+        bus.publish(first.clone()).await.unwrap();
+        bus.publish(second.clone()).await.unwrap();
+
+        let delivery = deliveries.next().await.unwrap();
+        assert_eq!(delivery.event().id, first.id);
+        delivery.ack().await.unwrap();
+        let delivery = deliveries.next().await.unwrap();
+        assert_eq!((delivery.event().id, delivery.attempt()), (second.id, 1));
+        delivery.nack("boom").await.unwrap();
+        let delivery = deliveries.next().await.unwrap();
+        assert_eq!((delivery.event().id, delivery.attempt()), (second.id, 2));
+
+        // Dropped without an ack, it times out and has no deliveries left
+        drop(delivery);
+        let next = tokio::time::timeout(Duration::from_millis(100), deliveries.next()).await;
+        assert!(next.is_err());
+        let dead_letter = dead.next().await.unwrap();
+        assert_eq!(dead_letter.caused_by, Some(second.id.into()));
+        assert_eq!(dead_letter.payload.get::<u32>("deliveries"), Some(2));
+        assert_eq!(
+            dead_letter.payload.get::<String>("error").as_deref(),
+            Some("boom")
+        );
+        let original: Event = dead_letter.payload.get("event").unwrap();
+        assert_eq!(original.id, second.id);
+    }
+}
@@ -0,0 +1,218 @@
+//! Coalescing publisher for high-throughput event producers
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use super::{Event, EventBus};
+use crate::Error;
+
+/// Coalescing settings of a [`BatchPublisher`]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BatchConfig {
+    /// Events that fill a batch, publishing it straight away
+    pub max_events: usize,
+
+    /// How long the first event of a batch waits for more, in milliseconds
+    pub window_ms: u64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_events: 256,
+            window_ms: 10,
+        }
+    }
+}
+
+/// Request to the publishing task
+enum Command {
+    /// Add an event to the current batch
+    Publish(Event),
+
+    /// Publish the current batch now and report the outcome
+    Flush(oneshot::Sender<Result<()>>),
+}
+
+/// Publisher coalescing events into batches before handing them to a bus
+///
+/// Events are collected until a batch is full or its window elapses and then
+/// published with [`EventBus::publish_batch`] from a background task, so
+/// publishing only waits when the publisher falls a whole batch behind.
+/// Failures of background publishes are logged; [`BatchPublisher::flush`]
+/// reports its own. Events still pending are published when the publisher is
+/// dropped.
+#[derive(Debug, Clone)]
+pub struct BatchPublisher {
+    /// Channel to the publishing task
+    commands: mpsc::Sender<Command>,
+
+    /// Coalescing settings
+    config: BatchConfig,
+}
+
+impl BatchPublisher {
+    /// Start publishing batches to a bus; must be called within a Tokio
+    /// runtime
+    pub fn new(bus: Arc<dyn EventBus>, config: BatchConfig) -> Self {
+        let config = BatchConfig {
+            max_events: config.max_events.max(1),
+            ..config
+        };
+        let (commands, receiver) = mpsc::channel(config.max_events);
+        tokio::spawn(run(bus, config, receiver));
+        Self { commands, config }
+    }
+
+    /// Get the coalescing settings
+    pub fn config(&self) -> &BatchConfig {
+        &self.config
+    }
+
+    /// Add an event to the current batch, linking it to the event or task
+    /// being handled and its trace
+    pub async fn publish(&self, event: Event) -> Result<()> {
+        let event = event.with_current_cause().with_current_trace();
+        self.commands
+            .send(Command::Publish(event))
+            .await
+            .map_err(|_| stopped())?;
+        Ok(())
+    }
+
+    /// Publish the pending events now
+    pub async fn flush(&self) -> Result<()> {
+        let (reply, result) = oneshot::channel();
+        self.commands
+            .send(Command::Flush(reply))
+            .await
+            .map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+/// Collect events into batches and publish them until every publisher is
+/// dropped
+async fn run(bus: Arc<dyn EventBus>, config: BatchConfig, mut commands: mpsc::Receiver<Command>) {
+    let window = Duration::from_millis(config.window_ms);
+    let mut batch = Vec::with_capacity(config.max_events);
+    let mut deadline = Instant::now();
+    loop {
+        // Only a started batch has a deadline
+        let command = if batch.is_empty() {
+            commands.recv().await
+        } else {
+            match tokio::time::timeout_at(deadline, commands.recv()).await {
+                Ok(command) => command,
+                Err(_) => {
+                    publish(bus.as_ref(), &mut batch).await;
+                    continue;
+                }
+            }
+        };
+        match command {
+            Some(Command::Publish(event)) => {
+                if batch.is_empty() {
+                    deadline = Instant::now() + window;
+                }
+                batch.push(event);
+                if batch.len() >= config.max_events {
+                    publish(bus.as_ref(), &mut batch).await;
+                }
+            }
+            Some(Command::Flush(reply)) => {
+                let events = std::mem::take(&mut batch);
+                let result = if events.is_empty() {
+                    Ok(())
+                } else {
+                    bus.publish_batch(events).await
+                };
+                let _ = reply.send(result);
+            }
+            None => {
+                publish(bus.as_ref(), &mut batch).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Publish and clear a batch, logging failures
+async fn publish(bus: &dyn EventBus, batch: &mut Vec<Event>) {
+    if batch.is_empty() {
+        return;
+    }
+    let events = std::mem::take(batch);
+    let count = events.len();
+    if let Err(e) = bus.publish_batch(events).await {
+        tracing::warn!(error = %e, events = count, "Failed to publish event batch");
+    }
+}
+
+/// Error for a publisher whose task stopped
+fn stopped() -> Error {
+    Error::Event("Batch publisher stopped".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InMemoryEventBus, Metadata};
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_batch_publisher() {
+        let bus = Arc::new(InMemoryEventBus::new());
+        bus.register_topic("tick").await.unwrap();
+        let mut batches = bus.subscribe_batches("tick", 10).await.unwrap();
+
+        let config = BatchConfig {
+            max_events: 2,
+            window_ms: 3_600_000,
+        };
+        let publisher = BatchPublisher::new(bus.clone(), config);
+        for _ in 0..3 {
+            publisher
+                .publish(Event::new("tick", Metadata::new()))
+                .await
+                .unwrap();
+        }
+        // A full batch is published without waiting for the window
+        assert_eq!(batches.next().await.unwrap().len(), 2);
+        publisher.flush().await.unwrap();
+        assert_eq!(batches.next().await.unwrap().len(), 1);
+
+        let config = BatchConfig {
+            max_events: 100,
+            window_ms: 10,
+        };
+        let publisher = BatchPublisher::new(bus.clone(), config);
+        publisher
+            .publish(Event::new("tick", Metadata::new()))
+            .await
+            .unwrap();
+        assert_eq!(batches.next().await.unwrap().len(), 1);
+
+        bus.publish_batch(vec![
+            Event::new("tick", Metadata::new()),
+            Event::new("tick", Metadata::new()),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(batches.next().await.unwrap().len(), 2);
+        assert!(bus
+            .publish_batch(vec![
+                Event::new("tick", Metadata::new()),
+                Event::new("unknown", Metadata::new()),
+            ])
+            .await
+            .is_err());
+        assert_eq!(bus.subscriber_stats()[0].lag, 0);
+    }
+}
@@ -0,0 +1,85 @@
+//! Causal links between events and the work that emits them
+
+use std::future::Future;
+
+use uuid::Uuid;
+
+use super::Event;
+
+tokio::task_local! {
+    /// Cause of the work running in the current task
+    static CURRENT: Cause;
+}
+
+/// Position of a piece of work in a causal chain
+///
+/// Work run inside [`Cause::scope`] can look up what it is handling with
+/// [`Cause::current`], so events it emits are linked to that cause.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cause {
+    /// Identifier shared by the whole chain
+    pub correlation_id: Uuid,
+
+    /// ID of the event or task being handled
+    pub caused_by: Uuid,
+}
+
+impl Cause {
+    /// Cause of the work handling an event
+    pub fn of(event: &Event) -> Self {
+        Self {
+            correlation_id: event.correlation_id,
+            caused_by: event.id.into(),
+        }
+    }
+
+    /// Cause of the work executing a task, continuing the current chain if
+    /// there is one
+    pub fn task(task_id: Uuid) -> Self {
+        Self {
+            correlation_id: Self::current().map_or(task_id, |cause| cause.correlation_id),
+            caused_by: task_id,
+        }
+    }
+
+    /// Get the cause of the work running in the current task
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|cause| *cause).ok()
+    }
+
+    /// Run work with this as its cause
+    pub async fn scope<F: Future>(self, work: F) -> F::Output {
+        CURRENT.scope(self, work).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    #[tokio::test]
+    async fn test_chain_through_task() {
+        let root = Event::new("request", Metadata::new());
+        assert_eq!(root.correlation_id, Uuid::from(root.id));
+        assert!(Cause::current().is_none());
+
+        let task_id = Uuid::new_v4();
+        let emitted = Cause::of(&root)
+            .scope(async {
+                Cause::task(task_id)
+                    .scope(async { Event::new("step", Metadata::new()).with_current_cause() })
+                    .await
+            })
+            .await;
+        assert_eq!(emitted.correlation_id, Uuid::from(root.id));
+        assert_eq!(emitted.caused_by, Some(task_id));
+
+        // Explicit links win over the current cause
+        let reply = Event::new("reply", Metadata::new()).with_cause(Cause::of(&root));
+        let kept = Cause::task(task_id)
+            .scope(async { reply.clone().with_current_cause() })
+            .await;
+        assert_eq!(kept.caused_by, Some(root.id.into()));
+    }
+}
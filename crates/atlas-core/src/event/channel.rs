@@ -0,0 +1,461 @@
+//! Bounded subscriber queues with overflow policies
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use futures::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use super::{Event, EventBatchStream, EventPriority, EventStream};
+use crate::Error;
+
+/// Events queued for each subscriber of a new bus
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// What publishing does when a subscriber's queue is full
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait until the subscriber makes room
+    Block,
+
+    /// Drop the subscriber's oldest queued event of the lowest priority
+    #[default]
+    DropOldest,
+
+    /// Drop the subscriber's newest event of the lowest priority, which is
+    /// the event being published unless it outranks a queued one
+    DropNewest,
+
+    /// Fail the publish once the event is queued for every other subscriber
+    Error,
+}
+
+/// Bound and overflow behavior of a subscriber's queue
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    /// Maximum events queued for the subscriber
+    pub capacity: usize,
+
+    /// What publishing does when the queue is full
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CHANNEL_CAPACITY,
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// Queue depth and delivery counters of a subscriber
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SubscriberStats {
+    /// Subscriber ID
+    pub id: Uuid,
+
+    /// Topic or pattern subscribed to
+    pub topic: String,
+
+    /// Events published but not yet received, i.e. how far the subscriber
+    /// lags behind
+    pub lag: usize,
+
+    /// Maximum events queued
+    pub capacity: usize,
+
+    /// Events received
+    pub delivered: u64,
+
+    /// Events dropped because the queue was full
+    pub dropped: u64,
+}
+
+/// Queued events, one queue per priority
+#[derive(Debug, Default)]
+struct Levels {
+    /// Queued events of each priority, oldest first
+    levels: [VecDeque<Event>; EventPriority::COUNT],
+
+    /// Total queued events
+    len: usize,
+}
+
+impl Levels {
+    /// Queue an event behind the others of its priority
+    fn push(&mut self, event: Event) {
+        self.levels[event.priority.level()].push_back(event);
+        self.len += 1;
+    }
+
+    /// Take the oldest event of the highest priority
+    fn pop(&mut self) -> Option<Event> {
+        let event = self
+            .levels
+            .iter_mut()
+            .rev()
+            .find_map(|level| level.pop_front())?;
+        self.len -= 1;
+        Some(event)
+    }
+
+    /// Get the lowest priority with queued events
+    fn lowest(&self) -> Option<EventPriority> {
+        EventPriority::ALL
+            .into_iter()
+            .find(|priority| !self.levels[priority.level()].is_empty())
+    }
+
+    /// Drop the oldest or newest event of a priority
+    fn evict(&mut self, priority: EventPriority, oldest: bool) {
+        let level = &mut self.levels[priority.level()];
+        let evicted = if oldest {
+            level.pop_front()
+        } else {
+            level.pop_back()
+        };
+        self.len -= usize::from(evicted.is_some());
+    }
+}
+
+/// Bounded queue of events for one subscriber
+///
+/// Events are delivered highest priority first and in publishing order
+/// within a priority. When the queue is full, the drop policies discard
+/// events of the lowest priority first.
+#[derive(Debug)]
+pub struct EventQueue {
+    /// Subscriber ID
+    id: Uuid,
+
+    /// Topic or pattern subscribed to
+    topic: String,
+
+    /// Bound and overflow behavior
+    config: ChannelConfig,
+
+    /// Queued events
+    events: Mutex<Levels>,
+
+    /// Signalled when an event is queued or the queue closes
+    readable: Notify,
+
+    /// Signalled when an event is taken or the queue closes
+    writable: Notify,
+
+    /// Whether the subscriber or the bus went away
+    closed: AtomicBool,
+
+    /// Events received
+    delivered: AtomicU64,
+
+    /// Events dropped
+    dropped: AtomicU64,
+}
+
+impl EventQueue {
+    /// Create an empty queue for a subscriber
+    pub fn new<S: Into<String>>(topic: S, config: ChannelConfig) -> Arc<Self> {
+        Arc::new(Self {
+            id: Uuid::new_v4(),
+            topic: topic.into(),
+            config: ChannelConfig {
+                capacity: config.capacity.max(1),
+                ..config
+            },
+            events: Mutex::new(Levels::default()),
+            readable: Notify::new(),
+            writable: Notify::new(),
+            closed: AtomicBool::new(false),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Whether the subscriber or the bus went away
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Close the queue, ending the subscriber's stream once it is drained
+    /// and releasing blocked publishers
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.readable.notify_one();
+        self.writable.notify_waiters();
+    }
+
+    /// Queue an event, applying the overflow policy if the queue is full
+    pub async fn push(&self, mut event: Event) -> Result<()> {
+        loop {
+            let writable = self.writable.notified();
+            {
+                let mut events = self.events.lock().map_err(|_| poisoned())?;
+                match self.offer(&mut events, event)? {
+                    Some(blocked) => event = blocked,
+                    None => return Ok(()),
+                }
+            }
+            writable.await;
+        }
+    }
+
+    /// Queue events in order, taking the lock once for as many as fit
+    ///
+    /// Overflowing events are handled as by [`EventQueue::push`]; with
+    /// [`OverflowPolicy::Error`] the rest of the batch is still offered.
+    pub async fn push_batch(&self, events: Vec<Event>) -> Result<()> {
+        let mut events = events.into_iter();
+        let mut result = Ok(());
+        let blocked = {
+            let mut queued = self.events.lock().map_err(|_| poisoned())?;
+            let mut blocked = None;
+            for event in events.by_ref() {
+                match self.offer(&mut queued, event) {
+                    Ok(None) => {}
+                    Ok(Some(event)) => {
+                        blocked = Some(event);
+                        break;
+                    }
+                    Err(e) => result = Err(e),
+                }
+            }
+            blocked
+        };
+        // Wait for room one event at a time once the queue blocks
+        for event in blocked.into_iter().chain(events) {
+            self.push(event).await?;
+        }
+        result
+    }
+
+    /// Queue an event without waiting, handing it back if the queue is full
+    /// and publishing should block
+    fn offer(&self, events: &mut Levels, event: Event) -> Result<Option<Event>> {
+        if self.is_closed() {
+            return Ok(None);
+        }
+        if events.len < self.config.capacity {
+            events.push(event);
+            self.readable.notify_one();
+            return Ok(None);
+        }
+        let lowest = events.lowest().unwrap_or(event.priority);
+        match self.config.overflow {
+            OverflowPolicy::Block => Ok(Some(event)),
+            OverflowPolicy::DropOldest | OverflowPolicy::DropNewest => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                let oldest = self.config.overflow == OverflowPolicy::DropOldest;
+                // The event being published is the newest of its priority
+                if event.priority < lowest || (event.priority == lowest && !oldest) {
+                    return Ok(None);
+                }
+                events.evict(lowest, oldest);
+                events.push(event);
+                self.readable.notify_one();
+                Ok(None)
+            }
+            OverflowPolicy::Error => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Err(Error::Event(format!(
+                    "Queue of subscriber {} to {} is full",
+                    self.id, self.topic
+                ))
+                .into())
+            }
+        }
+    }
+
+    /// Take the oldest event of the highest priority, waiting for one; `None`
+    /// once the queue is closed and drained
+    pub async fn pop(&self) -> Option<Event> {
+        let mut batch = self.pop_batch(1).await?;
+        batch.pop()
+    }
+
+    /// Take up to `max_events` queued events in delivery order, waiting for
+    /// at least one; `None` once the queue is closed and drained
+    pub async fn pop_batch(&self, max_events: usize) -> Option<Vec<Event>> {
+        loop {
+            {
+                let mut events = self.events.lock().ok()?;
+                let batch: Vec<Event> = std::iter::from_fn(|| events.pop())
+                    .take(max_events.max(1))
+                    .collect();
+                if !batch.is_empty() {
+                    self.delivered
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    self.writable.notify_waiters();
+                    return Some(batch);
+                }
+                if self.is_closed() {
+                    return None;
+                }
+            }
+            self.readable.notified().await;
+        }
+    }
+
+    /// Get the queue depth and delivery counters
+    pub fn stats(&self) -> SubscriberStats {
+        SubscriberStats {
+            id: self.id,
+            topic: self.topic.clone(),
+            lag: self.events.lock().map_or(0, |events| events.len),
+            capacity: self.config.capacity,
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stream the queued events, closing the queue when the stream is dropped
+    pub fn into_stream(self: Arc<Self>) -> EventStream {
+        let subscription = Subscription(self);
+        futures::stream::unfold(subscription, |subscription| async move {
+            let event = subscription.0.pop().await?;
+            Some((event, subscription))
+        })
+        .boxed()
+    }
+
+    /// Stream the queued events in batches of up to `max_events`, closing the
+    /// queue when the stream is dropped
+    pub fn into_batch_stream(self: Arc<Self>, max_events: usize) -> EventBatchStream {
+        let subscription = Subscription(self);
+        futures::stream::unfold(subscription, move |subscription| async move {
+            let batch = subscription.0.pop_batch(max_events).await?;
+            Some((batch, subscription))
+        })
+        .boxed()
+    }
+}
+
+/// Closes a queue when its subscriber goes away
+struct Subscription(Arc<EventQueue>);
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Error for a lock poisoned by a panicking thread
+fn poisoned() -> Error {
+    Error::Event("Subscriber queue poisoned".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    fn event(n: u32) -> Event {
+        let mut payload = Metadata::new();
+        payload.insert("n", n);
+        Event::new("tick", payload)
+    }
+
+    fn numbers(queue: &EventQueue) -> Vec<u32> {
+        let events = queue.events.lock().unwrap();
+        let levels = events.levels.iter().rev().flatten();
+        levels.filter_map(|e| e.payload.get("n")).collect()
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let queue_with = |overflow| {
+            EventQueue::new(
+                "tick",
+                ChannelConfig {
+                    capacity: 2,
+                    overflow,
+                },
+            )
+        };
+
+        let oldest = queue_with(OverflowPolicy::DropOldest);
+        let newest = queue_with(OverflowPolicy::DropNewest);
+        let error = queue_with(OverflowPolicy::Error);
+        for n in 0..3 {
+            oldest.push(event(n)).await.unwrap();
+            newest.push(event(n)).await.unwrap();
+            let pushed = error.push(event(n)).await;
+            assert_eq!(pushed.is_err(), n == 2);
+        }
+        assert_eq!(numbers(&oldest), vec![1, 2]);
+        assert_eq!(numbers(&newest), vec![0, 1]);
+        assert_eq!(newest.stats().dropped, 1);
+        assert_eq!(error.stats().lag, 2);
+
+        let block = queue_with(OverflowPolicy::Block);
+        block.push(event(0)).await.unwrap();
+        block.push(event(1)).await.unwrap();
+        let publisher = tokio::spawn({
+            let block = block.clone();
+            async move { block.push(event(2)).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!publisher.is_finished());
+        assert_eq!(block.pop().await.unwrap().payload.get::<u32>("n"), Some(0));
+        publisher.await.unwrap().unwrap();
+        assert_eq!(numbers(&block), vec![1, 2]);
+        assert_eq!(block.stats().delivered, 1);
+    }
+
+    #[tokio::test]
+    async fn test_priority_order() {
+        let queue = |overflow| {
+            EventQueue::new(
+                "tick",
+                ChannelConfig {
+                    capacity: 3,
+                    overflow,
+                },
+            )
+        };
+        let oldest = queue(OverflowPolicy::DropOldest);
+        let newest = queue(OverflowPolicy::DropNewest);
+        let priorities = [
+            EventPriority::Low,
+            EventPriority::Normal,
+            EventPriority::Low,
+            EventPriority::Critical,
+            EventPriority::Low,
+        ];
+        for queue in [&oldest, &newest] {
+            for (n, priority) in (0..).zip(priorities) {
+                queue.push(event(n).with_priority(priority)).await.unwrap();
+            }
+        }
+        assert_eq!(numbers(&oldest), vec![3, 1, 4]);
+        assert_eq!(numbers(&newest), vec![3, 1, 0]);
+        assert_eq!(
+            oldest.pop().await.unwrap().priority,
+            EventPriority::Critical
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_closes_queue() {
+        let queue = EventQueue::new("tick", ChannelConfig::default());
+        queue.push(event(0)).await.unwrap();
+        let mut stream = queue.clone().into_stream();
+        assert!(stream.next().await.is_some());
+        drop(stream);
+        assert!(queue.is_closed());
+
+        let queue = EventQueue::new("tick", ChannelConfig::default());
+        let mut stream = queue.clone().into_stream();
+        queue.push(event(1)).await.unwrap();
+        queue.close();
+        assert!(stream.next().await.is_some());
+        assert!(stream.next().await.is_none());
+    }
+}
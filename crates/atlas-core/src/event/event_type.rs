@@ -0,0 +1,183 @@
+//! Types of the events published by the framework and by applications
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Type of an event, which is also the topic it is published on
+///
+/// Events the framework publishes have their own variants so handlers can
+/// match them exhaustively; every other type is [`EventType::Custom`]. Types
+/// are converted from and serialized as their dotted names, so a custom type
+/// spelling a framework name is the framework variant.
+#[derive(Clone, Debug)]
+pub enum EventType {
+    /// A task was submitted
+    TaskCreated,
+
+    /// A task started executing
+    TaskStarted,
+
+    /// A task finished successfully
+    TaskCompleted,
+
+    /// A task failed
+    TaskFailed,
+
+    /// A task was cancelled before finishing
+    TaskCancelled,
+
+    /// An agent's state changed
+    StateUpdated,
+
+    /// An agent's configuration was reloaded
+    ConfigReloaded,
+
+    /// An agent started
+    AgentStarted,
+
+    /// An agent stopped
+    AgentStopped,
+
+    /// Application-defined type
+    Custom(String),
+}
+
+impl EventType {
+    /// Every framework event type
+    pub const BUILT_IN: [EventType; 9] = [
+        EventType::TaskCreated,
+        EventType::TaskStarted,
+        EventType::TaskCompleted,
+        EventType::TaskFailed,
+        EventType::TaskCancelled,
+        EventType::StateUpdated,
+        EventType::ConfigReloaded,
+        EventType::AgentStarted,
+        EventType::AgentStopped,
+    ];
+
+    /// Get the dotted name of the type
+    pub fn as_str(&self) -> &str {
+        match self {
+            EventType::TaskCreated => "task.created",
+            EventType::TaskStarted => "task.started",
+            EventType::TaskCompleted => "task.completed",
+            EventType::TaskFailed => "task.failed",
+            EventType::TaskCancelled => "task.cancelled",
+            EventType::StateUpdated => "state.updated",
+            EventType::ConfigReloaded => "config.reloaded",
+            EventType::AgentStarted => "agent.started",
+            EventType::AgentStopped => "agent.stopped",
+            EventType::Custom(name) => name,
+        }
+    }
+
+    /// Whether the type is published by the framework
+    pub fn is_built_in(&self) -> bool {
+        !matches!(self, EventType::Custom(_))
+    }
+}
+
+impl From<&str> for EventType {
+    fn from(name: &str) -> Self {
+        EventType::BUILT_IN
+            .into_iter()
+            .find(|built_in| built_in.as_str() == name)
+            .unwrap_or_else(|| EventType::Custom(name.to_string()))
+    }
+}
+
+impl From<String> for EventType {
+    fn from(name: String) -> Self {
+        match EventType::from(name.as_str()) {
+            EventType::Custom(_) => EventType::Custom(name),
+            built_in => built_in,
+        }
+    }
+}
+
+impl From<&String> for EventType {
+    fn from(name: &String) -> Self {
+        EventType::from(name.as_str())
+    }
+}
+
+impl From<EventType> for String {
+    fn from(event_type: EventType) -> Self {
+        match event_type {
+            EventType::Custom(name) => name,
+            built_in => built_in.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq for EventType {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for EventType {}
+
+impl Hash for EventType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for EventType {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for EventType {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Serialize for EventType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(EventType::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_type() {
+        assert_eq!(EventType::from("task.started"), EventType::TaskStarted);
+        assert!(EventType::from("config.reloaded").is_built_in());
+        assert_eq!(
+            EventType::from("invoice.paid".to_string()),
+            EventType::Custom("invoice.paid".to_string())
+        );
+        // Spelling a framework name by hand still matches the variant
+        assert_eq!(
+            EventType::Custom("task.failed".to_string()),
+            EventType::TaskFailed
+        );
+
+        let json = serde_json::to_string(&EventType::StateUpdated).unwrap();
+        assert_eq!(json, "\"state.updated\"");
+        let parsed: EventType = serde_json::from_str(&json).unwrap();
+        assert!(matches!(parsed, EventType::StateUpdated));
+    }
+}
@@ -0,0 +1,312 @@
+//! In-process event bus
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::channel::{ChannelConfig, EventQueue, OverflowPolicy, SubscriberStats};
+use super::pattern::{PatternTrie, TopicPattern};
+use super::{Event, EventBatchStream, EventBus, EventStream};
+use crate::Error;
+
+/// Event bus delivering events between tasks of one process
+///
+/// Each subscriber has its own bounded queue. When a slow subscriber's queue
+/// is full, publishing follows the bus's [`OverflowPolicy`], which by default
+/// drops that subscriber's oldest event.
+#[derive(Debug)]
+pub struct InMemoryEventBus {
+    /// Queue settings of new subscribers
+    channel: ChannelConfig,
+
+    /// Subscriber queues of each registered topic
+    topics: RwLock<HashMap<String, Vec<Arc<EventQueue>>>>,
+
+    /// Subscriber queues of each wildcard pattern
+    patterns: RwLock<PatternTrie<Vec<Arc<EventQueue>>>>,
+}
+
+impl Default for InMemoryEventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for InMemoryEventBus {
+    fn drop(&mut self) {
+        // End every subscriber's stream once it drains
+        for queue in self.queues() {
+            queue.close();
+        }
+    }
+}
+
+impl InMemoryEventBus {
+    /// Create a bus with no topics
+    pub fn new() -> Self {
+        Self::with_channel(ChannelConfig::default())
+    }
+
+    /// Create a bus queueing up to `capacity` events per subscriber
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_channel(ChannelConfig {
+            capacity,
+            ..Default::default()
+        })
+    }
+
+    /// Create a bus whose subscribers get queues with the given settings
+    pub fn with_channel(channel: ChannelConfig) -> Self {
+        Self {
+            channel,
+            topics: RwLock::new(HashMap::new()),
+            patterns: RwLock::new(PatternTrie::new()),
+        }
+    }
+
+    /// Set what publishing does when a subscriber's queue is full
+    pub fn with_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.channel.overflow = overflow;
+        self
+    }
+
+    /// List the registered topics
+    pub fn topics(&self) -> Vec<String> {
+        match self.topics.read() {
+            Ok(topics) => topics.keys().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Get the queue depth and delivery counters of every subscriber
+    pub fn subscriber_stats(&self) -> Vec<SubscriberStats> {
+        self.queues()
+            .iter()
+            .filter(|queue| !queue.is_closed())
+            .map(|queue| queue.stats())
+            .collect()
+    }
+
+    /// Subscribe to a topic or pattern with queue settings other than the
+    /// bus's
+    pub fn subscribe_with(&self, topic: &str, channel: ChannelConfig) -> Result<EventStream> {
+        Ok(self.add_subscriber(topic, channel)?.into_stream())
+    }
+
+    /// Add a subscriber queue for a topic or pattern
+    fn add_subscriber(&self, topic: &str, channel: ChannelConfig) -> Result<Arc<EventQueue>> {
+        let pattern = TopicPattern::parse(topic)?;
+        let queue = EventQueue::new(topic, channel);
+        if pattern.is_literal() {
+            let mut topics = self.topics.write().map_err(|_| poisoned())?;
+            let queues = topics
+                .get_mut(topic)
+                .ok_or_else(|| Error::Event(format!("Topic not registered: {}", topic)))?;
+            queues.retain(|queue| !queue.is_closed());
+            queues.push(queue.clone());
+        } else {
+            let mut patterns = self.patterns.write().map_err(|_| poisoned())?;
+            let mut queues = patterns.get(&pattern).cloned().unwrap_or_default();
+            queues.retain(|queue| !queue.is_closed());
+            queues.push(queue.clone());
+            patterns.insert(&pattern, queues);
+            // Drop the patterns nobody follows any more
+            patterns.retain(|queues| queues.iter().any(|queue| !queue.is_closed()));
+        }
+        Ok(queue)
+    }
+
+    /// Get every subscriber queue
+    fn queues(&self) -> Vec<Arc<EventQueue>> {
+        let mut queues = Vec::new();
+        if let Ok(topics) = self.topics.read() {
+            queues.extend(topics.values().flatten().cloned());
+        }
+        if let Ok(patterns) = self.patterns.read() {
+            queues.extend(patterns.values().into_iter().flatten().cloned());
+        }
+        queues
+    }
+
+    /// Get the queues of the subscribers to a registered topic
+    fn subscribers(&self, topic: &str) -> Result<Vec<Arc<EventQueue>>> {
+        let mut queues = {
+            let topics = self.topics.read().map_err(|_| poisoned())?;
+            topics
+                .get(topic)
+                .cloned()
+                .ok_or_else(|| Error::Event(format!("Topic not registered: {}", topic)))?
+        };
+        let patterns = self.patterns.read().map_err(|_| poisoned())?;
+        queues.extend(patterns.matches(topic).into_iter().flatten().cloned());
+        Ok(queues)
+    }
+}
+
+/// Error for a lock poisoned by a panicking thread
+fn poisoned() -> Error {
+    Error::Event("Event bus topics poisoned".to_string())
+}
+
+#[async_trait]
+impl EventBus for InMemoryEventBus {
+    async fn register_topic(&self, topic: &str) -> Result<()> {
+        let mut topics = self.topics.write().map_err(|_| poisoned())?;
+        topics.entry(topic.to_string()).or_default();
+        Ok(())
+    }
+
+    async fn publish(&self, event: Event) -> Result<()> {
+        let mut result = Ok(());
+        for queue in self.subscribers(event.topic())? {
+            // Deliver to every subscriber even if one of them overflows
+            if let Err(e) = queue.push(event.clone()).await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<EventStream> {
+        self.subscribe_with(topic, self.channel)
+    }
+
+    async fn publish_batch(&self, events: Vec<Event>) -> Result<()> {
+        // Look up every topic first so an unknown one publishes nothing
+        let mut subscribers: HashMap<String, Vec<Arc<EventQueue>>> = HashMap::new();
+        for event in &events {
+            if !subscribers.contains_key(event.topic()) {
+                let queues = self.subscribers(event.topic())?;
+                subscribers.insert(event.topic().to_string(), queues);
+            }
+        }
+
+        // Hand each subscriber its share of the batch in one go
+        let mut batches: Vec<(Arc<EventQueue>, Vec<Event>)> = Vec::new();
+        for event in events {
+            for queue in &subscribers[event.topic()] {
+                match batches.iter_mut().find(|(q, _)| Arc::ptr_eq(q, queue)) {
+                    Some((_, batch)) => batch.push(event.clone()),
+                    None => batches.push((queue.clone(), vec![event.clone()])),
+                }
+            }
+        }
+        let mut result = Ok(());
+        for (queue, batch) in batches {
+            if let Err(e) = queue.push_batch(batch).await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    async fn subscribe_batches(&self, topic: &str, max_events: usize) -> Result<EventBatchStream> {
+        let queue = self.add_subscriber(topic, self.channel)?;
+        Ok(queue.into_batch_stream(max_events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let bus = InMemoryEventBus::new();
+        assert!(bus.subscribe("task.done").await.is_err());
+        bus.register_topic("task.done").await.unwrap();
+        bus.register_topic("task.failed").await.unwrap();
+
+        let mut first = bus.subscribe("task.done").await.unwrap();
+        let mut second = bus.subscribe("task.done").await.unwrap();
+        let mut payload = Metadata::new();
+        payload.insert("task", "fetch");
+        bus.publish(Event::new("task.failed", Metadata::new()))
+            .await
+            .unwrap();
+        bus.publish(Event::new("task.done", payload)).await.unwrap();
+
+        for stream in [&mut first, &mut second] {
+            let event = stream.next().await.unwrap();
+            assert_eq!(event.event_type, "task.done");
+            assert_eq!(
+                event.payload.get::<String>("task"),
+                Some("fetch".to_string())
+            );
+        }
+        assert!(bus
+            .publish(Event::new("unknown", Metadata::new()))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pattern_subscriptions() {
+        let bus = InMemoryEventBus::new();
+        let mut tasks = bus.subscribe("task.*").await.unwrap();
+        let mut planner = bus.subscribe("agent.planner.#").await.unwrap();
+        assert!(bus.subscribe("task.d*").await.is_err());
+
+        // Patterns follow topics registered after subscribing
+        for topic in ["task.done", "agent.planner.step", "agent.writer.step"] {
+            bus.register_topic(topic).await.unwrap();
+            bus.publish(Event::new(topic, Metadata::new()))
+                .await
+                .unwrap();
+        }
+        assert_eq!(tasks.next().await.unwrap().event_type, "task.done");
+        assert_eq!(
+            planner.next().await.unwrap().event_type,
+            "agent.planner.step"
+        );
+
+        drop(planner);
+        let _agents = bus.subscribe("agent.#").await.unwrap();
+        let patterns = bus.patterns.read().unwrap();
+        assert!(patterns
+            .get(&TopicPattern::parse("agent.planner.#").unwrap())
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bounded_subscribers() {
+        let bus = InMemoryEventBus::with_capacity(2).with_overflow(OverflowPolicy::Error);
+        bus.register_topic("tick").await.unwrap();
+        let mut slow = bus.subscribe("tick").await.unwrap();
+        let _fast = bus
+            .subscribe_with(
+                "tick",
+                ChannelConfig {
+                    capacity: 8,
+                    overflow: OverflowPolicy::DropOldest,
+                },
+            )
+            .unwrap();
+
+        for _ in 0..2 {
+            bus.publish(Event::new("tick", Metadata::new()))
+                .await
+                .unwrap();
+        }
+        assert!(bus
+            .publish(Event::new("tick", Metadata::new()))
+            .await
+            .is_err());
+        let mut lags: Vec<(usize, u64)> = bus
+            .subscriber_stats()
+            .iter()
+            .map(|s| (s.lag, s.dropped))
+            .collect();
+        lags.sort();
+        assert_eq!(lags, vec![(2, 1), (3, 0)]);
+
+        slow.next().await.unwrap();
+        drop(bus);
+        assert!(slow.next().await.is_some());
+        assert!(slow.next().await.is_none());
+    }
+}
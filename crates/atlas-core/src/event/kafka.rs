@@ -0,0 +1,615 @@
+//! Kafka transport for the event bus
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::RDKafkaErrorCode;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::pattern::TopicPattern;
+use super::{AckConfig, BrokerAck, BrokerAckStream, BrokerDelivery, Event, EventBus, EventStream};
+use crate::{Codec, Error};
+
+/// Event metadata key holding the key events are partitioned by
+///
+/// Events with the same key land in the same partition and so keep their
+/// order. Events without one are spread across partitions.
+pub const PARTITION_KEY: &str = "partition_key";
+
+/// When consumed offsets are committed
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffsetCommit {
+    /// Commit periodically in the background; a restarted consumer may see
+    /// the last few events again
+    #[default]
+    Periodic,
+
+    /// Commit each event's offset as it is delivered
+    EachEvent,
+}
+
+/// Kafka connection and topic settings
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KafkaConfig {
+    /// Comma-separated bootstrap brokers
+    pub brokers: String,
+
+    /// Prefix of the consumer groups of the subscriptions; give each agent
+    /// its own, so every agent sees every event
+    ///
+    /// Each subscription gets a group of its own, named after its topic, so
+    /// every subscription sees every event. Acknowledged subscriptions of
+    /// buses sharing the prefix share a group and so the events, and reply
+    /// subscriptions use a fresh group each.
+    pub group_id: String,
+
+    /// Prefix of every Kafka topic
+    pub topic_prefix: String,
+
+    /// Partitions of topics created on registration
+    pub partitions: i32,
+
+    /// Replication factor of topics created on registration
+    pub replication: i32,
+
+    /// When consumed offsets are committed
+    pub commit: OffsetCommit,
+
+    /// Whether a new consumer group starts from the oldest retained event
+    /// rather than the next one published
+    pub from_beginning: bool,
+
+    /// How long publishing waits for a full producer queue, in milliseconds
+    pub publish_timeout_ms: u64,
+
+    /// Encoding of published events; every process on the bus must use the
+    /// same one
+    pub codec: Codec,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            brokers: "localhost:9092".to_string(),
+            group_id: "atlas".to_string(),
+            topic_prefix: "atlas".to_string(),
+            partitions: 1,
+            replication: 1,
+            commit: OffsetCommit::default(),
+            from_beginning: false,
+            publish_timeout_ms: 5000,
+            codec: Codec::default(),
+        }
+    }
+}
+
+impl KafkaConfig {
+    /// Connect to brokers as a consumer group
+    pub fn new<B: Into<String>, G: Into<String>>(brokers: B, group_id: G) -> Self {
+        Self {
+            brokers: brokers.into(),
+            group_id: group_id.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Event bus carrying events between processes over Kafka
+///
+/// Each topic is the Kafka topic `<prefix>.<event_type>`, created on
+/// registration, with events encoded by the configured [`Codec`] and keyed
+/// by their [`PARTITION_KEY`] metadata. Wildcard patterns become regex
+/// subscriptions and so follow topics created later.
+///
+/// Acknowledged subscriptions commit an event's offset once it is
+/// acknowledged, handing out one event at a time, and seek back to events
+/// that are rejected or time out so they are delivered again. See
+/// [`KafkaConfig::group_id`] for the consumer group of each subscription.
+pub struct KafkaEventBus {
+    /// Connection and topic settings
+    config: KafkaConfig,
+
+    /// Producer shared by all publishes
+    producer: FutureProducer,
+
+    /// Client creating topics
+    admin: AdminClient<DefaultClientContext>,
+
+    /// Topics registered by this process
+    topics: RwLock<HashSet<String>>,
+
+    /// Subscriptions made by this process, by topic, telling apart the
+    /// groups of repeated ones
+    subscriptions: Mutex<HashMap<String, u32>>,
+}
+
+impl std::fmt::Debug for KafkaEventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaEventBus")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KafkaEventBus {
+    /// Create a bus; connections are made on first use
+    pub fn new(config: KafkaConfig) -> Result<Self> {
+        let client = client_config(&config);
+        let producer = client
+            .create()
+            .map_err(|e| kafka_error("Failed to create producer", e))?;
+        let admin = client
+            .create()
+            .map_err(|e| kafka_error("Failed to create admin client", e))?;
+        Ok(Self {
+            config,
+            producer,
+            admin,
+            topics: RwLock::new(HashSet::new()),
+            subscriptions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Get the connection and topic settings
+    pub fn config(&self) -> &KafkaConfig {
+        &self.config
+    }
+
+    /// Name of the Kafka topic carrying an event topic
+    fn kafka_topic(&self, topic: &str) -> String {
+        format!("{}.{}", self.config.topic_prefix, topic)
+    }
+
+    /// Check that a topic was registered by this process
+    fn check_registered(&self, topic: &str) -> Result<()> {
+        let topics = self
+            .topics
+            .read()
+            .map_err(|_| Error::Event("Event bus topics poisoned".to_string()))?;
+        if !topics.contains(topic) {
+            return Err(Error::Event(format!("Topic not registered: {}", topic)).into());
+        }
+        Ok(())
+    }
+
+    /// Get the Kafka topic, or regex of topics, a subscription follows
+    fn subscription(&self, topic: &str) -> Result<String> {
+        let pattern = TopicPattern::parse(topic)?;
+        if pattern.is_literal() {
+            self.check_registered(topic)?;
+            Ok(self.kafka_topic(topic))
+        } else {
+            Ok(topic_regex(&self.config.topic_prefix, &pattern))
+        }
+    }
+
+    /// Name the group of a subscription to a topic, distinct from the
+    /// groups of the bus's earlier subscriptions to it
+    fn group(&self, topic: &str) -> Result<String> {
+        let mut subscriptions = self
+            .subscriptions
+            .lock()
+            .map_err(|_| Error::Event("Event bus subscriptions poisoned".to_string()))?;
+        let earlier = subscriptions.entry(topic.to_string()).or_default();
+        let group = match *earlier {
+            0 => format!("{}.{}", self.config.group_id, topic),
+            n => format!("{}.{}.{}", self.config.group_id, topic, n),
+        };
+        *earlier += 1;
+        Ok(group)
+    }
+
+    /// Stream the events on a topic to a consumer in a group, committing
+    /// offsets as configured, or never
+    fn events(
+        &self,
+        topic: &str,
+        group: &str,
+        commit: Option<OffsetCommit>,
+    ) -> Result<EventStream> {
+        let subscription = self.subscription(topic)?;
+        let consumer = self.consumer(group, commit == Some(OffsetCommit::Periodic))?;
+        consumer
+            .subscribe(&[&subscription])
+            .map_err(|e| kafka_error("Failed to subscribe", e))?;
+
+        let codec = self.config.codec;
+        Ok(
+            futures::stream::unfold(consumer, move |consumer| async move {
+                loop {
+                    let event = match consumer.recv().await {
+                        Ok(message) => {
+                            if commit == Some(OffsetCommit::EachEvent) {
+                                if let Err(e) = consumer.commit_message(&message, CommitMode::Async)
+                                {
+                                    tracing::warn!(error = %e, "Failed to commit Kafka offset");
+                                }
+                            }
+                            message.payload().and_then(|payload| decode(codec, payload))
+                        }
+                        Err(e) => {
+                            tracing::warn!(error = %e, "Kafka delivery failed");
+                            None
+                        }
+                    };
+                    if let Some(event) = event {
+                        return Some((event, consumer));
+                    }
+                }
+            })
+            .boxed(),
+        )
+    }
+
+    /// Create a consumer in a group, committing offsets in the background or
+    /// only when told to
+    fn consumer(&self, group: &str, auto_commit: bool) -> Result<StreamConsumer> {
+        let mut client = client_config(&self.config);
+        client
+            .set("group.id", group)
+            .set("enable.auto.commit", auto_commit.to_string())
+            .set(
+                "auto.offset.reset",
+                if self.config.from_beginning {
+                    "earliest"
+                } else {
+                    "latest"
+                },
+            );
+        client
+            .create()
+            .map_err(|e| kafka_error("Failed to create consumer", e).into())
+    }
+}
+
+#[async_trait]
+impl EventBus for KafkaEventBus {
+    async fn register_topic(&self, topic: &str) -> Result<()> {
+        if !TopicPattern::parse(topic)?.is_literal() {
+            return Err(Error::Event(format!("Topics cannot contain wildcards: {}", topic)).into());
+        }
+        let name = self.kafka_topic(topic);
+        let new_topic = NewTopic::new(
+            &name,
+            self.config.partitions,
+            TopicReplication::Fixed(self.config.replication),
+        );
+        let results = self
+            .admin
+            .create_topics([&new_topic], &AdminOptions::new())
+            .await
+            .map_err(|e| kafka_error("Failed to create topic", e))?;
+        for result in results {
+            match result {
+                Ok(_) | Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
+                Err((name, code)) => {
+                    return Err(
+                        kafka_error(&format!("Failed to create topic {}", name), code).into(),
+                    )
+                }
+            }
+        }
+
+        let mut topics = self
+            .topics
+            .write()
+            .map_err(|_| Error::Event("Event bus topics poisoned".to_string()))?;
+        topics.insert(topic.to_string());
+        Ok(())
+    }
+
+    async fn publish(&self, event: Event) -> Result<()> {
+        self.check_registered(event.topic())?;
+        let topic = self.kafka_topic(event.topic());
+        let payload = self.config.codec.encode(&event)?;
+        let key = event.metadata.get::<String>(PARTITION_KEY);
+        let mut record = FutureRecord::to(&topic).payload(&payload);
+        if let Some(key) = &key {
+            record = record.key(key);
+        }
+        self.producer
+            .send(
+                record,
+                Duration::from_millis(self.config.publish_timeout_ms),
+            )
+            .await
+            .map_err(|(e, _)| kafka_error("Failed to publish event", e))?;
+        Ok(())
+    }
+
+    async fn publish_batch(&self, events: Vec<Event>) -> Result<()> {
+        // Queue every record before waiting for any delivery, letting the
+        // producer batch them; records are queued in order
+        let mut records = Vec::with_capacity(events.len());
+        for event in &events {
+            self.check_registered(event.topic())?;
+            let topic = self.kafka_topic(event.topic());
+            let payload = self.config.codec.encode(event)?;
+            let key = event.metadata.get::<String>(PARTITION_KEY);
+            records.push((topic, payload, key));
+        }
+        let timeout = Duration::from_millis(self.config.publish_timeout_ms);
+        let deliveries = records.iter().map(|(topic, payload, key)| {
+            let mut record = FutureRecord::to(topic).payload(payload);
+            if let Some(key) = key {
+                record = record.key(key);
+            }
+            self.producer.send(record, timeout)
+        });
+        futures::future::try_join_all(deliveries)
+            .await
+            .map_err(|(e, _)| kafka_error("Failed to publish event", e))?;
+        Ok(())
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<EventStream> {
+        let group = self.group(topic)?;
+        self.events(topic, &group, Some(self.config.commit))
+    }
+
+    async fn subscribe_replies(&self, topic: &str) -> Result<EventStream> {
+        // Replies are for this requester alone, and only while it waits
+        let group = format!("{}.reply.{}", self.config.group_id, Uuid::new_v4());
+        self.events(topic, &group, None)
+    }
+
+    async fn subscribe_with_acks(
+        &self,
+        topic: &str,
+        config: &AckConfig,
+    ) -> Result<Option<BrokerAckStream>> {
+        let subscription = self.subscription(topic)?;
+        let group = format!("{}.acked.{}", self.config.group_id, topic);
+        let consumer = self.consumer(&group, false)?;
+        consumer
+            .subscribe(&[&subscription])
+            .map_err(|e| kafka_error("Failed to subscribe", e))?;
+        let acked = Acked {
+            consumer: Arc::new(consumer),
+            codec: self.config.codec,
+            visibility_timeout: Duration::from_millis(config.visibility_timeout_ms),
+            outstanding: None,
+            redeliveries: HashMap::new(),
+        };
+        Ok(Some(
+            futures::stream::unfold(acked, |mut acked| async move {
+                let delivery = acked.next().await?;
+                Some((delivery, acked))
+            })
+            .boxed(),
+        ))
+    }
+}
+
+/// Offset of a message in a partition
+#[derive(Clone, Debug)]
+struct Position {
+    /// Kafka topic
+    topic: String,
+
+    /// Partition of the topic
+    partition: i32,
+
+    /// Offset of the message
+    offset: i64,
+}
+
+/// State of an acknowledged subscription, handing out one delivery at a
+/// time
+struct Acked {
+    /// Consumer committing offsets only when told to
+    consumer: Arc<StreamConsumer>,
+
+    /// Encoding of the events
+    codec: Codec,
+
+    /// How long a delivery may go unacknowledged
+    visibility_timeout: Duration,
+
+    /// Delivery handed out last, its deliveries so far, and whether it was
+    /// acknowledged once settled
+    outstanding: Option<(Position, u32, oneshot::Receiver<bool>)>,
+
+    /// Offset to deliver again in each partition, and its deliveries so far
+    redeliveries: HashMap<(String, i32), (i64, u32)>,
+}
+
+impl Acked {
+    /// Wait for the last delivery to settle, then deliver the next event
+    async fn next(&mut self) -> Option<BrokerDelivery> {
+        if let Some((position, attempt, acked)) = self.outstanding.take() {
+            let partition = (position.topic.clone(), position.partition);
+            match tokio::time::timeout(self.visibility_timeout, acked).await {
+                Ok(Ok(true)) => {
+                    self.redeliveries.remove(&partition);
+                }
+                _ => {
+                    let offset = Offset::Offset(position.offset);
+                    if let Err(e) = self.consumer.seek(
+                        &position.topic,
+                        position.partition,
+                        offset,
+                        self.visibility_timeout,
+                    ) {
+                        tracing::warn!(error = %e, "Failed to seek back to Kafka offset");
+                    }
+                    self.redeliveries
+                        .insert(partition, (position.offset, attempt));
+                }
+            }
+        }
+
+        loop {
+            let (position, event) = match self.consumer.recv().await {
+                Ok(message) => (
+                    Position {
+                        topic: message.topic().to_string(),
+                        partition: message.partition(),
+                        offset: message.offset(),
+                    },
+                    message
+                        .payload()
+                        .and_then(|payload| decode(self.codec, payload)),
+                ),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Kafka delivery failed");
+                    continue;
+                }
+            };
+            let Some(event) = event else {
+                if let Err(e) = commit(&self.consumer, &position) {
+                    tracing::warn!(error = %e, "Failed to commit Kafka offset");
+                }
+                continue;
+            };
+            let attempt = match self
+                .redeliveries
+                .get(&(position.topic.clone(), position.partition))
+            {
+                Some((offset, attempt)) if *offset == position.offset => attempt + 1,
+                _ => 1,
+            };
+            let (settled, acked) = oneshot::channel();
+            self.outstanding = Some((position.clone(), attempt, acked));
+            return Some(BrokerDelivery {
+                event,
+                attempt,
+                ack: Box::new(KafkaAck {
+                    consumer: self.consumer.clone(),
+                    position,
+                    settled,
+                }),
+            });
+        }
+    }
+}
+
+/// Acknowledgement of a Kafka message
+struct KafkaAck {
+    /// Consumer the message was delivered to
+    consumer: Arc<StreamConsumer>,
+
+    /// Offset of the message
+    position: Position,
+
+    /// Tells the subscription whether the message was acknowledged
+    settled: oneshot::Sender<bool>,
+}
+
+#[async_trait]
+impl BrokerAck for KafkaAck {
+    async fn ack(self: Box<Self>) -> Result<()> {
+        let result = commit(&self.consumer, &self.position);
+        let _ = self.settled.send(true);
+        result
+    }
+
+    async fn nack(self: Box<Self>) -> Result<()> {
+        let _ = self.settled.send(false);
+        Ok(())
+    }
+}
+
+/// Commit the offset after a message
+fn commit(consumer: &StreamConsumer, position: &Position) -> Result<()> {
+    let mut offsets = TopicPartitionList::new();
+    offsets
+        .add_partition_offset(
+            &position.topic,
+            position.partition,
+            Offset::Offset(position.offset + 1),
+        )
+        .map_err(|e| kafka_error("Invalid Kafka offset", e))?;
+    consumer
+        .commit(&offsets, CommitMode::Async)
+        .map_err(|e| kafka_error("Failed to commit Kafka offset", e).into())
+}
+
+/// Client settings shared by producers, consumers and the admin client
+fn client_config(config: &KafkaConfig) -> ClientConfig {
+    let mut client = ClientConfig::new();
+    client.set("bootstrap.servers", &config.brokers);
+    client
+}
+
+/// Regex subscription matching the Kafka topics of a wildcard pattern
+fn topic_regex(prefix: &str, pattern: &TopicPattern) -> String {
+    let mut regex = format!("^{}", escape(prefix));
+    for segment in pattern.as_str().split('.') {
+        match segment {
+            "*" => regex.push_str(r"\.[^.]+"),
+            "#" => regex.push_str(r"(\.[^.]+)*"),
+            literal => {
+                regex.push_str(r"\.");
+                regex.push_str(&escape(literal));
+            }
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Escape the regex metacharacters in a topic name
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() && c != '_' && c != '-' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Decode an event, skipping malformed messages
+fn decode(codec: Codec, payload: &[u8]) -> Option<Event> {
+    match codec.decode(payload) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            tracing::warn!(error = %e, "Skipping malformed event message");
+            None
+        }
+    }
+}
+
+/// Wrap a Kafka client error
+fn kafka_error(context: &str, e: impl std::fmt::Display) -> Error {
+    Error::Event(format!("{}: {}", context, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_regex() {
+        let regex_of = |pattern: &str| topic_regex("atlas", &TopicPattern::parse(pattern).unwrap());
+        assert_eq!(regex_of("task.*"), r"^atlas\.task\.[^.]+$");
+        assert_eq!(
+            regex_of("agent.planner.#"),
+            r"^atlas\.agent\.planner(\.[^.]+)*$"
+        );
+        assert_eq!(regex_of("#.done"), r"^atlas(\.[^.]+)*\.done$");
+    }
+
+    #[test]
+    fn test_subscription_groups() {
+        let bus = KafkaEventBus::new(KafkaConfig::new("localhost:9092", "planner")).unwrap();
+        assert_eq!(bus.group("task.*").unwrap(), "planner.task.*");
+        assert_eq!(bus.group("task.*").unwrap(), "planner.task.*.1");
+        assert_eq!(bus.group("task.done").unwrap(), "planner.task.done");
+    }
+}
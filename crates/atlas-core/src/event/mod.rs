@@ -0,0 +1,477 @@
+//! Event system for inter-agent communication
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{Error, EventId, Metadata};
+
+pub mod ack;
+pub mod batch;
+pub mod cause;
+pub mod channel;
+pub mod event_type;
+pub mod in_memory;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod pattern;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod trace;
+pub mod version;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisEventBus;
+pub use ack::{
+    subscribe_acked, AckConfig, AckStream, BrokerAck, BrokerAckStream, BrokerDelivery, Delivery,
+    DEAD_LETTER_TOPIC,
+};
+pub use batch::{BatchConfig, BatchPublisher};
+pub use cause::Cause;
+pub use channel::{ChannelConfig, OverflowPolicy, SubscriberStats};
+pub use event_type::EventType;
+pub use in_memory::InMemoryEventBus;
+#[cfg(feature = "kafka")]
+pub use kafka::{KafkaConfig, KafkaEventBus, OffsetCommit};
+#[cfg(feature = "nats")]
+pub use nats::{JetStreamConfig, NatsEventBus};
+pub use pattern::TopicPattern;
+pub use trace::{TraceContext, TRACEPARENT};
+pub use version::Upcasters;
+
+/// Metadata key naming the topic a request's replies are published on
+pub const REPLY_TO: &str = "reply_to";
+
+/// Delivery priority of an event
+///
+/// Subscribers receive queued events of a higher priority first, so control
+/// events such as cancellation overtake bulk telemetry.
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum EventPriority {
+    /// Bulk events such as telemetry
+    Low,
+
+    /// Ordinary events
+    #[default]
+    Normal,
+
+    /// Events that should not wait behind ordinary ones
+    High,
+
+    /// Control events such as cancellation and shutdown
+    Critical,
+}
+
+impl EventPriority {
+    /// Number of priorities
+    pub const COUNT: usize = 4;
+
+    /// Every priority, lowest first
+    pub const ALL: [Self; Self::COUNT] = [Self::Low, Self::Normal, Self::High, Self::Critical];
+
+    /// Index of the priority, lowest first
+    pub fn level(self) -> usize {
+        self as usize
+    }
+}
+
+/// Event published on a bus; its type is the topic it is published on
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Event {
+    /// Unique identifier for this event
+    pub id: EventId,
+
+    /// The type of event
+    pub event_type: EventType,
+
+    /// Event payload
+    pub payload: Metadata,
+
+    /// Event metadata
+    pub metadata: Metadata,
+
+    /// Identifier shared by the events of one causal chain; an event that
+    /// starts a chain uses its own ID, and one received without an ID starts
+    /// a chain of its own
+    #[serde(default = "Uuid::new_v4")]
+    pub correlation_id: Uuid,
+
+    /// ID of the event or task this event was emitted while handling
+    #[serde(default)]
+    pub caused_by: Option<Uuid>,
+
+    /// Delivery priority
+    #[serde(default)]
+    pub priority: EventPriority,
+}
+
+impl Event {
+    pub fn new<T: Into<EventType>>(event_type: T, payload: Metadata) -> Self {
+        let id = EventId::new();
+        Self {
+            id,
+            event_type: event_type.into(),
+            payload,
+            metadata: Metadata::new(),
+            correlation_id: id.into(),
+            caused_by: None,
+            priority: EventPriority::default(),
+        }
+    }
+
+    /// Set the delivery priority
+    pub fn with_priority(mut self, priority: EventPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Link the event to the event or task that caused it
+    pub fn with_cause(mut self, cause: Cause) -> Self {
+        self.correlation_id = cause.correlation_id;
+        self.caused_by = Some(cause.caused_by);
+        self
+    }
+
+    /// Link the event to the current [`Cause`], unless it is already linked
+    pub fn with_current_cause(self) -> Self {
+        match Cause::current() {
+            Some(cause) if self.caused_by.is_none() => self.with_cause(cause),
+            _ => self,
+        }
+    }
+
+    /// Record the current [`TraceContext`], or a new trace, as the event's
+    /// [`TRACEPARENT`], unless it already has one
+    pub fn with_current_trace(mut self) -> Self {
+        if self.metadata.get::<String>(TRACEPARENT).is_none() {
+            let context = TraceContext::current().unwrap_or_else(TraceContext::root);
+            self.metadata.insert(TRACEPARENT, context.to_string());
+        }
+        self
+    }
+
+    /// Get the topic the event is published on
+    pub fn topic(&self) -> &str {
+        self.event_type.as_str()
+    }
+
+    /// Get the event type without its version suffix
+    pub fn base_type(&self) -> &str {
+        version::split_version(self.event_type.as_str()).0
+    }
+
+    /// Get the version of the event type, 1 unless it ends in `v<N>`
+    pub fn version(&self) -> u32 {
+        version::split_version(self.event_type.as_str()).1
+    }
+
+    /// Get the topic replies to this event go to, if it is a request
+    pub fn reply_to(&self) -> Option<String> {
+        self.metadata.get(REPLY_TO)
+    }
+
+    /// Create a reply to this request, caused by it
+    pub fn reply(&self, payload: Metadata) -> Result<Event> {
+        let topic = self.reply_to().ok_or_else(|| {
+            Error::Event(format!(
+                "Event {} ({}) is not a request",
+                self.event_type, self.id
+            ))
+        })?;
+        Ok(Event::new(topic, payload).with_cause(Cause::of(self)))
+    }
+
+    /// Convert the payload to a typed value
+    pub fn try_payload<T: DeserializeOwned>(&self) -> Result<T> {
+        self.payload.to_typed().map_err(|e| {
+            Error::Event(format!(
+                "Invalid payload for event {} ({}): {}",
+                self.event_type, self.id, e
+            ))
+            .into()
+        })
+    }
+}
+
+/// Event whose payload is a typed value
+///
+/// The payload must serialize to a JSON object, whose fields become the
+/// payload of the untyped [`Event`].
+#[derive(Clone, Debug)]
+pub struct TypedEvent<T> {
+    /// Unique identifier for this event
+    pub id: EventId,
+
+    /// The type of event
+    pub event_type: EventType,
+
+    /// Event payload
+    pub payload: T,
+
+    /// Event metadata
+    pub metadata: Metadata,
+
+    /// Identifier shared by the events of one causal chain
+    pub correlation_id: Uuid,
+
+    /// ID of the event or task this event was emitted while handling
+    pub caused_by: Option<Uuid>,
+
+    /// Delivery priority
+    pub priority: EventPriority,
+}
+
+impl<T: Serialize + DeserializeOwned> TypedEvent<T> {
+    pub fn new<S: Into<EventType>>(event_type: S, payload: T) -> Self {
+        let id = EventId::new();
+        Self {
+            id,
+            event_type: event_type.into(),
+            payload,
+            metadata: Metadata::new(),
+            correlation_id: id.into(),
+            caused_by: None,
+            priority: EventPriority::default(),
+        }
+    }
+
+    /// Convert an event, failing if its payload is not a `T`
+    pub fn from_event(event: Event) -> Result<Self> {
+        Ok(Self {
+            payload: event.try_payload()?,
+            id: event.id,
+            event_type: event.event_type,
+            metadata: event.metadata,
+            correlation_id: event.correlation_id,
+            caused_by: event.caused_by,
+            priority: event.priority,
+        })
+    }
+
+    /// Convert to an event that can be published
+    pub fn into_event(self) -> Result<Event> {
+        let payload = Metadata::from_serialize(&self.payload).map_err(|e| {
+            Error::Event(format!(
+                "Invalid payload for event {}: {}",
+                self.event_type, e
+            ))
+        })?;
+        Ok(Event {
+            id: self.id,
+            event_type: self.event_type,
+            payload,
+            metadata: self.metadata,
+            correlation_id: self.correlation_id,
+            caused_by: self.caused_by,
+            priority: self.priority,
+        })
+    }
+}
+
+/// Stream of events delivered to a subscriber
+pub type EventStream = BoxStream<'static, Event>;
+
+/// Stream of event batches delivered to a subscriber
+pub type EventBatchStream = BoxStream<'static, Vec<Event>>;
+
+/// Handler for events delivered by a bus
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    /// Process an event
+    async fn handle_event(&self, event: Event) -> Result<()>;
+}
+
+/// Publish/subscribe transport for events
+///
+/// Events are routed by topic, which is their `event_type`. Topics must be
+/// registered before events are published or subscribed to on them, except
+/// that subscribing to a [`TopicPattern`] with wildcards follows every
+/// matching topic, including ones registered later. In-process buses deliver
+/// queued events by [`EventPriority`]; remote transports carry the priority
+/// but deliver in publishing order.
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    /// Register a topic; registering a known topic does nothing
+    async fn register_topic(&self, topic: &str) -> Result<()>;
+
+    /// Publish an event to the current subscribers of its topic
+    async fn publish(&self, event: Event) -> Result<()>;
+
+    /// Stream the events published after subscribing on a topic, or on
+    /// every topic matching a pattern
+    async fn subscribe(&self, topic: &str) -> Result<EventStream>;
+
+    /// Publish events in order, sharing the per-publish overhead where the
+    /// transport allows; by default they are published one at a time
+    async fn publish_batch(&self, events: Vec<Event>) -> Result<()> {
+        for event in events {
+            self.publish(event).await?;
+        }
+        Ok(())
+    }
+
+    /// Subscribe like [`EventBus::subscribe`], receiving up to `max_events`
+    /// of the events already delivered at a time
+    async fn subscribe_batches(&self, topic: &str, max_events: usize) -> Result<EventBatchStream> {
+        let events = self.subscribe(topic).await?;
+        Ok(events.ready_chunks(max_events.max(1)).boxed())
+    }
+
+    /// Stream events whose acknowledgements the broker tracks, redelivering
+    /// them as configured, for [`subscribe_acked`]; by default `None`,
+    /// leaving acknowledgements to be tracked in this process
+    async fn subscribe_with_acks(
+        &self,
+        _topic: &str,
+        _config: &AckConfig,
+    ) -> Result<Option<BrokerAckStream>> {
+        Ok(None)
+    }
+
+    /// Stream the events published on a reply topic after subscribing, for
+    /// this subscriber alone; by default the same as [`EventBus::subscribe`]
+    async fn subscribe_replies(&self, topic: &str) -> Result<EventStream> {
+        self.subscribe(topic).await
+    }
+
+    /// Publish a request and wait for the first reply caused by it
+    ///
+    /// Replies go to the request's [`REPLY_TO`] topic, which defaults to
+    /// `<event_type>.reply`.
+    async fn request(&self, mut event: Event, timeout: Duration) -> Result<Event> {
+        let reply_to = match event.reply_to() {
+            Some(reply_to) => reply_to,
+            None => {
+                let reply_to = format!("{}.reply", event.topic());
+                event.metadata.insert(REPLY_TO, &reply_to);
+                reply_to
+            }
+        };
+        // Subscribe before publishing so a quick reply is not missed
+        self.register_topic(&reply_to).await?;
+        let replies = self.subscribe_replies(&reply_to).await?;
+        let (id, event_type) = (event.id, event.event_type.clone());
+        self.publish(event).await?;
+
+        let mut replies =
+            replies.filter(move |reply| std::future::ready(reply.caused_by == Some(id.into())));
+        match tokio::time::timeout(timeout, replies.next()).await {
+            Ok(Some(reply)) => Ok(reply),
+            Ok(None) => Err(Error::Event(format!(
+                "Subscription closed before a reply to {} ({})",
+                event_type, id
+            ))
+            .into()),
+            Err(_) => Err(Error::Timeout(format!(
+                "No reply to {} ({}) within {:?}",
+                event_type, id, timeout
+            ))
+            .into()),
+        }
+    }
+
+    /// Publish a reply to a request on its [`REPLY_TO`] topic
+    async fn respond(&self, request: &Event, payload: Metadata) -> Result<()> {
+        let reply = request.reply(payload)?;
+        self.register_topic(reply.topic()).await?;
+        self.publish(reply).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TaskDone {
+        task: String,
+        attempts: u32,
+    }
+
+    #[test]
+    fn test_typed_payload() {
+        let done = TaskDone {
+            task: "fetch".to_string(),
+            attempts: 2,
+        };
+        let event = TypedEvent::new("task.done", done).into_event().unwrap();
+        assert_eq!(event.payload.get::<u32>("attempts"), Some(2));
+
+        let typed = TypedEvent::<TaskDone>::from_event(event.clone()).unwrap();
+        assert_eq!(typed.id, event.id);
+        assert_eq!(typed.payload.task, "fetch");
+
+        let mut payload = Metadata::new();
+        payload.insert("task", "fetch");
+        let err = Event::new("task.done", payload)
+            .try_payload::<TaskDone>()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("task.done") && err.contains("attempts"));
+        assert!(TypedEvent::new("count", 3).into_event().is_err());
+    }
+
+    #[test]
+    fn test_missing_correlation_id() {
+        let decode = || {
+            let mut value = serde_json::to_value(Event::new("legacy", Metadata::new())).unwrap();
+            value.as_object_mut().unwrap().remove("correlation_id");
+            serde_json::from_value::<Event>(value)
+                .unwrap()
+                .correlation_id
+        };
+        let (first, second) = (decode(), decode());
+        assert!(!first.is_nil());
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_request_reply() {
+        let bus = std::sync::Arc::new(InMemoryEventBus::new());
+        bus.register_topic("math.double").await.unwrap();
+        let mut requests = bus.subscribe("math.double").await.unwrap();
+        let responder = tokio::spawn({
+            let bus = bus.clone();
+            async move {
+                let request = requests.next().await.unwrap();
+                let n: u32 = request.payload.get("n").unwrap();
+                let mut payload = Metadata::new();
+                payload.insert("n", n * 2);
+                // An unrelated reply on the same topic is not picked up
+                bus.publish(Event::new("math.double.reply", payload.clone()))
+                    .await
+                    .unwrap();
+                bus.respond(&request, payload).await.unwrap();
+            }
+        });
+
+        let mut payload = Metadata::new();
+        payload.insert("n", 21);
+        let request = Event::new("math.double", payload);
+        let id = request.id;
+        let reply = bus.request(request, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(reply.payload.get::<u32>("n"), Some(42));
+        assert_eq!(reply.caused_by, Some(id.into()));
+        responder.await.unwrap();
+
+        let request = Event::new("math.double", Metadata::new());
+        let err = bus
+            .request(request, Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No reply"));
+        assert!(Event::new("math.double", Metadata::new())
+            .reply(Metadata::new())
+            .is_err());
+    }
+}
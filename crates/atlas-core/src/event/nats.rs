@@ -0,0 +1,491 @@
+//! NATS transport for the event bus
+
+use std::collections::HashSet;
+use std::hash::Hasher;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_nats::jetstream::{self, consumer, stream, AckKind};
+use async_trait::async_trait;
+use fnv::FnvHasher;
+use futures::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use super::pattern::TopicPattern;
+use super::{AckConfig, BrokerAck, BrokerAckStream, BrokerDelivery, Event, EventBus, EventStream};
+use crate::{Codec, Error};
+
+/// Subject prefix used by a new bus
+pub const DEFAULT_SUBJECT_PREFIX: &str = "atlas.events";
+
+/// JetStream durability settings
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JetStreamConfig {
+    /// Name of the stream storing the bus's events, created if missing
+    pub stream: String,
+
+    /// Name prefix of a durable consumer per subscription, so a restarted
+    /// subscriber resumes where it stopped; without one, subscribers only
+    /// receive events published after subscribing
+    #[serde(default)]
+    pub durable: Option<String>,
+}
+
+/// Event bus carrying events between processes over NATS
+///
+/// Each topic is the subject `<prefix>.<event_type>`, with events encoded
+/// by the bus's [`Codec`], JSON by default. Wildcard patterns map onto NATS wildcards where possible and are
+/// otherwise filtered after delivery. With JetStream enabled, events are
+/// stored in a stream and publishing waits for the server to acknowledge
+/// them; with a durable consumer too, acknowledged subscriptions are
+/// acknowledged to JetStream, which redelivers what is not.
+#[derive(Debug)]
+pub struct NatsEventBus {
+    /// Connection to the server
+    client: async_nats::Client,
+
+    /// Prefix of every subject
+    prefix: String,
+
+    /// JetStream context and settings, when durability is enabled
+    jetstream: Option<(jetstream::Context, JetStreamConfig)>,
+
+    /// JetStream stream, created on first use
+    stream: OnceCell<stream::Stream>,
+
+    /// Topics registered by this process
+    topics: RwLock<HashSet<String>>,
+
+    /// Encoding of published events
+    codec: Codec,
+}
+
+impl NatsEventBus {
+    /// Connect to a NATS server
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|e| nats_error("Failed to connect to NATS", e))?;
+        Ok(Self::from_client(client))
+    }
+
+    /// Use an existing connection
+    pub fn from_client(client: async_nats::Client) -> Self {
+        Self {
+            client,
+            prefix: DEFAULT_SUBJECT_PREFIX.to_string(),
+            jetstream: None,
+            stream: OnceCell::new(),
+            topics: RwLock::new(HashSet::new()),
+            codec: Codec::default(),
+        }
+    }
+
+    /// Set the prefix of every subject
+    pub fn with_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Store events in a JetStream stream
+    pub fn with_jetstream(mut self, config: JetStreamConfig) -> Self {
+        self.jetstream = Some((jetstream::new(self.client.clone()), config));
+        self
+    }
+
+    /// Encode events with a codec; every process on the bus must use the
+    /// same one
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Get the connection to the server
+    pub fn client(&self) -> &async_nats::Client {
+        &self.client
+    }
+
+    /// Get the JetStream stream, creating it if needed, when durability is
+    /// enabled
+    async fn stream(&self) -> Result<Option<&stream::Stream>> {
+        let Some((context, config)) = &self.jetstream else {
+            return Ok(None);
+        };
+        let stream = self
+            .stream
+            .get_or_try_init(|| async {
+                context
+                    .get_or_create_stream(stream::Config {
+                        name: config.stream.clone(),
+                        subjects: vec![format!("{}.>", self.prefix)],
+                        ..Default::default()
+                    })
+                    .await
+                    .map_err(|e| nats_error("Failed to create JetStream stream", e))
+            })
+            .await?;
+        Ok(Some(stream))
+    }
+
+    /// Check that a topic was registered by this process
+    fn check_registered(&self, topic: &str) -> Result<()> {
+        let topics = self
+            .topics
+            .read()
+            .map_err(|_| Error::Event("Event bus topics poisoned".to_string()))?;
+        if !topics.contains(topic) {
+            return Err(Error::Event(format!("Topic not registered: {}", topic)).into());
+        }
+        Ok(())
+    }
+
+    /// Stream the events on a subject
+    async fn subscribe_subject(&self, subject: String) -> Result<EventStream> {
+        let codec = self.codec;
+        let Some(stream) = self.stream().await? else {
+            let subscriber = self
+                .client
+                .subscribe(subject)
+                .await
+                .map_err(|e| nats_error("Failed to subscribe", e))?;
+            return Ok(subscriber
+                .filter_map(move |message| async move { decode(codec, &message.payload) })
+                .boxed());
+        };
+
+        let durable = self
+            .jetstream
+            .as_ref()
+            .and_then(|(_, c)| c.durable.as_ref());
+        let Some(durable) = durable else {
+            let messages = stream
+                .create_consumer(consumer::pull::OrderedConfig {
+                    filter_subject: subject,
+                    deliver_policy: consumer::DeliverPolicy::New,
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| nats_error("Failed to create JetStream consumer", e))?
+                .messages()
+                .await
+                .map_err(|e| nats_error("Failed to consume JetStream messages", e))?;
+            return Ok(messages
+                .filter_map(move |message| async move {
+                    match message {
+                        Ok(message) => decode(codec, &message.payload),
+                        Err(e) => {
+                            tracing::warn!(error = %e, "JetStream delivery failed");
+                            None
+                        }
+                    }
+                })
+                .boxed());
+        };
+
+        let name = consumer_name(durable, &subject);
+        let messages = stream
+            .get_or_create_consumer(
+                &name,
+                consumer::pull::Config {
+                    durable_name: Some(name.clone()),
+                    filter_subject: subject,
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| nats_error("Failed to create JetStream consumer", e))?
+            .messages()
+            .await
+            .map_err(|e| nats_error("Failed to consume JetStream messages", e))?;
+        Ok(messages
+            .filter_map(move |message| async move {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "JetStream delivery failed");
+                        return None;
+                    }
+                };
+                // Acknowledge on delivery so a restart does not replay it;
+                // acknowledged subscriptions use their own consumers
+                if let Err(e) = message.ack().await {
+                    tracing::warn!(error = %e, "Failed to acknowledge JetStream message");
+                }
+                decode(codec, &message.payload)
+            })
+            .boxed())
+    }
+
+    /// Stream the messages on a subject through a durable consumer that
+    /// redelivers them until they are acknowledged, if durability is enabled
+    async fn subscribe_subject_acked(
+        &self,
+        subject: String,
+        config: &AckConfig,
+    ) -> Result<Option<BrokerAckStream>> {
+        let durable = self
+            .jetstream
+            .as_ref()
+            .and_then(|(_, c)| c.durable.as_ref());
+        let (Some(stream), Some(durable)) = (self.stream().await?, durable) else {
+            return Ok(None);
+        };
+        let codec = self.codec;
+        let name = consumer_name(&format!("{}-acked", durable), &subject);
+        let messages = stream
+            .get_or_create_consumer(
+                &name,
+                consumer::pull::Config {
+                    durable_name: Some(name.clone()),
+                    filter_subject: subject,
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ack_wait: Duration::from_millis(config.visibility_timeout_ms),
+                    // One delivery past the limit, to be dead-lettered
+                    max_deliver: i64::from(config.max_deliveries) + 1,
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| nats_error("Failed to create JetStream consumer", e))?
+            .messages()
+            .await
+            .map_err(|e| nats_error("Failed to consume JetStream messages", e))?;
+        Ok(Some(
+            messages
+                .filter_map(move |message| async move {
+                    let message = match message {
+                        Ok(message) => message,
+                        Err(e) => {
+                            tracing::warn!(error = %e, "JetStream delivery failed");
+                            return None;
+                        }
+                    };
+                    let attempt = message
+                        .info()
+                        .map(|info| u32::try_from(info.delivered).unwrap_or(u32::MAX))
+                        .unwrap_or(1);
+                    let Some(event) = decode(codec, &message.payload) else {
+                        // Malformed messages would only be redelivered
+                        if let Err(e) = message.ack_with(AckKind::Term).await {
+                            tracing::warn!(error = %e, "Failed to terminate JetStream message");
+                        }
+                        return None;
+                    };
+                    Some(BrokerDelivery {
+                        event,
+                        attempt,
+                        ack: Box::new(NatsAck(message)),
+                    })
+                })
+                .boxed(),
+        ))
+    }
+}
+
+/// Acknowledgement of a JetStream message
+struct NatsAck(jetstream::Message);
+
+#[async_trait]
+impl BrokerAck for NatsAck {
+    async fn ack(self: Box<Self>) -> Result<()> {
+        self.0
+            .ack()
+            .await
+            .map_err(|e| nats_error("Failed to acknowledge JetStream message", e).into())
+    }
+
+    async fn nack(self: Box<Self>) -> Result<()> {
+        self.0
+            .ack_with(AckKind::Nak(None))
+            .await
+            .map_err(|e| nats_error("Failed to reject JetStream message", e).into())
+    }
+}
+
+#[async_trait]
+impl EventBus for NatsEventBus {
+    async fn register_topic(&self, topic: &str) -> Result<()> {
+        if !TopicPattern::parse(topic)?.is_literal() {
+            return Err(Error::Event(format!("Topics cannot contain wildcards: {}", topic)).into());
+        }
+        self.stream().await?;
+        let mut topics = self
+            .topics
+            .write()
+            .map_err(|_| Error::Event("Event bus topics poisoned".to_string()))?;
+        topics.insert(topic.to_string());
+        Ok(())
+    }
+
+    async fn publish(&self, event: Event) -> Result<()> {
+        self.check_registered(event.topic())?;
+        let subject = format!("{}.{}", self.prefix, event.topic());
+        let payload = self.codec.encode(&event)?;
+        match &self.jetstream {
+            Some((context, _)) => {
+                context
+                    .publish(subject, payload.into())
+                    .await
+                    .map_err(|e| nats_error("Failed to publish event", e))?
+                    .await
+                    .map_err(|e| nats_error("Event not acknowledged by JetStream", e))?;
+            }
+            None => {
+                self.client
+                    .publish(subject, payload.into())
+                    .await
+                    .map_err(|e| nats_error("Failed to publish event", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish_batch(&self, events: Vec<Event>) -> Result<()> {
+        let Some((context, _)) = &self.jetstream else {
+            // Core publishes are buffered by the client already
+            for event in events {
+                self.publish(event).await?;
+            }
+            return Ok(());
+        };
+        // Send every event before waiting for any acknowledgement
+        let mut acks = Vec::with_capacity(events.len());
+        for event in &events {
+            self.check_registered(event.topic())?;
+            let subject = format!("{}.{}", self.prefix, event.topic());
+            let payload = self.codec.encode(event)?;
+            let ack = context
+                .publish(subject, payload.into())
+                .await
+                .map_err(|e| nats_error("Failed to publish event", e))?;
+            acks.push(async move { ack.await });
+        }
+        futures::future::try_join_all(acks)
+            .await
+            .map_err(|e| nats_error("Event not acknowledged by JetStream", e))?;
+        Ok(())
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<EventStream> {
+        let pattern = TopicPattern::parse(topic)?;
+        if pattern.is_literal() {
+            self.check_registered(topic)?;
+        }
+        let mut streams = Vec::new();
+        for subject in subjects(&self.prefix, &pattern) {
+            streams.push(self.subscribe_subject(subject).await?);
+        }
+        Ok(futures::stream::select_all(streams)
+            .filter(move |event| std::future::ready(pattern.matches(event.topic())))
+            .boxed())
+    }
+
+    async fn subscribe_with_acks(
+        &self,
+        topic: &str,
+        config: &AckConfig,
+    ) -> Result<Option<BrokerAckStream>> {
+        let pattern = TopicPattern::parse(topic)?;
+        if pattern.is_literal() {
+            self.check_registered(topic)?;
+        }
+        let mut streams = Vec::new();
+        for subject in subjects(&self.prefix, &pattern) {
+            match self.subscribe_subject_acked(subject, config).await? {
+                Some(deliveries) => streams.push(deliveries),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(
+            futures::stream::select_all(streams)
+                .filter_map(move |delivery| {
+                    let matches = pattern.matches(delivery.event.topic());
+                    async move {
+                        if matches {
+                            return Some(delivery);
+                        }
+                        // Caught by a wider subject than the pattern
+                        if let Err(e) = delivery.ack.ack().await {
+                            tracing::warn!(error = %e, "Failed to acknowledge JetStream message");
+                        }
+                        None
+                    }
+                })
+                .boxed(),
+        ))
+    }
+}
+
+/// Subjects covering every topic matching a pattern
+///
+/// NATS `>` matches one or more tokens and only at the end, so a trailing
+/// `#` needs the parent subject as well, and a `#` anywhere else falls back
+/// to every subject under the prefix.
+fn subjects(prefix: &str, pattern: &TopicPattern) -> Vec<String> {
+    let pattern = pattern.as_str();
+    match pattern.strip_suffix(".#") {
+        Some(head) if !head.contains('#') => {
+            vec![
+                format!("{}.{}", prefix, head),
+                format!("{}.{}.>", prefix, head),
+            ]
+        }
+        _ if pattern.contains('#') => vec![format!("{}.>", prefix)],
+        _ => vec![format!("{}.{}", prefix, pattern)],
+    }
+}
+
+/// Name of the durable consumer for a subject
+///
+/// Consumer names cannot contain subject wildcards or separators, so the
+/// subject is hashed, with FNV-1a so the name survives restarts and upgrades.
+fn consumer_name(durable: &str, subject: &str) -> String {
+    let mut hasher = FnvHasher::default();
+    hasher.write(subject.as_bytes());
+    format!("{}-{:016x}", durable, hasher.finish())
+}
+
+/// Decode an event, skipping malformed messages
+fn decode(codec: Codec, payload: &[u8]) -> Option<Event> {
+    match codec.decode(payload) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            tracing::warn!(error = %e, "Skipping malformed event message");
+            None
+        }
+    }
+}
+
+/// Wrap a NATS client error
+fn nats_error(context: &str, e: impl std::fmt::Display) -> Error {
+    Error::Event(format!("{}: {}", context, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_mapping() {
+        let subjects_of = |pattern: &str| subjects("atlas", &TopicPattern::parse(pattern).unwrap());
+        assert_eq!(subjects_of("task.done"), vec!["atlas.task.done"]);
+        assert_eq!(subjects_of("task.*"), vec!["atlas.task.*"]);
+        assert_eq!(
+            subjects_of("agent.planner.#"),
+            vec!["atlas.agent.planner", "atlas.agent.planner.>"]
+        );
+        assert_eq!(subjects_of("#.done"), vec!["atlas.>"]);
+        assert_eq!(subjects_of("#"), vec!["atlas.>"]);
+        assert_ne!(
+            consumer_name("planner", "atlas.task.*"),
+            consumer_name("planner", "atlas.task.>")
+        );
+        assert_eq!(
+            consumer_name("planner", "atlas.task.*"),
+            "planner-f558d6829d83265f"
+        );
+    }
+}
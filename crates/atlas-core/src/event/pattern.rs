@@ -0,0 +1,266 @@
+//! Topic patterns for wildcard subscriptions
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::Error;
+
+/// Separator between the segments of a topic
+pub const SEGMENT_SEPARATOR: char = '.';
+
+/// Segment of a topic pattern
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Segment {
+    /// Matches this exact segment
+    Literal(String),
+
+    /// `*`, matches exactly one segment
+    One,
+
+    /// `#`, matches zero or more segments
+    Any,
+}
+
+/// Pattern matching topics segment by segment
+///
+/// Topics are split on `.`. In a pattern, `*` matches exactly one segment
+/// and `#` matches zero or more, so `task.*` matches `task.done` but not
+/// `task.step.done`, while `agent.planner.#` matches `agent.planner` and
+/// every topic below it. Wildcards must be whole segments.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TopicPattern {
+    /// Parsed segments
+    segments: Vec<Segment>,
+
+    /// Pattern as written
+    source: String,
+}
+
+impl TopicPattern {
+    /// Parse a pattern
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let segments = pattern
+            .split(SEGMENT_SEPARATOR)
+            .map(|segment| match segment {
+                "*" => Ok(Segment::One),
+                "#" => Ok(Segment::Any),
+                "" => Err(Error::Event(format!(
+                    "Empty segment in topic pattern: {}",
+                    pattern
+                ))),
+                s if s.contains(['*', '#']) => Err(Error::Event(format!(
+                    "Wildcards must be whole segments in topic pattern: {}",
+                    pattern
+                ))),
+                s => Ok(Segment::Literal(s.to_string())),
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Self {
+            segments,
+            source: pattern.to_string(),
+        })
+    }
+
+    /// Whether the pattern has no wildcards and so matches a single topic
+    pub fn is_literal(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|s| matches!(s, Segment::Literal(_)))
+    }
+
+    /// Whether a topic matches the pattern
+    pub fn matches(&self, topic: &str) -> bool {
+        let topic: Vec<&str> = topic.split(SEGMENT_SEPARATOR).collect();
+        matches_segments(&self.segments, &topic)
+    }
+
+    /// Get the pattern as written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Whether topic segments match pattern segments
+fn matches_segments(pattern: &[Segment], topic: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => topic.is_empty(),
+        Some((Segment::Any, rest)) => {
+            (0..=topic.len()).any(|skip| matches_segments(rest, &topic[skip..]))
+        }
+        Some((Segment::One, rest)) => !topic.is_empty() && matches_segments(rest, &topic[1..]),
+        Some((Segment::Literal(segment), rest)) => {
+            topic.first() == Some(&segment.as_str()) && matches_segments(rest, &topic[1..])
+        }
+    }
+}
+
+/// Set of patterns with a value each, matched against topics in one walk
+///
+/// Patterns sharing a prefix share trie nodes, so matching a topic costs
+/// time proportional to its length and the wildcards along the way rather
+/// than to the number of patterns.
+#[derive(Debug)]
+pub struct PatternTrie<T> {
+    /// Root node, matching the empty prefix
+    root: Node<T>,
+}
+
+/// Trie node for a pattern prefix
+#[derive(Debug)]
+struct Node<T> {
+    /// Value of the pattern ending here
+    value: Option<T>,
+
+    /// Children by next segment
+    children: HashMap<Segment, Node<T>>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Default for PatternTrie<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PatternTrie<T> {
+    /// Create an empty trie
+    pub fn new() -> Self {
+        Self {
+            root: Node::default(),
+        }
+    }
+
+    /// Get the value of a pattern
+    pub fn get(&self, pattern: &TopicPattern) -> Option<&T> {
+        let mut node = &self.root;
+        for segment in &pattern.segments {
+            node = node.children.get(segment)?;
+        }
+        node.value.as_ref()
+    }
+
+    /// Set the value of a pattern, returning the previous value
+    pub fn insert(&mut self, pattern: &TopicPattern, value: T) -> Option<T> {
+        let mut node = &mut self.root;
+        for segment in &pattern.segments {
+            node = node.children.entry(segment.clone()).or_default();
+        }
+        node.value.replace(value)
+    }
+
+    /// Remove the values for which `keep` returns false
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        fn walk<T, F: FnMut(&T) -> bool>(node: &mut Node<T>, keep: &mut F) {
+            if node.value.as_ref().is_some_and(|v| !keep(v)) {
+                node.value = None;
+            }
+            for child in node.children.values_mut() {
+                walk(child, keep);
+            }
+            node.children
+                .retain(|_, child| child.value.is_some() || !child.children.is_empty());
+        }
+        walk(&mut self.root, &mut keep);
+    }
+
+    /// Get the values of every pattern
+    pub fn values(&self) -> Vec<&T> {
+        fn walk<'a, T>(node: &'a Node<T>, values: &mut Vec<&'a T>) {
+            values.extend(node.value.as_ref());
+            for child in node.children.values() {
+                walk(child, values);
+            }
+        }
+        let mut values = Vec::new();
+        walk(&self.root, &mut values);
+        values
+    }
+
+    /// Get the values of every pattern matching a topic, each once
+    pub fn matches(&self, topic: &str) -> Vec<&T> {
+        let segments: Vec<&str> = topic.split(SEGMENT_SEPARATOR).collect();
+        let mut found = Vec::new();
+        collect(&self.root, &segments, &mut found);
+        found
+    }
+}
+
+/// Collect the values of the patterns below `node` matching `rest`
+fn collect<'a, T>(node: &'a Node<T>, rest: &[&str], found: &mut Vec<&'a T>) {
+    if let Some(any) = node.children.get(&Segment::Any) {
+        for skip in 0..=rest.len() {
+            collect(any, &rest[skip..], found);
+        }
+    }
+    let Some((first, rest)) = rest.split_first() else {
+        if let Some(value) = &node.value {
+            // Several `#` can reach the same pattern along different paths
+            if !found.iter().any(|v| std::ptr::eq(*v, value)) {
+                found.push(value);
+            }
+        }
+        return;
+    };
+    if let Some(child) = node.children.get(&Segment::Literal(first.to_string())) {
+        collect(child, rest, found);
+    }
+    if let Some(child) = node.children.get(&Segment::One) {
+        collect(child, rest, found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        let one = TopicPattern::parse("task.*").unwrap();
+        assert!(one.matches("task.done"));
+        assert!(!one.matches("task"));
+        assert!(!one.matches("task.step.done"));
+
+        let any = TopicPattern::parse("agent.planner.#").unwrap();
+        assert!(any.matches("agent.planner"));
+        assert!(any.matches("agent.planner.task.done"));
+        assert!(!any.matches("agent.writer.task.done"));
+        assert!(TopicPattern::parse("#.done")
+            .unwrap()
+            .matches("task.step.done"));
+
+        assert!(TopicPattern::parse("task.d*").is_err());
+        assert!(TopicPattern::parse("task..done").is_err());
+        assert!(TopicPattern::parse("task.done").unwrap().is_literal());
+    }
+
+    #[test]
+    fn test_trie_matches_each_pattern_once() {
+        let mut trie = PatternTrie::new();
+        for pattern in ["#", "#.#", "task.*", "task.done", "agent.#"] {
+            trie.insert(&TopicPattern::parse(pattern).unwrap(), pattern);
+        }
+        let mut found = trie.matches("task.done");
+        found.sort();
+        assert_eq!(found, vec![&"#", &"#.#", &"task.*", &"task.done"]);
+
+        trie.retain(|pattern| !pattern.starts_with('#'));
+        assert_eq!(trie.matches("agent.x").len(), 1);
+        assert!(trie.get(&TopicPattern::parse("#").unwrap()).is_none());
+    }
+}
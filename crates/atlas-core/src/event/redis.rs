@@ -0,0 +1,343 @@
+//! Redis transport for the event bus
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::RwLock;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use redis::aio::MultiplexedConnection;
+use redis::streams::{StreamMaxlen, StreamRangeReply, StreamReadOptions, StreamReadReply};
+use redis::AsyncCommands;
+
+use super::pattern::TopicPattern;
+use super::{Event, EventBus, EventStream};
+use crate::{Codec, Error};
+
+/// Key prefix used by a new bus
+pub const DEFAULT_KEY_PREFIX: &str = "atlas.events";
+
+/// How long a stream subscriber blocks on one read, in milliseconds
+const STREAM_BLOCK_MS: usize = 1000;
+
+/// Events read from a stream at a time
+const STREAM_READ_COUNT: usize = 100;
+
+/// Event bus carrying events between processes over Redis
+///
+/// By default each topic is the pub/sub channel `<prefix>.<event_type>`, so
+/// events reach the subscribers connected when they are published and are
+/// otherwise lost. With [`RedisEventBus::with_stream`], events are appended
+/// to the stream `<prefix>` instead, which keeps the latest events, so a
+/// subscriber whose connection drops resumes after the last event it read.
+/// Every stream subscriber reads the whole stream and keeps the events on its
+/// topics. Events are encoded by the bus's [`Codec`], JSON by default.
+#[derive(Debug)]
+pub struct RedisEventBus {
+    /// Client opening subscriber connections
+    client: redis::Client,
+
+    /// Connection shared by publishes
+    connection: MultiplexedConnection,
+
+    /// Prefix of every channel and the stream key
+    prefix: String,
+
+    /// Approximate number of events kept in the stream, when events are
+    /// stored in a stream
+    stream_len: Option<usize>,
+
+    /// Topics registered by this process
+    topics: RwLock<HashSet<String>>,
+
+    /// Encoding of published events
+    codec: Codec,
+}
+
+impl RedisEventBus {
+    /// Connect to a Redis server
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(|e| redis_error("Invalid Redis URL", e))?;
+        Self::from_client(client).await
+    }
+
+    /// Use an existing client
+    pub async fn from_client(client: redis::Client) -> Result<Self> {
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| redis_error("Failed to connect to Redis", e))?;
+        Ok(Self {
+            client,
+            connection,
+            prefix: DEFAULT_KEY_PREFIX.to_string(),
+            stream_len: None,
+            topics: RwLock::new(HashSet::new()),
+            codec: Codec::default(),
+        })
+    }
+
+    /// Set the prefix of every channel and the stream key
+    pub fn with_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Store events in a stream keeping about `max_len` of the latest ones
+    pub fn with_stream(mut self, max_len: usize) -> Self {
+        self.stream_len = Some(max_len);
+        self
+    }
+
+    /// Encode events with a codec; every process on the bus must use the
+    /// same one
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Check that a topic was registered by this process
+    fn check_registered(&self, topic: &str) -> Result<()> {
+        let topics = self
+            .topics
+            .read()
+            .map_err(|_| Error::Event("Event bus topics poisoned".to_string()))?;
+        if !topics.contains(topic) {
+            return Err(Error::Event(format!("Topic not registered: {}", topic)).into());
+        }
+        Ok(())
+    }
+
+    /// Stream the events on the channels matching a pattern
+    async fn subscribe_channels(&self, pattern: &TopicPattern) -> Result<EventStream> {
+        let mut pubsub = self
+            .client
+            .get_async_pubsub()
+            .await
+            .map_err(|e| redis_error("Failed to connect to Redis", e))?;
+        if pattern.is_literal() {
+            pubsub
+                .subscribe(format!("{}.{}", self.prefix, pattern))
+                .await
+        } else {
+            pubsub.psubscribe(channel_glob(&self.prefix, pattern)).await
+        }
+        .map_err(|e| redis_error("Failed to subscribe", e))?;
+        let codec = self.codec;
+        Ok(pubsub
+            .into_on_message()
+            .filter_map(move |message| async move { decode(codec, message.get_payload_bytes()) })
+            .boxed())
+    }
+
+    /// Stream the events appended to the stream after subscribing
+    async fn subscribe_stream(&self) -> Result<EventStream> {
+        // Reads block, so each subscriber needs its own connection, and one
+        // that reconnects after failures
+        let mut connection = self
+            .client
+            .get_connection_manager()
+            .await
+            .map_err(|e| redis_error("Failed to connect to Redis", e))?;
+        // Start after the latest entry now rather than at the first read, so
+        // nothing published in between is missed
+        let latest: StreamRangeReply = connection
+            .xrevrange_count(&self.prefix, "+", "-", 1)
+            .await
+            .map_err(|e| redis_error("Failed to read event stream", e))?;
+        let last_id = latest
+            .ids
+            .first()
+            .map_or_else(|| "0-0".to_string(), |entry| entry.id.clone());
+
+        let key = self.prefix.clone();
+        let codec = self.codec;
+        let state = (connection, last_id, VecDeque::new());
+        Ok(
+            futures::stream::unfold(state, move |(mut connection, mut last_id, mut pending)| {
+                let key = key.clone();
+                async move {
+                    loop {
+                        if let Some(event) = pending.pop_front() {
+                            return Some((event, (connection, last_id, pending)));
+                        }
+                        let options = StreamReadOptions::default()
+                            .block(STREAM_BLOCK_MS)
+                            .count(STREAM_READ_COUNT);
+                        let reply: Option<StreamReadReply> = match connection
+                            .xread_options(&[&key], &[&last_id], &options)
+                            .await
+                        {
+                            Ok(reply) => reply,
+                            Err(e) => {
+                                tracing::warn!(error = %e, "Failed to read event stream");
+                                tokio::time::sleep(std::time::Duration::from_millis(
+                                    STREAM_BLOCK_MS as u64,
+                                ))
+                                .await;
+                                continue;
+                            }
+                        };
+                        for entry in reply.into_iter().flat_map(|r| r.keys).flat_map(|k| k.ids) {
+                            last_id = entry.id.clone();
+                            if let Some(event) = entry.get::<Vec<u8>>("event") {
+                                pending.extend(decode(codec, &event));
+                            }
+                        }
+                    }
+                }
+            })
+            .boxed(),
+        )
+    }
+}
+
+#[async_trait]
+impl EventBus for RedisEventBus {
+    async fn register_topic(&self, topic: &str) -> Result<()> {
+        if !TopicPattern::parse(topic)?.is_literal() {
+            return Err(Error::Event(format!("Topics cannot contain wildcards: {}", topic)).into());
+        }
+        let mut topics = self
+            .topics
+            .write()
+            .map_err(|_| Error::Event("Event bus topics poisoned".to_string()))?;
+        topics.insert(topic.to_string());
+        Ok(())
+    }
+
+    async fn publish(&self, event: Event) -> Result<()> {
+        self.check_registered(event.topic())?;
+        let payload = self.codec.encode(&event)?;
+        let mut connection = self.connection.clone();
+        match self.stream_len {
+            Some(max_len) => {
+                connection
+                    .xadd_maxlen::<_, _, _, _, ()>(
+                        &self.prefix,
+                        StreamMaxlen::Approx(max_len),
+                        "*",
+                        &[
+                            ("topic", event.topic().as_bytes()),
+                            ("event", payload.as_slice()),
+                        ],
+                    )
+                    .await
+            }
+            None => {
+                connection
+                    .publish::<_, _, ()>(format!("{}.{}", self.prefix, event.topic()), payload)
+                    .await
+            }
+        }
+        .map_err(|e| redis_error("Failed to publish event", e))?;
+        Ok(())
+    }
+
+    async fn publish_batch(&self, events: Vec<Event>) -> Result<()> {
+        // Send the whole batch in one round trip
+        let mut pipeline = redis::pipe();
+        for event in &events {
+            self.check_registered(event.topic())?;
+            let payload = self.codec.encode(event)?;
+            match self.stream_len {
+                Some(max_len) => pipeline.xadd_maxlen(
+                    &self.prefix,
+                    StreamMaxlen::Approx(max_len),
+                    "*",
+                    &[
+                        ("topic", event.topic().as_bytes()),
+                        ("event", payload.as_slice()),
+                    ],
+                ),
+                None => pipeline.publish(format!("{}.{}", self.prefix, event.topic()), payload),
+            }
+            .ignore();
+        }
+        let mut connection = self.connection.clone();
+        pipeline
+            .query_async::<()>(&mut connection)
+            .await
+            .map_err(|e| redis_error("Failed to publish event batch", e))?;
+        Ok(())
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<EventStream> {
+        let pattern = TopicPattern::parse(topic)?;
+        if pattern.is_literal() {
+            self.check_registered(topic)?;
+        }
+        let events = match self.stream_len {
+            Some(_) => self.subscribe_stream().await?,
+            None => self.subscribe_channels(&pattern).await?,
+        };
+        Ok(events
+            .filter(move |event| std::future::ready(pattern.matches(event.topic())))
+            .boxed())
+    }
+}
+
+/// Channel glob covering every topic matching a pattern
+///
+/// A glob `*` also matches separators, so the glob can be wider than the
+/// pattern; events are matched against the pattern again on delivery.
+fn channel_glob(prefix: &str, pattern: &TopicPattern) -> String {
+    let mut glob = escape(prefix);
+    for segment in pattern.as_str().split('.') {
+        match segment {
+            // Zero segments is also a match, so the separator is optional
+            "#" => glob.push('*'),
+            "*" => glob.push_str(".*"),
+            literal => {
+                glob.push('.');
+                glob.push_str(&escape(literal));
+            }
+        }
+    }
+    glob
+}
+
+/// Escape the glob metacharacters in a channel name
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Decode an event, skipping malformed messages
+fn decode(codec: Codec, payload: &[u8]) -> Option<Event> {
+    match codec.decode(payload) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            tracing::warn!(error = %e, "Skipping malformed event message");
+            None
+        }
+    }
+}
+
+/// Wrap a Redis client error
+fn redis_error(context: &str, e: impl std::fmt::Display) -> Error {
+    Error::Event(format!("{}: {}", context, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_glob() {
+        let glob_of = |pattern: &str| channel_glob("atlas", &TopicPattern::parse(pattern).unwrap());
+        assert_eq!(glob_of("task.*"), "atlas.task.*");
+        assert_eq!(glob_of("agent.planner.#"), "atlas.agent.planner*");
+        assert_eq!(glob_of("#.done"), "atlas*.done");
+        assert_eq!(
+            channel_glob("a[1]", &TopicPattern::parse("*").unwrap()),
+            r"a\[1\].*"
+        );
+    }
+}
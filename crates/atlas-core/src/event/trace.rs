@@ -0,0 +1,208 @@
+//! W3C trace context carried between publishers and handlers
+
+use std::fmt;
+use std::future::Future;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use super::Event;
+use crate::Error;
+
+/// Event metadata key holding the W3C `traceparent` of the publishing work
+pub const TRACEPARENT: &str = "traceparent";
+
+tokio::task_local! {
+    /// Trace context of the work running in the current task
+    static CURRENT: TraceContext;
+}
+
+/// W3C trace context of a piece of work
+///
+/// Events published inside [`TraceContext::scope`] carry it as their
+/// [`TRACEPARENT`], and handlers continue the trace with a child span, so
+/// the spans of a publisher and every downstream handler share a trace ID.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TraceContext {
+    /// Trace the work belongs to
+    trace_id: [u8; 16],
+
+    /// Span of the work
+    span_id: [u8; 8],
+
+    /// Span of the work this continues, if any
+    parent_span_id: Option<[u8; 8]>,
+
+    /// Whether the trace is recorded
+    sampled: bool,
+}
+
+impl TraceContext {
+    /// Start a new, sampled trace
+    pub fn root() -> Self {
+        Self {
+            trace_id: *Uuid::new_v4().as_bytes(),
+            span_id: new_span_id(),
+            parent_span_id: None,
+            sampled: true,
+        }
+    }
+
+    /// Context of a new span continuing this one
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: new_span_id(),
+            parent_span_id: Some(self.span_id),
+            ..*self
+        }
+    }
+
+    /// Context of a new span continuing the current trace, or of a new trace
+    pub fn child_of_current() -> Self {
+        Self::current().map_or_else(Self::root, |current| current.child())
+    }
+
+    /// Get the trace context of the work running in the current task
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|context| *context).ok()
+    }
+
+    /// Run work in this trace context
+    pub async fn scope<F: Future>(self, work: F) -> F::Output {
+        CURRENT.scope(self, work).await
+    }
+
+    /// Get the trace context an event was published in; malformed values
+    /// are ignored
+    pub fn of(event: &Event) -> Option<Self> {
+        let traceparent: String = event.metadata.get(TRACEPARENT)?;
+        Self::parse(&traceparent).ok()
+    }
+
+    /// Parse a `traceparent` value such as
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+    pub fn parse(traceparent: &str) -> Result<Self> {
+        let invalid = || Error::Event(format!("Invalid traceparent: {}", traceparent));
+        let mut parts = traceparent.trim().split('-');
+        let (Some(version), Some(trace_id), Some(span_id), Some(flags)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid().into());
+        };
+        let version: [u8; 1] = decode_hex(version).ok_or_else(invalid)?;
+        let trace_id: [u8; 16] = decode_hex(trace_id).ok_or_else(invalid)?;
+        let span_id: [u8; 8] = decode_hex(span_id).ok_or_else(invalid)?;
+        let flags: [u8; 1] = decode_hex(flags).ok_or_else(invalid)?;
+        // Version 00 has exactly four fields; later versions may add more
+        let extra = parts.next().is_some();
+        if version[0] == 0xff
+            || (version[0] == 0 && extra)
+            || trace_id == [0; 16]
+            || span_id == [0; 8]
+        {
+            return Err(invalid().into());
+        }
+        Ok(Self {
+            trace_id,
+            span_id,
+            parent_span_id: None,
+            sampled: flags[0] & 1 == 1,
+        })
+    }
+
+    /// Get the trace ID as hex
+    pub fn trace_id(&self) -> String {
+        encode_hex(&self.trace_id)
+    }
+
+    /// Get the span ID as hex
+    pub fn span_id(&self) -> String {
+        encode_hex(&self.span_id)
+    }
+
+    /// Get the ID of the span this continues as hex, if any
+    pub fn parent_span_id(&self) -> Option<String> {
+        self.parent_span_id.as_ref().map(|id| encode_hex(id))
+    }
+
+    /// Whether the trace is recorded
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+}
+
+/// Formats as a `traceparent` value
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            self.trace_id(),
+            self.span_id(),
+            u8::from(self.sampled)
+        )
+    }
+}
+
+/// Random, non-zero span ID
+fn new_span_id() -> [u8; 8] {
+    loop {
+        let bytes = Uuid::new_v4().into_bytes();
+        let mut id = [0; 8];
+        id.copy_from_slice(&bytes[..8]);
+        if id != [0; 8] {
+            return id;
+        }
+    }
+}
+
+/// Lowercase hex of some bytes
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Bytes of a lowercase hex string of exactly `N` bytes
+fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 || !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    #[tokio::test]
+    async fn test_propagation() {
+        let value = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let parsed = TraceContext::parse(value).unwrap();
+        assert_eq!(parsed.to_string(), value);
+        assert_eq!(parsed.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        for invalid in [
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00F067AA0BA902B7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(TraceContext::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        let publisher = TraceContext::root();
+        let event = publisher
+            .scope(async { Event::new("task.done", Metadata::new()).with_current_trace() })
+            .await;
+        let handler = TraceContext::of(&event).unwrap().child();
+        assert_eq!(handler.trace_id(), publisher.trace_id());
+        assert_eq!(handler.parent_span_id(), Some(publisher.span_id()));
+
+        // Events published outside any trace start one
+        let event = Event::new("task.done", Metadata::new()).with_current_trace();
+        assert!(TraceContext::of(&event).is_some());
+    }
+}
@@ -0,0 +1,177 @@
+//! Versioned event types and upcasting of old events
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::stream::StreamExt;
+
+use super::{Event, EventStream};
+use crate::{Error, Metadata};
+
+/// Payload conversion from one version of an event type to the next
+type Upcast = Arc<dyn Fn(Metadata) -> Result<Metadata> + Send + Sync>;
+
+/// Split an event type into its base type and version
+///
+/// A type ending in a `v<N>` segment, such as `task.created.v2`, is version
+/// `N` of its base type; any other type is version 1.
+pub fn split_version(event_type: &str) -> (&str, u32) {
+    let parsed = event_type.rsplit_once('.').and_then(|(base, last)| {
+        let version = last.strip_prefix('v')?.parse().ok()?;
+        Some((base, version))
+    });
+    parsed.unwrap_or((event_type, 1))
+}
+
+/// Event type of a version of a base type; version 1 is the base type itself
+pub fn versioned_type(base: &str, version: u32) -> String {
+    if version <= 1 {
+        base.to_string()
+    } else {
+        format!("{}.v{}", base, version)
+    }
+}
+
+/// Chain of payload conversions bringing old events to the current version
+/// of their type
+///
+/// Each conversion takes the payload of one version to the next, so an event
+/// several versions behind passes through every step in between. The current
+/// version of a type is the one after its last registered conversion.
+#[derive(Clone, Default)]
+pub struct Upcasters {
+    /// Conversions by base type and the version they convert from
+    steps: HashMap<(String, u32), Upcast>,
+}
+
+impl Upcasters {
+    /// Create a chain with no conversions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the conversion of an event type's payloads from `from_version` to
+    /// the next version
+    pub fn with<F>(mut self, event_type: &str, from_version: u32, upcast: F) -> Self
+    where
+        F: Fn(Metadata) -> Result<Metadata> + Send + Sync + 'static,
+    {
+        let (base, _) = split_version(event_type);
+        self.steps
+            .insert((base.to_string(), from_version.max(1)), Arc::new(upcast));
+        self
+    }
+
+    /// Get the current version of an event type
+    pub fn current_version(&self, event_type: &str) -> u32 {
+        let (base, _) = split_version(event_type);
+        self.steps
+            .keys()
+            .filter(|(b, _)| b == base)
+            .map(|(_, from)| from + 1)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Convert an event to the current version of its type, keeping its ID
+    /// and causal links; current events are returned unchanged
+    pub fn upcast(&self, mut event: Event) -> Result<Event> {
+        let (base, mut version) = split_version(event.event_type.as_str());
+        let base = base.to_string();
+        let mut upcast = false;
+        while let Some(step) = self.steps.get(&(base.clone(), version)) {
+            event.payload = step(event.payload).map_err(|e| {
+                Error::Event(format!(
+                    "Failed to upcast event {} ({}) from version {}: {}",
+                    event.event_type, event.id, version, e
+                ))
+            })?;
+            version += 1;
+            upcast = true;
+        }
+        if upcast {
+            event.event_type = versioned_type(&base, version).into();
+        }
+        Ok(event)
+    }
+
+    /// Convert the events of a stream, such as a replayed subscription to
+    /// `task.created.#`, skipping events that fail to convert
+    pub fn upcast_stream(&self, events: EventStream) -> EventStream {
+        let upcasters = self.clone();
+        events
+            .filter_map(move |event| {
+                let upcast = upcasters.upcast(event);
+                async move {
+                    match upcast {
+                        Ok(event) => Some(event),
+                        Err(e) => {
+                            tracing::warn!(error = %e, "Skipping event that failed to upcast");
+                            None
+                        }
+                    }
+                }
+            })
+            .boxed()
+    }
+}
+
+impl std::fmt::Debug for Upcasters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Upcasters")
+            .field("steps", &self.steps.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventBus, InMemoryEventBus};
+
+    #[tokio::test]
+    async fn test_upcast_chain() {
+        assert_eq!(split_version("task.created.v3"), ("task.created", 3));
+        assert_eq!(split_version("task.created"), ("task.created", 1));
+        assert_eq!(split_version("task.vip"), ("task.vip", 1));
+
+        // v1 had `name`, v2 renamed it to `title`, v3 added `priority`
+        let upcasters = Upcasters::new()
+            .with("task.created", 1, |mut payload| {
+                let name: String = payload
+                    .get("name")
+                    .ok_or_else(|| Error::Event("Missing name".to_string()))?;
+                payload.insert("title", name);
+                Ok(payload)
+            })
+            .with("task.created", 2, |mut payload| {
+                payload.insert("priority", 0);
+                Ok(payload)
+            });
+        assert_eq!(upcasters.current_version("task.created"), 3);
+
+        let bus = InMemoryEventBus::new();
+        let mut events = upcasters.upcast_stream(bus.subscribe("task.created.#").await.unwrap());
+        let mut v1 = Metadata::new();
+        v1.insert("name", "fetch");
+        let mut v2 = Metadata::new();
+        v2.insert("title", "store");
+        for (event_type, payload) in [
+            ("task.created", Metadata::new()),
+            ("task.created", v1),
+            ("task.created.v2", v2),
+        ] {
+            bus.register_topic(event_type).await.unwrap();
+            bus.publish(Event::new(event_type, payload)).await.unwrap();
+        }
+
+        // The v1 event without a name is skipped
+        for title in ["fetch", "store"] {
+            let event = events.next().await.unwrap();
+            assert_eq!(event.event_type, "task.created.v3");
+            assert_eq!(event.payload.get::<String>("title").as_deref(), Some(title));
+            assert_eq!(event.payload.get::<u32>("priority"), Some(0));
+        }
+    }
+}
@@ -2,29 +2,50 @@
 //! 
 //! This crate provides the core traits and types used throughout the Atlas framework.
 
+use std::collections::hash_map::{self, Entry};
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 pub mod agent;
+pub mod cancel;
+pub mod clock;
+pub mod codec;
+pub mod config;
+pub mod dyn_agent;
 pub mod error;
 pub mod event;
+pub mod resource;
 pub mod state;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod types;
 
 // Re-exports
 pub use agent::{Agent, AgentConfig, AgentState};
+pub use cancel::CancellationContext;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use codec::Codec;
+pub use config::{ConfigFormat, ConfigLoader, ValidationErrors};
+pub use dyn_agent::{new_dyn_agent, DynAgent};
 pub use error::{Error, ErrorKind};
-pub use event::{Event, EventBus, EventHandler};
-pub use state::{State, StateManager};
-pub use types::{Metadata, Resource, TaskId, Tool};
+pub use event::{
+    Cause, Event, EventBus, EventHandler, EventPriority, EventStream, EventType,
+    InMemoryEventBus, TopicPattern, TraceContext, TypedEvent,
+};
+pub use resource::{ManagedResource, PoolConfig, Pooled, ResourcePool};
+pub use state::{
+    FileStateManager, InMemoryStateManager, StateBackend, StateManager, VersionedState,
+};
+pub use types::{AgentId, EventId, Metadata, Resource, TaskId, Tool, ToolDescriptor};
 
 /// Core error types for the Atlas framework
 #[derive(Debug, Error)]
@@ -44,37 +65,611 @@ pub enum Error {
     #[error("Event error: {0}")]
     Event(String),
 
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+
+    #[error("{error}")]
+    Detailed {
+        #[source]
+        error: Box<Error>,
+        details: Metadata,
+    },
+
+    #[error("{}", .0.message)]
+    Remote(WireError),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+impl Error {
+    /// Get the stable, machine-readable code of the error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Agent(_) => ErrorCode::Agent,
+            Error::Config(_) => ErrorCode::Config,
+            Error::State(_) => ErrorCode::State,
+            Error::Tool(_) => ErrorCode::Tool,
+            Error::Event(_) => ErrorCode::Event,
+            Error::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            Error::Timeout(_) => ErrorCode::Timeout,
+            Error::Unavailable(_) => ErrorCode::Unavailable,
+            Error::Cancelled(_) => ErrorCode::Cancelled,
+            Error::Detailed { error, .. } => error.code(),
+            Error::Remote(wire) => wire.code,
+            Error::Other(e) => e
+                .downcast_ref::<Error>()
+                .map_or(ErrorCode::Internal, Error::code),
+        }
+    }
+
+    /// Whether retrying the failed operation may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Detailed { error, .. } => error.is_retryable(),
+            Error::Remote(wire) => wire.retryable,
+            Error::Other(e) => e.downcast_ref::<Error>().is_some_and(Error::is_retryable),
+            _ => self.code().is_retryable(),
+        }
+    }
+
+    /// Attach structured details, such as the offending key or limit
+    pub fn with_details(self, details: Metadata) -> Self {
+        match self {
+            Error::Detailed {
+                error,
+                details: mut existing,
+            } => {
+                existing.merge(details, MergeStrategy::Overwrite);
+                Error::Detailed {
+                    error,
+                    details: existing,
+                }
+            }
+            error => Error::Detailed {
+                error: Box::new(error),
+                details,
+            },
+        }
+    }
+
+    /// Get the structured details attached to the error, if any
+    pub fn details(&self) -> Option<&Metadata> {
+        match self {
+            Error::Detailed { details, .. } => Some(details),
+            Error::Remote(wire) => wire.details.as_ref(),
+            Error::Other(e) => e.downcast_ref::<Error>().and_then(Error::details),
+            _ => None,
+        }
+    }
+}
+
+/// Stable, machine-readable classification of an [`Error`]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Agent failure
+    Agent,
+
+    /// Invalid configuration
+    Config,
+
+    /// State failure
+    State,
+
+    /// Tool failure
+    Tool,
+
+    /// Event bus failure
+    Event,
+
+    /// Requested tool, resource or key does not exist
+    NotFound,
+
+    /// Request was malformed or rejected
+    InvalidRequest,
+
+    /// Operation did not finish in time
+    Timeout,
+
+    /// Dependency temporarily unavailable
+    Unavailable,
+
+    /// Caller cancelled the operation
+    Cancelled,
+
+    /// Unclassified failure
+    Internal,
+}
+
+impl ErrorCode {
+    /// Get the code as used on the wire
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Agent => "agent",
+            ErrorCode::Config => "config",
+            ErrorCode::State => "state",
+            ErrorCode::Tool => "tool",
+            ErrorCode::Event => "event",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::Unavailable => "unavailable",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    /// Whether errors with this code are transient
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorCode::Timeout | ErrorCode::Unavailable)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Serializable form of an error crossing a process boundary, such as an
+/// HTTP response or an event payload
+///
+/// Converting an [`Error`] keeps its code, message, details and
+/// retryability, and a received `WireError` converts back into an
+/// [`Error::Remote`] reporting the same.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WireError {
+    /// Machine-readable classification
+    pub code: ErrorCode,
+
+    /// Human-readable description
+    pub message: String,
+
+    /// Structured details, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Metadata>,
+
+    /// Whether retrying the failed operation may succeed
+    pub retryable: bool,
+}
+
+impl WireError {
+    /// Create an error that is not retryable and has no details
+    pub fn new<S: Into<String>>(code: ErrorCode, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+            retryable: false,
+        }
+    }
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for WireError {}
+
+impl From<Error> for WireError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Remote(wire) => wire,
+            Error::Other(e) => match e.downcast::<Error>() {
+                Ok(err) => err.into(),
+                Err(e) => WireError::new(ErrorCode::Internal, e.to_string()),
+            },
+            err => Self {
+                code: err.code(),
+                message: err.to_string(),
+                details: err.details().cloned(),
+                retryable: err.is_retryable(),
+            },
+        }
+    }
+}
+
+impl From<WireError> for Error {
+    fn from(wire: WireError) -> Self {
+        Error::Remote(wire)
+    }
+}
+
 /// Common metadata type used throughout the framework
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
-pub struct Metadata(HashMap<String, serde_json::Value>);
+///
+/// Clones share their keys and values until one of them is modified, which
+/// copies them, so passing metadata around is cheap however large it is.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata(Arc<HashMap<String, serde_json::Value>>);
 
 impl Metadata {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self::default()
     }
 
+    /// Get the keys and values for modification, copying them first if they
+    /// are shared with a clone
+    fn map_mut(&mut self) -> &mut HashMap<String, serde_json::Value> {
+        Arc::make_mut(&mut self.0)
+    }
+
+    /// Insert a value, panicking if it fails to serialize; prefer
+    /// [`Metadata::try_insert`] for values that may
     pub fn insert<K, V>(&mut self, key: K, value: V) -> Option<serde_json::Value>
     where
         K: Into<String>,
         V: Serialize,
     {
-        self.0.insert(
-            key.into(),
-            serde_json::to_value(value).expect("Failed to serialize value"),
-        )
+        self.try_insert(key, value).expect("Failed to serialize value")
+    }
+
+    /// Insert a value, returning the one it replaced, or an error if it fails
+    /// to serialize
+    pub fn try_insert<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> std::result::Result<Option<serde_json::Value>, MetadataError>
+    where
+        K: Into<String>,
+        V: Serialize,
+    {
+        let value = serde_json::to_value(value).map_err(|e| MetadataError::Serialize {
+            type_name: std::any::type_name::<V>(),
+            message: e.to_string(),
+        })?;
+        Ok(self.map_mut().insert(key.into(), value))
     }
 
     pub fn get<T>(&self, key: &str) -> Option<T>
     where
         T: for<'de> Deserialize<'de>,
     {
-        self.0
+        self.try_get(key).ok()
+    }
+
+    /// Get a value, telling a missing key apart from one holding another type
+    pub fn try_get<T>(&self, key: &str) -> std::result::Result<T, MetadataError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let value = self
+            .0
             .get(key)
-            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .ok_or_else(|| MetadataError::Missing(key.to_string()))?;
+        serde_json::from_value(value.clone()).map_err(|_| MetadataError::TypeMismatch {
+            key: key.to_string(),
+            expected: std::any::type_name::<T>(),
+            actual: json_type(value),
+        })
+    }
+
+    /// Convert a value serializing to a JSON object, such as a struct of
+    /// tool parameters, into metadata
+    pub fn from_serialize<T: Serialize>(value: &T) -> std::result::Result<Self, MetadataError> {
+        let invalid = |message: String| MetadataError::Serialize {
+            type_name: std::any::type_name::<T>(),
+            message,
+        };
+        match serde_json::to_value(value).map_err(|e| invalid(e.to_string()))? {
+            serde_json::Value::Object(map) => Ok(map.into_iter().collect()),
+            other => Err(invalid(format!("expected an object, got {}", json_type(&other)))),
+        }
+    }
+
+    /// Convert the metadata into a typed value, such as a struct of tool
+    /// parameters
+    pub fn to_typed<T: DeserializeOwned>(&self) -> std::result::Result<T, MetadataError> {
+        let value = serde_json::Value::Object(
+            self.0
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        );
+        serde_json::from_value(value).map_err(|e| MetadataError::Deserialize {
+            type_name: std::any::type_name::<T>(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Check whether a key is present
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Remove a key, returning its value
+    pub fn remove(&mut self, key: &str) -> Option<serde_json::Value> {
+        self.map_mut().remove(key)
+    }
+
+    /// Get a key's entry for in-place manipulation
+    pub fn entry<K: Into<String>>(&mut self, key: K) -> Entry<'_, String, serde_json::Value> {
+        self.map_mut().entry(key.into())
+    }
+
+    /// Iterate over the keys and values, in arbitrary order
+    pub fn iter(&self) -> hash_map::Iter<'_, String, serde_json::Value> {
+        self.0.iter()
+    }
+
+    /// Iterate over the keys, in arbitrary order
+    pub fn keys(&self) -> hash_map::Keys<'_, String, serde_json::Value> {
+        self.0.keys()
+    }
+
+    /// Get the number of keys
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check whether there are no keys
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get a nested value by a dot-separated path such as `plan.steps.0.tool`,
+    /// where numeric segments index into arrays
+    pub fn get_path<T>(&self, path: &str) -> Option<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let mut segments = path.split('.');
+        let mut value = self.0.get(segments.next()?)?;
+        for segment in segments {
+            value = match value {
+                serde_json::Value::Object(map) => map.get(segment)?,
+                serde_json::Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    /// Insert a nested value by a dot-separated path, creating missing
+    /// objects along the way and returning the value it replaced
+    ///
+    /// Numeric segments index into existing arrays; the index one past the
+    /// end appends. Paths running into other values, or past the end of an
+    /// array, are rejected.
+    pub fn insert_path<V>(&mut self, path: &str, value: V) -> Result<Option<serde_json::Value>>
+    where
+        V: Serialize,
+    {
+        let value = serde_json::to_value(value)?;
+        let Some((parents, last)) = path.rsplit_once('.') else {
+            return Ok(self.map_mut().insert(path.to_string(), value));
+        };
+        let invalid = || Error::State(format!("Cannot insert at metadata path {}", path));
+
+        let mut segments = parents.split('.');
+        let first = segments.next().unwrap_or_default();
+        let mut current = self
+            .map_mut()
+            .entry(first.to_string())
+            .or_insert_with(|| serde_json::Value::Object(Default::default()));
+        for segment in segments {
+            current = match current {
+                serde_json::Value::Object(map) => map
+                    .entry(segment)
+                    .or_insert_with(|| serde_json::Value::Object(Default::default())),
+                serde_json::Value::Array(items) => segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| items.get_mut(index))
+                    .ok_or_else(invalid)?,
+                _ => return Err(invalid().into()),
+            };
+        }
+
+        match current {
+            serde_json::Value::Object(map) => Ok(map.insert(last.to_string(), value)),
+            serde_json::Value::Array(items) => {
+                let index = last.parse::<usize>().map_err(|_| invalid())?;
+                if index < items.len() {
+                    Ok(Some(std::mem::replace(&mut items[index], value)))
+                } else if index == items.len() {
+                    items.push(value);
+                    Ok(None)
+                } else {
+                    Err(invalid().into())
+                }
+            }
+            _ => Err(invalid().into()),
+        }
+    }
+
+    /// Merge another metadata's top-level keys into this one
+    pub fn merge(&mut self, other: Metadata, strategy: MergeStrategy) {
+        let map = self.map_mut();
+        for (key, value) in other {
+            match map.get_mut(&key) {
+                Some(current) => merge_value(current, value, strategy, false),
+                None => {
+                    map.insert(key, value);
+                }
+            }
+        }
+    }
+
+    /// Merge another metadata into this one, recursing into objects present
+    /// on both sides so only conflicting leaves are resolved by the strategy
+    pub fn merge_deep(&mut self, other: Metadata, strategy: MergeStrategy) {
+        let map = self.map_mut();
+        for (key, value) in other {
+            match map.get_mut(&key) {
+                Some(current) => merge_value(current, value, strategy, true),
+                None => {
+                    map.insert(key, value);
+                }
+            }
+        }
+    }
+}
+
+impl From<HashMap<String, serde_json::Value>> for Metadata {
+    fn from(map: HashMap<String, serde_json::Value>) -> Self {
+        Self(Arc::new(map))
+    }
+}
+
+/// Takes the fields of a JSON object; any other value is refused
+impl TryFrom<serde_json::Value> for Metadata {
+    type Error = MetadataError;
+
+    fn try_from(value: serde_json::Value) -> std::result::Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Object(map) => Ok(map.into_iter().collect()),
+            other => Err(MetadataError::NotAnObject(json_type(&other))),
+        }
+    }
+}
+
+impl From<Metadata> for serde_json::Value {
+    fn from(metadata: Metadata) -> Self {
+        serde_json::Value::Object(metadata.into_iter().collect())
+    }
+}
+
+impl FromIterator<(String, serde_json::Value)> for Metadata {
+    fn from_iter<I: IntoIterator<Item = (String, serde_json::Value)>>(iter: I) -> Self {
+        Self(Arc::new(iter.into_iter().collect()))
+    }
+}
+
+impl IntoIterator for Metadata {
+    type Item = (String, serde_json::Value);
+    type IntoIter = hash_map::IntoIter<String, serde_json::Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Arc::unwrap_or_clone(self.0).into_iter()
+    }
+}
+
+impl Serialize for Metadata {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Metadata {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        HashMap::deserialize(deserializer).map(|map| Self(Arc::new(map)))
+    }
+}
+
+impl<'a> IntoIterator for &'a Metadata {
+    type Item = (&'a String, &'a serde_json::Value);
+    type IntoIter = hash_map::Iter<'a, String, serde_json::Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Error reading a typed value from metadata
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum MetadataError {
+    #[error("Missing metadata key: {0}")]
+    Missing(String),
+
+    #[error("Metadata key {key} holds {actual}, expected {expected}")]
+    TypeMismatch {
+        key: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+
+    #[error("Cannot convert {type_name} to metadata: {message}")]
+    Serialize {
+        type_name: &'static str,
+        message: String,
+    },
+
+    #[error("Cannot convert metadata to {type_name}: {message}")]
+    Deserialize {
+        type_name: &'static str,
+        message: String,
+    },
+
+    #[error("Metadata must be a JSON object, got {0}")]
+    NotAnObject(&'static str),
+}
+
+/// Name of a JSON value's type
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// How a metadata merge resolves a key present on both sides
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// The incoming value replaces the current one
+    #[default]
+    Overwrite,
+
+    /// The current value is kept
+    Keep,
+
+    /// Arrays on both sides are concatenated; other values are overwritten
+    CombineArrays,
+}
+
+/// Merge an incoming value into a current one
+fn merge_value(
+    current: &mut serde_json::Value,
+    incoming: serde_json::Value,
+    strategy: MergeStrategy,
+    deep: bool,
+) {
+    use serde_json::Value;
+
+    match (current, incoming) {
+        (Value::Object(current), Value::Object(incoming)) if deep => {
+            for (key, value) in incoming {
+                match current.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value, strategy, true),
+                    None => {
+                        current.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(current), Value::Array(incoming))
+            if strategy == MergeStrategy::CombineArrays =>
+        {
+            current.extend(incoming);
+        }
+        (current, incoming) => {
+            if strategy != MergeStrategy::Keep {
+                *current = incoming;
+            }
+        }
     }
 }
 
@@ -100,6 +695,124 @@ pub trait Agent: Send + Sync {
 
     /// Execute a task with the given parameters
     async fn execute_task(&self, task_id: TaskId, params: Metadata) -> Result<Metadata>;
+
+    /// Execute a task, stopping once the context is cancelled or its
+    /// deadline passes
+    ///
+    /// By default the execution is abandoned; agents override this to pass
+    /// the context on to their tools and record the outcome.
+    async fn execute_task_with(
+        &self,
+        task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        cancel.run(self.execute_task(task_id, params)).await
+    }
+
+    /// Prepare the agent to take work
+    async fn on_start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Stop taking work, returning once the work in progress has finished
+    async fn on_stop(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Hold new work until the agent is resumed
+    async fn pause(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Take new work again after a pause
+    async fn resume(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Report whether the agent can do its work
+    async fn health(&self) -> HealthStatus {
+        HealthStatus::healthy()
+    }
+}
+
+/// How well an agent or service is working, from best to worst
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthLevel {
+    /// Working normally
+    #[default]
+    Healthy,
+
+    /// Working, but slowed or missing some functionality
+    Degraded,
+
+    /// Unable to do its work
+    Unhealthy,
+}
+
+/// Health of an agent or service and the problems affecting it
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct HealthStatus {
+    /// Level of the worst problem found
+    pub level: HealthLevel,
+
+    /// Problems found, such as a backlogged queue or a failing tool
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<String>,
+}
+
+impl HealthStatus {
+    /// Status without problems
+    pub fn healthy() -> Self {
+        Self::default()
+    }
+
+    /// Add a problem, lowering the level to at least its level
+    pub fn with_issue<S: Into<String>>(mut self, level: HealthLevel, issue: S) -> Self {
+        self.level = self.level.max(level);
+        self.issues.push(issue.into());
+        self
+    }
+
+    /// Combine with the status of another component
+    pub fn combine(mut self, other: HealthStatus) -> Self {
+        self.level = self.level.max(other.level);
+        self.issues.extend(other.issues);
+        self
+    }
+
+    /// Whether there are no problems
+    pub fn is_healthy(&self) -> bool {
+        self.level == HealthLevel::Healthy
+    }
+
+    /// Whether work can be sent, possibly to be done more slowly
+    pub fn is_ready(&self) -> bool {
+        self.level != HealthLevel::Unhealthy
+    }
+}
+
+/// Source of health reports, such as an agent served by an MCP server
+#[async_trait]
+pub trait HealthProbe: Send + Sync {
+    /// Report the current health
+    async fn probe(&self) -> HealthStatus;
+}
+
+impl fmt::Debug for dyn HealthProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HealthProbe")
+    }
+}
+
+#[async_trait]
+impl<A: Agent> HealthProbe for A {
+    async fn probe(&self) -> HealthStatus {
+        self.health().await
+    }
 }
 
 /// Configuration trait for agents
@@ -117,47 +830,113 @@ pub trait AgentState: Clone + fmt::Debug + Send + Sync {
     fn snapshot(&self) -> Result<Metadata>;
 }
 
-/// Event system for inter-agent communication
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Event {
-    /// Unique identifier for this event
-    pub id: Uuid,
-    
-    /// The type of event
-    pub event_type: String,
-    
-    /// Event payload
-    pub payload: Metadata,
-    
-    /// Event metadata
-    pub metadata: Metadata,
+/// Asynchronous state management trait, for states kept in backends such as
+/// databases that cannot be updated without waiting
+///
+/// In-memory [`AgentState`]s shared behind a lock implement it, so code
+/// written against this trait works with either kind of state.
+#[async_trait]
+pub trait AsyncAgentState: Send + Sync {
+    /// Update the state with new data
+    async fn update(&self, data: Metadata) -> Result<()>;
+
+    /// Get a snapshot of the current state
+    async fn snapshot(&self) -> Result<Metadata>;
 }
 
-impl Event {
-    pub fn new<T: Into<String>>(event_type: T, payload: Metadata) -> Self {
-        Self {
-            id: Uuid::new_v4(),
-            event_type: event_type.into(),
-            payload,
-            metadata: Metadata::new(),
-        }
+#[async_trait]
+impl<S: AgentState> AsyncAgentState for RwLock<S> {
+    async fn update(&self, data: Metadata) -> Result<()> {
+        self.write().await.update(data)
+    }
+
+    async fn snapshot(&self) -> Result<Metadata> {
+        self.read().await.snapshot()
     }
 }
 
-/// Task identifier type
-#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
-pub struct TaskId(Uuid);
+#[async_trait]
+impl<S: AsyncAgentState + ?Sized> AsyncAgentState for Arc<S> {
+    async fn update(&self, data: Metadata) -> Result<()> {
+        (**self).update(data).await
+    }
 
-impl TaskId {
-    pub fn new() -> Self {
-        Self(Uuid::new_v4())
+    async fn snapshot(&self) -> Result<Metadata> {
+        (**self).snapshot().await
     }
 }
 
-impl Default for TaskId {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Define a UUID-backed identifier type, so IDs of different things cannot
+/// be mixed up
+macro_rules! uuid_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+        pub struct $name(Uuid);
+
+        impl $name {
+            pub fn new() -> Self {
+                Self(Uuid::new_v4())
+            }
+
+            /// Create a time-ordered ID, so IDs sort by creation time in stores
+            pub fn new_v7() -> Self {
+                Self(Uuid::now_v7())
+            }
+
+            /// Get the underlying UUID
+            pub fn as_uuid(&self) -> &Uuid {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = uuid::Error;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                Uuid::parse_str(s).map(Self)
+            }
+        }
+
+        impl From<Uuid> for $name {
+            fn from(id: Uuid) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<$name> for Uuid {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    };
+}
+
+uuid_id! {
+    /// Task identifier type
+    TaskId
+}
+
+uuid_id! {
+    /// Agent identifier type
+    AgentId
+}
+
+uuid_id! {
+    /// Event identifier type
+    EventId
 }
 
 /// Resource type for managing agent capabilities
@@ -212,6 +991,69 @@ impl Tool {
             config,
         }
     }
+
+    /// Describe the tool
+    pub fn descriptor(&self) -> ToolDescriptor {
+        ToolDescriptor::new(self.name.clone(), self.description.clone())
+            .with_config(self.config.clone())
+    }
+}
+
+/// Identity, schema and configuration of a tool
+///
+/// Every layer describes tools with this type, whether they are stored as
+/// a [`Tool`], executed by an agent or served over MCP.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ToolDescriptor {
+    /// Tool name
+    pub name: String,
+
+    /// Tool description
+    pub description: String,
+
+    /// Tool configuration
+    #[serde(default)]
+    pub config: Metadata,
+
+    /// JSON schema of the tool's parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<serde_json::Value>,
+}
+
+impl ToolDescriptor {
+    /// Describe a tool without configuration or schema
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            config: Metadata::new(),
+            input_schema: None,
+        }
+    }
+
+    /// Set the tool configuration
+    pub fn with_config(mut self, config: Metadata) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the JSON schema of the tool's parameters
+    pub fn with_input_schema(mut self, schema: Option<serde_json::Value>) -> Self {
+        self.input_schema = schema;
+        self
+    }
+}
+
+impl From<Tool> for ToolDescriptor {
+    fn from(tool: Tool) -> Self {
+        Self::new(tool.name, tool.description).with_config(tool.config)
+    }
+}
+
+impl From<ToolDescriptor> for Tool {
+    fn from(descriptor: ToolDescriptor) -> Self {
+        Tool::new(descriptor.name, descriptor.description, descriptor.config)
+    }
 }
 
 #[cfg(test)]
@@ -227,6 +1069,232 @@ mod tests {
         assert_eq!(metadata.get::<String>("nonexistent"), None);
     }
 
+    #[test]
+    fn test_metadata_copy_on_write() {
+        let mut original = Metadata::new();
+        original.insert("history", vec!["a"; 1000]);
+        let mut copy = original.clone();
+        assert!(Arc::ptr_eq(&original.0, &copy.0));
+
+        copy.insert("step", 2);
+        assert!(!Arc::ptr_eq(&original.0, &copy.0));
+        assert!(!original.contains_key("step"));
+        assert_eq!(copy.get::<Vec<String>>("history").map(|h| h.len()), Some(1000));
+
+        let json = serde_json::to_string(&copy).unwrap();
+        let parsed: Metadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.get::<i64>("step"), Some(2));
+    }
+
+    #[test]
+    fn test_metadata_try_insert() {
+        let mut metadata = Metadata::new();
+        assert_eq!(metadata.try_insert("count", 1), Ok(None));
+        assert_eq!(metadata.try_insert("count", 2), Ok(Some(serde_json::json!(1))));
+
+        // JSON object keys must be strings
+        let scores = HashMap::from([((1, 2), 0.5)]);
+        assert!(matches!(
+            metadata.try_insert("scores", scores),
+            Err(MetadataError::Serialize { .. })
+        ));
+        assert!(!metadata.contains_key("scores"));
+    }
+
+    #[test]
+    fn test_metadata_map() {
+        let mut metadata = Metadata::try_from(serde_json::json!({"a": 1, "b": 2})).unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert!(metadata.contains_key("a"));
+        *metadata.entry("a").or_insert(serde_json::json!(0)) = serde_json::json!(10);
+        metadata.entry("c").or_insert(serde_json::json!(3));
+        assert_eq!(metadata.remove("b"), Some(serde_json::json!(2)));
+
+        let mut keys: Vec<_> = metadata.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, ["a", "c"]);
+        let total: i64 = metadata.iter().filter_map(|(_, v)| v.as_i64()).sum();
+        assert_eq!(total, 13);
+        assert_eq!(
+            serde_json::Value::from(metadata),
+            serde_json::json!({"a": 10, "c": 3})
+        );
+        assert_eq!(
+            Metadata::try_from(serde_json::json!([1, 2])).unwrap_err(),
+            MetadataError::NotAnObject("array")
+        );
+    }
+
+    #[test]
+    fn test_metadata_try_get() {
+        let mut metadata = Metadata::new();
+        metadata.insert("count", 3);
+        assert_eq!(metadata.try_get::<u32>("count"), Ok(3));
+        assert_eq!(
+            metadata.try_get::<u32>("total"),
+            Err(MetadataError::Missing("total".to_string()))
+        );
+        assert!(matches!(
+            metadata.try_get::<String>("count"),
+            Err(MetadataError::TypeMismatch { actual: "number", .. })
+        ));
+    }
+
+    #[test]
+    fn test_metadata_typed() {
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        struct SearchParams {
+            query: String,
+            limit: u32,
+        }
+
+        let params = SearchParams {
+            query: "atlas".to_string(),
+            limit: 5,
+        };
+        let metadata = Metadata::from_serialize(&params).unwrap();
+        assert_eq!(metadata.get::<u32>("limit"), Some(5));
+        assert_eq!(metadata.to_typed::<SearchParams>().unwrap(), params);
+
+        assert!(matches!(
+            Metadata::from_serialize(&"atlas"),
+            Err(MetadataError::Serialize { .. })
+        ));
+        let mut incomplete = Metadata::new();
+        incomplete.insert("query", "atlas");
+        let error = incomplete.to_typed::<SearchParams>().unwrap_err();
+        assert!(error.to_string().contains("missing field `limit`"));
+    }
+
+    #[test]
+    fn test_metadata_merge() {
+        let mut base = Metadata::new();
+        base.insert("name", "atlas");
+        base.insert("tags", vec!["core"]);
+        base.insert("limits", serde_json::json!({"tokens": 100, "tools": ["search"]}));
+        let mut update = Metadata::new();
+        update.insert("name", "atlas-2");
+        update.insert("tags", vec!["agent"]);
+        update.insert("limits", serde_json::json!({"tools": ["fetch"]}));
+
+        let mut shallow = base.clone();
+        shallow.merge(update.clone(), MergeStrategy::Keep);
+        assert_eq!(shallow.get::<String>("name").as_deref(), Some("atlas"));
+        shallow.merge(update.clone(), MergeStrategy::Overwrite);
+        assert_eq!(
+            shallow.get::<serde_json::Value>("limits"),
+            Some(serde_json::json!({"tools": ["fetch"]}))
+        );
+
+        base.merge_deep(update, MergeStrategy::CombineArrays);
+        assert_eq!(base.get::<String>("name").as_deref(), Some("atlas-2"));
+        assert_eq!(base.get::<Vec<String>>("tags"), Some(vec!["core".into(), "agent".into()]));
+        assert_eq!(
+            base.get::<serde_json::Value>("limits"),
+            Some(serde_json::json!({"tokens": 100, "tools": ["search", "fetch"]}))
+        );
+    }
+
+    #[test]
+    fn test_metadata_path() {
+        let mut metadata = Metadata::new();
+        metadata.insert("plan", serde_json::json!({"steps": [{"tool": "search"}]}));
+        assert_eq!(metadata.get_path::<String>("plan.steps.0.tool").as_deref(), Some("search"));
+        assert_eq!(metadata.get_path::<String>("plan.steps.1.tool"), None);
+        assert_eq!(metadata.get_path::<String>("plan.steps.tool"), None);
+
+        metadata.insert_path("plan.steps.0.tool", "fetch").unwrap();
+        metadata.insert_path("plan.steps.1", serde_json::json!({"tool": "store"})).unwrap();
+        metadata.insert_path("plan.owner.name", "atlas").unwrap();
+        assert_eq!(metadata.get_path::<String>("plan.steps.0.tool").as_deref(), Some("fetch"));
+        assert_eq!(metadata.get_path::<String>("plan.steps.1.tool").as_deref(), Some("store"));
+        assert_eq!(metadata.get_path::<String>("plan.owner.name").as_deref(), Some("atlas"));
+        assert!(metadata.insert_path("plan.steps.5.tool", "late").is_err());
+        assert!(metadata.insert_path("plan.owner.name.first", "a").is_err());
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let mut details = Metadata::new();
+        details.insert("topic", "task.done");
+        let err = Error::Timeout("No reply".to_string()).with_details(details);
+        assert_eq!(err.code(), ErrorCode::Timeout);
+        assert!(err.is_retryable());
+        assert_eq!(err.to_string(), "Timed out: No reply");
+        assert_eq!(
+            err.details().and_then(|d| d.get::<String>("topic")).as_deref(),
+            Some("task.done")
+        );
+
+        let wrapped = Error::Other(err.into());
+        assert_eq!(wrapped.code().as_str(), "timeout");
+        assert!(wrapped.details().is_some());
+        let err = Error::Config("Missing name".to_string());
+        assert_eq!(err.code(), ErrorCode::Config);
+        assert!(!err.is_retryable());
+        assert_eq!(Error::Other(anyhow::anyhow!("boom")).code(), ErrorCode::Internal);
+    }
+
+    #[test]
+    fn test_wire_error_round_trip() {
+        let mut details = Metadata::new();
+        details.insert("attempts", 3);
+        let err = Error::Unavailable("search is down".to_string()).with_details(details);
+        let wire = WireError::from(err);
+        assert_eq!(wire.code, ErrorCode::Unavailable);
+        assert_eq!(wire.message, "Unavailable: search is down");
+        assert!(wire.retryable);
+
+        let json = serde_json::to_string(&wire).unwrap();
+        let received = Error::from(serde_json::from_str::<WireError>(&json).unwrap());
+        assert_eq!(received.code(), ErrorCode::Unavailable);
+        assert!(received.is_retryable());
+        assert_eq!(received.to_string(), "Unavailable: search is down");
+        assert_eq!(
+            received.details().and_then(|d| d.get::<u32>("attempts")),
+            Some(3)
+        );
+        assert_eq!(serde_json::to_string(&WireError::from(received)).unwrap(), json);
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct Counter(Metadata);
+
+    impl AgentState for Counter {
+        fn update(&mut self, data: Metadata) -> Result<()> {
+            self.0.merge(data, MergeStrategy::Overwrite);
+            Ok(())
+        }
+
+        fn snapshot(&self) -> Result<Metadata> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_health_status() {
+        let status = HealthStatus::healthy()
+            .with_issue(HealthLevel::Degraded, "queue backlogged")
+            .combine(HealthStatus::healthy());
+        assert_eq!(status.level, HealthLevel::Degraded);
+        assert!(status.is_ready() && !status.is_healthy());
+
+        let status = status.combine(
+            HealthStatus::healthy().with_issue(HealthLevel::Unhealthy, "store unreachable"),
+        );
+        assert!(!status.is_ready());
+        assert_eq!(status.issues, ["queue backlogged", "store unreachable"]);
+    }
+
+    #[tokio::test]
+    async fn test_async_state() {
+        let state: Arc<dyn AsyncAgentState> = Arc::new(RwLock::new(Counter::default()));
+        let mut data = Metadata::new();
+        data.insert("count", 1);
+        state.update(data).await.unwrap();
+        assert_eq!(state.snapshot().await.unwrap().get::<u32>("count"), Some(1));
+    }
+
     #[test]
     fn test_event() {
         let mut payload = Metadata::new();
@@ -247,5 +1315,30 @@ mod tests {
         let task_id2 = TaskId::new();
         
         assert_ne!(task_id, task_id2);
+
+        let parsed: TaskId = task_id.to_string().parse().unwrap();
+        assert_eq!(parsed, task_id);
+        assert_eq!(TaskId::from(*task_id.as_uuid()), task_id);
+        assert!("not-a-uuid".parse::<TaskId>().is_err());
+
+        let first = TaskId::new_v7();
+        let second = TaskId::new_v7();
+        assert!(first.to_string() < second.to_string());
+    }
+
+    #[test]
+    fn test_tool_descriptor() {
+        let mut config = Metadata::new();
+        config.insert("root", "/workspace");
+        let tool = Tool::new("read_file", "Read a file", config);
+
+        let descriptor = ToolDescriptor::from(tool.clone())
+            .with_input_schema(Some(serde_json::json!({"type": "object"})));
+        assert_eq!(descriptor.name, "read_file");
+        assert_eq!(descriptor.config.get::<String>("root").as_deref(), Some("/workspace"));
+
+        let round_trip = Tool::from(descriptor);
+        assert_eq!(round_trip.description, tool.description);
+        assert_eq!(round_trip.config.get::<String>("root").as_deref(), Some("/workspace"));
     }
 }
@@ -0,0 +1,353 @@
+//! Pooling of expensive handles shared between agents
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::{Clock, Error, HealthLevel, HealthProbe, HealthStatus, SystemClock};
+
+/// Resource whose instances are opened and closed on demand, such as
+/// database connections or browser sessions
+#[async_trait]
+pub trait ManagedResource: Send + Sync + 'static {
+    /// Instance handed out to users of the resource
+    type Handle: Send + Sync + 'static;
+
+    /// Open a new instance
+    async fn acquire(&self) -> Result<Self::Handle>;
+
+    /// Close an instance that is no longer needed
+    async fn release(&self, _handle: Self::Handle) -> Result<()> {
+        Ok(())
+    }
+
+    /// Check that an idle instance can still be used
+    async fn health(&self, _handle: &Self::Handle) -> HealthStatus {
+        HealthStatus::healthy()
+    }
+}
+
+/// Limits of a [`ResourcePool`]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PoolConfig {
+    /// Instances open at once, idle or in use
+    pub max_instances: usize,
+
+    /// How long an instance may stay idle before it is closed, in
+    /// milliseconds
+    pub idle_timeout_ms: u64,
+
+    /// How long to wait for an instance when all are in use, in
+    /// milliseconds
+    pub acquire_timeout_ms: u64,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_instances: 8,
+            idle_timeout_ms: 300_000,
+            acquire_timeout_ms: 30_000,
+        }
+    }
+}
+
+/// Shared state of a pool
+struct PoolInner<R: ManagedResource> {
+    /// Resource the instances belong to
+    resource: R,
+
+    /// Pool limits
+    config: PoolConfig,
+
+    /// Idle instances and when they were returned, oldest first
+    idle: Mutex<Vec<(R::Handle, Instant)>>,
+
+    /// One permit per instance that may be open
+    permits: Arc<Semaphore>,
+
+    /// Source of idle times
+    clock: Arc<dyn Clock>,
+}
+
+/// Pool of resource instances shared by agents
+///
+/// Instances are opened on demand up to `max_instances`, handed out as
+/// [`Pooled`] guards and returned to the pool when the guard is dropped.
+/// Idle instances are health-checked before they are reused and closed once
+/// they have been idle longer than `idle_timeout_ms`. Clones share the pool.
+pub struct ResourcePool<R: ManagedResource> {
+    inner: Arc<PoolInner<R>>,
+}
+
+impl<R: ManagedResource> Clone for ResourcePool<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<R: ManagedResource> ResourcePool<R> {
+    /// Create an empty pool for a resource
+    pub fn new(resource: R, config: PoolConfig) -> Self {
+        let config = PoolConfig {
+            max_instances: config.max_instances.max(1),
+            ..config
+        };
+        Self {
+            inner: Arc::new(PoolInner {
+                resource,
+                permits: Arc::new(Semaphore::new(config.max_instances)),
+                config,
+                idle: Mutex::new(Vec::new()),
+                clock: Arc::new(SystemClock),
+            }),
+        }
+    }
+
+    /// Measure idle times with a clock; must be called before the pool is
+    /// cloned
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        if let Some(inner) = Arc::get_mut(&mut self.inner) {
+            inner.clock = clock;
+        }
+        self
+    }
+
+    /// Get the pool limits
+    pub fn config(&self) -> &PoolConfig {
+        &self.inner.config
+    }
+
+    /// Get how many instances are idle
+    pub fn idle(&self) -> usize {
+        self.lock_idle().len()
+    }
+
+    /// Get how many instances are in use
+    pub fn in_use(&self) -> usize {
+        self.inner.config.max_instances - self.inner.permits.available_permits()
+    }
+
+    /// Take an instance, reusing a healthy idle one or opening a new one
+    ///
+    /// Waits while every instance is in use, failing with [`Error::Timeout`]
+    /// after `acquire_timeout_ms`.
+    pub async fn get(&self) -> Result<Pooled<R>> {
+        let timeout = Duration::from_millis(self.inner.config.acquire_timeout_ms);
+        let permit = tokio::time::timeout(timeout, self.inner.permits.clone().acquire_owned())
+            .await
+            .map_err(|_| Error::Timeout("No pooled resource became available".to_string()))?
+            .map_err(|_| Error::Unavailable("Resource pool is closed".to_string()))?;
+
+        self.evict_idle().await;
+        loop {
+            let Some((handle, _)) = self.lock_idle().pop() else {
+                break;
+            };
+            if self.inner.resource.health(&handle).await.is_ready() {
+                return Ok(self.pooled(handle, permit));
+            }
+            self.close_instance(handle).await;
+        }
+        let handle = self.inner.resource.acquire().await?;
+        Ok(self.pooled(handle, permit))
+    }
+
+    /// Close the instances idle for longer than the idle timeout, returning
+    /// how many were closed
+    pub async fn evict_idle(&self) -> usize {
+        let timeout = Duration::from_millis(self.inner.config.idle_timeout_ms);
+        let now = self.inner.clock.instant();
+        let expired: Vec<R::Handle> = {
+            let mut idle = self.lock_idle();
+            let (expired, kept) = std::mem::take(&mut *idle)
+                .into_iter()
+                .partition(|(_, since)| now.saturating_duration_since(*since) >= timeout);
+            *idle = kept;
+            expired.into_iter().map(|(handle, _)| handle).collect()
+        };
+        let count = expired.len();
+        for handle in expired {
+            self.close_instance(handle).await;
+        }
+        count
+    }
+
+    /// Close every idle instance
+    pub async fn close_idle(&self) {
+        let idle = std::mem::take(&mut *self.lock_idle());
+        for (handle, _) in idle {
+            self.close_instance(handle).await;
+        }
+    }
+
+    /// Wrap an instance in a guard returning it to the pool
+    fn pooled(&self, handle: R::Handle, permit: OwnedSemaphorePermit) -> Pooled<R> {
+        Pooled {
+            handle: Some(handle),
+            pool: self.clone(),
+            _permit: permit,
+        }
+    }
+
+    /// Release an instance, logging failures
+    async fn close_instance(&self, handle: R::Handle) {
+        if let Err(e) = self.inner.resource.release(handle).await {
+            tracing::warn!(error = %e, "Failed to release pooled resource");
+        }
+    }
+
+    /// Lock the idle instances
+    fn lock_idle(&self) -> std::sync::MutexGuard<'_, Vec<(R::Handle, Instant)>> {
+        self.inner
+            .idle
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+impl<R: ManagedResource> std::fmt::Debug for ResourcePool<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourcePool")
+            .field("config", &self.inner.config)
+            .field("idle", &self.idle())
+            .finish()
+    }
+}
+
+/// Exhausted pools report themselves degraded
+#[async_trait]
+impl<R: ManagedResource> HealthProbe for ResourcePool<R> {
+    async fn probe(&self) -> HealthStatus {
+        if self.inner.permits.available_permits() == 0 {
+            HealthStatus::healthy().with_issue(
+                HealthLevel::Degraded,
+                format!(
+                    "All {} pooled resources are in use",
+                    self.inner.config.max_instances
+                ),
+            )
+        } else {
+            HealthStatus::healthy()
+        }
+    }
+}
+
+/// Instance taken from a [`ResourcePool`], returned to it when dropped
+pub struct Pooled<R: ManagedResource> {
+    /// Instance, until it is returned or discarded
+    handle: Option<R::Handle>,
+
+    /// Pool the instance belongs to
+    pool: ResourcePool<R>,
+
+    /// Slot of the instance in the pool
+    _permit: OwnedSemaphorePermit,
+}
+
+impl<R: ManagedResource> Pooled<R> {
+    /// Close the instance instead of returning it, such as after it broke
+    pub async fn discard(mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.pool.close_instance(handle).await;
+        }
+    }
+}
+
+impl<R: ManagedResource> std::fmt::Debug for Pooled<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pooled").field("pool", &self.pool).finish()
+    }
+}
+
+impl<R: ManagedResource> Deref for Pooled<R> {
+    type Target = R::Handle;
+
+    fn deref(&self) -> &Self::Target {
+        self.handle.as_ref().expect("pooled resource was taken")
+    }
+}
+
+impl<R: ManagedResource> DerefMut for Pooled<R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.handle.as_mut().expect("pooled resource was taken")
+    }
+}
+
+impl<R: ManagedResource> Drop for Pooled<R> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let now = self.pool.inner.clock.instant();
+            // Return the instance before the permit is released
+            self.pool.lock_idle().push((handle, now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockClock;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Resource counting the connections it opens and closes
+    #[derive(Default)]
+    struct Connections {
+        opened: AtomicU32,
+        closed: AtomicU32,
+    }
+
+    #[async_trait]
+    impl ManagedResource for Arc<Connections> {
+        type Handle = u32;
+
+        async fn acquire(&self) -> Result<u32> {
+            Ok(self.opened.fetch_add(1, Ordering::SeqCst))
+        }
+
+        async fn release(&self, _handle: u32) -> Result<()> {
+            self.closed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resource_pool() {
+        let connections = Arc::new(Connections::default());
+        let clock = MockClock::default();
+        let config = PoolConfig {
+            max_instances: 2,
+            idle_timeout_ms: 60_000,
+            acquire_timeout_ms: 10,
+        };
+        let pool =
+            ResourcePool::new(connections.clone(), config).with_clock(Arc::new(clock.clone()));
+
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        assert_eq!((*first, *second), (0, 1));
+        assert_eq!(pool.in_use(), 2);
+        assert_eq!(pool.probe().await.level, HealthLevel::Degraded);
+        let err = pool.get().await.unwrap_err().downcast::<Error>().unwrap();
+        assert!(matches!(err, Error::Timeout(_)));
+
+        // Returned instances are reused until they idle out
+        drop(first);
+        assert_eq!(*pool.get().await.unwrap(), 0);
+        second.discard().await;
+        assert_eq!(connections.closed.load(Ordering::SeqCst), 1);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(pool.evict_idle().await, 1);
+        assert_eq!(*pool.get().await.unwrap(), 2);
+        assert_eq!(connections.opened.load(Ordering::SeqCst), 3);
+    }
+}
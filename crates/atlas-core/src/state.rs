@@ -0,0 +1,273 @@
+//! Loading, saving and watching agent states across backends
+
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Mutex};
+
+use crate::{AgentState, Codec, Error};
+
+/// Saved agent state and the version it was saved as
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VersionedState<S> {
+    /// Number of saves up to and including this one
+    pub version: u64,
+
+    /// Saved state
+    pub state: S,
+}
+
+/// Storage of an agent's state
+///
+/// Agents and the crates built on them save and restore state through this
+/// trait, so the backend can be chosen with [`StateBackend`] instead of
+/// being fixed by the agent implementation.
+#[async_trait]
+pub trait StateManager<S: AgentState>: Send + Sync {
+    /// Load the latest saved state, if one was saved
+    async fn load(&self) -> Result<Option<VersionedState<S>>>;
+
+    /// Save a state, returning the version it was saved as
+    async fn save(&self, state: S) -> Result<u64>;
+
+    /// Watch the latest saved state, starting with the current one
+    fn watch(&self) -> watch::Receiver<Option<VersionedState<S>>>;
+}
+
+/// Backend to keep agent states in
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StateBackend {
+    /// Process memory, lost on exit
+    #[default]
+    Memory,
+
+    /// File
+    File {
+        /// File holding the state
+        path: PathBuf,
+
+        /// Encoding of the file
+        #[serde(default)]
+        codec: Codec,
+    },
+}
+
+impl StateBackend {
+    /// Open a state manager on this backend
+    pub fn open<S>(&self) -> Arc<dyn StateManager<S>>
+    where
+        S: AgentState + Serialize + DeserializeOwned + 'static,
+    {
+        match self {
+            StateBackend::Memory => Arc::new(InMemoryStateManager::new()),
+            StateBackend::File { path, codec } => {
+                Arc::new(FileStateManager::new(path.clone()).with_codec(*codec))
+            }
+        }
+    }
+}
+
+/// State manager keeping the state in process memory
+#[derive(Debug)]
+pub struct InMemoryStateManager<S> {
+    /// Latest saved state
+    latest: watch::Sender<Option<VersionedState<S>>>,
+}
+
+impl<S> Default for InMemoryStateManager<S> {
+    fn default() -> Self {
+        Self {
+            latest: watch::channel(None).0,
+        }
+    }
+}
+
+impl<S> InMemoryStateManager<S> {
+    /// Create a manager with nothing saved
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl<S: AgentState + 'static> StateManager<S> for InMemoryStateManager<S> {
+    async fn load(&self) -> Result<Option<VersionedState<S>>> {
+        Ok(self.latest.borrow().clone())
+    }
+
+    async fn save(&self, state: S) -> Result<u64> {
+        let mut version = 0;
+        self.latest.send_modify(|latest| {
+            version = latest.as_ref().map_or(0, |saved| saved.version) + 1;
+            *latest = Some(VersionedState { version, state });
+        });
+        Ok(version)
+    }
+
+    fn watch(&self) -> watch::Receiver<Option<VersionedState<S>>> {
+        self.latest.subscribe()
+    }
+}
+
+/// State manager keeping the state in a file, encoded as JSON unless
+/// another [`Codec`] is set
+///
+/// Saves replace the file atomically. The file is read on the first load or
+/// save, so a manager picks up the state saved by a previous run.
+#[derive(Debug)]
+pub struct FileStateManager<S> {
+    /// File holding the state
+    path: PathBuf,
+
+    /// Encoding of the file
+    codec: Codec,
+
+    /// Latest saved state
+    latest: watch::Sender<Option<VersionedState<S>>>,
+
+    /// Whether the file has been read, held while saving
+    loaded: Mutex<bool>,
+
+    /// Type of the state kept in the file
+    _state: PhantomData<fn() -> S>,
+}
+
+impl<S> FileStateManager<S> {
+    /// Create a manager for a file, which need not exist yet
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            codec: Codec::default(),
+            latest: watch::channel(None).0,
+            loaded: Mutex::new(false),
+            _state: PhantomData,
+        }
+    }
+
+    /// Encode the file with a codec
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Get the file holding the state
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl<S: AgentState + DeserializeOwned> FileStateManager<S> {
+    /// Read the file unless it has been read already
+    async fn read_once(&self, loaded: &mut bool) -> Result<()> {
+        if *loaded {
+            return Ok(());
+        }
+        let saved = match tokio::fs::read(&self.path).await {
+            Ok(bytes) => Some(self.codec.decode(&bytes).map_err(|e| {
+                Error::State(format!("Invalid state file {}: {}", self.path.display(), e))
+            })?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        self.latest.send_replace(saved);
+        *loaded = true;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> StateManager<S> for FileStateManager<S>
+where
+    S: AgentState + Serialize + DeserializeOwned + 'static,
+{
+    async fn load(&self) -> Result<Option<VersionedState<S>>> {
+        self.read_once(&mut *self.loaded.lock().await).await?;
+        Ok(self.latest.borrow().clone())
+    }
+
+    async fn save(&self, state: S) -> Result<u64> {
+        let mut loaded = self.loaded.lock().await;
+        self.read_once(&mut loaded).await?;
+        let version = self
+            .latest
+            .borrow()
+            .as_ref()
+            .map_or(0, |saved| saved.version)
+            + 1;
+        let saved = VersionedState { version, state };
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, self.codec.encode(&saved)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        self.latest.send_replace(Some(saved));
+        Ok(version)
+    }
+
+    fn watch(&self) -> watch::Receiver<Option<VersionedState<S>>> {
+        self.latest.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    struct Counter {
+        count: u64,
+    }
+
+    impl AgentState for Counter {
+        fn update(&mut self, data: Metadata) -> Result<()> {
+            self.count += data.get::<u64>("count").unwrap_or(0);
+            Ok(())
+        }
+
+        fn snapshot(&self) -> Result<Metadata> {
+            let mut metadata = Metadata::new();
+            metadata.insert("count", self.count);
+            Ok(metadata)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_state_backends() {
+        let path = std::env::temp_dir()
+            .join(format!("atlas-state-{}", uuid::Uuid::new_v4()))
+            .join("agent.json");
+        let backends = [
+            StateBackend::Memory,
+            StateBackend::File {
+                path: path.clone(),
+                codec: Codec::default(),
+            },
+        ];
+        for backend in &backends {
+            let manager = backend.open::<Counter>();
+            assert!(manager.load().await.unwrap().is_none());
+            let mut changes = manager.watch();
+
+            assert_eq!(manager.save(Counter { count: 1 }).await.unwrap(), 1);
+            assert_eq!(manager.save(Counter { count: 2 }).await.unwrap(), 2);
+            changes.changed().await.unwrap();
+            assert_eq!(changes.borrow().as_ref().unwrap().state.count, 2);
+            assert_eq!(manager.load().await.unwrap().unwrap().version, 2);
+        }
+
+        // A new manager picks up what the last run saved
+        let reopened = backends[1].open::<Counter>();
+        let saved = reopened.load().await.unwrap().unwrap();
+        assert_eq!((saved.version, saved.state.count), (2, 2));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,184 @@
+//! Agents standing in for real ones in the tests of crates building on Atlas
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+use crate::{CancellationContext, DynAgent, Event, HealthStatus, Metadata, TaskId};
+
+/// Work a fake agent does for each task
+type TaskFn = Arc<dyn Fn(Metadata) -> BoxFuture<'static, Result<Metadata>> + Send + Sync>;
+
+/// Reaction of a fake agent to each event it handles
+type EventFn = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Agent answering tasks with a closure of their parameters, recording the
+/// events it handles and counting its starts
+///
+/// Cancelled tasks stop at their next await point, like those of real
+/// agents.
+pub struct FakeAgent {
+    /// Work done for each task
+    task: TaskFn,
+
+    /// Reaction to each event, after it is recorded
+    on_event: Option<EventFn>,
+
+    /// Types of the events handled, in order
+    events: Mutex<Vec<String>>,
+
+    /// Health reported
+    health: Mutex<HealthStatus>,
+
+    /// Whether health checks never answer
+    hung: AtomicBool,
+
+    /// Whether tasks fail while the agent is not started
+    requires_start: bool,
+
+    /// Whether the agent is started
+    started: AtomicBool,
+
+    /// Times the agent was started
+    starts: AtomicUsize,
+}
+
+impl FakeAgent {
+    /// Create an agent answering each task with the output of `task`
+    pub fn new<F, Fut>(task: F) -> Self
+    where
+        F: Fn(Metadata) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Metadata>> + Send + 'static,
+    {
+        Self {
+            task: Arc::new(move |params| task(params).boxed()),
+            on_event: None,
+            events: Mutex::new(Vec::new()),
+            health: Mutex::new(HealthStatus::healthy()),
+            hung: AtomicBool::new(false),
+            requires_start: false,
+            started: AtomicBool::new(false),
+            starts: AtomicUsize::new(0),
+        }
+    }
+
+    /// Create an agent answering each task with its parameters
+    pub fn echo() -> Self {
+        Self::new(|params| async move { Ok(params) })
+    }
+
+    /// React to each event handled, after recording it
+    pub fn on_event(mut self, react: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.on_event = Some(Arc::new(react));
+        self
+    }
+
+    /// Fail tasks while the agent is not started
+    pub fn requiring_start(mut self) -> Self {
+        self.requires_start = true;
+        self
+    }
+
+    /// Report a health from now on
+    pub fn set_health(&self, health: HealthStatus) {
+        *self.health.lock().unwrap_or_else(PoisonError::into_inner) = health;
+    }
+
+    /// Make health checks hang, or answer again
+    pub fn set_hung(&self, hung: bool) {
+        self.hung.store(hung, Ordering::SeqCst);
+    }
+
+    /// Get the types of the events handled, in order
+    pub fn events(&self) -> Vec<String> {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Check whether the agent is started
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::SeqCst)
+    }
+
+    /// Get the times the agent was started
+    pub fn starts(&self) -> usize {
+        self.starts.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl DynAgent for FakeAgent {
+    fn type_name(&self) -> &'static str {
+        "FakeAgent"
+    }
+
+    async fn snapshot(&self) -> Result<Metadata> {
+        Ok(Metadata::new())
+    }
+
+    async fn update_state(&self, _data: Metadata) -> Result<()> {
+        Ok(())
+    }
+
+    async fn handle_event(&self, event: Event) -> Result<()> {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(event.event_type.to_string());
+        if let Some(react) = &self.on_event {
+            react(&event);
+        }
+        Ok(())
+    }
+
+    async fn execute_task(&self, task_id: TaskId, params: Metadata) -> Result<Metadata> {
+        self.execute_task_with(task_id, params, &CancellationContext::new())
+            .await
+    }
+
+    async fn execute_task_with(
+        &self,
+        _task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        anyhow::ensure!(!self.requires_start || self.is_started(), "not started");
+        cancel.run((self.task)(params)).await
+    }
+
+    async fn on_start(&self) -> Result<()> {
+        self.starts.fetch_add(1, Ordering::SeqCst);
+        self.started.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn on_stop(&self) -> Result<()> {
+        self.started.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn pause(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn resume(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn health(&self) -> HealthStatus {
+        if self.hung.load(Ordering::SeqCst) {
+            std::future::pending::<()>().await;
+        }
+        self.health
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
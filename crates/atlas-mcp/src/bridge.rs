@@ -0,0 +1,316 @@
+//! Relay of event bus topics between processes
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use atlas_core::{Event, EventBus, TopicPattern};
+
+use crate::Error;
+
+/// Event metadata key listing the bridges an event has crossed, oldest first
+pub const BRIDGE_PATH: &str = "bridge_path";
+
+/// Topics an event bridge relays
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BridgeConfig {
+    /// Topics or patterns whose local events are sent to the other side
+    pub export: Vec<String>,
+
+    /// Topics or patterns accepted from the other side
+    pub import: Vec<String>,
+
+    /// Bridges an event may cross before it is dropped
+    pub max_hops: usize,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            export: Vec::new(),
+            import: vec!["#".to_string()],
+            max_hops: 8,
+        }
+    }
+}
+
+/// Message exchanged by the two ends of a bridge connection
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BridgeMessage {
+    /// Event published on the sender's bus
+    Event {
+        /// Published event
+        event: Event,
+    },
+}
+
+/// Relay joining the event buses of two processes into one event space
+///
+/// Each end exports the local events on its chosen topics to the other,
+/// which publishes the ones it imports on its own bus. Every relayed event
+/// records the bridges it crossed under [`BRIDGE_PATH`], so an event is never
+/// sent back through a bridge it already crossed, and topologies with cycles
+/// do not relay events forever.
+pub struct EventBridge {
+    /// ID recorded in the path of relayed events
+    id: Uuid,
+
+    /// Local event bus
+    bus: Arc<dyn EventBus>,
+
+    /// Relayed topics
+    config: BridgeConfig,
+
+    /// Parsed export patterns
+    export: Vec<TopicPattern>,
+
+    /// Parsed import patterns
+    import: Vec<TopicPattern>,
+}
+
+impl EventBridge {
+    /// Create a bridge for a local bus
+    pub fn new(bus: Arc<dyn EventBus>, config: BridgeConfig) -> Result<Self> {
+        let parse = |patterns: &[String]| -> Result<Vec<TopicPattern>> {
+            patterns.iter().map(|p| TopicPattern::parse(p)).collect()
+        };
+        Ok(Self {
+            id: Uuid::new_v4(),
+            export: parse(&config.export)?,
+            import: parse(&config.import)?,
+            bus,
+            config,
+        })
+    }
+
+    /// Get the ID the bridge records in relayed events
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Get the relayed topics
+    pub fn config(&self) -> &BridgeConfig {
+        &self.config
+    }
+
+    /// Relay events over a connection carrying [`BridgeMessage`]s as JSON
+    /// text until either side closes it
+    pub async fn run<S, R, E>(&self, mut outgoing: S, mut incoming: R) -> Result<()>
+    where
+        S: Sink<String> + Unpin,
+        S::Error: std::fmt::Display,
+        R: Stream<Item = std::result::Result<String, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let mut streams = Vec::with_capacity(self.config.export.len());
+        for topic in &self.config.export {
+            streams.push(self.bus.subscribe(topic).await?);
+        }
+        let mut local = futures::stream::select_all(streams);
+
+        loop {
+            tokio::select! {
+                event = local.next(), if !local.is_empty() => {
+                    let Some(event) = event else {
+                        return Ok(());
+                    };
+                    if let Some(message) = self.export(event)? {
+                        outgoing.send(message).await.map_err(|e| {
+                            Error::ServerError(format!("Failed to relay event: {}", e))
+                        })?;
+                    }
+                }
+                message = incoming.next() => match message {
+                    Some(Ok(message)) => self.import(&message).await,
+                    Some(Err(e)) => {
+                        return Err(Error::ServerError(format!("Bridge connection failed: {}", e)).into());
+                    }
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
+    /// Encode a local event for the other side, unless it should stay here
+    fn export(&self, mut event: Event) -> Result<Option<String>> {
+        let mut path: Vec<Uuid> = event.metadata.get(BRIDGE_PATH).unwrap_or_default();
+        // Events imported through this bridge, or that looped back, stay
+        if path.contains(&self.id) || path.len() >= self.config.max_hops {
+            return Ok(None);
+        }
+        if !self.export.iter().any(|p| p.matches(event.topic())) {
+            return Ok(None);
+        }
+        path.push(self.id);
+        event.metadata.try_insert(BRIDGE_PATH, path)?;
+        Ok(Some(serde_json::to_string(&BridgeMessage::Event {
+            event,
+        })?))
+    }
+
+    /// Publish an event received from the other side on the local bus
+    async fn import(&self, message: &str) {
+        let event = match serde_json::from_str(message) {
+            Ok(BridgeMessage::Event { event }) => event,
+            Err(e) => {
+                tracing::warn!(error = %e, "Skipping malformed bridge message");
+                return;
+            }
+        };
+        let path: Vec<Uuid> = event.metadata.get(BRIDGE_PATH).unwrap_or_default();
+        if !self.import.iter().any(|p| p.matches(event.topic())) || path.contains(&self.id) {
+            return;
+        }
+        let event_type = event.event_type.clone();
+        let published = async {
+            self.bus.register_topic(event.topic()).await?;
+            self.bus.publish(event).await
+        };
+        if let Err(e) = published.await {
+            tracing::warn!(%event_type, error = %e, "Failed to publish bridged event");
+        }
+    }
+}
+
+impl std::fmt::Debug for EventBridge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBridge")
+            .field("id", &self.id)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+/// WebSocket transport of event bridges
+#[cfg(feature = "websocket")]
+pub mod websocket {
+    use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+    use axum::extract::State;
+    use axum::response::Response;
+    use axum::routing::get;
+    use axum::Router;
+
+    use super::*;
+
+    /// Path of the bridge endpoint on an MCP server
+    pub const ROUTE: &str = "/events/bridge";
+
+    /// Router accepting bridge connections from other processes
+    pub fn router(bridge: Arc<EventBridge>) -> Router {
+        Router::new().route(ROUTE, get(upgrade)).with_state(bridge)
+    }
+
+    /// Upgrade a request to a bridge connection
+    async fn upgrade(State(bridge): State<Arc<EventBridge>>, ws: WebSocketUpgrade) -> Response {
+        ws.on_upgrade(move |socket| async move {
+            if let Err(e) = serve(&bridge, socket).await {
+                tracing::warn!(error = %e, "Event bridge connection failed");
+            }
+        })
+    }
+
+    /// Relay events over an accepted connection
+    async fn serve(bridge: &EventBridge, socket: WebSocket) -> Result<()> {
+        let (outgoing, incoming) = socket.split();
+        let outgoing =
+            outgoing.with(|text: String| async move { Ok::<_, axum::Error>(Message::Text(text)) });
+        let incoming = incoming.filter_map(|message| async move {
+            match message {
+                Ok(Message::Text(text)) => Some(Ok(text)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }
+        });
+        bridge.run(Box::pin(outgoing), Box::pin(incoming)).await
+    }
+
+    /// Connect to the bridge endpoint of another process, such as
+    /// `ws://host:port/events/bridge`, and relay events until the
+    /// connection closes
+    pub async fn connect(bridge: &EventBridge, url: &str) -> Result<()> {
+        use tokio_tungstenite::tungstenite::Message;
+
+        let (socket, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| Error::ServerError(format!("Failed to connect event bridge: {}", e)))?;
+        let (outgoing, incoming) = socket.split();
+        let outgoing = outgoing.with(|text: String| async move {
+            Ok::<_, tokio_tungstenite::tungstenite::Error>(Message::Text(text))
+        });
+        let incoming = incoming.filter_map(|message| async move {
+            match message {
+                Ok(Message::Text(text)) => Some(Ok(text)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }
+        });
+        bridge.run(Box::pin(outgoing), Box::pin(incoming)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::{InMemoryEventBus, Metadata};
+    use futures::channel::mpsc;
+
+    /// Run a bridge over in-memory channels
+    fn spawn(
+        bridge: Arc<EventBridge>,
+        outgoing: mpsc::UnboundedSender<String>,
+        incoming: mpsc::UnboundedReceiver<String>,
+    ) {
+        tokio::spawn(async move {
+            let incoming = incoming.map(Ok::<_, std::convert::Infallible>);
+            bridge.run(outgoing, incoming).await.unwrap();
+        });
+    }
+
+    #[tokio::test]
+    async fn test_relay_without_loops() {
+        let local = Arc::new(InMemoryEventBus::new());
+        let remote = Arc::new(InMemoryEventBus::new());
+        for topic in ["task.done", "telemetry"] {
+            local.register_topic(topic).await.unwrap();
+        }
+        remote.register_topic("task.done").await.unwrap();
+        let config = BridgeConfig {
+            export: vec!["task.*".to_string()],
+            ..Default::default()
+        };
+        let a = Arc::new(EventBridge::new(local.clone(), config.clone()).unwrap());
+        let b = Arc::new(EventBridge::new(remote.clone(), config).unwrap());
+        let mut local_events = local.subscribe("#").await.unwrap();
+        let mut remote_events = remote.subscribe("#").await.unwrap();
+        let (a_out, b_in) = mpsc::unbounded();
+        let (b_out, a_in) = mpsc::unbounded();
+        spawn(a.clone(), a_out, a_in);
+        spawn(b, b_out, b_in);
+        tokio::task::yield_now().await;
+
+        local
+            .publish(Event::new("telemetry", Metadata::new()))
+            .await
+            .unwrap();
+        let done = Event::new("task.done", Metadata::new());
+        local.publish(done.clone()).await.unwrap();
+
+        // Telemetry is not exported, and the relayed event is not echoed back
+        let relayed = remote_events.next().await.unwrap();
+        assert_eq!(relayed.id, done.id);
+        assert_eq!(
+            relayed.metadata.get::<Vec<Uuid>>(BRIDGE_PATH),
+            Some(vec![a.id()])
+        );
+        assert_eq!(local_events.next().await.unwrap().event_type, "telemetry");
+        assert_eq!(local_events.next().await.unwrap().id, done.id);
+        let echo =
+            tokio::time::timeout(std::time::Duration::from_millis(50), local_events.next()).await;
+        assert!(echo.is_err());
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::fmt;
 
+use atlas_core::WireError;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -116,7 +117,9 @@ impl From<Error> for ErrorResponse {
             Error::Other(err) => Self {
                 code: ErrorCode::ServerError,
                 message: err.to_string(),
-                details: None,
+                details: core_error(&err)
+                    .and_then(atlas_core::Error::details)
+                    .map(|details| serde_json::Value::from(details.clone())),
             },
         }
     }
@@ -132,13 +135,46 @@ impl From<Error> for axum::http::StatusCode {
             Error::ToolExecutionFailed(_) | Error::ResourceAccessFailed(_) => {
                 axum::http::StatusCode::INTERNAL_SERVER_ERROR
             }
-            Error::ServerError(_) | Error::Other(_) => {
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            Error::ServerError(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Other(err) => match core_error(&err).map(atlas_core::Error::code) {
+                Some(atlas_core::ErrorCode::Timeout) => axum::http::StatusCode::GATEWAY_TIMEOUT,
+                Some(atlas_core::ErrorCode::Unavailable) => {
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE
+                }
+                Some(atlas_core::ErrorCode::NotFound) => axum::http::StatusCode::NOT_FOUND,
+                Some(atlas_core::ErrorCode::InvalidRequest) => axum::http::StatusCode::BAD_REQUEST,
+                _ => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        }
+    }
+}
+
+impl From<Error> for WireError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Other(e) => atlas_core::Error::Other(e).into(),
+            err => {
+                let code = match &err {
+                    Error::ToolNotFound(_) | Error::ResourceNotFound(_) => {
+                        atlas_core::ErrorCode::NotFound
+                    }
+                    Error::InvalidRequest(_) => atlas_core::ErrorCode::InvalidRequest,
+                    Error::ToolExecutionFailed(_) => atlas_core::ErrorCode::Tool,
+                    Error::ResourceAccessFailed(_) | Error::ServerError(_) | Error::Other(_) => {
+                        atlas_core::ErrorCode::Internal
+                    }
+                };
+                WireError::new(code, err.to_string())
             }
         }
     }
 }
 
+/// Core error wrapped by an error, if any
+fn core_error(err: &anyhow::Error) -> Option<&atlas_core::Error> {
+    err.downcast_ref::<atlas_core::Error>()
+}
+
 /// Result type for MCP operations
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -164,6 +200,31 @@ mod tests {
         assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
     }
 
+    #[test]
+    fn test_core_error_mapping() {
+        let mut details = atlas_core::Metadata::new();
+        details.insert("service", "search");
+        let core = atlas_core::Error::Unavailable("search is down".to_string())
+            .with_details(details);
+
+        let status: axum::http::StatusCode = Error::Other(core.into()).into();
+        assert_eq!(status, axum::http::StatusCode::SERVICE_UNAVAILABLE);
+        let core = atlas_core::Error::Timeout("search".to_string());
+        let status: axum::http::StatusCode = Error::Other(core.into()).into();
+        assert_eq!(status, axum::http::StatusCode::GATEWAY_TIMEOUT);
+
+        let core = atlas_core::Error::Tool("bad input".to_string())
+            .with_details(serde_json::json!({"field": "query"}).try_into().unwrap());
+        let response: ErrorResponse = Error::Other(core.into()).into();
+        assert_eq!(response.details, Some(serde_json::json!({"field": "query"})));
+
+        let wire = WireError::from(Error::ToolNotFound("search".to_string()));
+        assert_eq!(wire.code, atlas_core::ErrorCode::NotFound);
+        assert_eq!(wire.message, "Tool not found: search");
+        let core = atlas_core::Error::Timeout("search".to_string());
+        assert!(WireError::from(Error::Other(core.into())).retryable);
+    }
+
     #[test]
     fn test_error_display() {
         let err = Error::InvalidRequest("bad request".to_string());
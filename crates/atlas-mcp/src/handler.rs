@@ -1,17 +1,20 @@
 //! HTTP handlers for the MCP server endpoints
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::tasks::{SubmitTaskRequest, TaskHost, TaskQuery, TaskRecord};
+use crate::types::ToolInfo;
 use crate::{ServerState, MCPTool, MCPResource};
-use atlas_core::Metadata;
+use atlas_core::{HealthStatus, Metadata, TaskId};
 
 /// Health check response
 #[derive(Debug, Serialize)]
@@ -20,13 +23,6 @@ pub struct HealthCheck {
     version: String,
 }
 
-/// Tool information response
-#[derive(Debug, Serialize)]
-pub struct ToolInfo {
-    name: String,
-    description: String,
-}
-
 /// Resource information response
 #[derive(Debug, Serialize)]
 pub struct ResourceInfo {
@@ -59,21 +55,28 @@ pub async fn health_check(
     })
 }
 
+/// Readiness probe handler, failing while any health probe is unhealthy
+pub async fn readiness(
+    State(state): State<Arc<ServerState>>,
+) -> (StatusCode, Json<HealthStatus>) {
+    let mut status = HealthStatus::healthy();
+    for probe in &state.health_probes {
+        status = status.combine(probe.probe().await);
+    }
+    let code = if status.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(status))
+}
+
 /// List available tools
 pub async fn list_tools(
     State(state): State<Arc<ServerState>>,
 ) -> Json<Vec<ToolInfo>> {
     let tools = state.tools.read().await;
-    let mut tool_list = Vec::new();
-
-    for (name, tool) in tools.tools.iter() {
-        tool_list.push(ToolInfo {
-            name: name.clone(),
-            description: tool.description().to_string(),
-        });
-    }
-
-    Json(tool_list)
+    Json(tools.descriptors().into_iter().map(ToolInfo::from).collect())
 }
 
 /// Execute a tool
@@ -88,7 +91,7 @@ pub async fn execute_tool(
         .get(&tool_name)
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let params = Metadata::from(request.params);
+    let params = Metadata::try_from(request.params).map_err(|_| StatusCode::BAD_REQUEST)?;
     
     match tool.execute(params).await {
         Ok(result) => Ok(Json(ExecuteToolResponse {
@@ -133,7 +136,7 @@ pub async fn access_resource(
         .get(&resource_name)
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let params = Metadata::from(params);
+    let params = Metadata::try_from(params).map_err(|_| StatusCode::BAD_REQUEST)?;
     
     match resource.access(params).await {
         Ok(result) => Ok(Json(serde_json::to_value(result).unwrap())),
@@ -141,6 +144,45 @@ pub async fn access_resource(
     }
 }
 
+/// Get the host of the served agent, if the server hosts one
+fn task_host(state: &ServerState) -> Result<&TaskHost, StatusCode> {
+    state.tasks.as_deref().ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Submit a task to the served agent
+pub async fn submit_task(
+    State(state): State<Arc<ServerState>>,
+    Json(request): Json<SubmitTaskRequest>,
+) -> Result<(StatusCode, Json<TaskRecord>), StatusCode> {
+    let host = task_host(&state)?;
+    let task_id = request.task_id.unwrap_or_else(TaskId::new);
+    let record = host
+        .submit(task_id, request.params)
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    Ok((StatusCode::ACCEPTED, Json(record)))
+}
+
+/// Get the status of a task, waiting for it to finish if asked to
+pub async fn task_status(
+    State(state): State<Arc<ServerState>>,
+    Path(task_id): Path<TaskId>,
+    Query(query): Query<TaskQuery>,
+) -> Result<Json<TaskRecord>, StatusCode> {
+    let host = task_host(&state)?;
+    let wait = query.wait_ms.map(Duration::from_millis);
+    host.get(task_id, wait).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Cancel a task if it is still running and forget it
+pub async fn remove_task(
+    State(state): State<Arc<ServerState>>,
+    Path(task_id): Path<TaskId>,
+) -> Result<Json<TaskRecord>, StatusCode> {
+    let host = task_host(&state)?;
+    host.remove(task_id).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -183,6 +225,40 @@ mod tests {
         assert_eq!(response.0.version, "0.1.0");
     }
 
+    struct Probe(HealthStatus);
+
+    #[async_trait]
+    impl atlas_core::HealthProbe for Probe {
+        async fn probe(&self) -> HealthStatus {
+            self.0.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let config = ServerConfig {
+            name: "test".to_string(),
+            version: "0.1.0".to_string(),
+            description: None,
+            capabilities: ServerCapabilities::default(),
+        };
+        let mut state = ServerState::new(config);
+        let degraded = HealthStatus::healthy()
+            .with_issue(atlas_core::HealthLevel::Degraded, "Agent is paused");
+        state.health_probes.push(Arc::new(Probe(degraded)));
+        let state = Arc::new(state);
+        let (code, status) = readiness(State(state.clone())).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(status.0.issues, ["Agent is paused"]);
+
+        let mut state = Arc::try_unwrap(state).ok().unwrap();
+        let unhealthy = HealthStatus::healthy()
+            .with_issue(atlas_core::HealthLevel::Unhealthy, "Agent is stopped");
+        state.health_probes.push(Arc::new(Probe(unhealthy)));
+        let (code, _) = readiness(State(Arc::new(state))).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
     #[tokio::test]
     async fn test_list_tools() {
         let config = ServerConfig {
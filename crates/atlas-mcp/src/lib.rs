@@ -17,16 +17,27 @@ use tokio::sync::RwLock;
 use tower_http::trace::TraceLayer;
 use uuid::Uuid;
 
-use atlas_core::{Metadata, Resource, Tool};
+use atlas_core::{
+    CancellationContext, ConfigLoader, HealthProbe, Metadata, Resource, Tool, ToolDescriptor,
+    ValidationErrors,
+};
 
+pub mod bridge;
 pub mod error;
 pub mod handler;
+#[cfg(feature = "client")]
+pub mod remote;
 pub mod server;
+pub mod tasks;
 pub mod types;
 
 // Re-exports
+pub use bridge::{BridgeConfig, EventBridge};
 pub use error::Error;
+#[cfg(feature = "client")]
+pub use remote::{RemoteAgent, RemoteAgentConfig};
 pub use server::MCPServer;
+pub use tasks::{TaskHost, TaskHostConfig, TaskRecord, TaskStatus};
 pub use types::{MCPRequest, MCPResponse, MCPTool, MCPResource};
 
 /// MCP server configuration
@@ -42,9 +53,42 @@ pub struct ServerConfig {
     pub description: Option<String>,
     
     /// Server capabilities
+    #[serde(default)]
     pub capabilities: ServerCapabilities,
 }
 
+impl ServerConfig {
+    /// Prefix of the environment variables overriding file settings
+    pub const ENV_PREFIX: &'static str = "ATLAS_MCP";
+
+    /// Load a configuration file in JSON, TOML or YAML, going by its
+    /// extension
+    ///
+    /// Settings may reference environment variables as `${VAR}`, and
+    /// variables such as `ATLAS_MCP_VERSION` override them.
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let config: Self = ConfigLoader::new()
+            .with_file(path)
+            .with_env_prefix(Self::ENV_PREFIX)
+            .load()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Validate the configuration, listing every problem found
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+        self.check(&mut errors);
+        errors.into_result()
+    }
+
+    /// Record the problems with the configuration on its own
+    pub(crate) fn check(&self, errors: &mut ValidationErrors) {
+        errors.ensure(!self.name.trim().is_empty(), "Server name is required");
+        errors.ensure(!self.version.trim().is_empty(), "Server version is required");
+    }
+}
+
 /// Server capabilities configuration
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct ServerCapabilities {
@@ -78,6 +122,21 @@ impl ToolRegistry {
     pub fn get(&self, name: &str) -> Option<Arc<dyn MCPTool>> {
         self.tools.get(name).cloned()
     }
+
+    /// Describe the registered tools under their registered names, sorted
+    /// by name
+    pub fn descriptors(&self) -> Vec<ToolDescriptor> {
+        let mut descriptors: Vec<ToolDescriptor> = self
+            .tools
+            .iter()
+            .map(|(name, tool)| ToolDescriptor {
+                name: name.clone(),
+                ..tool.descriptor()
+            })
+            .collect();
+        descriptors.sort_by(|a, b| a.name.cmp(&b.name));
+        descriptors
+    }
 }
 
 /// MCP resource registry
@@ -113,9 +172,33 @@ pub trait MCPTool: Send + Sync {
     
     /// Get the tool's description
     fn description(&self) -> &str;
+
+    /// Get the JSON schema for the tool's parameters, if it declares one
+    fn input_schema(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Describe the tool's identity, schema and configuration
+    fn descriptor(&self) -> ToolDescriptor {
+        ToolDescriptor::new(self.name(), self.description())
+            .with_input_schema(self.input_schema())
+    }
     
     /// Execute the tool with the given parameters
     async fn execute(&self, params: Metadata) -> Result<Metadata>;
+
+    /// Execute the tool, stopping once the context is cancelled or its
+    /// deadline passes
+    ///
+    /// By default the execution is abandoned; tools that can stop cleanly or
+    /// hand the deadline to a client override this.
+    async fn execute_with(
+        &self,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        cancel.run(self.execute(params)).await
+    }
 }
 
 /// MCP resource trait
@@ -142,6 +225,12 @@ pub struct ServerState {
     
     /// Resource registry
     pub resources: Arc<RwLock<ResourceRegistry>>,
+
+    /// Sources of the health reported by the readiness probe
+    pub health_probes: Vec<Arc<dyn HealthProbe>>,
+
+    /// Host of the agent running submitted tasks, if the server serves one
+    pub tasks: Option<Arc<TaskHost>>,
 }
 
 impl ServerState {
@@ -150,6 +239,8 @@ impl ServerState {
             config,
             tools: Arc::new(RwLock::new(ToolRegistry::new())),
             resources: Arc::new(RwLock::new(ResourceRegistry::new())),
+            health_probes: Vec::new(),
+            tasks: None,
         }
     }
 }
@@ -158,10 +249,13 @@ impl ServerState {
 pub fn create_router(state: ServerState) -> Router {
     Router::new()
         .route("/", get(handler::health_check))
+        .route("/ready", get(handler::readiness))
         .route("/tools", get(handler::list_tools))
         .route("/tools/:name", post(handler::execute_tool))
         .route("/resources", get(handler::list_resources))
         .route("/resources/:name", get(handler::access_resource))
+        .route("/tasks", post(handler::submit_task))
+        .route("/tasks/:id", get(handler::task_status).delete(handler::remove_task))
         .layer(TraceLayer::new_for_http())
         .with_state(Arc::new(state))
 }
@@ -0,0 +1,367 @@
+//! Agents running behind a remote MCP server
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use atlas_core::{
+    Agent, AgentConfig, AgentState, CancellationContext, Event, HealthLevel, HealthStatus,
+    Metadata, TaskId,
+};
+
+use crate::tasks::{SubmitTaskRequest, TaskRecord, TaskStatus};
+
+/// Configuration of a remote agent
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RemoteAgentConfig {
+    /// Base URL of the MCP server serving the agent
+    pub url: String,
+
+    /// How long each status request waits for the task to finish
+    #[serde(default = "default_poll_wait_ms")]
+    pub poll_wait_ms: u64,
+}
+
+fn default_poll_wait_ms() -> u64 {
+    30_000
+}
+
+impl RemoteAgentConfig {
+    /// Configure an agent served at a URL
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            poll_wait_ms: default_poll_wait_ms(),
+        }
+    }
+
+    /// Set how long each status request waits for the task to finish
+    pub fn with_poll_wait(mut self, wait: Duration) -> Self {
+        self.poll_wait_ms = wait.as_millis() as u64;
+        self
+    }
+}
+
+impl AgentConfig for RemoteAgentConfig {
+    fn validate(&self) -> Result<()> {
+        let mut errors = atlas_core::ValidationErrors::new();
+        errors.ensure(
+            self.url.starts_with("http://") || self.url.starts_with("https://"),
+            format!("Remote agent URL {} must be an HTTP(S) URL", self.url),
+        );
+        errors.ensure(
+            self.poll_wait_ms > 0,
+            "Remote agent poll wait must be positive",
+        );
+        errors.into_result()
+    }
+}
+
+/// Counts of the tasks a remote agent ran
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RemoteState {
+    /// Tasks submitted to the server
+    pub submitted: u64,
+
+    /// Tasks that completed
+    pub completed: u64,
+
+    /// Tasks that failed or were cancelled
+    pub failed: u64,
+}
+
+impl AgentState for RemoteState {
+    fn update(&mut self, _data: Metadata) -> Result<()> {
+        Err(atlas_core::Error::State("Remote agent state cannot be updated".to_string()).into())
+    }
+
+    fn snapshot(&self) -> Result<Metadata> {
+        Ok(Metadata::from_serialize(self)?)
+    }
+}
+
+/// Agent running its tasks on a remote MCP server
+///
+/// Tasks are submitted to the server's `/tasks` endpoint and their status is
+/// long-polled until they finish, so remote agents can be registered with an
+/// orchestrator alongside local ones. Cancelling a task cancels it on the
+/// server too. Events are not forwarded; connect the processes with an
+/// [`EventBridge`](crate::EventBridge) instead.
+pub struct RemoteAgent {
+    /// Agent configuration
+    config: RemoteAgentConfig,
+
+    /// HTTP client
+    client: reqwest::Client,
+
+    /// Counts of the tasks run
+    state: Arc<RwLock<RemoteState>>,
+}
+
+impl RemoteAgent {
+    /// Get the agent configuration
+    pub fn config(&self) -> &RemoteAgentConfig {
+        &self.config
+    }
+
+    /// Submit a task without waiting for it to finish
+    pub async fn submit(&self, task_id: TaskId, params: Metadata) -> Result<TaskRecord> {
+        let request = SubmitTaskRequest {
+            task_id: Some(task_id),
+            params,
+        };
+        let response = self
+            .client
+            .post(self.endpoint("tasks"))
+            .json(&request)
+            .send()
+            .await
+            .map_err(unavailable)?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Get the status of a task, waiting up to `wait` for it to finish
+    pub async fn status(&self, task_id: TaskId, wait: Option<Duration>) -> Result<TaskRecord> {
+        let mut request = self
+            .client
+            .get(self.endpoint(&format!("tasks/{}", task_id)));
+        if let Some(wait) = wait {
+            request = request.query(&[("wait_ms", wait.as_millis() as u64)]);
+        }
+        let response = request.send().await.map_err(unavailable)?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Cancel a task if it is still running and have the server forget it,
+    /// returning its last status
+    pub async fn remove(&self, task_id: TaskId) -> Result<TaskRecord> {
+        let response = self
+            .client
+            .delete(self.endpoint(&format!("tasks/{}", task_id)))
+            .send()
+            .await
+            .map_err(unavailable)?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Wait for a submitted task to finish
+    async fn finish(&self, mut record: TaskRecord) -> Result<TaskRecord> {
+        let wait = Duration::from_millis(self.config.poll_wait_ms);
+        while !record.status.is_finished() {
+            record = self.status(record.task_id, Some(wait)).await?;
+        }
+        Ok(record)
+    }
+
+    /// Get the URL of an endpoint of the server
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.config.url.trim_end_matches('/'), path)
+    }
+}
+
+#[async_trait]
+impl Agent for RemoteAgent {
+    type Config = RemoteAgentConfig;
+    type State = RemoteState;
+
+    async fn new(config: Self::Config) -> Result<Self> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            client: reqwest::Client::new(),
+            state: Arc::new(RwLock::new(RemoteState::default())),
+        })
+    }
+
+    async fn state(&self) -> Result<Arc<RwLock<Self::State>>> {
+        Ok(self.state.clone())
+    }
+
+    async fn handle_event(&self, event: Event) -> Result<()> {
+        Err(atlas_core::Error::Agent(format!(
+            "Remote agent at {} does not take events, got {}",
+            self.config.url, event.event_type
+        ))
+        .into())
+    }
+
+    async fn execute_task(&self, task_id: TaskId, params: Metadata) -> Result<Metadata> {
+        self.execute_task_with(task_id, params, &CancellationContext::new())
+            .await
+    }
+
+    async fn execute_task_with(
+        &self,
+        task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        let record = cancel.run(self.submit(task_id, params)).await?;
+        self.state.write().await.submitted += 1;
+        let finished = cancel.run(self.finish(record)).await;
+        if let Err(e) = self.remove(task_id).await {
+            tracing::warn!(%task_id, error = %e, "Failed to remove remote task");
+        }
+        let record = match finished {
+            Ok(record) => record,
+            Err(e) => {
+                self.state.write().await.failed += 1;
+                return Err(e);
+            }
+        };
+        match (record.status, record.result, record.error) {
+            (TaskStatus::Completed, result, _) => {
+                self.state.write().await.completed += 1;
+                Ok(result.unwrap_or_default())
+            }
+            (status, _, error) => {
+                self.state.write().await.failed += 1;
+                let error = error.map(atlas_core::Error::Remote).unwrap_or_else(|| {
+                    atlas_core::Error::Agent(format!("Remote task ended {:?}", status))
+                });
+                Err(error.into())
+            }
+        }
+    }
+
+    async fn on_start(&self) -> Result<()> {
+        let response = self
+            .client
+            .get(self.endpoint(""))
+            .send()
+            .await
+            .map_err(unavailable)?;
+        check(response).await?;
+        Ok(())
+    }
+
+    async fn health(&self) -> HealthStatus {
+        let status = match self.client.get(self.endpoint("ready")).send().await {
+            Ok(response) => response.json::<HealthStatus>().await.map_err(unavailable),
+            Err(e) => Err(unavailable(e)),
+        };
+        status.unwrap_or_else(|e| {
+            HealthStatus::healthy().with_issue(HealthLevel::Unhealthy, e.to_string())
+        })
+    }
+}
+
+impl std::fmt::Debug for RemoteAgent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteAgent")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Describe a server that could not be reached
+fn unavailable(e: reqwest::Error) -> atlas_core::Error {
+    atlas_core::Error::Unavailable(format!("Remote agent unreachable: {}", e))
+}
+
+/// Fail on error responses, with an error that tells whether retrying may help
+async fn check(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = format!("Remote agent answered {}: {}", status, body);
+    let error = if status.is_server_error() {
+        atlas_core::Error::Unavailable(message)
+    } else {
+        atlas_core::Error::Agent(message)
+    };
+    Err(error.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_router, ServerCapabilities, ServerConfig, ServerState, TaskHost};
+
+    /// Local agent echoing its parameters, or failing if told to
+    #[derive(Debug)]
+    struct Echo;
+
+    #[derive(Clone, Debug)]
+    struct EchoConfig;
+
+    impl AgentConfig for EchoConfig {
+        fn validate(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Agent for Echo {
+        type Config = EchoConfig;
+        type State = RemoteState;
+
+        async fn new(_config: Self::Config) -> Result<Self> {
+            Ok(Self)
+        }
+
+        async fn state(&self) -> Result<Arc<RwLock<Self::State>>> {
+            Ok(Arc::default())
+        }
+
+        async fn handle_event(&self, _event: Event) -> Result<()> {
+            Ok(())
+        }
+
+        async fn execute_task(&self, _task_id: TaskId, params: Metadata) -> Result<Metadata> {
+            if params.contains_key("fail") {
+                return Err(atlas_core::Error::Tool("told to fail".to_string()).into());
+            }
+            Ok(params)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_agent() {
+        let config = ServerConfig {
+            name: "test".to_string(),
+            version: "0.1.0".to_string(),
+            description: None,
+            capabilities: ServerCapabilities::default(),
+        };
+        let mut state = ServerState::new(config);
+        state.tasks = Some(Arc::new(TaskHost::new(Arc::new(Echo))));
+        let router = create_router(state);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service())
+                .await
+        });
+
+        let agent = RemoteAgent::new(RemoteAgentConfig::new(url)).await.unwrap();
+        agent.on_start().await.unwrap();
+        assert!(agent.health().await.is_ready());
+
+        let mut params = Metadata::new();
+        params.insert("answer", 42);
+        let result = agent
+            .execute_task(TaskId::new(), params.clone())
+            .await
+            .unwrap();
+        assert_eq!(result.get::<u64>("answer"), Some(42));
+
+        params.insert("fail", true);
+        let e = agent.execute_task(TaskId::new(), params).await.unwrap_err();
+        let e = e.downcast::<atlas_core::Error>().unwrap();
+        assert_eq!(e.code(), atlas_core::ErrorCode::Tool);
+
+        let state = agent.state().await.unwrap();
+        let state = state.read().await;
+        assert_eq!((state.submitted, state.completed, state.failed), (2, 1, 1));
+    }
+}
@@ -1,5 +1,6 @@
 //! MCP server implementation
 
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
@@ -8,9 +9,11 @@ use axum::Router;
 use tokio::sync::RwLock;
 use tracing::{info, warn};
 
+use atlas_core::{DynAgent, HealthProbe, ValidationErrors};
+
 use crate::{
     create_router, Error, MCPTool, MCPResource, ServerConfig, ServerState,
-    ToolRegistry, ResourceRegistry,
+    TaskHost, TaskHostConfig, ToolRegistry, ResourceRegistry,
 };
 
 /// MCP server builder
@@ -19,6 +22,11 @@ pub struct ServerBuilder {
     config: Option<ServerConfig>,
     tools: Vec<(String, Box<dyn MCPTool>)>,
     resources: Vec<(String, Box<dyn MCPResource>)>,
+    health_probes: Vec<Arc<dyn HealthProbe>>,
+    agent: Option<Arc<dyn DynAgent>>,
+    task_config: TaskHostConfig,
+    #[cfg(feature = "websocket")]
+    event_bridge: Option<Arc<crate::EventBridge>>,
 }
 
 impl ServerBuilder {
@@ -51,11 +59,40 @@ impl ServerBuilder {
         self
     }
 
+    /// Include the health of an agent or service in the readiness probe
+    pub fn health_probe(mut self, probe: Arc<dyn HealthProbe>) -> Self {
+        self.health_probes.push(probe);
+        self
+    }
+
+    /// Serve an agent, accepting tasks for it under `/tasks`
+    pub fn agent(mut self, agent: Arc<dyn DynAgent>) -> Self {
+        self.agent = Some(agent);
+        self
+    }
+
+    /// Keep the served agent's tasks and limit waits for them as configured
+    pub fn task_config(mut self, config: TaskHostConfig) -> Self {
+        self.task_config = config;
+        self
+    }
+
+    /// Accept event bridge connections from other processes
+    #[cfg(feature = "websocket")]
+    pub fn event_bridge(mut self, bridge: crate::EventBridge) -> Self {
+        self.event_bridge = Some(Arc::new(bridge));
+        self
+    }
+
     /// Build the server
-    pub fn build(self) -> Result<MCPServer> {
-        let config = self.config.ok_or_else(|| {
+    ///
+    /// Fails listing every problem found if the configuration is invalid or
+    /// declares tools or resources that are not registered.
+    pub fn build(mut self) -> Result<MCPServer> {
+        let config = self.config.take().ok_or_else(|| {
             Error::ServerError("Server configuration is required".to_string())
         })?;
+        self.validate(&config)?;
 
         let mut tool_registry = ToolRegistry::new();
         for (name, tool) in self.tools {
@@ -71,13 +108,67 @@ impl ServerBuilder {
             config,
             tools: Arc::new(RwLock::new(tool_registry)),
             resources: Arc::new(RwLock::new(resource_registry)),
+            health_probes: self.health_probes,
+            tasks: self
+                .agent
+                .map(|agent| Arc::new(TaskHost::new(agent).with_config(self.task_config))),
         };
 
-        Ok(MCPServer {
+        let server = MCPServer {
             state: Arc::new(state),
             router: create_router(state),
-        })
+        };
+        #[cfg(feature = "websocket")]
+        let server = match self.event_bridge {
+            Some(bridge) => MCPServer {
+                router: server.router.merge(crate::bridge::websocket::router(bridge)),
+                ..server
+            },
+            None => server,
+        };
+
+        Ok(server)
+    }
+
+    /// Check the configuration and that its declared capabilities match the
+    /// registered tools and resources
+    fn validate(&self, config: &ServerConfig) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+        config.check(&mut errors);
+        let tools = registered_names("Tool", &self.tools, &mut errors);
+        let resources = registered_names("Resource", &self.resources, &mut errors);
+        for tool in &config.capabilities.tools {
+            errors.ensure(
+                tools.contains(tool.as_str()),
+                format!("Declared tool {} is not registered", tool),
+            );
+        }
+        for resource in &config.capabilities.resources {
+            errors.ensure(
+                resources.contains(resource.as_str()),
+                format!("Declared resource {} is not registered", resource),
+            );
+        }
+        errors.into_result()
+    }
+}
+
+/// Collect the names things are registered under, recording blank and
+/// duplicate names
+fn registered_names<'a, T>(
+    kind: &str,
+    registered: &'a [(String, T)],
+    errors: &mut ValidationErrors,
+) -> HashSet<&'a str> {
+    let mut names = HashSet::new();
+    for (name, _) in registered {
+        if name.trim().is_empty() {
+            errors.push(format!("{} names must not be blank", kind));
+        } else if !names.insert(name.as_str()) {
+            errors.push(format!("{} {} is registered more than once", kind, name));
+        }
     }
+    names
 }
 
 /// MCP server
@@ -206,4 +297,40 @@ mod tests {
         assert_eq!(tool.name(), "test_tool");
         assert_eq!(tool.description(), "A test tool");
     }
+
+    #[test]
+    fn test_builder_validation() {
+        let config = ServerConfig {
+            name: String::new(),
+            version: "0.1.0".to_string(),
+            description: None,
+            capabilities: crate::ServerCapabilities {
+                tools: vec!["test_tool".to_string(), "search".to_string()],
+                resources: vec![],
+            },
+        };
+
+        let err = ServerBuilder::new()
+            .config(config.clone())
+            .tool("test_tool", TestTool)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Configuration error: 2 problems: Server name is required; \
+             Declared tool search is not registered"
+        );
+
+        let config = ServerConfig {
+            name: "test_server".to_string(),
+            ..config
+        };
+        assert!(ServerBuilder::new()
+            .config(config)
+            .tool("test_tool", TestTool)
+            .tool("search", TestTool)
+            .build()
+            .is_ok());
+    }
 }
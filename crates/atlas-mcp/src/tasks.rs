@@ -0,0 +1,376 @@
+//! Tasks submitted to an agent hosted behind the MCP server
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use atlas_core::{CancellationContext, DynAgent, Metadata, TaskId, WireError};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, RwLock};
+
+use crate::Error;
+
+/// Progress of a submitted task
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    /// The agent is working on it
+    Running,
+
+    /// Finished with a result
+    Completed,
+
+    /// Finished with an error
+    Failed,
+
+    /// Cancelled before it finished
+    Cancelled,
+}
+
+impl TaskStatus {
+    /// Whether the task finished, one way or another
+    pub fn is_finished(&self) -> bool {
+        *self != TaskStatus::Running
+    }
+}
+
+/// Status of a submitted task, with its result once it finished
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TaskRecord {
+    /// ID of the task
+    pub task_id: TaskId,
+
+    /// Progress of the task
+    pub status: TaskStatus,
+
+    /// Result, if it completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Metadata>,
+
+    /// Error, if it failed or was cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<WireError>,
+}
+
+/// Request submitting a task
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SubmitTaskRequest {
+    /// ID to run the task under, or a new one if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<TaskId>,
+
+    /// Task parameters
+    #[serde(default)]
+    pub params: Metadata,
+}
+
+/// Query of a task's status
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TaskQuery {
+    /// Wait up to this long for the task to finish before answering
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_ms: Option<u64>,
+}
+
+/// How many tasks a host keeps, for how long, and how long status queries
+/// may wait
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TaskHostConfig {
+    /// How long finished tasks are kept for their submitters, in
+    /// milliseconds
+    pub retention_ms: u64,
+
+    /// Most tasks kept at once, running or finished; the oldest finished
+    /// ones make room for new ones
+    pub max_tasks: usize,
+
+    /// Longest a status query may wait for its task to finish, in
+    /// milliseconds
+    pub max_wait_ms: u64,
+}
+
+impl Default for TaskHostConfig {
+    fn default() -> Self {
+        Self {
+            retention_ms: 600_000,
+            max_tasks: 10_000,
+            max_wait_ms: 30_000,
+        }
+    }
+}
+
+/// Task running or finished, and how to cancel it
+struct Hosted {
+    /// Latest record of the task
+    record: watch::Sender<TaskRecord>,
+
+    /// Cancels the task
+    cancel: CancellationContext,
+
+    /// When the task finished, if it did
+    finished_at: Option<Instant>,
+}
+
+/// Runs the tasks submitted to an agent and keeps their records until they
+/// are removed, expire or make room for newer ones
+pub struct TaskHost {
+    /// Agent running the tasks
+    agent: Arc<dyn DynAgent>,
+
+    /// Submitted tasks, by ID
+    tasks: Arc<RwLock<HashMap<TaskId, Hosted>>>,
+
+    /// Retention and limits
+    config: TaskHostConfig,
+}
+
+impl TaskHost {
+    /// Host an agent
+    pub fn new(agent: Arc<dyn DynAgent>) -> Self {
+        Self {
+            agent,
+            tasks: Arc::new(RwLock::new(HashMap::new())),
+            config: TaskHostConfig::default(),
+        }
+    }
+
+    /// Keep tasks and limit waits as configured
+    pub fn with_config(mut self, config: TaskHostConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Get the hosted agent
+    pub fn agent(&self) -> &Arc<dyn DynAgent> {
+        &self.agent
+    }
+
+    /// Start running a task, returning its record
+    ///
+    /// Submitting a task under the ID of a known task returns that task's
+    /// record instead, so retried submissions do not run twice. Fails if
+    /// the host keeps as many tasks as it may and all of them are running.
+    pub async fn submit(&self, task_id: TaskId, params: Metadata) -> Result<TaskRecord, Error> {
+        let mut tasks = self.tasks.write().await;
+        if let Some(hosted) = tasks.get(&task_id) {
+            return Ok(hosted.record.borrow().clone());
+        }
+        self.make_room(&mut tasks)?;
+        let record = TaskRecord {
+            task_id,
+            status: TaskStatus::Running,
+            result: None,
+            error: None,
+        };
+        let (sender, _) = watch::channel(record.clone());
+        let cancel = CancellationContext::new();
+        tasks.insert(
+            task_id,
+            Hosted {
+                record: sender.clone(),
+                cancel: cancel.clone(),
+                finished_at: None,
+            },
+        );
+
+        let agent = self.agent.clone();
+        let hosted = self.tasks.clone();
+        tokio::spawn(async move {
+            // Run the task apart so a panic fails it instead of leaving it
+            // running forever
+            let work = {
+                let cancel = cancel.clone();
+                tokio::spawn(async move { agent.execute_task_with(task_id, params, &cancel).await })
+            };
+            let result = work.await.unwrap_or_else(|e| {
+                tracing::error!(%task_id, error = %e, "Task panicked");
+                Err(atlas_core::Error::Agent(format!("Task {} panicked", task_id)).into())
+            });
+            sender.send_modify(|record| {
+                if record.status.is_finished() {
+                    return;
+                }
+                match result {
+                    Ok(result) => {
+                        record.status = TaskStatus::Completed;
+                        record.result = Some(result);
+                    }
+                    Err(e) => {
+                        record.status = if cancel.is_cancelled() {
+                            TaskStatus::Cancelled
+                        } else {
+                            TaskStatus::Failed
+                        };
+                        record.error = Some(atlas_core::Error::Other(e).into());
+                    }
+                }
+            });
+            if let Some(hosted) = hosted.write().await.get_mut(&task_id) {
+                hosted.finished_at.get_or_insert_with(Instant::now);
+            }
+        });
+        Ok(record)
+    }
+
+    /// Get the record of a task, waiting up to `wait`, at most the
+    /// configured longest wait, for it to finish
+    pub async fn get(&self, task_id: TaskId, wait: Option<Duration>) -> Option<TaskRecord> {
+        let mut receiver = self.tasks.read().await.get(&task_id)?.record.subscribe();
+        let longest = Duration::from_millis(self.config.max_wait_ms);
+        if let Some(wait) = wait.map(|wait| wait.min(longest)) {
+            // Timing out just answers with the task still running
+            let _ = tokio::time::timeout(
+                wait,
+                receiver.wait_for(|record| record.status.is_finished()),
+            )
+            .await;
+        }
+        let record = receiver.borrow().clone();
+        Some(record)
+    }
+
+    /// Cancel a task if it is still running and forget it, returning its
+    /// last record
+    pub async fn remove(&self, task_id: TaskId) -> Option<TaskRecord> {
+        let hosted = self.tasks.write().await.remove(&task_id)?;
+        hosted.cancel.cancel();
+        hosted.record.send_modify(|record| {
+            if !record.status.is_finished() {
+                record.status = TaskStatus::Cancelled;
+                let e = atlas_core::Error::Cancelled(format!("Task {} was cancelled", task_id));
+                record.error = Some(e.into());
+            }
+        });
+        let record = hosted.record.borrow().clone();
+        Some(record)
+    }
+
+    /// Forget the tasks finished longer ago than the retention, then the
+    /// oldest finished ones until a new task fits
+    fn make_room(&self, tasks: &mut HashMap<TaskId, Hosted>) -> Result<(), Error> {
+        let retention = Duration::from_millis(self.config.retention_ms);
+        tasks.retain(|_, hosted| {
+            !matches!(hosted.finished_at, Some(finished_at) if finished_at.elapsed() >= retention)
+        });
+        while tasks.len() >= self.config.max_tasks {
+            let oldest = tasks
+                .iter()
+                .filter_map(|(task_id, hosted)| Some((*task_id, hosted.finished_at?)))
+                .min_by_key(|(_, finished_at)| *finished_at)
+                .map(|(task_id, _)| task_id);
+            let Some(oldest) = oldest else {
+                return Err(Error::ServerError(format!(
+                    "All {} tasks the host keeps are running",
+                    tasks.len()
+                )));
+            };
+            tasks.remove(&oldest);
+        }
+        Ok(())
+    }
+
+    /// Number of tasks running or kept
+    pub async fn len(&self) -> usize {
+        self.tasks.read().await.len()
+    }
+
+    /// Whether no tasks are running or kept
+    pub async fn is_empty(&self) -> bool {
+        self.tasks.read().await.is_empty()
+    }
+}
+
+impl std::fmt::Debug for TaskHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskHost")
+            .field("agent", &self.agent.type_name())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::testing::FakeAgent;
+    use atlas_core::ErrorCode;
+
+    /// Agent echoing its parameters after a delay, or failing if told to
+    fn echo() -> Arc<FakeAgent> {
+        Arc::new(FakeAgent::new(|params: Metadata| async move {
+            if params.contains_key("panic") {
+                panic!("told to panic");
+            }
+            let delay = params.get("delay_ms").unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            if params.contains_key("fail") {
+                return Err(atlas_core::Error::Tool("told to fail".to_string()).into());
+            }
+            Ok(params)
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_task_lifecycle() {
+        let host = TaskHost::new(echo());
+        let wait = Some(Duration::from_secs(1));
+
+        let mut params = Metadata::new();
+        params.insert("delay_ms", 10);
+        let task_id = TaskId::new();
+        let record = host.submit(task_id, params.clone()).await.unwrap();
+        assert_eq!(record.status, TaskStatus::Running);
+        let record = host.get(task_id, wait).await.unwrap();
+        assert_eq!(record.status, TaskStatus::Completed);
+        assert_eq!(record.result.unwrap().get::<u64>("delay_ms"), Some(10));
+        assert!(host.remove(task_id).await.is_some());
+        assert!(host.is_empty().await);
+
+        params.insert("fail", true);
+        let failing = TaskId::new();
+        host.submit(failing, params.clone()).await.unwrap();
+        let record = host.get(failing, wait).await.unwrap();
+        assert_eq!(record.status, TaskStatus::Failed);
+        assert_eq!(record.error.unwrap().code, ErrorCode::Tool);
+
+        params.insert("delay_ms", 10_000);
+        let slow = TaskId::new();
+        host.submit(slow, params).await.unwrap();
+        let record = host.remove(slow).await.unwrap();
+        assert_eq!(record.status, TaskStatus::Cancelled);
+        assert!(host.get(slow, None).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retention_and_limits() {
+        let host = TaskHost::new(echo()).with_config(TaskHostConfig {
+            retention_ms: 50,
+            max_tasks: 3,
+            max_wait_ms: 100,
+        });
+        let mut params = Metadata::new();
+        params.insert("panic", true);
+        let panicking = TaskId::new();
+        host.submit(panicking, params).await.unwrap();
+        let record = host.get(panicking, Some(Duration::from_secs(1))).await;
+        assert_eq!(record.unwrap().status, TaskStatus::Failed);
+
+        // Waits are cut short, and finished tasks expire
+        let mut slow = Metadata::new();
+        slow.insert("delay_ms", 10_000);
+        let running = TaskId::new();
+        host.submit(running, slow.clone()).await.unwrap();
+        let started = Instant::now();
+        let record = host.get(running, Some(Duration::from_secs(10))).await;
+        assert_eq!(record.unwrap().status, TaskStatus::Running);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        host.submit(TaskId::new(), slow.clone()).await.unwrap();
+        assert!(host.get(panicking, None).await.is_none());
+        assert_eq!(host.len().await, 2);
+
+        // Running tasks are never dropped to make room
+        host.submit(TaskId::new(), slow.clone()).await.unwrap();
+        assert!(host.submit(TaskId::new(), slow).await.is_err());
+        assert_eq!(host.len().await, 3);
+    }
+}
@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use atlas_core::Metadata;
+use atlas_core::{Metadata, ToolDescriptor};
 
 /// MCP request types
 #[derive(Debug, Clone, Deserialize)]
@@ -93,6 +93,26 @@ pub struct ToolInfo {
     pub input_schema: Option<Value>,
 }
 
+impl From<&ToolDescriptor> for ToolInfo {
+    fn from(descriptor: &ToolDescriptor) -> Self {
+        Self {
+            name: descriptor.name.clone(),
+            description: descriptor.description.clone(),
+            input_schema: descriptor.input_schema.clone(),
+        }
+    }
+}
+
+impl From<ToolDescriptor> for ToolInfo {
+    fn from(descriptor: ToolDescriptor) -> Self {
+        Self {
+            name: descriptor.name,
+            description: descriptor.description,
+            input_schema: descriptor.input_schema,
+        }
+    }
+}
+
 /// Resource information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceInfo {
@@ -0,0 +1,234 @@
+//! Which hosted agents may invoke which tools
+
+use anyhow::Result;
+use atlas_agent::{Envelope, ExecuteTask};
+use atlas_core::{AgentId, TopicPattern};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::workflow::TOOL_KEY;
+
+/// Topic a denied tool invocation is reported on
+pub const TOOL_DENIED: &str = "tool.denied";
+
+/// Name denials of anonymous invocations are reported under
+pub const ANONYMOUS: &str = "(anonymous)";
+
+/// Whether an invocation is let through
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// Let the invocation through
+    Allow,
+
+    /// Refuse the invocation
+    Deny,
+}
+
+/// Rule granting or refusing agents the use of tools
+#[derive(Clone, Debug)]
+pub struct ToolRule {
+    /// Agents the rule applies to
+    agent: TopicPattern,
+
+    /// Tools the rule applies to
+    tool: TopicPattern,
+
+    /// Whether matching invocations are let through
+    access: Access,
+}
+
+impl ToolRule {
+    /// Create a rule for the agents and tools matching two patterns
+    pub fn new(access: Access, agent: &str, tool: &str) -> Result<Self> {
+        Ok(Self {
+            agent: TopicPattern::parse(agent)?,
+            tool: TopicPattern::parse(tool)?,
+            access,
+        })
+    }
+
+    /// Whether the rule applies to an agent invoking a tool
+    pub fn matches(&self, agent: &str, tool: &str) -> bool {
+        self.agent.matches(agent) && self.tool.matches(tool)
+    }
+
+    /// Get whether matching invocations are let through
+    pub fn access(&self) -> Access {
+        self.access
+    }
+}
+
+/// Access control list for the tools hosted agents offer each other
+///
+/// Agent names and tool names are matched like topics, so `#` stands for
+/// every agent or tool and `fs.*` for the tools directly under `fs`. A rule
+/// denying an invocation wins over any allowing it; invocations no rule
+/// matches get the default access, which denies them unless changed. Work
+/// done outside every hosted agent invokes tools anonymously, and only
+/// rules for every agent (`#`) apply to it.
+#[derive(Clone, Debug)]
+pub struct ToolAcl {
+    /// Rules, in the order they were added
+    rules: Vec<ToolRule>,
+
+    /// Access of invocations no rule matches
+    default: Access,
+}
+
+impl Default for ToolAcl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ToolAcl {
+    /// Create a list denying every invocation
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            default: Access::Deny,
+        }
+    }
+
+    /// Set the access of invocations no rule matches
+    pub fn with_default(mut self, access: Access) -> Self {
+        self.default = access;
+        self
+    }
+
+    /// Add a rule
+    pub fn with_rule(mut self, rule: ToolRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Let the agents matching a pattern invoke the tools matching another
+    pub fn allow(self, agent: &str, tool: &str) -> Result<Self> {
+        Ok(self.with_rule(ToolRule::new(Access::Allow, agent, tool)?))
+    }
+
+    /// Refuse the agents matching a pattern the tools matching another
+    pub fn deny(self, agent: &str, tool: &str) -> Result<Self> {
+        Ok(self.with_rule(ToolRule::new(Access::Deny, agent, tool)?))
+    }
+
+    /// Get the rules, in the order they were added
+    pub fn rules(&self) -> &[ToolRule] {
+        &self.rules
+    }
+
+    /// Decide whether an agent may invoke a tool
+    pub fn check(&self, agent: &str, tool: &str) -> Access {
+        self.decide(|rule| rule.matches(agent, tool))
+    }
+
+    /// Decide whether work done outside every hosted agent may invoke a tool
+    pub fn check_anonymous(&self, tool: &str) -> Access {
+        self.decide(|rule| rule.agent.as_str() == "#" && rule.tool.matches(tool))
+    }
+
+    /// Check a task sent as an [`ExecuteTask`] message, for
+    /// [`Agent::accept_tasks_with`](atlas_agent::Agent::accept_tasks_with)
+    ///
+    /// `name` resolves the sender to the name rules match, and senders it
+    /// cannot resolve invoke anonymously. Senders are as claimed by the
+    /// message, so a bus open to untrusted publishers must be secured by
+    /// its broker.
+    pub fn authorize_message(
+        &self,
+        envelope: &Envelope<ExecuteTask>,
+        name: impl Fn(AgentId) -> Option<String>,
+    ) -> Result<()> {
+        let Some(tool) = envelope.message.params.get::<String>(TOOL_KEY) else {
+            return Ok(());
+        };
+        let sender = envelope.from.and_then(name);
+        let access = match &sender {
+            Some(sender) => self.check(sender, &tool),
+            None => self.check_anonymous(&tool),
+        };
+        match access {
+            Access::Allow => Ok(()),
+            Access::Deny => {
+                let sender = sender.unwrap_or_else(|| ANONYMOUS.to_string());
+                Err(Error::ToolDenied(sender, tool).into())
+            }
+        }
+    }
+
+    /// Decide the access of the invocations a filter picks rules for
+    fn decide(&self, applies: impl Fn(&ToolRule) -> bool) -> Access {
+        let mut matching = self
+            .rules
+            .iter()
+            .filter(|rule| applies(rule))
+            .map(|rule| rule.access)
+            .peekable();
+        if matching.peek().is_none() {
+            self.default
+        } else if matching.any(|access| access == Access::Deny) {
+            Access::Deny
+        } else {
+            Access::Allow
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::{Metadata, TaskId};
+
+    #[test]
+    fn test_tool_acl() {
+        let acl = ToolAcl::new()
+            .allow("#", "search")
+            .unwrap()
+            .allow("admin", "#")
+            .unwrap()
+            .deny("#", "fs.delete")
+            .unwrap();
+        assert_eq!(acl.check("intern", "search"), Access::Allow);
+        assert_eq!(acl.check("intern", "fs.write"), Access::Deny);
+        assert_eq!(acl.check("admin", "fs.write"), Access::Allow);
+        // Denials win over rules allowing the invocation
+        assert_eq!(acl.check("admin", "fs.delete"), Access::Deny);
+
+        let open = acl.with_default(Access::Allow);
+        assert_eq!(open.check("intern", "fs.write"), Access::Allow);
+        assert!(ToolAcl::new().allow("intern", "fs.*x").is_err());
+    }
+
+    #[test]
+    fn test_anonymous_invocations() {
+        let acl = ToolAcl::new()
+            .allow("#", "search")
+            .unwrap()
+            .allow("admin", "#")
+            .unwrap();
+        assert_eq!(acl.check_anonymous("search"), Access::Allow);
+        // Rules naming agents do not apply to anonymous work
+        assert_eq!(acl.check_anonymous("fs.delete"), Access::Deny);
+
+        let admin = AgentId::new();
+        let names = |id: AgentId| (id == admin).then(|| "admin".to_string());
+        let mut params = Metadata::new();
+        params.insert(TOOL_KEY, "fs.delete");
+        let mut envelope = Envelope {
+            from: Some(admin),
+            to: AgentId::new(),
+            message: ExecuteTask {
+                task_id: TaskId::new(),
+                params,
+            },
+        };
+        assert!(acl.authorize_message(&envelope, names).is_ok());
+        envelope.from = Some(AgentId::new());
+        let err = acl.authorize_message(&envelope, names).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ToolDenied(agent, _)) if agent == ANONYMOUS
+        ));
+    }
+}
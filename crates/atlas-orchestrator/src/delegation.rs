@@ -0,0 +1,665 @@
+//! Supervisors decomposing goals and delegating the parts to workers
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+pub use atlas_agent::TASK_PROGRESS;
+use atlas_agent::{PlanStep, Planner, TaskPlan};
+use atlas_core::{
+    CancellationContext, Cause, EventBus, EventStream, Metadata, TaskId, ValidationErrors,
+};
+use atlas_mcp::ToolInfo;
+use futures::future::join_all;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::error::Error;
+use crate::team::GOAL_KEY;
+use crate::Orchestrator;
+
+/// Parameter holding the plan step a worker performs
+pub const TASK_KEY: &str = "task";
+
+/// Parameter holding the results of the steps a step depends on, by step ID
+pub const DEPENDENCIES_KEY: &str = "dependencies";
+
+/// Parameter holding the results of every step, by step ID, for the
+/// synthesizer
+pub const RESULTS_KEY: &str = "results";
+
+/// Hosted agent a supervisor delegates work to
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DelegateWorker {
+    /// Name of the agent within the orchestrator, which plan steps assign
+    /// work by
+    pub name: String,
+
+    /// What the worker does, shown to the planner
+    pub description: String,
+}
+
+/// One attempt of a worker at a plan step
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Assignment {
+    /// ID of the plan step
+    pub step: String,
+
+    /// Name of the worker
+    pub worker: String,
+
+    /// Attempt at the step, from 1
+    pub attempt: u32,
+
+    /// Task the worker ran
+    pub task_id: TaskId,
+
+    /// Progress the worker reported, in order
+    pub progress: Vec<Metadata>,
+
+    /// Result of the task, if it succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Metadata>,
+
+    /// Error of the task, if it failed or stalled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of a supervisor working on a goal
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DelegationOutcome {
+    /// Goal worked on
+    pub goal: String,
+
+    /// Plan the goal was decomposed into
+    pub plan: TaskPlan,
+
+    /// Final answer of the synthesizer
+    pub answer: Metadata,
+
+    /// Result of every step, by step ID
+    pub results: HashMap<String, Metadata>,
+
+    /// Every attempt at a step, in the order they finished
+    pub assignments: Vec<Assignment>,
+}
+
+/// Supervisor decomposing goals with a [`Planner`] and delegating the steps
+/// to hosted workers
+///
+/// [`Delegator::run`] has the [`Orchestrator`] hosting the workers and the
+/// synthesizer run their work, so their tasks are authorized and their
+/// panics reported like any other. Workers are described to the planner as tools, so each step can name the
+/// worker it is for; steps naming no known worker are spread over the
+/// workers in turn. Steps run once the steps they depend on have finished,
+/// with the step's parameters plus the [`GOAL_KEY`], the step under
+/// [`TASK_KEY`] and the upstream results under [`DEPENDENCIES_KEY`]. A step
+/// whose worker fails, or reports no progress on [`TASK_PROGRESS`] within
+/// the stall timeout, is reassigned to another worker. The synthesizer
+/// finally receives the goal and every result under [`RESULTS_KEY`] and
+/// answers for the supervisor.
+#[derive(Clone)]
+pub struct Delegator {
+    /// Name of the supervisor, for logs
+    name: String,
+
+    /// Planner decomposing goals into steps
+    planner: Arc<dyn Planner>,
+
+    /// Name of the hosted agent combining the results of the steps into
+    /// the answer
+    synthesizer: String,
+
+    /// Workers, in the order they were added
+    workers: Vec<DelegateWorker>,
+
+    /// Bus the workers report progress on
+    event_bus: Option<Arc<dyn EventBus>>,
+
+    /// Attempts at a step before giving up on the goal
+    max_attempts: u32,
+
+    /// Time a worker may go without reporting progress before its step is
+    /// reassigned
+    stall_timeout: Option<Duration>,
+}
+
+impl Delegator {
+    /// Create a supervisor with no workers, answering through a hosted
+    /// synthesizer
+    pub fn new(
+        name: impl Into<String>,
+        planner: Arc<dyn Planner>,
+        synthesizer: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            planner,
+            synthesizer: synthesizer.into(),
+            workers: Vec::new(),
+            event_bus: None,
+            max_attempts: 3,
+            stall_timeout: None,
+        }
+    }
+
+    /// Add a hosted agent as a worker
+    pub fn with_worker(mut self, name: impl Into<String>, description: impl Into<String>) -> Self {
+        self.workers.push(DelegateWorker {
+            name: name.into(),
+            description: description.into(),
+        });
+        self
+    }
+
+    /// Follow the progress workers report on a bus
+    pub fn with_event_bus(mut self, event_bus: Arc<dyn EventBus>) -> Self {
+        self.event_bus = Some(event_bus);
+        self
+    }
+
+    /// Set the attempts at a step before giving up on the goal
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Reassign steps whose worker reports no progress for this long, as
+    /// heard on the bus set with [`Delegator::with_event_bus`]
+    ///
+    /// Atlas agents report progress every
+    /// [`DEFAULT_PROGRESS_INTERVAL`](atlas_agent::DEFAULT_PROGRESS_INTERVAL)
+    /// unless configured otherwise, so the timeout should be several times
+    /// their interval.
+    pub fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Get the name of the supervisor
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the workers, in the order they were added
+    pub fn workers(&self) -> &[DelegateWorker] {
+        &self.workers
+    }
+
+    /// Work on a goal through the workers hosted by an orchestrator
+    ///
+    /// Fails if the workers or synthesizer are not hosted, if the goal
+    /// cannot be planned, or once every attempt at a step has failed, with
+    /// the error of the last.
+    pub async fn run(
+        &self,
+        orchestrator: &Orchestrator,
+        goal: impl Into<String>,
+    ) -> Result<DelegationOutcome> {
+        self.validate(orchestrator)?;
+        let goal = goal.into();
+        let tools: Vec<ToolInfo> = self
+            .workers
+            .iter()
+            .map(|worker| ToolInfo {
+                name: worker.name.clone(),
+                description: worker.description.clone(),
+                input_schema: None,
+            })
+            .collect();
+        let plan = self.planner.plan(&goal, &tools).await?;
+        let layers = plan.layers()?;
+        tracing::info!(
+            supervisor = %self.name,
+            goal = %goal,
+            steps = plan.steps.len(),
+            "Delegation started"
+        );
+
+        let monitor = match &self.event_bus {
+            Some(bus) => {
+                bus.register_topic(TASK_PROGRESS).await?;
+                Monitor::start(bus.subscribe(TASK_PROGRESS).await?)
+            }
+            None => Monitor::default(),
+        };
+
+        let mut results: HashMap<String, Metadata> = HashMap::new();
+        let mut assignments = Vec::new();
+        let mut turn = 0;
+        for layer in layers {
+            let runs = layer.into_iter().map(|step| {
+                let dependencies: HashMap<&String, &Metadata> = step
+                    .depends_on
+                    .iter()
+                    .filter_map(|dep| results.get(dep).map(|r| (dep, r)))
+                    .collect();
+                let mut params = Metadata::from(step.task.parameters.clone());
+                params.insert(GOAL_KEY, &goal);
+                params.insert(TASK_KEY, &step.task);
+                params.insert(DEPENDENCIES_KEY, dependencies);
+                turn += 1;
+                self.delegate(orchestrator, step, params, turn - 1, &monitor)
+            });
+            for (step, result, attempts) in join_all(runs).await {
+                assignments.extend(attempts);
+                results.insert(step, result?);
+            }
+        }
+        monitor.stop();
+
+        let mut params = Metadata::new();
+        params.insert(GOAL_KEY, &goal);
+        params.insert(RESULTS_KEY, &results);
+        let answer = orchestrator
+            .execute(&self.synthesizer, TaskId::new(), params)
+            .await?;
+        tracing::info!(
+            supervisor = %self.name,
+            attempts = assignments.len(),
+            "Delegation finished"
+        );
+        Ok(DelegationOutcome {
+            goal,
+            plan,
+            answer,
+            results,
+            assignments,
+        })
+    }
+
+    /// Run a step, reassigning it to the next worker after each failure,
+    /// and return its result with every attempt at it
+    async fn delegate(
+        &self,
+        orchestrator: &Orchestrator,
+        step: &PlanStep,
+        params: Metadata,
+        turn: usize,
+        monitor: &Monitor,
+    ) -> (String, Result<Metadata>, Vec<Assignment>) {
+        let first = step
+            .tool
+            .as_ref()
+            .and_then(|tool| self.workers.iter().position(|w| &w.name == tool))
+            .unwrap_or(turn % self.workers.len());
+        let mut attempts = Vec::new();
+        let mut last_error = None;
+
+        for attempt in 1..=self.max_attempts {
+            let worker = &self.workers[(first + attempt as usize - 1) % self.workers.len()];
+            let task_id = TaskId::new();
+            let (sender, mut progress) = mpsc::unbounded_channel();
+            monitor.watch(task_id, sender);
+            let cancel = CancellationContext::new();
+            let work = Cause::task(task_id.into()).scope(orchestrator.execute_with(
+                &worker.name,
+                task_id,
+                params.clone(),
+                &cancel,
+            ));
+            tokio::pin!(work);
+
+            let mut reports = Vec::new();
+            let result = loop {
+                let report = async {
+                    match self.stall_timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, progress.recv()).await,
+                        None => Ok(progress.recv().await),
+                    }
+                };
+                tokio::select! {
+                    result = &mut work => break result,
+                    report = report => match report {
+                        Ok(Some(report)) => reports.push(report),
+                        Ok(None) => {}
+                        Err(_) => {
+                            cancel.cancel();
+                            break Err(atlas_core::Error::Timeout(format!(
+                                "Worker {} reported no progress on step {} for {:?}",
+                                worker.name,
+                                step.id,
+                                self.stall_timeout.unwrap_or_default()
+                            ))
+                            .into());
+                        }
+                    },
+                }
+            };
+            monitor.forget(task_id);
+
+            let mut assignment = Assignment {
+                step: step.id.clone(),
+                worker: worker.name.clone(),
+                attempt,
+                task_id,
+                progress: reports,
+                output: None,
+                error: None,
+            };
+            match result {
+                Ok(output) => {
+                    assignment.output = Some(output.clone());
+                    attempts.push(assignment);
+                    return (step.id.clone(), Ok(output), attempts);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        supervisor = %self.name,
+                        step = %step.id,
+                        worker = %worker.name,
+                        attempt,
+                        error = %e,
+                        "Step failed, reassigning"
+                    );
+                    assignment.error = Some(e.to_string());
+                    attempts.push(assignment);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        let reason = last_error.map_or_else(|| "no attempts".to_string(), |e| e.to_string());
+        let error = Error::StepFailed(step.id.clone(), reason).into();
+        (step.id.clone(), Err(error), attempts)
+    }
+
+    /// Check that there are hosted workers to delegate to
+    fn validate(&self, orchestrator: &Orchestrator) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+        errors.ensure(
+            !self.workers.is_empty(),
+            format!("Supervisor {} has no workers", self.name),
+        );
+        let agents = self.workers.iter().map(|w| &w.name);
+        for agent in agents.chain([&self.synthesizer]) {
+            errors.ensure(
+                orchestrator.agent(agent).is_some(),
+                format!("Supervisor {} agent {} is not hosted", self.name, agent),
+            );
+        }
+        errors.ensure(self.max_attempts > 0, "Steps need at least one attempt");
+        errors.ensure(
+            self.stall_timeout.is_none() || self.event_bus.is_some(),
+            "A stall timeout needs an event bus to hear progress on",
+        );
+        errors.into_result()
+    }
+}
+
+impl std::fmt::Debug for Delegator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Delegator")
+            .field("name", &self.name)
+            .field("synthesizer", &self.synthesizer)
+            .field("workers", &self.workers)
+            .field("max_attempts", &self.max_attempts)
+            .field("stall_timeout", &self.stall_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Task progress reports waiting to be handed to the steps they are about
+type Watchers = Arc<Mutex<HashMap<Uuid, mpsc::UnboundedSender<Metadata>>>>;
+
+/// Follower of the progress reported on a bus, handing each report to the
+/// step running its task
+#[derive(Default)]
+struct Monitor {
+    /// Steps waiting for reports, by task ID
+    watchers: Watchers,
+
+    /// Task reading the reports, if there is a bus
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Start reading reports from a subscription
+    fn start(mut reports: EventStream) -> Self {
+        let watchers = Watchers::default();
+        let reader = tokio::spawn({
+            let watchers = watchers.clone();
+            async move {
+                while let Some(event) = reports.next().await {
+                    let Some(task) = event.caused_by else {
+                        continue;
+                    };
+                    let watchers = watchers.lock().unwrap_or_else(PoisonError::into_inner);
+                    if let Some(watcher) = watchers.get(&task) {
+                        let _ = watcher.send(event.payload);
+                    }
+                }
+            }
+        });
+        Self {
+            watchers,
+            reader: Some(reader),
+        }
+    }
+
+    /// Hand the reports about a task to a step
+    fn watch(&self, task_id: TaskId, sender: mpsc::UnboundedSender<Metadata>) {
+        self.lock().insert(task_id.into(), sender);
+    }
+
+    /// Stop handing on the reports about a task
+    fn forget(&self, task_id: TaskId) {
+        self.lock().remove(&Uuid::from(task_id));
+    }
+
+    /// Stop reading reports
+    fn stop(&self) {
+        if let Some(reader) = &self.reader {
+            reader.abort();
+        }
+    }
+
+    /// Lock the steps waiting for reports
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, mpsc::UnboundedSender<Metadata>>> {
+        self.watchers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AgentRegistration;
+    use async_trait::async_trait;
+    use atlas_agent::TaskConfig;
+    use atlas_core::testing::FakeAgent;
+    use atlas_core::{Event, InMemoryEventBus};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Planner returning the same plan for every goal
+    struct FixedPlanner(TaskPlan);
+
+    #[async_trait]
+    impl Planner for FixedPlanner {
+        async fn plan(&self, _goal: &str, tools: &[ToolInfo]) -> Result<TaskPlan> {
+            assert!(tools.iter().any(|t| t.name == "flaky"));
+            Ok(self.0.clone())
+        }
+    }
+
+    /// Agent answering tasks with a closure of their parameters
+    fn worker(answer: impl Fn(&Metadata) -> Result<Metadata> + Send + Sync + 'static) -> FakeAgent {
+        FakeAgent::new(move |params| std::future::ready(answer(&params)))
+    }
+
+    /// Agent reporting progress on a bus, then adding up its inputs or, if
+    /// told to, hanging until cancelled
+    fn reporter(bus: Arc<dyn EventBus>, hang: bool) -> FakeAgent {
+        FakeAgent::new(move |params| {
+            let bus = bus.clone();
+            async move {
+                let mut payload = Metadata::new();
+                payload.insert("percentage", 50);
+                let report = Event::new(TASK_PROGRESS, payload).with_current_cause();
+                bus.publish(report).await?;
+                if hang {
+                    std::future::pending::<()>().await;
+                }
+                adder(&params)
+            }
+        })
+    }
+
+    /// Host and start the synthesizer and workers under their names
+    async fn host<const N: usize>(workers: [(&str, FakeAgent); N]) -> Orchestrator {
+        let orchestrator = Orchestrator::new();
+        for (name, agent) in workers.into_iter().chain([("writer", synthesizer())]) {
+            orchestrator
+                .register(AgentRegistration::new(name, Arc::new(agent)))
+                .unwrap();
+        }
+        orchestrator.start_all().await.unwrap();
+        orchestrator
+    }
+
+    fn step(id: &str, tool: Option<&str>, deps: &[&str]) -> PlanStep {
+        PlanStep {
+            id: id.to_string(),
+            task: TaskConfig {
+                name: id.to_string(),
+                ..Default::default()
+            },
+            tool: tool.map(str::to_string),
+            depends_on: deps.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    fn output(value: impl Serialize) -> Metadata {
+        let mut output = Metadata::new();
+        output.insert("value", value);
+        output
+    }
+
+    fn plan() -> Arc<dyn Planner> {
+        Arc::new(FixedPlanner(TaskPlan {
+            goal: "sum".to_string(),
+            steps: vec![
+                step("a", Some("flaky"), &[]),
+                step("b", Some("steady"), &[]),
+                step("total", None, &["a", "b"]),
+            ],
+        }))
+    }
+
+    fn synthesizer() -> FakeAgent {
+        worker(|params| {
+            let results: HashMap<String, Metadata> = params.get(RESULTS_KEY).unwrap();
+            Ok(output(results["total"].get::<u32>("value").unwrap()))
+        })
+    }
+
+    /// Worker adding up the values of the steps it depends on, plus one
+    fn adder(params: &Metadata) -> Result<Metadata> {
+        let dependencies: HashMap<String, Metadata> = params.get(DEPENDENCIES_KEY).unwrap();
+        let sum: u32 = dependencies
+            .values()
+            .map(|d| d.get::<u32>("value").unwrap())
+            .sum();
+        Ok(output(sum + 1))
+    }
+
+    #[tokio::test]
+    async fn test_delegation() {
+        let failures = Arc::new(AtomicU32::new(0));
+        let flaky = {
+            let failures = failures.clone();
+            worker(move |params| {
+                failures.fetch_add(1, Ordering::SeqCst);
+                assert_eq!(params.get::<String>(GOAL_KEY).as_deref(), Some("add up"));
+                Err(atlas_core::Error::Tool("out of order".to_string()).into())
+            })
+        };
+        let orchestrator = host([("flaky", flaky), ("steady", worker(adder))]).await;
+        let supervisor = Delegator::new("boss", plan(), "writer")
+            .with_worker("flaky", "always fails")
+            .with_worker("steady", "adds up its inputs");
+
+        let outcome = supervisor.run(&orchestrator, "add up").await.unwrap();
+        // a and b each count 1, total adds them up and counts 1
+        assert_eq!(outcome.answer.get::<u32>("value"), Some(3));
+        // a names flaky, and total is the third step so its turn is flaky's
+        assert_eq!(failures.load(Ordering::SeqCst), 2);
+        let attempts: Vec<_> = outcome
+            .assignments
+            .iter()
+            .map(|a| (a.step.as_str(), a.worker.as_str(), a.error.is_some()))
+            .collect();
+        assert_eq!(
+            attempts,
+            [
+                ("a", "flaky", true),
+                ("a", "steady", false),
+                ("b", "steady", false),
+                ("total", "flaky", true),
+                ("total", "steady", false),
+            ]
+        );
+
+        let err = supervisor
+            .clone()
+            .with_max_attempts(1)
+            .run(&orchestrator, "add up")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Step a failed"));
+        let err = Delegator::new("idle", plan(), "writer")
+            .run(&orchestrator, "add up")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has no workers"));
+        let err = Delegator::new("boss", plan(), "missing")
+            .with_worker("steady", "adds up its inputs")
+            .run(&orchestrator, "add up")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("agent missing is not hosted"));
+        let err = supervisor
+            .with_stall_timeout(Duration::from_secs(1))
+            .run(&orchestrator, "add up")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("needs an event bus"));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_worker() {
+        let bus: Arc<dyn EventBus> = Arc::new(InMemoryEventBus::new());
+        let orchestrator = host([
+            ("flaky", reporter(bus.clone(), true)),
+            ("steady", reporter(bus.clone(), false)),
+        ])
+        .await;
+
+        let supervisor = Delegator::new("boss", plan(), "writer")
+            .with_worker("flaky", "hangs after reporting")
+            .with_worker("steady", "adds up its inputs")
+            .with_event_bus(bus)
+            .with_stall_timeout(Duration::from_millis(100));
+
+        let outcome = supervisor.run(&orchestrator, "add up").await.unwrap();
+        assert_eq!(outcome.answer.get::<u32>("value"), Some(3));
+        let stalled = &outcome.assignments[0];
+        assert_eq!(
+            (stalled.step.as_str(), stalled.worker.as_str()),
+            ("a", "flaky")
+        );
+        assert!(stalled.error.as_deref().unwrap().contains("no progress"));
+        assert_eq!(stalled.progress.len(), 1);
+        assert_eq!(outcome.assignments.len(), 5);
+    }
+}
@@ -0,0 +1,106 @@
+//! Error types for the orchestrator
+
+use thiserror::Error;
+
+/// Orchestrator error types
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Agent not found: {0}")]
+    AgentNotFound(String),
+
+    #[error("Agent already registered: {0}")]
+    AgentExists(String),
+
+    #[error("Agent is not running: {0}")]
+    NotRunning(String),
+
+    #[error("No running agent has capability: {0}")]
+    NoRoute(String),
+
+    #[error("Agent {0} failed: {1}")]
+    AgentFailed(String, String),
+
+    #[error("Supervisor {0} gave up: {1}")]
+    Escalated(String, String),
+
+    #[error("Workflow error: {0}")]
+    Workflow(String),
+
+    #[error("Lease on task {0} was lost to another worker")]
+    LeaseLost(atlas_core::TaskId),
+
+    #[error("Task queue error: {0}")]
+    Queue(String),
+
+    #[error("Agent {0} may not invoke tool {1}")]
+    ToolDenied(String, String),
+
+    #[error("Step {0} failed on every attempt: {1}")]
+    StepFailed(String, String),
+
+    #[error(transparent)]
+    Core(#[from] atlas_core::Error),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Result type for orchestrator operations
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<Error> for atlas_core::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::AgentNotFound(msg) => {
+                atlas_core::Error::Agent(format!("Agent not found: {}", msg))
+            }
+            Error::AgentExists(msg) => {
+                atlas_core::Error::Config(format!("Agent already registered: {}", msg))
+            }
+            Error::NotRunning(msg) => {
+                atlas_core::Error::Unavailable(format!("Agent is not running: {}", msg))
+            }
+            Error::NoRoute(msg) => {
+                atlas_core::Error::Unavailable(format!("No running agent has capability: {}", msg))
+            }
+            Error::AgentFailed(name, reason) => {
+                atlas_core::Error::Agent(format!("Agent {} failed: {}", name, reason))
+            }
+            Error::Escalated(name, reason) => {
+                atlas_core::Error::Agent(format!("Supervisor {} gave up: {}", name, reason))
+            }
+            Error::Workflow(msg) => atlas_core::Error::Config(format!("Workflow error: {}", msg)),
+            Error::LeaseLost(task_id) => atlas_core::Error::State(format!(
+                "Lease on task {} was lost to another worker",
+                task_id
+            )),
+            Error::Queue(msg) => {
+                atlas_core::Error::Unavailable(format!("Task queue error: {}", msg))
+            }
+            Error::ToolDenied(agent, tool) => {
+                atlas_core::Error::Tool(format!("Agent {} may not invoke tool {}", agent, tool))
+            }
+            Error::StepFailed(step, reason) => atlas_core::Error::Agent(format!(
+                "Step {} failed on every attempt: {}",
+                step, reason
+            )),
+            Error::Core(e) => e,
+            Error::Other(e) => atlas_core::Error::Other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_error_conversion() {
+        let core: atlas_core::Error = Error::NoRoute("search".to_string()).into();
+        assert!(matches!(core, atlas_core::Error::Unavailable(_)));
+        assert_eq!(
+            Error::AgentNotFound("planner".to_string()).to_string(),
+            "Agent not found: planner"
+        );
+    }
+}
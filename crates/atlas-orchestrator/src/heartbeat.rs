@@ -0,0 +1,37 @@
+//! Heartbeats of hosted agents
+
+use std::sync::Arc;
+
+use atlas_core::{DynAgent, Event, EventBus, HealthLevel, Metadata};
+use uuid::Uuid;
+
+pub use atlas_agent::heartbeat::{
+    HeartbeatConfig, Liveness, AGENT_HEARTBEAT, AGENT_KEY, HEALTH_KEY, INSTANCE_KEY,
+};
+
+/// Beat on behalf of an instance of an agent for as long as it answers
+/// health checks in time without being unhealthy, so a hung or broken agent
+/// falls silent
+pub(crate) async fn beat(
+    name: String,
+    instance: Uuid,
+    agent: Arc<dyn DynAgent>,
+    bus: Arc<dyn EventBus>,
+    config: HeartbeatConfig,
+) {
+    let mut ticks = tokio::time::interval(config.interval());
+    loop {
+        ticks.tick().await;
+        let health = match tokio::time::timeout(config.interval(), agent.health()).await {
+            Ok(health) if health.level != HealthLevel::Unhealthy => health,
+            _ => continue,
+        };
+        let mut payload = Metadata::new();
+        payload.insert(AGENT_KEY, &name);
+        payload.insert(INSTANCE_KEY, instance);
+        payload.insert(HEALTH_KEY, &health);
+        if let Err(e) = bus.publish(Event::new(AGENT_HEARTBEAT, payload)).await {
+            tracing::debug!(agent = %name, error = %e, "Failed to publish heartbeat");
+        }
+    }
+}
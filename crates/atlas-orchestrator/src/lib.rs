@@ -0,0 +1,50 @@
+//! Atlas Orchestrator - Multi-agent runtime for the Atlas framework
+//!
+//! This crate hosts several agents in one process, sharing an event bus and
+//! routing tasks between them, and supervises them so failed agents, or
+//! agents that stop sending heartbeats, are restarted. Agents can also work
+//! together as a [`Team`] under roles, or run the steps of a declarative
+//! [`Workflow`], and an [`AgentPool`] shares a workload between identical
+//! agents. Agents invoke the tools other agents offer, as far as a
+//! [`ToolAcl`] lets them. Agents in several processes can share the tasks
+//! of a [`TaskQueue`], leasing them through [`QueueWorker`]s. A
+//! [`Delegator`] supervises workers, planning a goal and delegating the
+//! steps to them.
+
+pub mod acl;
+pub mod delegation;
+pub mod error;
+pub mod heartbeat;
+pub mod orchestrator;
+pub mod pool;
+pub mod queue;
+pub mod supervisor;
+pub mod team;
+pub mod workflow;
+
+// Re-exports
+pub use acl::{Access, ToolAcl, ToolRule, ANONYMOUS, TOOL_DENIED};
+pub use delegation::{Assignment, DelegateWorker, DelegationOutcome, Delegator, TASK_PROGRESS};
+pub use error::Error;
+pub use heartbeat::{HeartbeatConfig, Liveness, AGENT_HEARTBEAT};
+pub use orchestrator::{
+    AgentFailure, AgentRegistration, AgentStatus, Orchestrator, OrchestratorStatus, RunState,
+    ToolHandle,
+};
+pub use pool::{
+    AgentFactory, AgentPool, Autoscaler, Balancing, LoadScaler, PoolStats,
+    DEFAULT_SCALE_DOWN_COOLDOWN,
+};
+#[cfg(feature = "postgres")]
+pub use queue::PostgresTaskQueue;
+#[cfg(feature = "redis")]
+pub use queue::RedisTaskQueue;
+pub use queue::{InMemoryTaskQueue, Lease, QueueWorker, QueuedTask, TaskQueue};
+pub use supervisor::{
+    RestartPolicy, RestartStrategy, Supervisor, SupervisorSpec, SUPERVISOR_ESCALATED,
+};
+pub use team::{Handoff, Protocol, Role, Team, TeamMember, TeamOutcome};
+pub use workflow::{
+    Action, Compensation, Condition, RunStatus, Step, StepState, StepStatus, TaskStep, Workflow,
+    WorkflowRun,
+};
@@ -0,0 +1,1244 @@
+//! Hosting agents, their lifecycles and the routing of their tasks
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::time::Instant;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use atlas_agent::{Balancer, RouterConfig};
+use atlas_core::{
+    CancellationContext, DynAgent, Event, EventBus, EventStream, HealthLevel, HealthProbe,
+    HealthStatus, InMemoryEventBus, Metadata, TaskId, TopicPattern,
+};
+use futures::{FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::acl::{Access, ToolAcl, ANONYMOUS, TOOL_DENIED};
+use crate::heartbeat::{self, AGENT_HEARTBEAT, AGENT_KEY, HEALTH_KEY, INSTANCE_KEY};
+use crate::workflow::TOOL_KEY;
+use crate::{Error, HeartbeatConfig, Liveness};
+
+/// Agent to host in an [`Orchestrator`], with the capabilities tasks are
+/// routed by and the topics it listens on
+#[derive(Clone, Debug)]
+pub struct AgentRegistration {
+    /// Name the agent is hosted under
+    name: String,
+
+    /// Hosted agent
+    agent: Arc<dyn DynAgent>,
+
+    /// Capabilities tasks are routed to the agent by
+    capabilities: Vec<String>,
+
+    /// Topics or topic patterns the agent handles events from
+    topics: Vec<String>,
+
+    /// Tools the agent offers the other hosted agents
+    tools: Vec<String>,
+}
+
+impl AgentRegistration {
+    /// Register an agent under a name
+    pub fn new(name: impl Into<String>, agent: Arc<dyn DynAgent>) -> Self {
+        Self {
+            name: name.into(),
+            agent,
+            capabilities: Vec::new(),
+            topics: Vec::new(),
+            tools: Vec::new(),
+        }
+    }
+
+    /// Route tasks needing a capability to the agent
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    /// Pass the events published on a topic, or on topics matching a
+    /// pattern such as `task.*`, to the agent while it runs
+    pub fn with_topic(mut self, topic: impl Into<String>) -> Self {
+        self.topics.push(topic.into());
+        self
+    }
+
+    /// Offer a tool of the agent to the other hosted agents, which invoke
+    /// it with [`Orchestrator::invoke_tool`] or a [`ToolHandle`]
+    pub fn with_tool(mut self, tool: impl Into<String>) -> Self {
+        self.tools.push(tool.into());
+        self
+    }
+
+    /// Get the name the agent is hosted under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the capabilities tasks are routed to the agent by
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    /// Get the tools the agent offers the other hosted agents
+    pub fn tools(&self) -> &[String] {
+        &self.tools
+    }
+}
+
+/// Failures kept for supervisors that have not received them yet
+const FAILURE_BUFFER: usize = 64;
+
+tokio::task_local! {
+    /// Name of the hosted agent whose work runs in the current task
+    static CALLER: String;
+}
+
+/// Handle a hosted agent invokes the tools other agents offer through,
+/// bound to the agent so it cannot invoke them as another
+///
+/// Hosts hand each agent its own handle from
+/// [`Orchestrator::tool_handle`].
+#[derive(Clone)]
+pub struct ToolHandle {
+    /// Orchestrator hosting the agents
+    orchestrator: Weak<Orchestrator>,
+
+    /// Name of the invoking agent
+    agent: String,
+}
+
+impl std::fmt::Debug for ToolHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolHandle")
+            .field("agent", &self.agent)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ToolHandle {
+    /// Get the name of the invoking agent
+    pub fn agent(&self) -> &str {
+        &self.agent
+    }
+
+    /// Invoke a tool offered by one of the running, live agents
+    pub async fn invoke(&self, tool: &str, params: Metadata) -> Result<Metadata> {
+        let orchestrator = self
+            .orchestrator
+            .upgrade()
+            .ok_or_else(|| Error::AgentNotFound(self.agent.clone()))?;
+        orchestrator
+            .invoke_as(Some(&self.agent), tool, params)
+            .await
+    }
+}
+
+/// Stage of a hosted agent's lifecycle
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    /// Registered but not started yet
+    Registered,
+
+    /// Taking tasks and events
+    Running,
+
+    /// Holding tasks and events until resumed
+    Paused,
+
+    /// Stopped, until started again
+    Stopped,
+
+    /// Panicked while handling work, or stopped beating, until restarted
+    Failed,
+}
+
+/// Panic of a hosted agent while handling a task or an event, or its
+/// heartbeats stopping
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AgentFailure {
+    /// Name the agent is hosted under
+    pub agent: String,
+
+    /// Message the agent panicked with, or how long it was silent
+    pub reason: String,
+}
+
+/// Status of a hosted agent
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AgentStatus {
+    /// Name the agent is hosted under
+    pub name: String,
+
+    /// Concrete type of the agent
+    pub type_name: String,
+
+    /// Stage of the agent's lifecycle
+    pub state: RunState,
+
+    /// Whether the agent beats as often as it should
+    pub liveness: Liveness,
+
+    /// Capabilities tasks are routed to the agent by
+    pub capabilities: Vec<String>,
+
+    /// Health reported by the agent
+    pub health: HealthStatus,
+}
+
+/// Status of every agent hosted by an orchestrator
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OrchestratorStatus {
+    /// Status of each agent, by name
+    pub agents: Vec<AgentStatus>,
+
+    /// Combined health of the agents
+    pub health: HealthStatus,
+}
+
+/// Task passing the events on a running agent's topics to it
+struct Listener {
+    /// Task handling the events
+    task: JoinHandle<()>,
+
+    /// Tells the task to stop once the event in progress is handled
+    stop: oneshot::Sender<()>,
+}
+
+/// Agent hosted by an orchestrator
+struct Hosted {
+    /// How the agent was registered
+    registration: AgentRegistration,
+
+    /// Stage of the agent's lifecycle
+    state: RunState,
+
+    /// Task passing events from the bus to the agent while it runs
+    listener: Option<Listener>,
+
+    /// Task beating on behalf of the agent while it runs
+    heartbeat: Option<JoinHandle<()>>,
+
+    /// Running instance of the agent, told apart from agents sharing its
+    /// name by its heartbeats
+    instance: Uuid,
+
+    /// When the agent last beat, or was started
+    last_heartbeat: Instant,
+
+    /// Whether the agent beats as often as it should
+    liveness: Liveness,
+
+    /// Health the agent last beat with
+    health: HealthStatus,
+}
+
+/// Hosted agents, by name
+type Agents = RwLock<HashMap<String, Hosted>>;
+
+/// Runtime hosting several agents in one process
+///
+/// Agents are registered under unique names and share the orchestrator's
+/// event bus. Tasks are sent to an agent by name with
+/// [`Orchestrator::execute`], or routed by capability with
+/// [`Orchestrator::route`], which picks among the running agents having it
+/// with the same [`Balancer`] as a [`RouterAgent`](atlas_agent::RouterAgent),
+/// by their load and the health they last beat with.
+///
+/// An agent panicking while handling a task or an event is marked
+/// [`RunState::Failed`] and its failure sent to
+/// [`Orchestrator::failures`] subscribers, such as a
+/// [`Supervisor`](crate::Supervisor), instead of taking its caller or
+/// listener down with it. With [`Orchestrator::with_heartbeats`], running
+/// agents also beat on [`AGENT_HEARTBEAT`]; agents missing beats stop
+/// being routed to, and agents missing too many fail the same way.
+///
+/// Agents invoke the tools other agents offer through
+/// [`Orchestrator::invoke_tool`] or a [`ToolHandle`]. Under
+/// [`Orchestrator::with_tool_acl`], every task naming a tool under
+/// [`TOOL_KEY`] is checked against the [`ToolAcl`] as invoked by the hosted
+/// agent whose work sends it, or anonymously from outside any agent's work;
+/// denied invocations are refused and reported on [`TOOL_DENIED`].
+pub struct Orchestrator {
+    /// Hosted agents, by name
+    agents: Arc<Agents>,
+
+    /// Failures of the hosted agents
+    failures: broadcast::Sender<AgentFailure>,
+
+    /// Bus shared by the hosted agents
+    event_bus: Arc<dyn EventBus>,
+
+    /// Chooser of the agents taking routed tasks and tool invocations
+    balancer: Balancer<String>,
+
+    /// How the agents beat, if they do
+    heartbeats: Option<HeartbeatConfig>,
+
+    /// Task judging the agents by their heartbeats
+    watchdog: Mutex<Option<JoinHandle<()>>>,
+
+    /// Which agents may invoke which tools, if restricted
+    tool_acl: Option<ToolAcl>,
+}
+
+impl Default for Orchestrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Orchestrator {
+    /// Create an orchestrator with an in-process event bus
+    pub fn new() -> Self {
+        Self {
+            agents: Arc::default(),
+            failures: broadcast::channel(FAILURE_BUFFER).0,
+            event_bus: Arc::new(InMemoryEventBus::new()),
+            balancer: Balancer::new(RouterConfig::default()),
+            heartbeats: None,
+            watchdog: Mutex::new(None),
+            tool_acl: None,
+        }
+    }
+
+    /// Share an event bus between the hosted agents
+    pub fn with_event_bus(mut self, bus: Arc<dyn EventBus>) -> Self {
+        self.event_bus = bus;
+        self
+    }
+
+    /// Have running agents beat on the event bus, marking those missing
+    /// beats stale and reporting those missing too many failed
+    pub fn with_heartbeats(mut self, config: HeartbeatConfig) -> Self {
+        self.heartbeats = Some(config);
+        self
+    }
+
+    /// Restrict which agents may invoke which tools; without a list every
+    /// agent may invoke every tool
+    pub fn with_tool_acl(mut self, acl: ToolAcl) -> Self {
+        self.tool_acl = Some(acl);
+        self
+    }
+
+    /// Get the event bus shared by the hosted agents
+    pub fn event_bus(&self) -> &Arc<dyn EventBus> {
+        &self.event_bus
+    }
+
+    /// Host an agent; it takes work once started
+    pub fn register(&self, registration: AgentRegistration) -> Result<()> {
+        let mut agents = self.write_agents();
+        if agents.contains_key(&registration.name) {
+            return Err(Error::AgentExists(registration.name).into());
+        }
+        tracing::info!(agent = %registration.name, "Agent registered");
+        agents.insert(
+            registration.name.clone(),
+            Hosted {
+                registration,
+                state: RunState::Registered,
+                listener: None,
+                heartbeat: None,
+                instance: Uuid::new_v4(),
+                last_heartbeat: Instant::now(),
+                liveness: Liveness::Alive,
+                health: HealthStatus::healthy(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Stop hosting an agent, stopping it first if it runs
+    pub async fn unregister(&self, name: &str) -> Result<Arc<dyn DynAgent>> {
+        let state = self.hosted(name, |hosted| hosted.state)?;
+        if matches!(state, RunState::Running | RunState::Paused) {
+            self.stop(name).await?;
+        }
+        let hosted = self
+            .write_agents()
+            .remove(name)
+            .ok_or_else(|| Error::AgentNotFound(name.to_string()))?;
+        Ok(hosted.registration.agent)
+    }
+
+    /// Get a hosted agent
+    pub fn agent(&self, name: &str) -> Option<Arc<dyn DynAgent>> {
+        self.hosted(name, |hosted| hosted.registration.agent.clone())
+            .ok()
+    }
+
+    /// Get the names of the hosted agents, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.read_agents().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Get the stage of a hosted agent's lifecycle
+    pub fn state(&self, name: &str) -> Result<RunState> {
+        self.hosted(name, |hosted| hosted.state)
+    }
+
+    /// Get whether a hosted agent beats as often as it should
+    pub fn liveness(&self, name: &str) -> Result<Liveness> {
+        self.hosted(name, |hosted| hosted.liveness)
+    }
+
+    /// Subscribe to the failures of the hosted agents
+    pub fn failures(&self) -> broadcast::Receiver<AgentFailure> {
+        self.failures.subscribe()
+    }
+
+    /// Start an agent and pass it the events on its topics
+    pub async fn start(&self, name: &str) -> Result<()> {
+        let registration = self.hosted(name, |hosted| hosted.registration.clone())?;
+        registration.agent.on_start().await?;
+
+        let mut streams = Vec::with_capacity(registration.topics.len());
+        for topic in &registration.topics {
+            if TopicPattern::parse(topic)?.is_literal() {
+                self.event_bus.register_topic(topic).await?;
+            }
+            streams.push(self.event_bus.subscribe(topic).await?);
+        }
+        let listener = (!streams.is_empty()).then(|| {
+            let agent = registration.agent.clone();
+            let name = registration.name.clone();
+            let agents = Arc::downgrade(&self.agents);
+            let failures = self.failures.clone();
+            let mut events = futures::stream::select_all(streams);
+            let (stop, mut stopping) = oneshot::channel();
+            let task = tokio::spawn(async move {
+                loop {
+                    let event = tokio::select! {
+                        biased;
+                        _ = &mut stopping => break,
+                        event = events.next() => match event {
+                            Some(event) => event,
+                            None => break,
+                        },
+                    };
+                    let event_type = event.event_type.clone();
+                    let handling = AssertUnwindSafe(agent.handle_event(event)).catch_unwind();
+                    match CALLER.scope(name.clone(), handling).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => {
+                            tracing::warn!(agent = %name, %event_type, error = %e, "Event handling failed");
+                        }
+                        Err(panic) => {
+                            if let Some(agents) = agents.upgrade() {
+                                fail(&agents, &failures, &name, panic);
+                            }
+                            break;
+                        }
+                    }
+                }
+            });
+            Listener { task, stop }
+        });
+
+        let instance = Uuid::new_v4();
+        let heartbeat = match &self.heartbeats {
+            Some(config) => {
+                self.watch_heartbeats(config).await?;
+                Some(tokio::spawn(heartbeat::beat(
+                    registration.name.clone(),
+                    instance,
+                    registration.agent.clone(),
+                    self.event_bus.clone(),
+                    config.clone(),
+                )))
+            }
+            None => None,
+        };
+
+        self.update(name, |hosted| {
+            if let Some(previous) = std::mem::replace(&mut hosted.listener, listener) {
+                previous.task.abort();
+            }
+            if let Some(previous) = std::mem::replace(&mut hosted.heartbeat, heartbeat) {
+                previous.abort();
+            }
+            hosted.state = RunState::Running;
+            hosted.instance = instance;
+            hosted.last_heartbeat = Instant::now();
+            hosted.liveness = Liveness::Alive;
+        })?;
+        tracing::info!(agent = %name, "Agent started");
+        Ok(())
+    }
+
+    /// Stop passing an agent events, once the one in progress is handled,
+    /// and stop the agent once its work in progress finishes
+    pub async fn stop(&self, name: &str) -> Result<()> {
+        let (agent, listener) = self.update(name, |hosted| {
+            (hosted.registration.agent.clone(), hosted.listener.take())
+        })?;
+        if let Some(listener) = listener {
+            // The listener may have stopped already, after a panic
+            let _ = listener.stop.send(());
+            let _ = listener.task.await;
+        }
+        agent.on_stop().await?;
+        self.update(name, |hosted| {
+            if let Some(heartbeat) = hosted.heartbeat.take() {
+                heartbeat.abort();
+            }
+            hosted.state = RunState::Stopped;
+        })?;
+        tracing::info!(agent = %name, "Agent stopped");
+        Ok(())
+    }
+
+    /// Hold an agent's new work until it is resumed
+    pub async fn pause(&self, name: &str) -> Result<()> {
+        let agent = self.hosted(name, |hosted| hosted.registration.agent.clone())?;
+        agent.pause().await?;
+        self.update(name, |hosted| hosted.state = RunState::Paused)
+    }
+
+    /// Let a paused agent take work again
+    pub async fn resume(&self, name: &str) -> Result<()> {
+        let agent = self.hosted(name, |hosted| hosted.registration.agent.clone())?;
+        agent.resume().await?;
+        self.update(name, |hosted| hosted.state = RunState::Running)
+    }
+
+    /// Stop an agent, if it runs, and start it again
+    pub async fn restart(&self, name: &str) -> Result<()> {
+        if self.state(name)? != RunState::Registered {
+            if let Err(e) = self.stop(name).await {
+                tracing::warn!(agent = %name, error = %e, "Failed to stop agent before restarting it");
+            }
+        }
+        self.start(name).await
+    }
+
+    /// Start every agent not running yet, in name order
+    pub async fn start_all(&self) -> Result<()> {
+        for name in self.names() {
+            if matches!(
+                self.state(&name)?,
+                RunState::Registered | RunState::Stopped | RunState::Failed
+            ) {
+                self.start(&name).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop every running or paused agent, returning the first failure
+    /// once every agent was asked to stop
+    pub async fn stop_all(&self) -> Result<()> {
+        let mut result = Ok(());
+        for name in self.names() {
+            if !matches!(self.state(&name)?, RunState::Running | RunState::Paused) {
+                continue;
+            }
+            if let Err(e) = self.stop(&name).await {
+                tracing::warn!(agent = %name, error = %e, "Failed to stop agent");
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Execute a task on a running or paused agent by name
+    pub async fn execute(&self, name: &str, task_id: TaskId, params: Metadata) -> Result<Metadata> {
+        let agent = self.runnable(name)?;
+        self.authorize_task(&params).await?;
+        self.guard(name, agent.execute_task(task_id, params)).await
+    }
+
+    /// Execute a task on a running or paused agent by name, stopping once
+    /// the context is cancelled or its deadline passes
+    pub async fn execute_with(
+        &self,
+        name: &str,
+        task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        let agent = self.runnable(name)?;
+        self.authorize_task(&params).await?;
+        self.guard(name, agent.execute_task_with(task_id, params, cancel))
+            .await
+    }
+
+    /// Execute a task on one of the running, live agents having a
+    /// capability, weighing them by load and health like a
+    /// [`RouterAgent`](atlas_agent::RouterAgent)
+    pub async fn route(
+        &self,
+        capability: &str,
+        task_id: TaskId,
+        params: Metadata,
+    ) -> Result<Metadata> {
+        let (name, agent) = self
+            .pick(|registration| registration.capabilities.iter().any(|c| c == capability))
+            .ok_or_else(|| Error::NoRoute(capability.to_string()))?;
+        tracing::debug!(agent = %name, %capability, task_id = %task_id, "Routing task");
+        self.authorize_task(&params).await?;
+        let dispatch = self.balancer.dispatch(name.clone());
+        let result = self.guard(&name, agent.execute_task(task_id, params)).await;
+        dispatch.finish(&result);
+        result
+    }
+
+    /// Invoke a tool offered by one of the running, live agents, picked like
+    /// routed tasks, as the hosted agent whose work calls this
+    ///
+    /// The tool runs as a task with the tool named under
+    /// [`TOOL_KEY`](crate::workflow::TOOL_KEY). Invocations the tool access
+    /// list denies fail with [`Error::ToolDenied`] and are published on
+    /// [`TOOL_DENIED`] for auditing. Work outside any hosted agent invokes
+    /// anonymously; agents working outside the orchestrator use their
+    /// [`ToolHandle`].
+    pub async fn invoke_tool(&self, tool: &str, params: Metadata) -> Result<Metadata> {
+        let caller = current_caller();
+        self.invoke_as(caller.as_deref(), tool, params).await
+    }
+
+    /// Get the handle an agent invokes tools through
+    ///
+    /// Fails from the work of another hosted agent, which may not invoke
+    /// tools as this one.
+    pub fn tool_handle(self: &Arc<Self>, name: &str) -> Result<ToolHandle> {
+        self.hosted(name, |_| ())?;
+        if let Some(caller) = current_caller().filter(|caller| caller != name) {
+            return Err(Error::ToolDenied(caller, format!("as {}", name)).into());
+        }
+        Ok(ToolHandle {
+            orchestrator: Arc::downgrade(self),
+            agent: name.to_string(),
+        })
+    }
+
+    /// Publish an event on the shared bus
+    pub async fn publish(&self, event: Event) -> Result<()> {
+        self.event_bus.publish(event).await
+    }
+
+    /// Report the status and health of every hosted agent, counting failed
+    /// and dead agents unhealthy and stale ones degraded
+    pub async fn status(&self) -> OrchestratorStatus {
+        let hosted: Vec<(AgentRegistration, RunState, Liveness)> = {
+            let agents = self.read_agents();
+            let mut hosted: Vec<_> = agents
+                .values()
+                .map(|hosted| (hosted.registration.clone(), hosted.state, hosted.liveness))
+                .collect();
+            hosted.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+            hosted
+        };
+
+        let mut health = HealthStatus::healthy();
+        let mut agents = Vec::with_capacity(hosted.len());
+        for (registration, state, liveness) in hosted {
+            let mut agent_health = registration.agent.health().await;
+            if state == RunState::Failed {
+                agent_health = agent_health.with_issue(HealthLevel::Unhealthy, "agent failed");
+            }
+            match liveness {
+                Liveness::Alive => {}
+                Liveness::Stale => {
+                    agent_health =
+                        agent_health.with_issue(HealthLevel::Degraded, "missed heartbeats");
+                }
+                Liveness::Dead => {
+                    agent_health =
+                        agent_health.with_issue(HealthLevel::Unhealthy, "stopped beating");
+                }
+            }
+            for issue in &agent_health.issues {
+                health = health.with_issue(
+                    agent_health.level,
+                    format!("{}: {}", registration.name, issue),
+                );
+            }
+            health.level = health.level.max(agent_health.level);
+            agents.push(AgentStatus {
+                type_name: registration.agent.type_name().to_string(),
+                name: registration.name,
+                state,
+                liveness,
+                capabilities: registration.capabilities,
+                health: agent_health,
+            });
+        }
+        OrchestratorStatus { agents, health }
+    }
+
+    /// Invoke a tool as an agent, or anonymously
+    async fn invoke_as(
+        &self,
+        caller: Option<&str>,
+        tool: &str,
+        mut params: Metadata,
+    ) -> Result<Metadata> {
+        if let Some(caller) = caller {
+            self.hosted(caller, |_| ())?;
+        }
+        self.authorize(caller, tool).await?;
+        let (name, agent) = self
+            .pick(|registration| registration.tools.iter().any(|t| t == tool))
+            .ok_or_else(|| Error::NoRoute(format!("tool {}", tool)))?;
+        tracing::debug!(agent = %name, caller = caller.unwrap_or(ANONYMOUS), %tool, "Invoking tool");
+        params.insert(TOOL_KEY, tool);
+        let dispatch = self.balancer.dispatch(name.clone());
+        let result = self
+            .guard(&name, agent.execute_task(TaskId::new(), params))
+            .await;
+        dispatch.finish(&result);
+        result
+    }
+
+    /// Check a task naming a tool against the tool access list, as invoked
+    /// by the hosted agent whose work sends it
+    async fn authorize_task(&self, params: &Metadata) -> Result<()> {
+        match params.get::<String>(TOOL_KEY) {
+            Some(tool) if self.tool_acl.is_some() => {
+                self.authorize(current_caller().as_deref(), &tool).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check an invocation against the tool access list, reporting it if
+    /// denied
+    async fn authorize(&self, caller: Option<&str>, tool: &str) -> Result<()> {
+        let Some(acl) = &self.tool_acl else {
+            return Ok(());
+        };
+        let access = match caller {
+            Some(caller) => acl.check(caller, tool),
+            None => acl.check_anonymous(tool),
+        };
+        if access == Access::Allow {
+            return Ok(());
+        }
+        let caller = caller.unwrap_or(ANONYMOUS);
+        tracing::warn!(agent = %caller, %tool, "Tool invocation denied");
+        if let Err(e) = self.report_denial(caller, tool).await {
+            tracing::warn!(agent = %caller, %tool, error = %e, "Failed to report denial");
+        }
+        Err(Error::ToolDenied(caller.to_string(), tool.to_string()).into())
+    }
+
+    /// Publish a denied tool invocation on [`TOOL_DENIED`]
+    async fn report_denial(&self, caller: &str, tool: &str) -> Result<()> {
+        let mut payload = Metadata::new();
+        payload.insert(AGENT_KEY, caller);
+        payload.insert(TOOL_KEY, tool);
+        self.event_bus.register_topic(TOOL_DENIED).await?;
+        let event = Event::new(TOOL_DENIED, payload)
+            .with_current_cause()
+            .with_current_trace();
+        self.event_bus.publish(event).await
+    }
+
+    /// Pick one of the running, live agents whose registration matches,
+    /// by their load and the health they last beat with
+    fn pick(
+        &self,
+        matches: impl Fn(&AgentRegistration) -> bool,
+    ) -> Option<(String, Arc<dyn DynAgent>)> {
+        let mut candidates: Vec<_> = self
+            .read_agents()
+            .values()
+            .filter(|hosted| hosted.state == RunState::Running)
+            .filter(|hosted| hosted.liveness == Liveness::Alive)
+            .filter(|hosted| matches(&hosted.registration))
+            .map(|hosted| {
+                (
+                    hosted.registration.name.clone(),
+                    hosted.health.level,
+                    hosted.registration.agent.clone(),
+                )
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.0.cmp(&b.0));
+        self.balancer.choose(candidates)
+    }
+
+    /// Run an agent's work, reporting the agent failed if it panics
+    async fn guard<T>(
+        &self,
+        name: &str,
+        work: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let work = AssertUnwindSafe(work).catch_unwind();
+        match CALLER.scope(name.to_string(), work).await {
+            Ok(result) => result,
+            Err(panic) => {
+                let reason = fail(&self.agents, &self.failures, name, panic);
+                Err(Error::AgentFailed(name.to_string(), reason).into())
+            }
+        }
+    }
+
+    /// Start judging the agents by their heartbeats, unless already done
+    async fn watch_heartbeats(&self, config: &HeartbeatConfig) -> Result<()> {
+        if self.lock_watchdog().is_some() {
+            return Ok(());
+        }
+        config.validate()?;
+        self.event_bus.register_topic(AGENT_HEARTBEAT).await?;
+        let beats = self.event_bus.subscribe(AGENT_HEARTBEAT).await?;
+        self.lock_watchdog().get_or_insert_with(|| {
+            tokio::spawn(watch(
+                Arc::downgrade(&self.agents),
+                self.failures.clone(),
+                beats,
+                config.clone(),
+            ))
+        });
+        Ok(())
+    }
+
+    /// Lock the task judging the agents by their heartbeats
+    fn lock_watchdog(&self) -> std::sync::MutexGuard<'_, Option<JoinHandle<()>>> {
+        self.watchdog.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Get a hosted agent able to take tasks
+    fn runnable(&self, name: &str) -> Result<Arc<dyn DynAgent>> {
+        let (agent, state) = self.hosted(name, |hosted| {
+            (hosted.registration.agent.clone(), hosted.state)
+        })?;
+        if !matches!(state, RunState::Running | RunState::Paused) {
+            return Err(Error::NotRunning(name.to_string()).into());
+        }
+        Ok(agent)
+    }
+
+    /// Read a hosted agent
+    fn hosted<T>(&self, name: &str, read: impl FnOnce(&Hosted) -> T) -> Result<T> {
+        self.read_agents()
+            .get(name)
+            .map(read)
+            .ok_or_else(|| Error::AgentNotFound(name.to_string()).into())
+    }
+
+    /// Change a hosted agent
+    fn update<T>(&self, name: &str, change: impl FnOnce(&mut Hosted) -> T) -> Result<T> {
+        self.write_agents()
+            .get_mut(name)
+            .map(change)
+            .ok_or_else(|| Error::AgentNotFound(name.to_string()).into())
+    }
+
+    /// Lock the hosted agents for reading
+    fn read_agents(&self) -> RwLockReadGuard<'_, HashMap<String, Hosted>> {
+        self.agents.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock the hosted agents for writing
+    fn write_agents(&self) -> RwLockWriteGuard<'_, HashMap<String, Hosted>> {
+        self.agents.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Orchestrator {
+    fn drop(&mut self) {
+        for hosted in self.write_agents().values_mut() {
+            for task in [
+                hosted.listener.take().map(|l| l.task),
+                hosted.heartbeat.take(),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                task.abort();
+            }
+        }
+        if let Some(watchdog) = self.lock_watchdog().take() {
+            watchdog.abort();
+        }
+    }
+}
+
+/// Get the name of the hosted agent whose work runs in the current task
+fn current_caller() -> Option<String> {
+    CALLER.try_with(String::clone).ok()
+}
+
+/// Mark an agent failed after it panicked and tell the subscribers,
+/// returning the panic message
+fn fail(
+    agents: &Agents,
+    failures: &broadcast::Sender<AgentFailure>,
+    name: &str,
+    panic: Box<dyn Any + Send>,
+) -> String {
+    let reason = panic
+        .downcast_ref::<&str>()
+        .map(|reason| reason.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    tracing::error!(agent = %name, %reason, "Agent panicked");
+    report(agents, failures, name, reason.clone());
+    reason
+}
+
+/// Mark an agent failed, stop beating for it and tell the subscribers
+fn report(agents: &Agents, failures: &broadcast::Sender<AgentFailure>, name: &str, reason: String) {
+    if let Some(hosted) = agents
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_mut(name)
+    {
+        hosted.state = RunState::Failed;
+        if let Some(heartbeat) = hosted.heartbeat.take() {
+            heartbeat.abort();
+        }
+    }
+    // Nobody may be subscribed; the state still records the failure
+    let _ = failures.send(AgentFailure {
+        agent: name.to_string(),
+        reason,
+    });
+}
+
+/// Record the heartbeats of the hosted agents and judge the running ones
+/// by them, until the orchestrator is dropped
+async fn watch(
+    agents: Weak<Agents>,
+    failures: broadcast::Sender<AgentFailure>,
+    mut beats: EventStream,
+    config: HeartbeatConfig,
+) {
+    let mut ticks = tokio::time::interval(config.interval());
+    loop {
+        tokio::select! {
+            beat = beats.next() => {
+                let (Some(beat), Some(agents)) = (beat, agents.upgrade()) else {
+                    return;
+                };
+                let (Some(name), Some(instance)) = (
+                    beat.payload.get::<String>(AGENT_KEY),
+                    beat.payload.get::<Uuid>(INSTANCE_KEY),
+                ) else {
+                    continue;
+                };
+                // Agents sharing the name on the bus do not keep this one alive
+                let mut agents = agents.write().unwrap_or_else(PoisonError::into_inner);
+                if let Some(hosted) = agents.get_mut(&name).filter(|h| h.instance == instance) {
+                    hosted.last_heartbeat = Instant::now();
+                    if let Some(health) = beat.payload.get(HEALTH_KEY) {
+                        hosted.health = health;
+                    }
+                }
+            }
+            _ = ticks.tick() => {
+                let Some(agents) = agents.upgrade() else {
+                    return;
+                };
+                judge(&agents, &failures, &config);
+            }
+        }
+    }
+}
+
+/// Mark the running agents by the time since their last beat, reporting
+/// the dead ones failed
+fn judge(agents: &Agents, failures: &broadcast::Sender<AgentFailure>, config: &HeartbeatConfig) {
+    let mut dead = Vec::new();
+    for (name, hosted) in agents
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter_mut()
+    {
+        if !matches!(hosted.state, RunState::Running | RunState::Paused) {
+            continue;
+        }
+        let silence = hosted.last_heartbeat.elapsed();
+        let liveness = config.liveness(silence);
+        if liveness != hosted.liveness {
+            tracing::warn!(
+                agent = %name,
+                ?liveness,
+                silence_ms = silence.as_millis() as u64,
+                "Agent liveness changed"
+            );
+            hosted.liveness = liveness;
+        }
+        if liveness == Liveness::Dead {
+            dead.push((name.clone(), silence));
+        }
+    }
+    for (name, silence) in dead {
+        let reason = format!("no heartbeat for {} ms", silence.as_millis());
+        report(agents, failures, &name, reason);
+    }
+}
+
+/// Orchestrators report the combined health of their agents
+#[async_trait]
+impl HealthProbe for Orchestrator {
+    async fn probe(&self) -> HealthStatus {
+        self.status().await.health
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::testing::FakeAgent;
+    use atlas_core::Cause;
+    use std::time::Duration;
+
+    /// Agent answering with its name
+    fn named(name: &str) -> Arc<FakeAgent> {
+        let name = name.to_string();
+        Arc::new(FakeAgent::new(move |_| {
+            let mut result = Metadata::new();
+            result.insert("agent", &name);
+            async move { Ok(result) }
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_routing_and_lifecycle() {
+        let orchestrator = Orchestrator::new();
+        let first = named("first");
+        orchestrator
+            .register(
+                AgentRegistration::new("first", first.clone())
+                    .with_capability("search")
+                    .with_topic("note"),
+            )
+            .unwrap();
+        orchestrator
+            .register(AgentRegistration::new("second", named("second")).with_capability("search"))
+            .unwrap();
+        assert!(orchestrator
+            .register(AgentRegistration::new("first", named("first")))
+            .is_err());
+
+        // Only running agents take routed tasks
+        assert!(orchestrator
+            .route("search", TaskId::new(), Metadata::new())
+            .await
+            .is_err());
+        orchestrator.start_all().await.unwrap();
+        let mut answered = Vec::new();
+        for _ in 0..2 {
+            let result = orchestrator
+                .route("search", TaskId::new(), Metadata::new())
+                .await
+                .unwrap();
+            answered.push(result.get::<String>("agent").unwrap());
+        }
+        answered.sort();
+        assert_eq!(answered, ["first", "second"]);
+
+        orchestrator
+            .publish(Event::new("note", Metadata::new()))
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert_eq!(first.events(), ["note"]);
+
+        orchestrator.stop("second").await.unwrap();
+        assert!(orchestrator
+            .execute("second", TaskId::new(), Metadata::new())
+            .await
+            .is_err());
+        let result = orchestrator
+            .route("search", TaskId::new(), Metadata::new())
+            .await
+            .unwrap();
+        assert_eq!(result.get::<String>("agent").as_deref(), Some("first"));
+        assert_eq!(orchestrator.state("second").unwrap(), RunState::Stopped);
+        orchestrator.unregister("second").await.unwrap();
+        assert_eq!(orchestrator.names(), ["first"]);
+    }
+
+    #[tokio::test]
+    async fn test_status() {
+        let orchestrator = Orchestrator::new();
+        let broken = Arc::new(FakeAgent::echo());
+        broken.set_health(
+            HealthStatus::healthy().with_issue(HealthLevel::Unhealthy, "model is down"),
+        );
+        orchestrator
+            .register(AgentRegistration::new("writer", named("writer")))
+            .unwrap();
+        orchestrator
+            .register(AgentRegistration::new("broken", broken))
+            .unwrap();
+        orchestrator.start("writer").await.unwrap();
+
+        let status = orchestrator.status().await;
+        let states: Vec<_> = status
+            .agents
+            .iter()
+            .map(|a| (a.name.as_str(), a.state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ("broken", RunState::Registered),
+                ("writer", RunState::Running)
+            ]
+        );
+        assert_eq!(status.health.level, HealthLevel::Unhealthy);
+        assert_eq!(status.health.issues, ["broken: model is down"]);
+        assert_eq!(orchestrator.probe().await.level, HealthLevel::Unhealthy);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats() {
+        let orchestrator = Orchestrator::new().with_heartbeats(HeartbeatConfig {
+            interval_ms: 10,
+            stale_after: 3,
+            dead_after: 20,
+        });
+        let flaky = named("flaky");
+        for (name, agent) in [("flaky", flaky.clone()), ("steady", named("steady"))] {
+            orchestrator
+                .register(AgentRegistration::new(name, agent).with_capability("search"))
+                .unwrap();
+        }
+        let mut failures = orchestrator.failures();
+        orchestrator.start_all().await.unwrap();
+
+        // A hung agent stops beating, goes stale and is no longer routed to,
+        // even while another agent of the same name beats on the bus
+        flaky.set_hung(true);
+        let bus = orchestrator.event_bus().clone();
+        let impostor = tokio::spawn(async move {
+            let instance = Uuid::new_v4();
+            loop {
+                let mut payload = Metadata::new();
+                payload.insert(AGENT_KEY, "flaky");
+                payload.insert(INSTANCE_KEY, instance);
+                let _ = bus.publish(Event::new(AGENT_HEARTBEAT, payload)).await;
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert_eq!(orchestrator.liveness("flaky").unwrap(), Liveness::Stale);
+        impostor.abort();
+        for _ in 0..2 {
+            let result = orchestrator
+                .route("search", TaskId::new(), Metadata::new())
+                .await
+                .unwrap();
+            assert_eq!(result.get::<String>("agent").as_deref(), Some("steady"));
+        }
+
+        // Then it is dead and reported failed, for supervisors to restart
+        let failure = failures.recv().await.unwrap();
+        assert_eq!(failure.agent, "flaky");
+        assert_eq!(orchestrator.state("flaky").unwrap(), RunState::Failed);
+        assert_eq!(orchestrator.liveness("flaky").unwrap(), Liveness::Dead);
+        assert_eq!(orchestrator.liveness("steady").unwrap(), Liveness::Alive);
+
+        flaky.set_hung(false);
+        assert_eq!(
+            orchestrator.status().await.health.level,
+            HealthLevel::Unhealthy
+        );
+        orchestrator.restart("flaky").await.unwrap();
+        assert_eq!(orchestrator.liveness("flaky").unwrap(), Liveness::Alive);
+
+        // An agent beating with ill health loses its turns to healthy ones
+        flaky.set_health(HealthStatus::healthy().with_issue(HealthLevel::Degraded, "slow model"));
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        for _ in 0..2 {
+            let result = orchestrator
+                .route("search", TaskId::new(), Metadata::new())
+                .await
+                .unwrap();
+            assert_eq!(result.get::<String>("agent").as_deref(), Some("steady"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_acl() {
+        let acl = ToolAcl::new()
+            .allow("#", "search")
+            .unwrap()
+            .allow("admin", "#")
+            .unwrap();
+        let orchestrator = Arc::new(Orchestrator::new().with_tool_acl(acl));
+        orchestrator
+            .register(
+                AgentRegistration::new("files", named("files"))
+                    .with_tool("search")
+                    .with_tool("fs.delete"),
+            )
+            .unwrap();
+        for name in ["intern", "admin"] {
+            orchestrator
+                .register(AgentRegistration::new(name, named(name)))
+                .unwrap();
+        }
+        orchestrator.start_all().await.unwrap();
+        let bus = orchestrator.event_bus();
+        bus.register_topic(TOOL_DENIED).await.unwrap();
+        let mut denials = bus.subscribe(TOOL_DENIED).await.unwrap();
+        let intern = orchestrator.tool_handle("intern").unwrap();
+        let admin = orchestrator.tool_handle("admin").unwrap();
+
+        let result = intern.invoke("search", Metadata::new()).await.unwrap();
+        assert_eq!(result.get::<String>("agent").as_deref(), Some("files"));
+
+        // A low-trust agent cannot reach a destructive tool, and is reported
+        // against the task it was working on
+        let task_id = Uuid::new_v4();
+        let err = Cause::task(task_id)
+            .scope(intern.invoke("fs.delete", Metadata::new()))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ToolDenied(agent, tool)) if agent == "intern" && tool == "fs.delete"
+        ));
+        let denial = denials.next().await.unwrap();
+        assert_eq!(
+            denial.payload.get::<String>(AGENT_KEY).as_deref(),
+            Some("intern")
+        );
+        assert_eq!(
+            denial.payload.get::<String>(TOOL_KEY).as_deref(),
+            Some("fs.delete")
+        );
+        assert_eq!(denial.caused_by, Some(task_id));
+
+        assert!(admin.invoke("fs.delete", Metadata::new()).await.is_ok());
+        assert!(admin.invoke("fs.format", Metadata::new()).await.is_err());
+        assert!(orchestrator.tool_handle("stranger").is_err());
+
+        // Tasks naming a tool are checked however they are sent
+        let mut params = Metadata::new();
+        params.insert(TOOL_KEY, "fs.delete");
+        let err = orchestrator
+            .execute("files", TaskId::new(), params)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ToolDenied(agent, _)) if agent == ANONYMOUS
+        ));
+        let mut params = Metadata::new();
+        params.insert(TOOL_KEY, "search");
+        assert!(orchestrator
+            .execute("files", TaskId::new(), params)
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,636 @@
+//! Pools of identical agents sharing a workload
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use atlas_core::{CancellationContext, DynAgent, Event, HealthStatus, Metadata, TaskId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Time after a resize during which a new pool does not shrink
+pub const DEFAULT_SCALE_DOWN_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Creates the agents of a pool
+pub type AgentFactory = Arc<dyn Fn() -> Result<Arc<dyn DynAgent>> + Send + Sync>;
+
+/// How a pool picks the agent running a task
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Balancing {
+    /// Agents take turns
+    #[default]
+    RoundRobin,
+
+    /// The agent running the fewest tasks, taking turns among equals
+    LeastBusy,
+}
+
+/// Load and throughput of a pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PoolStats {
+    /// Agents in the pool
+    pub size: usize,
+
+    /// Tasks running
+    pub in_flight: usize,
+
+    /// Tasks that succeeded
+    pub completed: u64,
+
+    /// Tasks that failed
+    pub failed: u64,
+}
+
+impl PoolStats {
+    /// Get the tasks running per agent
+    pub fn load(&self) -> f64 {
+        if self.size == 0 {
+            return 0.0;
+        }
+        self.in_flight as f64 / self.size as f64
+    }
+}
+
+/// Decides how many agents a pool needs
+///
+/// The pool asks before each task and grows to the answer at once, but
+/// shrinks to it only once its cooldown has passed since it last resized.
+/// Closures taking [`PoolStats`] are autoscalers.
+pub trait Autoscaler: Send + Sync {
+    /// Get the number of agents the pool should have
+    fn desired_size(&self, stats: &PoolStats) -> usize;
+}
+
+impl<F> Autoscaler for F
+where
+    F: Fn(&PoolStats) -> usize + Send + Sync,
+{
+    fn desired_size(&self, stats: &PoolStats) -> usize {
+        self(stats)
+    }
+}
+
+/// Adds an agent when the pool is busy and removes one when it is idle
+///
+/// An agent is only removed if the agents left would not be busy enough to
+/// add it back.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct LoadScaler {
+    /// Fewest agents to keep
+    pub min: usize,
+
+    /// Most agents to run
+    pub max: usize,
+
+    /// Load per agent at or above which an agent is added
+    pub scale_up_load: f64,
+
+    /// Load per agent at or below which an agent is removed
+    pub scale_down_load: f64,
+}
+
+impl LoadScaler {
+    /// Scale between a number of agents
+    pub fn new(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max,
+            scale_up_load: 1.0,
+            scale_down_load: 0.0,
+        }
+    }
+}
+
+impl LoadScaler {
+    /// Whether the pool would stay below the load adding an agent at with
+    /// one agent less
+    fn can_shrink(&self, stats: &PoolStats) -> bool {
+        stats.size > 1 && (stats.in_flight as f64) / ((stats.size - 1) as f64) < self.scale_up_load
+    }
+}
+
+impl Autoscaler for LoadScaler {
+    fn desired_size(&self, stats: &PoolStats) -> usize {
+        let size = if stats.load() >= self.scale_up_load {
+            stats.size + 1
+        } else if stats.load() <= self.scale_down_load && self.can_shrink(stats) {
+            stats.size.saturating_sub(1)
+        } else {
+            stats.size
+        };
+        size.clamp(self.min.max(1), self.max.max(1))
+    }
+}
+
+/// Agent of a pool with the tasks it is running
+struct Instance {
+    /// Agent
+    agent: Arc<dyn DynAgent>,
+
+    /// Tasks running
+    in_flight: AtomicUsize,
+
+    /// Wakes those waiting for the agent to finish its tasks
+    idle: Notify,
+}
+
+impl Instance {
+    /// Wait until the agent runs no tasks
+    async fn drained(&self) {
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+/// Counts a task as running on an instance until dropped
+struct Busy(Arc<Instance>);
+
+impl Busy {
+    fn new(instance: Arc<Instance>) -> Self {
+        instance.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(instance)
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Identical agents sharing tasks, for workloads one agent can't keep up with
+///
+/// A pool is itself an agent: hosted in an [`Orchestrator`](crate::Orchestrator)
+/// it starts, stops and pauses its agents together, and each task or event
+/// it receives goes to one of them.
+pub struct AgentPool {
+    /// Creates agents
+    factory: AgentFactory,
+
+    /// Agents in the pool
+    instances: RwLock<Vec<Arc<Instance>>>,
+
+    /// How agents are picked
+    balancing: Balancing,
+
+    /// Resizes the pool before each task, if set
+    autoscaler: Option<Box<dyn Autoscaler>>,
+
+    /// Time after a resize during which the autoscaler may not shrink the
+    /// pool
+    cooldown: Duration,
+
+    /// When the pool was last resized
+    resized_at: Mutex<Option<Instant>>,
+
+    /// Agents removed from the pool, stopping once they finish their tasks
+    retiring: Mutex<Vec<JoinHandle<()>>>,
+
+    /// Turn of the next task
+    next: AtomicUsize,
+
+    /// Whether the pool was started, so new agents are started too
+    started: AtomicBool,
+
+    /// Tasks that succeeded
+    completed: AtomicU64,
+
+    /// Tasks that failed
+    failed: AtomicU64,
+
+    /// Serializes resizes
+    resizing: tokio::sync::Mutex<()>,
+}
+
+impl AgentPool {
+    /// Create a pool of `size` agents made by a factory
+    pub fn new<F>(factory: F, size: usize) -> Result<Self>
+    where
+        F: Fn() -> Result<Arc<dyn DynAgent>> + Send + Sync + 'static,
+    {
+        if size == 0 {
+            return Err(
+                atlas_core::Error::Config("Agent pools need at least one agent".into()).into(),
+            );
+        }
+        let instances = (0..size)
+            .map(|_| Self::instance(&factory))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            factory: Arc::new(factory),
+            instances: RwLock::new(instances),
+            balancing: Balancing::default(),
+            autoscaler: None,
+            cooldown: DEFAULT_SCALE_DOWN_COOLDOWN,
+            resized_at: Mutex::new(None),
+            retiring: Mutex::new(Vec::new()),
+            next: AtomicUsize::new(0),
+            started: AtomicBool::new(false),
+            completed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            resizing: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Pick agents this way
+    pub fn with_balancing(mut self, balancing: Balancing) -> Self {
+        self.balancing = balancing;
+        self
+    }
+
+    /// Resize the pool before each task as an autoscaler decides
+    pub fn with_autoscaler<A: Autoscaler + 'static>(mut self, autoscaler: A) -> Self {
+        self.autoscaler = Some(Box::new(autoscaler));
+        self
+    }
+
+    /// Set the time after a resize during which the autoscaler may not
+    /// shrink the pool, so it does not shrink and grow back with every
+    /// lull
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Get the number of agents in the pool
+    pub fn size(&self) -> usize {
+        self.read_instances().len()
+    }
+
+    /// Get the agents in the pool
+    pub fn agents(&self) -> Vec<Arc<dyn DynAgent>> {
+        self.read_instances()
+            .iter()
+            .map(|instance| instance.agent.clone())
+            .collect()
+    }
+
+    /// Get the load and throughput of the pool
+    pub fn stats(&self) -> PoolStats {
+        let instances = self.read_instances();
+        PoolStats {
+            size: instances.len(),
+            in_flight: instances
+                .iter()
+                .map(|instance| instance.in_flight.load(Ordering::SeqCst))
+                .sum(),
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Grow or shrink the pool to `size` agents
+    ///
+    /// New agents are started if the pool was; the idlest agents are the
+    /// ones removed. Removed agents take no new tasks and are stopped in the
+    /// background once they finish the tasks they are running; stopping the
+    /// pool waits for them.
+    pub async fn resize(&self, size: usize) -> Result<()> {
+        if size == 0 {
+            return Err(
+                atlas_core::Error::Config("Agent pools need at least one agent".into()).into(),
+            );
+        }
+        let _guard = self.resizing.lock().await;
+        let current = self.size();
+        if size > current {
+            let mut added = Vec::with_capacity(size - current);
+            for _ in current..size {
+                let instance = Self::instance(self.factory.as_ref())?;
+                if self.started.load(Ordering::SeqCst) {
+                    instance.agent.on_start().await?;
+                }
+                added.push(instance);
+            }
+            self.write_instances().extend(added);
+        } else if size < current {
+            let removed = {
+                let mut instances = self.write_instances();
+                instances.sort_by_key(|instance| instance.in_flight.load(Ordering::SeqCst));
+                instances.drain(..current - size).collect::<Vec<_>>()
+            };
+            if self.started.load(Ordering::SeqCst) {
+                let mut retiring = self.lock_retiring();
+                retiring.retain(|retirement| !retirement.is_finished());
+                retiring.extend(removed.into_iter().map(|instance| {
+                    tokio::spawn(async move {
+                        instance.drained().await;
+                        if let Err(e) = instance.agent.on_stop().await {
+                            tracing::warn!(error = %e, "Failed to stop pooled agent");
+                        }
+                    })
+                }));
+            }
+        }
+        *self
+            .resized_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+        tracing::debug!(from = current, to = size, "Resized agent pool");
+        Ok(())
+    }
+
+    /// Resize the pool as its autoscaler decides, returning the new size
+    pub async fn autoscale(&self) -> Result<usize> {
+        let Some(autoscaler) = &self.autoscaler else {
+            return Ok(self.size());
+        };
+        let current = self.size();
+        let size = autoscaler.desired_size(&self.stats());
+        if size < current && self.cooling_down() {
+            return Ok(current);
+        }
+        if size != current {
+            self.resize(size).await?;
+        }
+        Ok(self.size())
+    }
+
+    /// Whether the pool resized too recently to shrink
+    fn cooling_down(&self) -> bool {
+        let resized_at = *self
+            .resized_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        matches!(resized_at, Some(at) if at.elapsed() < self.cooldown)
+    }
+
+    /// Run work on the agent picked for it
+    async fn dispatch<T, F, Fut>(&self, work: F) -> Result<T>
+    where
+        F: FnOnce(Arc<dyn DynAgent>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if let Err(e) = self.autoscale().await {
+            tracing::warn!(error = %e, "Failed to autoscale agent pool");
+        }
+        let busy = self.pick();
+        let result = work(busy.0.agent.clone()).await;
+        drop(busy);
+        let counter = if result.is_ok() {
+            &self.completed
+        } else {
+            &self.failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Pick the agent to run the next task, counting the task as running on
+    /// it before it can be removed
+    fn pick(&self) -> Busy {
+        let instances = self.read_instances();
+        let turn = self.next.fetch_add(1, Ordering::Relaxed) % instances.len();
+        let instance = match self.balancing {
+            Balancing::RoundRobin => &instances[turn],
+            Balancing::LeastBusy => instances[turn..]
+                .iter()
+                .chain(&instances[..turn])
+                .min_by_key(|instance| instance.in_flight.load(Ordering::SeqCst))
+                .unwrap_or(&instances[turn]),
+        };
+        Busy::new(instance.clone())
+    }
+
+    /// Run a lifecycle operation on every agent
+    async fn each<F, Fut>(&self, operation: F) -> Result<()>
+    where
+        F: Fn(Arc<dyn DynAgent>) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        futures::future::try_join_all(self.agents().into_iter().map(operation)).await?;
+        Ok(())
+    }
+
+    /// Make an agent with the factory
+    fn instance(
+        factory: &(dyn Fn() -> Result<Arc<dyn DynAgent>> + Send + Sync),
+    ) -> Result<Arc<Instance>> {
+        Ok(Arc::new(Instance {
+            agent: factory()?,
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }))
+    }
+
+    fn lock_retiring(&self) -> std::sync::MutexGuard<'_, Vec<JoinHandle<()>>> {
+        self.retiring.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn read_instances(&self) -> std::sync::RwLockReadGuard<'_, Vec<Arc<Instance>>> {
+        self.instances
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_instances(&self) -> std::sync::RwLockWriteGuard<'_, Vec<Arc<Instance>>> {
+        self.instances
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Debug for AgentPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentPool")
+            .field("balancing", &self.balancing)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl DynAgent for AgentPool {
+    fn type_name(&self) -> &'static str {
+        "AgentPool"
+    }
+
+    async fn snapshot(&self) -> Result<Metadata> {
+        Ok(Metadata::from_serialize(&self.stats())?)
+    }
+
+    async fn update_state(&self, data: Metadata) -> Result<()> {
+        self.each(|agent| {
+            let data = data.clone();
+            async move { agent.update_state(data).await }
+        })
+        .await
+    }
+
+    async fn handle_event(&self, event: Event) -> Result<()> {
+        self.dispatch(|agent| async move { agent.handle_event(event).await })
+            .await
+    }
+
+    async fn execute_task(&self, task_id: TaskId, params: Metadata) -> Result<Metadata> {
+        self.dispatch(|agent| async move { agent.execute_task(task_id, params).await })
+            .await
+    }
+
+    async fn execute_task_with(
+        &self,
+        task_id: TaskId,
+        params: Metadata,
+        cancel: &CancellationContext,
+    ) -> Result<Metadata> {
+        self.dispatch(|agent| async move { agent.execute_task_with(task_id, params, cancel).await })
+            .await
+    }
+
+    async fn on_start(&self) -> Result<()> {
+        let _guard = self.resizing.lock().await;
+        self.started.store(true, Ordering::SeqCst);
+        self.each(|agent| async move { agent.on_start().await })
+            .await
+    }
+
+    async fn on_stop(&self) -> Result<()> {
+        let _guard = self.resizing.lock().await;
+        self.started.store(false, Ordering::SeqCst);
+        let retiring = std::mem::take(&mut *self.lock_retiring());
+        futures::future::join_all(retiring).await;
+        self.each(|agent| async move { agent.on_stop().await })
+            .await
+    }
+
+    async fn pause(&self) -> Result<()> {
+        self.each(|agent| async move { agent.pause().await }).await
+    }
+
+    async fn resume(&self) -> Result<()> {
+        self.each(|agent| async move { agent.resume().await }).await
+    }
+
+    async fn health(&self) -> HealthStatus {
+        let mut health = HealthStatus::healthy();
+        for agent in self.agents() {
+            health = health.combine(agent.health().await);
+        }
+        health
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::testing::FakeAgent;
+
+    /// Make agents answering with their number after a while, once started
+    fn factory() -> impl Fn() -> Result<Arc<dyn DynAgent>> + Send + Sync {
+        let made = AtomicUsize::new(0);
+        move || {
+            let number = made.fetch_add(1, Ordering::SeqCst);
+            let worker = FakeAgent::new(move |params| async move {
+                let delay = params.get("delay_ms").unwrap_or(0);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                let mut result = Metadata::new();
+                result.insert("worker", number);
+                Ok(result)
+            });
+            Ok(Arc::new(worker.requiring_start()) as Arc<dyn DynAgent>)
+        }
+    }
+
+    async fn worker(pool: &AgentPool, delay_ms: u64) -> usize {
+        let mut params = Metadata::new();
+        params.insert("delay_ms", delay_ms);
+        let result = pool.execute_task(TaskId::new(), params).await.unwrap();
+        result.get("worker").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_balancing() {
+        let pool = AgentPool::new(factory(), 3).unwrap();
+        assert!(pool
+            .execute_task(TaskId::new(), Metadata::new())
+            .await
+            .is_err());
+        pool.on_start().await.unwrap();
+        let mut turns = Vec::new();
+        for _ in 0..4 {
+            turns.push(worker(&pool, 0).await);
+        }
+        assert_eq!(turns, [1, 2, 0, 1]);
+
+        // The busy worker is passed over
+        let pool = AgentPool::new(factory(), 2)
+            .unwrap()
+            .with_balancing(Balancing::LeastBusy);
+        pool.on_start().await.unwrap();
+        let (slow, fast) = tokio::join!(worker(&pool, 50), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            (worker(&pool, 0).await, worker(&pool, 0).await)
+        });
+        assert_eq!((slow, fast), (0, (1, 1)));
+        assert_eq!(pool.stats().completed, 3);
+        assert!(AgentPool::new(factory(), 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_autoscaling() {
+        let pool = AgentPool::new(factory(), 1)
+            .unwrap()
+            .with_autoscaler(LoadScaler::new(1, 3))
+            .with_cooldown(Duration::from_millis(50));
+        pool.on_start().await.unwrap();
+
+        // Each task waiting on a busy pool adds a started agent
+        let workers = futures::future::join_all((0..3).map(|_| worker(&pool, 30))).await;
+        assert_eq!(pool.size(), 3);
+        let mut workers = workers;
+        workers.sort();
+        assert_eq!(workers, [0, 1, 2]);
+
+        // An idle pool shrinks back one agent per cooldown
+        worker(&pool, 0).await;
+        assert_eq!(pool.size(), 3);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        worker(&pool, 0).await;
+        worker(&pool, 0).await;
+        assert_eq!(pool.size(), 2);
+        pool.resize(2).await.unwrap();
+        assert_eq!(pool.agents().len(), 2);
+        assert_eq!(pool.stats().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn test_shrink_drains() {
+        let pool = Arc::new(AgentPool::new(factory(), 2).unwrap());
+        pool.on_start().await.unwrap();
+        let removed = pool.agents()[0].clone();
+        let tasks = [50, 80].map(|delay| {
+            let pool = pool.clone();
+            tokio::spawn(async move { worker(&pool, delay).await })
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // Both agents are busy; the one removed stops once its task is done
+        pool.resize(1).await.unwrap();
+        assert_eq!(pool.size(), 1);
+        let idle = removed.execute_task(TaskId::new(), Metadata::new()).await;
+        assert!(idle.is_ok());
+        let mut workers = Vec::new();
+        for task in tasks {
+            workers.push(task.await.unwrap());
+        }
+        workers.sort();
+        assert_eq!(workers, [0, 1]);
+        pool.on_stop().await.unwrap();
+        let stopped = removed.execute_task(TaskId::new(), Metadata::new()).await;
+        assert!(stopped.is_err());
+    }
+}
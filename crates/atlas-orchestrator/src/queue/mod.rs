@@ -0,0 +1,475 @@
+//! Shared queues agents in several processes lease tasks from
+
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use atlas_core::{CancellationContext, Metadata, TaskId};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::{Error, Orchestrator};
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresTaskQueue;
+#[cfg(feature = "redis")]
+pub use self::redis::RedisTaskQueue;
+
+/// Task waiting in a queue for an agent with its capability
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueuedTask {
+    /// ID of the task; queueing a task twice under one ID queues it once
+    pub id: TaskId,
+
+    /// Capability of the agents that can run the task
+    pub capability: String,
+
+    /// Task parameters
+    #[serde(default)]
+    pub params: Metadata,
+}
+
+impl QueuedTask {
+    /// Queue a task for the agents having a capability
+    pub fn new(capability: impl Into<String>, params: Metadata) -> Self {
+        Self {
+            id: TaskId::new_v7(),
+            capability: capability.into(),
+            params,
+        }
+    }
+}
+
+/// Task leased by a worker, hidden from the other workers until the lease
+/// expires
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Lease {
+    /// Leased task
+    pub task: QueuedTask,
+
+    /// Token telling this lease apart from later leases of the task
+    pub token: Uuid,
+
+    /// Name of the worker holding the lease
+    pub worker: String,
+
+    /// Times the task was leased, this lease included
+    pub attempts: u32,
+}
+
+/// Queue of tasks leased by workers for a visibility timeout
+///
+/// A leased task is hidden from other workers until its lease expires, so a
+/// worker that crashes loses its tasks to its peers once their leases
+/// expire. Workers running long tasks extend their leases to keep them.
+/// The `redis` and `postgres` features add backends sharing a queue between
+/// processes.
+#[async_trait]
+pub trait TaskQueue: Send + Sync {
+    /// Add a task to the back of the queue
+    async fn enqueue(&self, task: QueuedTask) -> Result<()>;
+
+    /// Lease the oldest visible task for a worker, hiding it from the other
+    /// workers for `visibility`
+    async fn lease(&self, worker: &str, visibility: Duration) -> Result<Option<Lease>>;
+
+    /// Keep a lease for `visibility` from now, failing with
+    /// [`Error::LeaseLost`] if the task was leased again since
+    async fn extend(&self, lease: &Lease, visibility: Duration) -> Result<()>;
+
+    /// Remove a leased task once it is done, failing with
+    /// [`Error::LeaseLost`] if the task was leased again since
+    async fn complete(&self, lease: &Lease) -> Result<()>;
+
+    /// Give up a lease, making the task visible again after `delay`
+    async fn release(&self, lease: &Lease, delay: Duration) -> Result<()>;
+
+    /// Get the number of tasks queued or leased
+    async fn len(&self) -> Result<usize>;
+
+    /// Check whether no tasks are queued or leased
+    async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+}
+
+/// Task kept by an [`InMemoryTaskQueue`]
+#[derive(Debug)]
+struct Entry {
+    /// Queued task
+    task: QueuedTask,
+
+    /// Times the task was leased
+    attempts: u32,
+
+    /// When the task becomes visible to workers
+    visible_at: Instant,
+
+    /// Token of the latest lease, if any
+    lease: Option<Uuid>,
+}
+
+/// Task queue shared by the workers of one process
+#[derive(Debug, Default)]
+pub struct InMemoryTaskQueue {
+    /// Tasks in the order they were queued
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl InMemoryTaskQueue {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change the entry of a task under a lease, failing if the task was
+    /// leased again since
+    fn with_lease<T>(
+        &self,
+        lease: &Lease,
+        change: impl FnOnce(&mut Vec<Entry>, usize) -> T,
+    ) -> Result<T> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = entries
+            .iter()
+            .position(|entry| entry.task.id == lease.task.id && entry.lease == Some(lease.token))
+            .ok_or(Error::LeaseLost(lease.task.id))?;
+        Ok(change(&mut entries, index))
+    }
+}
+
+#[async_trait]
+impl TaskQueue for InMemoryTaskQueue {
+    async fn enqueue(&self, task: QueuedTask) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if entries.iter().all(|entry| entry.task.id != task.id) {
+            entries.push(Entry {
+                task,
+                attempts: 0,
+                visible_at: Instant::now(),
+                lease: None,
+            });
+        }
+        Ok(())
+    }
+
+    async fn lease(&self, worker: &str, visibility: Duration) -> Result<Option<Lease>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(entry) = entries.iter_mut().find(|entry| entry.visible_at <= now) else {
+            return Ok(None);
+        };
+        let token = Uuid::new_v4();
+        entry.attempts += 1;
+        entry.visible_at = now + visibility;
+        entry.lease = Some(token);
+        Ok(Some(Lease {
+            task: entry.task.clone(),
+            token,
+            worker: worker.to_string(),
+            attempts: entry.attempts,
+        }))
+    }
+
+    async fn extend(&self, lease: &Lease, visibility: Duration) -> Result<()> {
+        self.with_lease(lease, |entries, index| {
+            entries[index].visible_at = Instant::now() + visibility;
+        })
+    }
+
+    async fn complete(&self, lease: &Lease) -> Result<()> {
+        self.with_lease(lease, |entries, index| {
+            entries.remove(index);
+        })
+    }
+
+    async fn release(&self, lease: &Lease, delay: Duration) -> Result<()> {
+        self.with_lease(lease, |entries, index| {
+            let entry = &mut entries[index];
+            entry.visible_at = Instant::now() + delay;
+            entry.lease = None;
+        })
+    }
+
+    async fn len(&self) -> Result<usize> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len())
+    }
+}
+
+/// Worker leasing tasks from a queue and routing them to the orchestrator's
+/// agents by capability
+///
+/// Each worker process runs one or more workers under unique names. A task
+/// is completed once an agent ran it, and released to be tried again after
+/// the retry delay when it fails, until it was leased `max_attempts` times.
+/// The worker extends its lease while the task runs, and drops the task if
+/// the lease is lost to another worker.
+pub struct QueueWorker {
+    /// Name of the worker, for leases and logs
+    name: String,
+
+    /// Queue the tasks are leased from
+    queue: Arc<dyn TaskQueue>,
+
+    /// Orchestrator routing the tasks to agents
+    orchestrator: Arc<Orchestrator>,
+
+    /// How long a lease hides a task from the other workers
+    visibility: Duration,
+
+    /// Time between leases while the queue is empty
+    poll_interval: Duration,
+
+    /// Delay before a failed task is visible again
+    retry_delay: Duration,
+
+    /// Leases after which a task is dropped rather than run again
+    max_attempts: u32,
+}
+
+impl QueueWorker {
+    /// Create a worker with 30 second leases, polling every second and
+    /// trying tasks up to 5 times
+    pub fn new(
+        name: impl Into<String>,
+        queue: Arc<dyn TaskQueue>,
+        orchestrator: Arc<Orchestrator>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            queue,
+            orchestrator,
+            visibility: Duration::from_secs(30),
+            poll_interval: Duration::from_secs(1),
+            retry_delay: Duration::from_secs(1),
+            max_attempts: 5,
+        }
+    }
+
+    /// Set how long a lease hides a task from the other workers
+    pub fn with_visibility(mut self, visibility: Duration) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Set the time between leases while the queue is empty
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set the delay before a failed task is visible again
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Set the leases after which a task is dropped rather than run again
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Get the name of the worker
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Lease a task and run it, returning whether there was one
+    pub async fn run_once(&self) -> Result<bool> {
+        let Some(lease) = self.queue.lease(&self.name, self.visibility).await? else {
+            return Ok(false);
+        };
+        let task = &lease.task;
+        if lease.attempts > self.max_attempts {
+            tracing::error!(
+                worker = %self.name,
+                task_id = %task.id,
+                attempts = lease.attempts - 1,
+                "Dropping queued task after its last attempt"
+            );
+            self.queue.complete(&lease).await?;
+            return Ok(true);
+        }
+
+        let work = self
+            .orchestrator
+            .route(&task.capability, task.id, task.params.clone());
+        match self.hold(&lease, work).await {
+            Ok(Ok(_)) => self.queue.complete(&lease).await?,
+            Ok(Err(e)) => {
+                tracing::warn!(
+                    worker = %self.name,
+                    task_id = %task.id,
+                    error = %e,
+                    "Queued task failed"
+                );
+                self.queue.release(&lease, self.retry_delay).await?;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    worker = %self.name,
+                    task_id = %task.id,
+                    error = %e,
+                    "Lost the lease of a queued task"
+                );
+            }
+        }
+        Ok(true)
+    }
+
+    /// Run tasks until cancelled, waiting while the queue is empty
+    pub async fn run(&self, cancel: &CancellationContext) {
+        while !cancel.is_cancelled() {
+            let idle = match cancel.run(self.run_once()).await {
+                Ok(leased) => !leased,
+                Err(e) if cancel.is_cancelled() => {
+                    tracing::debug!(worker = %self.name, error = %e, "Queue worker stopped");
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        worker = %self.name,
+                        error = %e,
+                        "Failed to lease a queued task"
+                    );
+                    true
+                }
+            };
+            if idle {
+                let _ = cancel
+                    .run(async {
+                        tokio::time::sleep(self.poll_interval).await;
+                        Ok(())
+                    })
+                    .await;
+            }
+        }
+    }
+
+    /// Do the work of a lease, extending the lease until the work is done
+    ///
+    /// The work is abandoned only once the lease is lost; failing to extend
+    /// it otherwise is retried at the next renewal, while the lease holds.
+    async fn hold<T>(&self, lease: &Lease, work: impl Future<Output = T>) -> Result<T> {
+        let period = (self.visibility / 2).max(Duration::from_millis(1));
+        let mut renewals = tokio::time::interval_at(Instant::now() + period, period);
+        tokio::pin!(work);
+        loop {
+            tokio::select! {
+                output = &mut work => return Ok(output),
+                _ = renewals.tick() => match self.queue.extend(lease, self.visibility).await {
+                    Ok(()) => {}
+                    Err(e) if matches!(e.downcast_ref::<Error>(), Some(Error::LeaseLost(_))) => {
+                        return Err(e);
+                    }
+                    Err(e) => tracing::warn!(
+                        worker = %self.name,
+                        task = %lease.task.id,
+                        error = %e,
+                        "Failed to extend a lease, retrying"
+                    ),
+                },
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for QueueWorker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueueWorker")
+            .field("name", &self.name)
+            .field("visibility", &self.visibility)
+            .field("poll_interval", &self.poll_interval)
+            .field("retry_delay", &self.retry_delay)
+            .field("max_attempts", &self.max_attempts)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AgentRegistration;
+    use atlas_core::testing::FakeAgent;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_lease_expiry() {
+        let queue = InMemoryTaskQueue::new();
+        let task = QueuedTask::new("search", Metadata::new());
+        queue.enqueue(task.clone()).await.unwrap();
+        queue.enqueue(task.clone()).await.unwrap();
+        assert_eq!(queue.len().await.unwrap(), 1);
+
+        let visibility = Duration::from_millis(20);
+        let crashed = queue.lease("crashed", visibility).await.unwrap().unwrap();
+        assert!(queue.lease("peer", visibility).await.unwrap().is_none());
+
+        // The lease of the crashed worker expires and a peer takes the task
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let peer = queue.lease("peer", visibility).await.unwrap().unwrap();
+        assert_eq!((peer.task.id, peer.attempts), (task.id, 2));
+        let e = queue.complete(&crashed).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<Error>(),
+            Some(Error::LeaseLost(_))
+        ));
+
+        queue.extend(&peer, visibility).await.unwrap();
+        queue.complete(&peer).await.unwrap();
+        assert!(queue.is_empty().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_queue_worker() {
+        let orchestrator = Arc::new(Orchestrator::new());
+        // The agent fails its first task
+        let runs = Arc::new(AtomicU32::new(0));
+        let agent = FakeAgent::new({
+            let runs = runs.clone();
+            move |params| {
+                let first = runs.fetch_add(1, Ordering::SeqCst) == 0;
+                async move {
+                    if first {
+                        return Err(atlas_core::Error::Tool("flaky".to_string()).into());
+                    }
+                    Ok(params)
+                }
+            }
+        });
+        orchestrator
+            .register(AgentRegistration::new("flaky", Arc::new(agent)).with_capability("search"))
+            .unwrap();
+        orchestrator.start_all().await.unwrap();
+
+        let queue = Arc::new(InMemoryTaskQueue::new());
+        queue
+            .enqueue(QueuedTask::new("search", Metadata::new()))
+            .await
+            .unwrap();
+        let worker = QueueWorker::new("worker", queue.clone(), orchestrator)
+            .with_retry_delay(Duration::ZERO)
+            .with_max_attempts(2);
+
+        // The first attempt fails and releases the task, the second runs it
+        assert!(worker.run_once().await.unwrap());
+        assert_eq!(queue.len().await.unwrap(), 1);
+        assert!(worker.run_once().await.unwrap());
+        assert!(queue.is_empty().await.unwrap());
+        assert!(!worker.run_once().await.unwrap());
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,245 @@
+//! Postgres backend for the task queue
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use atlas_core::Metadata;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::types::Json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use super::{Lease, QueuedTask, TaskQueue};
+use crate::Error;
+
+/// Statements creating the queue table, applied when connecting
+const SCHEMA: &[&str] = &[
+    r#"CREATE TABLE IF NOT EXISTS atlas_task_queue (
+        queue TEXT NOT NULL,
+        id UUID NOT NULL,
+        capability TEXT NOT NULL,
+        params JSONB NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        lease UUID,
+        worker TEXT,
+        visible_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        queued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        PRIMARY KEY (queue, id)
+    )"#,
+    "CREATE INDEX IF NOT EXISTS atlas_task_queue_visible \
+     ON atlas_task_queue (queue, visible_at)",
+];
+
+/// Task queue shared between processes through a Postgres table
+///
+/// Each task is a row of `atlas_task_queue` under the queue's name, so
+/// queues sharing the table may hold tasks with the same ID. Leasing
+/// skips the rows other workers are leasing at the same moment, so workers
+/// do not wait on each other, and leases are judged by the database's
+/// clock.
+#[derive(Clone, Debug)]
+pub struct PostgresTaskQueue {
+    /// Connection pool
+    pool: PgPool,
+
+    /// Name of the queue
+    name: String,
+}
+
+impl PostgresTaskQueue {
+    /// Connect to a database, creating the queue table if needed, and use
+    /// the queue with a name
+    pub async fn connect(url: &str, name: impl Into<String>) -> Result<Self> {
+        let pool = PgPoolOptions::new().max_connections(5).connect(url).await?;
+        Self::from_pool(pool, name).await
+    }
+
+    /// Use an existing pool, creating the queue table if needed
+    pub async fn from_pool(pool: PgPool, name: impl Into<String>) -> Result<Self> {
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        Ok(Self {
+            pool,
+            name: name.into(),
+        })
+    }
+
+    /// Get the name of the queue
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Make a leased task visible again after a delay, giving up the lease
+    /// if asked to
+    async fn defer(&self, lease: &Lease, delay: Duration, release: bool) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE atlas_task_queue
+             SET visible_at = now() + make_interval(secs => $3),
+                 lease = CASE WHEN $4 THEN NULL ELSE lease END,
+                 worker = CASE WHEN $4 THEN NULL ELSE worker END
+             WHERE queue = $5 AND id = $1 AND lease = $2",
+        )
+        .bind(Uuid::from(lease.task.id))
+        .bind(lease.token)
+        .bind(delay.as_secs_f64())
+        .bind(release)
+        .bind(&self.name)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::LeaseLost(lease.task.id).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TaskQueue for PostgresTaskQueue {
+    async fn enqueue(&self, task: QueuedTask) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO atlas_task_queue (id, queue, capability, params)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (queue, id) DO NOTHING",
+        )
+        .bind(Uuid::from(task.id))
+        .bind(&self.name)
+        .bind(&task.capability)
+        .bind(Json(&task.params))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn lease(&self, worker: &str, visibility: Duration) -> Result<Option<Lease>> {
+        let token = Uuid::new_v4();
+        let row = sqlx::query(
+            "UPDATE atlas_task_queue
+             SET visible_at = now() + make_interval(secs => $2),
+                 lease = $3,
+                 worker = $4,
+                 attempts = attempts + 1
+             WHERE queue = $1 AND id = (
+                 SELECT id FROM atlas_task_queue
+                 WHERE queue = $1 AND visible_at <= now()
+                 ORDER BY visible_at, queued_at
+                 LIMIT 1
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING id, capability, params, attempts",
+        )
+        .bind(&self.name)
+        .bind(visibility.as_secs_f64())
+        .bind(token)
+        .bind(worker)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let params: Json<Metadata> = row.try_get("params")?;
+        Ok(Some(Lease {
+            task: QueuedTask {
+                id: row.try_get::<Uuid, _>("id")?.into(),
+                capability: row.try_get("capability")?,
+                params: params.0,
+            },
+            token,
+            worker: worker.to_string(),
+            attempts: row.try_get::<i32, _>("attempts")? as u32,
+        }))
+    }
+
+    async fn extend(&self, lease: &Lease, visibility: Duration) -> Result<()> {
+        self.defer(lease, visibility, false).await
+    }
+
+    async fn complete(&self, lease: &Lease) -> Result<()> {
+        let result =
+            sqlx::query("DELETE FROM atlas_task_queue WHERE queue = $3 AND id = $1 AND lease = $2")
+                .bind(Uuid::from(lease.task.id))
+                .bind(lease.token)
+                .bind(&self.name)
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::LeaseLost(lease.task.id).into());
+        }
+        Ok(())
+    }
+
+    async fn release(&self, lease: &Lease, delay: Duration) -> Result<()> {
+        self.defer(lease, delay, true).await
+    }
+
+    async fn len(&self) -> Result<usize> {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM atlas_task_queue WHERE queue = $1")
+                .bind(&self.name)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Connect to the queue with a name on the database named by
+    /// `ATLAS_TEST_POSTGRES_URL`, if set
+    async fn queue(name: &str) -> Option<PostgresTaskQueue> {
+        let url = std::env::var("ATLAS_TEST_POSTGRES_URL").ok()?;
+        let queue = PostgresTaskQueue::connect(&url, format!("{}-{}", name, Uuid::new_v4()))
+            .await
+            .unwrap();
+        Some(queue)
+    }
+
+    #[tokio::test]
+    async fn test_leases() {
+        let Some(queue) = queue("leases").await else {
+            return;
+        };
+        let task = QueuedTask::new("search", Metadata::new());
+        queue.enqueue(task.clone()).await.unwrap();
+        queue.enqueue(task.clone()).await.unwrap();
+        assert_eq!(queue.len().await.unwrap(), 1);
+
+        let visibility = Duration::from_millis(200);
+        let crashed = queue.lease("crashed", visibility).await.unwrap().unwrap();
+        assert!(queue.lease("peer", visibility).await.unwrap().is_none());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let peer = queue.lease("peer", visibility).await.unwrap().unwrap();
+        assert_eq!((peer.task.id, peer.attempts), (task.id, 2));
+        let e = queue.complete(&crashed).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<Error>(),
+            Some(Error::LeaseLost(_))
+        ));
+        queue.extend(&peer, visibility).await.unwrap();
+        queue.complete(&peer).await.unwrap();
+        assert_eq!(queue.len().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_queues_share_ids() {
+        let (Some(first), Some(second)) = (queue("first").await, queue("second").await) else {
+            return;
+        };
+        let task = QueuedTask::new("search", Metadata::new());
+        first.enqueue(task.clone()).await.unwrap();
+        second.enqueue(task.clone()).await.unwrap();
+        assert_eq!(second.len().await.unwrap(), 1);
+
+        // Completing the task on one queue leaves it on the other
+        let visibility = Duration::from_secs(30);
+        let lease = first.lease("worker", visibility).await.unwrap().unwrap();
+        first.complete(&lease).await.unwrap();
+        assert!(second.complete(&lease).await.is_err());
+        let lease = second.lease("worker", visibility).await.unwrap().unwrap();
+        assert_eq!(lease.task.id, task.id);
+        second.complete(&lease).await.unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+//! Redis backend for the task queue
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use uuid::Uuid;
+
+use super::{Lease, QueuedTask, TaskQueue};
+use crate::Error;
+
+/// Key prefix used by a new queue
+pub const DEFAULT_KEY_PREFIX: &str = "atlas.queue";
+
+/// Queue a task unless one with its ID is queued already
+///
+/// KEYS: visible, tasks. ARGV: task ID, task.
+const ENQUEUE: &str = r#"
+if redis.call('HSETNX', KEYS[2], ARGV[1], ARGV[2]) == 0 then return 0 end
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+redis.call('ZADD', KEYS[1], now, ARGV[1])
+return 1
+"#;
+
+/// Lease the oldest visible task, returning it and its attempts
+///
+/// KEYS: visible, tasks, leases, attempts. ARGV: visibility in ms, token.
+const LEASE: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local ids = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', now, 'LIMIT', 0, 1)
+if #ids == 0 then return false end
+local id = ids[1]
+redis.call('ZADD', KEYS[1], now + tonumber(ARGV[1]), id)
+redis.call('HSET', KEYS[3], id, ARGV[2])
+local attempts = redis.call('HINCRBY', KEYS[4], id, 1)
+return {redis.call('HGET', KEYS[2], id), attempts}
+"#;
+
+/// Make a leased task visible again after a delay, removing the lease if
+/// asked to
+///
+/// KEYS: visible, leases. ARGV: task ID, token, delay in ms, whether to
+/// remove the lease.
+const DEFER: &str = r#"
+if redis.call('HGET', KEYS[2], ARGV[1]) ~= ARGV[2] then return 0 end
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+redis.call('ZADD', KEYS[1], 'XX', now + tonumber(ARGV[3]), ARGV[1])
+if ARGV[4] == '1' then redis.call('HDEL', KEYS[2], ARGV[1]) end
+return 1
+"#;
+
+/// Remove a leased task
+///
+/// KEYS: visible, tasks, leases, attempts. ARGV: task ID, token.
+const COMPLETE: &str = r#"
+if redis.call('HGET', KEYS[3], ARGV[1]) ~= ARGV[2] then return 0 end
+redis.call('ZREM', KEYS[1], ARGV[1])
+redis.call('HDEL', KEYS[2], ARGV[1])
+redis.call('HDEL', KEYS[3], ARGV[1])
+redis.call('HDEL', KEYS[4], ARGV[1])
+return 1
+"#;
+
+/// Task queue shared between processes over Redis
+///
+/// The queue `<prefix>.<name>` keeps its tasks in the hash `.tasks` and
+/// orders them in the sorted set `.visible` by when they become visible,
+/// so expired leases make their tasks visible again without anyone
+/// noticing the worker crashed. Leases are judged by the Redis server's
+/// clock, so workers need not agree on the time.
+#[derive(Clone)]
+pub struct RedisTaskQueue {
+    /// Connection shared by the queue's operations, reconnecting after
+    /// failures
+    connection: ConnectionManager,
+
+    /// Prefix of the queue's keys
+    prefix: String,
+
+    /// Name of the queue
+    name: String,
+}
+
+impl RedisTaskQueue {
+    /// Connect to a Redis server and use the queue with a name
+    pub async fn connect(url: &str, name: impl Into<String>) -> Result<Self> {
+        let client = redis::Client::open(url).map_err(|e| queue_error("Invalid Redis URL", e))?;
+        Self::from_client(client, name).await
+    }
+
+    /// Use an existing client
+    pub async fn from_client(client: redis::Client, name: impl Into<String>) -> Result<Self> {
+        let connection = client
+            .get_connection_manager()
+            .await
+            .map_err(|e| queue_error("Failed to connect to Redis", e))?;
+        Ok(Self {
+            connection,
+            prefix: DEFAULT_KEY_PREFIX.to_string(),
+            name: name.into(),
+        })
+    }
+
+    /// Set the prefix of the queue's keys
+    pub fn with_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Get the name of the queue
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get a key of the queue
+    fn key(&self, suffix: &str) -> String {
+        format!("{}.{}.{}", self.prefix, self.name, suffix)
+    }
+
+    /// Make a leased task visible again after a delay, giving up the lease
+    /// if asked to
+    async fn defer(&self, lease: &Lease, delay: Duration, release: bool) -> Result<()> {
+        let deferred: i32 = Script::new(DEFER)
+            .key(self.key("visible"))
+            .key(self.key("leases"))
+            .arg(lease.task.id.to_string())
+            .arg(lease.token.to_string())
+            .arg(delay.as_millis() as u64)
+            .arg(if release { "1" } else { "0" })
+            .invoke_async(&mut self.connection.clone())
+            .await
+            .map_err(|e| queue_error("Failed to update lease", e))?;
+        if deferred == 0 {
+            return Err(Error::LeaseLost(lease.task.id).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TaskQueue for RedisTaskQueue {
+    async fn enqueue(&self, task: QueuedTask) -> Result<()> {
+        let encoded = serde_json::to_string(&task)?;
+        let _: i32 = Script::new(ENQUEUE)
+            .key(self.key("visible"))
+            .key(self.key("tasks"))
+            .arg(task.id.to_string())
+            .arg(encoded)
+            .invoke_async(&mut self.connection.clone())
+            .await
+            .map_err(|e| queue_error("Failed to queue task", e))?;
+        Ok(())
+    }
+
+    async fn lease(&self, worker: &str, visibility: Duration) -> Result<Option<Lease>> {
+        let token = Uuid::new_v4();
+        let leased: Option<(String, u32)> = Script::new(LEASE)
+            .key(self.key("visible"))
+            .key(self.key("tasks"))
+            .key(self.key("leases"))
+            .key(self.key("attempts"))
+            .arg(visibility.as_millis() as u64)
+            .arg(token.to_string())
+            .invoke_async(&mut self.connection.clone())
+            .await
+            .map_err(|e| queue_error("Failed to lease task", e))?;
+        let Some((task, attempts)) = leased else {
+            return Ok(None);
+        };
+        Ok(Some(Lease {
+            task: serde_json::from_str(&task)?,
+            token,
+            worker: worker.to_string(),
+            attempts,
+        }))
+    }
+
+    async fn extend(&self, lease: &Lease, visibility: Duration) -> Result<()> {
+        self.defer(lease, visibility, false).await
+    }
+
+    async fn complete(&self, lease: &Lease) -> Result<()> {
+        let completed: i32 = Script::new(COMPLETE)
+            .key(self.key("visible"))
+            .key(self.key("tasks"))
+            .key(self.key("leases"))
+            .key(self.key("attempts"))
+            .arg(lease.task.id.to_string())
+            .arg(lease.token.to_string())
+            .invoke_async(&mut self.connection.clone())
+            .await
+            .map_err(|e| queue_error("Failed to complete task", e))?;
+        if completed == 0 {
+            return Err(Error::LeaseLost(lease.task.id).into());
+        }
+        Ok(())
+    }
+
+    async fn release(&self, lease: &Lease, delay: Duration) -> Result<()> {
+        self.defer(lease, delay, true).await
+    }
+
+    async fn len(&self) -> Result<usize> {
+        self.connection
+            .clone()
+            .zcard(self.key("visible"))
+            .await
+            .map_err(|e| queue_error("Failed to count tasks", e).into())
+    }
+}
+
+impl std::fmt::Debug for RedisTaskQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisTaskQueue")
+            .field("prefix", &self.prefix)
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Describe a failed Redis operation
+fn queue_error(context: &str, e: impl std::fmt::Display) -> Error {
+    Error::Queue(format!("{}: {}", context, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::Metadata;
+
+    /// Connect to a fresh queue on the server named by
+    /// `ATLAS_TEST_REDIS_URL`, if set
+    async fn queue() -> Option<RedisTaskQueue> {
+        let url = std::env::var("ATLAS_TEST_REDIS_URL").ok()?;
+        let queue = RedisTaskQueue::connect(&url, Uuid::new_v4().to_string())
+            .await
+            .unwrap()
+            .with_prefix("atlas.test");
+        Some(queue)
+    }
+
+    #[tokio::test]
+    async fn test_leases() {
+        let Some(queue) = queue().await else {
+            return;
+        };
+        assert!(queue.key("tasks").starts_with("atlas.test."));
+        let task = QueuedTask::new("search", Metadata::new());
+        queue.enqueue(task.clone()).await.unwrap();
+        queue.enqueue(task.clone()).await.unwrap();
+        assert_eq!(queue.len().await.unwrap(), 1);
+
+        let visibility = Duration::from_millis(100);
+        let crashed = queue.lease("crashed", visibility).await.unwrap().unwrap();
+        assert!(queue.lease("peer", visibility).await.unwrap().is_none());
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let peer = queue.lease("peer", visibility).await.unwrap().unwrap();
+        assert_eq!((peer.task.id, peer.attempts), (task.id, 2));
+        let e = queue.complete(&crashed).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<Error>(),
+            Some(Error::LeaseLost(_))
+        ));
+
+        queue.release(&peer, Duration::ZERO).await.unwrap();
+        let retry = queue.lease("peer", visibility).await.unwrap().unwrap();
+        assert_eq!(retry.attempts, 3);
+        queue.complete(&retry).await.unwrap();
+        assert_eq!(queue.len().await.unwrap(), 0);
+    }
+}
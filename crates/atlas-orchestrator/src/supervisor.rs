@@ -0,0 +1,544 @@
+//! Supervising hosted agents and restarting them when they fail
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use atlas_core::{Event, Metadata, ValidationErrors};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+
+use crate::{AgentFailure, Error, Orchestrator};
+
+/// Topic on which a root supervisor announces that it gave up
+pub const SUPERVISOR_ESCALATED: &str = "supervisor.escalated";
+
+/// Children a supervisor restarts when one of them fails
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartStrategy {
+    /// Only the failed child
+    #[default]
+    OneForOne,
+
+    /// Every child, for children that cannot work without each other
+    OneForAll,
+}
+
+/// How often and how quickly a supervisor restarts its children
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RestartPolicy {
+    /// Children restarted when one fails
+    pub strategy: RestartStrategy,
+
+    /// Restarts allowed within the window before the supervisor gives up
+    /// and escalates to its parent
+    pub max_restarts: u32,
+
+    /// Window restarts are counted in, in milliseconds
+    pub window_ms: u64,
+
+    /// Delay before the first restart in the window, in milliseconds; it
+    /// doubles with every further restart
+    pub initial_backoff_ms: u64,
+
+    /// Longest delay before a restart, in milliseconds
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            strategy: RestartStrategy::OneForOne,
+            max_restarts: 3,
+            window_ms: 60_000,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 10_000,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Delay before a restart, given how many restarts the window holds
+    pub fn backoff(&self, restarts: u32) -> Duration {
+        let factor = 2u64.saturating_pow(restarts);
+        Duration::from_millis(
+            self.initial_backoff_ms
+                .saturating_mul(factor)
+                .min(self.max_backoff_ms),
+        )
+    }
+}
+
+/// Child of a supervisor
+#[derive(Clone, Debug)]
+enum Child {
+    /// Agent hosted by the orchestrator, by name
+    Agent(String),
+
+    /// Supervisor of its own children
+    Supervisor(SupervisorSpec),
+}
+
+/// Description of a supervisor and its children, which may be supervisors
+/// themselves
+#[derive(Clone, Debug)]
+pub struct SupervisorSpec {
+    /// Name of the supervisor, for logs and escalations
+    name: String,
+
+    /// How the children are restarted
+    policy: RestartPolicy,
+
+    /// Supervised children, started in order
+    children: Vec<Child>,
+}
+
+impl SupervisorSpec {
+    /// Describe a supervisor with the default policy and no children
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            policy: RestartPolicy::default(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Restart the children following a policy
+    pub fn with_policy(mut self, policy: RestartPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Supervise a hosted agent
+    pub fn with_agent(mut self, name: impl Into<String>) -> Self {
+        self.children.push(Child::Agent(name.into()));
+        self
+    }
+
+    /// Supervise a child supervisor, which escalates to this one when it
+    /// gives up
+    pub fn with_supervisor(mut self, supervisor: SupervisorSpec) -> Self {
+        self.children.push(Child::Supervisor(supervisor));
+        self
+    }
+
+    /// Get the name of the supervisor
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the agents supervised by the supervisor or its descendants, in
+    /// start order
+    pub fn agents(&self) -> Vec<&str> {
+        let mut agents = Vec::new();
+        for child in &self.children {
+            match child {
+                Child::Agent(name) => agents.push(name.as_str()),
+                Child::Supervisor(spec) => agents.extend(spec.agents()),
+            }
+        }
+        agents
+    }
+}
+
+/// Outcome of a failure in a supervision tree
+enum Outcome {
+    /// The failed agent is not in the subtree
+    Unsupervised,
+
+    /// The subtree restarted the failed agent
+    Restarted,
+
+    /// The subtree gave up, stopped its agents and escalates with a reason
+    Escalated(String),
+}
+
+/// Supervisor running within a supervision tree
+struct Node {
+    /// Description of the supervisor
+    spec: SupervisorSpec,
+
+    /// Running child supervisors, at the indexes of their children
+    supervisors: Vec<Option<Node>>,
+
+    /// When the children were restarted within the window
+    restarts: VecDeque<Instant>,
+}
+
+impl Node {
+    /// Prepare a supervisor and its descendants
+    fn new(spec: SupervisorSpec) -> Self {
+        let supervisors = spec
+            .children
+            .iter()
+            .map(|child| match child {
+                Child::Agent(_) => None,
+                Child::Supervisor(spec) => Some(Node::new(spec.clone())),
+            })
+            .collect();
+        Self {
+            spec,
+            supervisors,
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Handle the failure of an agent in the subtree
+    fn handle<'a>(
+        &'a mut self,
+        orchestrator: &'a Orchestrator,
+        failure: &'a AgentFailure,
+    ) -> BoxFuture<'a, Outcome> {
+        Box::pin(async move {
+            let index = self.spec.children.iter().position(|child| match child {
+                Child::Agent(name) => *name == failure.agent,
+                Child::Supervisor(spec) => spec.agents().contains(&failure.agent.as_str()),
+            });
+            let Some(index) = index else {
+                return Outcome::Unsupervised;
+            };
+
+            let mut reason = format!("{} failed: {}", failure.agent, failure.reason);
+            if let Some(child) = &mut self.supervisors[index] {
+                match child.handle(orchestrator, failure).await {
+                    Outcome::Escalated(escalated) => reason = escalated,
+                    outcome => return outcome,
+                }
+            }
+
+            let Some(backoff) = self.admit_restart() else {
+                self.stop(orchestrator).await;
+                return Outcome::Escalated(format!(
+                    "{} exceeded {} restarts after {}",
+                    self.spec.name, self.spec.policy.max_restarts, reason
+                ));
+            };
+            tracing::warn!(
+                supervisor = %self.spec.name,
+                %reason,
+                backoff_ms = backoff.as_millis() as u64,
+                "Restarting after a failure"
+            );
+            tokio::time::sleep(backoff).await;
+
+            let restarted = match self.spec.policy.strategy {
+                RestartStrategy::OneForOne => vec![index],
+                RestartStrategy::OneForAll => (0..self.spec.children.len()).collect(),
+            };
+            if let Err(e) = self.restart(orchestrator, &restarted).await {
+                self.stop(orchestrator).await;
+                return Outcome::Escalated(format!(
+                    "{} could not restart its children: {}",
+                    self.spec.name, e
+                ));
+            }
+            Outcome::Restarted
+        })
+    }
+
+    /// Count a restart, returning the delay before it or `None` if the
+    /// window holds too many restarts already
+    fn admit_restart(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let window = Duration::from_millis(self.spec.policy.window_ms);
+        while self
+            .restarts
+            .front()
+            .is_some_and(|restart| now.duration_since(*restart) > window)
+        {
+            self.restarts.pop_front();
+        }
+        let restarts = self.restarts.len() as u32;
+        if restarts >= self.spec.policy.max_restarts {
+            return None;
+        }
+        self.restarts.push_back(now);
+        Some(self.spec.policy.backoff(restarts))
+    }
+
+    /// Restart children, stopping them all before starting them in order;
+    /// restarted supervisors start counting their restarts afresh
+    async fn restart(&mut self, orchestrator: &Orchestrator, indexes: &[usize]) -> Result<()> {
+        let agents: Vec<String> = indexes
+            .iter()
+            .flat_map(|index| match &self.spec.children[*index] {
+                Child::Agent(name) => vec![name.clone()],
+                Child::Supervisor(spec) => spec.agents().into_iter().map(String::from).collect(),
+            })
+            .collect();
+        for agent in agents.iter().rev() {
+            stop_agent(orchestrator, agent).await;
+        }
+        for agent in &agents {
+            orchestrator.start(agent).await?;
+        }
+        for index in indexes {
+            if let Some(child) = &mut self.supervisors[*index] {
+                child.reset();
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget the restarts of the subtree
+    fn reset(&mut self) {
+        self.restarts.clear();
+        for child in self.supervisors.iter_mut().flatten() {
+            child.reset();
+        }
+    }
+
+    /// Stop every agent of the subtree, last started first
+    async fn stop(&self, orchestrator: &Orchestrator) {
+        for agent in self.spec.agents().into_iter().rev() {
+            stop_agent(orchestrator, agent).await;
+        }
+    }
+}
+
+/// Stop an agent, logging rather than failing if it cannot be stopped
+async fn stop_agent(orchestrator: &Orchestrator, agent: &str) {
+    if let Err(e) = orchestrator.stop(agent).await {
+        tracing::warn!(%agent, error = %e, "Failed to stop agent");
+    }
+}
+
+/// Root of a running supervision tree
+///
+/// The supervisor starts its agents and restarts them according to its
+/// [`RestartPolicy`] when they panic, waiting longer after each restart.
+/// A supervisor restarting its children more often than the policy allows
+/// stops them and escalates to its parent, which restarts it as one of its
+/// children. When the root gives up, its agents stay stopped and the
+/// failure is announced on [`SUPERVISOR_ESCALATED`].
+pub struct Supervisor {
+    /// Name of the root supervisor
+    name: String,
+
+    /// Reason the tree gave up, once it has
+    escalation: watch::Receiver<Option<String>>,
+
+    /// Task handling the failures of the agents
+    monitor: JoinHandle<()>,
+}
+
+impl Supervisor {
+    /// Start the agents of a supervision tree and supervise them
+    ///
+    /// Fails without starting anything if an agent is not hosted by the
+    /// orchestrator or appears more than once in the tree.
+    pub async fn start(orchestrator: &Arc<Orchestrator>, spec: SupervisorSpec) -> Result<Self> {
+        let mut errors = ValidationErrors::new();
+        let mut seen = HashSet::new();
+        for agent in spec.agents() {
+            errors.ensure(
+                orchestrator.state(agent).is_ok(),
+                format!("Agent {} is not registered", agent),
+            );
+            errors.ensure(
+                seen.insert(agent),
+                format!("Agent {} is supervised more than once", agent),
+            );
+        }
+        errors.into_result()?;
+        orchestrator
+            .event_bus()
+            .register_topic(SUPERVISOR_ESCALATED)
+            .await?;
+
+        let failures = orchestrator.failures();
+        for agent in spec.agents() {
+            orchestrator.restart(agent).await?;
+        }
+
+        let name = spec.name.clone();
+        let (escalate, escalation) = watch::channel(None);
+        let monitor = tokio::spawn(monitor(
+            Arc::downgrade(orchestrator),
+            Node::new(spec),
+            failures,
+            escalate,
+        ));
+        Ok(Self {
+            name,
+            escalation,
+            monitor,
+        })
+    }
+
+    /// Get the name of the root supervisor
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get why the tree gave up, if it has
+    pub fn escalation(&self) -> Option<String> {
+        self.escalation.borrow().clone()
+    }
+
+    /// Fail with [`Error::Escalated`] if the tree gave up
+    pub fn check(&self) -> Result<()> {
+        match self.escalation() {
+            Some(reason) => Err(Error::Escalated(self.name.clone(), reason).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.monitor.abort();
+    }
+}
+
+/// Handle the failures of a tree's agents until it gives up or the
+/// orchestrator is dropped
+async fn monitor(
+    orchestrator: Weak<Orchestrator>,
+    mut root: Node,
+    mut failures: broadcast::Receiver<AgentFailure>,
+    escalate: watch::Sender<Option<String>>,
+) {
+    loop {
+        let failure = match failures.recv().await {
+            Ok(failure) => failure,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                tracing::warn!(supervisor = %root.spec.name, missed, "Missed agent failures");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let Some(orchestrator) = orchestrator.upgrade() else {
+            return;
+        };
+        let Outcome::Escalated(reason) = root.handle(&orchestrator, &failure).await else {
+            continue;
+        };
+
+        tracing::error!(supervisor = %root.spec.name, %reason, "Supervisor gave up");
+        let mut payload = Metadata::new();
+        payload.insert("supervisor", &root.spec.name);
+        payload.insert("agent", &failure.agent);
+        payload.insert("reason", &reason);
+        let event = Event::new(SUPERVISOR_ESCALATED, payload);
+        if let Err(e) = orchestrator.publish(event).await {
+            tracing::warn!(error = %e, "Failed to announce the escalation");
+        }
+        escalate.send_replace(Some(reason));
+        return;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentRegistration, RunState};
+    use atlas_core::testing::FakeAgent;
+    use atlas_core::TaskId;
+    use futures::StreamExt;
+
+    /// Agent panicking on every task and event
+    fn crashing() -> FakeAgent {
+        FakeAgent::new(|_| -> std::future::Ready<Result<Metadata>> { panic!("task crashed") })
+            .on_event(|_| panic!("event handler crashed"))
+    }
+
+    fn policy(strategy: RestartStrategy, max_restarts: u32) -> RestartPolicy {
+        RestartPolicy {
+            strategy,
+            max_restarts,
+            initial_backoff_ms: 1,
+            ..Default::default()
+        }
+    }
+
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RestartPolicy {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 500,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(500));
+        assert_eq!(policy.backoff(100), Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_restart_and_escalation() {
+        let orchestrator = Arc::new(Orchestrator::new());
+        let crashing = Arc::new(crashing());
+        let steady = Arc::new(FakeAgent::echo());
+        orchestrator
+            .register(AgentRegistration::new("crashing", crashing.clone()).with_topic("poke"))
+            .unwrap();
+        orchestrator
+            .register(AgentRegistration::new("steady", steady.clone()))
+            .unwrap();
+        let spec = SupervisorSpec::new("root")
+            .with_policy(policy(RestartStrategy::OneForOne, 3))
+            .with_agent("steady")
+            .with_supervisor(
+                SupervisorSpec::new("workers")
+                    .with_policy(policy(RestartStrategy::OneForAll, 1))
+                    .with_agent("crashing"),
+            );
+        assert!(
+            Supervisor::start(&orchestrator, spec.clone().with_agent("missing"))
+                .await
+                .is_err()
+        );
+        let supervisor = Supervisor::start(&orchestrator, spec).await.unwrap();
+        let mut escalations = orchestrator
+            .event_bus()
+            .subscribe(SUPERVISOR_ESCALATED)
+            .await
+            .unwrap();
+        assert_eq!(crashing.starts(), 1);
+
+        // A panicking task fails the caller and the agent, which restarts
+        let err = orchestrator
+            .execute("crashing", TaskId::new(), Metadata::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Agent crashing failed: task crashed");
+        settle().await;
+        assert_eq!(crashing.starts(), 2);
+        assert_eq!(orchestrator.state("crashing").unwrap(), RunState::Running);
+
+        // Too many restarts escalate to the root, which restarts the workers
+        orchestrator
+            .publish(Event::new("poke", Metadata::new()))
+            .await
+            .unwrap();
+        settle().await;
+        assert_eq!(crashing.starts(), 3);
+        assert_eq!(steady.starts(), 1);
+        assert!(supervisor.check().is_ok());
+
+        // Once the root gives up too, the agents stay stopped
+        for _ in 0..6 {
+            let _ = orchestrator
+                .execute("crashing", TaskId::new(), Metadata::new())
+                .await;
+            settle().await;
+        }
+        assert!(supervisor.escalation().is_some());
+        assert!(supervisor.check().is_err());
+        assert_eq!(orchestrator.state("steady").unwrap(), RunState::Stopped);
+        let event = escalations.next().await.unwrap();
+        assert_eq!(
+            event.payload.get::<String>("supervisor").as_deref(),
+            Some("root")
+        );
+    }
+}
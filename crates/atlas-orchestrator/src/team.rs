@@ -0,0 +1,478 @@
+//! Teams of agents working together on a goal under roles
+
+use std::fmt;
+
+use anyhow::Result;
+use atlas_core::{Metadata, TaskId, ValidationErrors};
+use serde::{Deserialize, Serialize};
+
+use crate::Orchestrator;
+
+/// Parameter holding the goal the team works on
+pub const GOAL_KEY: &str = "goal";
+
+/// Parameter holding the role a member works in
+pub const ROLE_KEY: &str = "role";
+
+/// Parameter holding the output of the previous member
+pub const INPUT_KEY: &str = "input";
+
+/// Parameter holding the reviews rejecting the work
+pub const FEEDBACK_KEY: &str = "feedback";
+
+/// Result field in which a reviewer approves the work
+pub const APPROVED_KEY: &str = "approved";
+
+/// Part a member plays in a team
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Breaks the goal down into steps
+    Planner,
+
+    /// Gathers what the work needs
+    Researcher,
+
+    /// Does the work
+    Executor,
+
+    /// Checks the work, approving it or sending it back with feedback
+    Reviewer,
+
+    /// Application-defined role
+    Custom(String),
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Planner => write!(f, "planner"),
+            Role::Researcher => write!(f, "researcher"),
+            Role::Executor => write!(f, "executor"),
+            Role::Reviewer => write!(f, "reviewer"),
+            Role::Custom(role) => write!(f, "{}", role),
+        }
+    }
+}
+
+/// How the members of a team hand work to each other
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Protocol {
+    /// Every member works once, in the order they joined, on the output of
+    /// the member before
+    #[default]
+    Sequential,
+
+    /// The members other than reviewers work in order, then every reviewer
+    /// checks the result; work a reviewer rejects goes back to the
+    /// executors with the feedback, for at most `max_rounds` rounds in all
+    Review {
+        /// Rounds of execution and review before giving up
+        max_rounds: u32,
+    },
+}
+
+/// Hosted agent taking part in a team
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TeamMember {
+    /// Part the agent plays
+    pub role: Role,
+
+    /// Name of the agent within the orchestrator
+    pub name: String,
+}
+
+/// Work one member did for the team
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Handoff {
+    /// Name of the member
+    pub member: String,
+
+    /// Part the member played
+    pub role: Role,
+
+    /// Round of the work, from 1
+    pub round: u32,
+
+    /// Task the member ran
+    pub task_id: TaskId,
+
+    /// Parameters the member received
+    pub input: Metadata,
+
+    /// Result the member handed on
+    pub output: Metadata,
+}
+
+/// Result of a team working on a goal
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TeamOutcome {
+    /// Goal the team worked on
+    pub goal: String,
+
+    /// Output of the last member doing the work
+    pub result: Metadata,
+
+    /// Whether every reviewer approved the result; always true without a
+    /// review
+    pub approved: bool,
+
+    /// Rounds of work done
+    pub rounds: u32,
+
+    /// Work of every member, in order
+    pub handoffs: Vec<Handoff>,
+}
+
+/// Hosted agents grouped under roles, working together on goals
+///
+/// [`Team::run`] has the [`Orchestrator`] hosting the members run their
+/// work, so their tasks are authorized and their panics reported like any
+/// other. It passes each member a task with the [`GOAL_KEY`], its
+/// [`ROLE_KEY`] and, under [`INPUT_KEY`], the output of the member before
+/// it; the first member's input is empty. Under [`Protocol::Review`],
+/// reviewers answer with an [`APPROVED_KEY`] flag, and the outputs of the
+/// ones rejecting the work reach the executors as a list under
+/// [`FEEDBACK_KEY`].
+#[derive(Clone, Debug)]
+pub struct Team {
+    /// Name of the team, for logs
+    name: String,
+
+    /// Members, in the order they joined
+    members: Vec<TeamMember>,
+
+    /// How the members hand work to each other
+    protocol: Protocol,
+}
+
+impl Team {
+    /// Create a team with no members working sequentially
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            members: Vec::new(),
+            protocol: Protocol::default(),
+        }
+    }
+
+    /// Add a hosted agent playing a role
+    pub fn with_member(mut self, role: Role, name: impl Into<String>) -> Self {
+        self.members.push(TeamMember {
+            role,
+            name: name.into(),
+        });
+        self
+    }
+
+    /// Hand work between the members following a protocol
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Get the name of the team
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the members, in the order they joined
+    pub fn members(&self) -> &[TeamMember] {
+        &self.members
+    }
+
+    /// Get the members playing a role
+    pub fn with_role<'a>(&'a self, role: &'a Role) -> impl Iterator<Item = &'a TeamMember> {
+        self.members
+            .iter()
+            .filter(move |member| member.role == *role)
+    }
+
+    /// Work on a goal, handing it between the members hosted by an
+    /// orchestrator
+    ///
+    /// Fails listing every problem if the members are not hosted or cannot
+    /// follow the protocol, and with the first member's error if one fails.
+    pub async fn run(
+        &self,
+        orchestrator: &Orchestrator,
+        goal: impl Into<String>,
+    ) -> Result<TeamOutcome> {
+        self.validate(orchestrator)?;
+        let mut run = Run {
+            orchestrator,
+            goal: goal.into(),
+            handoffs: Vec::new(),
+        };
+        tracing::info!(team = %self.name, goal = %run.goal, "Team started");
+
+        let outcome = match self.protocol {
+            Protocol::Sequential => {
+                let result = run.work(&self.members, Metadata::new(), 1).await?;
+                run.finish(result, true, 1)
+            }
+            Protocol::Review { max_rounds } => {
+                let (workers, reviewers): (Vec<_>, Vec<_>) = self
+                    .members
+                    .iter()
+                    .cloned()
+                    .partition(|member| member.role != Role::Reviewer);
+                let executors: Vec<_> = self.with_role(&Role::Executor).cloned().collect();
+
+                let mut work = run.work(&workers, Metadata::new(), 1).await?;
+                let mut round = 1;
+                loop {
+                    let mut rejections = Vec::new();
+                    for reviewer in &reviewers {
+                        let review = run
+                            .work(std::slice::from_ref(reviewer), work.clone(), round)
+                            .await?;
+                        if !review.get::<bool>(APPROVED_KEY).unwrap_or(false) {
+                            rejections.push(review);
+                        }
+                    }
+                    if rejections.is_empty() {
+                        break run.finish(work, true, round);
+                    }
+                    if round >= max_rounds {
+                        tracing::warn!(team = %self.name, rounds = round, "Work was not approved");
+                        break run.finish(work, false, round);
+                    }
+                    round += 1;
+                    let mut input = work;
+                    input.insert(FEEDBACK_KEY, rejections);
+                    work = run.work(&executors, input, round).await?;
+                }
+            }
+        };
+        tracing::info!(
+            team = %self.name,
+            approved = outcome.approved,
+            rounds = outcome.rounds,
+            "Team finished"
+        );
+        Ok(outcome)
+    }
+
+    /// Check that the members are hosted and can follow the protocol
+    fn validate(&self, orchestrator: &Orchestrator) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+        errors.ensure(
+            !self.members.is_empty(),
+            format!("Team {} has no members", self.name),
+        );
+        for member in &self.members {
+            errors.ensure(
+                orchestrator.agent(&member.name).is_some(),
+                format!("Team {} member {} is not hosted", self.name, member.name),
+            );
+        }
+        if let Protocol::Review { max_rounds } = self.protocol {
+            errors.ensure(max_rounds > 0, "Reviews need at least one round");
+            for role in [Role::Executor, Role::Reviewer] {
+                errors.ensure(
+                    self.with_role(&role).next().is_some(),
+                    format!("Team {} has no {}, which reviews need", self.name, role),
+                );
+            }
+        }
+        errors.into_result()
+    }
+}
+
+/// Work in progress on a goal
+struct Run<'a> {
+    /// Orchestrator running the members' work
+    orchestrator: &'a Orchestrator,
+
+    /// Goal being worked on
+    goal: String,
+
+    /// Work done so far
+    handoffs: Vec<Handoff>,
+}
+
+impl Run<'_> {
+    /// Have members work in order, each on the output of the one before,
+    /// returning the output of the last
+    async fn work(
+        &mut self,
+        members: &[TeamMember],
+        mut input: Metadata,
+        round: u32,
+    ) -> Result<Metadata> {
+        for member in members {
+            let mut params = Metadata::new();
+            params.insert(GOAL_KEY, &self.goal);
+            params.insert(ROLE_KEY, member.role.to_string());
+            params.insert(INPUT_KEY, input);
+
+            let task_id = TaskId::new();
+            tracing::debug!(member = %member.name, role = %member.role, round, "Handing off");
+            let output = self
+                .orchestrator
+                .execute(&member.name, task_id, params.clone())
+                .await?;
+            self.handoffs.push(Handoff {
+                member: member.name.clone(),
+                role: member.role.clone(),
+                round,
+                task_id,
+                input: params,
+                output: output.clone(),
+            });
+            input = output;
+        }
+        Ok(input)
+    }
+
+    /// Conclude the run
+    fn finish(self, result: Metadata, approved: bool, rounds: u32) -> TeamOutcome {
+        TeamOutcome {
+            goal: self.goal,
+            result,
+            approved,
+            rounds,
+            handoffs: self.handoffs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AgentRegistration;
+    use atlas_core::testing::FakeAgent;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Agent answering tasks with a closure of their parameters
+    fn answering(answer: impl Fn(&Metadata) -> Metadata + Send + Sync + 'static) -> FakeAgent {
+        FakeAgent::new(move |params| {
+            let output = answer(&params);
+            async move { Ok(output) }
+        })
+    }
+
+    /// Host and start agents under their names
+    async fn host<const N: usize>(members: [(&str, FakeAgent); N]) -> Orchestrator {
+        let orchestrator = Orchestrator::new();
+        for (name, agent) in members {
+            orchestrator
+                .register(AgentRegistration::new(name, Arc::new(agent)))
+                .unwrap();
+        }
+        orchestrator.start_all().await.unwrap();
+        orchestrator
+    }
+
+    fn output(key: &str, value: impl Serialize) -> Metadata {
+        let mut output = Metadata::new();
+        output.insert(key, value);
+        output
+    }
+
+    #[tokio::test]
+    async fn test_sequential_team() {
+        let planner = |params: &Metadata| {
+            output(
+                "plan",
+                format!("outline {}", params.get::<String>(GOAL_KEY).unwrap()),
+            )
+        };
+        let writer = |params: &Metadata| {
+            let input: Metadata = params.get(INPUT_KEY).unwrap();
+            output(
+                "draft",
+                format!("draft from {}", input.get::<String>("plan").unwrap()),
+            )
+        };
+        let orchestrator = host([
+            ("planner", answering(planner)),
+            ("writer", answering(writer)),
+        ])
+        .await;
+        let team = Team::new("writers")
+            .with_member(Role::Planner, "planner")
+            .with_member(Role::Executor, "writer");
+        let outcome = team.run(&orchestrator, "a report").await.unwrap();
+        assert!(outcome.approved);
+        assert_eq!(
+            outcome.result.get::<String>("draft").as_deref(),
+            Some("draft from outline a report")
+        );
+        let roles: Vec<_> = outcome.handoffs.iter().map(|h| h.role.clone()).collect();
+        assert_eq!(roles, [Role::Planner, Role::Executor]);
+        assert!(Team::new("empty")
+            .run(&orchestrator, "anything")
+            .await
+            .is_err());
+
+        // Members run only while the orchestrator hosts and runs them
+        let err = team
+            .clone()
+            .with_member(Role::Reviewer, "missing")
+            .run(&orchestrator, "a report")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("member missing is not hosted"));
+        orchestrator.stop("writer").await.unwrap();
+        assert!(team.run(&orchestrator, "a report").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_review_rounds() {
+        let drafts = Arc::new(AtomicU32::new(0));
+        let writer = {
+            let drafts = drafts.clone();
+            move |params: &Metadata| {
+                let input: Metadata = params.get(INPUT_KEY).unwrap();
+                let revised = input.get::<Vec<Metadata>>(FEEDBACK_KEY).is_some();
+                output(
+                    "draft",
+                    drafts.fetch_add(1, Ordering::SeqCst) + u32::from(revised),
+                )
+            }
+        };
+        let editor = |params: &Metadata| {
+            let input: Metadata = params.get(INPUT_KEY).unwrap();
+            let draft: u32 = input.get("draft").unwrap();
+            let mut review = output(APPROVED_KEY, draft >= 2);
+            review.insert("note", "needs more detail");
+            review
+        };
+        let orchestrator = host([
+            ("researcher", answering(|_| output("facts", 3))),
+            ("writer", answering(writer)),
+            ("editor", answering(editor)),
+        ])
+        .await;
+        let team = Team::new("writers")
+            .with_protocol(Protocol::Review { max_rounds: 3 })
+            .with_member(Role::Researcher, "researcher")
+            .with_member(Role::Executor, "writer")
+            .with_member(Role::Reviewer, "editor");
+        let outcome = team.run(&orchestrator, "a report").await.unwrap();
+        assert!(outcome.approved);
+        assert_eq!(outcome.rounds, 2);
+        assert_eq!(outcome.result.get::<u32>("draft"), Some(2));
+        assert_eq!(outcome.handoffs.len(), 5);
+
+        let strict = team
+            .clone()
+            .with_protocol(Protocol::Review { max_rounds: 1 });
+        drafts.store(0, Ordering::SeqCst);
+        let outcome = strict.run(&orchestrator, "a report").await.unwrap();
+        assert!(!outcome.approved);
+        assert_eq!(outcome.rounds, 1);
+
+        let err = Team::new("solo")
+            .with_protocol(Protocol::Review { max_rounds: 2 })
+            .with_member(Role::Executor, "writer")
+            .run(&orchestrator, "a report")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has no reviewer"));
+    }
+}
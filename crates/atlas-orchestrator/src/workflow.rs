@@ -0,0 +1,962 @@
+//! Declarative workflows of agent tasks, run and resumed by an orchestrator
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use atlas_core::{ConfigFormat, ConfigLoader, Metadata, TaskId, ValidationErrors};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Error, Orchestrator};
+
+/// Task parameter naming the tool a [`Action::Task`] step runs
+pub const TOOL_KEY: &str = "tool";
+
+/// Workflow of steps run by an [`Orchestrator`]
+///
+/// Steps run in order. Task parameters may map data from earlier steps:
+/// a string that is exactly `{{path}}` is replaced by the value at the
+/// dotted path, and `{{path}}` within a longer string by its text. Paths
+/// start with `input`, the workflow's input, or `steps.<id>`, the output of
+/// a completed step. When a step fails, the completed steps declaring
+/// compensations are undone, latest first, saga style. Workflows are usually
+/// loaded from JSON or YAML files.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Workflow {
+    /// Name of the workflow
+    pub name: String,
+
+    /// What the workflow does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Steps, run in order
+    pub steps: Vec<Step>,
+}
+
+/// Step of a workflow
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Step {
+    /// Identifier of the step, unique within the workflow
+    pub id: String,
+
+    /// What the step does
+    #[serde(flatten)]
+    pub action: Action,
+
+    /// Task undoing the step, run when a later step fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compensate: Option<TaskStep>,
+}
+
+/// What a workflow step does
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Execute a task on a hosted agent
+    Task(TaskStep),
+
+    /// Run steps at the same time, waiting for all of them
+    Parallel {
+        /// Steps to run
+        steps: Vec<Step>,
+    },
+
+    /// Run one list of steps or another depending on a condition
+    Branch {
+        /// Condition choosing the steps
+        condition: Condition,
+
+        /// Steps run when the condition holds
+        #[serde(default)]
+        then: Vec<Step>,
+
+        /// Steps run when it does not
+        #[serde(default, rename = "else")]
+        otherwise: Vec<Step>,
+    },
+
+    /// Run steps again and again while a condition holds
+    Loop {
+        /// Condition checked before each iteration
+        condition: Condition,
+
+        /// Steps of an iteration
+        steps: Vec<Step>,
+
+        /// Iterations after which the loop ends even if the condition holds
+        max_iterations: u32,
+    },
+}
+
+/// Task a workflow step executes on an agent, named or routed to by
+/// capability
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TaskStep {
+    /// Agent to execute the task on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+
+    /// Capability to route the task by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capability: Option<String>,
+
+    /// Tool to run, passed as the task's [`TOOL_KEY`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+
+    /// Task parameters, which may map data from earlier steps
+    #[serde(default)]
+    pub params: Metadata,
+
+    /// Attempts after the first before the step fails
+    #[serde(default)]
+    pub retries: u32,
+
+    /// Delay between attempts, in milliseconds
+    #[serde(default)]
+    pub retry_delay_ms: u64,
+}
+
+/// Condition on the workflow's input and step outputs
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// The value at a path equals a value
+    Equals {
+        /// Dotted path of the value
+        path: String,
+
+        /// Value to compare with
+        value: Value,
+    },
+
+    /// A path has a value other than null
+    Exists {
+        /// Dotted path of the value
+        path: String,
+    },
+
+    /// A condition does not hold
+    Not(Box<Condition>),
+
+    /// Every condition holds
+    All(Vec<Condition>),
+
+    /// At least one condition holds
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    /// Whether the condition holds in a context
+    pub fn evaluate(&self, context: &Metadata) -> bool {
+        match self {
+            Condition::Equals { path, value } => {
+                context.get_path::<Value>(path).as_ref() == Some(value)
+            }
+            Condition::Exists { path } => context
+                .get_path::<Value>(path)
+                .is_some_and(|value| !value.is_null()),
+            Condition::Not(condition) => !condition.evaluate(context),
+            Condition::All(conditions) => conditions.iter().all(|c| c.evaluate(context)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.evaluate(context)),
+        }
+    }
+}
+
+/// Progress of a step
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepState {
+    /// Not started
+    #[default]
+    Pending,
+
+    /// Started but not finished, or interrupted
+    Running,
+
+    /// Finished successfully
+    Completed,
+
+    /// Failed after its last attempt
+    Failed,
+
+    /// Undone by its compensation after a later step failed
+    Compensated,
+}
+
+/// Status of a step in a run
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StepStatus {
+    /// Progress of the step
+    pub state: StepState,
+
+    /// Attempts made at a task, or iterations started by a loop
+    pub attempts: u32,
+
+    /// Iterations a loop finished
+    #[serde(default)]
+    pub iterations: u32,
+
+    /// Output of a completed task, or the branch a branch step took
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Metadata>,
+
+    /// Error of the last failed attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Progress of a run
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// Steps are being run
+    Running,
+
+    /// Every step completed
+    Completed,
+
+    /// A step failed, and completed steps could not all be undone; the run
+    /// can be resumed
+    Failed,
+
+    /// A step failed, and the completed steps with compensations were
+    /// undone; the run can be resumed to redo them
+    Compensated,
+}
+
+/// Compensation run to undo a step
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Compensation {
+    /// ID of the step undone
+    pub step: String,
+
+    /// Attempts made at the compensation
+    pub attempts: u32,
+
+    /// Output of the compensation, if it succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Metadata>,
+
+    /// Error of the last attempt, if it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Run of a workflow, saved to resume it after a failure or a restart
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WorkflowRun {
+    /// Name of the workflow
+    pub workflow: String,
+
+    /// Input of the run
+    pub input: Metadata,
+
+    /// Progress of the run
+    pub status: RunStatus,
+
+    /// Status of each step that started, by ID
+    pub steps: BTreeMap<String, StepStatus>,
+
+    /// Error the run failed with, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// IDs of the completed steps, in the order they completed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completed: Vec<String>,
+
+    /// Compensations run after the run failed, in the order they ran
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compensations: Vec<Compensation>,
+}
+
+impl WorkflowRun {
+    /// Start a run of a workflow
+    pub fn new(workflow: &Workflow, input: Metadata) -> Self {
+        Self {
+            workflow: workflow.name.clone(),
+            input,
+            status: RunStatus::Running,
+            steps: BTreeMap::new(),
+            error: None,
+            completed: Vec::new(),
+            compensations: Vec::new(),
+        }
+    }
+
+    /// Get the status of a step
+    pub fn step(&self, id: &str) -> Option<&StepStatus> {
+        self.steps.get(id)
+    }
+
+    /// Get the output of a completed step
+    pub fn output(&self, id: &str) -> Option<&Metadata> {
+        self.steps
+            .get(id)
+            .filter(|status| status.state == StepState::Completed)
+            .and_then(|status| status.output.as_ref())
+    }
+
+    /// Get what step parameters and conditions see: the input and the
+    /// outputs of the completed steps
+    pub fn context(&self) -> Metadata {
+        let outputs: BTreeMap<&str, &Metadata> = self
+            .steps
+            .keys()
+            .filter_map(|id| Some((id.as_str(), self.output(id)?)))
+            .collect();
+        let mut context = Metadata::new();
+        context.insert("input", &self.input);
+        context.insert("steps", outputs);
+        context
+    }
+
+    /// Get the status of a step to change it
+    fn status(&mut self, id: &str) -> &mut StepStatus {
+        self.steps.entry(id.to_string()).or_default()
+    }
+}
+
+impl Workflow {
+    /// Parse a workflow in a format and validate it
+    pub fn parse(format: ConfigFormat, text: &str) -> Result<Self> {
+        let workflow: Self = serde_json::from_value(format.parse(text)?)
+            .map_err(|e| atlas_core::Error::Config(format!("Invalid workflow: {}", e)))?;
+        workflow.validate()?;
+        Ok(workflow)
+    }
+
+    /// Load a workflow from a JSON, YAML or TOML file and validate it
+    ///
+    /// `${VAR}` references to environment variables are expanded.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let workflow: Self = ConfigLoader::new().with_file(path).load()?;
+        workflow.validate()?;
+        Ok(workflow)
+    }
+
+    /// Check the steps, listing every problem found
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = ValidationErrors::new();
+        errors.ensure(!self.name.trim().is_empty(), "Workflow name is required");
+        errors.ensure(!self.steps.is_empty(), "Workflow has no steps");
+        check_steps(&self.steps, &mut HashSet::new(), &mut errors);
+        errors.into_result()
+    }
+
+    /// Get the names of the agents the tasks run on, sorted
+    pub fn agents(&self) -> Vec<&str> {
+        let mut agents = Vec::new();
+        collect_agents(&self.steps, &mut agents);
+        agents.sort();
+        agents.dedup();
+        agents
+    }
+}
+
+/// Check steps and the steps within them
+fn check_steps<'a>(steps: &'a [Step], ids: &mut HashSet<&'a str>, errors: &mut ValidationErrors) {
+    for step in steps {
+        if step.id.trim().is_empty() {
+            errors.push("Step IDs must not be blank");
+        } else if !ids.insert(&step.id) {
+            errors.push(format!("Step {} is defined more than once", step.id));
+        }
+        if let Some(task) = &step.compensate {
+            errors.ensure(
+                task.agent.is_some() != task.capability.is_some(),
+                format!(
+                    "Compensation of {} needs either an agent or a capability",
+                    step.id
+                ),
+            );
+        }
+        match &step.action {
+            Action::Task(task) => errors.ensure(
+                task.agent.is_some() != task.capability.is_some(),
+                format!("Task {} needs either an agent or a capability", step.id),
+            ),
+            Action::Parallel { steps } => check_steps(steps, ids, errors),
+            Action::Branch {
+                then, otherwise, ..
+            } => {
+                check_steps(then, ids, errors);
+                check_steps(otherwise, ids, errors);
+            }
+            Action::Loop {
+                steps,
+                max_iterations,
+                ..
+            } => {
+                errors.ensure(
+                    *max_iterations > 0,
+                    format!("Loop {} needs at least one iteration", step.id),
+                );
+                check_steps(steps, ids, errors);
+            }
+        }
+    }
+}
+
+/// Collect the agents tasks name
+fn collect_agents<'a>(steps: &'a [Step], agents: &mut Vec<&'a str>) {
+    for step in steps {
+        for steps in step.action.children() {
+            collect_agents(steps, agents);
+        }
+        if let Action::Task(TaskStep {
+            agent: Some(agent), ..
+        }) = &step.action
+        {
+            agents.push(agent);
+        }
+        if let Some(TaskStep {
+            agent: Some(agent), ..
+        }) = &step.compensate
+        {
+            agents.push(agent);
+        }
+    }
+}
+
+/// Find a step by ID among steps and the steps within them
+fn find_step<'a>(steps: &'a [Step], id: &str) -> Option<&'a Step> {
+    steps.iter().find_map(|step| {
+        if step.id == id {
+            return Some(step);
+        }
+        step.action
+            .children()
+            .into_iter()
+            .find_map(|steps| find_step(steps, id))
+    })
+}
+
+impl Action {
+    /// Get the lists of steps within the step
+    fn children(&self) -> Vec<&[Step]> {
+        match self {
+            Action::Task(_) => Vec::new(),
+            Action::Parallel { steps } | Action::Loop { steps, .. } => vec![steps],
+            Action::Branch {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+        }
+    }
+}
+
+/// Collect the IDs of steps and the steps within them
+fn step_ids<'a>(steps: &'a [Step], ids: &mut Vec<&'a str>) {
+    for step in steps {
+        ids.push(&step.id);
+        for steps in step.action.children() {
+            step_ids(steps, ids);
+        }
+    }
+}
+
+impl Orchestrator {
+    /// Run a workflow on the hosted agents
+    ///
+    /// Fails if the workflow is invalid or names agents that are not
+    /// hosted. A failing step fails the run rather than this call; the run
+    /// records the status of every step, so it can be saved and resumed.
+    pub async fn run_workflow(&self, workflow: &Workflow, input: Metadata) -> Result<WorkflowRun> {
+        self.resume_workflow(workflow, WorkflowRun::new(workflow, input))
+            .await
+    }
+
+    /// Resume a run of a workflow, skipping the steps that completed and
+    /// trying failed and interrupted ones again
+    pub async fn resume_workflow(
+        &self,
+        workflow: &Workflow,
+        mut run: WorkflowRun,
+    ) -> Result<WorkflowRun> {
+        workflow.validate()?;
+        let mut errors = ValidationErrors::new();
+        errors.ensure(
+            run.workflow == workflow.name,
+            format!("Run is of workflow {}, not {}", run.workflow, workflow.name),
+        );
+        for agent in workflow.agents() {
+            errors.ensure(
+                self.state(agent).is_ok(),
+                format!("Agent {} is not registered", agent),
+            );
+        }
+        errors.into_result()?;
+
+        tracing::info!(workflow = %workflow.name, "Workflow started");
+        run.status = RunStatus::Running;
+        run.error = None;
+        run.compensations.clear();
+        match self.run_steps(&workflow.steps, &mut run).await {
+            Ok(()) => {
+                run.status = RunStatus::Completed;
+                tracing::info!(workflow = %workflow.name, "Workflow completed");
+            }
+            Err(e) => {
+                tracing::warn!(workflow = %workflow.name, error = %e, "Workflow failed");
+                run.error = Some(e.to_string());
+                let undone = self.compensate(workflow, &mut run).await;
+                run.status = if undone && !run.compensations.is_empty() {
+                    RunStatus::Compensated
+                } else {
+                    RunStatus::Failed
+                };
+            }
+        }
+        Ok(run)
+    }
+
+    /// Undo the completed steps that have compensations, latest first,
+    /// returning whether every compensation succeeded
+    async fn compensate(&self, workflow: &Workflow, run: &mut WorkflowRun) -> bool {
+        let mut undone = true;
+        for id in run.completed.clone().iter().rev() {
+            let Some(task) = find_step(&workflow.steps, id).and_then(|s| s.compensate.as_ref())
+            else {
+                continue;
+            };
+            if run.step(id).map(|status| status.state) != Some(StepState::Completed) {
+                continue;
+            }
+            let mut compensation = Compensation {
+                step: id.clone(),
+                attempts: 0,
+                output: None,
+                error: None,
+            };
+            let context = run.context();
+            match self
+                .attempt_task(id, task, &context, &mut compensation.attempts)
+                .await
+            {
+                Ok(output) => {
+                    compensation.output = Some(output);
+                    run.status(id).state = StepState::Compensated;
+                    run.completed.retain(|completed| completed != id);
+                }
+                Err(e) => {
+                    tracing::error!(step = %id, error = %e, "Workflow compensation failed");
+                    compensation.error = Some(e.to_string());
+                    undone = false;
+                }
+            }
+            run.compensations.push(compensation);
+        }
+        undone
+    }
+
+    /// Run steps in order, stopping at the first failure
+    fn run_steps<'a>(
+        &'a self,
+        steps: &'a [Step],
+        run: &'a mut WorkflowRun,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            for step in steps {
+                self.run_step(step, run).await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Run a step unless it completed
+    async fn run_step(&self, step: &Step, run: &mut WorkflowRun) -> Result<()> {
+        if run.status(&step.id).state == StepState::Completed {
+            return Ok(());
+        }
+        run.status(&step.id).state = StepState::Running;
+        let result = match &step.action {
+            Action::Task(task) => self.run_task(&step.id, task, run).await,
+            Action::Parallel { steps } => self.run_parallel(steps, run).await,
+            Action::Branch {
+                condition,
+                then,
+                otherwise,
+            } => {
+                // A resumed branch keeps the choice it made
+                let taken = match run.status(&step.id).output.as_ref() {
+                    Some(output) => output.get::<bool>("taken").unwrap_or(true),
+                    None => condition.evaluate(&run.context()),
+                };
+                let mut output = Metadata::new();
+                output.insert("taken", taken);
+                run.status(&step.id).output = Some(output);
+                self.run_steps(if taken { then } else { otherwise }, run)
+                    .await
+            }
+            Action::Loop {
+                condition,
+                steps,
+                max_iterations,
+            } => {
+                self.run_loop(&step.id, condition, steps, *max_iterations, run)
+                    .await
+            }
+        };
+
+        let status = run.status(&step.id);
+        match result {
+            Ok(()) => {
+                status.state = StepState::Completed;
+                status.error = None;
+                run.completed.retain(|id| *id != step.id);
+                run.completed.push(step.id.clone());
+                Ok(())
+            }
+            Err(e) => {
+                status.state = StepState::Failed;
+                status.error = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Execute a task of a step, recording its attempts and output
+    async fn run_task(&self, id: &str, task: &TaskStep, run: &mut WorkflowRun) -> Result<()> {
+        let context = run.context();
+        let mut attempts = run.status(id).attempts;
+        let result = self.attempt_task(id, task, &context, &mut attempts).await;
+        let status = run.status(id);
+        status.attempts = attempts;
+        status.output = Some(result?);
+        Ok(())
+    }
+
+    /// Execute a task, trying again after failures as often as it allows
+    async fn attempt_task(
+        &self,
+        id: &str,
+        task: &TaskStep,
+        context: &Metadata,
+        attempts: &mut u32,
+    ) -> Result<Metadata> {
+        let mut params = map_params(&task.params, context)
+            .map_err(|e| Error::Workflow(format!("Step {}: {}", id, e)))?;
+        if let Some(tool) = &task.tool {
+            params.insert(TOOL_KEY, tool);
+        }
+        let mut retries = 0;
+        loop {
+            *attempts += 1;
+            let task_id = TaskId::new();
+            let result = match (&task.agent, &task.capability) {
+                (Some(agent), _) => self.execute(agent, task_id, params.clone()).await,
+                (None, Some(capability)) => self.route(capability, task_id, params.clone()).await,
+                (None, None) => unreachable!("validated tasks name an agent or a capability"),
+            };
+            match result {
+                Err(e) if retries < task.retries => {
+                    tracing::warn!(step = %id, error = %e, "Retrying workflow step");
+                    retries += 1;
+                    tokio::time::sleep(Duration::from_millis(task.retry_delay_ms)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run steps at the same time, each on a copy of the run whose
+    /// statuses are merged back once all of them finish
+    async fn run_parallel(&self, steps: &[Step], run: &mut WorkflowRun) -> Result<()> {
+        let copies: Vec<WorkflowRun> = steps.iter().map(|_| run.clone()).collect();
+        let results = futures::future::join_all(steps.iter().zip(copies).map(
+            |(step, mut copy)| async move {
+                let result = self.run_step(step, &mut copy).await;
+                (step, copy, result)
+            },
+        ))
+        .await;
+
+        let mut failure = None;
+        for (step, copy, result) in results {
+            let mut ids = Vec::new();
+            step_ids(std::slice::from_ref(step), &mut ids);
+            for id in &ids {
+                match copy.steps.get(*id) {
+                    Some(status) => run.steps.insert(id.to_string(), status.clone()),
+                    None => run.steps.remove(*id),
+                };
+            }
+            run.completed.retain(|id| !ids.contains(&id.as_str()));
+            run.completed.extend(
+                copy.completed
+                    .into_iter()
+                    .filter(|id| ids.contains(&id.as_str())),
+            );
+            if let Err(e) = result {
+                failure.get_or_insert(e);
+            }
+        }
+        failure.map_or(Ok(()), Err)
+    }
+
+    /// Run iterations of a loop, resuming an interrupted one
+    async fn run_loop(
+        &self,
+        id: &str,
+        condition: &Condition,
+        steps: &[Step],
+        max_iterations: u32,
+        run: &mut WorkflowRun,
+    ) -> Result<()> {
+        loop {
+            let status = run.status(id);
+            if status.attempts == status.iterations {
+                if status.iterations >= max_iterations || !condition.evaluate(&run.context()) {
+                    return Ok(());
+                }
+                let mut ids = Vec::new();
+                step_ids(steps, &mut ids);
+                for id in ids {
+                    run.steps.remove(id);
+                }
+                run.status(id).attempts += 1;
+            }
+            self.run_steps(steps, run).await?;
+            run.status(id).iterations += 1;
+        }
+    }
+}
+
+/// Replace the `{{path}}` references in parameters with the values they
+/// point to in a context
+fn map_params(params: &Metadata, context: &Metadata) -> Result<Metadata> {
+    let value = serde_json::to_value(params)?;
+    Ok(Metadata::try_from(map_value(value, context)?)?)
+}
+
+/// Replace the `{{path}}` references in a value
+fn map_value(value: Value, context: &Metadata) -> Result<Value> {
+    match value {
+        Value::String(text) => map_text(&text, context),
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| map_value(item, context))
+            .collect::<Result<_>>()
+            .map(Value::Array),
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| Ok((key, map_value(value, context)?)))
+            .collect::<Result<_>>()
+            .map(Value::Object),
+        value => Ok(value),
+    }
+}
+
+/// Replace the `{{path}}` references in a string
+fn map_text(text: &str, context: &Metadata) -> Result<Value> {
+    let lookup = |path: &str| {
+        context
+            .get_path::<Value>(path.trim())
+            .ok_or_else(|| Error::Workflow(format!("Nothing at {}", path.trim())))
+    };
+    if let Some(path) = text
+        .strip_prefix("{{")
+        .and_then(|rest| rest.strip_suffix("}}"))
+        .filter(|path| !path.contains("{{"))
+    {
+        return Ok(lookup(path)?);
+    }
+
+    let mut mapped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        mapped.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| Error::Workflow(format!("Unterminated reference in {:?}", text)))?;
+        match lookup(&rest[start + 2..start + end])? {
+            Value::String(value) => mapped.push_str(&value),
+            value => mapped.push_str(&value.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    mapped.push_str(rest);
+    Ok(Value::String(mapped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AgentRegistration;
+    use atlas_core::testing::FakeAgent;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Tasks run by the echoing agent, and failures it has left to give
+    #[derive(Default)]
+    struct Echo {
+        calls: AtomicU32,
+        failures: AtomicU32,
+    }
+
+    const WORKFLOW: &str = r#"{
+        "name": "research",
+        "steps": [
+            {"id": "fetch", "type": "task", "agent": "echo", "tool": "search",
+             "params": {"query": "{{input.topic}}"}, "retries": 1},
+            {"id": "both", "type": "parallel", "steps": [
+                {"id": "left", "type": "task", "agent": "echo",
+                 "params": {"text": "left of {{steps.fetch.query}}"}},
+                {"id": "right", "type": "task", "capability": "echo",
+                 "params": {"query": "{{steps.fetch.query}}"}}
+            ]},
+            {"id": "check", "type": "branch",
+             "condition": {"equals": {"path": "steps.right.query", "value": "rust"}},
+             "then": [{"id": "approve", "type": "task", "agent": "echo"}],
+             "else": [{"id": "reject", "type": "task", "agent": "echo"}]},
+            {"id": "repeat", "type": "loop", "max_iterations": 3,
+             "condition": {"not": {"exists": {"path": "steps.again.done"}}},
+             "steps": [{"id": "again", "type": "task", "agent": "echo"}]}
+        ]
+    }"#;
+
+    /// Host an agent answering with its parameters and a count of its
+    /// tasks, failing as many as it is told to
+    fn orchestrator(echo: Arc<Echo>) -> Orchestrator {
+        let agent = FakeAgent::new(move |mut params| {
+            let calls = echo.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let failed = echo
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            params.insert("calls", calls);
+            async move {
+                anyhow::ensure!(!failed, "temporary failure");
+                Ok(params)
+            }
+        });
+        let orchestrator = Orchestrator::new();
+        orchestrator
+            .register(AgentRegistration::new("echo", Arc::new(agent)).with_capability("echo"))
+            .unwrap();
+        orchestrator
+    }
+
+    #[tokio::test]
+    async fn test_run_workflow() {
+        let workflow = Workflow::parse(ConfigFormat::Json, WORKFLOW).unwrap();
+        let echo = Arc::new(Echo::default());
+        echo.failures.store(1, Ordering::SeqCst);
+        let orchestrator = orchestrator(echo.clone());
+        orchestrator.start_all().await.unwrap();
+
+        let mut input = Metadata::new();
+        input.insert("topic", "rust");
+        let run = orchestrator.run_workflow(&workflow, input).await.unwrap();
+        assert_eq!(run.status, RunStatus::Completed, "{:?}", run.error);
+
+        let fetch = run.step("fetch").unwrap();
+        assert_eq!(fetch.attempts, 2);
+        let output = run.output("fetch").unwrap();
+        assert_eq!(output.get::<String>("query").as_deref(), Some("rust"));
+        assert_eq!(output.get::<String>("tool").as_deref(), Some("search"));
+        assert_eq!(
+            run.output("left").unwrap().get::<String>("text").as_deref(),
+            Some("left of rust")
+        );
+        assert!(run.output("approve").is_some());
+        assert!(run.step("reject").is_none());
+        assert_eq!(run.step("repeat").unwrap().iterations, 3);
+
+        let mut unknown = workflow.clone();
+        unknown.steps[0].action = Action::Task(TaskStep {
+            agent: Some("missing".to_string()),
+            ..Default::default()
+        });
+        assert!(orchestrator
+            .run_workflow(&unknown, Metadata::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resume_workflow() {
+        let mut workflow = Workflow::parse(ConfigFormat::Json, WORKFLOW).unwrap();
+        if let Action::Parallel { steps } = &mut workflow.steps[1].action {
+            if let Action::Task(task) = &mut steps[1].action {
+                task.params.insert("extra", "{{input.extra}}");
+            }
+        }
+        let echo = Arc::new(Echo::default());
+        let orchestrator = orchestrator(echo.clone());
+        orchestrator.start_all().await.unwrap();
+
+        // The input lacks what the right step maps, so the run fails there
+        let mut input = Metadata::new();
+        input.insert("topic", "go");
+        let run = orchestrator.run_workflow(&workflow, input).await.unwrap();
+        assert_eq!(run.status, RunStatus::Failed);
+        assert!(run.error.as_deref().unwrap().contains("input.extra"));
+        assert_eq!(run.step("left").unwrap().state, StepState::Completed);
+        assert_eq!(run.step("right").unwrap().state, StepState::Failed);
+        assert_eq!(run.step("both").unwrap().state, StepState::Failed);
+        assert_eq!(echo.calls.load(Ordering::SeqCst), 2);
+
+        let saved = serde_json::to_string(&run).unwrap();
+        let mut run: WorkflowRun = serde_json::from_str(&saved).unwrap();
+        run.input.insert("extra", 1);
+        let run = orchestrator.resume_workflow(&workflow, run).await.unwrap();
+        assert_eq!(run.status, RunStatus::Completed, "{:?}", run.error);
+        assert!(run.output("reject").is_some());
+        // Only the right step, the branch taken and the loop ran again
+        assert_eq!(echo.calls.load(Ordering::SeqCst), 7);
+
+        assert!(Workflow::parse(ConfigFormat::Json, r#"{"name": "empty", "steps": []}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_compensation() {
+        let workflow = Workflow::parse(
+            ConfigFormat::Json,
+            r#"{
+                "name": "order",
+                "steps": [
+                    {"id": "reserve", "type": "task", "agent": "echo",
+                     "compensate": {"agent": "echo", "params": {"release": "{{input.item}}"}}},
+                    {"id": "note", "type": "task", "agent": "echo"},
+                    {"id": "charge", "type": "task", "agent": "echo",
+                     "compensate": {"agent": "echo", "params": {"refund": "{{input.amount}}"}}},
+                    {"id": "ship", "type": "task", "agent": "echo",
+                     "params": {"address": "{{input.address}}"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let orchestrator = orchestrator(Arc::new(Echo::default()));
+        orchestrator.start_all().await.unwrap();
+
+        // Shipping lacks an address, so the charge then the reservation are undone
+        let mut input = Metadata::new();
+        input.insert("item", "book");
+        input.insert("amount", 12);
+        let run = orchestrator
+            .run_workflow(&workflow, input.clone())
+            .await
+            .unwrap();
+        assert_eq!(run.status, RunStatus::Compensated);
+        let undone: Vec<&str> = run.compensations.iter().map(|c| c.step.as_str()).collect();
+        assert_eq!(undone, ["charge", "reserve"]);
+        let refund = run.compensations[0].output.as_ref().unwrap();
+        assert_eq!(refund.get::<u32>("refund"), Some(12));
+        assert_eq!(run.step("reserve").unwrap().state, StepState::Compensated);
+        assert_eq!(run.step("note").unwrap().state, StepState::Completed);
+
+        // A compensation failing leaves the run failed with its step completed
+        input.remove("amount");
+        let run = orchestrator.run_workflow(&workflow, input).await.unwrap();
+        assert_eq!(run.status, RunStatus::Failed);
+        assert!(run.compensations[0].error.is_some());
+        assert_eq!(run.step("charge").unwrap().state, StepState::Completed);
+        assert_eq!(run.step("reserve").unwrap().state, StepState::Compensated);
+    }
+}